@@ -0,0 +1,39 @@
+#!/bin/bash
+# Time cold starts: N runs of `project list` against an existing database,
+# each a fresh process, plus the average time spent opening the database as
+# reported by `db profile`. Pass a second binary to compare wall time with it.
+# Usage: ./scripts/bench-cold-start.sh [BIN] [BASELINE_BIN] [N]
+set -e
+BIN=${1:-./target/release/lopen-memory}
+BASELINE=$2
+N=${3:-500}
+DIR=$(mktemp -d)
+DB="$DIR/bench.db"
+
+"$BIN" --db "$DB" --no-skill-check project add bench "$DIR" >/dev/null
+
+wall() {
+  local start end
+  start=$(date +%s%N)
+  for _ in $(seq "$N"); do
+    "$1" --db "$DB" --no-skill-check project list >/dev/null
+  done
+  end=$(date +%s%N)
+  echo "$(( (end - start) / N / 1000 ))"
+}
+
+open_time() {
+  local total=0 us
+  for _ in $(seq "$N"); do
+    us=$("$BIN" --db "$DB" --no-skill-check db profile project list 2>&1 >/dev/null \
+      | sed -n 's/^profile: database opened in \([0-9]*\)us$/\1/p')
+    total=$(( total + us ))
+  done
+  echo "$(( total / N ))"
+}
+
+echo "$BIN: $(wall "$BIN")us per command, $(open_time)us of it opening the database ($N runs)"
+if [ -n "$BASELINE" ]; then
+  echo "$BASELINE: $(wall "$BASELINE")us per command ($N runs)"
+fi
+rm -rf "$DIR"
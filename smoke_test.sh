@@ -7,10 +7,44 @@ set -e
 BIN="./target/release/lopen-memory"
 DB="/tmp/lopen-memory-smoke-test.db"
 export LOPEN_MEMORY_DB="$DB"
+SMOKE_HOME="/tmp/lopen-memory-smoke"
+export LOPEN_MEMORY_CONFIG="$SMOKE_HOME/config.json"
+export AGENTS_SKILLS_DIR="$SMOKE_HOME/skills"
+
+# Run a command that must fail with the given exit code.
+expect_exit() {
+    local want=$1
+    shift
+    set +e
+    "$@"
+    local got=$?
+    set -e
+    if [ "$got" -ne "$want" ]; then
+        echo "FAIL: expected exit $want, got $got: $*"
+        exit 1
+    fi
+}
+
+# Run a command and fail unless its stderr does (1) or does not (0) contain the skill notice.
+expect_skill_notice() {
+    local want=$1
+    shift
+    local got=0
+    if "$@" 2>&1 >/dev/null | grep -q "notice: skill file"; then
+        got=1
+    fi
+    if [ "$got" -ne "$want" ]; then
+        echo "FAIL: expected skill notice=$want, got $got: $*"
+        exit 1
+    fi
+}
 
 rm -f "$DB"
+rm -rf "$SMOKE_HOME"
 echo "=== lopen-memory smoke test ==="
 echo
+# The startup skill check has its own section; keep its notice out of the rest.
+$BIN config set skill_check false
 
 # ── Projects ──────────────────────────────────────────────────────────────────
 echo "--- project add ---"
@@ -75,10 +109,62 @@ echo "--- feature transition ---"
 $BIN feature transition --feature login-flow --module auth Planning
 $BIN feature show --feature login-flow --module auth
 
+echo "--- feature lock: blocked operations ---"
+$BIN feature lock --feature token-refresh --module auth
+expect_exit 4 $BIN feature rename --feature token-refresh --module auth token-renew
+expect_exit 4 $BIN feature set-description --feature token-refresh --module auth "Renamed goal"
+expect_exit 4 $BIN task add --feature token-refresh stray-task
+expect_exit 4 $BIN feature transition --feature token-refresh --module auth Planning
+expect_exit 4 $BIN feature remove --feature token-refresh --module auth --cascade
+expect_exit 4 $BIN module remove --module auth --project my-app --cascade
+
+echo "--- feature lock: permitted operations ---"
+$BIN feature show --feature token-refresh --module auth
+$BIN feature list --module auth --project my-app
+$BIN feature set-details --feature token-refresh --module auth "Shipped; documented externally"
+$BIN feature unlock --feature token-refresh --module auth
+$BIN feature rename --feature token-refresh --module auth token-refresh
+
+echo "--- module lock ---"
+$BIN module lock --module payments --project my-app
+expect_exit 4 $BIN module rename --module payments --project my-app billing
+expect_exit 4 $BIN feature add --module payments --project my-app refunds
+expect_exit 4 $BIN module remove --module payments --project my-app
+$BIN module show --module payments --project my-app
+$BIN module unlock --module payments --project my-app
+
+echo "--- rename with --update-references ---"
+$BIN feature add --module auth token-refresh-v2 "Supersedes token-refresh; keep token-refresh-v2 as is"
+$BIN feature rename --feature token-refresh --module auth token-renew --update-references --dry-run
+$BIN feature rename --feature token-refresh --module auth token-renew --update-references
+$BIN feature show --feature token-refresh-v2 --module auth
+$BIN feature remove --feature token-refresh-v2 --module auth
+
+echo "--- name history ---"
+[ "$($BIN feature name-history --feature token-renew --module auth | grep -c "token-refresh → token-renew")" = "1" ]
+$BIN --json feature name-history --feature token-renew --module auth | grep '"old_name": "token-refresh"' >/dev/null
+$BIN project name-history --project renamed-app | grep "other-app → renamed-app" >/dev/null
+$BIN module name-history --module payments --project my-app | grep "never been renamed" >/dev/null
+$BIN --json find tknrefresh --include-former-names | grep '"matched_former_name": "token-refresh"' >/dev/null
+
 # ── Tasks ──────────────────────────────────────────────────────────────────────
 echo "--- task add ---"
 $BIN task add --feature login-flow implement-jwt "Implement JWT issuance"
 $BIN task add --feature login-flow write-tests "Write integration tests"
+$BIN task add --feature login-flow wire-session "Wire session store" --start
+$BIN task add --feature login-flow plan-logout "Plan logout" --plan
+
+echo "--- use context ---"
+$BIN use --show
+$BIN use --project my-app --module auth --feature login-flow
+$BIN use --show
+$BIN task add scratch-task "Added through the use context"
+$BIN task list
+$BIN feature list
+$BIN task remove --task scratch-task
+$BIN --json use --show
+$BIN use --clear
+expect_exit 1 $BIN task list
 
 echo "--- task list ---"
 $BIN task list --feature login-flow
@@ -89,6 +175,8 @@ $BIN task set-details --task implement-jwt "Use HS256 algorithm with 1h expiry"
 echo "--- task transition ---"
 $BIN task transition --task implement-jwt --feature login-flow Planning
 $BIN task show --task implement-jwt --feature login-flow
+$BIN task show --task write-tests --feature login-flow | grep "^>.*write-tests" >/dev/null
+$BIN --json task show --task write-tests --feature login-flow | grep '"siblings"' >/dev/null
 
 # ── Research ──────────────────────────────────────────────────────────────────
 echo "--- research add ---"
@@ -106,6 +194,47 @@ $BIN research set-source --research jwt-rfc "https://datatracker.ietf.org/doc/ht
 
 echo "--- research set-researched-at ---"
 $BIN research set-researched-at --research oauth2-flows 2024-06-01
+$BIN --json research show --research oauth2-flows | grep '"age_days"' >/dev/null
+# Implausible dates are accepted with a warning, and the timeline flags them.
+RDB="/tmp/lopen-memory-smoke-researched.db"
+rm -f "$RDB"*
+$BIN --db "$RDB" research add old-notes "Notes"
+$BIN --db "$RDB" research set-researched-at --research old-notes 1985-03-01 2>&1 | grep "warning: research old-notes: researched_at 1985-03-01T00:00:00Z is before 1990" >/dev/null
+$BIN --db "$RDB" research show --research old-notes --timeline > "$RDB.out"
+head -2 "$RDB.out" | grep "researched_at  *1985-03-01T00:00:00Z  ([0-9]* days ago)" >/dev/null
+grep "! researched_at 1985-03-01T00:00:00Z is before 1990" "$RDB.out" >/dev/null
+$BIN --db "$RDB" validate | grep "research-researched-at-implausible" >/dev/null
+$BIN --db "$RDB" research set-researched-at --research old-notes 2999-01-01 2>&1 | grep "is in the future" >/dev/null
+$BIN --db "$RDB" --json research show --research old-notes --timeline | grep '"field": "researched_at"' >/dev/null
+$BIN --db "$RDB" research set-researched-at --research old-notes 2020-01-01 2>&1 | if grep "warning" >/dev/null; then echo "FAIL: plausible date warned"; exit 1; fi
+$BIN --db "$RDB" validate | grep -x "no violations found" >/dev/null
+rm -f "$RDB"*
+
+echo "--- research list --group-by staleness ---"
+GBDB="/tmp/lopen-memory-smoke-groupby.db"
+rm -f "$GBDB"*
+for spec in new:5 mid:45 old:200 older:800; do
+  $BIN --db "$GBDB" research add "${spec%%:*}" "Aged ${spec#*:} days"
+  $BIN --db "$GBDB" research set-researched-at --research "${spec%%:*}" "$(date -u -d "-${spec#*:} days" +%F)"
+done
+$BIN --db "$GBDB" research list --group-by staleness > "$GBDB.out"
+grep -x "fresh (<30d): 1" "$GBDB.out" >/dev/null
+grep -x "aging (30–90d): 1" "$GBDB.out" >/dev/null
+grep -x "stale (90–365d): 1" "$GBDB.out" >/dev/null
+grep -x "ancient (>365d): 1" "$GBDB.out" >/dev/null
+grep -A1 "^aging" "$GBDB.out" | grep " mid " >/dev/null
+$BIN --db "$GBDB" research list --group-by staleness --buckets 10,100,1000 | grep -x "aging (10–100d): 1" >/dev/null
+$BIN --db "$GBDB" research list --group-by staleness --buckets 10,100,1000 | grep -x "stale (100–1000d): 2" >/dev/null
+$BIN config set staleness_buckets '[50, 60, 70]'
+$BIN --db "$GBDB" research list --group-by staleness | grep -x "ancient (>70d): 2" >/dev/null
+$BIN config unset staleness_buckets
+$BIN --db "$GBDB" --json research list --group-by staleness | grep '"ancient"' >/dev/null
+$BIN --db "$GBDB" --json research list --group-by staleness | grep '"name": "older"' >/dev/null
+expect_exit 1 $BIN --db "$GBDB" research list --group-by staleness --buckets 90,30,365
+expect_exit 1 $BIN --db "$GBDB" research list --group-by staleness --buckets 0,30,365
+expect_exit 1 $BIN --db "$GBDB" research list --group-by staleness --buckets 30,90
+expect_exit 1 $BIN --db "$GBDB" research list --group-by kind
+rm -f "$GBDB"*
 
 echo "--- research search ---"
 $BIN research search jwt
@@ -117,15 +246,222 @@ $BIN research link --research jwt-rfc --module auth
 $BIN research link --research jwt-rfc --feature login-flow
 $BIN research link --research jwt-rfc --task implement-jwt
 
+echo "--- research link / unlink results ---"
+$BIN research add link-probe "Scratch record for link result checks"
+for target in "--project my-app" "--module auth" "--feature login-flow" "--task implement-jwt"; do
+    $BIN --json research link --research link-probe $target | grep '"created": true' >/dev/null
+    $BIN --json research link --research link-probe $target | grep '"created": false' >/dev/null
+    $BIN research link --research link-probe $target | grep "^already linked" >/dev/null
+    $BIN --json research unlink --research link-probe $target | grep '"removed": true' >/dev/null
+    $BIN --json research unlink --research link-probe $target | grep '"removed": false' >/dev/null
+    $BIN research unlink --research link-probe $target | grep "was not linked" >/dev/null
+done
+$BIN --json research link --research link-probe --task implement-jwt
+$BIN research remove --research link-probe
+
+echo "--- entity-side research links ---"
+$BIN research add side-probe "Linked from the entity side"
+$BIN project link-research --project my-app --research side-probe
+$BIN module link-research --module auth --project my-app --research side-probe
+$BIN feature link-research --feature login-flow --module auth --research side-probe
+$BIN task link-research --task implement-jwt --feature login-flow --research side-probe | grep "task implement-jwt → research side-probe" >/dev/null
+$BIN task link-research --task implement-jwt --research side-probe | grep "already linked" >/dev/null
+$BIN task list-research --task implement-jwt | grep side-probe >/dev/null
+$BIN --json module list-research --module auth | grep '"name": "side-probe"' >/dev/null
+$BIN feature unlink-research --feature login-flow --research side-probe | grep "unlinked feature login-flow" >/dev/null
+$BIN --json feature unlink-research --feature login-flow --research side-probe | grep '"removed": false' >/dev/null
+expect_exit 1 $BIN task link-research --task no-such-task --research side-probe
+$BIN research remove --research side-probe
+
+echo "--- research capture ---"
+$BIN research capture --task implement-jwt --name jwt-clock-skew "Allow 30s leeway on exp checks" --also-feature
+expect_exit 1 $BIN research capture --task implement-jwt --name jwt-clock-skew "Duplicate"
+$BIN research capture --task implement-jwt --name jwt-clock-skew "Leeway is configurable" --append --also-feature
+$BIN --json research capture --task write-tests --name jwt-clock-skew "Tests cover leeway" --append
+$BIN research show --research jwt-clock-skew
+$BIN research remove --research jwt-clock-skew
+
+echo "--- research links --dedupe / normalize-links ---"
+$BIN research add chain-probe "Linked at three levels of one chain"
+$BIN research link --research chain-probe --module auth
+$BIN research link --research chain-probe --feature login-flow
+$BIN research link --research chain-probe --task implement-jwt
+$BIN research links --research chain-probe --dedupe
+[ "$($BIN research links --research chain-probe --dedupe | grep -c '^  [a-z]')" -eq 1 ]
+$BIN research normalize-links --research chain-probe < /dev/null
+[ "$($BIN research links --research chain-probe | grep -c '^  [a-z]')" -eq 3 ]
+$BIN research normalize-links --research chain-probe --yes
+[ "$($BIN research links --research chain-probe | grep -c '^  [a-z]')" -eq 1 ]
+$BIN research links --research chain-probe | grep implement-jwt >/dev/null
+$BIN research normalize-links --research chain-probe
+$BIN research remove --research chain-probe
+
+echo "--- last created ---"
+$BIN last --type task --n 2
+[ "$($BIN last --type task --n 2 | wc -l)" -eq 2 ]
+$BIN --json last --type task --n 1 > /tmp/lopen-memory-smoke-last.json
+grep '"type": "task"' /tmp/lopen-memory-smoke-last.json >/dev/null
+grep '"breadcrumb": "my-app > ' /tmp/lopen-memory-smoke-last.json >/dev/null
+$BIN task add --feature login-flow last-probe
+$BIN --json last --type task --n 1 | grep '"name": "last-probe"' >/dev/null
+$BIN last | grep "^research" >/dev/null
+$BIN task remove --task last-probe
+expect_exit 1 $BIN last --type widget
+rm -f /tmp/lopen-memory-smoke-last.json
+
 echo "--- research links ---"
 $BIN research links --research jwt-rfc
 
+echo "--- research link descriptions ---"
+# Every kind of link carries its entity's description: one line in plain output, in full in JSON.
+$BIN research links --research jwt-rfc | grep "Updated description" >/dev/null
+$BIN research links --research jwt-rfc | grep "Auth and session management" >/dev/null
+$BIN research show --research jwt-rfc | grep "User login and session creation" >/dev/null
+$BIN research show --research jwt-rfc | grep "Implement JWT issuance" >/dev/null
+$BIN --json research show --research jwt-rfc > /tmp/lopen-memory-smoke-links.json
+for d in "Updated description" "Auth and session management" "User login and session creation" "Implement JWT issuance"; do
+    grep "\"description\": \"$d" /tmp/lopen-memory-smoke-links.json >/dev/null
+done
+$BIN task set-description --task implement-jwt "Implement JWT issuance
+Sign with RS256 and rotate keys every 90 days so a leaked key has a bounded lifetime"
+if $BIN research links --research jwt-rfc | grep "rotate keys" >/dev/null; then echo "FAIL: multi-line description not cut to one line"; exit 1; fi
+$BIN research links --research jwt-rfc | grep "Implement JWT issuance…" >/dev/null
+$BIN --json research links --research jwt-rfc | grep "rotate keys every 90 days" >/dev/null
+$BIN task set-description --task implement-jwt "Implement JWT issuance"
+rm -f /tmp/lopen-memory-smoke-links.json
+
+echo "--- research list link counts ---"
+$BIN research list
+$BIN research list --unlinked | grep oauth2-flows >/dev/null
+$BIN research list --min-links 4 | grep jwt-rfc >/dev/null
+[ "$($BIN research list --min-links 5)" = "no research found" ]
+$BIN --json research list --min-links 1 | grep '"link_counts"' >/dev/null
+# Link counts must come from bridge-table index lookups in a single query, not scans.
+plan=$($BIN db profile research list 2>&1 >/dev/null)
+echo "$plan" | grep -q "SEARCH rt USING COVERING INDEX"
+if echo "$plan" | grep -Eq "SCAN (rp|rm|rf|rt)\b"; then
+    echo "FAIL: research list scans a bridge table"
+    exit 1
+fi
+[ "$(echo "$plan" | grep -c "ms  SELECT")" -eq 1 ]
+
+echo "--- research list stale threshold ---"
+$BIN research list | grep "oauth2-flows .*2024-06-01!" >/dev/null
+$BIN research list --stale | grep oauth2-flows >/dev/null
+$BIN config set stale_days 36500
+[ "$($BIN research list --stale)" = "no research found" ]
+LOPEN_MEMORY_STALE_DAYS=30 $BIN research list --stale | grep oauth2-flows >/dev/null
+$BIN --json research list --stale-days 30 | grep '"is_stale": true' >/dev/null
+$BIN config unset stale_days
+
+echo "--- research search --include-former-names ---"
+$BIN research add former-probe "Linked before a rename"
+$BIN research link --research former-probe --feature token-renew
+$BIN research rename --research former-probe former-probe-2
+[ "$($BIN research search --linked-to token-refresh)" = "no research found matching: linked to: token-refresh" ]
+$BIN research search --linked-to token-refresh --include-former-names | grep "feature token-refresh (former name)" >/dev/null
+$BIN research search former-probe --include-former-names | grep "matched former name 'former-probe'" >/dev/null
+$BIN research remove --research former-probe-2
+
+echo "--- research search by linked entity ---"
+$BIN research search --linked-to auth
+$BIN research search --linked-to implement
+$BIN research search jwt --linked-to my-app
+$BIN --json research search --linked-to login
+
 echo "--- research show ---"
 $BIN research show --research jwt-rfc
 
 echo "--- project show with research ---"
 $BIN project show --project my-app
 
+echo "--- export markdown ---"
+EXPORT="/tmp/lopen-memory-smoke-export"
+rm -rf "$EXPORT" "$EXPORT-2"
+
+echo "--- export json ---"
+$BIN export --format json > "$EXPORT.json"
+grep '"format": "lopen-memory"' "$EXPORT.json" >/dev/null
+grep '"name": "my-app"' "$EXPORT.json" >/dev/null
+grep '"name": "jwt-rfc"' "$EXPORT.json" >/dev/null
+grep '"task_id": ' "$EXPORT.json" >/dev/null
+$BIN export --format json --file "$EXPORT-2/dump.json" | grep "exported .* to $EXPORT-2/dump.json" >/dev/null
+grep '"name": "my-app"' "$EXPORT-2/dump.json" >/dev/null
+expect_exit 1 $BIN export --format md --out "$EXPORT" --project my-app
+expect_exit 1 $BIN export --format json --out "$EXPORT"
+rm -rf "$EXPORT.json" "$EXPORT-2"
+# An empty store is a valid, empty document.
+EDB="/tmp/lopen-memory-smoke-export.db"
+rm -f "$EDB"*
+$BIN --db "$EDB" export --format json | grep '"projects": \[\]' >/dev/null
+# --project keeps one subtree and only the research linked into it.
+$BIN --db "$EDB" project add kept-app /kept
+$BIN --db "$EDB" project add other-app /other
+$BIN --db "$EDB" module add --project kept-app kept-module
+$BIN --db "$EDB" research add kept-note "Linked to the kept module"
+$BIN --db "$EDB" research add other-note "Linked to the other project"
+$BIN --db "$EDB" research link --research kept-note --module kept-module
+$BIN --db "$EDB" research link --research other-note --project other-app
+$BIN --db "$EDB" export --format json --project kept-app > "$EXPORT.json"
+grep '"name": "kept-module"' "$EXPORT.json" >/dev/null
+grep '"name": "kept-note"' "$EXPORT.json" >/dev/null
+grep '"module_id": ' "$EXPORT.json" >/dev/null
+if grep -E 'other-app|other-note|"project_id": 2' "$EXPORT.json" >/dev/null; then
+  echo "FAIL: --project exported outside the subtree"; exit 1
+fi
+expect_exit 1 $BIN --db "$EDB" export --format json --project no-such-app
+rm -f "$EDB"* "$EXPORT.json"
+
+echo "--- import ---"
+IMDB="/tmp/lopen-memory-smoke-import.db"
+rm -f "$IMDB"*
+$BIN export --format json --file "$EXPORT.json" >/dev/null
+$BIN --db "$IMDB" --json import --file "$EXPORT.json" | grep '"on_conflict": "fail"' >/dev/null
+$BIN project list > "$EXPORT-projects.txt"
+$BIN --db "$IMDB" project list | diff "$EXPORT-projects.txt" - >/dev/null
+$BIN --db "$IMDB" research show --research jwt-rfc | grep "my-app" >/dev/null
+# A round trip through a second database gives back the same tree and links.
+$BIN --db "$IMDB" export --format json | sed '/exported_at/d' > "$EXPORT-2.json"
+sed '/exported_at/d' "$EXPORT.json" | diff - "$EXPORT-2.json" >/dev/null
+# Conflicts: fail leaves the database untouched, skip creates nothing, rename adds -2.
+expect_exit 1 $BIN --db "$IMDB" import --file "$EXPORT.json"
+$BIN --db "$IMDB" project list | diff "$EXPORT-projects.txt" - >/dev/null
+$BIN --db "$IMDB" import --file "$EXPORT.json" --on-conflict skip > "$EXPORT-2.json"
+grep "imported nothing" "$EXPORT-2.json" >/dev/null
+grep "skipped [0-9]* projects" "$EXPORT-2.json" >/dev/null
+$BIN --db "$IMDB" import --file "$EXPORT.json" --on-conflict rename | grep "renamed project my-app to my-app-2" >/dev/null
+$BIN --db "$IMDB" research show --research jwt-rfc-2 | grep "my-app-2" >/dev/null
+expect_exit 1 $BIN --db "$IMDB" import --file "$EXPORT.json" --on-conflict merge
+echo '{"format": "something-else"}' > "$EXPORT-2.json"
+expect_exit 1 $BIN --db "$IMDB" import --file "$EXPORT-2.json"
+# Imported timestamps are stored canonical; one that does not parse is refused.
+python3 - "$EXPORT.json" "$EXPORT-2.json" <<'PY'
+import json, sys
+doc = json.load(open(sys.argv[1]))
+doc["projects"] = []
+doc["research"] = [{"id": 1, "name": "ts-loose", "researched_at": "2024/03/04 05:06:07"},
+                   {"id": 2, "name": "ts-bad", "researched_at": "2024"}]
+json.dump(doc, open(sys.argv[2], "w"))
+doc["research"] = doc["research"][:1]
+json.dump(doc, open(sys.argv[2] + ".ok", "w"))
+PY
+$BIN --db "$IMDB" import --file "$EXPORT-2.json" 2>&1 | grep -qF "researched_at of 'ts-bad' is not a timestamp: '2024'" \
+    || { echo "FAIL: unparseable import timestamp accepted"; exit 1; }
+expect_exit 1 $BIN --db "$IMDB" import --file "$EXPORT-2.json" 2>/dev/null
+$BIN --db "$IMDB" import --file "$EXPORT-2.json.ok" >/dev/null
+$BIN --db "$IMDB" --json research show --research ts-loose | grep -q '"researched_at": "2024-03-04T05:06:07Z"'
+$BIN --db "$IMDB" research list >/dev/null
+rm -f "$IMDB"* "$EXPORT.json" "$EXPORT-2.json" "$EXPORT-2.json.ok" "$EXPORT-projects.txt"
+
+$BIN export --format md --out "$EXPORT"
+$BIN --json export --out "$EXPORT-2"
+diff -r "$EXPORT" "$EXPORT-2"
+grep "\[jwt-rfc\](../research/jwt-rfc.md)" "$EXPORT/projects/my-app.md" >/dev/null
+grep "(../projects/my-app.md#task-" "$EXPORT/research/jwt-rfc.md" >/dev/null
+cat "$EXPORT/index.md"
+expect_exit 1 $BIN export --format pdf --out "$EXPORT"
+rm -rf "$EXPORT" "$EXPORT-2"
+
 echo "--- research unlink ---"
 $BIN research unlink --research jwt-rfc --task implement-jwt
 $BIN research links --research jwt-rfc
@@ -153,6 +489,3268 @@ echo "--- remove research ---"
 $BIN research remove --research jwt-rfc
 $BIN research list
 
+echo "--- project bootstrap ---"
+REPO="/tmp/lopen-memory-smoke-repo"
+rm -rf "$REPO"
+mkdir -p "$REPO/crates/core" "$REPO/crates/cli" "$REPO/docs" "$REPO/target" "$REPO/.git"
+printf '[workspace]\nmembers = ["crates/*"]\n' > "$REPO/Cargo.toml"
+$BIN project bootstrap --path "$REPO" --name boot-app --modules-from auto --dry-run
+expect_exit 1 $BIN project show --project boot-app
+$BIN project bootstrap --path "$REPO" --name boot-app --modules-from top-dirs
+expect_exit 1 $BIN project bootstrap --path "$REPO" --name boot-app --modules-from workspace
+# A dry run checks the same things the real run does and fails the same way.
+expect_exit 1 $BIN project bootstrap --path "$REPO" --name boot-app --modules-from workspace --dry-run
+$BIN --json project bootstrap --path "$REPO" --name boot-app --dry-run 2>/dev/null | grep '"project already exists: boot-app' >/dev/null
+$BIN project bootstrap --path "$REPO" --name boot-app --modules-from workspace --if-not-exists --dry-run
+$BIN project bootstrap --path "$REPO" --name boot-app --modules-from workspace --if-not-exists
+$BIN module show --module core --project boot-app
+$BIN --json module show --module core --project boot-app | grep '"path": "crates/core"' >/dev/null
+mkdir -p "$REPO/web-app" "$REPO/Web App"
+$BIN --json project bootstrap --path "$REPO" --name boot-dup --modules-from top-dirs --dry-run | grep '"reason": "slug web-app already taken by' >/dev/null
+$BIN project show --project boot-app
+$BIN project remove --project boot-app --cascade
+rm -rf "$REPO"
+
+echo "--- remove --relink-research-to-parent ---"
+RDB="/tmp/lopen-memory-smoke-relink.db"
+rm -f "$RDB"
+$BIN --db "$RDB" project add relink-app /relink
+$BIN --db "$RDB" module add --project relink-app rl-mod
+$BIN --db "$RDB" feature add --module rl-mod rl-feat
+$BIN --db "$RDB" feature add --module rl-mod rl-feat-2
+$BIN --db "$RDB" task add --feature rl-feat rl-task
+$BIN --db "$RDB" research add rl-on-task "Linked to the task"
+$BIN --db "$RDB" research add rl-on-feature "Linked to the feature and the task"
+$BIN --db "$RDB" research add rl-on-module "Already on the module"
+$BIN --db "$RDB" research link --research rl-on-task --task rl-task
+$BIN --db "$RDB" research link --research rl-on-feature --feature rl-feat
+$BIN --db "$RDB" research link --research rl-on-feature --task rl-task
+$BIN --db "$RDB" research link --research rl-on-module --module rl-mod
+$BIN --db "$RDB" research link --research rl-on-module --feature rl-feat
+$BIN --db "$RDB" --json feature remove --feature rl-feat --cascade --relink-research-to-parent | grep '"research_relinked": 2' >/dev/null
+[ "$($BIN --db "$RDB" --json module list-research --module rl-mod | grep -c '"name": "rl-on')" = "3" ]
+$BIN --db "$RDB" research link --research rl-on-task --feature rl-feat-2
+$BIN --db "$RDB" module remove --module rl-mod --cascade --relink-research-to-parent | grep "relinked 3 research" >/dev/null
+[ "$($BIN --db "$RDB" --json project list-research --project relink-app | grep -c '"name": "rl-on')" = "3" ]
+$BIN --db "$RDB" module add --project relink-app rl-plain
+$BIN --db "$RDB" research link --research rl-on-task --module rl-plain
+$BIN --db "$RDB" --json module remove --module rl-plain > /tmp/lopen-memory-smoke-relink.json
+if grep research_relinked /tmp/lopen-memory-smoke-relink.json >/dev/null; then
+    echo "FAIL: module remove relinked research without the flag"
+    exit 1
+fi
+rm -f "$RDB"* /tmp/lopen-memory-smoke-relink.json
+
+echo "--- db stats / profile ---"
+$BIN db stats
+$BIN db profile project show --project my-app
+# Updates print the row they loaded and changed; they never read it back.
+# Each project and module resolved for a write also checks it is not archived.
+[ "$($BIN --json db profile module set-details --module auth --project my-app "Notes" 2>&1 >/dev/null | grep -c "ms  ")" -eq 6 ]
+$BIN research add profile-probe "Query count probe"
+[ "$($BIN --json db profile research set-source --research profile-probe "https://example.com" 2>&1 >/dev/null | grep -c "ms  ")" -eq 3 ]
+$BIN research remove --research profile-probe
+
+echo "--- skill check ---"
+rm -rf "$SMOKE_HOME"
+expect_skill_notice 0 $BIN --json project list
+expect_skill_notice 0 $BIN --no-skill-check project list
+expect_skill_notice 1 $BIN project list
+expect_skill_notice 0 $BIN project list
+rm -f "$SMOKE_HOME/skill-check"
+$BIN config set skill_check false
+expect_skill_notice 0 $BIN project list
+$BIN config list
+$BIN config unset skill_check
+$BIN skill update
+$BIN skill update
+expect_skill_notice 0 $BIN project list
+echo "stale" >> "$AGENTS_SKILLS_DIR/lopen-memory/SKILL.md"
+rm -f "$SMOKE_HOME/skill-check"
+expect_skill_notice 1 $BIN project list
+rm -rf "$SMOKE_HOME"
+$BIN config set skill_check false
+
+echo "--- timestamp normalization migration ---"
+if command -v sqlite3 >/dev/null; then
+    LEGACY_DB="/tmp/lopen-memory-smoke-legacy.db"
+    rm -f "$LEGACY_DB"
+    $BIN --db "$LEGACY_DB" project add legacy-a /a
+    $BIN --db "$LEGACY_DB" project add legacy-b /b
+    $BIN --db "$LEGACY_DB" research add legacy-r "Legacy research"
+    sqlite3 "$LEGACY_DB" "UPDATE projects SET updated_at='2024-01-02 03:04:05' WHERE name='legacy-a';
+        UPDATE projects SET updated_at='not a date' WHERE name='legacy-b';
+        UPDATE research SET researched_at='2023-06-01', created_at='2023-06-01T12:00:00.123+02:00';
+        PRAGMA user_version=2;"
+    $BIN --db "$LEGACY_DB" project list
+    [ "$(sqlite3 "$LEGACY_DB" "SELECT updated_at FROM projects WHERE name='legacy-a'")" = "2024-01-02T03:04:05Z" ]
+    [ "$(sqlite3 "$LEGACY_DB" "SELECT created_at FROM research")" = "2023-06-01T10:00:00Z" ]
+    [ "$(sqlite3 "$LEGACY_DB" "SELECT value FROM timestamp_issues")" = "not a date" ]
+    rm -f "$LEGACY_DB"*
+else
+    echo "sqlite3 not found; skipping"
+fi
+
+echo "--- find ---"
+FDB="/tmp/lopen-memory-smoke-find.db"
+rm -f "$FDB"
+$BIN --db "$FDB" project add catalog /catalog
+$BIN --db "$FDB" module add --project catalog logout
+$BIN --db "$FDB" module add --project catalog log
+$BIN --db "$FDB" feature add --module logout login-flow
+$BIN --db "$FDB" task add --feature login-flow implement-jwt
+$BIN --db "$FDB" task add --feature login-flow fijian-wt
+$BIN --db "$FDB" research add jwt-rfc "JWT spec"
+# Name of the best match for a query.
+top_match() {
+    $BIN --db "$FDB" --json find "$1" | grep '"name": ' | sed -n 1p | sed 's/.*"name": "\(.*\)".*/\1/'
+}
+[ "$(top_match log)" = "log" ]            # exact match beats prefix matches
+[ "$(top_match catlog)" = "catalog" ]     # subsequence with a gap
+[ "$(top_match ij)" = "implement-jwt" ]   # word starts beat an earlier consecutive pair
+[ "$(top_match JWT)" = "jwt-rfc" ]        # case-insensitive, prefix boosted
+[ "$(top_match lgf)" = "login-flow" ]     # interleaved across a word boundary
+$BIN --db "$FDB" --json find jwt | grep '"score"' >/dev/null
+$BIN --db "$FDB" --json find jwt | grep '"breadcrumb": "catalog > logout > login-flow > implement-jwt"' >/dev/null
+[ "$($BIN --db "$FDB" --json find o --limit 2 | grep -c '"score"')" = "2" ]
+$BIN --db "$FDB" find zzz | grep "no names match" >/dev/null
+$BIN --db "$FDB" find jwt
+# Text in descriptions and details is searched too, ignoring case.
+$BIN --db "$FDB" project add other /other
+$BIN --db "$FDB" module add --project other billing
+$BIN --db "$FDB" feature add --module billing invoices
+$BIN --db "$FDB" feature set-details --feature login-flow "Apply Rate Limiting after five failures."
+$BIN --db "$FDB" task set-details --task fijian-wt --feature login-flow "rate limiting per tenant"
+$BIN --db "$FDB" feature set-details --feature invoices "rate limiting on exports"
+$BIN --db "$FDB" research set-content --research jwt-rfc "Nothing about rate limiting here."
+$BIN --db "$FDB" find "rate limiting" | grep "^feature  F1 *Draft *catalog > logout > login-flow  (details)$" >/dev/null
+$BIN --db "$FDB" find "rate limiting" | grep "^research R1 .*jwt-rfc  (content)$" >/dev/null
+$BIN --db "$FDB" --json find "rate limiting" --type task | python3 -c '
+import json, sys
+hits = json.load(sys.stdin)
+assert [(h["type"], h["breadcrumb"], h["matched"]) for h in hits] == [
+    ("task", "catalog > logout > login-flow > fijian-wt", ["details"])], hits
+'
+$BIN --db "$FDB" research link --research jwt-rfc --module billing
+$BIN --db "$FDB" --json find "rate limiting" --project other | python3 -c '
+import json, sys
+hits = json.load(sys.stdin)
+assert sorted(h["breadcrumb"] for h in hits) == ["jwt-rfc", "other > billing > invoices"], hits
+'
+$BIN --db "$FDB" --json find "rate limiting" --state Draft --type feature | grep -c '"type": "feature"' | grep -x 2 >/dev/null
+[ "$($BIN --db "$FDB" --json find "rate limiting" --state Building | python3 -c 'import json,sys; print(len(json.load(sys.stdin)))')" = "0" ]
+expect_exit 1 $BIN --db "$FDB" find jwt --type widget
+expect_exit 1 $BIN --db "$FDB" find jwt --state Shipped
+rm -f "$FDB"*
+
+echo "--- validate ---"
+VDB="/tmp/lopen-memory-smoke-validate.db"
+rm -f "$VDB"
+$BIN --db "$VDB" project add val-app /val "Validation app"
+$BIN --db "$VDB" module add --project val-app val-core "Core"
+$BIN --db "$VDB" feature add --module val-core val-feat "Feature"
+$BIN --db "$VDB" task add --feature val-feat val-task "Task"
+$BIN --db "$VDB" validate
+$BIN --db "$VDB" project complete --project val-app --force
+expect_exit 6 $BIN --db "$VDB" validate --project val-app
+$BIN --db "$VDB" --json validate | grep '"rule": "project-completed-open-modules"' >/dev/null
+$BIN --db "$VDB" module add --project val-app val-empty
+$BIN --db "$VDB" --json validate | grep '"breadcrumb": "val-app > val-empty"' >/dev/null
+$BIN config set validate_suppress '["project-completed-open-modules"]'
+$BIN --db "$VDB" validate
+$BIN config unset validate_suppress
+rm -f "$VDB"*
+
+echo "--- short refs ---"
+XDB="/tmp/lopen-memory-smoke-refs.db"
+rm -f "$XDB"
+$BIN --db "$XDB" project add ref-app /ref
+$BIN --db "$XDB" module add --project P1 ref-core
+$BIN --db "$XDB" feature add --module m1 ref-feat
+$BIN --db "$XDB" task add --feature F1 ref-task
+$BIN --db "$XDB" research add ref-notes
+$BIN --db "$XDB" research link --research r1 --task T1
+$BIN --db "$XDB" task show --task t1 | grep "^ref: *T1$" >/dev/null
+$BIN --db "$XDB" feature list --module M1 | grep "^F1 *ref-feat" >/dev/null
+$BIN --db "$XDB" --json module show --module M1 | grep '"ref": "F1"' >/dev/null
+$BIN --db "$XDB" --json research show --research R1 | grep '"ref": "T1"' >/dev/null
+# A ref of the wrong type is an error naming both types, not a lookup of the wrong entity.
+expect_exit 1 $BIN --db "$XDB" task show --task F1
+$BIN --db "$XDB" task show --task F1 2>&1 | grep "F1 is a feature; --task expects a task" >/dev/null || { echo "FAIL: ref mismatch message"; exit 1; }
+expect_exit 1 $BIN --db "$XDB" feature show --feature t1
+expect_exit 1 $BIN --db "$XDB" task show --task T99
+# A name that happens to look like a ref still resolves as that name.
+$BIN --db "$XDB" task add --feature ref-feat m1
+$BIN --db "$XDB" --json task show --task m1 | grep '"name": "m1"' >/dev/null
+rm -f "$XDB"*
+
+echo "--- module paths ---"
+MDB="/tmp/lopen-memory-smoke-paths.db"
+MROOT="/tmp/lopen-memory-smoke-mono"
+rm -rf "$MDB" "$MROOT"
+mkdir -p "$MROOT/services/auth/src"
+$BIN --db "$MDB" project add mono "$MROOT"
+$BIN --db "$MDB" module add --project mono mono-auth --path ./services/auth/
+$BIN --db "$MDB" module add --project mono mono-billing
+$BIN --db "$MDB" module set-path --module mono-billing services/billing 2>&1 | grep "does not exist under project root" >/dev/null
+$BIN --db "$MDB" --json module show --module mono-auth | grep '"path": "services/auth"' >/dev/null
+$BIN --db "$MDB" module list --project mono | grep "services/billing/" >/dev/null
+expect_exit 1 $BIN --db "$MDB" module set-path --module mono-billing /abs/path
+expect_exit 1 $BIN --db "$MDB" module set-path --module mono-billing ../outside
+$BIN --db "$MDB" module set-path --module mono-billing ""
+# From inside a module path, feature commands pick that module up without --module.
+ABS_BIN="$(pwd)/target/release/lopen-memory"
+(cd "$MROOT/services/auth/src" && "$ABS_BIN" --db "$MDB" feature add mono-login)
+$BIN --db "$MDB" feature list --module mono-auth | grep mono-login >/dev/null
+(cd "$MROOT" && expect_exit 1 "$ABS_BIN" --db "$MDB" feature list)
+rm -rf "$MDB"* "$MROOT"
+
+echo "--- research select ---"
+SDB="/tmp/lopen-memory-smoke-select.db"
+rm -f "$SDB"
+$BIN --db "$SDB" project add sel-app /sel
+$BIN --db "$SDB" module add --project sel-app sel-auth
+$BIN --db "$SDB" feature add --module sel-auth sel-login
+$BIN --db "$SDB" task add --feature sel-login sel-form
+$BIN --db "$SDB" research add sel-jwt "JWT token format"
+$BIN --db "$SDB" research set-content --research sel-jwt "$(printf 'JWT tokens carry signed claims. %.0s' $(seq 20))"
+$BIN --db "$SDB" research add sel-pkce "Login form with PKCE"
+$BIN --db "$SDB" research set-content --research sel-pkce "Authorization code flow with PKCE for the login form."
+$BIN --db "$SDB" research add sel-long
+$BIN --db "$SDB" research set-content --research sel-long "$(printf 'Login rate limits apply per account. %.0s' $(seq 10))"
+$BIN --db "$SDB" research add sel-css "Styling"
+$BIN --db "$SDB" research set-content --research sel-css "Use grid."
+$BIN --db "$SDB" research link --research sel-pkce --feature sel-login
+$BIN --db "$SDB" research link --research sel-jwt --project sel-app
+# Decision for a named record in JSON output (decision precedes name by three lines).
+select_decision() {
+    grep -A3 "\"decision\": \"$1\"" | grep "\"name\": \"$2\"" >/dev/null
+}
+$BIN --db "$SDB" --json research select --query "login form with jwt tokens" --max-chars 200 > /tmp/lopen-memory-smoke-select.json
+select_decision summary sel-jwt < /tmp/lopen-memory-smoke-select.json      # content too long, description fits
+select_decision included sel-pkce < /tmp/lopen-memory-smoke-select.json    # short content fits whole
+select_decision truncated sel-long < /tmp/lopen-memory-smoke-select.json   # no description, cut to the budget
+select_decision skipped sel-css < /tmp/lopen-memory-smoke-select.json      # no overlap
+grep '"used_chars": 200' /tmp/lopen-memory-smoke-select.json >/dev/null
+$BIN --db "$SDB" --json research select --query "login jwt" --max-chars 30 | grep '"reason": "budget exhausted"' >/dev/null
+# Scoped to a task, only research linked to it or its ancestors is considered.
+$BIN --db "$SDB" --json research select --query "login jwt styling" --task sel-form > /tmp/lopen-memory-smoke-select.json
+select_decision included sel-pkce < /tmp/lopen-memory-smoke-select.json
+if grep '"name": "sel-css"' /tmp/lopen-memory-smoke-select.json >/dev/null; then echo "FAIL: unlinked research considered"; exit 1; fi
+$BIN --db "$SDB" research select --query "login" --max-chars 500
+expect_exit 1 $BIN --db "$SDB" research select --query "the"
+expect_exit 1 $BIN --db "$SDB" research select --query "login" --project sel-app --task sel-form
+rm -f "$SDB"* /tmp/lopen-memory-smoke-select.json
+
+echo "--- parent progress on completion ---"
+GDB="/tmp/lopen-memory-smoke-progress.db"
+rm -f "$GDB"
+$BIN --db "$GDB" project add prog-app /prog
+$BIN --db "$GDB" module add --project prog-app prog-core
+$BIN --db "$GDB" feature add --module prog-core prog-login --start
+$BIN --db "$GDB" feature add --module prog-core prog-logout
+$BIN --db "$GDB" task add --feature prog-login wire-routes --start
+$BIN --db "$GDB" task add --feature prog-login add-metrics --start
+$BIN --db "$GDB" task add --feature prog-login add-docs --start
+# Non-Complete transitions say nothing about the parent.
+if $BIN --db "$GDB" task transition --task add-docs Planning 2>/dev/null | grep "remaining" >/dev/null; then echo "FAIL: progress on non-Complete transition"; exit 1; fi
+$BIN --db "$GDB" task transition --task add-docs Building
+$BIN --db "$GDB" task transition --task add-docs Complete
+$BIN --db "$GDB" task transition --task wire-routes Complete | grep "(feature prog-login: 2/3 tasks complete; remaining: add-metrics)" >/dev/null
+$BIN --db "$GDB" --json task transition --task add-metrics Complete > /tmp/lopen-memory-smoke-progress.json
+grep '"complete": 3' /tmp/lopen-memory-smoke-progress.json >/dev/null
+grep '"total": 3' /tmp/lopen-memory-smoke-progress.json >/dev/null
+grep '"remaining": \[\]' /tmp/lopen-memory-smoke-progress.json >/dev/null
+$BIN --db "$GDB" feature transition --feature prog-login Complete | grep "(module prog-core: 1/2 features complete; remaining: prog-logout)" >/dev/null
+rm -f "$GDB"* /tmp/lopen-memory-smoke-progress.json
+
+echo "--- parent lifecycle on transition ---"
+LDB="/tmp/lopen-memory-smoke-lift.db"
+rm -f "$LDB"
+$BIN --db "$LDB" project add lift-app /lift
+$BIN --db "$LDB" module add --project lift-app lift-core
+$BIN --db "$LDB" feature add --module lift-core lift-login
+$BIN --db "$LDB" task add --feature lift-login lift-form
+# By default a parent left behind is only warned about.
+$BIN --db "$LDB" task transition --task lift-form Planning 2>&1 | grep "parent feature lift-login (F1) is still Draft" >/dev/null
+$BIN --db "$LDB" feature show --feature lift-login | grep "Draft" >/dev/null
+LOPEN_MEMORY_PARENT_POLICY=ignore $BIN --db "$LDB" task transition --task lift-form Draft >/dev/null
+if LOPEN_MEMORY_PARENT_POLICY=ignore $BIN --db "$LDB" task transition --task lift-form Planning 2>&1 | grep "warning" >/dev/null; then echo "FAIL: warned under ignore policy"; exit 1; fi
+expect_exit 1 env LOPEN_MEMORY_PARENT_POLICY=sometimes $BIN --db "$LDB" task transition --task lift-form Building
+# A locked parent blocks the lift and nothing moves.
+$BIN --db "$LDB" feature lock --feature lift-login
+expect_exit 4 $BIN --db "$LDB" task transition --task lift-form Building --lift-parents
+$BIN --db "$LDB" module show --module lift-core | grep "Draft" >/dev/null
+$BIN --db "$LDB" task show --task lift-form | grep "Planning" >/dev/null
+$BIN --db "$LDB" feature unlock --feature lift-login
+$BIN --db "$LDB" task transition --task lift-form Building --lift-parents > /tmp/lopen-memory-smoke-lift.out
+grep "lifted module lift-core: Draft → Planning → Building" /tmp/lopen-memory-smoke-lift.out >/dev/null
+grep "lifted feature lift-login: Draft → Planning → Building" /tmp/lopen-memory-smoke-lift.out >/dev/null
+$BIN --db "$LDB" feature show --feature lift-login | grep "Building" >/dev/null
+# Parents at or beyond the target are left alone.
+$BIN --db "$LDB" task add --feature lift-login lift-docs
+$BIN --db "$LDB" --json task transition --task lift-docs Planning --lift-parents > /tmp/lopen-memory-smoke-lift.out
+if grep "lifted_parents" /tmp/lopen-memory-smoke-lift.out >/dev/null; then echo "FAIL: lifted a parent already ahead"; exit 1; fi
+$BIN config set parent_policy lift
+$BIN --db "$LDB" feature add --module lift-core lift-logout
+$BIN --db "$LDB" module transition --module lift-core Draft
+$BIN --db "$LDB" --json feature transition --feature lift-logout Planning | grep '"lifted_parents"' >/dev/null
+$BIN config unset parent_policy
+rm -f "$LDB"* /tmp/lopen-memory-smoke-lift.out
+
+echo "--- research bibliography ---"
+BDB="/tmp/lopen-memory-smoke-biblio.db"
+rm -f "$BDB"*
+$BIN --db "$BDB" project add bib-app /bib
+$BIN --db "$BDB" project add bib-other /other
+$BIN --db "$BDB" module add --project bib-app bib-core
+$BIN --db "$BDB" feature add --module bib-core bib-login
+$BIN --db "$BDB" research add jwt-spec "JWT"
+$BIN --db "$BDB" research add jwt-notes "Notes on JWT"
+$BIN --db "$BDB" research add book "A book"
+$BIN --db "$BDB" research add unsourced "No source"
+$BIN --db "$BDB" research add elsewhere "Other project"
+$BIN --db "$BDB" research set-source --research jwt-spec "https://datatracker.ietf.org/doc/html/rfc7519"
+$BIN --db "$BDB" research set-source --research jwt-notes "http://DataTracker.ietf.org/doc/html/rfc7519/"
+$BIN --db "$BDB" research set-source --research book "Designing Data-Intensive Applications"
+$BIN --db "$BDB" research set-source --research elsewhere "https://example.com/other"
+$BIN --db "$BDB" research link --research jwt-spec --project bib-app
+$BIN --db "$BDB" research link --research jwt-notes --feature bib-login
+$BIN --db "$BDB" research link --research book --module bib-core
+$BIN --db "$BDB" research link --research unsourced --project bib-app
+$BIN --db "$BDB" research link --research elsewhere --project bib-other
+$BIN --db "$BDB" research bibliography --project bib-app > "$BDB.out"
+# http/https, host case, and a trailing slash collapse into one source.
+[ "$(grep -c "rfc7519" "$BDB.out")" -eq 1 ]
+grep -x "  cited by: jwt-notes, jwt-spec" "$BDB.out" >/dev/null
+grep -x "Designing Data-Intensive Applications" "$BDB.out" >/dev/null
+if grep "example.com" "$BDB.out" >/dev/null; then echo "FAIL: bibliography crossed projects"; exit 1; fi
+$BIN --db "$BDB" research bibliography --project bib-app --format md | grep -x "1. \[https://datatracker.ietf.org/doc/html/rfc7519\](https://datatracker.ietf.org/doc/html/rfc7519) — cited by jwt-notes, jwt-spec" >/dev/null
+$BIN --db "$BDB" --json research bibliography --project bib-app | grep '"url": false' >/dev/null
+expect_exit 1 $BIN --db "$BDB" research bibliography --project bib-app --format html
+rm -f "$BDB"*
+
+echo "--- analytics activity ---"
+ADB="/tmp/lopen-memory-smoke-activity.db"
+rm -f "$ADB"*
+$BIN --db "$ADB" project add act-app /act
+$BIN --db "$ADB" project add act-other /other
+$BIN --db "$ADB" module add --project act-app act-core
+$BIN --db "$ADB" feature add --module act-core act-login
+$BIN --db "$ADB" task add --feature act-login act-form
+$BIN --db "$ADB" task rename --task act-form act-page
+$BIN --db "$ADB" research add act-notes "Notes"
+$BIN --db "$ADB" research add act-elsewhere "Other project"
+$BIN --db "$ADB" research link --research act-notes --feature act-login
+$BIN --db "$ADB" research link --research act-elsewhere --project act-other
+TODAY=$(date -u +%F)
+$BIN --db "$ADB" --json analytics activity --project act-app --since "$TODAY" > "$ADB.out"
+# Everything happened today: one each plus the task's rename; act-other's research is not counted.
+grep '"date": "'"$TODAY"'"' "$ADB.out" >/dev/null
+grep '"total": 6' "$ADB.out" >/dev/null
+grep '"task": 2' "$ADB.out" >/dev/null
+grep '"research": 1' "$ADB.out" >/dev/null
+$BIN --db "$ADB" analytics activity --project act-app > "$ADB.out"
+[ "$(grep -c "^Mon\|^Tue\|^Wed\|^Thu\|^Fri\|^Sat\|^Sun" "$ADB.out")" -eq 7 ]
+grep "█" "$ADB.out" >/dev/null
+grep "^6 changes on 1 of 84 days; busiest $TODAY (6)" "$ADB.out" >/dev/null
+$BIN --db "$ADB" analytics activity --project act-other --since "$TODAY" | grep "^2 changes on 1 of 1 days" >/dev/null
+expect_exit 1 $BIN --db "$ADB" analytics activity --project act-app --since 2025-13-01
+expect_exit 1 $BIN --db "$ADB" analytics activity --project act-app --since 2999-01-01
+rm -f "$ADB"*
+
+echo "--- scoping by module and project ---"
+SDB="/tmp/lopen-memory-smoke-scope.db"
+rm -f "$SDB"*
+$BIN --db "$SDB" project add scope-a /a
+$BIN --db "$SDB" project add scope-b /b
+$BIN --db "$SDB" module add --project scope-a payments
+$BIN --db "$SDB" module add --project scope-a billing
+$BIN --db "$SDB" module add --project scope-b ledger
+$BIN --db "$SDB" feature add --module payments api
+$BIN --db "$SDB" feature add --module billing api
+$BIN --db "$SDB" feature add --module ledger api
+# Three features named api: --module picks one for task add, list, and show.
+expect_exit 3 $BIN --db "$SDB" task add --feature api new-task
+$BIN --db "$SDB" task add --feature api --module payments new-task
+$BIN --db "$SDB" task add --feature api --module billing new-task
+$BIN --db "$SDB" --json task list --feature api --module payments | grep '"name": "new-task"' >/dev/null
+if $BIN --db "$SDB" --json task list --feature api --module ledger | grep "new-task" >/dev/null; then echo "FAIL: task list ignored --module"; exit 1; fi
+expect_exit 3 $BIN --db "$SDB" task show --task new-task
+$BIN --db "$SDB" --json task show --task new-task --feature api --module billing | grep '"feature_id": 2' >/dev/null
+$BIN --db "$SDB" --json task show --task new-task --module payments | grep '"feature_id": 1' >/dev/null
+# --project narrows a feature across its modules, or the module name.
+expect_exit 3 $BIN --db "$SDB" feature show --feature api --project scope-a
+$BIN --db "$SDB" --json feature show --feature api --project scope-b | grep '"module": "ledger"' >/dev/null
+$BIN --db "$SDB" --json feature show --feature api --module billing --project scope-a | grep '"module": "billing"' >/dev/null
+expect_exit 1 $BIN --db "$SDB" feature show --feature api --module billing --project scope-b
+# Two modules named core in different projects: --project settles --module.
+$BIN --db "$SDB" module add --project scope-a core
+$BIN --db "$SDB" module add --project scope-b core
+$BIN --db "$SDB" feature add --module core --project scope-a alpha-only
+$BIN --db "$SDB" feature add --module core --project scope-b beta-only
+$BIN --db "$SDB" feature add --module core --project scope-b x
+expect_exit 3 $BIN --db "$SDB" feature add --module core x
+expect_exit 3 $BIN --db "$SDB" feature show --feature x --module core
+$BIN --db "$SDB" --json feature show --feature x --module core --project scope-b | grep '"name": "x"' >/dev/null
+expect_exit 1 $BIN --db "$SDB" feature show --feature x --module core --project scope-a
+$BIN --db "$SDB" --json feature list --module core --project scope-a | grep '"alpha-only"' >/dev/null
+if $BIN --db "$SDB" --json feature list --module core --project scope-a | grep '"beta-only"' >/dev/null; then echo "FAIL: feature list crossed projects"; exit 1; fi
+rm -f "$SDB"*
+
+echo "--- db doctor ---"
+DDB="/tmp/lopen-memory-smoke-doctor.db"
+rm -f "$DDB"*
+$BIN --db "$DDB" project add doc-app /doc
+$BIN --db "$DDB" module add --project doc-app doc-core
+$BIN --db "$DDB" feature add --module doc-core doc-login
+$BIN --db "$DDB" research add doc-notes "Notes"
+$BIN --db "$DDB" research link --research doc-notes --feature doc-login
+$BIN --db "$DDB" db doctor | grep -x "no problems found" >/dev/null
+$BIN --db "$DDB" --paranoid --json db doctor | grep '"total": 0' >/dev/null
+if command -v python3 >/dev/null; then
+  # Foreign keys are off by default in Python's sqlite3, as in older builds.
+  python3 - "$DDB" <<'PY'
+import sqlite3, sys
+db = sqlite3.connect(sys.argv[1])
+db.execute("INSERT INTO modules (project_id, name) VALUES (999, 'lost-module')")
+db.execute("INSERT INTO tasks (feature_id, name) VALUES (999, 'lost-task')")
+db.execute("INSERT INTO research_features (research_id, feature_id) VALUES (999, 1)")
+db.execute("INSERT INTO research_projects (research_id, project_id) VALUES (1, 999)")
+db.execute("INSERT INTO contexts VALUES ('/gone', 999, NULL, NULL, '2024-01-01T00:00:00Z')")
+db.execute("UPDATE features SET last_worked_on='2024-01-02 03:04:05' WHERE name='doc-login'")
+db.commit()
+PY
+  expect_exit 1 $BIN --db "$DDB" db doctor
+  $BIN --db "$DDB" db doctor 2>/dev/null | grep -x "  1 modules without a project (modules) \[--fix deletes\]: id=2 project_id=999" >/dev/null
+  $BIN --db "$DDB" db doctor 2>/dev/null | grep "^  1 tasks without a feature (tasks)" >/dev/null
+  # Errors are listed before warnings.
+  [ "$($BIN --db "$DDB" db doctor 2>/dev/null | grep -E '^(errors|warnings):' | tr '\n' ' ')" = "errors: warnings: " ]
+  $BIN --db "$DDB" --json db doctor 2>/dev/null | python3 -c "
+import json,sys
+v=json.load(sys.stdin)
+assert v['total']==6 and v['errors']==5 and v['warnings']==1, v
+f={x['check']:x for x in v['findings']}
+assert f['orphan-contexts']['rows']==[{'cwd':'/gone','project_id':999}], f
+assert f['noncanonical-timestamp']['rows'][0]['canonical']=='2024-01-02T03:04:05Z' and f['noncanonical-timestamp']['severity']=='warning', f"
+  $BIN --db "$DDB" --json db doctor --fix | grep '"fixed": true' >/dev/null
+  $BIN --db "$DDB" db doctor | grep -x "no problems found" >/dev/null
+  # The real link survived the repair.
+  $BIN --db "$DDB" --json research show --research doc-notes | grep '"doc-login"' >/dev/null
+  # What needs a person stays an error after --fix.
+  python3 - "$DDB" <<'PY'
+import sqlite3, sys
+db = sqlite3.connect(sys.argv[1])
+db.execute("PRAGMA ignore_check_constraints=ON")
+db.execute("UPDATE features SET state='Shipped' WHERE name='doc-login'")
+db.execute("INSERT INTO projects (name, path) VALUES ('DOC-APP', '/doc2')")
+db.execute("UPDATE research SET researched_at='someday'")
+db.commit()
+PY
+  expect_exit 1 $BIN --db "$DDB" db doctor --fix
+  $BIN --db "$DDB" --json db doctor 2>/dev/null | python3 -c "
+import json,sys
+v=json.load(sys.stdin)
+checks=sorted(x['check'] for x in v['findings'])
+# SQLite's own check sees the broken CHECK constraint as well.
+assert checks==['duplicate-name','integrity','invalid-state','unparseable-timestamp'], checks
+assert all(x['fix'] is None and x['severity']=='error' for x in v['findings']), v"
+  $BIN --db "$DDB" db doctor 2>/dev/null | grep -x "  1 names that differ only in case or normal form (projects): ids=1,2 names=doc-app / DOC-APP" >/dev/null
+fi
+rm -f "$DDB"*
+
+echo "--- progress ---"
+PDB="/tmp/lopen-memory-smoke-progress.db"
+rm -f "$PDB"*
+$BIN --db "$PDB" project add prog-app /prog
+$BIN --db "$PDB" module add --project prog-app prog-core
+$BIN --db "$PDB" feature add --module prog-core prog-login
+$BIN --db "$PDB" task add --feature prog-login prog-form
+$BIN --db "$PDB" task set-details --task prog-form "Existing notes"
+# Without --touch-state the state is left alone; the line is appended with a timestamp.
+$BIN --db "$PDB" progress --task prog-form "Sketched the form" | grep -x "progress on prog-app > prog-core > prog-login > prog-form \[Draft\]: details now [0-9]* chars" >/dev/null
+$BIN --db "$PDB" --json task show --task prog-form | grep '"details": "Existing notes\\n\[[0-9T:Z-]*\] Sketched the form"' >/dev/null
+# --touch-state moves it to Building and lifts parents under --lift-parents, in one go.
+$BIN --db "$PDB" --json progress --task prog-form "Wired validation" --touch-state --lift-parents > "$PDB.out"
+grep '"to": "Building"' "$PDB.out" >/dev/null
+grep '"lifted_parents"' "$PDB.out" >/dev/null
+$BIN --db "$PDB" --json feature show --feature prog-login | grep '"state": "Building"' >/dev/null
+# Feature and module are marked worked on at the same moment as the task.
+TASK_TS=$($BIN --db "$PDB" --json task show --task prog-form | grep '"last_worked_on"' | head -1)
+$BIN --db "$PDB" --json module show --module prog-core | grep -F "$TASK_TS" >/dev/null
+# touch_state in the config does what the flag does; without --task the context's Building task is used.
+$BIN --db "$PDB" task add --feature prog-login prog-api
+$BIN config set touch_state true
+$BIN --db "$PDB" progress --task prog-api "Started the API" 2>/dev/null | grep "Draft → Building" >/dev/null
+$BIN config unset touch_state
+PCWD=$(mktemp -d)
+(cd "$PCWD" && "$ABS_BIN" --db "$PDB" use --project prog-app --module prog-core --feature prog-login >/dev/null)
+(cd "$PCWD" && "$ABS_BIN" --db "$PDB" progress "More API work" 2>/dev/null) | grep "prog-api \[Building\]" >/dev/null
+expect_exit 1 $BIN --db "$PDB" progress --task prog-api "   "
+rm -rf "$PCWD"
+rm -f "$PDB"*
+
+echo "--- numeric names ---"
+NDB="/tmp/lopen-memory-smoke-numeric.db"
+rm -f "$NDB"*
+$BIN --db "$NDB" project add first /first
+$BIN --db "$NDB" project add 2024 /y2024
+$BIN --db "$NDB" project add 1 /one
+# A numeric name is found as a name; ids without a matching name still work.
+$BIN --db "$NDB" --json project show --project 2024 | grep '"path": "/y2024"' >/dev/null
+$BIN --db "$NDB" --json project show --project 2 | grep '"name": "2024"' >/dev/null
+# "1" is both project 1's id and project 3's name: the name wins, id: forces the id.
+$BIN --db "$NDB" --json project show --project 1 | grep '"path": "/one"' >/dev/null
+$BIN --db "$NDB" --json project show --project id:1 | grep '"name": "first"' >/dev/null
+$BIN --db "$NDB" module add --project 2024 42
+$BIN --db "$NDB" --json module show --module 42 | grep '"project": "2024"' >/dev/null
+$BIN --db "$NDB" module rename --module 42 answer
+$BIN --db "$NDB" project rename --project 2024 2025
+$BIN --db "$NDB" --json project show --project 2025 | grep '"path": "/y2024"' >/dev/null
+expect_exit 1 $BIN --db "$NDB" project show --project id:abc
+expect_exit 1 $BIN --db "$NDB" project show --project id:99
+rm -f "$NDB"*
+
+echo "--- did you mean ---"
+YDB="/tmp/lopen-memory-smoke-suggest.db"
+rm -f "$YDB"*
+$BIN --db "$YDB" project add sug-app /sug
+$BIN --db "$YDB" project add sug-other /other
+$BIN --db "$YDB" module add --project sug-app auth
+$BIN --db "$YDB" module add --project sug-app auth-v2
+$BIN --db "$YDB" module add --project sug-other authz
+$BIN --db "$YDB" feature add --module auth login
+$BIN --db "$YDB" task add --feature login write-tests
+$BIN --db "$YDB" research add jwt-notes "Notes"
+$BIN --db "$YDB" module show --module athu --project sug-app 2>&1 | grep -x "error: module not found: athu; did you mean: auth?" >/dev/null
+$BIN --db "$YDB" module show --module aut --project sug-app 2>&1 | grep -x "error: module not found: aut; did you mean: auth, auth-v2?" >/dev/null
+# Suggestions stay within the scope given.
+$BIN --db "$YDB" module show --module athu --project sug-other 2>&1 | grep -x "error: module not found: athu; did you mean: authz?" >/dev/null
+$BIN --db "$YDB" project show --project sug-ap 2>&1 | grep "did you mean: sug-app" >/dev/null
+$BIN --db "$YDB" feature show --feature lgoin 2>&1 | grep "did you mean: login?" >/dev/null
+$BIN --db "$YDB" task show --task write-test 2>&1 | grep "did you mean: write-tests?" >/dev/null
+$BIN --db "$YDB" research show --research jwt-note 2>&1 | grep "did you mean: jwt-notes?" >/dev/null
+$BIN --db "$YDB" module show --module zzzz 2>&1 | grep -x "error: module not found: zzzz" >/dev/null
+rm -f "$YDB"*
+
+echo "--- status ---"
+SDB="/tmp/lopen-memory-smoke-status.db"
+rm -f "$SDB"*
+$BIN --db "$SDB" project add st-app /st
+$BIN --db "$SDB" project add st-done /done
+$BIN --db "$SDB" module add --project st-app core
+$BIN --db "$SDB" feature add --module core login
+$BIN --db "$SDB" task add --feature login draft-it
+$BIN --db "$SDB" task add --feature login build-it --start
+$BIN --db "$SDB" project complete --project st-done
+$BIN --db "$SDB" status | grep -x "st-app (P1)" >/dev/null
+if $BIN --db "$SDB" status | grep "st-done" >/dev/null; then echo "FAIL: completed project in status"; exit 1; fi
+$BIN --db "$SDB" status --project st-done | grep -x "st-done (P2)" >/dev/null
+$BIN --db "$SDB" status | grep -x "  tasks     Draft 1, Building 1" >/dev/null
+$BIN --db "$SDB" status | grep "task     T2    Building .*st-app > core > login > build-it" >/dev/null
+$BIN --db "$SDB" --json status --project st-app | python3 -c '
+import json, sys
+p = json.load(sys.stdin)["projects"][0]
+assert p["counts"]["tasks"] == {"Draft": 1, "Planning": 0, "Building": 1, "Complete": 0, "Amending": 0, "Blocked": 0}, p["counts"]
+assert "T2" in [i["ref"] for i in p["in_progress"]], p["in_progress"]
+assert p["recent"][0]["type"] == "task", p["recent"]
+'
+expect_exit 1 $BIN --db "$SDB" status --project nope
+rm -f "$SDB"*
+
+echo "--- closed stdout ---"
+PDB="/tmp/lopen-memory-smoke-pipe.db"
+rm -f "$PDB"*
+$BIN --db "$PDB" project add pipe-app /pipe
+python3 - "$PDB" <<'PY'
+import sqlite3, sys
+c = sqlite3.connect(sys.argv[1])
+ts = "2026-01-01T00:00:00Z"
+c.executemany(
+    "INSERT INTO research(name, description, content, source, researched_at, created_at, updated_at)"
+    " VALUES (?, ?, ?, '', ?, ?, ?)",
+    [("pipe-%d" % i, "d" * 60, "x" * 200, ts, ts, ts) for i in range(3000)],
+)
+c.commit()
+PY
+for args in "--json research list" "research list"; do
+  $BIN --db "$PDB" $args 2>/tmp/lopen-memory-smoke-pipe.err | head -2 >/dev/null
+  code=${PIPESTATUS[0]}
+  if [ "$code" -ne 0 ]; then echo "FAIL: $args into a closed pipe exited $code"; exit 1; fi
+  if grep -E "panicked|Broken pipe" /tmp/lopen-memory-smoke-pipe.err >/dev/null; then echo "FAIL: $args into a closed pipe:"; cat /tmp/lopen-memory-smoke-pipe.err; exit 1; fi
+done
+rm -f "$PDB"* /tmp/lopen-memory-smoke-pipe.err
+
+echo "--- next ---"
+NXDB="/tmp/lopen-memory-smoke-next.db"
+rm -f "$NXDB"*
+$BIN --db "$NXDB" project add nx-app /nx
+$BIN --db "$NXDB" module add --project nx-app core
+$BIN --db "$NXDB" feature add --module core login
+$BIN --db "$NXDB" feature add --module core export
+$BIN --db "$NXDB" task add --feature login draft-one "First draft"
+$BIN --db "$NXDB" task add --feature login plan-one
+$BIN --db "$NXDB" task add --feature export draft-two
+$BIN --db "$NXDB" research add nx-notes "Notes"
+$BIN --db "$NXDB" research link --research nx-notes --feature login
+$BIN --db "$NXDB" next --project nx-app | head -1 | grep -x "nx-app > core > login > draft-one (T1) \[Draft\] — Draft" >/dev/null
+$BIN --db "$NXDB" next --project nx-app | grep "research: *nx-notes (feature)" >/dev/null
+$BIN --db "$NXDB" feature transition --feature login Planning
+$BIN --db "$NXDB" task transition --task plan-one --feature login Planning
+$BIN --db "$NXDB" next --project nx-app | head -1 | grep "login > plan-one (T2) \[Planning\]" >/dev/null
+$BIN --db "$NXDB" next --feature export | head -1 | grep "export > draft-two (T3)" >/dev/null
+$BIN --db "$NXDB" task transition --task draft-two --feature export Planning
+$BIN --db "$NXDB" task transition --task draft-two --feature export Building
+$BIN --db "$NXDB" --json next --project nx-app --count 3 | python3 -c '
+import json, sys
+got = json.load(sys.stdin)
+assert [c["name"] for c in got] == ["draft-two", "plan-one", "draft-one"], got
+assert got[0]["reason"] == "in progress", got[0]
+assert got[2]["description"] == "First draft", got[2]
+assert got[1]["research"] == [{"name": "nx-notes", "linked_to": "feature"}], got[1]
+'
+for st in Planning Building; do $BIN --db "$NXDB" feature transition --feature export $st; done
+$BIN --db "$NXDB" feature transition --feature export Complete --force
+$BIN --db "$NXDB" --json next --module core --count 5 | python3 -c '
+import json, sys
+got = json.load(sys.stdin)
+assert "draft-two" not in [c["name"] for c in got], got
+'
+expect_exit 1 $BIN --db "$NXDB" next --project nx-app --count 0
+rm -f "$NXDB"*
+
+echo "--- show --with-ancestors ---"
+WDB="/tmp/lopen-memory-smoke-ancestors.db"
+rm -f "$WDB"*
+$BIN --db "$WDB" project add wa-app /wa "The app"
+$BIN --db "$WDB" module add --project wa-app core "Core module"
+$BIN --db "$WDB" module set-details --module core "Use snake_case everywhere."
+$BIN --db "$WDB" feature add --module core login "Login"
+$BIN --db "$WDB" task add --feature login wire-it
+$BIN --db "$WDB" task show --task wire-it --with-ancestors | grep -x "module core (M1):" >/dev/null
+$BIN --db "$WDB" task show --task wire-it --with-ancestors | grep "^details: *Use snake_case everywhere.$" >/dev/null
+$BIN --db "$WDB" task show --task wire-it --with-ancestors | grep -x "feature login (F1):" >/dev/null
+if $BIN --db "$WDB" task show --task wire-it | grep "module core (M1):" >/dev/null; then echo "FAIL: ancestors without --with-ancestors"; exit 1; fi
+$BIN --db "$WDB" --json task show --task wire-it --with-ancestors | python3 -c '
+import json, sys
+a = json.load(sys.stdin)["ancestors"]
+assert sorted(a) == ["feature", "module", "project"], a
+assert a["module"]["details"] == "Use snake_case everywhere.", a["module"]
+assert a["project"]["path"] == "/wa", a["project"]
+'
+$BIN --db "$WDB" --json feature show --feature login --with-ancestors | python3 -c '
+import json, sys
+a = json.load(sys.stdin)["ancestors"]
+assert sorted(a) == ["module", "project"], a
+'
+if $BIN --db "$WDB" --json feature show --feature login | grep '"ancestors"' >/dev/null; then echo "FAIL: ancestors key without the flag"; exit 1; fi
+rm -f "$WDB"*
+
+echo "--- trace ---"
+TDB="/tmp/lopen-memory-smoke-trace.db"
+TLOG="/tmp/lopen-memory-smoke-trace.log"
+rm -f "$TDB"* "$TLOG"
+$BIN --db "$TDB" --trace-file "$TLOG" project add tr-app /tr
+LOPEN_MEMORY_ACTOR=agent-7 $BIN --db "$TDB" --trace-file "$TLOG" --json module add --project tr-app core >/dev/null
+$BIN --db "$TDB" --trace-file "$TLOG" module list --project tr-app >/dev/null
+expect_exit 1 $BIN --db "$TDB" --trace-file "$TLOG" module transition --module core Complete
+$BIN --db "$TDB" module rename --module core kernel 3>>"$TLOG" >/dev/null
+LOPEN_MEMORY_TRACE_FD=3 $BIN --db "$TDB" module set-details --module kernel "Conventions" 3>>"$TLOG" >/dev/null
+python3 - "$TLOG" <<'PY'
+import json, sys
+lines = [json.loads(l) for l in open(sys.argv[1])]
+got = [(l["command"], l["type"], l["id"], l["summary"]) for l in lines]
+assert got == [
+    ("project add", "project", 1, "added project P1 (tr-app)"),
+    ("module add", "module", 1, "added module M1 (core)"),
+    ("module set-details", "module", 1, "updated module M1 (kernel)"),
+], got
+assert lines[1]["actor"] == "agent-7", lines[1]
+PY
+# An unwritable trace warns and the command still succeeds.
+$BIN --db "$TDB" --trace-file /nonexistent-dir/trace.log project add tr-other /o 2>&1 | grep "warning: could not write trace" >/dev/null
+$BIN --db "$TDB" --json project show --project tr-other >/dev/null
+rm -f "$TDB"* "$TLOG"
+
+echo "--- numeric bounds ---"
+BDB="/tmp/lopen-memory-smoke-bounds.db"
+rm -f "$BDB"*
+$BIN --db "$BDB" project add b-app /b
+$BIN --db "$BDB" research add old-notes "Old"
+$BIN --db "$BDB" research set-researched-at --research old-notes 2020-01-01
+# A negative day count used to reach SQLite as "--5 days" and filter nothing.
+expect_exit 1 $BIN --db "$BDB" research list --stale-days -5
+$BIN --db "$BDB" research list --stale-days -5 2>&1 | grep -x "error: --stale-days must be between 1 and 36500 days (got -5)" >/dev/null
+expect_exit 1 $BIN --db "$BDB" research list --stale-days=-5
+expect_exit 1 $BIN --db "$BDB" research list --stale-days 0
+expect_exit 1 $BIN --db "$BDB" research list --stale-days 36501
+$BIN --db "$BDB" research list --stale-days 1 | grep old-notes >/dev/null
+$BIN --db "$BDB" research list --stale-days 36500 | grep "no research found" >/dev/null
+expect_exit 1 $BIN --db "$BDB" research search Old --stale-days -1
+expect_exit 1 $BIN --db "$BDB" research list --min-links -1
+expect_exit 1 $BIN --db "$BDB" find old --limit -1
+$BIN --db "$BDB" find old --limit 0 >/dev/null
+expect_exit 1 $BIN --db "$BDB" last --n -1
+expect_exit 1 $BIN --db "$BDB" next --project b-app --count 0
+expect_exit 1 $BIN --db "$BDB" validate --building-days 0
+expect_exit 1 $BIN --db "$BDB" cleanup --older-than -1
+expect_exit 1 $BIN --db "$BDB" research select --query old --max-chars 0
+expect_exit 1 $BIN --db "$BDB" lock acquire --name b --ttl -10
+$BIN --db "$BDB" diff --research old-notes --research old-notes --context -1 2>&1 | grep -x "error: --context must be between 0 and 10000 lines (got -1)" >/dev/null
+# Out-of-range settings are warned about and the default used.
+LOPEN_MEMORY_STALE_DAYS=-5 $BIN --db "$BDB" research list 2>&1 | grep "warning: LOPEN_MEMORY_STALE_DAYS must be between 1 and 36500 days (got -5); ignoring it" >/dev/null
+LOPEN_MEMORY_STALE_DAYS=-5 $BIN --db "$BDB" --json research list --stale 2>/dev/null | grep '"name": "old-notes"' >/dev/null
+rm -f "$BDB"*
+
+echo "--- full-text research search ---"
+FDB="/tmp/lopen-memory-smoke-fts.db"
+rm -f "$FDB"*
+$BIN --db "$FDB" research add "session cookies" "Mentions JWT rotation in passing"
+$BIN --db "$FDB" research add "jwt rotation" "Rotating signing keys"
+$BIN --db "$FDB" research add "key storage" "Where the keys live"
+$BIN --db "$FDB" research set-content --research "key storage" "Rotation is manual"
+# Every word must appear, each as a prefix, in any indexed column.
+$BIN --db "$FDB" research search "jwt rot" | grep "jwt rotation" >/dev/null
+$BIN --db "$FDB" research search "jwt rot" | grep "session cookies" >/dev/null
+if $BIN --db "$FDB" research search "jwt rot" | grep "key storage" >/dev/null; then echo "FAIL: a record missing a word matched"; exit 1; fi
+# A hit in the name ranks above one in the description; rank is in JSON.
+$BIN --db "$FDB" --json research search "jwt rotation" | python3 -c '
+import json, sys
+rows = json.load(sys.stdin)
+assert [r["name"] for r in rows] == ["jwt rotation", "session cookies"], rows
+assert all(isinstance(r["rank"], float) for r in rows), rows
+assert rows[0]["rank"] <= rows[1]["rank"], rows
+'
+# Edits reach the index through the triggers.
+$BIN --db "$FDB" research set-content --research "key storage" "Keys sit in a vault"
+if $BIN --db "$FDB" research search rotation | grep "key storage" >/dev/null; then echo "FAIL: stale index entry"; exit 1; fi
+$BIN --db "$FDB" research search vault | grep "key storage" >/dev/null
+# A database from before the index gets it backfilled when opened.
+python3 - "$FDB" <<'PY'
+import sqlite3, sys
+c = sqlite3.connect(sys.argv[1])
+for t in ("insert", "delete", "update"):
+    c.execute("DROP TRIGGER research_fts_" + t)
+c.execute("DROP TABLE research_fts")
+c.execute("INSERT INTO research (name, description, content, source, researched_at, created_at, updated_at) "
+          "VALUES ('legacy notes', 'Written before the index', '', '', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')")
+c.execute("PRAGMA user_version = 8")
+c.commit()
+PY
+$BIN --db "$FDB" research search "legacy index" | grep "legacy notes" >/dev/null
+$BIN --db "$FDB" research search "jwt rot" | grep "jwt rotation" >/dev/null
+rm -f "$FDB"*
+
+echo "--- text from file or stdin ---"
+XDB="/tmp/lopen-memory-smoke-textin.db"
+XDOC="/tmp/lopen-memory-smoke-textin.md"
+rm -f "$XDB"* "$XDOC"
+python3 - "$XDOC" <<'PY'
+import sys
+lines = ["# Design notes", ""]
+for i in range(200):
+    lines.append(f"- step {i}: quote \"this\", keep $HOME and `ticks` literal")
+open(sys.argv[1], "w").write("\n".join(lines) + "\n\n")
+PY
+[ "$(wc -c < "$XDOC")" -gt 8000 ]
+$BIN --db "$XDB" project add x-app /x
+$BIN --db "$XDB" module add --project x-app core
+$BIN --db "$XDB" feature add --module core login
+$BIN --db "$XDB" task add --feature login wire-up
+$BIN --db "$XDB" research add x-notes
+# Each entity's setters, alternating --file and stdin, must store the document byte for byte.
+$BIN --db "$XDB" project set-description --project x-app --file "$XDOC" >/dev/null
+$BIN --db "$XDB" module set-description --module core - < "$XDOC" >/dev/null
+$BIN --db "$XDB" module set-details --module core --file - < "$XDOC" >/dev/null
+$BIN --db "$XDB" feature set-description --feature login --file "$XDOC" >/dev/null
+cat "$XDOC" | $BIN --db "$XDB" feature set-details --feature login - >/dev/null
+$BIN --db "$XDB" task set-description --task wire-up - < "$XDOC" >/dev/null
+$BIN --db "$XDB" task set-details --task wire-up --file "$XDOC" >/dev/null
+$BIN --db "$XDB" research set-description --research x-notes --file "$XDOC" >/dev/null
+cat "$XDOC" | $BIN --db "$XDB" research set-content --research x-notes - >/dev/null
+for shown in "project show --project x-app:description" "module show --module core:description" \
+             "module show --module core:details" "feature show --feature login:description" \
+             "feature show --feature login:details" "task show --task wire-up:description" \
+             "task show --task wire-up:details" "research show --research x-notes:description" \
+             "research show --research x-notes:content"; do
+    $BIN --db "$XDB" --json ${shown%%:*} | python3 -c '
+import json, sys
+field, path = sys.argv[1], sys.argv[2]
+got = json.load(sys.stdin)[field]
+assert got == open(path).read(), (field, len(got))
+' "${shown##*:}" "$XDOC"
+done
+expect_exit 2 $BIN --db "$XDB" task set-details --task wire-up "inline" --file "$XDOC"
+expect_exit 2 $BIN --db "$XDB" task set-details --task wire-up
+expect_exit 2 $BIN --db "$XDB" task set-details --task wire-up --file /nonexistent/notes.md
+rm -f "$XDB"* "$XDOC"
+
+echo "--- research archive ---"
+ADB="/tmp/lopen-memory-smoke-archive.db"
+rm -f "$ADB"*
+$BIN --db "$ADB" project add ar-app /ar
+$BIN --db "$ADB" module add --project ar-app core
+$BIN --db "$ADB" research add token-notes-v1 "Token rotation, first take"
+$BIN --db "$ADB" research add token-notes-v2 "Token rotation, current"
+$BIN --db "$ADB" research set-content --research token-notes-v1 "rotate tokens daily"
+$BIN --db "$ADB" research set-content --research token-notes-v2 "rotate tokens hourly"
+$BIN --db "$ADB" research link --research token-notes-v1 --module core
+$BIN --db "$ADB" research link --research token-notes-v2 --module core
+$BIN --db "$ADB" research archive --research token-notes-v1 | grep -x "archived research: token-notes-v1" >/dev/null
+$BIN --db "$ADB" research archive --research token-notes-v1 | grep "already archived" >/dev/null
+# Left out by default everywhere research is surfaced...
+if $BIN --db "$ADB" research search token | grep token-notes-v1 >/dev/null; then echo "FAIL: archived research in search"; exit 1; fi
+if $BIN --db "$ADB" research list | grep token-notes-v1 >/dev/null; then echo "FAIL: archived research in list"; exit 1; fi
+if $BIN --db "$ADB" research select --query "rotate tokens" | grep token-notes-v1 >/dev/null; then echo "FAIL: archived research in select"; exit 1; fi
+if $BIN --db "$ADB" module list-research --module core | grep token-notes-v1 >/dev/null; then echo "FAIL: archived research in list-research"; exit 1; fi
+$BIN --db "$ADB" research search token | grep token-notes-v2 >/dev/null
+# ...and back with --include-archived, marked.
+$BIN --db "$ADB" research search token --include-archived | grep "token-notes-v1.*\[archived\]" >/dev/null
+$BIN --db "$ADB" research list --include-archived | grep "token-notes-v1.*\[archived\]" >/dev/null
+$BIN --db "$ADB" research select --query "rotate tokens" --include-archived | grep token-notes-v1 >/dev/null
+$BIN --db "$ADB" module list-research --module core --include-archived | grep "token-notes-v1.*\[archived\]" >/dev/null
+$BIN --db "$ADB" --json research list --include-archived | grep '"archived": true' >/dev/null
+# Show still finds it, and its links are untouched.
+$BIN --db "$ADB" research show --research token-notes-v1 | grep "archived" >/dev/null
+$BIN --db "$ADB" --json research show --research token-notes-v1 | grep '"archived": true' >/dev/null
+$BIN --db "$ADB" research links --research token-notes-v1 | grep core >/dev/null
+$BIN --db "$ADB" research unarchive --research token-notes-v1 >/dev/null
+$BIN --db "$ADB" research list | grep token-notes-v1 >/dev/null
+rm -f "$ADB"*
+
+echo "--- append and prepend ---"
+PDB="/tmp/lopen-memory-smoke-append.db"
+rm -f "$PDB"*
+$BIN --db "$PDB" project add ap-app /ap
+$BIN --db "$PDB" module add --project ap-app core
+$BIN --db "$PDB" feature add --module core login
+$BIN --db "$PDB" task add --feature login wire-up
+$BIN --db "$PDB" research add ap-notes
+# With nothing stored, append is a plain set.
+[ "$($BIN --db "$PDB" --json task set-details --task wire-up --append "first" | python3 -c 'import json,sys; print(json.load(sys.stdin)["details"])')" = "first" ]
+$BIN --db "$PDB" task set-details --task wire-up --append "second" | grep -x "appended to details for task: wire-up" >/dev/null
+$BIN --db "$PDB" --json task set-details --task wire-up --prepend "zero" | python3 -c '
+import json, sys
+assert json.load(sys.stdin)["details"] == "zero\n\nfirst\n\nsecond"
+'
+for e in "module set-details --module core" "feature set-details --feature login"; do
+    $BIN --db "$PDB" $e "base" >/dev/null
+    $BIN --db "$PDB" --json $e --append "more" | grep '"details": "base\\n\\nmore"' >/dev/null
+done
+$BIN --db "$PDB" research set-content --research ap-notes "Findings"
+printf 'Follow-up\n' | $BIN --db "$PDB" --json research set-content --research ap-notes --append - | grep '"content": "Findings\\n\\nFollow-up\\n"' >/dev/null
+# Without a flag the text is still replaced; both flags together are refused.
+$BIN --db "$PDB" --json task set-details --task wire-up "fresh" | grep '"details": "fresh"' >/dev/null
+expect_exit 2 $BIN --db "$PDB" task set-details --task wire-up --append --prepend "x"
+rm -f "$PDB"*
+
+echo "--- snapshots ---"
+SDB="/tmp/lopen-memory-smoke-snapshot.db"
+rm -f "$SDB"*
+$BIN --db "$SDB" project add sn-app /sn
+$BIN --db "$SDB" project add sn-other /so
+$BIN --db "$SDB" module add --project sn-app core
+$BIN --db "$SDB" feature add --module core login
+$BIN --db "$SDB" task add --feature login wire-up
+$BIN --db "$SDB" task set-details --task wire-up "Original notes"
+$BIN --db "$SDB" research add sn-notes
+$BIN --db "$SDB" research link --research sn-notes --task wire-up
+$BIN --db "$SDB" snapshot create --project sn-app --name before-merge | grep "1 modules, 1 features, 1 tasks, 1 research" >/dev/null
+expect_exit 1 $BIN --db "$SDB" snapshot create --project sn-app --name before-merge
+# A risky change to sn-app while sn-other keeps changing.
+$BIN --db "$SDB" task remove --task wire-up
+$BIN --db "$SDB" module rename --module core kernel
+$BIN --db "$SDB" module add --project sn-other billing
+# Restoring over a live project needs --replace or --as.
+expect_exit 1 $BIN --db "$SDB" snapshot restore --name before-merge
+$BIN --db "$SDB" snapshot restore --name before-merge --as sn-app-old | grep "restored snapshot before-merge as project sn-app-old" >/dev/null
+$BIN --db "$SDB" snapshot restore --name before-merge --replace | grep "replacing it" >/dev/null
+$BIN --db "$SDB" --json export --format json --project sn-app | grep '"details": "Original notes"' >/dev/null
+$BIN --db "$SDB" tree --project sn-app | grep core >/dev/null
+if $BIN --db "$SDB" tree --project sn-app | grep kernel >/dev/null; then echo "FAIL: replaced subtree survived"; exit 1; fi
+[ "$($BIN --db "$SDB" research links --research sn-notes | grep -c wire-up)" -eq 2 ]
+$BIN --db "$SDB" module list --project sn-other | grep billing >/dev/null
+# Replacing again finds the project by name now that it has a new id; snapshots follow it.
+$BIN --db "$SDB" snapshot restore --name before-merge --replace >/dev/null
+$BIN --db "$SDB" snapshot list --project sn-app | grep before-merge >/dev/null
+[ "$($BIN --db "$SDB" research links --research sn-notes | grep -c wire-up)" -eq 2 ]
+# A locked module blocks --replace and nothing changes.
+$BIN --db "$SDB" module lock --module core --project sn-app
+expect_exit 4 $BIN --db "$SDB" snapshot restore --name before-merge --replace
+$BIN --db "$SDB" module unlock --module core --project sn-app
+$BIN --db "$SDB" snapshot drop --name before-merge | grep "dropped snapshot: before-merge" >/dev/null
+expect_exit 1 $BIN --db "$SDB" snapshot drop --name before-merge
+$BIN --db "$SDB" snapshot list | grep "no snapshots" >/dev/null
+rm -f "$SDB"*
+
+echo "--- create-time state and details ---"
+CDB="/tmp/lopen-memory-smoke-create.db"
+rm -f "$CDB"*
+$BIN --db "$CDB" project add ct-app /ct
+$BIN --db "$CDB" --json module add --project ct-app core "Core" --state Building --details "Owns startup" > /tmp/ct.json
+python3 -c "import json;d=json.load(open('/tmp/ct.json'));assert d['state']=='Building' and d['details']=='Owns startup', d"
+$BIN --db "$CDB" module show --module core | grep "Owns startup" >/dev/null
+$BIN --db "$CDB" --json feature add --module core login --state Planning > /tmp/ct.json
+python3 -c "import json;d=json.load(open('/tmp/ct.json'));assert d['state']=='Planning' and d['details']=='', d"
+$BIN --db "$CDB" --json task add --feature login wire-up "Wire it" --details "Start with the router" > /tmp/ct.json
+python3 -c "import json;d=json.load(open('/tmp/ct.json'));assert d['state']=='Draft' and d['details']=='Start with the router', d"
+$BIN --db "$CDB" task add --feature login route --state Building | grep "Draft → Planning → Building" >/dev/null
+# Complete and Amending are not starting states; nothing is inserted.
+expect_exit 1 $BIN --db "$CDB" task add --feature login done-already --state Complete
+expect_exit 1 $BIN --db "$CDB" module add --project ct-app legacy --state Amending
+expect_exit 1 $BIN --db "$CDB" task add --feature login typo --state Bilding
+if $BIN --db "$CDB" task list --feature login | grep "done-already\|typo" >/dev/null; then echo "FAIL: rejected add left a row"; exit 1; fi
+expect_exit 2 $BIN --db "$CDB" task add --feature login both --start --state Planning
+rm -f "$CDB"* /tmp/ct.json
+
+echo "--- research show link order ---"
+LDB="/tmp/lopen-memory-smoke-linkorder.db"
+rm -f "$LDB"*
+$BIN --db "$LDB" project add zeta /z
+$BIN --db "$LDB" project add alpha /a
+$BIN --db "$LDB" module add --project alpha auth
+$BIN --db "$LDB" feature add --module auth login
+$BIN --db "$LDB" task add --feature login jwt
+$BIN --db "$LDB" module add --project zeta billing
+$BIN --db "$LDB" research add lo-notes
+# Linked deepest first and across projects, so insertion order is no help.
+for target in "--task jwt" "--project zeta" "--module billing" "--feature login" "--module auth"; do
+    $BIN --db "$LDB" research link --research lo-notes $target >/dev/null
+done
+$BIN --db "$LDB" research show --research lo-notes > /tmp/lo.out
+[ "$(grep -n "^  alpha$" /tmp/lo.out | cut -d: -f1)" -lt "$(grep -n "^  zeta$" /tmp/lo.out | cut -d: -f1)" ]
+grep "login .*(under linked module auth)" /tmp/lo.out >/dev/null
+grep "jwt .*(under linked feature login)" /tmp/lo.out >/dev/null
+grep "billing .*(under linked project zeta)" /tmp/lo.out >/dev/null
+if grep "^    module .* auth .*(under linked" /tmp/lo.out >/dev/null; then echo "FAIL: unlinked-ancestor module marked"; exit 1; fi
+$BIN --db "$LDB" --json research show --research lo-notes | python3 -c "
+import json, sys
+links = json.load(sys.stdin)['linked_to']
+assert [(l['type'], l['name']) for l in links] == [('module', 'auth'), ('feature', 'login'), ('task', 'jwt'), ('project', 'zeta'), ('module', 'billing')], links
+assert links[0]['under'] is None and links[3]['under'] is None
+assert links[2]['under'] == {'type': 'feature', 'id': 1, 'name': 'login'}, links[2]
+"
+rm -f "$LDB"* /tmp/lo.out
+
+echo "--- task order ---"
+ODB="/tmp/lopen-memory-smoke-taskorder.db"
+rm -f "$ODB"*
+$BIN --db "$ODB" project add to-app /to
+$BIN --db "$ODB" module add --project to-app core
+$BIN --db "$ODB" feature add --module core login
+for t in one two three; do $BIN --db "$ODB" task add --feature login $t >/dev/null; done
+$BIN --db "$ODB" task add --feature login zero --before one >/dev/null
+$BIN --db "$ODB" task add --feature login two-b --after two >/dev/null
+[ "$($BIN --db "$ODB" task list --feature login | awk '{print $2}' | tr '\n' ' ')" = "zero one two two-b three " ]
+expect_exit 1 $BIN --db "$ODB" task add --feature login stray --before missing
+expect_exit 2 $BIN --db "$ODB" task add --feature login both --before one --after two
+$BIN --db "$ODB" task reorder --feature login three one | grep "reordered tasks in feature login" >/dev/null
+[ "$($BIN --db "$ODB" task list --feature login | awk '{print $2}' | tr '\n' ' ')" = "three one zero two two-b " ]
+expect_exit 1 $BIN --db "$ODB" task reorder --feature login one one
+# Removing a task leaves gaps; the order stays put and new tasks go last.
+$BIN --db "$ODB" task remove --task zero >/dev/null
+$BIN --db "$ODB" task add --feature login last >/dev/null
+$BIN --db "$ODB" feature show --feature login > /tmp/to.out
+[ "$(grep -n "three" /tmp/to.out | head -1 | cut -d: -f1)" -lt "$(grep -n " one" /tmp/to.out | head -1 | cut -d: -f1)" ]
+$BIN --db "$ODB" tree --project to-app | tail -1 | grep "last" >/dev/null
+$BIN --db "$ODB" --json task show --task three --feature login | python3 -c "import json,sys;d=json.load(sys.stdin);assert d['position']==1 and d['siblings'][0]['name']=='three', d"
+# An export and import keeps the order.
+$BIN --db "$ODB" --json export --format json --project to-app > /tmp/to.json
+rm -f "$ODB"*
+$BIN --db "$ODB" import --file /tmp/to.json >/dev/null
+[ "$($BIN --db "$ODB" task list --feature login | awk '{print $2}' | tr '\n' ' ')" = "three one two two-b last " ]
+# A database from before positions lists tasks in creation order.
+python3 - "$ODB" <<'PY'
+import sqlite3, sys
+c = sqlite3.connect(sys.argv[1])
+c.execute("DROP INDEX idx_tasks_position")
+c.execute("ALTER TABLE tasks DROP COLUMN position")
+c.execute("PRAGMA user_version = 11")
+c.commit()
+PY
+[ "$($BIN --db "$ODB" task list --feature login | awk '{print $2}' | tr '\n' ' ')" = "three one two two-b last " ]
+rm -f "$ODB"* /tmp/to.out /tmp/to.json
+
+echo "--- extract todos ---"
+XDB="/tmp/lopen-memory-smoke-todos.db"
+rm -f "$XDB"*
+$BIN --db "$XDB" project add xt-app /xt
+$BIN --db "$XDB" module add --project xt-app core
+$BIN --db "$XDB" feature add --module core login
+$BIN --db "$XDB" task add --feature login handle-token-refresh >/dev/null
+cat > /tmp/xt.md <<'MD'
+## Plan
+- TODO: handle token refresh
+  * [ ] **TODO(ana):** Validate the `exp` claim & clock skew!
+1. FIXME - login loop on Safari
+> TODO rotate signing keys
+TODOS are tracked elsewhere
+Some text mentioning a TODO inline
+- TODO: already tracked (task T1)
+```
+// TODO: inside a code fence
+```
+- TODO:
+MD
+$BIN --db "$XDB" feature set-details --feature login --file /tmp/xt.md >/dev/null
+# Dry run by default: lists, changes nothing.
+$BIN --db "$XDB" feature extract-todos --feature login | grep "would create 4 task(s)" >/dev/null
+[ "$($BIN --db "$XDB" task list --feature login | wc -l)" -eq 1 ]
+$BIN --db "$XDB" --json feature extract-todos --feature login --apply > /tmp/xt.json
+python3 - <<'PY'
+import json
+d = json.load(open("/tmp/xt.json"))
+assert d["applied"] is True, d
+got = [(t["line"], t["name"], t["ref"]) for t in d["tasks"]]
+assert got == [
+    (2, "handle-token-refresh-2", "T2"),
+    (3, "validate-the-exp-claim-clock-skew", "T3"),
+    (4, "login-loop-on-safari", "T4"),
+    (5, "rotate-signing-keys", "T5"),
+], got
+assert d["tasks"][1]["description"] == "**TODO(ana):** Validate the `exp` claim & clock skew!"
+PY
+$BIN --db "$XDB" feature show --feature login | grep "^1. FIXME - login loop on Safari (task T4)$" >/dev/null
+$BIN --db "$XDB" feature show --feature login | grep "// TODO: inside a code fence$" >/dev/null
+$BIN --db "$XDB" task show --task login-loop-on-safari | grep "FIXME - login loop on Safari" >/dev/null
+# Lines now carry their task ref, so a second run finds nothing.
+$BIN --db "$XDB" feature extract-todos --feature login --apply | grep "no untracked TODO or FIXME lines" >/dev/null
+[ "$($BIN --db "$XDB" task list --feature login | wc -l)" -eq 5 ]
+$BIN --db "$XDB" feature set-details --feature login --append "* TODO: one more" >/dev/null
+$BIN --db "$XDB" feature lock --feature login >/dev/null
+expect_exit 4 $BIN --db "$XDB" feature extract-todos --feature login --apply
+$BIN --db "$XDB" feature extract-todos --feature login | grep "one-more" >/dev/null
+rm -f "$XDB"* /tmp/xt.md /tmp/xt.json
+
+echo "--- task dependencies ---"
+DDB="/tmp/lopen-memory-smoke-deps.db"
+rm -f "$DDB"*
+$BIN --db "$DDB" project add dp-app /dp
+$BIN --db "$DDB" module add --project dp-app core --state Building
+$BIN --db "$DDB" feature add --module core login --state Building
+$BIN --db "$DDB" feature add --module core audit
+for t in schema api ui; do $BIN --db "$DDB" task add --feature login $t >/dev/null; done
+$BIN --db "$DDB" task add --feature audit log-logins >/dev/null
+$BIN --db "$DDB" task depend --task api --on schema | grep "task api now depends on schema" >/dev/null
+$BIN --db "$DDB" task depend --task ui --on api >/dev/null
+$BIN --db "$DDB" task depend --task api --on schema | grep "already depends" >/dev/null
+# Across features, and cycles of any length are refused.
+$BIN --db "$DDB" task depend --task log-logins --on api >/dev/null
+expect_exit 1 $BIN --db "$DDB" task depend --task schema --on ui
+expect_exit 1 $BIN --db "$DDB" task depend --task schema --on schema
+expect_exit 1 $BIN --db "$DDB" task undepend --task schema --on ui
+# Building and Complete wait on dependencies unless --force; Planning does not.
+$BIN --db "$DDB" task transition --task api Planning >/dev/null
+expect_exit 1 $BIN --db "$DDB" task transition --task api Building
+$BIN --db "$DDB" task show --task api | grep "Planning" >/dev/null
+$BIN --db "$DDB" task transition --task api Building --force 2>&1 | grep "warning: moving task api to Building with 1 incomplete dependency: schema \[Draft\]" >/dev/null
+[ "$($BIN --db "$DDB" task list --feature login --ready | awk '{print $2}' | tr '\n' ' ')" = "schema " ]
+$BIN --db "$DDB" --json next --project dp-app --count 10 --ready | python3 -c "import json,sys;n=[c['name'] for c in json.load(sys.stdin)];assert n==['schema'], n"
+$BIN --db "$DDB" --json task show --task api | python3 -c "
+import json, sys
+d = json.load(sys.stdin)
+assert [x['name'] for x in d['depends_on']] == ['schema'], d['depends_on']
+assert [x['name'] for x in d['blocks']] == ['ui', 'log-logins'], d['blocks']
+"
+$BIN --db "$DDB" feature show --feature login | grep "ui .*(after api)" >/dev/null
+for t in schema api; do
+    $BIN --db "$DDB" task transition --task $t Planning >/dev/null 2>&1 || true
+    $BIN --db "$DDB" task transition --task $t Building >/dev/null 2>&1 || true
+    $BIN --db "$DDB" task transition --task $t Complete >/dev/null
+done
+[ "$($BIN --db "$DDB" task list --feature login --ready | awk '{print $2}')" = "ui" ]
+# Exports carry dependencies; removing a task drops its dependencies.
+$BIN --db "$DDB" --json export --format json --project dp-app > /tmp/dp.json
+python3 -c "import json;d=json.load(open('/tmp/dp.json'));assert len(d['dependencies'])==3, d['dependencies']"
+$BIN --db "$DDB" task remove --task api >/dev/null
+$BIN --db "$DDB" --json task show --task ui | python3 -c "import json,sys;assert json.load(sys.stdin)['depends_on']==[]"
+rm -f "$DDB"*
+$BIN --db "$DDB" --json import --file /tmp/dp.json | python3 -c "import json,sys;d=json.load(sys.stdin);assert d['created']['dependencies']==3, d"
+$BIN --db "$DDB" task show --task ui | grep "depends_on: *api \[Complete\]" >/dev/null
+rm -f "$DDB"* /tmp/dp.json
+
+echo "--- priority ---"
+PDB="/tmp/lopen-memory-smoke-priority.db"
+rm -f "$PDB"*
+$BIN --db "$PDB" project add pr-app /pr
+$BIN --db "$PDB" module add --project pr-app core
+$BIN --db "$PDB" feature add --module core login --priority high
+$BIN --db "$PDB" feature add --module core theming
+for t in schema api ui; do $BIN --db "$PDB" task add --feature login $t >/dev/null; done
+$BIN --db "$PDB" task add --feature login hotfix --priority Critical >/dev/null
+$BIN --db "$PDB" task set-priority --task schema low | grep "priority medium → low" >/dev/null
+expect_exit 1 $BIN --db "$PDB" task set-priority --task api urgent
+expect_exit 1 $BIN --db "$PDB" task add --feature login other --priority urgent
+expect_exit 1 $BIN --db "$PDB" task list --feature login --sort urgency
+# Work order by default; most urgent first, ids breaking ties, with --sort priority.
+[ "$($BIN --db "$PDB" task list --feature login | awk '{print $2}' | tr '\n' ' ')" = "schema api ui hotfix " ]
+[ "$($BIN --db "$PDB" task list --feature login --sort priority | awk '{print $2 "/" $4}' | tr '\n' ' ')" = "hotfix/critical api/medium ui/medium schema/low " ]
+[ "$($BIN --db "$PDB" task list --feature login --priority medium | awk '{print $2}' | tr '\n' ' ')" = "api ui " ]
+[ "$($BIN --db "$PDB" feature list --module core --sort priority --priority high | awk '{print $2}')" = "login" ]
+$BIN --db "$PDB" feature set-priority --feature theming critical >/dev/null
+[ "$($BIN --db "$PDB" feature list --module core --sort priority | awk '{print $2}' | tr '\n' ' ')" = "theming login " ]
+$BIN --db "$PDB" feature show --feature login | grep "^priority: *high" >/dev/null
+$BIN --db "$PDB" feature show --feature login | grep "hotfix .*critical" >/dev/null
+$BIN --db "$PDB" --json feature show --feature login | python3 -c "import json,sys;d=json.load(sys.stdin);assert d['priority']=='high' and d['tasks'][3]['priority']=='critical', d"
+# next prefers the more urgent task within a tier.
+$BIN --db "$PDB" --json next --project pr-app --count 2 | python3 -c "import json,sys;n=[(c['name'],c['priority']) for c in json.load(sys.stdin)];assert n==[('hotfix','critical'),('api','medium')], n"
+$BIN --db "$PDB" next --project pr-app --count 1 | grep "\[Draft, critical\]" >/dev/null
+# Priorities survive an export and import.
+$BIN --db "$PDB" --json export --format json --project pr-app > /tmp/pr.json
+rm -f "$PDB"*
+$BIN --db "$PDB" import --file /tmp/pr.json >/dev/null
+$BIN --db "$PDB" --json task show --task hotfix | python3 -c "import json,sys;assert json.load(sys.stdin)['priority']=='critical'"
+$BIN --db "$PDB" --json feature show --feature theming | python3 -c "import json,sys;assert json.load(sys.stdin)['priority']=='critical'"
+rm -f "$PDB"* /tmp/pr.json
+
+echo "--- effective project completion ---"
+EDB="/tmp/lopen-memory-smoke-effective.db"
+rm -f "$EDB"*
+# empty-done: no modules, flag set. empty-open: no modules, flag unset.
+# all-done: every module Complete, flag unset. reopened: flag set, a module Amending.
+# agree-open: flag unset, a module Draft.
+for p in empty-done empty-open all-done reopened agree-open; do $BIN --db "$EDB" project add $p /$p >/dev/null; done
+$BIN --db "$EDB" project complete --project empty-done >/dev/null
+$BIN --db "$EDB" module add --project all-done a1 --state Building >/dev/null
+$BIN --db "$EDB" module transition --module a1 --project all-done Complete >/dev/null
+$BIN --db "$EDB" module add --project reopened r1 --state Building >/dev/null
+$BIN --db "$EDB" module transition --module r1 --project reopened Complete >/dev/null
+$BIN --db "$EDB" project complete --project reopened >/dev/null
+$BIN --db "$EDB" module transition --module r1 --project reopened Amending >/dev/null
+$BIN --db "$EDB" module add --project agree-open o1 >/dev/null
+$BIN --db "$EDB" module add --project agree-open o2 --state Building >/dev/null
+$BIN --db "$EDB" module transition --module o2 --project agree-open Complete >/dev/null
+names() { awk '{print $2}' | tr '\n' ' '; }
+[ "$($BIN --db "$EDB" project list --incomplete | names)" = "empty-open all-done agree-open " ]
+[ "$($BIN --db "$EDB" project list --incomplete --effective | names)" = "empty-open reopened agree-open " ]
+[ "$($BIN --db "$EDB" project list --completed --effective | names)" = "empty-done all-done " ]
+$BIN --db "$EDB" project list | grep "^P3 .*incomplete  \[state mismatch: every module is Complete" >/dev/null
+$BIN --db "$EDB" project list | grep "^P4 .*complete  \[state mismatch: marked completed" >/dev/null
+[ "$($BIN --db "$EDB" project list | grep -c "state mismatch")" -eq 2 ]
+$BIN --db "$EDB" --json project list | python3 -c "
+import json, sys
+got = {p['name']: (p['completed'], p['effectively_completed'], p['state_mismatch']) for p in json.load(sys.stdin)}
+assert got == {
+    'empty-done': (True, True, False),
+    'empty-open': (False, False, False),
+    'all-done': (False, True, True),
+    'reopened': (True, False, True),
+    'agree-open': (False, False, False),
+}, got
+"
+$BIN --db "$EDB" project show --project all-done | grep "^state_mismatch: *every module is Complete" >/dev/null
+if $BIN --db "$EDB" project show --project agree-open | grep state_mismatch >/dev/null; then echo "FAIL: agreeing project shows a mismatch"; exit 1; fi
+# validate reports both directions: the reopened one as an error, the unmarked one as a warning.
+$BIN --db "$EDB" --json validate | python3 -c "
+import json, sys
+d = json.load(sys.stdin)
+rules = {v['rule']: [e['breadcrumb'] for e in v['entities']] for v in d['violations']}
+assert rules['project-incomplete-modules-complete'] == ['all-done'], rules
+assert rules['project-completed-open-modules'] == ['reopened'], rules
+"
+rm -f "$EDB"*
+
+echo "--- blocked ---"
+BDB="/tmp/lopen-memory-smoke-blocked.db"
+rm -f "$BDB"*
+$BIN --db "$BDB" project add bl-app /bl
+$BIN --db "$BDB" module add --project bl-app core --state Building
+$BIN --db "$BDB" feature add --module core billing --state Building
+$BIN --db "$BDB" task add --feature billing charge --state Building
+$BIN --db "$BDB" task add --feature billing refund
+$BIN --db "$BDB" task transition --task charge Blocked --reason "waiting on the Stripe API key" | grep "Building → Blocked" >/dev/null
+$BIN --db "$BDB" task list --feature billing | grep "^T1 .*Blocked .*\[blocked: waiting on the Stripe API key\]$" >/dev/null
+if $BIN --db "$BDB" task list --feature billing | grep "refund.*blocked" >/dev/null; then echo "FAIL: unblocked task tagged"; exit 1; fi
+$BIN --db "$BDB" task show --task charge | grep "^blocked: *waiting on the Stripe API key$" >/dev/null
+$BIN --db "$BDB" --json task show --task charge | python3 -c "import json,sys;d=json.load(sys.stdin);assert d['state']=='Blocked' and d['blocked_reason']=='waiting on the Stripe API key', d"
+$BIN --db "$BDB" --json task show --task refund | python3 -c "import json,sys;assert json.load(sys.stdin)['blocked_reason'] is None"
+$BIN --db "$BDB" feature show --feature billing | grep "charge .*\[blocked: waiting on the Stripe API key\]" >/dev/null
+$BIN --db "$BDB" --json feature show --feature billing | python3 -c "import json,sys;t=json.load(sys.stdin)['tasks'];assert [x['blocked_reason'] for x in t]==['waiting on the Stripe API key', None], t"
+# Only Building may block; Blocked goes back to Building or Draft.
+expect_exit 1 $BIN --db "$BDB" task transition --task refund Blocked
+expect_exit 1 $BIN --db "$BDB" task transition --task charge Complete
+expect_exit 1 $BIN --db "$BDB" task add --feature billing later --state Blocked
+$BIN --db "$BDB" task transition --task charge Building >/dev/null
+if $BIN --db "$BDB" task show --task charge | grep "^blocked:" >/dev/null; then echo "FAIL: unblocked task still shows blocked"; exit 1; fi
+# Features and modules block the same way; a missing reason warns.
+$BIN --db "$BDB" feature transition --feature billing Blocked 2>&1 | grep "warning: feature billing moved to Blocked without a reason" >/dev/null
+$BIN --db "$BDB" module show --module core | grep "billing .*Blocked  \[blocked: no reason given\]" >/dev/null
+$BIN --db "$BDB" feature list --module core | grep "\[blocked: no reason given\]" >/dev/null
+$BIN --db "$BDB" module transition --module core Blocked --reason "vendor outage" >/dev/null
+$BIN --db "$BDB" module list --project bl-app | grep "\[blocked: vendor outage\]" >/dev/null
+$BIN --db "$BDB" --json module show --module core | python3 -c "import json,sys;d=json.load(sys.stdin);assert d['blocked_reason']=='vendor outage' and d['features'][0]['blocked_reason']=='', d"
+$BIN --db "$BDB" --json status --project bl-app | python3 -c "import json,sys;c=json.load(sys.stdin)['projects'][0]['counts'];assert c['modules']['Blocked']==1 and c['tasks']['Blocked']==0, c"
+# A database whose CHECK constraints predate Blocked is widened on open.
+python3 - "$BDB" <<'PY'
+import sqlite3, sys
+db = sqlite3.connect(sys.argv[1], isolation_level=None)
+db.execute("UPDATE modules SET state='Building'")
+db.execute("UPDATE features SET state='Building'")
+version = db.execute("PRAGMA schema_version").fetchone()[0]
+db.execute("PRAGMA writable_schema=ON")
+db.execute("UPDATE sqlite_master SET sql=replace(sql, ',''Blocked''', '') WHERE name IN ('modules','features','tasks')")
+db.execute("PRAGMA schema_version=%d" % (version + 1))
+db.execute("PRAGMA writable_schema=OFF")
+db.execute("PRAGMA user_version=14")
+db.close()
+db = sqlite3.connect(sys.argv[1])
+try:
+    db.execute("UPDATE tasks SET state='Blocked'")
+    raise SystemExit("old CHECK accepted Blocked")
+except sqlite3.IntegrityError:
+    pass
+PY
+$BIN --db "$BDB" task transition --task charge Blocked --reason "again" >/dev/null
+[ "$(python3 -c "import sqlite3,sys;print(sqlite3.connect(sys.argv[1]).execute('PRAGMA integrity_check').fetchone()[0])" "$BDB")" = "ok" ]
+rm -f "$BDB"*
+
+echo "--- setup bundles ---"
+SUD="/tmp/lopen-memory-smoke-setup"
+rm -rf "$SUD"; mkdir -p "$SUD/old" "$SUD/new"
+old() { HOME="$SUD/old" LOPEN_MEMORY_CONFIG="$SUD/old/config.json" AGENTS_SKILLS_DIR="$SUD/old/skills" $ABS_BIN --no-skill-check --db "$SUD/old/db" "$@"; }
+new() { HOME="$SUD/new" LOPEN_MEMORY_CONFIG="$SUD/new/config.json" AGENTS_SKILLS_DIR="$SUD/new/skills" $ABS_BIN --no-skill-check --db "$SUD/new/db" "$@"; }
+old project add su-app "$SUD/old/code/su-app" >/dev/null
+old module add --project su-app core >/dev/null
+old feature add --module core sync >/dev/null
+old task add --feature sync fetch >/dev/null
+old task add --feature sync store >/dev/null
+old task depend --task store --on fetch >/dev/null
+old research add su-notes "Notes" >/dev/null
+old research link --research su-notes --feature sync >/dev/null
+old config set workspace "$SUD/old/code" >/dev/null
+old skill install >/dev/null
+echo "# local edits" >> "$SUD/old/skills/lopen-memory/SKILL.md"
+old setup export --out "$SUD/setup.tar.gz" --with-db | grep "config, skill (installed), database (1 project(s), 1 research)" >/dev/null
+[ "$(tar tzf "$SUD/setup.tar.gz" | tr '\n' ' ')" = "manifest.json config.json skill/SKILL.md database.json " ]
+tar xzOf "$SUD/setup.tar.gz" manifest.json | python3 -c "import json,sys;m=json.load(sys.stdin);assert m['format']=='lopen-memory-setup' and m['format_version']==1 and m['home'].endswith('/old'), m"
+# A fresh machine takes everything, with home paths moved across.
+new setup apply "$SUD/setup.tar.gz" > "$SUD/out"
+grep "^config: installed" "$SUD/out" >/dev/null
+grep "^skill: installed" "$SUD/out" >/dev/null
+grep "^database: imported" "$SUD/out" >/dev/null
+[ "$(new config get workspace)" = "$SUD/new/code" ]
+new project show --project su-app | grep "$SUD/new/code/su-app" >/dev/null
+grep "# local edits" "$SUD/new/skills/lopen-memory/SKILL.md" >/dev/null
+new task show --task store | grep "fetch" >/dev/null
+# Applying it again changes nothing.
+new --json setup apply "$SUD/setup.tar.gz" | python3 -c "import json,sys;d=json.load(sys.stdin);assert [d[k]['action'] for k in ('config','skill','database')]==['unchanged']*3, d"
+[ "$(new --json project list | python3 -c "import json,sys;print(len(json.load(sys.stdin)))")" = "1" ]
+# Local differences stop the apply until --overwrite or --merge says what to do.
+new config set workspace /elsewhere >/dev/null
+new project add su-local /local >/dev/null
+expect_exit 1 new setup apply "$SUD/setup.tar.gz"
+new setup apply "$SUD/setup.tar.gz" 2>&1 | grep "pass --overwrite to take the bundle's, or --merge" >/dev/null
+[ "$(new config get workspace)" = "/elsewhere" ]
+new setup apply "$SUD/setup.tar.gz" --merge | grep "kept local workspace" >/dev/null
+[ "$(new config get workspace)" = "/elsewhere" ]
+new project show --project su-local >/dev/null
+new --json setup apply "$SUD/setup.tar.gz" --merge | python3 -c "import json,sys;d=json.load(sys.stdin);assert d['database']['action']=='unchanged', d"
+new setup apply "$SUD/setup.tar.gz" --overwrite | grep "^database: overwritten" >/dev/null
+[ "$(new config get workspace)" = "$SUD/new/code" ]
+expect_exit 1 new project show --project su-local
+new setup apply "$SUD/setup.tar.gz" --overwrite | grep "^database: unchanged" >/dev/null
+expect_exit 2 new setup apply "$SUD/setup.tar.gz" --overwrite --merge
+# Explicit mappings win over the home directory; --keep-paths leaves paths alone.
+rm -rf "$SUD/new"; mkdir -p "$SUD/new"
+new setup apply "$SUD/setup.tar.gz" --map-path "$SUD/old/code=/srv/code" --skip-db | grep "^database: skipped" >/dev/null
+[ "$(new config get workspace)" = "/srv/code" ]
+expect_exit 1 new project show --project su-app
+rm -rf "$SUD/new"; mkdir -p "$SUD/new"
+new setup apply "$SUD/setup.tar.gz" --keep-paths >/dev/null
+[ "$(new config get workspace)" = "$SUD/old/code" ]
+expect_exit 1 new setup apply "$SUD/setup.tar.gz" --map-path nothing
+echo '{}' > "$SUD/bogus.tar.gz"
+expect_exit 1 new setup apply "$SUD/bogus.tar.gz"
+rm -rf "$SUD"
+
+echo "--- state history ---"
+SHD="/tmp/lopen-memory-smoke-state-history.db"
+rm -f "$SHD"*
+$BIN --db "$SHD" project add sh-app /sh >/dev/null
+$BIN --db "$SHD" module add --project sh-app core >/dev/null
+$BIN --db "$SHD" feature add --module core api >/dev/null
+$BIN --db "$SHD" task add --feature api endpoint >/dev/null
+$BIN --db "$SHD" task history --task endpoint | grep "task endpoint has no recorded state changes" >/dev/null
+$BIN --db "$SHD" task transition --task endpoint Planning --lift-parents >/dev/null
+$BIN --db "$SHD" task transition --task endpoint Building --lift-parents >/dev/null
+$BIN --db "$SHD" task transition --task endpoint Complete >/dev/null
+$BIN --db "$SHD" task transition --task endpoint Amending --reason "spec changed" >/dev/null
+$BIN --db "$SHD" task transition --task endpoint Draft >/dev/null
+$BIN --db "$SHD" --json task history --task endpoint | python3 -c "
+import json,sys
+h=json.load(sys.stdin)['history']
+assert [(x['from_state'],x['to_state']) for x in h]==[('Draft','Planning'),('Planning','Building'),('Building','Complete'),('Complete','Amending'),('Amending','Draft')], h
+assert h[0]['seconds_in_from_state'] is None and all(x['seconds_in_from_state']>=0 for x in h[1:]), h
+assert [x['reason'] for x in h]==[None,None,None,'spec changed',None], h
+"
+$BIN --db "$SHD" task history --task endpoint | grep "Building → Complete  (after .* in Building)$" >/dev/null
+$BIN --db "$SHD" task history --task endpoint | grep "Complete → Amending  (after .* in Complete): spec changed$" >/dev/null
+$BIN --db "$SHD" --json task show --task endpoint --with-history | python3 -c "import json,sys;assert json.load(sys.stdin)['history'][3]['reason']=='spec changed'"
+# Lifted parents are recorded too.
+$BIN --db "$SHD" --json feature history --feature api | python3 -c "import json,sys;assert [x['to_state'] for x in json.load(sys.stdin)['history']]==['Planning','Building'], 'feature'"
+$BIN --db "$SHD" module history --module core | grep "Draft → Planning" >/dev/null
+$BIN --db "$SHD" --json task show --task endpoint | python3 -c "import json,sys;assert 'history' not in json.load(sys.stdin)"
+$BIN --db "$SHD" --json task show --task endpoint --with-history | python3 -c "import json,sys;assert len(json.load(sys.stdin)['history'])==5"
+$BIN --db "$SHD" task show --task endpoint --with-history | grep "^history:$" >/dev/null
+$BIN --db "$SHD" --json module show --module core --with-history | python3 -c "import json,sys;assert len(json.load(sys.stdin)['history'])==2"
+# Creating past Draft records each step walked.
+$BIN --db "$SHD" task add --feature api started --start >/dev/null
+$BIN --db "$SHD" --json task history --task started | python3 -c "
+import json,sys
+h=json.load(sys.stdin)['history']
+assert [(x['from_state'],x['to_state']) for x in h]==[('Draft','Planning'),('Planning','Building')], h"
+$BIN --db "$SHD" feature add --module core planned --plan >/dev/null
+$BIN --db "$SHD" feature history --feature planned | grep "Draft → Planning" >/dev/null
+$BIN --db "$SHD" module add --project sh-app side --start >/dev/null
+[ "$($BIN --db "$SHD" --json module history --module side | python3 -c "import json,sys;print(len(json.load(sys.stdin)['history']))")" = 2 ]
+$BIN --db "$SHD" task remove --task started >/dev/null
+$BIN --db "$SHD" feature remove --feature planned >/dev/null
+$BIN --db "$SHD" module remove --module side >/dev/null
+$BIN --db "$SHD" project complete --project sh-app --force --reason "shipped" >/dev/null
+$BIN --db "$SHD" --json project show --project sh-app --with-history | python3 -c "import json,sys;h=json.load(sys.stdin)['history'];assert [(x['from_state'],x['to_state'],x['reason']) for x in h]==[('incomplete','complete','shipped')], h"
+# Removing an entity removes its history, cascades included.
+$BIN --db "$SHD" project remove --project sh-app --cascade >/dev/null
+[ "$(python3 -c "import sqlite3,sys;print(sqlite3.connect(sys.argv[1]).execute('SELECT COUNT(*) FROM state_history').fetchone()[0])" "$SHD")" = "0" ]
+rm -f "$SHD"*
+
+echo "--- next --about ---"
+ABDB="/tmp/lopen-memory-smoke-about.db"
+rm -f "$ABDB"*
+$BIN --db "$ABDB" project add ab-app /ab >/dev/null
+$BIN --db "$ABDB" module add --project ab-app core --state Building >/dev/null
+$BIN --db "$ABDB" feature add --module core api --state Building >/dev/null
+$BIN --db "$ABDB" task add --feature api routes "HTTP routes" --state Building >/dev/null
+$BIN --db "$ABDB" task add --feature api cache-layer "Add a Redis cache" --state Building >/dev/null
+$BIN --db "$ABDB" task add --feature api docs "Write the docs" >/dev/null
+$BIN --db "$ABDB" task add --feature api warmup "Prime entries" --details "Fill the CACHE on boot" >/dev/null
+# Without --about: in-progress tasks first, then Draft, each tier in its own order.
+$BIN --db "$ABDB" --json next --project ab-app --count 4 | python3 -c "import json,sys;assert [c['name'] for c in json.load(sys.stdin)]==['routes','cache-layer','docs','warmup']"
+# With it: matches move up within their tier only; the Draft match stays behind every in-progress task.
+$BIN --db "$ABDB" --json next --project ab-app --count 4 --about "cache" | python3 -c "
+import json,sys
+got=json.load(sys.stdin)
+assert [c['name'] for c in got]==['cache-layer','routes','warmup','docs'], [c['name'] for c in got]
+assert got[0]['about']=={'score':5,'matches':[{'term':'cache','in':['name','description']}]}, got[0]
+assert got[1]['about']['matches']==[] and got[2]['about']['matches']==[{'term':'cache','in':['details']}], got
+"
+$BIN --db "$ABDB" --json next --project ab-app --count 4 --about "docs" | python3 -c "import json,sys;assert [c['name'] for c in json.load(sys.stdin)]==['routes','cache-layer','docs','warmup']"
+$BIN --db "$ABDB" next --project ab-app --about "redis http" --count 2 | grep "^about: *redis (description)$" >/dev/null
+$BIN --db "$ABDB" next --project ab-app --about "nothing-here" | grep "^about: *no match$" >/dev/null
+# --require-match drops what matches nothing; the count applies after.
+$BIN --db "$ABDB" --json next --project ab-app --count 5 --about cache --require-match | python3 -c "import json,sys;assert [c['name'] for c in json.load(sys.stdin)]==['cache-layer','warmup']"
+$BIN --db "$ABDB" next --project ab-app --about zzz --require-match | grep "no open tasks in this project matching --about" >/dev/null
+expect_exit 1 $BIN --db "$ABDB" next --project ab-app --about "  "
+expect_exit 2 $BIN --db "$ABDB" next --project ab-app --require-match
+rm -f "$ABDB"*
+
+echo "--- transition-all ---"
+TADB="/tmp/lopen-memory-smoke-transition-all.db"
+rm -f "$TADB"*
+$BIN --db "$TADB" project add ta-app /ta >/dev/null
+$BIN --db "$TADB" module add --project ta-app core --state Building >/dev/null
+$BIN --db "$TADB" feature add --module core api --state Building >/dev/null
+$BIN --db "$TADB" task add --feature api schema --state Building >/dev/null
+$BIN --db "$TADB" task add --feature api routes --state Building >/dev/null
+$BIN --db "$TADB" task add --feature api docs >/dev/null
+$BIN --db "$TADB" task add --feature api done-already --state Building >/dev/null
+$BIN --db "$TADB" task transition --task done-already Complete >/dev/null
+# schema waits on routes, listed after it but moving in the same batch.
+$BIN --db "$TADB" task depend --task schema --on routes >/dev/null
+# docs is Draft, so Complete is invalid for it; --strict changes nothing.
+expect_exit 1 $BIN --db "$TADB" task transition-all --feature api Complete --strict
+$BIN --db "$TADB" task transition-all --feature api Complete --strict 2>&1 | grep "nothing was changed (--strict)" >/dev/null
+[ "$($BIN --db "$TADB" --json task show --task schema | python3 -c "import json,sys;print(json.load(sys.stdin)['state'])")" = "Building" ]
+$BIN --db "$TADB" --json task transition-all --feature api Complete --reason "shipped" | python3 -c "
+import json,sys
+got=json.load(sys.stdin)
+assert [(r['task'],r['from'],r['to'],r['result']) for r in got]==[('schema','Building','Complete','transitioned'),('routes','Building','Complete','transitioned'),('docs','Draft','Complete','invalid'),('done-already','Complete','Complete','no-op')], got
+assert 'invalid transition' in got[2]['error'], got[2]
+"
+$BIN --db "$TADB" task show --task schema | grep "Building → Complete: shipped" >/dev/null
+$BIN --db "$TADB" --json task history --task schema | python3 -c "import json,sys;assert json.load(sys.stdin)['history'][-1]['to_state']=='Complete'"
+$BIN --db "$TADB" task transition-all --feature api Planning | grep "^4 task(s) in api: 1 transitioned, 0 already Planning, 3 invalid$" >/dev/null
+$BIN --db "$TADB" task transition-all --feature api Complete 2>&1 | grep "^task docs: invalid: invalid transition: Planning → Complete" >/dev/null
+# A dependency outside the batch that is not Complete holds the task back.
+$BIN --db "$TADB" feature add --module core ui --state Building >/dev/null
+$BIN --db "$TADB" task add --feature ui page --state Building >/dev/null
+$BIN --db "$TADB" task depend --task page --on docs >/dev/null
+$BIN --db "$TADB" --json task transition-all --feature ui Complete | python3 -c "import json,sys;r=json.load(sys.stdin)[0];assert r['result']=='invalid' and 'docs [Planning]' in r['error'], r"
+# Features of a module: a feature with tasks left is invalid; a locked one too.
+$BIN --db "$TADB" feature add --module core empty --state Building >/dev/null
+$BIN --db "$TADB" feature lock --feature empty >/dev/null
+$BIN --db "$TADB" --json feature transition-all --module core Complete | python3 -c "
+import json,sys
+got={r['feature']:r for r in json.load(sys.stdin)}
+assert got['api']['result']=='invalid' and 'incomplete task(s): docs [Planning]' in got['api']['error'], got
+assert got['ui']['result']=='invalid' and got['empty']['result']=='invalid' and 'locked' in got['empty']['error'], got
+"
+$BIN --db "$TADB" feature unlock --feature empty >/dev/null
+$BIN --db "$TADB" feature transition-all --module core Complete | grep "^feature empty: Building → Complete$" >/dev/null
+expect_exit 1 $BIN --db "$TADB" task transition-all --feature api Finished
+expect_exit 1 $BIN --db "$TADB" task transition-all --feature nope Complete
+rm -f "$TADB"*
+
+echo "--- link to finished work ---"
+LCDB="/tmp/lopen-memory-smoke-link-complete.db"
+rm -f "$LCDB"*
+$BIN --db "$LCDB" project add lc-app /lc >/dev/null
+$BIN --db "$LCDB" module add --project lc-app core --state Building >/dev/null
+$BIN --db "$LCDB" feature add --module core api --state Building >/dev/null
+$BIN --db "$LCDB" task add --feature api shipped --state Building >/dev/null
+$BIN --db "$LCDB" task add --feature api follow-up >/dev/null
+$BIN --db "$LCDB" research add lc-notes "notes" >/dev/null
+# Open targets link quietly.
+[ -z "$($BIN --db "$LCDB" research link --research lc-notes --task follow-up 2>&1 >/dev/null)" ]
+$BIN --db "$LCDB" --json research link --research lc-notes --task follow-up 2>/dev/null | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['target_state']=='Draft' and v['warning'] is None, v"
+$BIN --db "$LCDB" task transition --task shipped Complete >/dev/null
+# Each kind warns from both sides when finished, and still links.
+$BIN --db "$LCDB" research link --research lc-notes --task shipped 2>&1 >/dev/null | grep "^warning: linking research lc-notes to task shipped, which is Complete; .*--force" >/dev/null
+$BIN --db "$LCDB" task link-research --task shipped --research lc-notes 2>&1 >/dev/null | grep "which is Complete" >/dev/null
+$BIN --db "$LCDB" --json task list-research --task shipped | grep lc-notes >/dev/null
+$BIN --db "$LCDB" task transition --task follow-up Planning >/dev/null
+$BIN --db "$LCDB" task transition --task follow-up Building >/dev/null
+$BIN --db "$LCDB" task transition --task follow-up Complete >/dev/null
+$BIN --db "$LCDB" feature transition --feature api Complete >/dev/null
+$BIN --db "$LCDB" research link --research lc-notes --feature api 2>&1 >/dev/null | grep "to feature api, which is Complete" >/dev/null
+$BIN --db "$LCDB" feature link-research --feature api --research lc-notes 2>&1 >/dev/null | grep "to feature api, which is Complete" >/dev/null
+$BIN --db "$LCDB" module transition --module core Complete >/dev/null
+$BIN --db "$LCDB" research link --research lc-notes --module core 2>&1 >/dev/null | grep "to module core, which is Complete" >/dev/null
+$BIN --db "$LCDB" module link-research --module core --research lc-notes 2>&1 >/dev/null | grep "to module core, which is Complete" >/dev/null
+$BIN --db "$LCDB" --json research link --research lc-notes --project lc-app 2>/dev/null | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['target_state']=='active' and v['warning'] is None, v"
+$BIN --db "$LCDB" project complete --project lc-app >/dev/null
+$BIN --db "$LCDB" --json project link-research --project lc-app --research lc-notes 2>/dev/null | python3 -c "
+import json,sys
+v=json.load(sys.stdin)
+assert v['target_state']=='completed' and 'which is completed' in v['warning'] and v['linked'], v
+"
+$BIN --db "$LCDB" research link --research lc-notes --project lc-app 2>&1 >/dev/null | grep "to project lc-app, which is completed" >/dev/null
+# --force and the config key both link silently.
+[ -z "$($BIN --db "$LCDB" research link --research lc-notes --task shipped --force 2>&1 >/dev/null)" ]
+[ -z "$($BIN --db "$LCDB" module link-research --module core --research lc-notes --force 2>&1 >/dev/null)" ]
+$BIN --db "$LCDB" --json research link --research lc-notes --feature api --force | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['target_state']=='Complete' and v['warning'] is None, v"
+$BIN config set allow_complete_links true >/dev/null
+[ -z "$($BIN --db "$LCDB" research link --research lc-notes --project lc-app 2>&1 >/dev/null)" ]
+$BIN config unset allow_complete_links >/dev/null
+rm -f "$LCDB"*
+echo "ok"
+echo
+
+echo "--- research sections ---"
+RSDB="/tmp/lopen-memory-smoke-sections.db"
+rm -f "$RSDB"*
+$BIN --db "$RSDB" research add rs-notes "notes" >/dev/null
+# Content with ordinary headings and a fenced section-like line stays whole.
+printf 'Intro.\n\n## Summary\nplain heading\n\n```md\n## fake\n```\n' > /tmp/lopen-memory-smoke-sections.md
+$BIN --db "$RSDB" research set-content --research rs-notes --file /tmp/lopen-memory-smoke-sections.md >/dev/null
+$BIN --db "$RSDB" --json research show --research rs-notes | python3 -c "
+import json,sys
+v=json.load(sys.stdin)
+assert v['content']==open('/tmp/lopen-memory-smoke-sections.md').read() and v['sections']==[], v
+"
+$BIN --db "$RSDB" research set-section --research rs-notes --section benchmarks "$(printf 'p50 3ms\n### detail\n```\n## not-a-section\n```')" | grep "^added section benchmarks of research: rs-notes$" >/dev/null
+printf 'quote one\n\n> quoted\n' | $BIN --db "$RSDB" research set-section --research rs-notes --section quotes - >/dev/null
+# Round trip: each section comes back exactly, the preamble is untouched.
+[ "$($BIN --db "$RSDB" research show --research rs-notes --section benchmarks)" = "$(printf 'p50 3ms\n### detail\n```\n## not-a-section\n```')" ]
+$BIN --db "$RSDB" --json research show --research rs-notes --section quotes | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['content']=='quote one\n\n> quoted' and v['section']=='quotes', v"
+$BIN --db "$RSDB" --json research show --research rs-notes | python3 -c "
+import json,sys
+v=json.load(sys.stdin)
+assert v['sections']==['benchmarks','quotes'], v
+assert v['content'].startswith(open('/tmp/lopen-memory-smoke-sections.md').read().rstrip()+'\n\n## benchmarks\n'), v
+"
+$BIN --db "$RSDB" research show --research rs-notes | grep "^sections: *benchmarks, quotes$" >/dev/null
+# Updating one section leaves the others alone.
+$BIN --db "$RSDB" --json research set-section --research rs-notes --section benchmarks "p50 2ms" | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['action']=='updated' and v['sections']==['benchmarks','quotes'], v"
+[ "$($BIN --db "$RSDB" research show --research rs-notes --section quotes)" = "$(printf 'quote one\n\n> quoted')" ]
+[ "$($BIN --db "$RSDB" research show --research rs-notes --section benchmarks)" = "p50 2ms" ]
+# Bad names, text that would split, empty text, and unknown sections exit 1.
+expect_exit 1 $BIN --db "$RSDB" research set-section --research rs-notes --section Bench "x"
+expect_exit 1 $BIN --db "$RSDB" research set-section --research rs-notes --section a--b "x"
+expect_exit 1 $BIN --db "$RSDB" research set-section --research rs-notes --section extra "$(printf 'a\n## other\nb')"
+expect_exit 1 $BIN --db "$RSDB" research set-section --research rs-notes --section extra "$(printf '```\nopen fence')"
+expect_exit 1 $BIN --db "$RSDB" research set-section --research rs-notes --section extra ""
+expect_exit 1 $BIN --db "$RSDB" research show --research rs-notes --section missing
+$BIN --db "$RSDB" research show --research rs-notes --section missing 2>&1 | grep "no section 'missing' (sections: benchmarks, quotes)" >/dev/null
+# Removing needs --remove-section and restores the content before it was added.
+$BIN --db "$RSDB" research set-section --research rs-notes --section quotes --remove-section | grep "^removed section quotes" >/dev/null
+$BIN --db "$RSDB" research set-section --research rs-notes --section benchmarks --remove-section >/dev/null
+$BIN --db "$RSDB" --json research show --research rs-notes | python3 -c "
+import json,sys
+v=json.load(sys.stdin)
+assert v['content']==open('/tmp/lopen-memory-smoke-sections.md').read() and v['sections']==[], repr(v['content'])
+"
+expect_exit 1 $BIN --db "$RSDB" research set-section --research rs-notes --section quotes --remove-section
+# set-content still replaces the whole body.
+$BIN --db "$RSDB" research set-section --research rs-notes --section a "one" >/dev/null
+$BIN --db "$RSDB" research set-content --research rs-notes "flat" >/dev/null
+$BIN --db "$RSDB" --json research show --research rs-notes | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['content']=='flat' and v['sections']==[], v"
+rm -f "$RSDB"* /tmp/lopen-memory-smoke-sections.md
+echo "ok"
+echo
+
+echo "--- feature clone ---"
+FCDB="/tmp/lopen-memory-smoke-feature-clone.db"
+rm -f "$FCDB"*
+$BIN --db "$FCDB" project add fc-app /fc >/dev/null
+$BIN --db "$FCDB" module add --project fc-app users >/dev/null
+$BIN --db "$FCDB" module add --project fc-app orders >/dev/null
+$BIN --db "$FCDB" feature add --module users crud "CRUD endpoints" --state Building --details "REST, paginated" --priority high >/dev/null
+$BIN --db "$FCDB" task add --feature crud create "POST" --state Building >/dev/null
+$BIN --db "$FCDB" task add --feature crud read "GET" --details "with filters" >/dev/null
+$BIN --db "$FCDB" task add --feature crud delete "DELETE" --priority low >/dev/null
+$BIN --db "$FCDB" task add --feature crud update "PUT" --before delete >/dev/null
+$BIN --db "$FCDB" task transition --task create Complete >/dev/null
+$BIN --db "$FCDB" research add fc-rest "REST conventions" >/dev/null
+$BIN --db "$FCDB" research link --research fc-rest --feature crud >/dev/null
+$BIN --db "$FCDB" research link --research fc-rest --task read >/dev/null
+$BIN --db "$FCDB" --json feature clone --feature crud --to-module orders --name crud | python3 -c "
+import json,sys
+v=json.load(sys.stdin)
+assert v['name']=='crud' and v['state']=='Draft' and v['description']=='CRUD endpoints' and v['details']=='REST, paginated' and v['priority']=='high', v
+assert v['cloned_from']['name']=='crud' and 'research_links' not in v, v
+assert [(t['name'],t['state'],t['feature_id']) for t in v['tasks']]==[(n,'Draft',v['id']) for n in ['create','read','update','delete']], v['tasks']
+assert v['tasks'][1]['details']=='with filters' and v['tasks'][3]['priority']=='low', v['tasks']
+"
+# Without the flag, no research is linked to the copy.
+$BIN --db "$FCDB" --json feature list-research --feature crud --module orders | python3 -c "import json,sys;assert json.load(sys.stdin)['research']==[]"
+# The source is untouched.
+$BIN --db "$FCDB" --json task show --task create --feature 1 | python3 -c "import json,sys;assert json.load(sys.stdin)['state']=='Complete'"
+$BIN --db "$FCDB" --json feature clone --feature crud --module users --to-module orders --name crud-v2 --with-research-links | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['research_links']==2, v"
+$BIN --db "$FCDB" --json feature list-research --feature crud-v2 | grep fc-rest >/dev/null
+$BIN --db "$FCDB" --json task list-research --task read --feature crud-v2 | grep fc-rest >/dev/null
+$BIN --db "$FCDB" feature clone --feature crud --module users --to-module users --name crud-copy | grep "^cloned feature crud as [0-9]*: crud-copy (module: users) with 4 task(s)$" >/dev/null
+# A taken name fails before anything is written.
+before=$($BIN --db "$FCDB" --json task list --feature crud --module orders | python3 -c "import json,sys;print(len(json.load(sys.stdin)))")
+expect_exit 1 $BIN --db "$FCDB" feature clone --feature crud --module users --to-module orders --name CRUD
+$BIN --db "$FCDB" feature clone --feature crud --module users --to-module orders --name crud 2>&1 | grep "a feature named .crud. already exists in module .orders." >/dev/null
+[ "$($BIN --db "$FCDB" --json feature list --module orders | python3 -c "import json,sys;print(len(json.load(sys.stdin)))")" = "2" ]
+[ "$($BIN --db "$FCDB" --json task list --feature crud --module orders | python3 -c "import json,sys;print(len(json.load(sys.stdin)))")" = "$before" ]
+$BIN --db "$FCDB" module lock --module orders >/dev/null
+expect_exit 4 $BIN --db "$FCDB" feature clone --feature crud --module users --to-module orders --name crud-v3
+expect_exit 1 $BIN --db "$FCDB" feature clone --feature nope --to-module orders --name x
+rm -f "$FCDB"*
+echo "ok"
+echo
+
+echo "--- research links paging ---"
+RPDB="/tmp/lopen-memory-smoke-links-paging.db"
+rm -f "$RPDB"*
+$BIN --db "$RPDB" project add rp-app /rp >/dev/null
+$BIN --db "$RPDB" module add --project rp-app core >/dev/null
+$BIN --db "$RPDB" feature add --module core api >/dev/null
+$BIN --db "$RPDB" research add conventions "house style" >/dev/null
+$BIN --db "$RPDB" research link --research conventions --project rp-app >/dev/null
+$BIN --db "$RPDB" research link --research conventions --module core >/dev/null
+$BIN --db "$RPDB" research link --research conventions --feature api >/dev/null
+# 400 linked tasks, written directly; names t000..t399 sort in id order.
+python3 - "$RPDB" <<'PY'
+import sqlite3, sys
+db = sqlite3.connect(sys.argv[1])
+fid = db.execute("SELECT id FROM features WHERE name='api'").fetchone()[0]
+rid = db.execute("SELECT id FROM research WHERE name='conventions'").fetchone()[0]
+for i in range(400):
+    cur = db.execute("INSERT INTO tasks (feature_id, name, position) VALUES (?, ?, ?)", (fid, "t%03d" % i, i))
+    db.execute("INSERT INTO research_tasks (research_id, task_id) VALUES (?, ?)", (rid, cur.lastrowid))
+db.commit()
+PY
+# show caps the list at 50 and counts everything.
+$BIN --db "$RPDB" --json research show --research conventions | python3 -c "
+import json,sys
+v=json.load(sys.stdin)
+assert len(v['linked_to'])==50, len(v['linked_to'])
+assert v['link_counts']=={'project':1,'module':1,'feature':1,'task':400,'total':403}, v['link_counts']
+assert v['links_omitted']==353, v['links_omitted']
+assert [l['type'] for l in v['linked_to'][:4]]==['project','module','feature','task'] and v['linked_to'][3]['name']=='t000', v['linked_to'][:4]
+assert v['linked_to'][3]['under']['type']=='feature', v['linked_to'][3]
+"
+$BIN --db "$RPDB" research show --research conventions | grep "^  and 353 more link(s) (use research links --research conventions)$" >/dev/null
+[ "$($BIN --db "$RPDB" research show --research conventions | grep -c '^    task ')" = "47" ]
+$BIN --db "$RPDB" --json research show --research conventions --links-limit 0 | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['linked_to']==[] and v['links_omitted']==403 and v['link_counts']['total']==403"
+$BIN --db "$RPDB" --json research show --research conventions --links-limit 1000 | python3 -c "import json,sys;v=json.load(sys.stdin);assert len(v['linked_to'])==403 and v['links_omitted']==0"
+$BIN --db "$RPDB" research show --research conventions --links-limit 1000 | grep "more link(s)" >/dev/null && exit 1
+expect_exit 1 $BIN --db "$RPDB" research show --research conventions --links-limit -1
+# links pages through the full set in the same order; pages join up exactly.
+$BIN --db "$RPDB" --json research links --research conventions > /tmp/lopen-memory-smoke-links-all.json
+for off in 0 100 200 300 400; do
+    $BIN --db "$RPDB" --json research links --research conventions --limit 100 --offset $off
+done | python3 -c "
+import json,sys
+pages=[json.loads(p) for p in sys.stdin.read().replace(']\n[', ']\x00[').split('\x00')]
+assert [len(p) for p in pages]==[100,100,100,100,3], [len(p) for p in pages]
+full=json.load(open('/tmp/lopen-memory-smoke-links-all.json'))
+assert [l['id'] for p in pages for l in p]==[l['id'] for l in full] and len(full)==403
+"
+$BIN --db "$RPDB" --json research links --research conventions --type task --limit 5 --offset 398 | python3 -c "import json,sys;v=json.load(sys.stdin);assert [l['name'] for l in v]==['t398','t399'], v"
+$BIN --db "$RPDB" --json research links --research conventions --type module | python3 -c "import json,sys;v=json.load(sys.stdin);assert [l['name'] for l in v]==['core'], v"
+$BIN --db "$RPDB" research links --research conventions --limit 10 --offset 20 | grep "^showing 21-30 of 403 link(s)$" >/dev/null
+$BIN --db "$RPDB" research links --research conventions --type task --offset 500 | grep "^no links at offset 500 (400 in all)$" >/dev/null
+[ "$($BIN --db "$RPDB" --json research links --research conventions --offset 500)" = "[]" ]
+# --dedupe drops the project, module, and feature links before paging.
+$BIN --db "$RPDB" --json research links --research conventions --dedupe --limit 2 | python3 -c "import json,sys;v=json.load(sys.stdin);assert [l['name'] for l in v]==['t000','t001'], v"
+expect_exit 1 $BIN --db "$RPDB" research links --research conventions --type research
+expect_exit 1 $BIN --db "$RPDB" research links --research conventions --offset -1
+rm -f "$RPDB"* /tmp/lopen-memory-smoke-links-all.json
+echo "ok"
+echo
+
+echo "--- archived projects ---"
+ARDB="/tmp/lopen-memory-smoke-archived.db"
+rm -f "$ARDB"*
+$BIN --db "$ARDB" project add old-app /old >/dev/null
+$BIN --db "$ARDB" project add new-app /new >/dev/null
+$BIN --db "$ARDB" module add --project old-app core >/dev/null
+$BIN --db "$ARDB" module add --project new-app core >/dev/null
+$BIN --db "$ARDB" feature add --module core --project old-app api >/dev/null
+$BIN --db "$ARDB" task add --feature api old-task >/dev/null
+$BIN --db "$ARDB" research add ar-notes "notes" >/dev/null
+$BIN --db "$ARDB" --json project archive --project old-app | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['archived'] is True, v"
+$BIN --db "$ARDB" project archive --project old-app | grep "already archived" >/dev/null
+# Hidden from list unless asked for.
+! $BIN --db "$ARDB" project list | grep old-app >/dev/null
+$BIN --db "$ARDB" project list --archived | grep "old-app.*\[archived\]" >/dev/null
+! $BIN --db "$ARDB" project list --archived | grep new-app >/dev/null
+[ "$($BIN --db "$ARDB" --json project list --all | python3 -c "import json,sys;print(len(json.load(sys.stdin)))")" = 2 ]
+expect_exit 2 $BIN --db "$ARDB" project list --archived --all
+# Reads still work, by id and by name.
+OLD_ID=$($BIN --db "$ARDB" --json project show --project old-app | python3 -c "import json,sys;print(json.load(sys.stdin)['id'])")
+$BIN --db "$ARDB" project show --project "$OLD_ID" | grep "^archived: *yes" >/dev/null
+$BIN --db "$ARDB" task show --task old-task >/dev/null
+# A name in both resolves to the active project.
+$BIN --db "$ARDB" --json module show --module core | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['project']=='new-app', v"
+# Changes inside are refused until unarchived.
+expect_exit 1 $BIN --db "$ARDB" task transition --task old-task Planning
+$BIN --db "$ARDB" task transition --task old-task Planning 2>&1 | grep "project old-app is archived; unarchive it first" >/dev/null
+expect_exit 1 $BIN --db "$ARDB" module add --project old-app extra
+expect_exit 1 $BIN --db "$ARDB" project set-description --project old-app "new words"
+expect_exit 1 $BIN --db "$ARDB" research link --research ar-notes --task old-task
+expect_exit 1 $BIN --db "$ARDB" feature clone --feature api --to-module core --to-project old-app --name api-copy
+$BIN --db "$ARDB" --json project unarchive --project old-app | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['archived'] is False, v"
+$BIN --db "$ARDB" task transition --task old-task Planning >/dev/null
+$BIN --db "$ARDB" module add --project old-app extra >/dev/null
+$BIN --db "$ARDB" project list | grep old-app >/dev/null
+rm -f "$ARDB"*
+
+echo "--- share bundles ---"
+SHDB="/tmp/lopen-memory-smoke-share.db"
+SHARE="/tmp/lopen-memory-smoke-share.lmshare"
+rm -f "$SHDB"* "$SHARE"
+$BIN --db "$SHDB" project add sh-app /sh "Shared app" >/dev/null
+$BIN --db "$SHDB" project add sh-private /private >/dev/null
+$BIN --db "$SHDB" module add --project sh-app core "Core" >/dev/null
+$BIN --db "$SHDB" module add --project sh-private core >/dev/null
+$BIN --db "$SHDB" feature add --module core --project sh-app api "API" >/dev/null
+$BIN --db "$SHDB" task add --feature api endpoints "Endpoints" >/dev/null
+$BIN --db "$SHDB" task add --feature api docs >/dev/null
+$BIN --db "$SHDB" task transition --task endpoints Planning 2>/dev/null >/dev/null
+$BIN --db "$SHDB" task rename --task docs api-docs >/dev/null
+$BIN --db "$SHDB" research add sh-notes "Pagination findings" >/dev/null
+$BIN --db "$SHDB" research add sh-secret "Private findings" >/dev/null
+$BIN --db "$SHDB" research link --research sh-notes --task endpoints >/dev/null
+$BIN --db "$SHDB" research link --research sh-secret --project sh-private >/dev/null
+$BIN --db "$SHDB" --json share create --project sh-app --out "$SHARE" | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['counts']=={'modules':1,'features':1,'tasks':2,'research':1}, v"
+# Every read command exercised answers the same from the bundle as from the source.
+for cmd in "project show --project sh-app" "module list --project sh-app" "feature list --module core --project sh-app" \
+    "task list --feature api" "task show --task endpoints" "task history --task endpoints" "task name-history --task api-docs" \
+    "research show --research sh-notes" "research links --research sh-notes" "research search pagination" \
+    "tree --project sh-app" "status --project sh-app" "find endpoints" "task list-research --task endpoints"; do
+    for fmt in "" "--json"; do
+        want=$($BIN --db "$SHDB" $fmt $cmd)
+        got=$($BIN --db "$SHDB" $fmt share open "$SHARE" $cmd)
+        if [ "$want" != "$got" ]; then
+            echo "FAIL: share open differs for: $fmt $cmd"
+            diff <(echo "$want") <(echo "$got")
+            exit 1
+        fi
+    done
+done
+# Only the shared project and the research linked into it go in.
+! $BIN --db "$SHDB" share open "$SHARE" project list | grep sh-private >/dev/null
+! $BIN --db "$SHDB" share open "$SHARE" research list | grep sh-secret >/dev/null
+$BIN --db "$SHDB" share open "$SHARE" --json research list | grep sh-notes >/dev/null
+# Mutations are refused before they run, and the source is untouched.
+expect_exit 1 $BIN --db "$SHDB" share open "$SHARE" task transition --task endpoints Building
+$BIN --db "$SHDB" share open "$SHARE" task add --feature api more 2>&1 | grep "share bundles are read-only: \`task add\`" >/dev/null
+expect_exit 1 $BIN --db "$SHDB" share open "$SHARE" use --project sh-app
+expect_exit 1 $BIN --db "$SHDB" share open "$SHARE" share open "$SHARE" project list
+$BIN --db "$SHDB" task show --task endpoints | grep "Planning" >/dev/null
+# Not a bundle, or a bundle from a newer schema.
+echo "nope" > /tmp/lopen-memory-smoke-notshare
+expect_exit 1 $BIN --db "$SHDB" share open /tmp/lopen-memory-smoke-notshare project list
+$BIN --db "$SHDB" setup export --out /tmp/lopen-memory-smoke-setup.tar.gz >/dev/null
+$BIN --db "$SHDB" share open /tmp/lopen-memory-smoke-setup.tar.gz project list 2>&1 | grep "is not a share bundle" >/dev/null
+python3 - "$SHARE" <<'PY'
+import io, json, sys, tarfile
+src = sys.argv[1]
+with tarfile.open(src, "r:gz") as t:
+    members = {m.name: t.extractfile(m).read() for m in t.getmembers()}
+manifest = json.loads(members["manifest.json"])
+manifest["schema_version"] += 1
+members["manifest.json"] = json.dumps(manifest).encode()
+with tarfile.open(src + ".newer", "w:gz") as t:
+    for name, data in members.items():
+        info = tarfile.TarInfo(name)
+        info.size = len(data)
+        t.addfile(info, io.BytesIO(data))
+PY
+$BIN --db "$SHDB" share open "$SHARE.newer" project list 2>&1 | grep "newer than this binary's" >/dev/null
+[ -z "$(ls /tmp/lopen-memory-share-* 2>/dev/null)" ]
+# Bodies kept in external files travel inside the bundle, so it opens
+# without the sharer's content directory.
+$BIN config set content_external_threshold 20 >/dev/null
+$BIN --db "$SHDB" research set-content --research sh-notes "Cursor pagination beats offsets on large tables." >/dev/null
+[ -f "$SHDB-content/1.md" ] || { echo "FAIL: share test content not external"; exit 1; }
+$BIN --db "$SHDB" share create --project sh-app --out "$SHARE" >/dev/null
+$BIN config unset content_external_threshold >/dev/null
+rm -rf "$SHDB-content"
+$BIN --db "$SHDB" share open "$SHARE" research show --research sh-notes 2>/tmp/sh.err | grep "Cursor pagination beats" >/dev/null \
+    || { echo "FAIL: external body not inlined in the share bundle"; exit 1; }
+[ ! -s /tmp/sh.err ] || { echo "FAIL: share open warned"; cat /tmp/sh.err; exit 1; }
+rm -rf "$SHDB"* "$SHARE" "$SHARE.newer" /tmp/lopen-memory-smoke-notshare /tmp/lopen-memory-smoke-setup.tar.gz /tmp/sh.err
+
+echo "--- transition graph ---"
+TGDB="/tmp/lopen-memory-smoke-graph.db"
+rm -f "$TGDB"*
+$BIN --db "$TGDB" project add tg-app /tg >/dev/null
+$BIN --db "$TGDB" module add --project tg-app core >/dev/null
+$BIN --db "$TGDB" feature add --module core api >/dev/null
+$BIN --db "$TGDB" --json task show --task "$($BIN --db "$TGDB" --json task add --feature api probe | python3 -c "import json,sys;print(json.load(sys.stdin)['id'])")" \
+    | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['allowed_transitions']==['Planning'], v"
+$BIN --db "$TGDB" feature show --feature api | grep "^next: *Planning$" >/dev/null
+$BIN --db "$TGDB" --json module show --module core | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['allowed_transitions']==['Planning'], v"
+$BIN --db "$TGDB" task transition --task probe Complete 2>&1 | grep "invalid transition: Draft → Complete (allowed from Draft: Planning; Complete is reached through Planning → Building)" >/dev/null
+# Explore the graph through the CLI alone: walk a fresh task along each path
+# found so far, read what show allows from there, and check that every
+# allowed move is taken and every other one refused. Then every state must
+# be reachable from Draft, and Draft from every state.
+python3 - "$BIN" "$TGDB" <<'PY'
+import json, subprocess, sys
+from collections import deque
+BIN, DB = sys.argv[1], sys.argv[2]
+STATES = ["Draft", "Planning", "Building", "Complete", "Amending", "Blocked"]
+counter = [0]
+def run(*args):
+    return subprocess.run([BIN, "--db", DB, "--json", *args], capture_output=True, text=True)
+def task_at(path):
+    counter[0] += 1
+    name = "walk-%d" % counter[0]
+    tid = json.loads(run("task", "add", "--feature", "api", name).stdout)["id"]
+    for state in path:
+        r = run("task", "transition", "--task", str(tid), state, "--reason", "graph walk")
+        assert r.returncode == 0, (path, state, r.stderr)
+    return tid
+edges = {}
+paths = {"Draft": []}
+queue = deque(["Draft"])
+while queue:
+    state = queue.popleft()
+    tid = task_at(paths[state])
+    allowed = json.loads(run("task", "show", "--task", str(tid)).stdout)["allowed_transitions"]
+    edges[state] = allowed
+    for target in STATES:
+        if target == state:
+            continue
+        r = run("task", "transition", "--task", str(task_at(paths[state])), target, "--reason", "graph walk")
+        if target in allowed:
+            assert r.returncode == 0, (state, target, r.stderr)
+        else:
+            assert r.returncode == 1 and "invalid transition" in r.stderr, (state, target, r.stderr)
+    for target in allowed:
+        if target not in paths:
+            paths[target] = paths[state] + [target]
+            queue.append(target)
+assert sorted(paths) == sorted(STATES), "unreachable from Draft: %s" % (set(STATES) - set(paths))
+for state in STATES:
+    seen, todo = {state}, [state]
+    while todo:
+        for n in edges[todo.pop()]:
+            if n not in seen:
+                seen.add(n)
+                todo.append(n)
+    assert "Draft" in seen, "Draft unreachable from %s" % state
+PY
+rm -f "$TGDB"*
+
+echo "--- trash ---"
+TRDB="/tmp/lopen-memory-smoke-trash.db"
+rm -f "$TRDB"*
+TR="$BIN --db $TRDB"
+$TR project add tr-app /tr "Trash app" >/dev/null
+$TR module add --project tr-app core "Core" >/dev/null
+$TR feature add --module core --project tr-app api "API" >/dev/null
+$TR task add --feature api endpoints "Endpoints" >/dev/null
+$TR task add --feature api docs >/dev/null
+$TR research add tr-notes "Findings" >/dev/null
+$TR research link --research tr-notes --task endpoints >/dev/null
+$TR --json module remove --project tr-app --module core --cascade --soft | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['trashed'] is True, v"
+# Gone from lists and resolvers, and the name is free.
+$TR module list --project tr-app | grep -q "no modules found"
+$TR research links --research tr-notes | grep -q "no links found"
+expect_exit 1 $TR task show --task endpoints 2>/dev/null
+$TR --json trash list | python3 -c "
+import json,sys
+v=json.load(sys.stdin)
+assert len(v)==1 and v[0]['type']=='module' and v[0]['ref']=='M1', v
+assert v[0]['contains']['features']==1 and v[0]['contains']['tasks']==2, v"
+$TR module add --project tr-app core "Taken" >/dev/null
+expect_exit 1 $TR trash restore --module core 2>/dev/null
+$TR trash restore --module core 2>&1 | grep -q "another module is named core"
+$TR module remove --project tr-app --module core --hard >/dev/null
+# Restore brings back the subtree with its ids and research links.
+$TR --json trash restore --module M1 | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['restored'] is True and v['skipped_links']==0, v"
+$TR --json task show --task endpoints | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['id']==1, v"
+$TR research links --research tr-notes | grep -q endpoints
+$TR trash list | grep -q "the trash is empty"
+# A child restores only once its parent is back; a purged parent is reported.
+$TR task remove --task docs --soft >/dev/null
+$TR feature remove --module core --feature api --cascade --soft >/dev/null
+$TR trash restore --task docs 2>&1 | grep -q "its feature F1 is in the trash"
+expect_exit 1 $TR trash restore --task docs 2>/dev/null
+expect_exit 1 $TR trash purge --older-than -1 2>/dev/null
+$TR trash purge --older-than 30 | grep -q "purged 0 item(s)"
+python3 -c "
+import sqlite3
+c=sqlite3.connect('$TRDB')
+c.execute(\"UPDATE trash SET deleted_at='2020-01-01T00:00:00Z' WHERE entity_type='feature'\")
+c.commit()"
+$TR trash purge --older-than 30 | grep -q "purged 1 item(s)"
+$TR trash restore --task docs 2>&1 | grep -q "was purged from the trash"
+# Research keeps its content until the trash is purged; soft_delete makes it the default.
+$TR config set soft_delete true >/dev/null
+$TR research remove --research tr-notes >/dev/null
+$TR --json trash list --type research | python3 -c "import json,sys;v=json.load(sys.stdin);assert [e['name'] for e in v]==['tr-notes'], v"
+$TR trash restore --research tr-notes >/dev/null
+$TR research show --research tr-notes | grep -q "Findings"
+$TR research remove --research tr-notes --hard >/dev/null
+$TR config unset soft_delete >/dev/null
+$TR trash list --type research | grep -q "the trash is empty"
+expect_exit 1 $TR trash list --type widget 2>/dev/null
+expect_exit 2 $TR trash restore 2>/dev/null
+$TR trash purge | grep -q "purged 1 item(s)"
+rm -f "$TRDB"*
+
+echo "--- db backup ---"
+BKDIR="/tmp/lopen-memory-smoke-backup"
+rm -rf "$BKDIR"
+mkdir -p "$BKDIR"
+BK="$BIN --db $BKDIR/memory.db"
+$BK project add bk-app /bk >/dev/null
+FIRST=$($BK --json db backup | python3 -c "
+import json,os,sys
+v=json.load(sys.stdin)
+assert os.path.basename(v['path']).startswith('memory-backup-') and v['bytes']==os.path.getsize(v['path']), v
+print(v['path'])")
+$BK project add bk-later /later >/dev/null
+$BK db backup --keep 2 >/dev/null
+$BK --json db backup --keep 2 | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['removed']==['$FIRST'], v"
+[ "$(ls "$BKDIR" | grep -c '^memory-backup-')" -eq 2 ]
+$BK db backup --to "$BKDIR/named.db" | grep -q "wrote backup $BKDIR/named.db"
+expect_exit 1 $BK db backup --to "$BKDIR/named.db" 2>/dev/null
+expect_exit 1 $BK db backup --to "$BKDIR/other.db" --keep 1 2>/dev/null
+expect_exit 1 $BK db backup --keep 0 2>/dev/null
+# Restore checks the file before touching the live database.
+echo "not a database" > "$BKDIR/junk.db"
+expect_exit 1 $BK db restore --from "$BKDIR/junk.db" 2>/dev/null
+python3 -c "import sqlite3;sqlite3.connect('$BKDIR/foreign.db').execute('CREATE TABLE t(x)')"
+$BK db restore --from "$BKDIR/foreign.db" 2>&1 | grep -q "not a lopen-memory database"
+expect_exit 1 $BK db restore --from "$BKDIR/memory.db" 2>/dev/null
+expect_exit 1 $BK db restore --from "$BKDIR/missing.db" 2>/dev/null
+$BK project list | grep -q bk-later
+# A good backup replaces the database, and the replaced one is kept.
+cp "$BKDIR/named.db" "$BKDIR/old.db"
+python3 -c "import sqlite3;c=sqlite3.connect('$BKDIR/old.db');c.execute('DELETE FROM projects WHERE name=\"bk-later\"');c.execute('PRAGMA user_version=17');c.commit()"
+PREV=$($BK --json db restore --from "$BKDIR/old.db" | python3 -c "
+import json,sys
+v=json.load(sys.stdin)
+assert v['migrated_from']==17 and v['schema_version']>17, v
+print(v['previous'])")
+$BK project list | grep -q bk-app
+if $BK project list | grep bk-later >/dev/null; then echo "FAIL: restore kept a later project"; exit 1; fi
+$BK db restore --from "$PREV" >/dev/null
+$BK project list | grep -q bk-later
+# Bodies kept in external files go into the backup, so a restore brings
+# back the content of the time it was taken.
+$BIN config set content_external_threshold 20 >/dev/null
+$BK research add bk-notes "Backed-up findings" >/dev/null
+$BK research set-content --research bk-notes "The body as it was at backup time." >/dev/null
+[ -f "$BKDIR/memory.db-content/1.md" ] || { echo "FAIL: backup test content not external"; exit 1; }
+$BK db backup --to "$BKDIR/with-content.db" >/dev/null
+$BK research set-content --research bk-notes "The body as it was changed afterwards." >/dev/null
+$BK db restore --from "$BKDIR/with-content.db" >/dev/null
+$BIN config unset content_external_threshold >/dev/null
+$BK research show --research bk-notes | grep -q "as it was at backup time" \
+    || { echo "FAIL: restore paired old rows with the current content file"; exit 1; }
+rm -rf "$BKDIR"
+
+echo "--- schema migrations ---"
+V0DB="/tmp/lopen-memory-smoke-v0.db"
+rm -f "$V0DB"*
+# A database as the first release wrote it: the original tables, user_version 0.
+v0_fixture() {
+    python3 - "$1" "$2" <<'PY'
+import sqlite3, sys
+c = sqlite3.connect(sys.argv[1])
+states = "CHECK(state IN ('Draft','Planning','Building','Complete','Amending'))"
+c.executescript(f"""
+CREATE TABLE projects (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL UNIQUE, path TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '', completed INTEGER NOT NULL DEFAULT 0,
+    updated_at TEXT NOT NULL DEFAULT (datetime('now')));
+CREATE TABLE modules (id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE, name TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '', details TEXT NOT NULL DEFAULT '',
+    state TEXT NOT NULL DEFAULT 'Draft' {states}, last_worked_on TEXT NOT NULL DEFAULT (datetime('now')),
+    UNIQUE(project_id, name));
+CREATE TABLE features (id INTEGER PRIMARY KEY AUTOINCREMENT,
+    module_id INTEGER NOT NULL REFERENCES modules(id) ON DELETE CASCADE, name TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '', details TEXT NOT NULL DEFAULT '',
+    state TEXT NOT NULL DEFAULT 'Draft' {states}, last_worked_on TEXT NOT NULL DEFAULT (datetime('now')),
+    UNIQUE(module_id, name));
+CREATE TABLE tasks (id INTEGER PRIMARY KEY AUTOINCREMENT,
+    feature_id INTEGER NOT NULL REFERENCES features(id) ON DELETE CASCADE, name TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '', details TEXT NOT NULL DEFAULT '',
+    state TEXT NOT NULL DEFAULT 'Draft' {states}, last_worked_on TEXT NOT NULL DEFAULT (datetime('now')),
+    UNIQUE(feature_id, name));
+CREATE TABLE research (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT '', content TEXT NOT NULL DEFAULT '', source TEXT NOT NULL DEFAULT '',
+    researched_at TEXT NOT NULL DEFAULT (datetime('now')), created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    updated_at TEXT NOT NULL DEFAULT (datetime('now')));
+CREATE TABLE research_projects (research_id INTEGER NOT NULL REFERENCES research(id) ON DELETE CASCADE,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE, PRIMARY KEY (research_id, project_id));
+CREATE TABLE research_modules (research_id INTEGER NOT NULL REFERENCES research(id) ON DELETE CASCADE,
+    module_id INTEGER NOT NULL REFERENCES modules(id) ON DELETE CASCADE, PRIMARY KEY (research_id, module_id));
+CREATE TABLE research_features (research_id INTEGER NOT NULL REFERENCES research(id) ON DELETE CASCADE,
+    feature_id INTEGER NOT NULL REFERENCES features(id) ON DELETE CASCADE, PRIMARY KEY (research_id, feature_id));
+CREATE TABLE research_tasks (research_id INTEGER NOT NULL REFERENCES research(id) ON DELETE CASCADE,
+    task_id INTEGER NOT NULL REFERENCES tasks(id) ON DELETE CASCADE, PRIMARY KEY (research_id, task_id));
+INSERT INTO projects (name, path, description, updated_at) VALUES ('old-app', '/old', 'From v0', '2024-03-04 05:06:07');
+INSERT INTO modules (project_id, name, state) VALUES (1, 'core', 'Building');
+INSERT INTO features (module_id, name, state) VALUES (1, 'api', 'Planning');
+INSERT INTO tasks (feature_id, name, state) VALUES (1, 'endpoints', 'Planning'), (1, 'docs', 'Draft');
+INSERT INTO research (name, description, content) VALUES ('old-notes', 'Notes', 'pagination with cursors');
+INSERT INTO research_tasks VALUES (1, 1);
+{sys.argv[2]}
+PRAGMA user_version = 0;
+""")
+PY
+}
+v0_fixture "$V0DB" ""
+CURRENT=$($BIN --db "$V0DB.fresh" --json db stats | python3 -c "import json,sys;print(json.load(sys.stdin)['schema_version'])")
+$BIN --db "$V0DB" --json db stats | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['schema_version']==$CURRENT, v"
+$BIN --db "$V0DB" tree --project old-app | grep -q "endpoints \[Planning\]"
+$BIN --db "$V0DB" --json task show --task endpoints | python3 -c "import json,sys;v=json.load(sys.stdin);assert v['priority']=='medium' and v['position']==1, v"
+$BIN --db "$V0DB" research search cursors | grep -q old-notes
+$BIN --db "$V0DB" task list-research --task endpoints | grep -q old-notes
+$BIN --db "$V0DB" task transition --task endpoints Building >/dev/null 2>&1
+$BIN --db "$V0DB" task transition --task endpoints Blocked >/dev/null
+$BIN --db "$V0DB" project show --project old-app | grep -q "2024-03-04T05:06:07Z"
+python3 -c "
+import sqlite3
+c=sqlite3.connect('$V0DB')
+idx={r[0] for r in c.execute(\"SELECT name FROM sqlite_master WHERE type='index'\")}
+assert 'idx_research_tasks_task' in idx, idx"
+$BIN --db "$V0DB" db doctor >/dev/null
+# A step that fails rolls back the whole pass: nothing of the earlier steps stays.
+rm -f "$V0DB"*
+v0_fixture "$V0DB" "CREATE VIEW name_history AS SELECT 1 AS id;"
+expect_exit 2 $BIN --db "$V0DB" project list 2>/dev/null
+python3 -c "
+import sqlite3
+c=sqlite3.connect('$V0DB')
+assert c.execute('PRAGMA user_version').fetchone()[0]==0
+assert 'locked' not in [r[1] for r in c.execute('PRAGMA table_info(modules)')]
+assert c.execute(\"SELECT COUNT(*) FROM sqlite_master WHERE name='contexts'\").fetchone()[0]==0"
+# A database from a newer binary is not opened at all.
+rm -f "$V0DB"*
+$BIN --db "$V0DB" project add new-app /new >/dev/null
+python3 -c "import sqlite3;c=sqlite3.connect('$V0DB');c.execute('PRAGMA user_version=999');c.commit()"
+expect_exit 2 $BIN --db "$V0DB" project list 2>/dev/null
+$BIN --db "$V0DB" --json project list 2>&1 | python3 -c "import json,sys;v=json.load(sys.stdin)['error'];assert v['kind']=='newer_schema' and 'upgrade lopen-memory' in v['message'], v"
+rm -f "$V0DB"*
+
+echo "--- busy database ---"
+BZDB="/tmp/lopen-memory-smoke-busy.db"
+rm -f "$BZDB"*
+BZ="$BIN --db $BZDB"
+$BZ project add bz-app /bz >/dev/null
+$BZ module add --project bz-app core >/dev/null
+$BZ feature add --module core --project bz-app api >/dev/null
+# Hold the write lock from another connection for a while.
+hold_lock() {
+    python3 -c "
+import sqlite3, sys, time
+c = sqlite3.connect('$BZDB', isolation_level=None)
+c.execute('BEGIN IMMEDIATE')
+open('$BZDB.held', 'w').close()
+time.sleep($1)
+c.execute('COMMIT')" &
+    while [ ! -f "$BZDB.held" ]; do sleep 0.05; done
+    rm -f "$BZDB.held"
+}
+# A write waits out a lock shorter than the timeout...
+hold_lock 0.5
+LOPEN_MEMORY_SLOW_QUERY_MS=0 $BZ task add --feature api waited >/dev/null
+wait
+# ...and gives up with exit 5 and a clear message once it runs out.
+hold_lock 2
+expect_exit 5 $BZ --busy-timeout-ms 100 task add --feature api gave-up 2>/dev/null
+LOPEN_MEMORY_BUSY_TIMEOUT=100 LOPEN_MEMORY_SLOW_QUERY_MS=0 $BZ task add --feature api gave-up 2>&1 | grep -q "database is busy, retried for 100ms"
+LOPEN_MEMORY_SLOW_QUERY_MS=0 $BZ --json --busy-timeout-ms 100 task add --feature api gave-up 2>&1 | tail -1 | python3 -c "import json,sys;v=json.load(sys.stdin)['error'];assert v['kind']=='busy' and v['retried_ms']==100, v"
+# The busy message is the only report: no raw SQLite error beside it, and
+# the time spent waiting does not make the statement a slow query.
+set +e
+LOPEN_MEMORY_SLOW_QUERY_MS=50 $BZ --busy-timeout-ms 300 task add --feature api gave-up 2>/tmp/bz.err >/dev/null
+set -e
+[ "$(wc -l < /tmp/bz.err)" -eq 1 ] && grep -q "^error: database is busy, retried for 300ms" /tmp/bz.err \
+    || { echo "FAIL: busy report not alone on stderr"; cat /tmp/bz.err; exit 1; }
+set +e
+LOPEN_MEMORY_SLOW_QUERY_MS=50 $BZ --json --busy-timeout-ms 300 task add --feature api gave-up 2>/tmp/bz.err >/dev/null
+set -e
+[ "$(wc -l < /tmp/bz.err)" -eq 1 ] || { echo "FAIL: busy JSON report not alone on stderr"; cat /tmp/bz.err; exit 1; }
+rm -f /tmp/bz.err
+wait
+expect_exit 1 $BZ --busy-timeout-ms -1 task list --feature api 2>/dev/null
+# Two writers interleaving transactions never fail on each other.
+writer() {
+    for i in $(seq 1 15); do
+        LOPEN_MEMORY_SLOW_QUERY_MS=0 $BZ task add --feature api "$1-$i" >/dev/null || exit 1
+        LOPEN_MEMORY_SLOW_QUERY_MS=0 $BZ task transition --task "$1-$i" Planning >/dev/null 2>&1 || exit 1
+        LOPEN_MEMORY_SLOW_QUERY_MS=0 $BZ task set-details --task "$1-$i" --append "step $i" >/dev/null || exit 1
+    done
+}
+writer left & LEFT=$!
+writer right & RIGHT=$!
+wait $LEFT
+wait $RIGHT
+[ "$($BZ --json task list --feature api | python3 -c "import json,sys;print(sum(1 for t in json.load(sys.stdin) if t['state']=='Planning'))")" -eq 30 ]
+rm -f "$BZDB"*
+
+echo "--- name collisions ---"
+NCDB="/tmp/lopen-memory-smoke-names.db"
+rm -f "$NCDB"*
+NC="$BIN --db $NCDB"
+$NC project add myapp /nc >/dev/null
+$NC project add other /nc2 >/dev/null
+$NC module add --project myapp auth >/dev/null
+$NC module add --project myapp billing >/dev/null
+$NC feature add --module auth login >/dev/null
+$NC feature add --module auth logout >/dev/null
+$NC task add --feature login form >/dev/null
+$NC task add --feature login submit >/dev/null
+$NC research add jwt >/dev/null
+$NC research add oauth >/dev/null
+# Fail unless a command exits 1 with the given message and no panic.
+expect_taken() {
+    local msg=$1
+    shift
+    expect_exit 1 "$@" 2>/tmp/nc.err
+    if grep "panicked" /tmp/nc.err >/dev/null; then
+        echo "FAIL: panic: $*"
+        exit 1
+    fi
+    grep -F "$msg" /tmp/nc.err >/dev/null || { echo "FAIL: expected '$msg': $*"; cat /tmp/nc.err; exit 1; }
+}
+# Caught by the clash check, in any case.
+expect_taken "a project named 'myapp' already exists" $NC project add MyApp /x
+expect_taken "a project named 'myapp' already exists" $NC project rename --project other myapp
+expect_taken "a module named 'auth' already exists in project 'myapp'" $NC module add --project myapp Auth
+expect_taken "a module named 'auth' already exists in project 'myapp'" $NC module rename --module billing auth
+expect_taken "a feature named 'login' already exists in module 'auth'" $NC feature add --module auth LOGIN
+expect_taken "a feature named 'login' already exists in module 'auth'" $NC feature rename --feature logout --module auth login
+expect_taken "a task named 'form' already exists in feature 'login'" $NC task add --feature login Form
+expect_taken "a task named 'form' already exists in feature 'login'" $NC task rename --task submit --feature login form
+expect_taken "a research record named 'jwt' already exists" $NC research add JWT
+expect_taken "a research record named 'jwt' already exists" $NC research rename --research oauth jwt
+# Names another process takes between the clash check and the write hit the
+# schema's UNIQUE constraints instead; triggers stand in for that process.
+python3 - "$NCDB" <<'PY'
+import sqlite3, sys
+c = sqlite3.connect(sys.argv[1])
+for table, parent in [("projects", None), ("modules", "project_id"), ("features", "module_id"), ("tasks", "feature_id"), ("research", None)]:
+    cols = "name, path" if table == "projects" else ("name" if parent is None else parent + ", name")
+    vals = "NEW.name, ''" if table == "projects" else ("NEW.name" if parent is None else "NEW." + parent + ", NEW.name")
+    for event in ["INSERT", "UPDATE OF name"]:
+        c.execute(f"CREATE TRIGGER race_{table}_{event.split()[0].lower()} BEFORE {event} ON {table} "
+                  f"WHEN NEW.name = 'grabbed' BEGIN INSERT INTO {table} ({cols}) VALUES ({vals}); END")
+c.commit()
+PY
+expect_taken "a project named 'grabbed' already exists" $NC project add grabbed /x
+expect_taken "a project named 'grabbed' already exists" $NC project rename --project other grabbed
+expect_taken "a module named 'grabbed' already exists in project 'myapp'" $NC module add --project myapp grabbed
+expect_taken "a module named 'grabbed' already exists in project 'myapp'" $NC module rename --module billing grabbed
+expect_taken "a feature named 'grabbed' already exists in module 'auth'" $NC feature add --module auth grabbed
+expect_taken "a feature named 'grabbed' already exists in module 'auth'" $NC feature rename --feature logout --module auth grabbed
+expect_taken "a feature named 'grabbed' already exists in module 'billing'" $NC feature clone --feature login --module auth --to-module billing --name grabbed
+expect_taken "a task named 'grabbed' already exists in feature 'login'" $NC task add --feature login grabbed
+expect_taken "a task named 'grabbed' already exists in feature 'login'" $NC task rename --task submit --feature login grabbed
+expect_taken "a research record named 'grabbed' already exists" $NC research add grabbed
+expect_taken "a research record named 'grabbed' already exists" $NC research rename --research oauth grabbed
+expect_taken "a research record named 'grabbed' already exists" $NC research capture --task form --name grabbed "x"
+# Nothing was written by the refused commands.
+[ "$($NC --json project list | grep -c '"name"')" -eq 2 ] || { echo "FAIL: refused add left a project behind"; exit 1; }
+$NC module show --module billing | grep "billing" >/dev/null
+rm -f "$NCDB"* /tmp/nc.err
+echo "name collisions: ok"
+
+echo "--- pinned CLI output ---"
+# Exact output and exit codes of the core commands, so moving logic between
+# the library and the CLI cannot change what a script sees.
+PINDB="/tmp/lopen-memory-smoke-pinned.db"
+rm -f "$PINDB"*
+pinned() {
+    echo "\$ $*"
+    set +e
+    $BIN --db "$PINDB" --no-skill-check "$@" 2>&1
+    echo "[exit $?]"
+    set -e
+}
+{
+    pinned project add pin-app /pin pinned
+    pinned --json project add pin-other /pin2
+    pinned module add --project pin-app auth sign-in
+    pinned --json module add --project pin-app billing --start
+    pinned module add --project pin-other auth
+    pinned feature add --module auth --project pin-app login --plan
+    pinned --json feature add --module billing invoices
+    pinned task add --feature login form
+    pinned --json task add --feature login submit --start
+    pinned research add jwt tokens
+    pinned --json research add oauth
+    pinned project list
+    pinned --json project list
+    pinned module list --project pin-app
+    pinned --json module list --project pin-app
+    pinned feature list --module billing
+    pinned --json task list --feature login
+    pinned project show --project pin-app
+    pinned --json module show --module M1
+    pinned feature show --feature login
+    pinned --json task show --task form
+    pinned --json research show --research jwt
+    pinned module rename --module billing payments
+    pinned --json feature rename --feature invoices bills
+    pinned task rename --task submit send --update-references --dry-run
+    pinned --json research rename --research oauth oidc
+    pinned project rename --project pin-other pin-side
+    pinned project show --project pin-ap
+    pinned module show --module auth
+    pinned feature show --feature T1
+    pinned task show --task id:x
+    pinned module add --project pin-app Auth
+    pinned task transition --task form Complete
+    pinned project archive --project pin-side
+    pinned module set-description --module M3 frozen
+    pinned task set-description --task form auth
+    pinned --json module rename --module M1 identity --update-references
+} | sed -E 's/[0-9]{4}-[0-9]{2}-[0-9]{2}T[0-9]{2}:[0-9]{2}:[0-9]{2}Z/<ts>/g' > /tmp/pinned.out
+diff -u - /tmp/pinned.out <<'PINNED' || { echo "FAIL: pinned CLI output changed"; exit 1; }
+$ project add pin-app /pin pinned
+added project 1: pin-app
+[exit 0]
+$ --json project add pin-other /pin2
+{
+  "archived": false,
+  "completed": false,
+  "description": "",
+  "effectively_completed": false,
+  "id": 2,
+  "name": "pin-other",
+  "path": "/pin2",
+  "ref": "P2",
+  "state_mismatch": false,
+  "updated_at": "<ts>"
+}
+[exit 0]
+$ module add --project pin-app auth sign-in
+added module 1: auth (project: pin-app)
+[exit 0]
+$ --json module add --project pin-app billing --start
+{
+  "description": "",
+  "details": "",
+  "id": 2,
+  "last_worked_on": "<ts>",
+  "locked": false,
+  "name": "billing",
+  "path": "",
+  "project_id": 1,
+  "ref": "M2",
+  "state": "Building"
+}
+[exit 0]
+$ module add --project pin-other auth
+added module 3: auth (project: pin-other)
+[exit 0]
+$ feature add --module auth --project pin-app login --plan
+added feature 1: login (module: auth) [Draft → Planning]
+[exit 0]
+$ --json feature add --module billing invoices
+{
+  "description": "",
+  "details": "",
+  "id": 2,
+  "last_worked_on": "<ts>",
+  "locked": false,
+  "module_id": 2,
+  "name": "invoices",
+  "priority": "medium",
+  "ref": "F2",
+  "state": "Draft"
+}
+[exit 0]
+$ task add --feature login form
+added task 1: form (feature: login)
+[exit 0]
+$ --json task add --feature login submit --start
+{
+  "description": "",
+  "details": "",
+  "feature_id": 1,
+  "id": 2,
+  "last_worked_on": "<ts>",
+  "name": "submit",
+  "position": 2,
+  "priority": "medium",
+  "ref": "T2",
+  "state": "Building"
+}
+[exit 0]
+$ research add jwt tokens
+added research 1: jwt
+[exit 0]
+$ --json research add oauth
+{
+  "age_days": {
+    "created_at": 0,
+    "researched_at": 0,
+    "updated_at": 0
+  },
+  "archived": false,
+  "content": "",
+  "created_at": "<ts>",
+  "description": "",
+  "id": 2,
+  "name": "oauth",
+  "ref": "R2",
+  "researched_at": "<ts>",
+  "source": "",
+  "updated_at": "<ts>"
+}
+[exit 0]
+$ project list
+P1    pin-app              /pin                                     incomplete
+P2    pin-other            /pin2                                    incomplete
+[exit 0]
+$ --json project list
+[
+  {
+    "archived": false,
+    "completed": false,
+    "description": "pinned",
+    "effectively_completed": false,
+    "id": 1,
+    "name": "pin-app",
+    "path": "/pin",
+    "ref": "P1",
+    "state_mismatch": false,
+    "updated_at": "<ts>"
+  },
+  {
+    "archived": false,
+    "completed": false,
+    "description": "",
+    "effectively_completed": false,
+    "id": 2,
+    "name": "pin-other",
+    "path": "/pin2",
+    "ref": "P2",
+    "state_mismatch": false,
+    "updated_at": "<ts>"
+  }
+]
+[exit 0]
+$ module list --project pin-app
+M1    auth                 Draft        <ts>
+M2    billing              Building     <ts>
+[exit 0]
+$ --json module list --project pin-app
+[
+  {
+    "description": "sign-in",
+    "details": "",
+    "id": 1,
+    "last_worked_on": "<ts>",
+    "locked": false,
+    "name": "auth",
+    "path": "",
+    "project_id": 1,
+    "ref": "M1",
+    "state": "Draft"
+  },
+  {
+    "description": "",
+    "details": "",
+    "id": 2,
+    "last_worked_on": "<ts>",
+    "locked": false,
+    "name": "billing",
+    "path": "",
+    "project_id": 1,
+    "ref": "M2",
+    "state": "Building"
+  }
+]
+[exit 0]
+$ feature list --module billing
+F2    invoices             Draft        medium    <ts>
+[exit 0]
+$ --json task list --feature login
+[
+  {
+    "description": "",
+    "details": "",
+    "feature_id": 1,
+    "id": 1,
+    "last_worked_on": "<ts>",
+    "name": "form",
+    "position": 1,
+    "priority": "medium",
+    "ref": "T1",
+    "state": "Draft"
+  },
+  {
+    "description": "",
+    "details": "",
+    "feature_id": 1,
+    "id": 2,
+    "last_worked_on": "<ts>",
+    "name": "submit",
+    "position": 2,
+    "priority": "medium",
+    "ref": "T2",
+    "state": "Building"
+  }
+]
+[exit 0]
+$ project show --project pin-app
+id:             1
+ref:            P1
+name:           pin-app
+path:           /pin
+description:    pinned
+completed:      false
+updated_at:     <ts>
+
+modules:
+  M1    auth                 Draft
+  M2    billing              Building
+[exit 0]
+$ --json module show --module M1
+{
+  "allowed_transitions": [
+    "Planning"
+  ],
+  "blocked_reason": null,
+  "description": "sign-in",
+  "details": "",
+  "features": [
+    {
+      "blocked_reason": null,
+      "id": 1,
+      "name": "login",
+      "ref": "F1",
+      "state": "Planning"
+    }
+  ],
+  "id": 1,
+  "last_transition_reason": null,
+  "last_worked_on": "<ts>",
+  "locked": false,
+  "name": "auth",
+  "path": "",
+  "project": "pin-app",
+  "project_id": 1,
+  "ref": "M1",
+  "research": [],
+  "state": "Draft"
+}
+[exit 0]
+$ feature show --feature login
+id:             1
+ref:            F1
+name:           login
+module:         auth
+description:    
+details:        
+state:          Planning
+next:           Building | Draft
+priority:       medium
+last_worked_on: <ts>
+locked:         false
+
+tasks:
+  T1    form                 Draft     medium
+  T2    submit               Building  medium
+[exit 0]
+$ --json task show --task form
+{
+  "allowed_transitions": [
+    "Planning"
+  ],
+  "blocked_reason": null,
+  "blocks": [],
+  "depends_on": [],
+  "description": "",
+  "details": "",
+  "feature": "login",
+  "feature_id": 1,
+  "id": 1,
+  "last_transition_reason": null,
+  "last_worked_on": "<ts>",
+  "name": "form",
+  "position": 1,
+  "priority": "medium",
+  "ref": "T1",
+  "research": [],
+  "siblings": [
+    {
+      "current": true,
+      "id": 1,
+      "name": "form",
+      "position": 1,
+      "ref": "T1",
+      "state": "Draft"
+    },
+    {
+      "current": false,
+      "id": 2,
+      "name": "submit",
+      "position": 2,
+      "ref": "T2",
+      "state": "Building"
+    }
+  ],
+  "state": "Draft"
+}
+[exit 0]
+$ --json research show --research jwt
+{
+  "age_days": {
+    "created_at": 0,
+    "researched_at": 0,
+    "updated_at": 0
+  },
+  "archived": false,
+  "attachments": [],
+  "content": "",
+  "created_at": "<ts>",
+  "description": "tokens",
+  "id": 1,
+  "link_counts": {
+    "feature": 0,
+    "module": 0,
+    "project": 0,
+    "task": 0,
+    "total": 0
+  },
+  "linked_to": [],
+  "links_omitted": 0,
+  "name": "jwt",
+  "ref": "R1",
+  "researched_at": "<ts>",
+  "sections": [],
+  "source": "",
+  "updated_at": "<ts>"
+}
+[exit 0]
+$ module rename --module billing payments
+renamed module 2: billing → payments
+[exit 0]
+$ --json feature rename --feature invoices bills
+{
+  "description": "",
+  "details": "",
+  "id": 2,
+  "last_worked_on": "<ts>",
+  "locked": false,
+  "module_id": 2,
+  "name": "bills",
+  "priority": "medium",
+  "ref": "F2",
+  "state": "Draft"
+}
+[exit 0]
+$ task rename --task submit send --update-references --dry-run
+would rename task 2: submit → send
+no references found
+[exit 0]
+$ --json research rename --research oauth oidc
+{
+  "age_days": {
+    "created_at": 0,
+    "researched_at": 0,
+    "updated_at": 0
+  },
+  "archived": false,
+  "content": "",
+  "created_at": "<ts>",
+  "description": "",
+  "id": 2,
+  "name": "oidc",
+  "ref": "R2",
+  "researched_at": "<ts>",
+  "source": "",
+  "updated_at": "<ts>"
+}
+[exit 0]
+$ project rename --project pin-other pin-side
+renamed project 2: pin-other → pin-side
+[exit 0]
+$ project show --project pin-ap
+error: project not found: pin-ap; did you mean: pin-app?
+[exit 1]
+$ module show --module auth
+error: module name 'auth' is ambiguous; specify --project to narrow scope
+[exit 3]
+$ feature show --feature T1
+error: T1 is a task; --feature expects a feature
+[exit 1]
+$ task show --task id:x
+error: invalid id: id:x (expected id:<number>)
+[exit 1]
+$ module add --project pin-app Auth
+error: a module named 'auth' already exists in project 'pin-app'
+[exit 1]
+$ task transition --task form Complete
+error: invalid transition: Draft → Complete (allowed from Draft: Planning; Complete is reached through Planning → Building) for task form
+[exit 1]
+$ project archive --project pin-side
+archived project: pin-side
+[exit 0]
+$ module set-description --module M3 frozen
+error: project pin-side is archived; unarchive it first (project unarchive --project pin-side)
+[exit 1]
+$ task set-description --task form auth
+updated description for task: form
+[exit 0]
+$ --json module rename --module M1 identity --update-references
+{
+  "description": "sign-in",
+  "details": "",
+  "id": 1,
+  "last_worked_on": "<ts>",
+  "locked": false,
+  "name": "identity",
+  "path": "",
+  "project_id": 1,
+  "ref": "M1",
+  "references": [
+    {
+      "field": "description",
+      "id": 1,
+      "lines": [
+        {
+          "line": 1,
+          "text": "auth"
+        }
+      ],
+      "name": "form",
+      "occurrences": 1,
+      "type": "task"
+    }
+  ],
+  "state": "Draft"
+}
+[exit 0]
+PINNED
+rm -f "$PINDB"*
+
+echo "--- JSON errors ---"
+# Under --json a failure is one {"error": {...}} object per line on stderr,
+# with a stable code whose exit status is fixed: ambiguous 3, io 2, the
+# rest 1 (invalid_state 4 when locked).
+JEDB="/tmp/lopen-memory-smoke-json-errors.db"
+rm -f "$JEDB"*
+JE="$BIN --db $JEDB --no-skill-check"
+$JE project add je-app /je >/dev/null
+$JE project add je-side /je2 >/dev/null
+$JE module add --project je-app auth >/dev/null
+$JE module add --project je-side auth >/dev/null
+$JE feature add --module auth --project je-app login >/dev/null
+$JE task add --feature login form >/dev/null
+$JE research add jwt >/dev/null
+json_error() {
+    local want_exit="$1" want_code="$2" want_entity="$3"
+    shift 3
+    set +e
+    $JE --json "$@" >/tmp/je.out 2>/tmp/je.err
+    local got=$?
+    set -e
+    if [ "$got" != "$want_exit" ]; then
+        echo "FAIL: $* exited $got, expected $want_exit"; cat /tmp/je.err; exit 1
+    fi
+    if [ -s /tmp/je.out ]; then
+        echo "FAIL: $* wrote to stdout on failure"; cat /tmp/je.out; exit 1
+    fi
+    python3 - "$want_code" "$want_entity" /tmp/je.err <<'PY' || { echo "FAIL: $* error JSON"; cat /tmp/je.err; exit 1; }
+import json, sys
+code, entity, path = sys.argv[1:]
+lines = open(path).read().splitlines()
+assert len(lines) == 1, lines
+e = json.loads(lines[0])["error"]
+assert set(e) == {"code", "message", "entity"}, e
+assert e["code"] == code, e
+assert e["entity"] == (None if entity == "-" else entity), e
+assert e["message"] and not e["message"].startswith("error:"), e
+PY
+}
+json_error 1 not_found project project show --project nope
+json_error 1 not_found module module show --module nope --project je-app
+json_error 1 not_found feature feature show --feature nope
+json_error 1 not_found task task show --task nope
+json_error 1 not_found research research show --research nope
+json_error 3 ambiguous module module show --module auth
+json_error 1 conflict - module add --project je-app auth
+json_error 1 invalid_state - task transition --task form Complete
+json_error 1 invalid_input - task add --feature login ""
+json_error 2 io - import --file /tmp/lopen-memory-smoke-no-such-file.json
+json_error 2 io - task set-details --task form --file /tmp/lopen-memory-smoke-no-such-file.md
+$JE feature lock --feature login >/dev/null
+json_error 4 invalid_state - task add --feature login stray
+# Without --json the same failure is still a plain line.
+set +e
+$JE module show --module auth >/tmp/je.out 2>/tmp/je.err
+got=$?
+set -e
+[ "$got" = 3 ] || { echo "FAIL: ambiguous exited $got without --json"; exit 1; }
+grep -qx "error: module name 'auth' is ambiguous; specify --project to narrow scope" /tmp/je.err \
+    || { echo "FAIL: plain ambiguous error"; cat /tmp/je.err; exit 1; }
+$JE project archive --project je-side >/dev/null
+json_error 1 invalid_state - module set-description --module auth --project je-side frozen
+echo "ok: JSON errors"
+
+echo "--- NDJSON output ---"
+NDDB="/tmp/lopen-memory-smoke-ndjson.db"
+rm -f "$NDDB"*
+ND="$BIN --db $NDDB --no-skill-check"
+# An empty list is no lines at all, not prose.
+[ -z "$($ND --format ndjson research list)" ] || { echo "FAIL: empty ndjson research list printed something"; exit 1; }
+[ -z "$($ND --format ndjson project list)" ] || { echo "FAIL: empty ndjson project list printed something"; exit 1; }
+$ND project add nd-app /nd >/dev/null
+$ND project add nd-side /nd2 >/dev/null
+$ND research add nd-one "first, with a comma" >/dev/null
+$ND research add nd-two >/dev/null
+$ND --format ndjson project list | python3 -c "
+import json, sys
+lines = sys.stdin.read().splitlines()
+assert [json.loads(l)['name'] for l in lines] == ['nd-app', 'nd-side'], lines
+assert all(l.startswith('{') and ': ' not in l for l in lines), lines"
+$ND --format ndjson research list | python3 -c "
+import json, sys
+rows = [json.loads(l) for l in sys.stdin.read().splitlines()]
+assert [r['name'] for r in rows] == ['nd-one', 'nd-two'], rows
+assert rows[0]['description'] == 'first, with a comma' and 'links' in rows[0], rows"
+# The same rows as --json, one per line.
+diff <($ND --json research list | python3 -c "import json,sys;[print(json.dumps(r,sort_keys=True)) for r in json.load(sys.stdin)]") \
+    <($ND --format ndjson research list | python3 -c "import json,sys;[print(json.dumps(json.loads(l),sort_keys=True)) for l in sys.stdin]") \
+    || { echo "FAIL: ndjson rows differ from --json"; exit 1; }
+[ "$($ND --format ndjson project show --project nd-app | wc -l)" -eq 1 ] || { echo "FAIL: ndjson show is not one line"; exit 1; }
+$ND --format ndjson project show --project nd-app | python3 -c "import json,sys;assert json.loads(sys.stdin.read())['name']=='nd-app'"
+$ND --format json project list | python3 -c "import json,sys;assert len(json.load(sys.stdin))==2"
+$ND --format plain project list | grep -q "^P1 "
+$ND --format ndjson project show --project nope 2>&1 >/dev/null | python3 -c "import json,sys;assert json.loads(sys.stdin.read())['error']['code']=='not_found'"
+expect_exit 2 $ND --json --format ndjson project list 2>/dev/null
+expect_exit 2 $ND --format xml project list 2>/dev/null
+echo "ok: NDJSON output"
+
+echo "--- CSV and TSV output ---"
+CVDB="/tmp/lopen-memory-smoke-csv.db"
+rm -f "$CVDB"*
+CV="$BIN --db $CVDB --no-skill-check"
+# An empty list is still its header.
+[ "$($CV --format csv project list)" = "archived,completed,description,effectively_completed,id,name,path,ref,state_mismatch,updated_at" ] \
+    || { echo "FAIL: empty csv project list is not its header"; exit 1; }
+$CV project add cv-app /cv >/dev/null
+$CV module add --project cv-app core >/dev/null
+$CV feature add --module core reports >/dev/null
+$CV task add --feature reports export >/dev/null
+$CV research add cv-notes "$(printf 'a, b "c"\nd\te\\f')" >/dev/null
+# Header matches the --json keys and every row parses back to the same values.
+for cmd in "project list" "module list --project cv-app" "feature list --module core" \
+    "task list --feature reports" "research list" "research search notes"; do
+    for fmt in csv tsv; do
+        $CV --json $cmd > /tmp/cv.json
+        $CV --format $fmt $cmd > /tmp/cv.out
+        python3 - "$fmt" "$cmd" <<'PY' || { echo "FAIL: --format $fmt $cmd"; cat /tmp/cv.out; exit 1; }
+import csv, json, sys
+fmt, cmd = sys.argv[1:]
+rows = json.load(open("/tmp/cv.json"))
+if fmt == "csv":
+    table = list(csv.reader(open("/tmp/cv.out", newline="")))
+else:
+    unescape = lambda f: f.replace("\\\\", "\0").replace("\\t", "\t").replace("\\n", "\n").replace("\\r", "\r").replace("\0", "\\")
+    table = [[unescape(f) for f in l.split("\t")] for l in open("/tmp/cv.out").read().splitlines()]
+header, body = table[0], table[1:]
+assert header == sorted(header), header
+assert set(rows[0]) <= set(header), (header, list(rows[0]))
+if cmd.startswith("research search"):
+    assert {"rank", "matched_links", "matched_former_name"} <= set(header), header
+else:
+    assert header == list(rows[0]), header
+assert len(body) == len(rows), body
+for row, fields in zip(rows, body):
+    for key, field in zip(header, fields):
+        v = row.get(key)
+        want = "" if v is None else v if isinstance(v, str) else json.dumps(v, separators=(",", ":"))
+        if isinstance(v, float):
+            assert float(field) == v, (key, field, v)
+        else:
+            assert field == want, (key, field, want)
+PY
+    done
+done
+$CV --format tsv research list | sed -n 2p | grep -qF 'a, b "c"\nd\te\\f' || { echo "FAIL: tsv escapes"; exit 1; }
+[ "$($CV --format csv research list | wc -l)" -eq 3 ] || { echo "FAIL: csv line break not quoted into one row"; exit 1; }
+echo "ok: CSV and TSV output"
+
+echo "--- list --fields and --sort ---"
+FSDB="/tmp/lopen-memory-smoke-fields.db"
+rm -f "$FSDB"*
+FS="$BIN --db $FSDB --no-skill-check"
+$FS project add fs-app /fs >/dev/null
+$FS project add fs-side /fs2 "the side one" >/dev/null
+$FS module add --project fs-app core >/dev/null
+$FS module add --project fs-app api --start >/dev/null
+$FS feature add --module core login >/dev/null
+$FS feature add --module core billing --priority high >/dev/null
+$FS task add --feature login form "the form" >/dev/null
+$FS task add --feature login submit --start >/dev/null
+$FS task add --feature login audit >/dev/null
+$FS research add fs-zeta >/dev/null
+$FS research add fs-alpha >/dev/null
+$FS research link --research fs-alpha --project fs-app >/dev/null
+# Columns in the order asked for, in plain, json, and csv.
+[ "$($FS task list --feature login --fields name,description | head -1)" = "form    the form" ] \
+    || { echo "FAIL: plain --fields columns"; $FS task list --feature login --fields name,description; exit 1; }
+$FS --json task list --feature login --fields state,name | python3 -c "
+import json, sys
+rows = json.load(sys.stdin)
+assert [sorted(r) for r in rows] == [['name', 'state']] * 3, rows"
+[ "$($FS --format csv task list --feature login --fields state,id,name | head -1)" = "state,id,name" ] || { echo "FAIL: csv --fields header"; exit 1; }
+$FS --format ndjson research list --fields name,links | python3 -c "
+import json, sys
+assert [json.loads(l) for l in sys.stdin] == [{'name': 'fs-zeta', 'links': 0}, {'name': 'fs-alpha', 'links': 1}]"
+# Sorting by any field, in the database, ascending unless :desc.
+[ "$($FS task list --feature login --fields name --sort name | tr '\n' ' ')" = "audit form submit " ] || { echo "FAIL: --sort name"; exit 1; }
+[ "$($FS task list --feature login --fields name --sort name:desc | tr '\n' ' ')" = "submit form audit " ] || { echo "FAIL: --sort name:desc"; exit 1; }
+[ "$($FS task list --feature login --fields name,state --sort state:desc | head -1)" = "submit  Building" ] || { echo "FAIL: --sort state:desc"; exit 1; }
+[ "$($FS module list --project fs-app --fields name --sort state:desc | tr '\n' ' ')" = "api core " ] || { echo "FAIL: module --sort state"; exit 1; }
+[ "$($FS feature list --module core --fields name --sort priority | tr '\n' ' ')" = "billing login " ] || { echo "FAIL: feature --sort priority"; exit 1; }
+[ "$($FS project list --fields name --sort description:desc | tr '\n' ' ')" = "fs-side fs-app " ] || { echo "FAIL: project --sort description"; exit 1; }
+[ "$($FS research list --fields name --sort name | tr '\n' ' ')" = "fs-alpha fs-zeta " ] || { echo "FAIL: research --sort name"; exit 1; }
+# Names sort as the CLI matches them: case and accents don't split the order.
+for n in Zebra apple auth-flow banana Éte éclair; do $FS feature add --module api "$n" >/dev/null; done
+[ "$($FS feature list --module api --fields name --sort name | tr '\n' ' ')" = "apple auth-flow banana éclair Éte Zebra " ] \
+    || { echo "FAIL: --sort name folds case and accents"; $FS feature list --module api --fields name --sort name; exit 1; }
+[ "$($FS feature list --module api --fields name --sort name:desc | tr '\n' ' ')" = "Zebra Éte éclair banana auth-flow apple " ] || { echo "FAIL: --sort name:desc folds case and accents"; exit 1; }
+[ "$($FS research list --fields name --sort links:desc | head -1)" = "fs-alpha" ] || { echo "FAIL: research --sort links"; exit 1; }
+[ "$($FS --json project list --sort name:desc | python3 -c 'import json,sys;print(json.load(sys.stdin)[0]["name"])')" = "fs-side" ] || { echo "FAIL: --sort under --json"; exit 1; }
+# Unknown fields name the valid ones for that entity.
+set +e
+$FS task list --feature login --fields name,colour 2>/tmp/fs.err; got=$?
+set -e
+[ "$got" = 1 ] || { echo "FAIL: unknown field exited $got"; exit 1; }
+grep -qx "error: unknown field 'colour' for task; valid fields: description, details, feature_id, id, last_worked_on, name, position, priority, ref, state" /tmp/fs.err \
+    || { echo "FAIL: unknown field error"; cat /tmp/fs.err; exit 1; }
+expect_exit 1 $FS module list --project fs-app --sort colour 2>/dev/null
+expect_exit 1 $FS project list --sort name:sideways 2>/dev/null
+expect_exit 1 $FS research list --sort age_days 2>/dev/null
+$FS --json research list --sort link_counts 2>&1 >/dev/null | python3 -c "
+import json, sys
+e = json.loads(sys.stdin.read())['error']
+assert e['code'] == 'invalid_input' and 'sortable fields: archived' in e['message'], e"
+expect_exit 2 $FS research list --group-by staleness --fields name 2>/dev/null
+echo "ok: list --fields and --sort"
+
+echo "--- list --limit and --offset ---"
+PGDB="/tmp/lopen-memory-smoke-paging.db"
+rm -f "$PGDB"*
+PG="$BIN --db $PGDB --no-skill-check"
+$PG project add pg-app /pg >/dev/null
+for n in 1 2 3 4 5; do
+    $PG module add --project pg-app "m$n" >/dev/null
+    $PG research add "pg-r$n" "paging record $n" >/dev/null
+done
+# Without either flag nothing changes: every row, a bare JSON array, no hint.
+[ "$($PG module list --project pg-app | wc -l)" -eq 5 ] || { echo "FAIL: unpaged list"; exit 1; }
+$PG --json module list --project pg-app | python3 -c "
+import json, sys
+assert len(json.load(sys.stdin)) == 5"
+! $PG research list | grep -q "more (use" || { echo "FAIL: hint on an unpaged list"; exit 1; }
+# A page in plain output ends with how many rows are left.
+[ "$($PG module list --project pg-app --limit 2 --fields name | tr '\n' ' ')" = "m1 m2 … 3 more (use --limit/--offset) " ] \
+    || { echo "FAIL: plain --limit"; $PG module list --project pg-app --limit 2 --fields name; exit 1; }
+[ "$($PG module list --project pg-app --offset 3 --fields name | tr '\n' ' ')" = "m4 m5 " ] || { echo "FAIL: plain --offset"; exit 1; }
+[ "$($PG research list --limit 1 --offset 1 | tail -1)" = "… 3 more (use --limit/--offset)" ] || { echo "FAIL: research list hint"; exit 1; }
+# Under --json a page says what it holds and how much there is.
+$PG --json module list --project pg-app --limit 2 --offset 1 --sort name:desc | python3 -c "
+import json, sys
+d = json.load(sys.stdin)
+assert sorted(d) == ['items', 'returned', 'total'], d
+assert [m['name'] for m in d['items']] == ['m4', 'm3'] and d['returned'] == 2 and d['total'] == 5, d"
+$PG --json project list --offset 9 | python3 -c "
+import json, sys
+assert json.load(sys.stdin) == {'items': [], 'returned': 0, 'total': 1}"
+$PG --json research search "paging record" --limit 2 | python3 -c "
+import json, sys
+d = json.load(sys.stdin)
+assert d['returned'] == 2 and d['total'] == 5 and 'rank' in d['items'][0], d"
+[ "$($PG research search "paging record" --limit 1 --offset 4 | wc -l)" -eq 1 ] || { echo "FAIL: research search --offset"; exit 1; }
+# An empty page still says what there is to page through.
+[ "$($PG module list --project pg-app --limit 0)" = "… 5 more (use --limit/--offset)" ] \
+    || { echo "FAIL: --limit 0 hint"; $PG module list --project pg-app --limit 0; exit 1; }
+[ "$($PG module list --project pg-app --offset 9)" = "no rows in this page (total 5; use --limit/--offset)" ] \
+    || { echo "FAIL: page past the end"; $PG module list --project pg-app --offset 9; exit 1; }
+[ "$($PG research list --offset 9)" = "no rows in this page (total 5; use --limit/--offset)" ] || { echo "FAIL: research list past the end"; exit 1; }
+# find pages its matches too: ten by default, --offset to go on, a total under --json.
+[ "$($PG find pg-r --type research --limit 2 | tail -1)" = "… 3 more (use --limit/--offset)" ] || { echo "FAIL: find hint"; exit 1; }
+[ "$($PG find pg-r --type research --offset 4 | wc -l)" -eq 1 ] || { echo "FAIL: find --offset"; exit 1; }
+$PG --json find pg-r --type research | python3 -c "
+import json, sys
+assert len(json.load(sys.stdin)) == 5"
+$PG --json find pg-r --type research --limit 2 --offset 1 | python3 -c "
+import json, sys
+d = json.load(sys.stdin)
+assert d['returned'] == 2 and d['total'] == 5 and len(d['items']) == 2, d"
+expect_exit 1 $PG find pg-r --offset -1 2>/dev/null
+# Row-by-row formats print only the page's rows.
+[ "$($PG --format ndjson research list --limit 2 --fields name | tr '\n' ' ')" = '{"name":"pg-r1"} {"name":"pg-r2"} ' ] || { echo "FAIL: ndjson --limit"; exit 1; }
+[ "$($PG --format csv module list --project pg-app --limit 1 --fields name)" = "$(printf 'name\nm1')" ] || { echo "FAIL: csv --limit"; exit 1; }
+expect_exit 1 $PG task list --feature nothing --limit -1 2>/dev/null
+expect_exit 1 $PG feature list --module m1 --offset -1 2>/dev/null
+expect_exit 2 $PG research list --group-by staleness --limit 2 2>/dev/null
+echo "ok: list --limit and --offset"
+
+echo "--- research list row errors ---"
+RLDB="/tmp/lopen-memory-smoke-rowerr.db"
+rm -rf "$RLDB"*
+$BIN --db "$RLDB" research add row-ok "readable" >/dev/null
+$BIN --db "$RLDB" research add row-bad "unreadable" >/dev/null
+if command -v python3 >/dev/null; then
+  # A blob where text belongs fails to read as a row, which the list reports
+  # instead of leaving the record out.
+  python3 - "$RLDB" <<'PY'
+import sqlite3, sys
+db = sqlite3.connect(sys.argv[1])
+db.execute("UPDATE research SET description = X'00ff' WHERE name = 'row-bad'")
+db.commit()
+PY
+  expect_exit 2 $BIN --db "$RLDB" research list 2>/dev/null
+  expect_exit 2 $BIN --db "$RLDB" --format ndjson research list 2>/dev/null
+  $BIN --db "$RLDB" research list 2>&1 >/dev/null | grep -q "^error: " || { echo "FAIL: row error not reported"; exit 1; }
+fi
+echo "ok: research list row errors"
+
+echo "--- completion guard ---"
+GDB2="/tmp/lopen-memory-smoke-guard.db"
+rm -f "$GDB2"*
+$BIN --db "$GDB2" project add guard-app /guard
+$BIN --db "$GDB2" module add --project guard-app guard-core --start
+$BIN --db "$GDB2" feature add --module guard-core guard-login --start
+$BIN --db "$GDB2" task add --feature guard-login guard-form --start
+$BIN --db "$GDB2" task add --feature guard-login guard-api
+# Unforced: refused with the outstanding children named, nothing changes.
+expect_exit 1 $BIN --db "$GDB2" feature transition --feature guard-login Complete
+$BIN --db "$GDB2" feature transition --feature guard-login Complete 2>&1 | grep "2 incomplete task(s): guard-form \[Building\], guard-api \[Draft\]" >/dev/null
+$BIN --db "$GDB2" --json feature show --feature guard-login | grep '"state": "Building"' >/dev/null
+expect_exit 1 $BIN --db "$GDB2" module transition --module guard-core Complete
+expect_exit 1 $BIN --db "$GDB2" project complete --project guard-app
+$BIN --db "$GDB2" --json project show --project guard-app | grep '"completed": false' >/dev/null
+# Forced: goes through with the list as a warning.
+$BIN --db "$GDB2" feature transition --feature guard-login Complete --force 2>&1 | grep "warning: completing feature guard-login with 2 incomplete task(s)" >/dev/null
+$BIN --db "$GDB2" --json feature show --feature guard-login | grep '"state": "Complete"' >/dev/null
+# Once everything under it is Complete, no flag is needed.
+$BIN --db "$GDB2" module transition --module guard-core Complete
+$BIN --db "$GDB2" project complete --project guard-app
+$BIN --db "$GDB2" project reopen --project guard-app
+$BIN --db "$GDB2" module add --project guard-app guard-extra
+$BIN --db "$GDB2" project complete --project guard-app --force 2>/dev/null
+rm -f "$GDB2"*
+
+echo "--- transition reasons ---"
+RDB="/tmp/lopen-memory-smoke-reason.db"
+rm -f "$RDB"
+$BIN --db "$RDB" project add reason-app /reason
+$BIN --db "$RDB" module add --project reason-app reason-core --start
+$BIN --db "$RDB" feature add --module reason-core reason-login --start
+$BIN --db "$RDB" task add --feature reason-login reason-form --start
+$BIN --db "$RDB" feature set-details --feature reason-login "Original notes"
+$BIN --db "$RDB" feature transition --feature reason-login Complete --reason "Shipped in v2" --force 2>/dev/null
+# The reason is kept on the entity, appended to the details, and echoed in JSON.
+$BIN --db "$RDB" --json feature show --feature reason-login | grep '"last_transition_reason": "Shipped in v2"' >/dev/null
+$BIN --db "$RDB" feature show --feature reason-login | grep "Building → Complete: Shipped in v2" >/dev/null
+$BIN --db "$RDB" feature show --feature reason-login | grep "Original notes" >/dev/null
+$BIN --db "$RDB" --json feature transition --feature reason-login Amending --reason "Sessions must expire" | grep '"reason": "Sessions must expire"' >/dev/null
+$BIN --db "$RDB" feature show --feature reason-login | grep "last_transition_reason.*Sessions must expire" >/dev/null
+# Amending without a reason warns; other moves do not, and they clear the last reason.
+if $BIN --db "$RDB" task transition --task reason-form Complete 2>&1 | grep "warning" >/dev/null; then
+  echo "FAIL: warned about a reason outside Amending"; exit 1
+fi
+$BIN --db "$RDB" task transition --task reason-form Amending 2>&1 | grep "moved to Amending without a reason" >/dev/null
+$BIN --db "$RDB" module transition --module reason-core Complete --reason "All done" --force 2>/dev/null
+$BIN --db "$RDB" module transition --module reason-core Amending --reason "  " 2>&1 | grep "without a reason" >/dev/null
+$BIN --db "$RDB" --json module show --module reason-core | grep '"last_transition_reason": null' >/dev/null
+$BIN --db "$RDB" --json task show --task reason-form | grep '"last_transition_reason": null' >/dev/null
+rm -f "$RDB"*
+
+echo "--- research import-dir ---"
+IDB="/tmp/lopen-memory-smoke-import.db"
+NOTES="/tmp/lopen-memory-smoke-notes"
+rm -rf "$IDB" "$NOTES"
+mkdir -p "$NOTES/deep"
+printf '# Token Expiry\n\nAccess tokens last 15 minutes.\n' > "$NOTES/Token Expiry.md"
+printf 'Cache keys are per tenant\nand expire hourly.\n' > "$NOTES/deep/cache-keys.md"
+printf 'not markdown\n' > "$NOTES/readme.txt"
+printf '\377\376' > "$NOTES/broken.md"
+touch -d 2021-03-04T05:06:07Z "$NOTES/Token Expiry.md"
+# One unreadable file fails on its own; the rest still import.
+expect_exit 1 $BIN --db "$IDB" research import-dir "$NOTES" --recursive
+$BIN --db "$IDB" --json research show --research token-expiry > /tmp/lopen-memory-smoke-import.json
+grep '"description": "Token Expiry"' /tmp/lopen-memory-smoke-import.json >/dev/null
+grep '"researched_at": "2021-03-04T05:06:07Z"' /tmp/lopen-memory-smoke-import.json >/dev/null
+$BIN --db "$IDB" --json research show --research cache-keys | grep '"description": "Cache keys are per tenant"' >/dev/null
+rm "$NOTES/broken.md"
+# Re-running is a no-op; --update only rewrites files that changed.
+$BIN --db "$IDB" research import-dir "$NOTES" --recursive | grep "0 created, 0 updated, 2 skipped, 0 failed" >/dev/null
+printf '# Token Expiry\n\nAccess tokens last 5 minutes.\n' > "$NOTES/Token Expiry.md"
+$BIN --db "$IDB" --json research import-dir "$NOTES" --recursive --update | grep '"updated": 1' >/dev/null
+$BIN --db "$IDB" research show --research token-expiry | grep "5 minutes" >/dev/null
+$BIN --db "$IDB" research import-dir "$NOTES" | grep "0 created, 0 updated, 1 skipped, 0 failed" >/dev/null
+rm -rf "$IDB"* "$NOTES" /tmp/lopen-memory-smoke-import.json
+
+echo "--- research attachments ---"
+ADB="/tmp/lopen-memory-smoke-attach.db"
+AFILES="/tmp/lopen-memory-smoke-files"
+rm -rf "$ADB" "$AFILES"
+mkdir -p "$AFILES"
+echo "n,ms" > "$AFILES/bench.csv"
+echo "spec" > "$AFILES/spec.pdf"
+$BIN --db "$ADB" research add perf-notes "Benchmark results"
+$BIN --db "$ADB" research attach --research perf-notes "$AFILES/bench.csv"
+$BIN --db "$ADB" research attach --research perf-notes "$AFILES/bench.csv" | grep "already attached" >/dev/null
+$BIN --db "$ADB" research attach --research perf-notes "$AFILES/spec.pdf" --copy
+[ -f "$SMOKE_HOME/attachments/1/spec.pdf" ]
+expect_exit 1 $BIN --db "$ADB" research attach --research perf-notes "$AFILES/missing.bin"
+$BIN --db "$ADB" --json research attachments --research perf-notes | grep '"size": 5' >/dev/null
+rm "$AFILES/bench.csv"
+$BIN --db "$ADB" research attachments --research perf-notes | grep "bench.csv  \[missing\]" >/dev/null
+$BIN --db "$ADB" research show --research perf-notes | grep "spec.pdf" >/dev/null
+$BIN --db "$ADB" --json research show --research perf-notes | grep '"copied": true' >/dev/null
+# Removing the record deletes its copies but never the files it only referenced.
+$BIN --db "$ADB" research remove --research perf-notes
+if [ -e "$SMOKE_HOME/attachments/1" ]; then echo "FAIL: copied attachments left behind"; exit 1; fi
+[ -f "$AFILES/spec.pdf" ]
+rm -rf "$ADB"* "$AFILES"
+
+echo "--- advisory locks ---"
+KDB="/tmp/lopen-memory-smoke-locks.db"
+rm -f "$KDB"
+$BIN --db "$KDB" lock acquire --name build-plan --ttl 300 --holder agent-1 | grep "acquired lock build-plan" >/dev/null
+# A held lock is refused to everyone, its holder included.
+expect_exit 4 $BIN --db "$KDB" lock acquire --name build-plan --holder agent-2
+expect_exit 4 $BIN --db "$KDB" lock acquire --name build-plan --holder agent-1
+$BIN --db "$KDB" --json lock acquire --name build-plan --holder agent-2 2>/dev/null | grep '"acquired": false' >/dev/null
+$BIN --db "$KDB" --json lock status --name build-plan > /tmp/lopen-memory-smoke-locks.json
+grep '"holder": "agent-1"' /tmp/lopen-memory-smoke-locks.json >/dev/null
+grep '"remaining_secs": 30[0-9]\|"remaining_secs": 2[0-9][0-9]' /tmp/lopen-memory-smoke-locks.json >/dev/null
+$BIN --db "$KDB" lock status | grep "agent-1" >/dev/null
+expect_exit 4 $BIN --db "$KDB" lock release --name build-plan --holder agent-2
+$BIN --db "$KDB" lock release --name build-plan --holder agent-1
+expect_exit 1 $BIN --db "$KDB" lock release --name build-plan
+$BIN --db "$KDB" --json lock status --name build-plan | grep '"held": false' >/dev/null
+# An expired lock no longer counts and is reaped by the next acquire.
+$BIN --db "$KDB" lock acquire --name deploy --ttl 1 --holder agent-1
+sleep 2
+$BIN --db "$KDB" lock status | grep "no locks held" >/dev/null
+$BIN --db "$KDB" --json lock acquire --name deploy --holder agent-2 | grep '"acquired": true' >/dev/null
+expect_exit 1 $BIN --db "$KDB" lock acquire --name deploy --ttl 0
+rm -f "$KDB"* /tmp/lopen-memory-smoke-locks.json
+
+echo "--- cleanup abandoned drafts ---"
+CDB="/tmp/lopen-memory-smoke-cleanup.db"
+rm -f "$CDB"
+$BIN --db "$CDB" project add tidy-app /tidy
+$BIN --db "$CDB" module add --project tidy-app tidy-core "Kept: has a description"
+$BIN --db "$CDB" module add --project tidy-app tidy-empty
+$BIN --db "$CDB" feature add --module tidy-core tidy-login "Kept: described"
+$BIN --db "$CDB" feature add --module tidy-core tidy-spike
+$BIN --db "$CDB" task add --feature tidy-login tidy-stub
+$BIN --db "$CDB" task add --feature tidy-login tidy-linked
+$BIN --db "$CDB" task add --feature tidy-login tidy-started --start
+$BIN --db "$CDB" research add tidy-notes "Keeps its task"
+$BIN --db "$CDB" research link --research tidy-notes --task tidy-linked
+# Fresh drafts are not abandoned yet.
+$BIN --db "$CDB" cleanup --dry-run | grep "no abandoned drafts found" >/dev/null
+$BIN --db "$CDB" cleanup --older-than 0 --dry-run > /tmp/lopen-memory-smoke-cleanup.out
+grep "would remove 3 abandoned draft(s): 1 task(s), 1 feature(s), 1 module(s)" /tmp/lopen-memory-smoke-cleanup.out >/dev/null
+grep "tidy-stub .*tidy-app > tidy-core > tidy-login" /tmp/lopen-memory-smoke-cleanup.out >/dev/null
+if grep -E "^  [a-z]+ +[A-Z][0-9]+ +(tidy-linked|tidy-started|tidy-login|tidy-core) " /tmp/lopen-memory-smoke-cleanup.out >/dev/null; then echo "FAIL: cleanup matched a kept entity"; exit 1; fi
+# Without --yes and without a terminal nothing is removed.
+$BIN --db "$CDB" cleanup --older-than 0 < /dev/null | grep "pass --yes" >/dev/null
+$BIN --db "$CDB" task show --task tidy-stub >/dev/null
+$BIN --db "$CDB" --json cleanup --older-than 0 --include-linked --dry-run | grep '"name": "tidy-linked"' >/dev/null
+if $BIN --db "$CDB" --json cleanup --older-than 0 --include-described --dry-run | grep '"name": "tidy-login"' >/dev/null; then echo "FAIL: feature with tasks matched"; exit 1; fi
+$BIN --db "$CDB" cleanup --older-than 0 --yes | grep "removed 3 abandoned draft(s)" >/dev/null
+expect_exit 1 $BIN --db "$CDB" task show --task tidy-stub
+expect_exit 1 $BIN --db "$CDB" module show --module tidy-empty
+$BIN --db "$CDB" task show --task tidy-linked >/dev/null
+$BIN --db "$CDB" feature lock --feature tidy-login
+$BIN --db "$CDB" --json cleanup --older-than 0 --include-linked --yes | grep '"removed": \[\]' >/dev/null
+rm -f "$CDB"* /tmp/lopen-memory-smoke-cleanup.out
+
+echo "--- unicode names ---"
+UDB="/tmp/lopen-memory-smoke-unicode.db"
+rm -f "$UDB"
+COMPOSED=$(printf 'caf\xc3\xa9')
+DECOMPOSED=$(printf 'cafe\xcc\x81')
+$BIN --db "$UDB" project add uni-app /uni
+$BIN --db "$UDB" module add --project uni-app "$DECOMPOSED"
+# Stored composed, so either form finds it and neither can be added again.
+$BIN --db "$UDB" module show --module "$COMPOSED" | grep "$COMPOSED" >/dev/null
+$BIN --db "$UDB" module show --module "$DECOMPOSED" >/dev/null
+expect_exit 1 $BIN --db "$UDB" module add --project uni-app "$COMPOSED"
+# Case folds beyond ASCII: ÉCLAIR finds éclair, and cannot sit beside it.
+$BIN --db "$UDB" research add "$(printf '\xc3\xa9clair')" "Pastry notes"
+$BIN --db "$UDB" research show --research "$(printf '\xc3\x89CLAIR')" | grep "Pastry notes" >/dev/null
+expect_exit 1 $BIN --db "$UDB" research add "$(printf '\xc3\x89clair')"
+$BIN --db "$UDB" research search "$(printf '\xc3\x89CLAIR')" | grep "Pastry notes" >/dev/null
+# Lookups ignore case for non-ASCII letters too.
+$BIN --db "$UDB" module add --project uni-app "$(printf '\xc3\x96l')"
+$BIN --db "$UDB" --json module show --module "$(printf '\xc3\xb6l')" | grep '"id": 2' >/dev/null
+# Names sort with accents beside their base letter rather than after z.
+$BIN --db "$UDB" lock acquire --name zebra
+$BIN --db "$UDB" lock acquire --name "$(printf '\xc3\x89clair')"
+$BIN --db "$UDB" lock acquire --name apple
+[ "$($BIN --db "$UDB" lock status | awk '{print $1}' | tr '\n' ' ')" = "apple $(printf '\xc3\x89clair') zebra " ]
+rm -f "$UDB"*
+
+echo "--- diff ---"
+DDB="/tmp/lopen-memory-smoke-diff.db"
+rm -f "$DDB"*
+$BIN --db "$DDB" project add diff-app /diff
+$BIN --db "$DDB" module add --project diff-app auth
+$BIN --db "$DDB" feature add --module auth a-login
+$BIN --db "$DDB" feature add --module auth b-login
+$BIN --db "$DDB" feature set-details --feature a-login "$(printf 'one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten')"
+$BIN --db "$DDB" feature set-details --feature b-login "$(printf 'one\nTWO\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\neleven')"
+$BIN --db "$DDB" diff --feature a-login --feature b-login --field details > "$DDB.out"
+grep -x -- "--- feature a-login (F1) details" "$DDB.out" >/dev/null
+grep -x "@@ -1,5 +1,5 @@" "$DDB.out" >/dev/null
+grep -x -- "-two" "$DDB.out" >/dev/null
+grep -x "+TWO" "$DDB.out" >/dev/null
+grep -x "@@ -8,3 +8,4 @@" "$DDB.out" >/dev/null
+$BIN --db "$DDB" --json diff --feature a-login --feature b-login | grep '"op": "added"' >/dev/null
+# Across types: a task against its feature; identical texts print nothing.
+$BIN --db "$DDB" task add --feature a-login t1
+$BIN --db "$DDB" task set-details --task t1 "$(printf 'one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten')"
+[ -z "$($BIN --db "$DDB" diff --task t1 --feature a-login)" ]
+$BIN --db "$DDB" --json diff --task t1 --feature a-login | grep '"identical": true' >/dev/null
+expect_exit 1 $BIN --db "$DDB" diff --project diff-app --feature a-login --field details
+expect_exit 1 $BIN --db "$DDB" diff --feature a-login --field details
+expect_exit 1 $BIN --db "$DDB" diff --feature a-login --feature no-such-feature
+
+echo "--- tree ---"
+$BIN --db "$DDB" module add --project diff-app billing
+$BIN --db "$DDB" task transition --task t1 Planning
+$BIN --db "$DDB" tree --project diff-app > "$DDB.out"
+head -1 "$DDB.out" | grep -x "diff-app" >/dev/null
+grep -x "├─ auth \[Draft\]" "$DDB.out" >/dev/null
+grep -x "│  ├─ a-login \[Draft\]" "$DDB.out" >/dev/null
+grep -x "│  │  └─ t1 \[Planning\]" "$DDB.out" >/dev/null
+grep -x "└─ billing \[Draft\]" "$DDB.out" >/dev/null
+# --state keeps only branches leading to a match; --depth stops early.
+$BIN --db "$DDB" tree --project diff-app --state Planning > "$DDB.out"
+if grep -E "billing|b-login" "$DDB.out" >/dev/null; then echo "FAIL: tree --state kept a branch without a match"; exit 1; fi
+grep "t1 \[Planning\]" "$DDB.out" >/dev/null
+if $BIN --db "$DDB" tree --project diff-app --depth 1 | grep "a-login" >/dev/null; then echo "FAIL: tree --depth 1 showed features"; exit 1; fi
+$BIN --db "$DDB" --json tree --project diff-app | grep '"tasks": \[' >/dev/null
+if $BIN --db "$DDB" --json tree --project diff-app --depth 2 | grep '"tasks"' >/dev/null; then echo "FAIL: tree --depth 2 listed tasks"; exit 1; fi
+expect_exit 1 $BIN --db "$DDB" tree --project diff-app --depth 4
+expect_exit 1 $BIN --db "$DDB" tree --project diff-app --state Done
+rm -f "$DDB"*
+
+echo "--- changelog ---"
+# Every subcommand the binary offers, and every migration it applies, must
+# have an entry in the compiled-in changelog.
+CHANGES_JSON=$($BIN --json changes)
+subcommands() { $BIN $1 --help | awk '/^Commands:/{f=1;next} /^[A-Z][a-z]*:/{f=0} f && /^  [a-z]/ && $1 != "help" {print $1}'; }
+for top in $(subcommands ""); do
+  subs=$(subcommands "$top")
+  for sub in ${subs:-"-"}; do
+    item=$(echo "$top $sub" | sed 's/ -$//')
+    if ! echo "$CHANGES_JSON" | grep -F "\"item\": \"$item\"" >/dev/null; then
+      echo "FAIL: no changelog entry for command '$item'"; exit 1
+    fi
+  done
+done
+SCHEMA=$(echo "$CHANGES_JSON" | grep '"schema_version"' | grep -oE '[0-9]+')
+for n in $(seq 1 "$SCHEMA"); do
+  if ! echo "$CHANGES_JSON" | grep -E "\"migration\": $n,?\$" >/dev/null; then
+    echo "FAIL: no changelog entry for migration $n"; exit 1
+  fi
+done
+$BIN --json db stats | grep "\"schema_version\": $SCHEMA" >/dev/null
+CURRENT=$(echo "$CHANGES_JSON" | grep '"current"' | cut -d'"' -f4)
+$BIN changes --since "$CURRENT" | grep "no changes since" >/dev/null
+if $BIN --json changes --since 1.0.5-alpha | grep '"version": "1.0.5-alpha"' >/dev/null; then
+  echo "FAIL: --since included the release it names"; exit 1
+fi
+expect_exit 7 $BIN changes --check 1.0.5-alpha
+$BIN changes --check "$CURRENT" | grep "no breaking changes" >/dev/null
+expect_exit 1 $BIN changes --since not-a-version
+
+echo "--- lazy database open ---"
+COLD_DIR="/tmp/lopen-memory-smoke-cold"
+COLD_DB="$COLD_DIR/memory.db"
+rm -rf "$COLD_DIR"
+# Help, argument errors, and commands with no use for the database never create it.
+$BIN --db "$COLD_DB" --help >/dev/null
+expect_exit 2 $BIN --db "$COLD_DB" project no-such-action
+$BIN --db "$COLD_DB" changes >/dev/null
+$BIN --db "$COLD_DB" config list >/dev/null
+if [ -e "$COLD_DIR" ]; then
+  echo "FAIL: database directory created without a database command"; exit 1
+fi
+# A fresh database still gets every table at the current schema version...
+COLD_STATS=$($BIN --db "$COLD_DB" --json db stats)
+for table in projects modules features tasks research research_projects research_modules \
+    research_features research_tasks contexts name_history research_attachments locks; do
+  echo "$COLD_STATS" | grep "\"$table\": 0" >/dev/null
+done
+echo "$COLD_STATS" | grep "\"schema_version\": $SCHEMA" >/dev/null
+# ...and later opens, which skip the schema pass, still enforce foreign keys.
+$BIN --db "$COLD_DB" project add cold-app /tmp
+$BIN --db "$COLD_DB" module add --project cold-app cold-module
+$BIN --db "$COLD_DB" project remove --project cold-app --cascade
+$BIN --db "$COLD_DB" --json db stats | grep '"modules": 0' >/dev/null
+if command -v sqlite3 >/dev/null; then
+  [ "$(sqlite3 "$COLD_DB" "PRAGMA journal_mode")" = "wal" ]
+fi
+rm -rf "$COLD_DIR"
+
+echo "--- external research content ---"
+CDB="/tmp/lopen-memory-smoke-content.db"
+rm -rf "$CDB"*
+$BIN config set content_external_threshold 40
+$BIN --db "$CDB" research add big-notes "Long findings"
+$BIN --db "$CDB" research set-content --research big-notes "The zephyrine cache holds every token for an hour before eviction."
+$BIN --db "$CDB" research add small-notes "Short findings"
+$BIN --db "$CDB" research set-content --research small-notes "zephyrine in short"
+[ -f "$CDB-content/1.md" ]
+if [ -e "$CDB-content/2.md" ]; then echo "FAIL: small content stored externally"; exit 1; fi
+$BIN --db "$CDB" research show --research big-notes | grep "holds every token" >/dev/null
+$BIN --db "$CDB" --json export --format json | grep "holds every token" >/dev/null
+# External bodies are only searched with --deep.
+if $BIN --db "$CDB" --json research search eviction | grep '"name"' >/dev/null; then echo "FAIL: search matched external content"; exit 1; fi
+$BIN --db "$CDB" --json research search eviction --deep | grep '"big-notes"' >/dev/null
+if $BIN --db "$CDB" --json research search external-content | grep '"name"' >/dev/null; then echo "FAIL: search matched the pointer"; exit 1; fi
+$BIN --db "$CDB" --json db maintain | grep '"reinline": \[\]' >/dev/null
+# Raising the threshold leaves the file in place until db maintain moves it back.
+$BIN config set content_external_threshold 1000
+$BIN --db "$CDB" db maintain | grep "pass --yes" >/dev/null
+[ -f "$CDB-content/1.md" ]
+$BIN --db "$CDB" db maintain --yes | grep "re-inlined 1 record" >/dev/null
+if [ -e "$CDB-content/1.md" ]; then echo "FAIL: re-inlined file left behind"; exit 1; fi
+$BIN --db "$CDB" --json research search eviction | grep '"big-notes"' >/dev/null
+# A missing file reads as empty with a warning, and db maintain exits 1.
+$BIN config set content_external_threshold 40
+$BIN --db "$CDB" research set-content --research big-notes "The zephyrine cache holds every token for an hour before eviction."
+rm "$CDB-content/1.md"
+$BIN --db "$CDB" research show --research big-notes 2>&1 >/dev/null | grep "cannot be read" >/dev/null
+expect_exit 1 $BIN --db "$CDB" db maintain
+# Removing the record deletes its file.
+$BIN --db "$CDB" research set-content --research big-notes "The zephyrine cache holds every token for an hour before eviction."
+$BIN --db "$CDB" research remove --research big-notes
+if [ -e "$CDB-content/1.md" ]; then echo "FAIL: content file left behind"; exit 1; fi
+# Each database keeps its own bodies: the same research id in another
+# database neither reads nor tidies away this one's file.
+CDB2="/tmp/lopen-memory-smoke-content2.db"
+rm -rf "$CDB2"*
+$BIN --db "$CDB" research add kept-notes "Kept findings"
+$BIN --db "$CDB" research set-content --research kept-notes "The first database keeps this body in its own content file."
+$BIN --db "$CDB2" research add other-notes "Other findings"
+$BIN --db "$CDB2" research set-content --research other-notes "The second database keeps a different body in its own file."
+$BIN --db "$CDB" research show --research kept-notes | grep "first database" >/dev/null
+$BIN --db "$CDB2" research show --research other-notes | grep "second database" >/dev/null
+$BIN --db "$CDB2" research remove --research other-notes
+$BIN --db "$CDB2" db maintain --yes >/dev/null
+[ -f "$CDB-content/3.md" ] || { echo "FAIL: another database's maintain deleted this one's file"; exit 1; }
+# Bodies in the directory every database used to share are copied over when
+# the database is upgraded.
+mkdir -p "$SMOKE_HOME/content"
+mv "$CDB-content/3.md" "$SMOKE_HOME/content/3.md"
+python3 -c "import sqlite3; sqlite3.connect('$CDB').execute('PRAGMA user_version=19')"
+$BIN --db "$CDB" research show --research kept-notes | grep "first database" >/dev/null
+[ -f "$CDB-content/3.md" ] || { echo "FAIL: legacy content file not adopted"; exit 1; }
+rm -rf "$SMOKE_HOME/content" "$CDB2"*
+$BIN config unset content_external_threshold
+rm -rf "$CDB"*
+
+echo "--- database open errors ---"
+ODIR="/tmp/lopen-memory-smoke-open"
+rm -rf "$ODIR"
+mkdir -p "$ODIR"
+# Each failure is one JSON object on stderr with the path, a code, and a
+# kind; exit 2.
+open_error() {
+    local want=$1 db=$2
+    set +e
+    $BIN --json --db "$db" project list 2>"$ODIR/err" >"$ODIR/out"
+    local got=$?
+    set -e
+    if [ "$got" -ne 2 ]; then echo "FAIL: expected exit 2 opening $db, got $got"; exit 1; fi
+    if [ -s "$ODIR/out" ]; then echo "FAIL: open error wrote to stdout"; exit 1; fi
+    grep "^{\"error\":{\"code\":\"\(io\|db\)\",\"entity\":null,\"kind\":\"$want\"" "$ODIR/err" >/dev/null
+    grep "\"path\":\"$db\"" "$ODIR/err" >/dev/null
+}
+echo "not a database" > "$ODIR/garbage.db"
+open_error corrupt "$ODIR/garbage.db"
+$BIN --db "$ODIR/garbage.db" project list 2>&1 | grep "error: failed to open database $ODIR/garbage.db (corrupt)" >/dev/null
+touch "$ODIR/file"
+open_error not_found "$ODIR/file/lopen.db"
+$BIN --db "$ODIR/file/lopen.db" project list 2>&1 | grep "cannot create directory $ODIR/file: .*(os error" >/dev/null
+mkdir "$ODIR/dir.db"
+open_error not_found "$ODIR/dir.db"
+$BIN --db "$ODIR/dir.db" project list 2>&1 | grep "$ODIR/dir.db is a directory; give the path of a database file inside it, e.g. $ODIR/dir.db/lopen-memory.db" >/dev/null
+# Relative paths resolve against the working directory: parents are created,
+# and errors name the absolute path.
+(cd "$ODIR" && "$ABS_BIN" --db rel/sub/lopen.db project list) | grep "no projects found" >/dev/null
+[ -f "$ODIR/rel/sub/lopen.db" ]
+(cd "$ODIR" && "$ABS_BIN" --db dir.db project list 2>&1) | grep "$ODIR/dir.db is a directory" >/dev/null
+if [ "$(id -u)" -ne 0 ]; then
+  mkdir "$ODIR/sealed"
+  chmod 000 "$ODIR/sealed"
+  open_error permission "$ODIR/sealed/lopen.db"
+  $BIN --db "$ODIR/sealed/lopen.db" project list 2>&1 | grep "cannot create database file $ODIR/sealed/lopen.db: .*(os error" >/dev/null
+  chmod 700 "$ODIR/sealed"
+elif [ -d /sys/kernel ]; then
+  # root ignores file modes, but sysfs still refuses new files.
+  open_error permission /sys/lopen-memory-smoke.db
+  $BIN --db /sys/lopen-memory-smoke.db project list 2>&1 | grep "cannot create database file /sys/lopen-memory-smoke.db: Permission denied" >/dev/null
+fi
+if command -v python3 >/dev/null; then
+  python3 - "$BIN" "$ODIR/locked.db" <<'PY'
+import sqlite3, subprocess, sys
+bin, db = sys.argv[1], sys.argv[2]
+holder = sqlite3.connect(db, isolation_level=None)
+holder.execute("CREATE TABLE t (x)")
+holder.execute("BEGIN EXCLUSIVE")
+holder.execute("INSERT INTO t VALUES (1)")
+r = subprocess.run([bin, "--json", "--busy-timeout-ms", "100", "--db", db, "project", "list"], capture_output=True, text=True)
+if r.returncode != 5 or '"kind":"busy"' not in r.stderr:
+    sys.exit("FAIL: locked database gave exit %d: %s" % (r.returncode, r.stderr))
+PY
+fi
+rm -rf "$ODIR"
+
 echo "--- JSON output test ---"
 $BIN --json project list
 $BIN --json research list
@@ -160,3 +3758,4 @@ $BIN --json research list
 echo
 echo "=== All smoke tests passed ==="
 rm -f "$DB"
+rm -rf "$SMOKE_HOME"
@@ -0,0 +1,869 @@
+use crate::output;
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+
+/// What part of the interface a change touches.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// A subcommand, named by its path: `research capture`
+    Command,
+    /// A flag, named with the command it belongs to: `module add --plan`
+    Flag,
+    /// A schema migration, named by the user_version it brings a database to
+    Schema,
+    /// Output or semantics of something that already existed
+    Behavior,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Command => "command",
+            Kind::Flag => "flag",
+            Kind::Schema => "schema",
+            Kind::Behavior => "behavior",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Added,
+    // Nothing has been removed yet; kept so consumers can rely on the value.
+    #[allow(dead_code)]
+    Removed,
+    Changed,
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Added => "added",
+            Action::Removed => "removed",
+            Action::Changed => "changed",
+        }
+    }
+}
+
+pub struct Change {
+    pub kind: Kind,
+    pub action: Action,
+    pub item: &'static str,
+    /// Set when a caller relying on the earlier behavior can break
+    pub breaking: bool,
+    /// The user_version this change brings a database to, for schema changes
+    pub migration: Option<i64>,
+    pub note: &'static str,
+}
+
+impl Change {
+    fn to_json(&self) -> Value {
+        json!({
+            "kind": self.kind.as_str(),
+            "action": self.action.as_str(),
+            "item": self.item,
+            "breaking": self.breaking,
+            "migration": self.migration,
+            "note": self.note,
+        })
+    }
+}
+
+pub struct Release {
+    pub version: &'static str,
+    pub changes: &'static [Change],
+}
+
+const fn command(item: &'static str, note: &'static str) -> Change {
+    Change {
+        kind: Kind::Command,
+        action: Action::Added,
+        item,
+        breaking: false,
+        migration: None,
+        note,
+    }
+}
+
+const fn flag(item: &'static str, note: &'static str) -> Change {
+    Change {
+        kind: Kind::Flag,
+        action: Action::Added,
+        item,
+        breaking: false,
+        migration: None,
+        note,
+    }
+}
+
+const fn schema(version: i64, note: &'static str) -> Change {
+    Change {
+        kind: Kind::Schema,
+        action: Action::Added,
+        item: "migration",
+        breaking: false,
+        migration: Some(version),
+        note,
+    }
+}
+
+const fn behavior(item: &'static str, breaking: bool, note: &'static str) -> Change {
+    Change {
+        kind: Kind::Behavior,
+        action: Action::Changed,
+        item,
+        breaking,
+        migration: None,
+        note,
+    }
+}
+
+/// Every release, oldest first. Each new subcommand, flag, and migration gets
+/// an entry under the version it ships in; the smoke test fails otherwise.
+/// Append only; never edit a released entry.
+pub const RELEASES: &[Release] = &[
+    Release {
+        version: "1.0.5-alpha",
+        changes: &[
+            command("project add", "first tracked release"),
+            command("project list", "first tracked release"),
+            command("project show", "first tracked release"),
+            command("project rename", "first tracked release"),
+            command("project set-description", "first tracked release"),
+            command("project set-path", "first tracked release"),
+            command("project complete", "first tracked release"),
+            command("project reopen", "first tracked release"),
+            command("project remove", "first tracked release"),
+            command("module add", "first tracked release"),
+            command("module list", "first tracked release"),
+            command("module show", "first tracked release"),
+            command("module rename", "first tracked release"),
+            command("module set-description", "first tracked release"),
+            command("module set-details", "first tracked release"),
+            command("module transition", "first tracked release"),
+            command("module remove", "first tracked release"),
+            command("feature add", "first tracked release"),
+            command("feature list", "first tracked release"),
+            command("feature show", "first tracked release"),
+            command("feature rename", "first tracked release"),
+            command("feature set-description", "first tracked release"),
+            command("feature set-details", "first tracked release"),
+            command("feature transition", "first tracked release"),
+            command("feature remove", "first tracked release"),
+            command("task add", "first tracked release"),
+            command("task list", "first tracked release"),
+            command("task show", "first tracked release"),
+            command("task rename", "first tracked release"),
+            command("task set-description", "first tracked release"),
+            command("task set-details", "first tracked release"),
+            command("task transition", "first tracked release"),
+            command("task remove", "first tracked release"),
+            command("research add", "first tracked release"),
+            command("research list", "first tracked release"),
+            command("research show", "first tracked release"),
+            command("research rename", "first tracked release"),
+            command("research set-description", "first tracked release"),
+            command("research set-content", "first tracked release"),
+            command("research set-source", "first tracked release"),
+            command("research set-researched-at", "first tracked release"),
+            command("research search", "first tracked release"),
+            command("research link", "first tracked release"),
+            command("research unlink", "first tracked release"),
+            command("research links", "first tracked release"),
+            command("research remove", "first tracked release"),
+            command("skill install", "first tracked release"),
+        ],
+    },
+    Release {
+        version: "1.1.0-alpha",
+        changes: &[
+            flag("--no-skill-check", "skip the daily check for a missing or stale skill file"),
+            flag("module add --plan", "walk the new module to Planning"),
+            flag("module add --start", "walk the new module to Building"),
+            flag("feature add --plan", "walk the new feature to Planning"),
+            flag("feature add --start", "walk the new feature to Building"),
+            flag("task add --plan", "walk the new task to Planning"),
+            flag("task add --start", "walk the new task to Building"),
+            command("db stats", "file size, page usage, and row counts"),
+            command("db profile", "run a command and report each query it executed"),
+            schema(1, "locked flag on modules and features"),
+            command("module lock", "guard a module against structural changes"),
+            command("module unlock", "lift a module lock"),
+            command("feature lock", "guard a feature against structural changes"),
+            command("feature unlock", "lift a feature lock"),
+            flag("research search --linked-to", "match research by linked entity names"),
+            flag("project rename --update-references", "rewrite whole-word references"),
+            flag("project rename --dry-run", "preview reference rewrites"),
+            flag("module rename --update-references", "rewrite whole-word references"),
+            flag("module rename --dry-run", "preview reference rewrites"),
+            flag("feature rename --update-references", "rewrite whole-word references"),
+            flag("feature rename --dry-run", "preview reference rewrites"),
+            flag("task rename --update-references", "rewrite whole-word references"),
+            flag("task rename --dry-run", "preview reference rewrites"),
+            flag("research rename --update-references", "rewrite whole-word references"),
+            flag("research rename --dry-run", "preview reference rewrites"),
+            command("skill update", "rewrite the installed skill file"),
+            command("config list", "show every setting"),
+            command("config get", "show one setting"),
+            command("config set", "change one setting"),
+            command("config unset", "remove one setting"),
+            schema(2, "contexts table for per-directory default scope"),
+            command("use", "set the default scope for the working directory"),
+            flag("research list --min-links", "only research with at least this many links"),
+            flag("research list --unlinked", "only research with no links"),
+            command("project bootstrap", "propose modules from the repository layout"),
+            behavior(
+                "research link",
+                false,
+                "JSON carries both ids and whether the link was created",
+            ),
+            behavior(
+                "research unlink",
+                false,
+                "JSON carries both ids and whether the link was removed",
+            ),
+            flag("research links --dedupe", "hide links implied by an ancestor link"),
+            command("research normalize-links", "drop links implied by an ancestor link"),
+            schema(3, "timestamps rewritten to canonical RFC 3339"),
+            behavior(
+                "timestamps",
+                true,
+                "every stored and printed timestamp is RFC 3339 UTC with a Z suffix",
+            ),
+            command("export", "write a cross-linked Markdown knowledge base"),
+            command("research capture", "record a finding and link it in one call"),
+            command("validate", "workflow consistency report; exits 6 on errors"),
+            command("project link-research", "link research from the project side"),
+            command("project unlink-research", "unlink research from the project side"),
+            command("project list-research", "research linked to a project"),
+            command("module link-research", "link research from the module side"),
+            command("module unlink-research", "unlink research from the module side"),
+            command("module list-research", "research linked to a module"),
+            command("feature link-research", "link research from the feature side"),
+            command("feature unlink-research", "unlink research from the feature side"),
+            command("feature list-research", "research linked to a feature"),
+            command("task link-research", "link research from the task side"),
+            command("task unlink-research", "unlink research from the task side"),
+            command("task list-research", "research linked to a task"),
+            command("find", "fuzzy name matching across the hierarchy"),
+            flag("research list --stale", "only research older than the stale threshold"),
+            flag("module remove --relink-research-to-parent", "keep research links on the project"),
+            flag("feature remove --relink-research-to-parent", "keep research links on the module"),
+            schema(4, "name_history table recording every rename"),
+            command("project name-history", "former names of a project"),
+            command("module name-history", "former names of a module"),
+            command("feature name-history", "former names of a feature"),
+            command("task name-history", "former names of a task"),
+            command("research name-history", "former names of a research record"),
+            flag("research search --include-former-names", "also match former names"),
+            command("research select", "pack relevant research into a character budget"),
+            schema(5, "path column on modules"),
+            command("module set-path", "map a module to a project subdirectory"),
+            flag("module add --path", "map the new module to a project subdirectory"),
+            behavior(
+                "refs",
+                false,
+                "entities are shown with short refs (P3, M7, F21, T104, R9) accepted by every resolver",
+            ),
+            schema(6, "research_attachments table"),
+            command("research attach", "attach a local file to a research record"),
+            command("research attachments", "files attached to a research record"),
+            command("research import-dir", "import a directory of Markdown notes"),
+            flag("task transition --lift-parents", "move parents that are behind along"),
+            flag("feature transition --lift-parents", "move parents that are behind along"),
+            schema(7, "locks table for advisory named locks"),
+            command("lock acquire", "take a named advisory lock; exits 4 while held"),
+            command("lock release", "give up a named advisory lock"),
+            command("lock status", "show held advisory locks"),
+            command("cleanup", "remove abandoned Draft modules, features, and tasks"),
+            command("last", "the most recently created entities"),
+            behavior(
+                "names",
+                true,
+                "names are stored in NFC and names differing only in case can no longer coexist",
+            ),
+            command("changes", "this changelog"),
+            schema(8, "last_transition_reason on modules, features, and tasks"),
+            flag("module transition --reason", "record why; appended to the details"),
+            flag("feature transition --reason", "record why; appended to the details"),
+            flag("task transition --reason", "record why; appended to the details"),
+            flag("export --file", "file for the JSON document"),
+            flag("export --project", "export one project subtree"),
+            behavior(
+                "export",
+                false,
+                "--format json writes one nested document; --out is only needed for md",
+            ),
+            behavior(
+                "research content",
+                false,
+                "bodies over content_external_threshold are stored in files under content/",
+            ),
+            flag("research search --deep", "also search externally stored content"),
+            command("db maintain", "check and tidy externally stored research content"),
+            command("import", "load a JSON export, remapping ids"),
+            command("diff", "unified diff of one field of two entities"),
+            command("tree", "a project's hierarchy as one indented tree"),
+            behavior(
+                "completion",
+                true,
+                "completing a feature, module, or project with incomplete children is refused",
+            ),
+            flag("feature transition --force", "complete despite incomplete tasks"),
+            flag("module transition --force", "complete despite incomplete features"),
+            flag("project complete --force", "complete despite incomplete modules"),
+            command("research bibliography", "sources cited across a project's research"),
+            behavior(
+                "database open errors",
+                false,
+                "--json prints them as a JSON object on stderr with the path and a kind",
+            ),
+            command("analytics activity", "changes per day to a project, by entity type"),
+            behavior(
+                "task add/list/show --module",
+                false,
+                "narrows the feature name, and task show's task name, instead of being ignored",
+            ),
+            behavior(
+                "feature show --project",
+                false,
+                "narrows the module name, or the feature name across the project, instead of being ignored",
+            ),
+            command("db doctor", "find and delete rows whose parent is gone"),
+            flag("--paranoid", "refuse to start unless foreign keys are enforced"),
+            command("progress", "append a progress line to a task and mark it worked on"),
+            behavior(
+                "not found errors",
+                false,
+                "suggest up to three close names from the same scope",
+            ),
+            flag("research show --timeline", "the three timestamps in order with their ages"),
+            flag("research list --group-by", "sections by staleness: fresh, aging, stale, ancient"),
+            flag("research list --buckets", "staleness boundaries in days"),
+            behavior(
+                "numeric names",
+                true,
+                "a number that is also an entity's name resolves to the name; id:N forces the id",
+            ),
+            behavior(
+                "research researched_at",
+                false,
+                "implausible values are warned about when set and reported by validate; JSON carries age_days",
+            ),
+            command("status", "counts by state, work in progress, and recent items per project"),
+            behavior(
+                "closed stdout",
+                false,
+                "output into a pipe closed early (| head) exits 0 quietly instead of panicking",
+            ),
+            command("next", "the next task to pick up, with breadcrumb, details, and linked research"),
+            flag("feature show --with-ancestors", "module and project description and details too"),
+            flag("task show --with-ancestors", "feature, module, and project description and details too"),
+            behavior(
+                "find",
+                false,
+                "also matches descriptions and details (research content), reporting the columns matched",
+            ),
+            flag("find --type", "only one entity type"),
+            flag("find --project", "only the project, what is in it, and research linked under it"),
+            flag("find --state", "only entities in one state"),
+            flag("--trace-file", "one JSON line per committed change, for supervisors; or LOPEN_MEMORY_TRACE_FD"),
+            behavior(
+                "numeric flags",
+                true,
+                "every count, day, and limit flag is range-checked up front (exit 1, naming the flag); negative values no longer slip through",
+            ),
+            schema(9, "research_fts full-text index over research, backfilled and kept current by triggers"),
+            behavior(
+                "research search",
+                true,
+                "terms match whole words by prefix and every word must appear; results are ordered by relevance, with rank in JSON",
+            ),
+            flag(
+                "set-description --file",
+                "read the text from a file, or from stdin with -; also set-details and research set-content",
+            ),
+            schema(10, "archived flag on research"),
+            command("research archive", "keep a superseded record and its links out of search, list, and select"),
+            command("research unarchive", "bring an archived record back"),
+            flag(
+                "research search --include-archived",
+                "also research list, research select, and every list-research",
+            ),
+            flag(
+                "task set-details --append",
+                "add to the details instead of replacing them; also --prepend, on module and feature set-details and research set-content",
+            ),
+            schema(11, "snapshots table holding named project snapshots"),
+            command("snapshot create", "save a project's subtree and research links under a name"),
+            command("snapshot list", "snapshots with what each holds"),
+            command("snapshot restore", "reinstate a snapshot in one transaction, with --replace or --as"),
+            command("snapshot drop", "delete a snapshot"),
+            flag(
+                "module/feature/task add --state",
+                "start in Draft, Planning, or Building",
+            ),
+            flag(
+                "module/feature/task add --details",
+                "store details with the new record",
+            ),
+            behavior(
+                "research show",
+                false,
+                "links in breadcrumb order, grouped by project, with linked ancestors marked (`under` in JSON)",
+            ),
+            schema(12, "tasks.position, the work order within a feature"),
+            command("task reorder", "set the work order of a feature's tasks"),
+            flag("task add --before/--after", "insert at a point in the work order"),
+            behavior(
+                "task list",
+                false,
+                "tasks in work order (position) rather than id order, also in feature show, tree, and export",
+            ),
+            behavior(
+                "--db",
+                false,
+                "a directory, or a parent that cannot be created or written, fails with the absolute path and OS error",
+            ),
+            command(
+                "feature extract-todos",
+                "turn TODO/FIXME lines in feature details into tasks",
+            ),
+            schema(13, "task_dependencies table: tasks waiting on other tasks"),
+            command("task depend", "make a task wait on another; cycles are refused"),
+            command("task undepend", "remove a dependency between two tasks"),
+            flag(
+                "task transition --force",
+                "move to Building or Complete with unmet dependencies, warning instead of failing",
+            ),
+            flag("task list --ready", "only tasks whose dependencies are all Complete"),
+            flag("next --ready", "leave out tasks still waiting on a dependency"),
+            behavior(
+                "task transition",
+                true,
+                "Building or Complete fails while a dependency is not Complete, unless --force",
+            ),
+            schema(14, "priority on tasks and features: low, medium, high, or critical"),
+            command("task set-priority", "set how much a task matters"),
+            command("feature set-priority", "set how much a feature matters"),
+            flag("task add --priority", "priority of the new task (default medium)"),
+            flag("feature add --priority", "priority of the new feature (default medium)"),
+            flag("task list --priority", "only tasks of one priority"),
+            flag("task list --sort", "priority for most urgent first"),
+            flag("feature list --priority", "only features of one priority"),
+            flag("feature list --sort", "priority for most urgent first"),
+            behavior(
+                "task list",
+                false,
+                "a priority column after the state, also in feature list and feature show",
+            ),
+            behavior(
+                "next",
+                false,
+                "the more urgent task first within each tier; priority in plain and JSON output",
+            ),
+            flag(
+                "project list --effective",
+                "judge --completed/--incomplete by module states rather than the completed flag",
+            ),
+            behavior(
+                "project list",
+                false,
+                "marks projects whose completed flag disagrees with their modules (`state_mismatch`, `effectively_completed` in JSON), also in project show",
+            ),
+            behavior(
+                "validate",
+                false,
+                "new warning rule project-incomplete-modules-complete",
+            ),
+            schema(15, "state CHECK constraints admit Blocked"),
+            behavior(
+                "transition",
+                false,
+                "new Blocked state: Building → Blocked, Blocked → Building or Draft; --reason is shown as `blocked:` in show and as [blocked: …] on list lines",
+            ),
+            behavior(
+                "module show",
+                false,
+                "Blocked children are tagged with their reason (blocked_reason in JSON), also in feature show",
+            ),
+            command(
+                "setup export",
+                "pack the config, the skill file, and optionally the database into one .tar.gz",
+            ),
+            command(
+                "setup apply",
+                "install a setup bundle, moving home paths across; idempotent on re-run",
+            ),
+            flag(
+                "setup apply --overwrite",
+                "replace an existing config and database with the bundle's",
+            ),
+            flag(
+                "setup apply --merge",
+                "keep existing settings and data, add what is missing",
+            ),
+            schema(16, "state_history table recording every change of state"),
+            command(
+                "task history",
+                "state transitions oldest first, with time spent in each",
+            ),
+            command("feature history", "state transitions oldest first"),
+            command("module history", "state transitions oldest first"),
+            command("project history", "when the project was marked complete or reopened"),
+            flag(
+                "next --about",
+                "put tasks matching the terms first within each tier, saying what matched",
+            ),
+            flag("next --require-match", "with --about, leave out tasks matching nothing"),
+            command(
+                "task transition-all",
+                "move every task of a feature in one transaction, reporting each result",
+            ),
+            command(
+                "feature transition-all",
+                "move every feature of a module in one transaction, reporting each result",
+            ),
+            flag(
+                "task show --with-history",
+                "include the state timeline (history in JSON); also on module, feature, and project show",
+            ),
+            behavior(
+                "research link",
+                false,
+                "warns on stderr when the target is Complete or a completed project; JSON carries target_state and warning",
+            ),
+            flag(
+                "research link --force",
+                "link to finished work without the warning; also on each link-research",
+            ),
+            command(
+                "research set-section",
+                "store, update, or with --remove-section delete one ## section of the content",
+            ),
+            flag("research show --section", "print only that section's text"),
+            command(
+                "feature clone",
+                "copy a feature and its tasks into a module under a new name, reset to Draft",
+            ),
+            flag(
+                "research show --links-limit",
+                "show at most N links (default 50); JSON adds link_counts and links_omitted",
+            ),
+            flag("research links --limit", "page through links with --offset"),
+            flag("research links --type", "only links to one entity type"),
+            behavior(
+                "research links",
+                false,
+                "JSON prints [] rather than a plain message when there are no links",
+            ),
+            schema(17, "projects.archived column, with triggers refusing changes inside an archived project"),
+            command(
+                "project archive",
+                "hide a project from project list and refuse changes to anything in it",
+            ),
+            command("project unarchive", "bring an archived project back"),
+            flag("project list --archived", "only archived projects"),
+            flag("project list --all", "archived projects as well"),
+            behavior(
+                "name resolution",
+                false,
+                "a name matching in both an archived and an active project resolves to the active one",
+            ),
+            behavior(
+                "archived projects",
+                false,
+                "commands that change a project, module, feature, or task in an archived project exit 1 until it is unarchived",
+            ),
+            command(
+                "share create",
+                "write a compressed read-only bundle of one project and the research linked into it",
+            ),
+            command(
+                "share open",
+                "run a read command against a share bundle; commands that would change it exit 1",
+            ),
+            behavior(
+                "module/feature/task show",
+                false,
+                "JSON adds allowed_transitions; plain output adds a next: line with the same states",
+            ),
+            behavior(
+                "transition",
+                false,
+                "an invalid transition names the states allowed from the current one and the path to the target",
+            ),
+            schema(18, "trash table holding soft-removed entities and everything removed with them"),
+            flag(
+                "remove --soft",
+                "move the entity and what cascades with it to the trash instead of deleting it",
+            ),
+            flag("remove --hard", "delete outright even when soft_delete is set"),
+            command(
+                "trash list",
+                "what soft removes put in the trash, newest first, with what each took along",
+            ),
+            command(
+                "trash restore",
+                "put a trashed entity back with its subtree and links, ids unchanged",
+            ),
+            command(
+                "trash purge",
+                "delete trashed entities for good, or with --older-than only the old ones",
+            ),
+            behavior(
+                "remove",
+                false,
+                "with soft_delete set to true in the config, every remove goes to the trash unless --hard",
+            ),
+            command(
+                "db backup",
+                "write a consistent copy of the database, timestamped next to it by default; --keep N rotates old ones",
+            ),
+            command(
+                "db restore",
+                "replace the database with a verified backup, saving the current one first",
+            ),
+            schema(19, "indexes on the entity side of the research link tables"),
+            schema(20, "external research content copied into a content directory of its own beside each database"),
+            behavior(
+                "research content",
+                false,
+                "external bodies live in <database>-content/ beside the database rather than one content/ directory shared by every database; db maintain only tidies that database's files",
+            ),
+            behavior(
+                "database open",
+                true,
+                "a database with a schema newer than the binary is refused with kind newer_schema",
+            ),
+            behavior(
+                "migrations",
+                false,
+                "all pending steps run in one transaction, so a failed upgrade leaves the database as it was",
+            ),
+            behavior(
+                "db doctor",
+                true,
+                "also runs integrity and foreign key checks and reports invalid states, bad timestamps, and folded duplicate names; JSON is a findings array graded by severity",
+            ),
+            flag(
+                "db doctor --fix",
+                "also rewrites timestamps into the canonical form, and deletes orphaned attachments and use contexts",
+            ),
+            flag(
+                "--busy-timeout-ms",
+                "how long to wait for another process's write lock (default 5000; also LOPEN_MEMORY_BUSY_TIMEOUT)",
+            ),
+            behavior(
+                "lock contention",
+                false,
+                "a write waits out another writer, then exits 5 with a database is busy error instead of a raw SQLite error or a panic",
+            ),
+            behavior(
+                "add, rename",
+                false,
+                "a name already taken is reported as e.g. a module named 'auth' already exists in project 'myapp', exit 1, also when another process takes it mid-write; database errors exit 2 instead of panicking",
+            ),
+            behavior(
+                "library",
+                false,
+                "the store is usable as the lopen_memory crate: db::open, the resolvers, and typed get/all/create/rename_to, update_*, transition_to, and delete per entity, and link_to/unlink_from/matching for research, returning Result<T, Error>; resolve::Resolver caches name lookups per parent for batches and forgets what renames and removes through it change; CLI output is unchanged",
+            ),
+            behavior(
+                "json errors",
+                true,
+                "under --json every error is {\"error\": {\"code\", \"message\", \"entity\"}} on stderr with a stable code (not_found, ambiguous, invalid_state, conflict, invalid_input, io, db); an ambiguous name now exits 3 instead of 1, and an unreadable import file 2",
+            ),
+            schema(21, "reason column on state_history, the reason given with each change of state"),
+            behavior(
+                "history",
+                false,
+                "each change of state shows the reason given with it, as reason in JSON (null when none was given), also in show --with-history",
+            ),
+            flag(
+                "project complete --reason",
+                "why the project is being completed or reopened (also on project reopen), kept in its state history",
+            ),
+            flag(
+                "--format",
+                "plain, json, ndjson, csv, or tsv before the command; ndjson prints one compact JSON object per list row or shown entity, and nothing for an empty list; csv and tsv print a header named as the JSON keys, then one row per entry",
+            ),
+            flag(
+                "list --fields",
+                "project, module, feature, task, and research list print only these JSON keys, in this order, in every format",
+            ),
+            flag(
+                "list --sort",
+                "project, module, feature, task, and research list order by any field, :desc to reverse; feature and task list take more than priority",
+            ),
+            flag(
+                "list --limit",
+                "project, module, feature, task, and research list, research search, and find show at most this many rows; under --json the output becomes {\"items\", \"returned\", \"total\"}, and plain output ends with how many more there are, or the total when the page is empty",
+            ),
+            flag(
+                "list --offset",
+                "skip this many rows first, for paging with --limit",
+            ),
+            flag(
+                "find --offset",
+                "skip this many matches first, for paging with --limit; plain output ends with how many more matched",
+            ),
+        ],
+    },
+];
+
+/// The version of this binary.
+pub fn current() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
+
+/// major.minor.patch with an optional pre-release tag, which sorts before the
+/// same version without one: 1.1.0-alpha < 1.1.0.
+fn parse(v: &str) -> Option<(u64, u64, u64, Option<&str>)> {
+    let v = v.trim().trim_start_matches('v');
+    let (core, pre) = match v.split_once('-') {
+        Some((c, p)) => (c, Some(p)),
+        None => (v, None),
+    };
+    let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch, pre))
+}
+
+fn compare(a: &(u64, u64, u64, Option<&str>), b: &(u64, u64, u64, Option<&str>)) -> Ordering {
+    (a.0, a.1, a.2)
+        .cmp(&(b.0, b.1, b.2))
+        .then(match (a.3, b.3) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(x), Some(y)) => x.cmp(y),
+        })
+}
+
+/// Releases newer than `since`, or every release when it is None.
+pub fn since(since: Option<&str>) -> Result<Vec<&'static Release>, String> {
+    let floor = match since {
+        Some(s) => Some(
+            parse(s)
+                .ok_or_else(|| format!("invalid version '{}': expected e.g. {}", s, current()))?,
+        ),
+        None => None,
+    };
+    Ok(RELEASES
+        .iter()
+        .filter(|r| match &floor {
+            Some(f) => parse(r.version)
+                .map(|v| compare(&v, f) == Ordering::Greater)
+                .unwrap_or(true),
+            None => true,
+        })
+        .collect())
+}
+
+fn release_json(r: &Release) -> Value {
+    json!({
+        "version": r.version,
+        "changes": r.changes.iter().map(|c| c.to_json()).collect::<Vec<_>>(),
+    })
+}
+
+fn print_change(c: &Change) {
+    let item = match c.migration {
+        Some(n) => format!("{} {}", c.item, n),
+        None => c.item.to_string(),
+    };
+    outln!(
+        "  {:<8} {:<8} {:<44} {}{}",
+        c.action.as_str(),
+        c.kind.as_str(),
+        item,
+        c.note,
+        if c.breaking { "  [breaking]" } else { "" }
+    );
+}
+
+/// List what changed in releases after `since`. With `check`, list only the
+/// breaking changes after that version and exit EXIT_BREAKING if there are any.
+pub fn changes(
+    since_version: Option<&str>,
+    check: Option<&str>,
+    schema_version: i64,
+    json: bool,
+) -> i32 {
+    let floor = check.or(since_version);
+    let releases = match since(floor) {
+        Ok(r) => r,
+        Err(e) => {
+            output::err(&e);
+            return 1;
+        }
+    };
+    let breaking: Vec<(&str, &Change)> = releases
+        .iter()
+        .flat_map(|r| {
+            r.changes
+                .iter()
+                .filter(|c| c.breaking)
+                .map(move |c| (r.version, c))
+        })
+        .collect();
+
+    if json {
+        let mut v = json!({
+            "current": current(),
+            "schema_version": schema_version,
+            "since": floor,
+            "breaking": !breaking.is_empty(),
+        });
+        if check.is_some() {
+            v["breaking_changes"] = breaking
+                .iter()
+                .map(|(version, c)| {
+                    let mut j = c.to_json();
+                    j["version"] = json!(version);
+                    j
+                })
+                .collect();
+        } else {
+            v["releases"] = releases.iter().map(|r| release_json(r)).collect();
+        }
+        output::print_json(&v);
+    } else if check.is_some() {
+        if breaking.is_empty() {
+            output::print_plain(&format!(
+                "no breaking changes since {}",
+                floor.unwrap_or_default()
+            ));
+        } else {
+            output::print_plain(&format!(
+                "{} breaking change(s) since {}:",
+                breaking.len(),
+                floor.unwrap_or_default()
+            ));
+            for (version, c) in &breaking {
+                outln!("{}", version);
+                print_change(c);
+            }
+        }
+    } else if releases.is_empty() {
+        output::print_plain(&format!("no changes since {}", floor.unwrap_or_default()));
+    } else {
+        for r in releases.iter().rev() {
+            outln!("{}", r.version);
+            for c in r.changes {
+                print_change(c);
+            }
+            outln!();
+        }
+    }
+    if check.is_some() && !breaking.is_empty() {
+        return output::EXIT_BREAKING;
+    }
+    0
+}
@@ -0,0 +1,175 @@
+use crate::error::Error;
+use crate::output;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const DEFAULT_CONFIG: &str = "/.lopen-memory/config.json";
+
+/// A config file chosen by the embedding program, ahead of the environment.
+static OVERRIDE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Use `p` as the config file for the rest of this process, whatever
+/// LOPEN_MEMORY_CONFIG says. For library callers that must not touch the
+/// environment, such as tests running side by side.
+pub fn set_path(p: PathBuf) {
+    *OVERRIDE.lock().unwrap_or_else(|e| e.into_inner()) = Some(p);
+}
+
+/// Location of the config file: the one given to `set_path`, else
+/// LOPEN_MEMORY_CONFIG, else ~/.lopen-memory/config.json.
+pub fn path() -> PathBuf {
+    if let Some(p) = OVERRIDE.lock().unwrap_or_else(|p| p.into_inner()).clone() {
+        return p;
+    }
+    if let Ok(p) = std::env::var("LOPEN_MEMORY_CONFIG") {
+        return PathBuf::from(p);
+    }
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    PathBuf::from(format!("{}{}", home, DEFAULT_CONFIG))
+}
+
+/// Directory holding the config file, also used for small state files such as markers.
+pub fn dir() -> PathBuf {
+    path()
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Read the config file. A missing file is an empty config; an unreadable or
+/// malformed one is reported on stderr and treated as empty.
+pub fn load() -> Map<String, Value> {
+    let p = path();
+    let text = match fs::read_to_string(&p) {
+        Ok(t) => t,
+        Err(_) => return Map::new(),
+    };
+    match serde_json::from_str::<Value>(&text) {
+        Ok(Value::Object(m)) => m,
+        _ => {
+            eprintln!(
+                "warning: ignoring config {}: not a JSON object",
+                p.display()
+            );
+            Map::new()
+        }
+    }
+}
+
+/// Write the whole config file, creating its directory when needed.
+pub fn save(map: &Map<String, Value>) -> Result<(), String> {
+    let p = path();
+    if let Some(parent) = p.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+    }
+    let text = serde_json::to_string_pretty(&Value::Object(map.clone())).unwrap_or_default();
+    fs::write(&p, text + "\n").map_err(|e| format!("failed to write {}: {}", p.display(), e))
+}
+
+/// A boolean setting, falling back to `default` when unset or not a boolean.
+pub fn get_bool(key: &str, default: bool) -> bool {
+    load().get(key).and_then(|v| v.as_bool()).unwrap_or(default)
+}
+
+/// An integer setting, or None when unset or not an integer.
+pub fn get_i64(key: &str) -> Option<i64> {
+    load().get(key).and_then(|v| v.as_i64())
+}
+
+/// A string setting, or None when unset or not a string.
+pub fn get_str(key: &str) -> Option<String> {
+    load()
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+}
+
+/// Config values are JSON; anything that does not parse as JSON is stored as a string.
+fn parse_value(raw: &str) -> Value {
+    serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+fn display_value(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+pub fn get(key: &str, json: bool) -> i32 {
+    let map = load();
+    let value = match map.get(key) {
+        Some(v) => v,
+        None => {
+            output::err(&format!("config key not set: {}", key));
+            return 1;
+        }
+    };
+    if json {
+        output::print_json(&json!({ "key": key, "value": value }));
+    } else {
+        output::print_plain(&display_value(value));
+    }
+    0
+}
+
+pub fn set(key: &str, raw: &str, json: bool) -> i32 {
+    let key = key.trim();
+    if key.is_empty() {
+        output::err("config key must not be empty");
+        return 1;
+    }
+    let mut map = load();
+    let value = parse_value(raw);
+    map.insert(key.to_string(), value.clone());
+    if let Err(e) = save(&map) {
+        return output::fail(&Error::Io(e));
+    }
+    if json {
+        output::print_json(&json!({ "key": key, "value": value }));
+    } else {
+        output::print_plain(&format!("set {} = {}", key, display_value(&value)));
+    }
+    0
+}
+
+pub fn unset(key: &str, json: bool) -> i32 {
+    let mut map = load();
+    let removed = map.remove(key).is_some();
+    if removed {
+        if let Err(e) = save(&map) {
+            return output::fail(&Error::Io(e));
+        }
+    }
+    if json {
+        output::print_json(&json!({ "key": key, "removed": removed }));
+    } else if removed {
+        output::print_plain(&format!("unset {}", key));
+    } else {
+        output::print_plain(&format!("{} was not set", key));
+    }
+    0
+}
+
+pub fn list(json: bool) -> i32 {
+    let map = load();
+    if json {
+        output::print_json(&json!({
+            "path": path().display().to_string(),
+            "values": Value::Object(map),
+        }));
+        return 0;
+    }
+    if map.is_empty() {
+        output::print_plain(&format!("no config set ({})", path().display()));
+        return 0;
+    }
+    for (k, v) in &map {
+        outln!("{} = {}", k, display_value(v));
+    }
+    0
+}
@@ -0,0 +1,199 @@
+use crate::config;
+use crate::db;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, Connection};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Prefix of the content column when the body lives in a file instead; the
+/// rest is the file name under the database's `dir()`.
+pub const MARKER: &str = "lopen-memory:external-content:";
+
+const THRESHOLD_KEY: &str = "content_external_threshold";
+const THRESHOLD_ENV: &str = "LOPEN_MEMORY_CONTENT_THRESHOLD";
+
+static WARNED: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+/// Where the external bodies of the database behind `conn` live:
+/// `<database>-content/` beside the file, like SQLite's own `-wal`, one
+/// `<research-id>.md` per record. Each database has its own, since research
+/// ids repeat across databases. An in-memory database uses `legacy_dir()`.
+pub fn dir(conn: &Connection) -> PathBuf {
+    match conn.path().filter(|p| !p.is_empty()) {
+        Some(path) => PathBuf::from(format!("{}-content", path)),
+        None => legacy_dir(),
+    }
+}
+
+/// `content/` next to the config file, where every database kept its bodies
+/// before each had its own directory.
+fn legacy_dir() -> PathBuf {
+    config::dir().join("content")
+}
+
+fn file_name(research_id: i64) -> String {
+    format!("{}.md", research_id)
+}
+
+/// Bytes above which research content is stored in a file rather than the
+/// database: LOPEN_MEMORY_CONTENT_THRESHOLD, else `content_external_threshold`
+/// in the config. Unset, zero, or negative keeps everything inline.
+pub fn threshold() -> Option<usize> {
+    std::env::var(THRESHOLD_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .or_else(|| config::get_i64(THRESHOLD_KEY))
+        .filter(|n| *n > 0)
+        .map(|n| n as usize)
+}
+
+/// The external file a stored content value points at, if it is a pointer.
+pub fn external_file(conn: &Connection, stored: &str) -> Option<PathBuf> {
+    stored.strip_prefix(MARKER).map(|name| dir(conn).join(name))
+}
+
+/// The body behind a stored content value: the value itself, or the text of
+/// the file it points at. A missing or unreadable file reads as empty, with a
+/// warning once per file.
+pub fn body(conn: &Connection, stored: &str) -> String {
+    read(&dir(conn), stored)
+}
+
+/// `body`, with the files under `dir`.
+fn read(dir: &Path, stored: &str) -> String {
+    let Some(path) = stored.strip_prefix(MARKER).map(|name| dir.join(name)) else {
+        return stored.to_string();
+    };
+    match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) => {
+            let mut warned = WARNED.lock().unwrap_or_else(|p| p.into_inner());
+            if warned.get_or_insert_with(HashSet::new).insert(path.clone()) {
+                eprintln!(
+                    "warning: research content file {} cannot be read ({}); run `lopen-memory db maintain`",
+                    path.display(),
+                    e
+                );
+            }
+            String::new()
+        }
+    }
+}
+
+/// Make `content_body(content)` available to SQL on this connection, so
+/// queries read external bodies without each call site knowing about them,
+/// and `content_searchable(content, deep)`, which is the inline body, or an
+/// external one only when `deep` is set.
+pub fn register(conn: &Connection) -> rusqlite::Result<()> {
+    let bodies = dir(conn);
+    conn.create_scalar_function("content_body", 1, FunctionFlags::SQLITE_UTF8, move |ctx| {
+        Ok(ctx.get::<Option<String>>(0)?.map(|s| read(&bodies, &s)))
+    })?;
+    let bodies = dir(conn);
+    conn.create_scalar_function(
+        "content_searchable",
+        2,
+        FunctionFlags::SQLITE_UTF8,
+        move |ctx| {
+            let deep: bool = ctx.get(1)?;
+            Ok(ctx.get::<Option<String>>(0)?.map(|s| {
+                if !s.starts_with(MARKER) {
+                    s
+                } else if deep {
+                    read(&bodies, &s)
+                } else {
+                    String::new()
+                }
+            }))
+        },
+    )
+}
+
+/// Store the content of research `id`: in a file when it is over the
+/// threshold, otherwise inline. A file left behind by a record that shrank
+/// is not deleted here, since the caller's transaction may still roll back;
+/// `db maintain` and `research remove` clean those up.
+pub fn save(conn: &Connection, id: i64, body: &str) -> rusqlite::Result<()> {
+    let stored = match threshold() {
+        Some(t) if body.len() > t => {
+            let name = file_name(id);
+            let path = dir(conn).join(&name);
+            fs::create_dir_all(dir(conn))
+                .and_then(|_| fs::write(&path, body))
+                .map_err(|e| io_error(&path, "write", e))?;
+            format!("{}{}", MARKER, name)
+        }
+        _ => body.to_string(),
+    };
+    conn.execute(
+        "UPDATE research SET content=?1 WHERE id=?2",
+        params![stored, id],
+    )?;
+    Ok(())
+}
+
+/// Delete the external file of a removed research record, if it had one.
+pub fn remove(conn: &Connection, research_id: i64) -> std::io::Result<()> {
+    let path = dir(conn).join(file_name(research_id));
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// A file failure as the SQLite error the caller's `?` passes on.
+fn io_error(path: &Path, action: &str, e: std::io::Error) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(Box::new(std::io::Error::new(
+        e.kind(),
+        format!("failed to {} {}: {}", action, path.display(), e),
+    )))
+}
+
+/// Write the bodies `conn` keeps in external files into `copy`, a copy of
+/// its database, so the copy stands alone wherever it goes.
+pub fn inline_into(conn: &Connection, copy: &Connection) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content_body(content) FROM research WHERE substr(content, 1, length(?1))=?1",
+    )?;
+    let bodies = stmt
+        .query_map(params![MARKER], |r| {
+            Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let tx = db::write_tx(copy)?;
+    for (id, body) in bodies {
+        tx.execute(
+            "UPDATE research SET content=?1 WHERE id=?2",
+            params![body, id],
+        )?;
+    }
+    tx.commit()
+}
+
+/// Copy the bodies this database points at from `legacy_dir()`, shared by
+/// every database before, into its own `dir()`. A file already there is
+/// left alone, so this can run again; the shared copies stay for any other
+/// database still to be opened.
+pub fn adopt_legacy(conn: &Connection) -> rusqlite::Result<()> {
+    let (from, to) = (legacy_dir(), dir(conn));
+    if from == to || !from.is_dir() {
+        return Ok(());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT substr(content, length(?1) + 1) FROM research WHERE substr(content, 1, length(?1))=?1",
+    )?;
+    let names = stmt
+        .query_map(params![MARKER], |r| r.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for name in names {
+        let (src, dest) = (from.join(&name), to.join(&name));
+        if src.is_file() && !dest.exists() {
+            fs::create_dir_all(&to)
+                .and_then(|_| fs::copy(&src, &dest))
+                .map_err(|e| io_error(&dest, "write", e))?;
+        }
+    }
+    Ok(())
+}
@@ -1,18 +1,735 @@
-use rusqlite::{Connection, Result};
+use crate::content;
+use crate::names;
+use crate::timestamp;
+use rusqlite::{params, Connection, Result, Transaction, TransactionBehavior};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-pub fn open(path: &str) -> Result<Connection> {
-    if let Some(parent) = Path::new(path).parent() {
-        if !parent.as_os_str().is_empty() {
-            std::fs::create_dir_all(parent).ok();
+/// How long a statement waits for another process's write lock before it
+/// fails, unless --busy-timeout-ms or LOPEN_MEMORY_BUSY_TIMEOUT says otherwise.
+pub const DEFAULT_BUSY_TIMEOUT_MS: u64 = 5000;
+
+pub const BUSY_TIMEOUT_ENV: &str = "LOPEN_MEMORY_BUSY_TIMEOUT";
+
+static BUSY_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_BUSY_TIMEOUT_MS);
+static BUSY_SINCE: Mutex<Option<Instant>> = Mutex::new(None);
+static GAVE_UP: AtomicBool = AtomicBool::new(false);
+static BUSY_SLEPT_US: AtomicU64 = AtomicU64::new(0);
+
+/// Set how long `open`ed connections wait out another writer, in milliseconds.
+pub fn set_busy_timeout(ms: u64) {
+    BUSY_TIMEOUT_MS.store(ms, Ordering::Relaxed);
+}
+
+pub fn busy_timeout_ms() -> u64 {
+    BUSY_TIMEOUT_MS.load(Ordering::Relaxed)
+}
+
+/// SQLite calls this while another connection holds the lock a statement
+/// needs; `count` is how many times it already has for this wait. Sleeps with
+/// a growing backoff and keeps asking SQLite to retry until the timeout runs
+/// out, then gives up, which the statement reports as SQLITE_BUSY and
+/// `gave_up` remembers.
+fn on_busy(count: i32) -> bool {
+    const BACKOFF_MS: [u64; 8] = [1, 2, 5, 10, 20, 25, 50, 100];
+    let Ok(mut since) = BUSY_SINCE.lock() else {
+        return false;
+    };
+    if count == 0 || since.is_none() {
+        *since = Some(Instant::now());
+    }
+    let waited = since.map_or(Duration::ZERO, |s| s.elapsed());
+    let timeout = Duration::from_millis(busy_timeout_ms());
+    if waited >= timeout {
+        GAVE_UP.store(true, Ordering::Relaxed);
+        return false;
+    }
+    let step = Duration::from_millis(BACKOFF_MS[(count as usize).min(BACKOFF_MS.len() - 1)]);
+    let sleep = step.min(timeout - waited);
+    std::thread::sleep(sleep);
+    BUSY_SLEPT_US.fetch_add(sleep.as_micros() as u64, Ordering::Relaxed);
+    true
+}
+
+/// How long `on_busy` slept waiting for other processes since the last call,
+/// which the statement that waited then counts as part of its run time.
+pub fn take_busy_wait() -> Duration {
+    Duration::from_micros(BUSY_SLEPT_US.swap(0, Ordering::Relaxed))
+}
+
+/// Whether some statement waited the whole busy timeout and failed.
+pub fn gave_up() -> bool {
+    GAVE_UP.load(Ordering::Relaxed)
+}
+
+/// Whether `e` is SQLite giving up on a database another connection held.
+pub fn is_busy(e: &rusqlite::Error) -> bool {
+    e.sqlite_error_code() == Some(rusqlite::ErrorCode::DatabaseBusy)
+}
+
+/// What to tell the user once `gave_up`.
+pub fn busy_message() -> String {
+    format!(
+        "database is busy, retried for {}ms: another process held the write lock; try again, or wait longer with --busy-timeout-ms or {}",
+        busy_timeout_ms(),
+        BUSY_TIMEOUT_ENV
+    )
+}
+
+/// Start a transaction that takes the write lock up front. A deferred one
+/// that reads first can find, on its first write, that another process
+/// committed in between, and SQLite then fails it at once instead of waiting;
+/// taking the lock at BEGIN waits through the busy handler like any statement.
+pub fn write_tx(conn: &Connection) -> Result<Transaction<'_>> {
+    Transaction::new_unchecked(conn, TransactionBehavior::Immediate)
+}
+
+/// The three-column rows `sql` returns for one id, such as the (id, name,
+/// state) of an entity's children for its show view.
+pub fn triples(conn: &Connection, sql: &str, id: i64) -> Result<Vec<(i64, String, String)>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?;
+    rows.collect()
+}
+
+/// Whether a write failed because it would duplicate a name the schema keeps
+/// unique. The clash check before each add and rename catches this first, so
+/// it only happens when another process takes the name in between.
+pub fn name_taken(e: &rusqlite::Error) -> bool {
+    match e {
+        rusqlite::Error::SqliteFailure(f, Some(msg)) => {
+            f.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE && msg.ends_with(".name")
         }
+        _ => false,
     }
+}
+
+/// Open the database, creating and migrating it when needed. A database
+/// already at `schema_version()` skips the DDL and migration pass, and the
+/// journal mode with it: WAL is set when the file is first initialized and
+/// persists in the file, so only the per-connection pragma runs on a warm
+/// open. With `paranoid`, and always in debug builds, the connection is
+/// refused unless foreign key enforcement reads back as on: the pragma is a
+/// silent no-op inside a transaction.
+pub fn open(path: &str, paranoid: bool) -> Result<Connection> {
+    preflight(path)?;
     let conn = Connection::open(path)?;
-    conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA foreign_keys=ON;")?;
-    init_schema(&conn)?;
+    conn.busy_handler(Some(on_busy))?;
+    conn.execute_batch("PRAGMA foreign_keys=ON;")?;
+    if paranoid || cfg!(debug_assertions) {
+        let on: bool = conn.query_row("PRAGMA foreign_keys", [], |r| r.get(0))?;
+        if !on {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+                Some("PRAGMA foreign_keys=ON did not take effect".into()),
+            ));
+        }
+    }
+    names::register(&conn)?;
+    content::register(&conn)?;
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    if version > schema_version() {
+        return Err(newer_schema(version));
+    }
+    if version < schema_version() {
+        conn.execute_batch("PRAGMA journal_mode=WAL;")?;
+        upgrade(&conn)?;
+    }
     Ok(conn)
 }
 
+/// Bring the schema up to date in one immediate transaction: the tables
+/// `init_schema` creates, then every migration step still pending. A step
+/// that fails rolls the whole pass back, so the database stays at the
+/// version it was opened at. The version is read again once the write lock
+/// is held, in case another process upgraded it meanwhile.
+fn upgrade(conn: &Connection) -> Result<()> {
+    let tx = write_tx(conn)?;
+    let version: i64 = tx.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    if version > schema_version() {
+        return Err(newer_schema(version));
+    }
+    init_schema(&tx)?;
+    migrate(&tx, version)?;
+    tx.commit()
+}
+
+/// A database written by a newer binary: its tables may have changed in ways
+/// this one would misread, so it is not opened at all.
+fn newer_schema(version: i64) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_SCHEMA),
+        Some(format!(
+            "the database has schema version {}, newer than this binary understands ({}); upgrade lopen-memory",
+            version,
+            schema_version()
+        )),
+    )
+}
+
+/// Check the path before SQLite sees it, since SQLite reports a directory,
+/// a missing parent it could not create, and an unwritable parent all as
+/// "unable to open database file". Creates the parent directories and, when
+/// the database does not exist yet, the empty file SQLite will initialize.
+/// Failures name the absolute path and the OS error, as SQLITE_CANTOPEN so
+/// `open_error_kind` classifies them as before.
+fn preflight(path: &str) -> Result<()> {
+    if path == ":memory:" {
+        return Ok(());
+    }
+    let target = Path::new(path);
+    let shown = std::path::absolute(target).unwrap_or_else(|_| target.to_path_buf());
+    if target.is_dir() {
+        return Err(cannot_open(format!(
+            "{} is a directory; give the path of a database file inside it, e.g. {}",
+            shown.display(),
+            shown.join("lopen-memory.db").display()
+        )));
+    }
+    if let Some(parent) = shown.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            return Err(cannot_open(format!(
+                "cannot create directory {}: {}",
+                parent.display(),
+                e
+            )));
+        }
+    }
+    if !target.exists() {
+        if let Err(e) = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(target)
+        {
+            if e.kind() != std::io::ErrorKind::AlreadyExists {
+                return Err(cannot_open(format!(
+                    "cannot create database file {}: {}",
+                    shown.display(),
+                    e
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cannot_open(message: String) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+        Some(message),
+    )
+}
+
+/// Why `open` failed, for agents that branch on it: `not_found` (the file or
+/// its directory cannot be there), `permission`, `locked` (another process
+/// holds the database), `corrupt` (not a SQLite database, or damaged),
+/// `newer_schema` (written by a newer lopen-memory), or `other`. SQLite reports most filesystem trouble as one "unable to open",
+/// so that case is narrowed by asking the filesystem directly.
+pub fn open_error_kind(path: &str, e: &rusqlite::Error) -> &'static str {
+    use rusqlite::ErrorCode;
+    use std::io::ErrorKind;
+    let Some(code) = e.sqlite_error_code() else {
+        return "other";
+    };
+    match code {
+        ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked => "locked",
+        ErrorCode::NotADatabase | ErrorCode::DatabaseCorrupt => "corrupt",
+        ErrorCode::SchemaChanged => "newer_schema",
+        ErrorCode::PermissionDenied | ErrorCode::ReadOnly => "permission",
+        ErrorCode::CannotOpen => match std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+        {
+            Err(io) => match io.kind() {
+                ErrorKind::NotFound | ErrorKind::NotADirectory | ErrorKind::IsADirectory => {
+                    "not_found"
+                }
+                ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem => "permission",
+                _ => "other",
+            },
+            Ok(_) => "other",
+        },
+        _ => "other",
+    }
+}
+
+/// One migration step: plain SQL, or Rust for data rewrites SQL cannot express.
+enum Migration {
+    Sql(&'static str),
+    Rust(fn(&Connection) -> Result<()>),
+}
+
+/// Schema changes to tables created by `init_schema`, applied in order. The
+/// index of the last applied step is recorded in PRAGMA user_version, so each
+/// step runs exactly once per database, and all pending steps run in one
+/// transaction. Append only; never edit a released step.
+const MIGRATIONS: &[Migration] = &[
+    // 1: lock flag guarding modules and features against structural changes
+    Migration::Sql(
+        "ALTER TABLE modules  ADD COLUMN locked INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE features ADD COLUMN locked INTEGER NOT NULL DEFAULT 0;",
+    ),
+    // 2: per-directory default scope recorded by `lopen-memory use`
+    Migration::Sql(
+        "CREATE TABLE IF NOT EXISTS contexts (
+         cwd         TEXT    PRIMARY KEY,
+         project_id  INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+         module_id   INTEGER REFERENCES modules(id) ON DELETE SET NULL,
+         feature_id  INTEGER REFERENCES features(id) ON DELETE SET NULL,
+         updated_at  TEXT    NOT NULL
+     );",
+    ),
+    // 3: rewrite every timestamp into the canonical RFC 3339 form
+    Migration::Rust(normalize_timestamps),
+    // 4: every rename, so former names stay traceable
+    Migration::Sql(
+        "CREATE TABLE IF NOT EXISTS name_history (
+         id          INTEGER PRIMARY KEY AUTOINCREMENT,
+         entity_type TEXT    NOT NULL,
+         entity_id   INTEGER NOT NULL,
+         old_name    TEXT    NOT NULL,
+         new_name    TEXT    NOT NULL,
+         renamed_at  TEXT    NOT NULL
+     );
+     CREATE INDEX IF NOT EXISTS idx_name_history_entity ON name_history(entity_type, entity_id);",
+    ),
+    // 5: subdirectory of the project a module's work lives in, relative to the project path
+    Migration::Rust(add_module_path),
+    // 6: local files (benchmarks, PDFs, flamegraphs) attached to research records
+    Migration::Sql(
+        "CREATE TABLE IF NOT EXISTS research_attachments (
+         id          INTEGER PRIMARY KEY AUTOINCREMENT,
+         research_id INTEGER NOT NULL REFERENCES research(id) ON DELETE CASCADE,
+         path        TEXT    NOT NULL,
+         copied      INTEGER NOT NULL DEFAULT 0,
+         size        INTEGER NOT NULL,
+         added_at    TEXT    NOT NULL,
+         UNIQUE(research_id, path)
+     );",
+    ),
+    // 7: advisory named locks for agents coordinating over one database
+    Migration::Sql(
+        "CREATE TABLE IF NOT EXISTS locks (
+         name        TEXT    PRIMARY KEY,
+         holder      TEXT    NOT NULL DEFAULT '',
+         acquired_at TEXT    NOT NULL,
+         expires_at  TEXT    NOT NULL
+     );",
+    ),
+    // 8: reason given with the most recent transition of a module, feature, or task
+    Migration::Rust(add_transition_reason),
+    // 9: full-text index over research, kept current by triggers and backfilled
+    Migration::Rust(add_research_fts),
+    // 10: archived flag keeping superseded research out of search, list, and select
+    Migration::Rust(add_research_archived),
+    // 11: named copies of a project subtree for `snapshot restore`
+    Migration::Sql(
+        "CREATE TABLE IF NOT EXISTS snapshots (
+         id           INTEGER PRIMARY KEY AUTOINCREMENT,
+         name         TEXT    NOT NULL UNIQUE,
+         project_id   INTEGER NOT NULL,
+         project_name TEXT    NOT NULL,
+         created_at   TEXT    NOT NULL,
+         document     TEXT    NOT NULL
+     );",
+    ),
+    // 12: explicit work order of the tasks within a feature
+    Migration::Rust(add_task_position),
+    // 13: tasks that cannot start until another is Complete
+    Migration::Sql(
+        "CREATE TABLE IF NOT EXISTS task_dependencies (
+         task_id            INTEGER NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+         depends_on_task_id INTEGER NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+         PRIMARY KEY (task_id, depends_on_task_id)
+     );
+     CREATE INDEX IF NOT EXISTS idx_task_dependencies_on ON task_dependencies(depends_on_task_id);",
+    ),
+    // 14: how much a task or feature matters: low, medium, high, or critical
+    Migration::Rust(add_priority),
+    // 15: Blocked joins the lifecycle states the CHECK constraints accept
+    Migration::Rust(allow_blocked_state),
+    // 16: every change of state, so time spent in each state can be traced;
+    // rows go with the entity they describe
+    Migration::Sql(
+        "CREATE TABLE IF NOT EXISTS state_history (
+         id          INTEGER PRIMARY KEY AUTOINCREMENT,
+         entity_type TEXT    NOT NULL,
+         entity_id   INTEGER NOT NULL,
+         from_state  TEXT    NOT NULL,
+         to_state    TEXT    NOT NULL,
+         at          TEXT    NOT NULL
+     );
+     CREATE INDEX IF NOT EXISTS idx_state_history_entity ON state_history(entity_type, entity_id);
+     CREATE TRIGGER IF NOT EXISTS state_history_projects AFTER DELETE ON projects BEGIN
+         DELETE FROM state_history WHERE entity_type='project' AND entity_id=old.id;
+     END;
+     CREATE TRIGGER IF NOT EXISTS state_history_modules AFTER DELETE ON modules BEGIN
+         DELETE FROM state_history WHERE entity_type='module' AND entity_id=old.id;
+     END;
+     CREATE TRIGGER IF NOT EXISTS state_history_features AFTER DELETE ON features BEGIN
+         DELETE FROM state_history WHERE entity_type='feature' AND entity_id=old.id;
+     END;
+     CREATE TRIGGER IF NOT EXISTS state_history_tasks AFTER DELETE ON tasks BEGIN
+         DELETE FROM state_history WHERE entity_type='task' AND entity_id=old.id;
+     END;",
+    ),
+    // 17: archived projects, kept out of `project list` and read-only until
+    // unarchived, with triggers refusing writes under them
+    Migration::Rust(add_project_archived),
+    // 18: the trash, holding what a soft remove took out until it is
+    // restored or purged
+    Migration::Sql(
+        "CREATE TABLE IF NOT EXISTS trash (
+         id          INTEGER PRIMARY KEY AUTOINCREMENT,
+         entity_type TEXT    NOT NULL,
+         entity_id   INTEGER NOT NULL,
+         name        TEXT    NOT NULL,
+         breadcrumb  TEXT    NOT NULL,
+         deleted_at  TEXT    NOT NULL,
+         document    TEXT    NOT NULL
+     );
+     CREATE INDEX IF NOT EXISTS idx_trash_entity ON trash(entity_type, entity_id);",
+    ),
+    // 19: the research links of one entity, looked up from the entity's side;
+    // each bridge table's primary key only leads with research_id
+    Migration::Sql(
+        "CREATE INDEX IF NOT EXISTS idx_research_projects_project ON research_projects(project_id);
+     CREATE INDEX IF NOT EXISTS idx_research_modules_module   ON research_modules(module_id);
+     CREATE INDEX IF NOT EXISTS idx_research_features_feature ON research_features(feature_id);
+     CREATE INDEX IF NOT EXISTS idx_research_tasks_task       ON research_tasks(task_id);",
+    ),
+    // 20: external research content copied from the directory every database
+    // shared into this database's own
+    Migration::Rust(content::adopt_legacy),
+    // 21: the reason given with each recorded change of state
+    Migration::Rust(add_state_history_reason),
+];
+
+/// The user_version a database opened by this binary ends up at.
+pub fn schema_version() -> i64 {
+    MIGRATIONS.len() as i64
+}
+
+/// Run the steps after `version`, inside the caller's transaction.
+fn migrate(conn: &Connection, version: i64) -> Result<()> {
+    for (i, step) in MIGRATIONS.iter().enumerate().skip(version.max(0) as usize) {
+        match step {
+            Migration::Sql(sql) => conn.execute_batch(sql)?,
+            Migration::Rust(f) => f(conn)?,
+        }
+        conn.pragma_update(None, "user_version", (i + 1) as i64)?;
+    }
+    Ok(())
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        params![table, column],
+        |r| r.get(0),
+    )
+}
+
+/// Add `modules.path` unless it is already there, so a database whose
+/// user_version was wound back to replay earlier steps still opens.
+fn add_module_path(conn: &Connection) -> Result<()> {
+    if !has_column(conn, "modules", "path")? {
+        conn.execute_batch("ALTER TABLE modules ADD COLUMN path TEXT NOT NULL DEFAULT '';")?;
+    }
+    Ok(())
+}
+
+/// Add `last_transition_reason` to modules, features, and tasks, skipping any
+/// table that already has it, for the same reason as `add_module_path`.
+fn add_transition_reason(conn: &Connection) -> Result<()> {
+    for table in ["modules", "features", "tasks"] {
+        if !has_column(conn, table, "last_transition_reason")? {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN last_transition_reason TEXT NOT NULL DEFAULT '';",
+                table
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// Add `state_history.reason`, skipping it when already there, for the same
+/// reason as `add_module_path`.
+fn add_state_history_reason(conn: &Connection) -> Result<()> {
+    if !has_column(conn, "state_history", "reason")? {
+        conn.execute_batch(
+            "ALTER TABLE state_history ADD COLUMN reason TEXT NOT NULL DEFAULT '';",
+        )?;
+    }
+    Ok(())
+}
+
+/// Add `research.archived`, skipping it when already there, for the same
+/// reason as `add_module_path`.
+fn add_research_archived(conn: &Connection) -> Result<()> {
+    if !has_column(conn, "research", "archived")? {
+        conn.execute_batch("ALTER TABLE research ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;")?;
+    }
+    Ok(())
+}
+
+/// Whether the project owning `row` of `table` (`new` or `old`) is archived,
+/// as SQL for a trigger's WHEN clause. The bridge tables reach the project
+/// through the entity they link to.
+fn archived_owner(table: &str, row: &str) -> String {
+    let project = match table {
+        "modules" | "research_projects" => format!("{}.project_id", row),
+        "features" | "research_modules" => format!(
+            "(SELECT project_id FROM modules WHERE id = {}.module_id)",
+            row
+        ),
+        "tasks" | "research_features" => format!(
+            "(SELECT m.project_id FROM features f JOIN modules m ON m.id = f.module_id
+              WHERE f.id = {}.feature_id)",
+            row
+        ),
+        _ => format!(
+            "(SELECT m.project_id FROM tasks t JOIN features f ON f.id = t.feature_id
+              JOIN modules m ON m.id = f.module_id WHERE t.id = {}.task_id)",
+            row
+        ),
+    };
+    format!("(SELECT archived FROM projects WHERE id = {}) = 1", project)
+}
+
+/// Add `projects.archived`, skipping the column when already there, for the
+/// same reason as `add_module_path`, and the triggers that keep everything
+/// under an archived project as it is: no module, feature, or task under one
+/// is added, changed, or deleted, and nothing new is linked to one. The
+/// project row itself only changes to be unarchived. Commands check first and
+/// say so; the triggers catch any write that gets past them.
+fn add_project_archived(conn: &Connection) -> Result<()> {
+    if !has_column(conn, "projects", "archived")? {
+        conn.execute_batch("ALTER TABLE projects ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;")?;
+    }
+    let refuse = "SELECT RAISE(ABORT, 'project is archived; unarchive it first');";
+    let mut sql = format!(
+        "CREATE TRIGGER IF NOT EXISTS archived_projects_update BEFORE UPDATE ON projects
+         WHEN old.archived = 1 AND new.archived = 1 BEGIN {} END;",
+        refuse
+    );
+    for table in ["modules", "features", "tasks"] {
+        sql.push_str(&format!(
+            "CREATE TRIGGER IF NOT EXISTS archived_{t}_insert BEFORE INSERT ON {t}
+             WHEN {new} BEGIN {r} END;
+             CREATE TRIGGER IF NOT EXISTS archived_{t}_update BEFORE UPDATE ON {t}
+             WHEN {old} OR {new} BEGIN {r} END;
+             CREATE TRIGGER IF NOT EXISTS archived_{t}_delete BEFORE DELETE ON {t}
+             WHEN {old} BEGIN {r} END;",
+            t = table,
+            new = archived_owner(table, "new"),
+            old = archived_owner(table, "old"),
+            r = refuse
+        ));
+    }
+    for table in [
+        "research_projects",
+        "research_modules",
+        "research_features",
+        "research_tasks",
+    ] {
+        sql.push_str(&format!(
+            "CREATE TRIGGER IF NOT EXISTS archived_{t}_insert BEFORE INSERT ON {t}
+             WHEN {new} BEGIN {r} END;",
+            t = table,
+            new = archived_owner(table, "new"),
+            r = refuse
+        ));
+    }
+    conn.execute_batch(&sql)
+}
+
+/// Add `tasks.position`, starting every existing task at its id so the
+/// order they were listed in (creation order) is kept, skipping the column
+/// when already there, for the same reason as `add_module_path`.
+fn add_task_position(conn: &Connection) -> Result<()> {
+    if !has_column(conn, "tasks", "position")? {
+        conn.execute_batch(
+            "ALTER TABLE tasks ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+             UPDATE tasks SET position = id;",
+        )?;
+    }
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_tasks_position ON tasks(feature_id, position);",
+    )
+}
+
+fn add_priority(conn: &Connection) -> Result<()> {
+    for table in ["tasks", "features"] {
+        if !has_column(conn, table, "priority")? {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN priority TEXT NOT NULL DEFAULT 'medium';",
+                table
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// Widen the state CHECK constraints of modules, features, and tasks to
+/// admit Blocked. SQLite cannot alter a constraint, and rebuilding the tables
+/// would need foreign keys off outside any transaction; since a wider CHECK
+/// changes nothing on disk, the stored table definitions are edited in place
+/// instead, as the SQLite documentation describes for such changes.
+fn allow_blocked_state(conn: &Connection) -> Result<()> {
+    const OLD: &str = "CHECK(state IN ('Draft','Planning','Building','Complete','Amending'))";
+    const NEW: &str =
+        "CHECK(state IN ('Draft','Planning','Building','Complete','Amending','Blocked'))";
+    let pending: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_schema
+         WHERE type = 'table' AND name IN ('modules', 'features', 'tasks') AND instr(sql, ?1) > 0",
+        params![OLD],
+        |r| r.get(0),
+    )?;
+    if pending == 0 {
+        return Ok(());
+    }
+    let version: i64 = conn.query_row("PRAGMA schema_version", [], |r| r.get(0))?;
+    conn.execute_batch("PRAGMA writable_schema = ON;")?;
+    let edited = conn.execute(
+        "UPDATE sqlite_schema SET sql = replace(sql, ?1, ?2)
+         WHERE type = 'table' AND name IN ('modules', 'features', 'tasks')",
+        params![OLD, NEW],
+    );
+    // Bump the schema cookie so every connection rereads the definitions.
+    conn.execute_batch(&format!(
+        "PRAGMA schema_version = {}; PRAGMA writable_schema = OFF;",
+        version + 1
+    ))?;
+    edited.map(|_| ())
+}
+
+/// Inline content as the full-text index sees it: a pointer to an external
+/// file indexes as empty, since only `--deep` searches external bodies.
+fn fts_content(row: &str) -> String {
+    format!(
+        "CASE WHEN instr({row}.content, '{marker}') = 1 THEN '' ELSE {row}.content END",
+        row = row,
+        marker = content::MARKER
+    )
+}
+
+/// Create `research_fts`, an FTS5 index over research name, description,
+/// inline content, and source, with triggers that keep it in step with the
+/// table, and index every existing record. A SQLite built without FTS5 gets
+/// no index, and search falls back to LIKE.
+fn add_research_fts(conn: &Connection) -> Result<()> {
+    let fts5: bool =
+        conn.query_row("SELECT sqlite_compileoption_used('ENABLE_FTS5')", [], |r| {
+            r.get(0)
+        })?;
+    if !fts5 {
+        return Ok(());
+    }
+    conn.execute_batch(&format!(
+        "DROP TABLE IF EXISTS research_fts;
+         CREATE VIRTUAL TABLE research_fts USING fts5(
+             name, description, content, source,
+             content='research', content_rowid='id'
+         );
+         CREATE TRIGGER IF NOT EXISTS research_fts_insert AFTER INSERT ON research BEGIN
+             INSERT INTO research_fts(rowid, name, description, content, source)
+             VALUES (new.id, new.name, new.description, {new}, new.source);
+         END;
+         CREATE TRIGGER IF NOT EXISTS research_fts_delete AFTER DELETE ON research BEGIN
+             INSERT INTO research_fts(research_fts, rowid, name, description, content, source)
+             VALUES ('delete', old.id, old.name, old.description, {old}, old.source);
+         END;
+         CREATE TRIGGER IF NOT EXISTS research_fts_update
+         AFTER UPDATE OF name, description, content, source ON research BEGIN
+             INSERT INTO research_fts(research_fts, rowid, name, description, content, source)
+             VALUES ('delete', old.id, old.name, old.description, {old}, old.source);
+             INSERT INTO research_fts(rowid, name, description, content, source)
+             VALUES (new.id, new.name, new.description, {new}, new.source);
+         END;
+         INSERT INTO research_fts(rowid, name, description, content, source)
+         SELECT id, name, description, {research}, source FROM research;",
+        old = fts_content("old"),
+        new = fts_content("new"),
+        research = fts_content("research"),
+    ))
+}
+
+/// Every timestamp column, as (table, column).
+pub const TIMESTAMP_COLUMNS: [(&str, &str); 8] = [
+    ("projects", "updated_at"),
+    ("modules", "last_worked_on"),
+    ("features", "last_worked_on"),
+    ("tasks", "last_worked_on"),
+    ("research", "researched_at"),
+    ("research", "created_at"),
+    ("research", "updated_at"),
+    ("contexts", "updated_at"),
+];
+
+/// Rewrite timestamps written by SQLite defaults, older releases, or by hand
+/// into `timestamp::CANONICAL`. Values that cannot be parsed are left as they
+/// are and recorded in `timestamp_issues` for later inspection.
+fn normalize_timestamps(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS timestamp_issues (
+             table_name  TEXT    NOT NULL,
+             column_name TEXT    NOT NULL,
+             row_id      INTEGER NOT NULL,
+             value       TEXT    NOT NULL,
+             PRIMARY KEY (table_name, column_name, row_id)
+         );",
+    )?;
+    let mut normalized = 0;
+    let mut unparseable: Vec<String> = Vec::new();
+    for (table, column) in TIMESTAMP_COLUMNS {
+        let rows: Vec<(i64, String)> = {
+            let mut stmt = conn.prepare(&format!("SELECT rowid, {} FROM {}", column, table))?;
+            let rows = stmt
+                .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect::<Result<_>>()?;
+            rows
+        };
+        for (rowid, value) in rows {
+            match timestamp::parse_tolerant(&value) {
+                Some(dt) => {
+                    let canon = timestamp::canonical(&dt);
+                    if canon != value {
+                        conn.execute(
+                            &format!("UPDATE {} SET {}=?1 WHERE rowid=?2", table, column),
+                            params![canon, rowid],
+                        )?;
+                        normalized += 1;
+                    }
+                }
+                None => {
+                    conn.execute(
+                        "INSERT OR REPLACE INTO timestamp_issues VALUES (?1,?2,?3,?4)",
+                        params![table, column, rowid, value],
+                    )?;
+                    unparseable.push(format!("{}.{} row {}: {:?}", table, column, rowid, value));
+                }
+            }
+        }
+    }
+    if normalized > 0 || !unparseable.is_empty() {
+        eprintln!(
+            "migration: normalized {} timestamp(s); {} could not be parsed and were left unchanged",
+            normalized,
+            unparseable.len()
+        );
+        for u in &unparseable {
+            eprintln!("  {}", u);
+        }
+    }
+    Ok(())
+}
+
 fn init_schema(conn: &Connection) -> Result<()> {
     conn.execute_batch("
         CREATE TABLE IF NOT EXISTS projects (
@@ -0,0 +1,120 @@
+use crate::db;
+use crate::output;
+
+/// Why a library call failed. The message is the one the CLI prints after
+/// `error:`, `code` the stable class it reports under `--json`, and
+/// `exit_code` the status it exits with:
+///
+/// | code            | exit                            |
+/// |-----------------|---------------------------------|
+/// | `not_found`     | 1                               |
+/// | `ambiguous`     | 3                               |
+/// | `invalid_state` | 1, or 4 when locked             |
+/// | `conflict`      | 1                               |
+/// | `invalid_input` | 1                               |
+/// | `io`            | 2                               |
+/// | `db`            | 2, or 5 when the database stayed busy |
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Nothing of type `entity` matches the name, id, or ref given.
+    #[error("{message}")]
+    NotFound {
+        entity: &'static str,
+        message: String,
+    },
+    /// A name matches more than one `entity`; a parent narrows it.
+    #[error("{message}")]
+    Ambiguous {
+        entity: &'static str,
+        message: String,
+    },
+    /// The lifecycle has no such move.
+    #[error("{0}")]
+    InvalidTransition(String),
+    /// The target is not in a state that allows the change, e.g. an archived
+    /// project or a parent with unfinished children.
+    #[error("{0}")]
+    InvalidState(String),
+    /// The change clashes with what is stored, e.g. a name already taken.
+    #[error("{0}")]
+    Conflict(String),
+    /// Input no database would accept, e.g. an empty name or a malformed id.
+    #[error("{0}")]
+    Invalid(String),
+    /// The target, or the parent it would change, is locked.
+    #[error("{0}")]
+    Locked(String),
+    /// Reading or writing a file outside the database failed.
+    #[error("{0}")]
+    Io(String),
+    #[error(transparent)]
+    Db(#[from] rusqlite::Error),
+}
+
+impl Error {
+    pub fn not_found(entity: &'static str, message: String) -> Error {
+        Error::NotFound { entity, message }
+    }
+
+    /// `e` from reading `entity` `id`, as NotFound when there is no such row.
+    pub(crate) fn missing(e: rusqlite::Error, entity: &'static str, id: i64) -> Error {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => {
+                Error::not_found(entity, format!("{} not found: {}", entity, id))
+            }
+            e => Error::Db(e),
+        }
+    }
+
+    pub fn ambiguous(entity: &'static str, message: String) -> Error {
+        Error::Ambiguous { entity, message }
+    }
+
+    /// The CLI's exit code: 1 for anything the user can fix by asking
+    /// differently, 3 when a parent would narrow the match, 4 when locked, 5
+    /// when another process held the database for the whole busy timeout, 2
+    /// for any other database or file failure.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Ambiguous { .. } => output::EXIT_AMBIGUOUS,
+            Error::Locked(_) => output::EXIT_LOCKED,
+            Error::Db(e) if db::is_busy(e) => output::EXIT_BUSY,
+            Error::Db(_) | Error::Io(_) => 2,
+            _ => 1,
+        }
+    }
+
+    /// The stable class reported as `error.code` under `--json`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::NotFound { .. } => "not_found",
+            Error::Ambiguous { .. } => "ambiguous",
+            Error::InvalidTransition(_) | Error::InvalidState(_) | Error::Locked(_) => {
+                "invalid_state"
+            }
+            Error::Conflict(_) => "conflict",
+            Error::Invalid(_) => "invalid_input",
+            Error::Io(_) => "io",
+            Error::Db(_) => "db",
+        }
+    }
+
+    /// The entity type a lookup failed for, when there was one.
+    pub fn entity(&self) -> Option<&'static str> {
+        match self {
+            Error::NotFound { entity, .. } | Error::Ambiguous { entity, .. } => Some(entity),
+            _ => None,
+        }
+    }
+}
+
+/// The code for a failure reported only as text, from the exit code it ended
+/// with; the inverse of the table on [`Error`].
+pub fn code_for_exit(exit: i32) -> &'static str {
+    match exit {
+        2 | output::EXIT_BUSY => "db",
+        output::EXIT_AMBIGUOUS => "ambiguous",
+        output::EXIT_LOCKED => "invalid_state",
+        _ => "invalid_input",
+    }
+}
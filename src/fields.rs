@@ -0,0 +1,214 @@
+//! `--fields`, `--sort`, `--limit`, and `--offset` for the list commands.
+//! Fields are named by their JSON keys, so one set of names serves every
+//! output format; sorting and paging happen in the query.
+
+use crate::error::Error;
+use crate::output;
+use rusqlite::{Connection, Params};
+use serde_json::{json, Map, Value};
+
+/// A `--sort` field and direction, checked against the entity's fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub field: &'static str,
+    pub desc: bool,
+}
+
+impl Sort {
+    /// ORDER BY terms: `expr`, the field's SQL, in this direction, then
+    /// `then` to break ties. Each term of a list such as a name's sort keys
+    /// takes the direction.
+    pub fn order_by(&self, expr: &str, then: &str) -> String {
+        let dir = if self.desc { " DESC" } else { " ASC" };
+        let mut terms = Vec::new();
+        let (mut depth, mut start) = (0, 0);
+        for (i, c) in expr.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    terms.push(format!("{}{}", expr[start..i].trim(), dir));
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        terms.push(format!("{}{}", expr[start..].trim(), dir));
+        format!("{}, {}", terms.join(", "), then)
+    }
+}
+
+/// `--limit` and `--offset`: the window of a list to show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Page {
+    pub limit: Option<i64>,
+    pub offset: i64,
+}
+
+impl Page {
+    /// Whether this is the whole list, as with neither flag.
+    pub fn is_whole(&self) -> bool {
+        self.limit.is_none() && self.offset == 0
+    }
+
+    /// ` LIMIT n OFFSET m` to append to a query, or nothing for the whole
+    /// list.
+    pub fn sql(&self) -> String {
+        if self.is_whole() {
+            return String::new();
+        }
+        format!(" LIMIT {} OFFSET {}", self.limit.unwrap_or(-1), self.offset)
+    }
+}
+
+/// The rows `sql`, a list query without its page, would return in all.
+pub fn count<P: Params>(conn: &Connection, sql: &str, params: P) -> rusqlite::Result<i64> {
+    conn.query_row(&format!("SELECT COUNT(*) FROM ({})", sql), params, |r| {
+        r.get(0)
+    })
+}
+
+/// The total behind a paged list, when it will be printed: with the JSON
+/// document or the plain hint, but not the row-by-row formats.
+pub fn wants_total(page: Page) -> bool {
+    !page.is_whole() && !output::format().per_row()
+}
+
+/// Whether an empty list is reported in prose: not row by row, where no rows
+/// says it, nor in a paged JSON document, whose counts do, nor on an empty
+/// page of a list that has rows, which [`print_more`] reports.
+pub fn empty_in_prose(json: bool, total: Option<i64>) -> bool {
+    !(output::format().per_row() || total.is_some_and(|t| json || t > 0))
+}
+
+/// Print a structured list: `rows` through [`print`] with `fields`, else
+/// with every one of `columns`. Under `--json` with a `total`, the rows are
+/// `items` in `{"items", "returned", "total"}`.
+pub fn print_list(
+    columns: &[&str],
+    fields: Option<&[&str]>,
+    rows: Vec<Value>,
+    total: Option<i64>,
+    json: bool,
+) {
+    match total {
+        Some(total) if json && output::format() == output::Format::Json => {
+            let items: Vec<Value> = match fields {
+                Some(fields) => rows.iter().map(|r| pick(fields, r)).collect(),
+                None => rows,
+            };
+            output::print_json(&json!({
+                "items": items,
+                "returned": items.len(),
+                "total": total,
+            }));
+        }
+        _ => match fields {
+            Some(fields) => print(fields, rows, json),
+            None => output::print_rows(columns, rows),
+        },
+    }
+}
+
+/// After a plain list cut short by `page`: `… N more (use --limit/--offset)`,
+/// or, when the page itself is empty, how many rows there are to page through.
+pub fn print_more(page: Page, returned: usize, total: Option<i64>) {
+    let total = total.unwrap_or(0);
+    let more = total - page.offset - returned as i64;
+    if more > 0 {
+        output::print_plain(&format!("… {} more (use --limit/--offset)", more));
+    } else if returned == 0 && total > 0 {
+        output::print_plain(&format!(
+            "no rows in this page (total {}; use --limit/--offset)",
+            total
+        ));
+    }
+}
+
+fn unknown(entity: &str, field: &str, valid: &[&str]) -> Error {
+    Error::Invalid(format!(
+        "unknown field '{}' for {}; valid fields: {}",
+        field,
+        entity,
+        valid.join(", ")
+    ))
+}
+
+fn field(entity: &str, valid: &'static [&'static str], name: &str) -> Result<&'static str, Error> {
+    valid
+        .iter()
+        .copied()
+        .find(|f| *f == name)
+        .ok_or_else(|| unknown(entity, name, valid))
+}
+
+/// `spec`, comma-separated field names, checked against `valid` and kept in
+/// the order given.
+pub fn parse_fields(
+    entity: &str,
+    valid: &'static [&'static str],
+    spec: &str,
+) -> Result<Vec<&'static str>, Error> {
+    let fields = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(|f| field(entity, valid, f))
+        .collect::<Result<Vec<_>, _>>()?;
+    if fields.is_empty() {
+        return Err(Error::Invalid(format!(
+            "--fields needs at least one field; valid fields: {}",
+            valid.join(", ")
+        )));
+    }
+    Ok(fields)
+}
+
+/// `spec`, `<field>` or `<field>:desc` (`:asc` is the default), checked
+/// against `valid`.
+pub fn parse_sort(entity: &str, valid: &'static [&'static str], spec: &str) -> Result<Sort, Error> {
+    let (name, desc) = match spec.rsplit_once(':') {
+        Some((name, "desc")) => (name, true),
+        Some((name, "asc")) => (name, false),
+        Some((_, dir)) => {
+            return Err(Error::Invalid(format!(
+                "invalid sort direction '{}'; must be asc or desc",
+                dir
+            )))
+        }
+        None => (spec, false),
+    };
+    let name = name.trim();
+    let field = valid.iter().copied().find(|f| *f == name).ok_or_else(|| {
+        Error::Invalid(format!(
+            "cannot sort {} by '{}'; sortable fields: {}",
+            entity,
+            name,
+            valid.join(", ")
+        ))
+    })?;
+    Ok(Sort { field, desc })
+}
+
+/// `row` with only `fields`.
+pub fn pick(fields: &[&str], row: &Value) -> Value {
+    let mut picked = Map::new();
+    for f in fields {
+        picked.insert(f.to_string(), row[*f].clone());
+    }
+    Value::Object(picked)
+}
+
+/// Print `rows` with only `fields`, in that order: aligned columns in plain
+/// output, else as the chosen format prints a list.
+pub fn print<I: IntoIterator<Item = Value>>(fields: &[&str], rows: I, json: bool) {
+    if json {
+        output::print_rows(fields, rows.into_iter().map(|r| pick(fields, &r)));
+        return;
+    }
+    let rows: Vec<Vec<String>> = rows
+        .into_iter()
+        .map(|r| fields.iter().map(|f| output::cell(&r[*f])).collect())
+        .collect();
+    output::print_columns(&rows);
+}
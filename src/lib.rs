@@ -0,0 +1,54 @@
+//! lopen-memory's store as a library, for tools that embed it instead of
+//! running the CLI.
+//!
+//! Open a database with [`db::open`], find entities with the resolvers in
+//! [`resolve`], and read or change them through the typed functions of each
+//! entity model, which return [`Result<T, Error>`](Error) and print nothing:
+//! `get`, `all`, `create`, `rename_to`, the `update_*` setters,
+//! `transition_to`, and `delete`; research adds `link_to`, `unlink_from`,
+//! and `matching` for search. The entity structs serialize to the same JSON
+//! the CLI prints under `--json`, and [`Error::exit_code`] is the exit code
+//! the CLI would end with.
+//!
+//! ```
+//! use lopen_memory::{db, models::{module, project}, resolve, state::State};
+//!
+//! let conn = db::open(":memory:", false)?;
+//! project::create(&conn, "myapp", "", "")?;
+//! let myapp = resolve::resolve_project(&conn, "myapp")?;
+//! let auth = module::create(&conn, myapp, "auth", "", "", "", None)?;
+//! let moved = module::transition_to(&conn, auth.id, &State::Planning, None, false)?;
+//! assert_eq!(moved.entity.state, "Planning");
+//! println!("{}", serde_json::to_string(&moved.entity).unwrap());
+//! # Ok::<(), lopen_memory::Error>(())
+//! ```
+//!
+//! A caller resolving many names, as a batch does, can look them up through
+//! one [`resolve::Resolver`], which reads each parent's names once. Renames
+//! and removes go through its `rename` and `remove` so it forgets what they
+//! change.
+//!
+//! The rest of each model is the CLI's: functions that print their result
+//! and return a process exit code.
+
+// First, so the outln!/out! it defines are in scope in every module below.
+#[macro_use]
+pub mod output;
+pub mod changes;
+pub mod config;
+pub mod content;
+pub mod db;
+pub mod error;
+pub mod fields;
+pub mod limits;
+pub mod models;
+pub mod names;
+pub mod priority;
+pub mod profile;
+pub mod resolve;
+pub mod skill;
+pub mod state;
+pub mod timestamp;
+pub mod trace;
+
+pub use error::Error;
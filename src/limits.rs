@@ -0,0 +1,115 @@
+/// Accepted ranges for numeric flags and settings, so every one is checked
+/// the same way and reports an out-of-range value with the same message.
+pub struct Range {
+    pub min: i64,
+    pub max: i64,
+    pub unit: &'static str,
+}
+
+/// Day thresholds: stale research, tasks stuck in Building. A hundred years
+/// is past any record worth keeping.
+pub const DAYS: Range = Range {
+    min: 1,
+    max: 36_500,
+    unit: "days",
+};
+
+/// Ages that may be zero, as `cleanup --older-than 0` for everything.
+pub const AGE_DAYS: Range = Range {
+    min: 0,
+    max: 36_500,
+    unit: "days",
+};
+
+/// How many results to show.
+pub const LIMIT: Range = Range {
+    min: 0,
+    max: 100_000,
+    unit: "",
+};
+
+/// How many results to skip when paging.
+pub const OFFSET: Range = Range {
+    min: 0,
+    max: 1_000_000,
+    unit: "",
+};
+
+/// How many candidates to rank, where none would be pointless.
+pub const COUNT: Range = Range {
+    min: 1,
+    max: 1_000,
+    unit: "",
+};
+
+/// Link counts to filter research by.
+pub const LINKS: Range = Range {
+    min: 0,
+    max: 1_000_000,
+    unit: "links",
+};
+
+/// Lines of context around a diff hunk.
+pub const CONTEXT_LINES: Range = Range {
+    min: 0,
+    max: 10_000,
+    unit: "lines",
+};
+
+/// A character budget for packed research text.
+pub const CHARS: Range = Range {
+    min: 1,
+    max: 100_000_000,
+    unit: "characters",
+};
+
+/// How long to wait out another writer, up to ten minutes.
+pub const BUSY_MS: Range = Range {
+    min: 0,
+    max: 600_000,
+    unit: "ms",
+};
+
+/// Lock lifetimes, up to a year.
+pub const TTL_SECS: Range = Range {
+    min: 1,
+    max: 31_536_000,
+    unit: "seconds",
+};
+
+impl Range {
+    /// `value` if it lies in the range, else an error naming `flag`, e.g.
+    /// `--stale-days must be between 1 and 36500 days (got -5)`.
+    pub fn check(&self, flag: &str, value: i64) -> Result<i64, String> {
+        if (self.min..=self.max).contains(&value) {
+            return Ok(value);
+        }
+        let unit = if self.unit.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", self.unit)
+        };
+        Err(format!(
+            "{} must be between {} and {}{} (got {})",
+            flag, self.min, self.max, unit, value
+        ))
+    }
+
+    /// `check` for an optional flag.
+    pub fn check_opt(&self, flag: &str, value: Option<i64>) -> Result<Option<i64>, String> {
+        value.map(|v| self.check(flag, v)).transpose()
+    }
+
+    /// A value read from the environment or the config file: out of range is
+    /// a warning naming where it came from, and the value is ignored.
+    pub fn setting(&self, source: &str, value: Option<i64>) -> Option<i64> {
+        let v = value?;
+        match self.check(source, v) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("warning: {}; ignoring it", e);
+                None
+            }
+        }
+    }
+}
@@ -1,11 +1,12 @@
-mod db;
-mod models;
-mod output;
-mod resolve;
-mod skill;
-mod state;
-
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use lopen_memory::{
+    changes, config, db, fields, limits, models, output, priority, profile, resolve, skill, state,
+    trace, Error,
+};
+use models::context;
+use models::placement::Placement;
+use serde_json::json;
+use std::io::Read;
 use std::process;
 
 const DEFAULT_DB: &str = "/.lopen-memory/lopen-memory.db";
@@ -21,6 +22,40 @@ fn db_path(override_path: Option<&String>) -> String {
     format!("{}{}", home, DEFAULT_DB)
 }
 
+/// The busy timeout: --busy-timeout-ms, else LOPEN_MEMORY_BUSY_TIMEOUT, else
+/// the default. A flag out of range is an error; the variable is warned about
+/// and skipped.
+fn busy_timeout(flag: Option<i64>) -> Result<u64, String> {
+    let ms = match flag {
+        Some(v) => Some(limits::BUSY_MS.check("--busy-timeout-ms", v)?),
+        None => limits::BUSY_MS.setting(
+            db::BUSY_TIMEOUT_ENV,
+            std::env::var(db::BUSY_TIMEOUT_ENV)
+                .ok()
+                .and_then(|v| v.trim().parse().ok()),
+        ),
+    };
+    Ok(ms.map_or(db::DEFAULT_BUSY_TIMEOUT_MS, |v| v as u64))
+}
+
+/// Report that the busy timeout ran out, as `error: database is busy, ...`
+/// or, under --json, `{"error": {"code": "db", "kind": "busy", ...}}`.
+fn report_busy(json: bool) {
+    if json {
+        output::err_json(&json!({
+            "code": "db",
+            "entity": null,
+            "kind": "busy",
+            "message": db::busy_message(),
+            "retried_ms": db::busy_timeout_ms(),
+        }));
+    } else {
+        // Straight to stderr: `output::err` holds back what the busy report
+        // already covers.
+        eprintln!("error: {}", db::busy_message());
+    }
+}
+
 // ── Top-level CLI ─────────────────────────────────────────────────────────────
 
 #[derive(Parser)]
@@ -36,6 +71,7 @@ fn db_path(override_path: Option<&String>) -> String {
         work progresses).\n\n\
         Every item moves through a lifecycle:\n\n  \
         Draft → Planning → Building → Complete → Amending\n\n\
+        with Blocked beside Building for work held up by something outside it.\n\n\
         Research entries form a cross-cutting knowledge store — notes, investigations, \
         discoveries, and reference material that can be linked to any project, module, \
         feature, or task so context is never lost.\n\n\
@@ -50,6 +86,26 @@ struct Cli {
     #[arg(long, global = true)]
     json: bool,
 
+    /// Output format: plain, json (as --json), ndjson (one compact JSON object per line, nothing for an empty list), or csv/tsv (a header row, then one row per list entry); give it before the command
+    #[arg(long, value_name = "FORMAT", value_parser = output::Format::parse, conflicts_with = "json")]
+    format: Option<output::Format>,
+
+    /// Skip the once-a-day check that the installed skill file is present and current
+    #[arg(long, global = true)]
+    no_skill_check: bool,
+
+    /// Fail to start unless foreign key enforcement is confirmed on (always checked in debug builds)
+    #[arg(long, global = true)]
+    paranoid: bool,
+
+    /// Milliseconds to wait for another process's write lock before giving up with exit 5 (default 5000, or LOPEN_MEMORY_BUSY_TIMEOUT)
+    #[arg(long, global = true, value_name = "MS", allow_negative_numbers = true)]
+    busy_timeout_ms: Option<i64>,
+
+    /// Append one JSON line per change this command commits (time, actor, command, entity, summary) to this file; LOPEN_MEMORY_TRACE_FD names a file descriptor instead
+    #[arg(long, global = true, value_name = "PATH")]
+    trace_file: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -81,11 +137,247 @@ enum Commands {
         #[command(subcommand)]
         action: ResearchAction,
     },
+    /// Move a working setup to another machine: `setup export` packs the config file, the skill file, and optionally the whole database into one .tar.gz; `setup apply` installs it there
+    Setup {
+        #[command(subcommand)]
+        action: SetupAction,
+    },
     /// Install or manage the SKILL.md agent skill file that helps LLM agents discover and use lopen-memory
     Skill {
         #[command(subcommand)]
         action: SkillAction,
     },
+    /// Export the store. `--format md` writes a directory of readable Markdown: one file per project (hierarchy as headings, tasks as checklists), one per research record, cross-linked, plus an index; output is deterministic so successive exports diff cleanly. `--format json` writes one nested document with every id, name, description, details, state, timestamp, and research link, for moving a store between machines
+    Export {
+        /// Output format: md (Markdown) or json
+        #[arg(long, default_value = "md")]
+        format: String,
+        /// Directory to write Markdown into; created if missing
+        #[arg(long)]
+        out: Option<String>,
+        /// File to write the JSON document to; printed to stdout when omitted
+        #[arg(long, conflicts_with = "out")]
+        file: Option<String>,
+        /// JSON only: export just this project (name or numeric ID) and the research linked into it
+        #[arg(long)]
+        project: Option<String>,
+    },
+    /// Load a document written by `export --format json` into this database. Ids are remapped to new ones and links follow them. Runs in one transaction, so any failure leaves the database untouched. A project or research record whose name already exists is handled by --on-conflict: skip it (a skipped project's subtree is not imported; links to a skipped research record go to the existing one), rename it to <name>-2, or fail the whole import
+    Import {
+        /// JSON document to load
+        #[arg(long)]
+        file: String,
+        /// What to do when a project or research name already exists: skip, rename, or fail
+        #[arg(long, default_value = "fail")]
+        on_conflict: String,
+    },
+    /// Where work stands at a glance, per project: modules, features, and tasks counted by state, everything in Building or Amending, and the most recently worked-on items. Covers every project not marked completed unless --project is given
+    Status {
+        /// Project name or numeric ID
+        #[arg(long)]
+        project: Option<String>,
+    },
+    /// Suggest the next task to work on: anything in Building or Amending, else the oldest Planning task whose feature is in Planning or Building, else the oldest Draft task, skipping Complete modules and features. Prints each with its breadcrumb, description, details, and linked research
+    Next {
+        /// Project name or numeric ID (default: the project set with `lopen-memory use`)
+        #[arg(long)]
+        project: Option<String>,
+        /// Only suggest tasks in this module
+        #[arg(long)]
+        module: Option<String>,
+        /// Only suggest tasks in this feature
+        #[arg(long)]
+        feature: Option<String>,
+        /// How many candidates to list, best first
+        #[arg(long, default_value_t = 1, allow_negative_numbers = true)]
+        count: i64,
+        /// Leave out tasks still waiting on a dependency that is not Complete
+        #[arg(long)]
+        ready: bool,
+        /// Words to bias toward: within each tier, tasks whose name, description, or details contain them come first, and each says what it matched
+        #[arg(long, value_name = "TERMS")]
+        about: Option<String>,
+        /// With --about, leave out tasks matching none of the terms instead of just ranking them last
+        #[arg(long, requires = "about")]
+        require_match: bool,
+    },
+    /// Log progress on a task in one step: append a timestamped line to its details and mark it, its feature, and its module as worked on now. Without --task, uses the most recently worked-on Building task of the `use` context feature
+    Progress {
+        /// Task name or numeric ID
+        #[arg(long)]
+        task: Option<String>,
+        /// Disambiguate by feature name or ID if the task name is not unique
+        #[arg(long)]
+        feature: Option<String>,
+        /// What was done, one line
+        text: String,
+        /// Move a Draft or Planning task to Building as well (default: the touch_state config key)
+        #[arg(long)]
+        touch_state: bool,
+        /// With --touch-state, move its module and feature forward too if they are in an earlier stage (see the parent_policy config key)
+        #[arg(long)]
+        lift_parents: bool,
+    },
+    /// Print a project's modules, features, and tasks as one indented tree with their states, to re-orient without four list commands
+    Tree {
+        /// Project name or numeric ID (default: the project set with `lopen-memory use`)
+        #[arg(long)]
+        project: Option<String>,
+        /// Only show branches containing something in this state: Draft, Planning, Building, Complete, Amending, or Blocked
+        #[arg(long)]
+        state: Option<String>,
+        /// Levels to show: 1 modules, 2 features, 3 tasks
+        #[arg(long, default_value_t = 3)]
+        depth: usize,
+    },
+    /// Show a unified diff of one text field of two entities, e.g. the details of two features being consolidated, or a task's details against its feature's. Name the two with repeated or mixed --project/--module/--feature/--task/--research flags; with mixed types the one higher in the hierarchy is the old side (research last). Names are looked up across the whole store, so use short refs (F12) when they are ambiguous. Identical texts print nothing
+    Diff {
+        /// Project name or numeric ID; repeat to compare two projects
+        #[arg(long)]
+        project: Vec<String>,
+        /// Module name or numeric ID; repeat to compare two modules
+        #[arg(long)]
+        module: Vec<String>,
+        /// Feature name or numeric ID; repeat to compare two features
+        #[arg(long)]
+        feature: Vec<String>,
+        /// Task name or numeric ID; repeat to compare two tasks
+        #[arg(long)]
+        task: Vec<String>,
+        /// Research record name or numeric ID; repeat to compare two records
+        #[arg(long)]
+        research: Vec<String>,
+        /// Field to compare: name, description, details (modules, features, tasks), path (projects, modules), content or source (research)
+        #[arg(long, default_value = "details")]
+        field: String,
+        /// Unchanged lines shown around each change
+        #[arg(long, default_value_t = 3, allow_negative_numbers = true)]
+        context: i64,
+    },
+    /// Find anything by name or text: fuzzy-match a query against the names of every project, module, feature, task, and research record (subsequence matching like fzf), and look for it, ignoring case, in their descriptions and details (research content). Lists name matches best first, then text-only matches, with type, id, state, breadcrumb, and which columns matched. Fast enough to drive an external picker on every keystroke
+    Find {
+        /// Characters to match, in order, anywhere in the name; the whole query is also looked for in descriptions and details (case-insensitive)
+        query: String,
+        /// Only this type: project, module, feature, task, or research
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+        /// Only the project and what is in it, including research linked anywhere under it (name or numeric ID)
+        #[arg(long)]
+        project: Option<String>,
+        /// Only entities in this state: Draft, Planning, Building, Complete, Amending, or Blocked; active or completed for projects
+        #[arg(long)]
+        state: Option<String>,
+        /// Maximum number of matches to show (default 10)
+        #[arg(long, allow_negative_numbers = true)]
+        limit: Option<i64>,
+        /// Skip this many matches first, for paging with --limit
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        offset: i64,
+        /// Also match names entities had before they were renamed
+        #[arg(long)]
+        include_former_names: bool,
+    },
+    /// List the most recently created entities, newest first, with state and breadcrumb — to confirm what a batch of adds just created. Research is ordered by created_at; other types by id. Without --type, the newest of every type in hierarchy order
+    Last {
+        /// Only this type: project, module, feature, task, or research
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+        /// How many of each type to show
+        #[arg(long, default_value_t = 5, allow_negative_numbers = true)]
+        n: i64,
+    },
+    /// Check workflow consistency across the hierarchy: Complete features with open tasks, completed projects with open modules, Building modules/features with nothing under them, tasks stuck in Building, and empty descriptions. Exits 6 when any error-severity rule fires. Suppress rules with `config set validate_suppress '["rule-id"]'`
+    Validate {
+        /// Only check this project (name or numeric ID)
+        #[arg(long)]
+        project: Option<String>,
+        /// Days a task may sit in Building before it is reported (default: config validate_building_days, else 14)
+        #[arg(long, allow_negative_numbers = true)]
+        building_days: Option<i64>,
+    },
+    /// Remove abandoned drafts: Draft modules, features, and tasks with no description or details, nothing under them, no research links, and no work for --older-than days. Locked entities and anything under a locked parent are left alone. Lists them first and removes them in one transaction on confirmation; without a terminal, only lists unless --yes is given
+    Cleanup {
+        /// Only look in this project (name or numeric ID)
+        #[arg(long)]
+        project: Option<String>,
+        /// Days since an entity was last worked on before it counts as abandoned
+        #[arg(long, default_value_t = 30, allow_negative_numbers = true)]
+        older_than: i64,
+        /// Also remove drafts that have a description or details
+        #[arg(long)]
+        include_described: bool,
+        /// Also remove drafts linked to research (the links are removed, never the research)
+        #[arg(long)]
+        include_linked: bool,
+        /// Only list what would be removed
+        #[arg(long, conflicts_with = "yes")]
+        dry_run: bool,
+        /// Remove without asking
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Set the default project, module, and feature for the current directory. Task and feature commands use them when --feature/--module are omitted, and say so on stderr. Explicit flags always win
+    Use {
+        /// Project name or numeric ID
+        #[arg(long, required_unless_present_any = ["clear", "show"])]
+        project: Option<String>,
+        /// Default module within the project
+        #[arg(long, requires = "project")]
+        module: Option<String>,
+        /// Default feature within the module
+        #[arg(long, requires = "module")]
+        feature: Option<String>,
+        /// Remove the context for the current directory
+        #[arg(long, conflicts_with_all = ["project", "show"])]
+        clear: bool,
+        /// Print the context for the current directory
+        #[arg(long, conflicts_with = "project")]
+        show: bool,
+    },
+    /// Read and change persistent settings stored in the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Inspect and diagnose the database itself: size, row counts, and query timings
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+    /// Advisory named locks for agents sharing this database: acquire one before a multi-step change, release it after. Other lopen-memory commands never check them. Expired locks are reaped on acquire
+    Lock {
+        #[command(subcommand)]
+        action: LockAction,
+    },
+    /// Named copies of one project's subtree and its research links, taken before a risky change and restored if it goes wrong. Other projects keep changing freely in between
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+    /// What `remove --soft` took out, kept until restored or purged. Nothing in the trash shows up in lists, searches, or name lookups
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+    /// Read-only bundles of one project for a teammate: `share create` packs the project's subtree and the research linked into it into one compressed file; `share open` runs read commands against it as if it were a database
+    Share {
+        #[command(subcommand)]
+        action: ShareAction,
+    },
+    /// How the memory store has been used over time, aggregated from the timestamps it keeps
+    Analytics {
+        #[command(subcommand)]
+        action: AnalyticsAction,
+    },
+    /// What changed in each release, from a changelog compiled into the binary: commands and flags added or removed, schema migrations, and changed behavior. With --check, exits 7 when a breaking change shipped after that version, so a harness pinned to it can refuse to run
+    Changes {
+        /// Only releases after this version, e.g. 1.0.5-alpha
+        #[arg(long, value_name = "VERSION")]
+        since: Option<String>,
+        /// List breaking changes after this version and exit 7 if there are any
+        #[arg(long, value_name = "VERSION", conflicts_with = "since")]
+        check: Option<String>,
+    },
 }
 
 // ── Project actions ───────────────────────────────────────────────────────────
@@ -101,6 +393,24 @@ enum ProjectAction {
         /// Stable one-sentence description of the project's purpose
         description: Option<String>,
     },
+    /// Register an existing repository as a project and optionally propose one Draft module per top-level directory or workspace member, with the directory noted in the module details. Everything is created in one transaction
+    Bootstrap {
+        /// Path to the root of the repository
+        #[arg(long)]
+        path: String,
+        /// Slug for the project (default: the repository directory name)
+        #[arg(long)]
+        name: Option<String>,
+        /// Propose modules from: top-dirs, workspace (Cargo.toml or package.json members), or auto (workspace when detected, otherwise top-dirs)
+        #[arg(long)]
+        modules_from: Option<String>,
+        /// Reuse the project if it already exists and only add the modules it is missing
+        #[arg(long)]
+        if_not_exists: bool,
+        /// Print the full plan, with any validation errors, without writing anything. Exits non-zero when the real run would fail
+        #[arg(long)]
+        dry_run: bool,
+    },
     /// List all registered projects, optionally filtered to only completed or only incomplete ones
     List {
         /// Show only completed projects
@@ -109,12 +419,36 @@ enum ProjectAction {
         /// Show only incomplete (active) projects
         #[arg(long, conflicts_with = "completed")]
         incomplete: bool,
+        /// Judge --completed/--incomplete by module states rather than the completed flag: complete when every module is Complete, or, with no modules, when the flag is set
+        #[arg(long)]
+        effective: bool,
+        /// Show only archived projects, which are otherwise left out
+        #[arg(long, conflicts_with = "all")]
+        archived: bool,
+        /// Show archived projects as well
+        #[arg(long)]
+        all: bool,
+        /// Columns to print, in this order, named by their JSON keys, e.g. id,name,state,description; applies to plain, --json, and --format output
+        #[arg(long, value_name = "FIELD,...")]
+        fields: Option<String>,
+        /// Order by this field, e.g. name or updated_at:desc; ids break ties
+        #[arg(long, value_name = "FIELD[:desc]")]
+        sort: Option<String>,
+        /// Show at most this many projects; with --json the output becomes {"items", "returned", "total"}
+        #[arg(long, allow_negative_numbers = true)]
+        limit: Option<i64>,
+        /// Skip this many projects first, for paging with --limit
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        offset: i64,
     },
     /// Display full details for a project including its description, path, completion status, and all child modules with their current lifecycle states
     Show {
         /// Project name or numeric ID
         #[arg(long)]
         project: String,
+        /// Also print when the project was marked complete or reopened (`history` in JSON)
+        #[arg(long)]
+        with_history: bool,
     },
     /// Change a project's slug name. Does not affect child modules or linked research
     Rename {
@@ -123,14 +457,24 @@ enum ProjectAction {
         project: String,
         /// The new slug name for the project
         new_name: String,
+        /// Also rewrite whole-word references to the old name in descriptions and details
+        #[arg(long)]
+        update_references: bool,
+        /// Preview the references that would be rewritten without changing anything
+        #[arg(long, requires = "update_references")]
+        dry_run: bool,
     },
     /// Replace the project's description — a stable one-sentence statement of the project's purpose. Update only when the goal itself changes
     SetDescription {
         /// Project name or numeric ID
         #[arg(long)]
         project: String,
-        /// Stable one-sentence statement of the project's purpose — should still make sense months later
-        description: String,
+        /// Stable one-sentence statement of the project's purpose — should still make sense months later; `-` reads it from stdin
+        #[arg(required_unless_present = "file")]
+        description: Option<String>,
+        /// Read the description from this file instead, or from stdin with `-`; kept exactly as read, trailing newlines included
+        #[arg(long, value_name = "PATH", conflicts_with = "description")]
+        file: Option<String>,
     },
     /// Update the absolute filesystem path associated with this project
     SetPath {
@@ -140,17 +484,38 @@ enum ProjectAction {
         /// New absolute filesystem path to associate with this project
         path: String,
     },
-    /// Mark a project as complete. Use when all work in the project is finished
+    /// Mark a project as complete. Use when all work in the project is finished; refused while any module is not Complete unless --force
     Complete {
         /// Project name or numeric ID
         #[arg(long)]
         project: String,
+        /// Complete even though its modules are not all Complete; they are listed as a warning
+        #[arg(long)]
+        force: bool,
+        /// Why the project is done; kept in its state history
+        #[arg(long)]
+        reason: Option<String>,
     },
     /// Reopen a previously completed project for further work
     Reopen {
         /// Project name or numeric ID
         #[arg(long)]
         project: String,
+        /// Why the project is being reopened; kept in its state history
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Archive a finished project: it keeps its modules, features, tasks, and research links, but drops out of `project list`, and nothing in it can be changed until it is unarchived
+    Archive {
+        /// Project name or numeric ID
+        #[arg(long)]
+        project: String,
+    },
+    /// Bring an archived project back into `project list` and allow changes in it again
+    Unarchive {
+        /// Project name or numeric ID
+        #[arg(long)]
+        project: String,
     },
     /// Delete a project. Use --cascade to also delete all child modules, features, and tasks. Without --cascade, removal fails if the project has children. Linked research records are never deleted — only the association is removed
     Remove {
@@ -160,6 +525,54 @@ enum ProjectAction {
         /// Also delete all child modules, features, and tasks. Without this flag, removal fails if children exist
         #[arg(long)]
         cascade: bool,
+        /// Move it to the trash instead of deleting it, so `trash restore` can bring it back (the default when the soft_delete setting is true)
+        #[arg(long, conflicts_with = "hard")]
+        soft: bool,
+        /// Delete it for good even when the soft_delete setting is true
+        #[arg(long)]
+        hard: bool,
+    },
+    /// Link research to this project. Mirrors `research link --project`, resolving the project first
+    LinkResearch {
+        /// Project name or numeric ID
+        #[arg(long)]
+        project: String,
+        /// Research name or numeric ID
+        #[arg(long)]
+        research: String,
+        /// Link even when the project is finished, without the warning
+        #[arg(long)]
+        force: bool,
+    },
+    /// Remove a research link from this project. Mirrors `research unlink --project`
+    UnlinkResearch {
+        /// Project name or numeric ID
+        #[arg(long)]
+        project: String,
+        /// Research name or numeric ID
+        #[arg(long)]
+        research: String,
+    },
+    /// List research linked directly to this project (not research linked only to its ancestors)
+    ListResearch {
+        /// Project name or numeric ID
+        #[arg(long)]
+        project: String,
+        /// Include archived research, which is left out by default
+        #[arg(long)]
+        include_archived: bool,
+    },
+    /// Show every rename of this project, oldest first, so references to former names can be traced
+    NameHistory {
+        /// Project name or numeric ID
+        #[arg(long)]
+        project: String,
+    },
+    /// Show when this project was marked complete or reopened, oldest first
+    History {
+        /// Project name or numeric ID
+        #[arg(long)]
+        project: String,
     },
 }
 
@@ -176,15 +589,42 @@ enum ModuleAction {
         name: String,
         /// Stable one-sentence description of what this area of the codebase covers
         description: Option<String>,
+        /// Subdirectory of the project path this module's work lives in (e.g. services/auth). Warns if it does not exist
+        #[arg(long)]
+        path: Option<String>,
+        /// Move the new module straight to Planning (Draft → Planning)
+        #[arg(long, conflicts_with_all = ["start", "state"])]
+        plan: bool,
+        /// Move the new module straight to Building (Draft → Planning → Building), for work that begins immediately
+        #[arg(long, conflicts_with_all = ["plan", "state"])]
+        start: bool,
+        /// Starting lifecycle state: Draft, Planning, or Building (a new module cannot start Complete or Amending)
+        #[arg(long)]
+        state: Option<String>,
+        /// Implementation notes to store with the new module, as set-details would
+        #[arg(long)]
+        details: Option<String>,
     },
-    /// List all modules in a project, optionally filtered by lifecycle state (Draft, Planning, Building, Complete, Amending)
+    /// List all modules in a project, optionally filtered by lifecycle state (Draft, Planning, Building, Complete, Amending, Blocked)
     List {
         /// Parent project name or numeric ID
         #[arg(long)]
         project: String,
-        /// Filter by lifecycle state: Draft, Planning, Building, Complete, or Amending
+        /// Filter by lifecycle state: Draft, Planning, Building, Complete, Amending, or Blocked
         #[arg(long)]
         state: Option<String>,
+        /// Columns to print, in this order, named by their JSON keys, e.g. id,name,state,description; applies to plain, --json, and --format output
+        #[arg(long, value_name = "FIELD,...")]
+        fields: Option<String>,
+        /// Order by this field, e.g. name or state:desc; states sort in lifecycle order, ids break ties
+        #[arg(long, value_name = "FIELD[:desc]")]
+        sort: Option<String>,
+        /// Show at most this many modules; with --json the output becomes {"items", "returned", "total"}
+        #[arg(long, allow_negative_numbers = true)]
+        limit: Option<i64>,
+        /// Skip this many modules first, for paging with --limit
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        offset: i64,
     },
     /// Display full details for a module including its description, details, lifecycle state, and all child features with their states
     Show {
@@ -194,6 +634,9 @@ enum ModuleAction {
         /// Disambiguate by project name or ID if the module name is not unique
         #[arg(long)]
         project: Option<String>,
+        /// Also print every state transition, oldest first (`history` in JSON)
+        #[arg(long)]
+        with_history: bool,
     },
     /// Change a module's slug name. Does not affect child features or linked research
     Rename {
@@ -205,6 +648,12 @@ enum ModuleAction {
         project: Option<String>,
         /// The new slug name for the module
         new_name: String,
+        /// Also rewrite whole-word references to the old name in descriptions and details
+        #[arg(long)]
+        update_references: bool,
+        /// Preview the references that would be rewritten without changing anything
+        #[arg(long, requires = "update_references")]
+        dry_run: bool,
     },
     /// Replace the module's description — a stable one-sentence statement of what this area of the codebase covers. Update only when the scope itself changes
     SetDescription {
@@ -214,10 +663,14 @@ enum ModuleAction {
         /// Disambiguate by project name or ID if the module name is not unique
         #[arg(long)]
         project: Option<String>,
-        /// Stable one-sentence statement of what this module covers — should still make sense months later
-        description: String,
+        /// Stable one-sentence statement of what this module covers — should still make sense months later; `-` reads it from stdin
+        #[arg(required_unless_present = "file")]
+        description: Option<String>,
+        /// Read the description from this file instead, or from stdin with `-`; kept exactly as read, trailing newlines included
+        #[arg(long, value_name = "PATH", conflicts_with = "description")]
+        file: Option<String>,
     },
-    /// Replace the module's working notes entirely. Use for implementation approach, design decisions, constraints, and evolving context. Fully overwritten on each call — there is no append mode
+    /// Replace the module's working notes, or add to them with --append or --prepend. Use for implementation approach, design decisions, constraints, and evolving context. Without either flag the details are fully overwritten
     SetDetails {
         /// Module name or numeric ID
         #[arg(long)]
@@ -225,10 +678,31 @@ enum ModuleAction {
         /// Disambiguate by project name or ID if the module name is not unique
         #[arg(long)]
         project: Option<String>,
-        /// Implementation notes, design decisions, and evolving context. Fully replaces existing details
-        details: String,
+        /// Implementation notes, design decisions, and evolving context. Fully replaces existing details; `-` reads it from stdin
+        #[arg(required_unless_present = "file")]
+        details: Option<String>,
+        /// Read the details from this file instead, or from stdin with `-`; kept exactly as read, trailing newlines included
+        #[arg(long, value_name = "PATH", conflicts_with = "details")]
+        file: Option<String>,
+        /// Add the text after the existing details, separated by a blank line, instead of replacing them
+        #[arg(long, conflicts_with = "prepend")]
+        append: bool,
+        /// Add the text before the existing details, separated by a blank line, instead of replacing them
+        #[arg(long)]
+        prepend: bool,
+    },
+    /// Map a module to the subdirectory of the project path its work lives in, e.g. services/auth in a monorepo. Commands run from inside that directory pick the module up when no --module or `use` module is given. Warns if the directory does not exist under the project root; an empty path clears the mapping
+    SetPath {
+        /// Module name or numeric ID
+        #[arg(long)]
+        module: String,
+        /// Disambiguate by project name or ID if the module name is not unique
+        #[arg(long)]
+        project: Option<String>,
+        /// Path relative to the project path
+        path: String,
     },
-    /// Move a module to a new lifecycle state: Draft, Planning, Building, Complete, or Amending. Transition children first — complete all features before completing the module
+    /// Move a module to a new lifecycle state: Draft, Planning, Building, Complete, Amending, or Blocked. Transition children first — completing a module is refused while any of its features is not Complete, unless --force
     Transition {
         /// Module name or numeric ID
         #[arg(long)]
@@ -236,8 +710,32 @@ enum ModuleAction {
         /// Disambiguate by project name or ID if the module name is not unique
         #[arg(long)]
         project: Option<String>,
-        /// Target lifecycle state: Draft, Planning, Building, Complete, or Amending
+        /// Target lifecycle state: Draft, Planning, Building, Complete, Amending, or Blocked
         state: String,
+        /// Why the move is happening, e.g. why finished work is being reopened. Kept as last_transition_reason and appended to the details with a timestamp. Moving to Amending without one warns
+        #[arg(long)]
+        reason: Option<String>,
+        /// Complete even though its features are not all Complete; they are listed as a warning
+        #[arg(long)]
+        force: bool,
+    },
+    /// Lock a module against structural changes: rename, set-description, adding or removing features, and removal fail until it is unlocked. Reads, set-details, and transitions to Amending stay allowed
+    Lock {
+        /// Module name or numeric ID
+        #[arg(long)]
+        module: String,
+        /// Disambiguate by project name or ID if the module name is not unique
+        #[arg(long)]
+        project: Option<String>,
+    },
+    /// Remove a module's lock so structural changes are allowed again
+    Unlock {
+        /// Module name or numeric ID
+        #[arg(long)]
+        module: String,
+        /// Disambiguate by project name or ID if the module name is not unique
+        #[arg(long)]
+        project: Option<String>,
     },
     /// Delete a module. Use --cascade to also delete all child features and tasks. Without --cascade, removal fails if children exist. Linked research is never deleted
     Remove {
@@ -250,6 +748,72 @@ enum ModuleAction {
         /// Also delete all child features and tasks. Without this flag, removal fails if children exist
         #[arg(long)]
         cascade: bool,
+        /// Before deleting, link research linked to the module, its features, or their tasks to the project, so it stays in context. Existing links are kept
+        #[arg(long)]
+        relink_research_to_parent: bool,
+        /// Move it to the trash instead of deleting it, so `trash restore` can bring it back (the default when the soft_delete setting is true)
+        #[arg(long, conflicts_with = "hard")]
+        soft: bool,
+        /// Delete it for good even when the soft_delete setting is true
+        #[arg(long)]
+        hard: bool,
+    },
+    /// Link research to this module. Mirrors `research link --module`, resolving the module first
+    LinkResearch {
+        /// Module name or numeric ID
+        #[arg(long)]
+        module: String,
+        /// Disambiguate by project name or ID if the module name is not unique
+        #[arg(long)]
+        project: Option<String>,
+        /// Research name or numeric ID
+        #[arg(long)]
+        research: String,
+        /// Link even when the module is finished, without the warning
+        #[arg(long)]
+        force: bool,
+    },
+    /// Remove a research link from this module. Mirrors `research unlink --module`
+    UnlinkResearch {
+        /// Module name or numeric ID
+        #[arg(long)]
+        module: String,
+        /// Disambiguate by project name or ID if the module name is not unique
+        #[arg(long)]
+        project: Option<String>,
+        /// Research name or numeric ID
+        #[arg(long)]
+        research: String,
+    },
+    /// List research linked directly to this module (not research linked only to its ancestors)
+    ListResearch {
+        /// Module name or numeric ID
+        #[arg(long)]
+        module: String,
+        /// Disambiguate by project name or ID if the module name is not unique
+        #[arg(long)]
+        project: Option<String>,
+        /// Include archived research, which is left out by default
+        #[arg(long)]
+        include_archived: bool,
+    },
+    /// Show every rename of this module, oldest first, so references to former names can be traced
+    NameHistory {
+        /// Module name or numeric ID
+        #[arg(long)]
+        module: String,
+        /// Disambiguate by project name or ID if the module name is not unique
+        #[arg(long)]
+        project: Option<String>,
+    },
+    /// Show every state transition of this module, oldest first, with how long each state before it lasted
+    History {
+        /// Module name or numeric ID
+        #[arg(long)]
+        module: String,
+        /// Disambiguate by project name or ID if the module name is not unique
+        #[arg(long)]
+        project: Option<String>,
     },
 }
 
@@ -259,9 +823,9 @@ enum ModuleAction {
 enum FeatureAction {
     /// Create a new feature within a module. Features are single discrete deliverables — something that can be described as "the ability to X" with a clear done state
     Add {
-        /// Parent module name or numeric ID
+        /// Parent module name or numeric ID (default: the module set with `lopen-memory use`)
         #[arg(long)]
-        module: String,
+        module: Option<String>,
         /// Disambiguate by project name or ID if the module name is not unique
         #[arg(long)]
         project: Option<String>,
@@ -269,18 +833,69 @@ enum FeatureAction {
         name: String,
         /// Stable one-sentence goal — describe it as "the ability to X"
         description: Option<String>,
+        /// Move the new feature straight to Planning (Draft → Planning)
+        #[arg(long, conflicts_with_all = ["start", "state"])]
+        plan: bool,
+        /// Move the new feature straight to Building (Draft → Planning → Building), for work that begins immediately
+        #[arg(long, conflicts_with_all = ["plan", "state"])]
+        start: bool,
+        /// Starting lifecycle state: Draft, Planning, or Building (a new feature cannot start Complete or Amending)
+        #[arg(long)]
+        state: Option<String>,
+        /// Implementation notes to store with the new feature, as set-details would
+        #[arg(long)]
+        details: Option<String>,
+        /// How much the feature matters: low, medium (default), high, or critical
+        #[arg(long)]
+        priority: Option<String>,
+    },
+    /// Copy a feature and all its tasks into a module under a new name, as a template: descriptions, details, and priorities are kept and everything starts in Draft
+    Clone {
+        /// Feature to copy, by name or numeric ID
+        #[arg(long)]
+        feature: String,
+        /// Disambiguate the source by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+        /// Module to put the copy in, by name or numeric ID
+        #[arg(long)]
+        to_module: String,
+        /// Disambiguate the target module by project name or ID
+        #[arg(long)]
+        to_project: Option<String>,
+        /// Name of the new feature; must be free in the target module
+        #[arg(long)]
+        name: String,
+        /// Also link the copy and its tasks to the research linked to the source feature and its tasks
+        #[arg(long)]
+        with_research_links: bool,
     },
-    /// List all features in a module, optionally filtered by lifecycle state (Draft, Planning, Building, Complete, Amending)
+    /// List all features in a module, optionally filtered by lifecycle state (Draft, Planning, Building, Complete, Amending, Blocked)
     List {
-        /// Parent module name or numeric ID
+        /// Parent module name or numeric ID (default: the module set with `lopen-memory use`)
         #[arg(long)]
-        module: String,
+        module: Option<String>,
         /// Disambiguate by project name or ID if the module name is not unique
         #[arg(long)]
         project: Option<String>,
-        /// Filter by lifecycle state: Draft, Planning, Building, Complete, or Amending
+        /// Filter by lifecycle state: Draft, Planning, Building, Complete, Amending, or Blocked
         #[arg(long)]
         state: Option<String>,
+        /// Filter by priority: low, medium, high, or critical
+        #[arg(long)]
+        priority: Option<String>,
+        /// Columns to print, in this order, named by their JSON keys, e.g. id,name,state,description; applies to plain, --json, and --format output
+        #[arg(long, value_name = "FIELD,...")]
+        fields: Option<String>,
+        /// Order by this field instead of id, e.g. priority or state:desc; priorities sort most urgent first and states in lifecycle order, ids break ties
+        #[arg(long, value_name = "FIELD[:desc]")]
+        sort: Option<String>,
+        /// Show at most this many features; with --json the output becomes {"items", "returned", "total"}
+        #[arg(long, allow_negative_numbers = true)]
+        limit: Option<i64>,
+        /// Skip this many features first, for paging with --limit
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        offset: i64,
     },
     /// Display full details for a feature including its description, details, lifecycle state, and all child tasks with their states
     Show {
@@ -290,9 +905,15 @@ enum FeatureAction {
         /// Disambiguate by module name or ID if the feature name is not unique
         #[arg(long)]
         module: Option<String>,
-        /// Disambiguate by project name or ID (used with --module)
+        /// Disambiguate by project name or ID: narrows --module, or the feature itself when there is no --module
         #[arg(long)]
         project: Option<String>,
+        /// Also print the description and details of the module and project it belongs to
+        #[arg(long)]
+        with_ancestors: bool,
+        /// Also print every state transition, oldest first (`history` in JSON)
+        #[arg(long)]
+        with_history: bool,
     },
     /// Change a feature's slug name. Does not affect child tasks or linked research
     Rename {
@@ -304,6 +925,12 @@ enum FeatureAction {
         module: Option<String>,
         /// The new slug name for the feature
         new_name: String,
+        /// Also rewrite whole-word references to the old name in descriptions and details
+        #[arg(long)]
+        update_references: bool,
+        /// Preview the references that would be rewritten without changing anything
+        #[arg(long, requires = "update_references")]
+        dry_run: bool,
     },
     /// Replace the feature's description — a stable one-sentence goal like "the ability to X". Update only when the goal itself changes
     SetDescription {
@@ -313,10 +940,14 @@ enum FeatureAction {
         /// Disambiguate by module name or ID if the feature name is not unique
         #[arg(long)]
         module: Option<String>,
-        /// Stable one-sentence goal statement — should still make sense months later without context
-        description: String,
+        /// Stable one-sentence goal statement — should still make sense months later without context; `-` reads it from stdin
+        #[arg(required_unless_present = "file")]
+        description: Option<String>,
+        /// Read the description from this file instead, or from stdin with `-`; kept exactly as read, trailing newlines included
+        #[arg(long, value_name = "PATH", conflicts_with = "description")]
+        file: Option<String>,
     },
-    /// Replace the feature's working notes entirely. Use for implementation approach, constraints, decisions, and links to relevant code. Fully overwritten on each call
+    /// Replace the feature's working notes, or add to them with --append or --prepend. Use for implementation approach, constraints, decisions, and links to relevant code. Without either flag the details are fully overwritten
     SetDetails {
         /// Feature name or numeric ID
         #[arg(long)]
@@ -324,62 +955,259 @@ enum FeatureAction {
         /// Disambiguate by module name or ID if the feature name is not unique
         #[arg(long)]
         module: Option<String>,
-        /// Implementation notes, design decisions, and evolving context. Fully replaces existing details
-        details: String,
+        /// Implementation notes, design decisions, and evolving context. Fully replaces existing details; `-` reads it from stdin
+        #[arg(required_unless_present = "file")]
+        details: Option<String>,
+        /// Read the details from this file instead, or from stdin with `-`; kept exactly as read, trailing newlines included
+        #[arg(long, value_name = "PATH", conflicts_with = "details")]
+        file: Option<String>,
+        /// Add the text after the existing details, separated by a blank line, instead of replacing them
+        #[arg(long, conflicts_with = "prepend")]
+        append: bool,
+        /// Add the text before the existing details, separated by a blank line, instead of replacing them
+        #[arg(long)]
+        prepend: bool,
     },
-    /// Move a feature to a new lifecycle state: Draft, Planning, Building, Complete, or Amending. Complete all child tasks before completing the feature
-    Transition {
+    /// Set how much the feature matters: low, medium, high, or critical. `feature list --sort priority` lists the most urgent first
+    SetPriority {
         /// Feature name or numeric ID
         #[arg(long)]
         feature: String,
         /// Disambiguate by module name or ID if the feature name is not unique
         #[arg(long)]
         module: Option<String>,
-        /// Target lifecycle state: Draft, Planning, Building, Complete, or Amending
-        state: String,
+        /// low, medium, high, or critical
+        priority: String,
     },
-    /// Delete a feature. Use --cascade to also delete all child tasks. Without --cascade, removal fails if tasks exist. Linked research is never deleted
-    Remove {
+    /// Turn TODO and FIXME lines in a feature's details into tasks. Lists what it would create (name slugged from the line, description the whole line); with --apply, creates them as Draft tasks at the end of the work order and marks each line with its task's ref, so they are not extracted twice. Markdown list markers and checkboxes are understood; lines in code fences are skipped
+    ExtractTodos {
         /// Feature name or numeric ID
         #[arg(long)]
         feature: String,
         /// Disambiguate by module name or ID if the feature name is not unique
         #[arg(long)]
         module: Option<String>,
-        /// Also delete all child tasks. Without this flag, removal fails if tasks exist
+        /// Create the tasks and rewrite the details, instead of only listing them
         #[arg(long)]
-        cascade: bool,
+        apply: bool,
     },
-}
-
-// ── Task actions ──────────────────────────────────────────────────────────────
-
-#[derive(Subcommand)]
-enum TaskAction {
-    /// Create a new task within a feature. Tasks are the smallest unit of tracked work — single concrete implementation steps that can be completed one at a time
-    Add {
-        /// Parent feature name or numeric ID
+    /// Move a feature to a new lifecycle state: Draft, Planning, Building, Complete, Amending, or Blocked. Completing a feature is refused while any of its tasks is not Complete, unless --force
+    Transition {
+        /// Feature name or numeric ID
         #[arg(long)]
         feature: String,
         /// Disambiguate by module name or ID if the feature name is not unique
         #[arg(long)]
         module: Option<String>,
-        /// Unique slug identifying this task within its parent feature
+        /// Target lifecycle state: Draft, Planning, Building, Complete, Amending, or Blocked
+        state: String,
+        /// When moving into Planning or Building, move its module forward too if they are in an earlier stage, in the same transaction. Without it they are only warned about (see the parent_policy config key)
+        #[arg(long)]
+        lift_parents: bool,
+        /// Why the move is happening, e.g. why finished work is being reopened. Kept as last_transition_reason and appended to the details with a timestamp. Moving to Amending without one warns
+        #[arg(long)]
+        reason: Option<String>,
+        /// Complete even though its tasks are not all Complete; they are listed as a warning
+        #[arg(long)]
+        force: bool,
+    },
+    /// Move every feature of a module to a new lifecycle state in one transaction, reporting each as transitioned, no-op (already there), or invalid. Invalid moves (a refused transition, a lock, tasks left when completing) are listed and skipped unless --strict
+    TransitionAll {
+        /// Module name or numeric ID
+        #[arg(long)]
+        module: String,
+        /// Disambiguate by project name or ID if the module name is not unique
+        #[arg(long)]
+        project: Option<String>,
+        /// Target lifecycle state: Draft, Planning, Building, Complete, Amending, or Blocked
+        state: String,
+        /// When moving into Planning or Building, move the module forward too if it is in an earlier stage
+        #[arg(long)]
+        lift_parents: bool,
+        /// Why the move is happening; recorded on every feature that moves
+        #[arg(long)]
+        reason: Option<String>,
+        /// Change nothing if the move is invalid for any feature
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Lock a feature against structural changes: rename, set-description, adding or removing tasks, and removal fail until it is unlocked. Reads, set-details, and transitions to Amending stay allowed
+    Lock {
+        /// Feature name or numeric ID
+        #[arg(long)]
+        feature: String,
+        /// Disambiguate by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+    },
+    /// Remove a feature's lock so structural changes are allowed again
+    Unlock {
+        /// Feature name or numeric ID
+        #[arg(long)]
+        feature: String,
+        /// Disambiguate by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+    },
+    /// Delete a feature. Use --cascade to also delete all child tasks. Without --cascade, removal fails if tasks exist. Linked research is never deleted
+    Remove {
+        /// Feature name or numeric ID
+        #[arg(long)]
+        feature: String,
+        /// Disambiguate by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+        /// Also delete all child tasks. Without this flag, removal fails if tasks exist
+        #[arg(long)]
+        cascade: bool,
+        /// Before deleting, link research linked to the feature or its tasks to the module, so it stays in context. Existing links are kept
+        #[arg(long)]
+        relink_research_to_parent: bool,
+        /// Move it to the trash instead of deleting it, so `trash restore` can bring it back (the default when the soft_delete setting is true)
+        #[arg(long, conflicts_with = "hard")]
+        soft: bool,
+        /// Delete it for good even when the soft_delete setting is true
+        #[arg(long)]
+        hard: bool,
+    },
+    /// Link research to this feature. Mirrors `research link --feature`, resolving the feature first
+    LinkResearch {
+        /// Feature name or numeric ID
+        #[arg(long)]
+        feature: String,
+        /// Disambiguate by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+        /// Research name or numeric ID
+        #[arg(long)]
+        research: String,
+        /// Link even when the feature is finished, without the warning
+        #[arg(long)]
+        force: bool,
+    },
+    /// Remove a research link from this feature. Mirrors `research unlink --feature`
+    UnlinkResearch {
+        /// Feature name or numeric ID
+        #[arg(long)]
+        feature: String,
+        /// Disambiguate by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+        /// Research name or numeric ID
+        #[arg(long)]
+        research: String,
+    },
+    /// List research linked directly to this feature (not research linked only to its ancestors)
+    ListResearch {
+        /// Feature name or numeric ID
+        #[arg(long)]
+        feature: String,
+        /// Disambiguate by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+        /// Include archived research, which is left out by default
+        #[arg(long)]
+        include_archived: bool,
+    },
+    /// Show every rename of this feature, oldest first, so references to former names can be traced
+    NameHistory {
+        /// Feature name or numeric ID
+        #[arg(long)]
+        feature: String,
+        /// Disambiguate by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+    },
+    /// Show every state transition of this feature, oldest first, with how long each state before it lasted
+    History {
+        /// Feature name or numeric ID
+        #[arg(long)]
+        feature: String,
+        /// Disambiguate by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+    },
+}
+
+// ── Task actions ──────────────────────────────────────────────────────────────
+
+#[derive(Subcommand)]
+enum TaskAction {
+    /// Create a new task within a feature. Tasks are the smallest unit of tracked work — single concrete implementation steps that can be completed one at a time
+    Add {
+        /// Parent feature name or numeric ID (default: the feature set with `lopen-memory use`)
+        #[arg(long)]
+        feature: Option<String>,
+        /// Disambiguate by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+        /// Unique slug identifying this task within its parent feature
         name: String,
         /// Stable one-sentence description of what this implementation step achieves
         description: Option<String>,
+        /// Move the new task straight to Planning (Draft → Planning)
+        #[arg(long, conflicts_with_all = ["start", "state"])]
+        plan: bool,
+        /// Move the new task straight to Building (Draft → Planning → Building), for work that begins immediately
+        #[arg(long, conflicts_with_all = ["plan", "state"])]
+        start: bool,
+        /// Starting lifecycle state: Draft, Planning, or Building (a new task cannot start Complete or Amending)
+        #[arg(long)]
+        state: Option<String>,
+        /// Implementation notes to store with the new task, as set-details would
+        #[arg(long)]
+        details: Option<String>,
+        /// Place the new task just before this sibling task in the feature's work order (default: last)
+        #[arg(long, conflicts_with = "after")]
+        before: Option<String>,
+        /// Place the new task just after this sibling task in the feature's work order
+        #[arg(long, conflicts_with = "before")]
+        after: Option<String>,
+        /// How much the task matters: low, medium (default), high, or critical
+        #[arg(long)]
+        priority: Option<String>,
+    },
+    /// Set the work order of a feature's tasks: the named tasks come first, in the order given, and the rest follow in their current order. `task list`, `feature show`, and `tree` list tasks in this order
+    Reorder {
+        /// Parent feature name or numeric ID (default: the feature set with `lopen-memory use`)
+        #[arg(long)]
+        feature: Option<String>,
+        /// Disambiguate by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+        /// Task names or IDs, in the order they should be worked on
+        #[arg(required = true, value_name = "TASK")]
+        tasks: Vec<String>,
     },
-    /// List all tasks in a feature, optionally filtered by lifecycle state (Draft, Planning, Building, Complete, Amending)
+    /// List all tasks in a feature, optionally filtered by lifecycle state (Draft, Planning, Building, Complete, Amending, Blocked)
     List {
-        /// Parent feature name or numeric ID
+        /// Parent feature name or numeric ID (default: the feature set with `lopen-memory use`)
         #[arg(long)]
-        feature: String,
+        feature: Option<String>,
         /// Disambiguate by module name or ID if the feature name is not unique
         #[arg(long)]
         module: Option<String>,
-        /// Filter by lifecycle state: Draft, Planning, Building, Complete, or Amending
+        /// Filter by lifecycle state: Draft, Planning, Building, Complete, Amending, or Blocked
         #[arg(long)]
         state: Option<String>,
+        /// Only tasks that can start now: not Complete, with every dependency Complete
+        #[arg(long)]
+        ready: bool,
+        /// Filter by priority: low, medium, high, or critical
+        #[arg(long)]
+        priority: Option<String>,
+        /// Columns to print, in this order, named by their JSON keys, e.g. id,name,state,description; applies to plain, --json, and --format output
+        #[arg(long, value_name = "FIELD,...")]
+        fields: Option<String>,
+        /// Order by this field instead of position (the work order), e.g. priority or state:desc; priorities sort most urgent first and states in lifecycle order, the work order breaks ties
+        #[arg(long, value_name = "FIELD[:desc]")]
+        sort: Option<String>,
+        /// Show at most this many tasks; with --json the output becomes {"items", "returned", "total"}
+        #[arg(long, allow_negative_numbers = true)]
+        limit: Option<i64>,
+        /// Skip this many tasks first, for paging with --limit
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        offset: i64,
     },
     /// Display full details for a task including its description, details, and current lifecycle state
     Show {
@@ -389,9 +1217,15 @@ enum TaskAction {
         /// Disambiguate by feature name or ID if the task name is not unique
         #[arg(long)]
         feature: Option<String>,
-        /// Disambiguate by module name or ID (used with --feature)
+        /// Disambiguate by module name or ID: narrows --feature, or the task itself when there is no --feature
         #[arg(long)]
         module: Option<String>,
+        /// Also print the description and details of the feature, module, and project it belongs to
+        #[arg(long)]
+        with_ancestors: bool,
+        /// Also print every state transition, oldest first (`history` in JSON)
+        #[arg(long)]
+        with_history: bool,
     },
     /// Change a task's slug name
     Rename {
@@ -403,6 +1237,12 @@ enum TaskAction {
         feature: Option<String>,
         /// The new slug name for the task
         new_name: String,
+        /// Also rewrite whole-word references to the old name in descriptions and details
+        #[arg(long)]
+        update_references: bool,
+        /// Preview the references that would be rewritten without changing anything
+        #[arg(long, requires = "update_references")]
+        dry_run: bool,
     },
     /// Replace the task's description — a stable one-sentence statement of what this step achieves. Update only when the goal itself changes
     SetDescription {
@@ -412,10 +1252,14 @@ enum TaskAction {
         /// Disambiguate by feature name or ID if the task name is not unique
         #[arg(long)]
         feature: Option<String>,
-        /// Stable one-sentence statement of what this step achieves
-        description: String,
+        /// Stable one-sentence statement of what this step achieves; `-` reads it from stdin
+        #[arg(required_unless_present = "file")]
+        description: Option<String>,
+        /// Read the description from this file instead, or from stdin with `-`; kept exactly as read, trailing newlines included
+        #[arg(long, value_name = "PATH", conflicts_with = "description")]
+        file: Option<String>,
     },
-    /// Replace the task's working notes entirely. Use for implementation specifics, blockers, and evolving context. Fully overwritten on each call
+    /// Replace the task's working notes, or add to them with --append or --prepend. Use for implementation specifics, blockers, and evolving context. Without either flag the details are fully overwritten
     SetDetails {
         /// Task name or numeric ID
         #[arg(long)]
@@ -423,10 +1267,31 @@ enum TaskAction {
         /// Disambiguate by feature name or ID if the task name is not unique
         #[arg(long)]
         feature: Option<String>,
-        /// Implementation specifics, blockers, and evolving context. Fully replaces existing details
-        details: String,
+        /// Implementation specifics, blockers, and evolving context. Fully replaces existing details; `-` reads it from stdin
+        #[arg(required_unless_present = "file")]
+        details: Option<String>,
+        /// Read the details from this file instead, or from stdin with `-`; kept exactly as read, trailing newlines included
+        #[arg(long, value_name = "PATH", conflicts_with = "details")]
+        file: Option<String>,
+        /// Add the text after the existing details, separated by a blank line, instead of replacing them
+        #[arg(long, conflicts_with = "prepend")]
+        append: bool,
+        /// Add the text before the existing details, separated by a blank line, instead of replacing them
+        #[arg(long)]
+        prepend: bool,
+    },
+    /// Set how much the task matters: low, medium, high, or critical. `task list --sort priority` lists the most urgent first, and `next` prefers them within each tier
+    SetPriority {
+        /// Task name or numeric ID
+        #[arg(long)]
+        task: String,
+        /// Disambiguate by feature name or ID if the task name is not unique
+        #[arg(long)]
+        feature: Option<String>,
+        /// low, medium, high, or critical
+        priority: String,
     },
-    /// Move a task to a new lifecycle state: Draft, Planning, Building, Complete, or Amending. Complete tasks before completing their parent feature
+    /// Move a task to a new lifecycle state: Draft, Planning, Building, Complete, Amending, or Blocked. Complete tasks before completing their parent feature
     Transition {
         /// Task name or numeric ID
         #[arg(long)]
@@ -434,8 +1299,67 @@ enum TaskAction {
         /// Disambiguate by feature name or ID if the task name is not unique
         #[arg(long)]
         feature: Option<String>,
-        /// Target lifecycle state: Draft, Planning, Building, Complete, or Amending
+        /// Target lifecycle state: Draft, Planning, Building, Complete, Amending, or Blocked
+        state: String,
+        /// When moving into Planning or Building, move its module and feature forward too if they are in an earlier stage, in the same transaction. Without it they are only warned about (see the parent_policy config key)
+        #[arg(long)]
+        lift_parents: bool,
+        /// Why the move is happening, e.g. why finished work is being reopened. Kept as last_transition_reason and appended to the details with a timestamp. Moving to Amending without one warns
+        #[arg(long)]
+        reason: Option<String>,
+        /// Move to Building or Complete even though a task this one depends on is not Complete (warns instead)
+        #[arg(long)]
+        force: bool,
+    },
+    /// Move every task of a feature to a new lifecycle state in one transaction, reporting each as transitioned, no-op (already there), or invalid. Invalid moves (a refused transition, a dependency outside the batch that is not Complete) are listed and skipped unless --strict. A task depending on another in the batch moves after it
+    TransitionAll {
+        /// Feature name or numeric ID
+        #[arg(long)]
+        feature: String,
+        /// Disambiguate by module name or ID if the feature name is not unique
+        #[arg(long)]
+        module: Option<String>,
+        /// Target lifecycle state: Draft, Planning, Building, Complete, Amending, or Blocked
         state: String,
+        /// When moving into Planning or Building, move the module and feature forward too if they are in an earlier stage
+        #[arg(long)]
+        lift_parents: bool,
+        /// Why the move is happening; recorded on every task that moves
+        #[arg(long)]
+        reason: Option<String>,
+        /// Change nothing if the move is invalid for any task
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Record that a task cannot start until another is Complete. `task transition` to Building or Complete is then refused until it is, unless --force; `task list --ready` and `next --ready` leave the task out. Refused when it would make a cycle
+    Depend {
+        /// Task that has to wait: name or numeric ID
+        #[arg(long)]
+        task: String,
+        /// Disambiguate --task by feature name or ID
+        #[arg(long)]
+        feature: Option<String>,
+        /// Task it waits on: name or numeric ID
+        #[arg(long)]
+        on: String,
+        /// Disambiguate --on by feature name or ID (default: --task's feature when --on names a task there)
+        #[arg(long)]
+        on_feature: Option<String>,
+    },
+    /// Remove a dependency recorded with `task depend`
+    Undepend {
+        /// Task that was waiting: name or numeric ID
+        #[arg(long)]
+        task: String,
+        /// Disambiguate --task by feature name or ID
+        #[arg(long)]
+        feature: Option<String>,
+        /// Task it waited on: name or numeric ID
+        #[arg(long)]
+        on: String,
+        /// Disambiguate --on by feature name or ID
+        #[arg(long)]
+        on_feature: Option<String>,
     },
     /// Delete a task permanently. This does not affect sibling tasks or the parent feature
     Remove {
@@ -445,6 +1369,69 @@ enum TaskAction {
         /// Disambiguate by feature name or ID if the task name is not unique
         #[arg(long)]
         feature: Option<String>,
+        /// Move it to the trash instead of deleting it, so `trash restore` can bring it back (the default when the soft_delete setting is true)
+        #[arg(long, conflicts_with = "hard")]
+        soft: bool,
+        /// Delete it for good even when the soft_delete setting is true
+        #[arg(long)]
+        hard: bool,
+    },
+    /// Link research to this task. Mirrors `research link --task`, resolving the task first
+    LinkResearch {
+        /// Task name or numeric ID
+        #[arg(long)]
+        task: String,
+        /// Disambiguate by feature name or ID if the task name is not unique
+        #[arg(long)]
+        feature: Option<String>,
+        /// Research name or numeric ID
+        #[arg(long)]
+        research: String,
+        /// Link even when the task is finished, without the warning
+        #[arg(long)]
+        force: bool,
+    },
+    /// Remove a research link from this task. Mirrors `research unlink --task`
+    UnlinkResearch {
+        /// Task name or numeric ID
+        #[arg(long)]
+        task: String,
+        /// Disambiguate by feature name or ID if the task name is not unique
+        #[arg(long)]
+        feature: Option<String>,
+        /// Research name or numeric ID
+        #[arg(long)]
+        research: String,
+    },
+    /// List research linked directly to this task (not research linked only to its ancestors)
+    ListResearch {
+        /// Task name or numeric ID
+        #[arg(long)]
+        task: String,
+        /// Disambiguate by feature name or ID if the task name is not unique
+        #[arg(long)]
+        feature: Option<String>,
+        /// Include archived research, which is left out by default
+        #[arg(long)]
+        include_archived: bool,
+    },
+    /// Show every rename of this task, oldest first, so references to former names can be traced
+    NameHistory {
+        /// Task name or numeric ID
+        #[arg(long)]
+        task: String,
+        /// Disambiguate by feature name or ID if the task name is not unique
+        #[arg(long)]
+        feature: Option<String>,
+    },
+    /// Show every state transition of this task, oldest first, with how long each state before it lasted
+    History {
+        /// Task name or numeric ID
+        #[arg(long)]
+        task: String,
+        /// Disambiguate by feature name or ID if the task name is not unique
+        #[arg(long)]
+        feature: Option<String>,
     },
 }
 
@@ -459,17 +1446,92 @@ enum ResearchAction {
         /// One sentence: what this research covers and why it is relevant
         description: Option<String>,
     },
-    /// List all research records, optionally filtered to those not updated within a given number of days (stale)
-    List {
-        /// Only show records not updated within this many days
+    /// Record a small finding against a task in one call: creates the research record with the given content (researched now) and links it to the task, and with --also-feature to the task's feature. With --append, adds to an existing record of that name instead
+    Capture {
+        /// Task the finding belongs to (name or numeric ID)
+        #[arg(long)]
+        task: String,
+        /// Disambiguate by feature name or ID if the task name is not unique
+        #[arg(long)]
+        feature: Option<String>,
+        /// Slug for the research record
+        #[arg(long)]
+        name: String,
+        /// The finding itself
+        content: String,
+        /// Also link the record to the task's feature
+        #[arg(long)]
+        also_feature: bool,
+        /// If a record with this name exists, append the content to it and make sure it is linked
+        #[arg(long)]
+        append: bool,
+    },
+    /// Import a directory of Markdown notes, one research record per .md file: name from the file name slug, description from the first heading (or first line), content from the body, researched_at from the file's modification time, and the file path as the source. Names that already exist are skipped unless --update. Safe to re-run: unchanged files are skipped and each file is written on its own, so one bad file does not stop the rest
+    ImportDir {
+        /// Directory containing the notes
+        dir: String,
+        /// Also import notes in subdirectories
+        #[arg(long)]
+        recursive: bool,
+        /// Refresh existing records of the same name from their file
         #[arg(long)]
+        update: bool,
+    },
+    /// List all research records with the number of work entities each is linked to, optionally filtered to those not updated within a given number of days (stale) or by link count. A `!` after the date marks records past the stale threshold
+    List {
+        /// Only show records not updated within this many days. Also sets the threshold for the stale marker
+        #[arg(long, allow_negative_numbers = true)]
         stale_days: Option<i64>,
+        /// Only show stale records, using the configured threshold (LOPEN_MEMORY_STALE_DAYS, else config stale_days, else 90)
+        #[arg(long)]
+        stale: bool,
+        /// Only show records linked to at least this many work entities
+        #[arg(long, conflicts_with = "unlinked", allow_negative_numbers = true)]
+        min_links: Option<i64>,
+        /// Only show records not linked to any work entity
+        #[arg(long)]
+        unlinked: bool,
+        /// Print records in sections. `staleness`: fresh, aging, stale, and ancient by days since researched_at
+        #[arg(long, value_name = "FIELD")]
+        group_by: Option<String>,
+        /// Staleness boundaries in days for --group-by staleness (default: the staleness_buckets config key, else 30,90,365)
+        #[arg(long, value_name = "DAYS,DAYS,DAYS", requires = "group_by")]
+        buckets: Option<String>,
+        /// Include archived research, which is left out by default
+        #[arg(long)]
+        include_archived: bool,
+        /// Columns to print, in this order, named by their JSON keys, e.g. id,name,description,links; applies to plain, --json, and --format output
+        #[arg(long, value_name = "FIELD,...", conflicts_with = "group_by")]
+        fields: Option<String>,
+        /// Order by this field, e.g. name or links:desc; ids break ties
+        #[arg(long, value_name = "FIELD[:desc]", conflicts_with = "group_by")]
+        sort: Option<String>,
+        /// Show at most this many records; with --json the output becomes {"items", "returned", "total"}
+        #[arg(long, allow_negative_numbers = true, conflicts_with = "group_by")]
+        limit: Option<i64>,
+        /// Skip this many records first, for paging with --limit
+        #[arg(
+            long,
+            default_value_t = 0,
+            allow_negative_numbers = true,
+            conflicts_with = "group_by"
+        )]
+        offset: i64,
     },
     /// Display full details for a research record including its description, content, source, researched_at date, and all linked work entities
     Show {
         /// Research record name or numeric ID
         #[arg(long)]
         research: String,
+        /// Only print researched_at, created_at, and updated_at in order with their ages, and flag implausible values
+        #[arg(long)]
+        timeline: bool,
+        /// Only print the text of this section of the content
+        #[arg(long, conflicts_with = "timeline")]
+        section: Option<String>,
+        /// Show at most this many links; the rest are counted, and `research links` pages through them
+        #[arg(long, default_value_t = models::research::SHOW_LINKS_LIMIT, allow_negative_numbers = true)]
+        links_limit: i64,
     },
     /// Change a research record's slug name
     Rename {
@@ -478,26 +1540,79 @@ enum ResearchAction {
         research: String,
         /// The new slug name for the research record
         new_name: String,
+        /// Also rewrite whole-word references to the old name in descriptions and details
+        #[arg(long)]
+        update_references: bool,
+        /// Preview the references that would be rewritten without changing anything
+        #[arg(long, requires = "update_references")]
+        dry_run: bool,
     },
     /// Replace the research description — one sentence covering what this research is about and why it is relevant
     SetDescription {
         /// Research record name or numeric ID
         #[arg(long)]
         research: String,
-        /// One sentence covering what this research is about and why it matters
-        description: String,
+        /// One sentence covering what this research is about and why it matters; `-` reads it from stdin
+        #[arg(required_unless_present = "file")]
+        description: Option<String>,
+        /// Read the description from this file instead, or from stdin with `-`; kept exactly as read, trailing newlines included
+        #[arg(long, value_name = "PATH", conflicts_with = "description")]
+        file: Option<String>,
     },
-    /// Replace the research content — the full findings, notes, conclusions, and key facts. Automatically updates researched_at to now unless --no-update-date is passed
+    /// Replace the research content — the full findings, notes, conclusions, and key facts — or add to it with --append or --prepend. Automatically updates researched_at to now unless --no-update-date is passed
     SetContent {
         /// Research record name or numeric ID
         #[arg(long)]
         research: String,
-        /// Full findings — notes, conclusions, key facts, and quotes from the source material
-        content: String,
+        /// Full findings — notes, conclusions, key facts, and quotes from the source material; `-` reads it from stdin
+        #[arg(required_unless_present = "file")]
+        content: Option<String>,
+        /// Read the content from this file instead, or from stdin with `-`; kept exactly as read, trailing newlines included
+        #[arg(long, value_name = "PATH", conflicts_with = "content")]
+        file: Option<String>,
+        /// Add the text after the existing content, separated by a blank line, instead of replacing it
+        #[arg(long, conflicts_with = "prepend")]
+        append: bool,
+        /// Add the text before the existing content, separated by a blank line, instead of replacing it
+        #[arg(long)]
+        prepend: bool,
         /// Do not update researched_at when setting content
         #[arg(long)]
         no_update_date: bool,
     },
+    /// Store or update one named section of the content, leaving the rest as it is. Sections are `## <name>` headings; a new one goes at the end
+    SetSection {
+        /// Research record name or numeric ID
+        #[arg(long)]
+        research: String,
+        /// Section name: lowercase letters, digits, and hyphens, e.g. benchmarks
+        #[arg(long)]
+        section: String,
+        /// Text of the section; `-` reads it from stdin
+        #[arg(required_unless_present_any = ["file", "remove_section"], conflicts_with = "remove_section")]
+        content: Option<String>,
+        /// Read the text from this file instead, or from stdin with `-`
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["content", "remove_section"])]
+        file: Option<String>,
+        /// Delete the section, heading and text
+        #[arg(long)]
+        remove_section: bool,
+        /// Do not update researched_at
+        #[arg(long)]
+        no_update_date: bool,
+    },
+    /// Archive a superseded or outdated research record. It keeps its content and links but is left out of search, list, select, and list-research unless --include-archived is given
+    Archive {
+        /// Research record name or numeric ID
+        #[arg(long)]
+        research: String,
+    },
+    /// Bring an archived research record back into search, list, select, and list-research
+    Unarchive {
+        /// Research record name or numeric ID
+        #[arg(long)]
+        research: String,
+    },
     /// Set or update the source reference — a URL, RFC number, paper title, or citation
     SetSource {
         /// Research record name or numeric ID
@@ -506,6 +1621,23 @@ enum ResearchAction {
         /// URL, RFC number, paper title, or other citation for the source material
         source: String,
     },
+    /// Attach a local file (benchmark CSV, PDF spec, flamegraph) to a research record. By default the absolute path is stored and the file stays where it is; with --copy a copy is kept under ~/.lopen-memory/attachments/<research-id>/ and deleted when the record is removed. Attaching the same file twice is a no-op
+    Attach {
+        /// Research record name or numeric ID
+        #[arg(long)]
+        research: String,
+        /// File to attach; must exist
+        path: String,
+        /// Keep a copy instead of referencing the file in place
+        #[arg(long)]
+        copy: bool,
+    },
+    /// List the files attached to a research record with their size and whether they still exist
+    Attachments {
+        /// Research record name or numeric ID
+        #[arg(long)]
+        research: String,
+    },
     /// Manually override the researched_at timestamp. Use when importing research done on a known prior date
     SetResearchedAt {
         /// Research record name or numeric ID
@@ -514,31 +1646,86 @@ enum ResearchAction {
         /// ISO 8601 date or datetime (e.g. 2025-01-15 or 2025-01-15T10:30:00Z)
         date: String,
     },
-    /// Full-text search across research names, descriptions, content, and sources. Optionally filter to stale records not updated within N days
+    /// Full-text search across research names, descriptions, content, and sources. Optionally narrow to records linked to an entity whose name matches --linked-to, or to stale records not updated within N days
     Search {
-        /// Search keyword matched against research name, description, content, and source
-        term: String,
-        /// Only include records not updated within this many days
+        /// Search keyword matched against research name, description, content, and source. Optional when --linked-to is given
+        #[arg(required_unless_present = "linked_to")]
+        term: Option<String>,
+        /// Only include records linked to a project, module, feature, or task whose name contains this term
         #[arg(long)]
+        linked_to: Option<String>,
+        /// Only include records not updated within this many days
+        #[arg(long, allow_negative_numbers = true)]
         stale_days: Option<i64>,
-    },
-    /// Associate a research record with a work entity. Exactly one of --project, --module, --feature, or --task must be provided. Linking the same pair twice is a no-op
-    Link {
-        /// Research record name or numeric ID
+        /// Also match research, and entities for --linked-to, by names they had before a rename
         #[arg(long)]
-        research: String,
-        /// Link to this project (exactly one of --project, --module, --feature, --task required)
+        include_former_names: bool,
+        /// Also search content stored in external files (see content_external_threshold), which is skipped by default
         #[arg(long)]
-        project: Option<String>,
-        /// Link to this module
+        deep: bool,
+        /// Include archived research, which is left out by default
         #[arg(long)]
-        module: Option<String>,
-        /// Link to this feature
+        include_archived: bool,
+        /// Show at most this many matches; with --json the output becomes {"items", "returned", "total"}
+        #[arg(long, allow_negative_numbers = true)]
+        limit: Option<i64>,
+        /// Skip this many matches first, for paging with --limit
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        offset: i64,
+    },
+    /// List every source cited by research linked to a project or anything in it, once each, with the records citing it. URLs differing only in http/https, host case, or a trailing slash count as one source
+    Bibliography {
+        /// Project name or numeric ID (default: the project set with `lopen-memory use`)
+        #[arg(long)]
+        project: Option<String>,
+        /// Output format: plain, or md for a numbered Markdown reference list with URLs as links
+        #[arg(long, default_value = "plain")]
+        format: String,
+    },
+    /// Assemble research relevant to what you are about to do within a character budget. Records are scored by term overlap with --query and packed best first: full content if it fits, else the description, else truncated content. Reports which records were included, summarized, truncated, or skipped. With one of --project, --module, --feature, or --task, only research linked to that entity or its ancestors is considered
+    Select {
+        /// What you are about to do; its words are matched against research names, descriptions, and content
+        #[arg(long)]
+        query: String,
+        /// Character budget for the packed research text
+        #[arg(long, default_value_t = 4000, allow_negative_numbers = true)]
+        max_chars: i64,
+        /// Only consider research linked to this project
+        #[arg(long)]
+        project: Option<String>,
+        /// Only consider research linked to this module or its project
+        #[arg(long)]
+        module: Option<String>,
+        /// Only consider research linked to this feature or its ancestors
+        #[arg(long)]
+        feature: Option<String>,
+        /// Only consider research linked to this task or its ancestors
+        #[arg(long)]
+        task: Option<String>,
+        /// Include archived research, which is left out by default
+        #[arg(long)]
+        include_archived: bool,
+    },
+    /// Associate a research record with a work entity. Exactly one of --project, --module, --feature, or --task must be provided. Linking the same pair twice is a no-op
+    Link {
+        /// Research record name or numeric ID
+        #[arg(long)]
+        research: String,
+        /// Link to this project (exactly one of --project, --module, --feature, --task required)
+        #[arg(long)]
+        project: Option<String>,
+        /// Link to this module
+        #[arg(long)]
+        module: Option<String>,
+        /// Link to this feature
         #[arg(long)]
         feature: Option<String>,
         /// Link to this task
         #[arg(long)]
         task: Option<String>,
+        /// Link even when the target is finished (Complete, or a completed project), without the warning
+        #[arg(long)]
+        force: bool,
     },
     /// Remove the association between a research record and a work entity. Exactly one of --project, --module, --feature, or --task must be provided. Unlinking a non-existent pair is a no-op
     Unlink {
@@ -563,12 +1750,45 @@ enum ResearchAction {
         /// Research record name or numeric ID
         #[arg(long)]
         research: String,
+        /// Hide links to an entity when a descendant of it is also linked, showing only the most specific
+        #[arg(long)]
+        dedupe: bool,
+        /// Only links to this type: project, module, feature, or task
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+        /// Show at most this many links
+        #[arg(long, allow_negative_numbers = true)]
+        limit: Option<i64>,
+        /// Skip this many links first, for paging with --limit
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        offset: i64,
+    },
+    /// Remove links made redundant because a descendant of the linked entity is also linked (e.g. a feature link when one of its tasks is linked). Asks for confirmation; without a terminal, only previews unless --yes is given
+    NormalizeLinks {
+        /// Research record name or numeric ID
+        #[arg(long)]
+        research: String,
+        /// Remove without asking
+        #[arg(long)]
+        yes: bool,
     },
     /// Delete a research record and all its link associations. Linked work entities are never affected — only the bridge rows are removed
     Remove {
         /// Research record name or numeric ID
         #[arg(long)]
         research: String,
+        /// Move it to the trash instead of deleting it, keeping its copied attachments and content file, so `trash restore` can bring it back (the default when the soft_delete setting is true)
+        #[arg(long, conflicts_with = "hard")]
+        soft: bool,
+        /// Delete it for good even when the soft_delete setting is true
+        #[arg(long)]
+        hard: bool,
+    },
+    /// Show every rename of this research, oldest first, so references to former names can be traced
+    NameHistory {
+        /// Research record name or numeric ID
+        #[arg(long)]
+        research: String,
     },
 }
 
@@ -582,38 +1802,702 @@ enum SkillAction {
         #[arg(long)]
         skills_dir: Option<String>,
     },
+    /// Rewrite the installed SKILL.md with the version embedded in this binary. A no-op if it is already current
+    Update {
+        /// Override the skills directory path (default: ~/.agents/skills/lopen-memory)
+        #[arg(long)]
+        skills_dir: Option<String>,
+    },
+}
+
+// ── Setup actions ─────────────────────────────────────────────────────────────
+
+#[derive(Subcommand)]
+enum SetupAction {
+    /// Write a setup bundle: manifest.json, config.json (when a config file exists), skill/SKILL.md (the installed copy, else the embedded one), and with --with-db database.json as `export --format json` writes it
+    Export {
+        /// Bundle file to write, e.g. setup.tar.gz
+        #[arg(long)]
+        out: String,
+        /// Include every project and research record
+        #[arg(long)]
+        with_db: bool,
+    },
+    /// Install a setup bundle. Paths under the exporting machine's home directory are moved under this one's. An existing config or database that differs from the bundle's stops the apply, changing nothing, unless --overwrite or --merge says what to do. Re-running with the same bundle and choice changes nothing
+    Apply {
+        /// Bundle file written by `setup export`
+        file: String,
+        /// Replace an existing config and database with the bundle's
+        #[arg(long, conflicts_with = "merge")]
+        overwrite: bool,
+        /// Keep existing config values and data; add only the settings, projects, and research that are missing
+        #[arg(long)]
+        merge: bool,
+        /// Rewrite paths starting with FROM to start with TO instead, in config values and project and module paths (repeatable; tried before the home directory mapping)
+        #[arg(long, value_name = "FROM=TO")]
+        map_path: Vec<String>,
+        /// Leave paths exactly as the bundle has them
+        #[arg(long)]
+        keep_paths: bool,
+        /// Override the skills directory path (default: ~/.agents/skills/lopen-memory)
+        #[arg(long)]
+        skills_dir: Option<String>,
+        /// Install the config and skill only, even if the bundle holds a database
+        #[arg(long)]
+        skip_db: bool,
+    },
+}
+
+// ── Config actions ────────────────────────────────────────────────────────────
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// List every setting in the config file (~/.lopen-memory/config.json, or LOPEN_MEMORY_CONFIG)
+    List,
+    /// Print the value of one setting
+    Get {
+        /// Setting name, e.g. skill_check
+        key: String,
+    },
+    /// Set a setting. Values are parsed as JSON (true, 30, "text"); anything else is stored as a string
+    Set {
+        /// Setting name, e.g. skill_check
+        key: String,
+        /// New value
+        value: String,
+    },
+    /// Remove a setting so its default applies again
+    Unset {
+        /// Setting name
+        key: String,
+    },
+}
+
+// ── Db actions ────────────────────────────────────────────────────────────────
+
+#[derive(Subcommand)]
+enum DbAction {
+    /// Show the database file size, page usage, total research content size, and row counts for every table
+    Stats,
+    /// Run any other lopen-memory command and print each query it executed, with timing and EXPLAIN QUERY PLAN output, to stderr. Queries slower than LOPEN_MEMORY_SLOW_QUERY_MS (default 250) always warn
+    Profile {
+        /// The command to profile, e.g. `db profile research search jwt`
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            required = true,
+            value_name = "COMMAND"
+        )]
+        args: Vec<String>,
+    },
+    /// Check research content stored in external files (see content_external_threshold): report records whose file is missing (exit 1), move bodies now at or under the threshold back into the database, and delete files no record points at. Asks for confirmation; without a terminal, only lists changes unless --yes is given
+    Maintain {
+        /// Apply changes without asking
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Check the store's health: SQLite's integrity and foreign key checks, rows whose parent is gone, unknown states, unparseable or non-canonical timestamps, and names that collide ignoring case. Prints errors then warnings and exits 1 while any error remains
+    Doctor {
+        /// Repair what is safe to, in one transaction: delete orphaned rows and rewrite timestamps into the canonical form
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Write a consistent copy of the database, safe while other processes write to it. Defaults to a timestamped file next to the database
+    Backup {
+        /// File to write, or a directory to put the timestamped file in
+        #[arg(long, value_name = "PATH")]
+        to: Option<String>,
+        /// Keep only the newest N timestamped backups in that directory
+        #[arg(long, value_name = "N", allow_negative_numbers = true)]
+        keep: Option<i64>,
+    },
+    /// Replace the database with a backup, once it checks out as an intact lopen-memory database this binary can open. The current contents are saved as a timestamped backup first, and an older schema is migrated
+    Restore {
+        /// Backup file to restore
+        #[arg(long, value_name = "PATH")]
+        from: String,
+    },
+}
+
+// ── Snapshot actions ──────────────────────────────────────────────────────────
+
+#[derive(Subcommand)]
+enum SnapshotAction {
+    /// Save the project's modules, features, tasks, and the research linked into them under a name. Names are unique across the database
+    Create {
+        /// Project name or numeric ID (default: the project set with `lopen-memory use`)
+        #[arg(long)]
+        project: Option<String>,
+        /// Snapshot name, e.g. before-merge
+        #[arg(long)]
+        name: String,
+    },
+    /// List snapshots, oldest first, with what each holds
+    List {
+        /// Only snapshots of this project
+        #[arg(long)]
+        project: Option<String>,
+    },
+    /// Reinstate a snapshot in one transaction, with new ids. Research that still exists is linked as it is now; research removed since is recreated
+    Restore {
+        /// Snapshot name
+        #[arg(long)]
+        name: String,
+        /// Remove the snapshotted project and everything under it first, then restore under its original name
+        #[arg(long, conflicts_with = "as_name")]
+        replace: bool,
+        /// Restore as a new project with this name, beside the current one
+        #[arg(long = "as", value_name = "PROJECT")]
+        as_name: Option<String>,
+    },
+    /// Delete a snapshot; the project is not touched
+    Drop {
+        /// Snapshot name
+        #[arg(long)]
+        name: String,
+    },
+}
+
+// ── Trash actions ─────────────────────────────────────────────────────────────
+
+#[derive(Subcommand)]
+enum TrashAction {
+    /// List what is in the trash, most recently removed first, with what each removal took along
+    List {
+        /// Only one entity type: project, module, feature, task, or research
+        #[arg(long = "type", value_name = "TYPE")]
+        kind: Option<String>,
+    },
+    /// Put a removed entity back with everything removed with it, ids unchanged. Fails while its parent is missing or another entity has its name
+    #[command(group(clap::ArgGroup::new("entity").required(true).args(["project", "module", "feature", "task", "research"])))]
+    Restore {
+        /// Project id, short ref, or name
+        #[arg(long)]
+        project: Option<String>,
+        /// Module id, short ref, or name
+        #[arg(long)]
+        module: Option<String>,
+        /// Feature id, short ref, or name
+        #[arg(long)]
+        feature: Option<String>,
+        /// Task id, short ref, or name
+        #[arg(long)]
+        task: Option<String>,
+        /// Research id, short ref, or name
+        #[arg(long)]
+        research: Option<String>,
+    },
+    /// Delete what is in the trash for good, with the copied attachments and content files of trashed research
+    Purge {
+        /// Only what was removed more than this many days ago
+        #[arg(long, value_name = "DAYS", allow_negative_numbers = true)]
+        older_than: Option<i64>,
+    },
+}
+
+// ── Share actions ─────────────────────────────────────────────────────────────
+
+#[derive(Subcommand)]
+enum ShareAction {
+    /// Write a compressed, versioned bundle of the project's modules, features, tasks, history, and the research linked into them, ids unchanged. Nothing else in the database goes in
+    Create {
+        /// Project name or numeric ID (default: the project set with `lopen-memory use`)
+        #[arg(long)]
+        project: Option<String>,
+        /// Bundle file to write, e.g. my-app.lmshare
+        #[arg(long)]
+        out: String,
+    },
+    /// Run a read command (list, show, search, tree, status, next, ...) against a bundle, e.g. `share open my-app.lmshare tree --project my-app`. Commands that would change anything exit 1
+    Open {
+        /// Bundle file written by `share create`
+        file: String,
+        /// The command to run
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            required = true,
+            value_name = "COMMAND"
+        )]
+        args: Vec<String>,
+    },
+}
+
+// ── Lock actions ──────────────────────────────────────────────────────────────
+
+#[derive(Subcommand)]
+enum LockAction {
+    /// Take a named lock for a number of seconds. Exits 4 if it is already held, by anyone, and has not expired
+    Acquire {
+        /// Lock name, e.g. build-plan
+        #[arg(long)]
+        name: String,
+        /// Seconds until the lock expires on its own
+        #[arg(long, default_value_t = 300, allow_negative_numbers = true)]
+        ttl: i64,
+        /// Who is taking it, e.g. an agent id. Shown by `lock status` and checked by `lock release --holder`
+        #[arg(long, default_value = "")]
+        holder: String,
+    },
+    /// Give up a named lock. Exits 1 if it is not held
+    Release {
+        /// Lock name
+        #[arg(long)]
+        name: String,
+        /// Only release it if this holder has it; exits 4 otherwise
+        #[arg(long)]
+        holder: Option<String>,
+    },
+    /// Show who holds a lock and for how much longer, or every held lock
+    Status {
+        /// Lock name; omit to list every held lock
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AnalyticsAction {
+    /// Changes per day to the project and everything in it, by entity type, drawn as a weekday-by-week calendar. Each entity counts on the day it was last worked on, plus renames and research creation
+    Activity {
+        /// Project name or numeric ID (default: the project set with `lopen-memory use`)
+        #[arg(long)]
+        project: Option<String>,
+        /// First day counted, YYYY-MM-DD (default: twelve weeks ago)
+        #[arg(long)]
+        since: Option<String>,
+    },
 }
 
 // ── Main ──────────────────────────────────────────────────────────────────────
 
 fn main() {
-    let cli = Cli::parse();
-    let path = db_path(cli.db.as_ref());
-    let conn = match db::open(&path) {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("error: failed to open database: {}", e);
-            process::exit(2);
+    // Parse first: --help and argument errors exit here, before anything
+    // touches the filesystem, and commands with no use for the database never
+    // open it.
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let format = match cli.format {
+        Some(format) => format,
+        None if cli.json => output::Format::Json,
+        None => output::Format::Plain,
+    };
+    output::set_format(format);
+    cli.json = format != output::Format::Plain;
+    output::json_errors(cli.json);
+    let code = match cli.command {
+        command @ (Commands::Skill { .. } | Commands::Config { .. } | Commands::Changes { .. }) => {
+            run_without_db(command, cli.json)
+        }
+        command => {
+            let path = db_path(cli.db.as_ref());
+            match busy_timeout(cli.busy_timeout_ms) {
+                Ok(ms) => db::set_busy_timeout(ms),
+                Err(e) => {
+                    let code = output::fail(&Error::Invalid(e));
+                    output::finish(code);
+                    process::exit(code);
+                }
+            }
+            let started = std::time::Instant::now();
+            let mut conn = match db::open(&path, cli.paranoid) {
+                Ok(c) => c,
+                Err(_) if db::gave_up() => {
+                    report_busy(cli.json);
+                    process::exit(output::EXIT_BUSY);
+                }
+                Err(e) => {
+                    let kind = db::open_error_kind(&path, &e);
+                    if cli.json {
+                        // A file that is missing or unreadable is io; the
+                        // rest is SQLite's to explain.
+                        let code = match kind {
+                            "not_found" | "permission" => "io",
+                            _ => "db",
+                        };
+                        output::err_json(&json!({
+                            "code": code,
+                            "entity": null,
+                            "kind": kind,
+                            "message": format!("failed to open database: {}", e),
+                            "path": path,
+                        }));
+                    } else {
+                        eprintln!("error: failed to open database {} ({}): {}", path, kind, e);
+                    }
+                    process::exit(2);
+                }
+            };
+            profile::install(&mut conn);
+            profile::opened(started.elapsed());
+            // The notice goes to stderr, but stays out of --json runs entirely so
+            // nothing extra appears around machine-read output.
+            if !cli.json && !cli.no_skill_check {
+                skill::startup_check();
+            }
+            let trace_to = trace::target(cli.trace_file.as_deref());
+            if trace_to.is_some() {
+                trace::install(&conn);
+            }
+            resolve::refuse_archived(writes(&command_path(&matches)));
+            let mut code = run(&conn, &path, command, cli.json);
+            if db::gave_up() {
+                report_busy(cli.json);
+                code = output::EXIT_BUSY;
+            }
+            if let Some(to) = trace_to {
+                trace::write(&to, &trace::events(&conn, &command_path(&matches)));
+            }
+            code
         }
     };
-    let json = cli.json;
+    let code = output::stdout_exit().unwrap_or(code);
+    output::finish(code);
+    process::exit(code);
+}
 
-    let code = match cli.command {
-        Commands::Project { action } => handle_project(&conn, action, json),
-        Commands::Module { action } => handle_module(&conn, action, json),
-        Commands::Feature { action } => handle_feature(&conn, action, json),
-        Commands::Task { action } => handle_task(&conn, action, json),
-        Commands::Research { action } => handle_research(&conn, action, json),
+/// Commands that only read, by their last word or full path. Every other
+/// command is refused anything in an archived project, and `share open` runs
+/// only these.
+const READS: [&str; 24] = [
+    "list",
+    "show",
+    "history",
+    "name-history",
+    "list-research",
+    "links",
+    "attachments",
+    "search",
+    "bibliography",
+    "select",
+    "status",
+    "next",
+    "tree",
+    "diff",
+    "find",
+    "last",
+    "validate",
+    "export",
+    "analytics activity",
+    "db stats",
+    "lock status",
+    "setup export",
+    "snapshot list",
+    "share create",
+];
+
+/// Commands that write but not into a project, or that check archived
+/// projects themselves: `project archive` and `unarchive`, and `feature
+/// clone`, which only reads its source and checks its target.
+const ARCHIVE_EXEMPT: [&str; 5] = [
+    "use",
+    "snapshot create",
+    "project archive",
+    "project unarchive",
+    "feature clone",
+];
+
+/// Whether `path` is one of `commands`, or ends in one of its single words.
+fn listed(commands: &[&str], path: &str) -> bool {
+    let last = path.rsplit(' ').next().unwrap_or(path);
+    commands.iter().any(|c| *c == path || *c == last)
+}
+
+/// Whether the command at `path` may change a project.
+fn writes(path: &str) -> bool {
+    !listed(&READS, path) && !listed(&ARCHIVE_EXEMPT, path)
+}
+
+/// The subcommands given, as `task set-details`, without their arguments.
+fn command_path(matches: &clap::ArgMatches) -> String {
+    let mut path = Vec::new();
+    let mut m = matches;
+    while let Some((name, sub)) = m.subcommand() {
+        path.push(name);
+        m = sub;
+    }
+    path.join(" ")
+}
+
+/// Commands that never read or write the database.
+fn run_without_db(command: Commands, json: bool) -> i32 {
+    match command {
         Commands::Skill { action } => handle_skill(action, json),
-    };
+        Commands::Config { action } => handle_config(action, json),
+        Commands::Changes { since, check } => changes::changes(
+            since.as_deref(),
+            check.as_deref(),
+            db::schema_version(),
+            json,
+        ),
+        _ => unreachable!("command needs the database"),
+    }
+}
 
-    process::exit(code);
+/// Every numeric flag checked against its range before anything runs.
+fn check_numbers(command: &Commands) -> Result<(), String> {
+    use limits::*;
+    match command {
+        Commands::Next { count, .. } => COUNT.check("--count", *count).map(drop),
+        Commands::Diff { context, .. } => CONTEXT_LINES.check("--context", *context).map(drop),
+        Commands::Find { limit, offset, .. } => LIMIT
+            .check_opt("--limit", *limit)
+            .and(OFFSET.check("--offset", *offset))
+            .map(drop),
+        Commands::Last { n, .. } => LIMIT.check("--n", *n).map(drop),
+        Commands::Validate { building_days, .. } => {
+            DAYS.check_opt("--building-days", *building_days).map(drop)
+        }
+        Commands::Cleanup { older_than, .. } => {
+            AGE_DAYS.check("--older-than", *older_than).map(drop)
+        }
+        Commands::Db {
+            action: DbAction::Backup { keep, .. },
+        } => COUNT.check_opt("--keep", *keep).map(drop),
+        Commands::Trash {
+            action: TrashAction::Purge { older_than },
+        } => AGE_DAYS.check_opt("--older-than", *older_than).map(drop),
+        Commands::Project {
+            action: ProjectAction::List { limit, offset, .. },
+        }
+        | Commands::Module {
+            action: ModuleAction::List { limit, offset, .. },
+        }
+        | Commands::Feature {
+            action: FeatureAction::List { limit, offset, .. },
+        }
+        | Commands::Task {
+            action: TaskAction::List { limit, offset, .. },
+        } => LIMIT
+            .check_opt("--limit", *limit)
+            .and(OFFSET.check("--offset", *offset))
+            .map(drop),
+        Commands::Research {
+            action:
+                ResearchAction::List {
+                    stale_days,
+                    min_links,
+                    limit,
+                    offset,
+                    ..
+                },
+        } => DAYS
+            .check_opt("--stale-days", *stale_days)
+            .and(LINKS.check_opt("--min-links", *min_links))
+            .and(LIMIT.check_opt("--limit", *limit))
+            .and(OFFSET.check("--offset", *offset))
+            .map(drop),
+        Commands::Research {
+            action:
+                ResearchAction::Search {
+                    stale_days,
+                    limit,
+                    offset,
+                    ..
+                },
+        } => DAYS
+            .check_opt("--stale-days", *stale_days)
+            .and(LIMIT.check_opt("--limit", *limit))
+            .and(OFFSET.check("--offset", *offset))
+            .map(drop),
+        Commands::Research {
+            action: ResearchAction::Show { links_limit, .. },
+        } => LIMIT.check("--links-limit", *links_limit).map(drop),
+        Commands::Research {
+            action: ResearchAction::Links { limit, offset, .. },
+        } => LIMIT
+            .check_opt("--limit", *limit)
+            .and(OFFSET.check("--offset", *offset))
+            .map(drop),
+        Commands::Research {
+            action: ResearchAction::Select { max_chars, .. },
+        } => CHARS.check("--max-chars", *max_chars).map(drop),
+        Commands::Lock {
+            action: LockAction::Acquire { ttl, .. },
+        } => TTL_SECS.check("--ttl", *ttl).map(drop),
+        _ => Ok(()),
+    }
+}
+
+fn run(conn: &rusqlite::Connection, path: &str, command: Commands, json: bool) -> i32 {
+    if let Err(e) = check_numbers(&command) {
+        return output::fail(&Error::Invalid(e));
+    }
+    match command {
+        Commands::Project { action } => handle_project(conn, action, json),
+        Commands::Module { action } => handle_module(conn, action, json),
+        Commands::Feature { action } => handle_feature(conn, action, json),
+        Commands::Task { action } => handle_task(conn, action, json),
+        Commands::Research { action } => handle_research(conn, action, json),
+        Commands::Export {
+            format,
+            out,
+            file,
+            project,
+        } => handle_export(conn, &format, out, file, project, json),
+        Commands::Status { project } => {
+            let pid = match project
+                .as_deref()
+                .map(|p| resolve::resolve_project(conn, p))
+                .transpose()
+            {
+                Ok(p) => p,
+                Err(e) => return output::fail(&e),
+            };
+            models::status::status(conn, pid, json)
+        }
+        Commands::Next {
+            project,
+            module,
+            feature,
+            count,
+            ready,
+            about,
+            require_match,
+        } => {
+            let about = match about
+                .map(|a| models::next::About::new(&a, require_match))
+                .transpose()
+            {
+                Ok(a) => a,
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
+            handle_next(
+                conn,
+                project.as_deref(),
+                module.as_deref(),
+                feature.as_deref(),
+                count as usize,
+                ready,
+                about.as_ref(),
+                json,
+            )
+        }
+        Commands::Progress {
+            task,
+            feature,
+            text,
+            touch_state,
+            lift_parents,
+        } => handle_progress(
+            conn,
+            task.as_deref(),
+            feature.as_deref(),
+            &text,
+            touch_state,
+            lift_parents,
+            json,
+        ),
+        Commands::Tree {
+            project,
+            state,
+            depth,
+        } => {
+            let pid = match project_or_context(conn, project.as_deref()) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            let state = match state.map(|s| s.parse::<state::State>()).transpose() {
+                Ok(s) => s,
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
+            models::tree::tree(conn, pid, state.as_ref(), depth, json)
+        }
+        Commands::Diff {
+            project,
+            module,
+            feature,
+            task,
+            research,
+            field,
+            context,
+        } => handle_diff(
+            conn,
+            [project, module, feature, task, research],
+            &field,
+            context as usize,
+            json,
+        ),
+        Commands::Import { file, on_conflict } => match on_conflict.parse() {
+            Ok(strategy) => models::import::import(conn, &file, strategy, json),
+            Err(e) => output::fail(&Error::Invalid(e)),
+        },
+        Commands::Find {
+            query,
+            kind,
+            project,
+            state,
+            limit,
+            offset,
+            include_former_names,
+        } => {
+            let project_id = match resolve_optional_project(conn, project.as_deref()) {
+                Ok(p) => p,
+                Err(e) => return output::fail(&e),
+            };
+            let filter = models::find::Filter {
+                kind: kind.as_deref(),
+                project_id,
+                state: state.as_deref(),
+            };
+            models::find::find(
+                conn,
+                &query,
+                &filter,
+                fields::Page { limit, offset },
+                include_former_names,
+                json,
+            )
+        }
+        Commands::Last { kind, n } => models::recent::last(conn, kind.as_deref(), n as usize, json),
+        Commands::Validate {
+            project,
+            building_days,
+        } => handle_validate(conn, project, building_days, json),
+        Commands::Cleanup {
+            project,
+            older_than,
+            include_described,
+            include_linked,
+            dry_run,
+            yes,
+        } => {
+            let project_id = match resolve_optional_project(conn, project.as_deref()) {
+                Ok(id) => id,
+                Err(e) => return output::fail(&e),
+            };
+            let criteria = models::cleanup::Criteria {
+                project_id,
+                older_than_days: older_than,
+                include_described,
+                include_linked,
+            };
+            models::cleanup::cleanup(conn, &criteria, dry_run, yes, json)
+        }
+        Commands::Use {
+            project,
+            module,
+            feature,
+            clear,
+            show,
+        } => handle_use(conn, project, module, feature, clear, show, json),
+        Commands::Db { action } => handle_db(conn, path, action, json),
+        Commands::Lock { action } => handle_lock(conn, action, json),
+        Commands::Snapshot { action } => handle_snapshot(conn, action, json),
+        Commands::Setup { action } => handle_setup(conn, action, json),
+        Commands::Trash { action } => handle_trash(conn, action, json),
+        Commands::Share { action } => handle_share(conn, action, json),
+        Commands::Analytics { action } => handle_analytics(conn, action, json),
+        command @ (Commands::Skill { .. } | Commands::Config { .. } | Commands::Changes { .. }) => {
+            run_without_db(command, json)
+        }
+    }
 }
 
 // ── Project handler ───────────────────────────────────────────────────────────
 
 fn handle_project(conn: &rusqlite::Connection, action: ProjectAction, json: bool) -> i32 {
-    use models::project;
+    use models::{bootstrap, history, project, trash};
     match action {
         ProjectAction::Add {
             name,
@@ -621,10 +2505,47 @@ fn handle_project(conn: &rusqlite::Connection, action: ProjectAction, json: bool
             description,
         } => project::add(conn, &name, &path, &description.unwrap_or_default(), json),
 
+        ProjectAction::Bootstrap {
+            path,
+            name,
+            modules_from,
+            if_not_exists,
+            dry_run,
+        } => {
+            let source = match modules_from
+                .map(|s| s.parse::<bootstrap::ModuleSource>())
+                .transpose()
+            {
+                Ok(s) => s,
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
+            bootstrap::bootstrap(
+                conn,
+                &path,
+                name.as_deref(),
+                source,
+                if_not_exists,
+                dry_run,
+                json,
+            )
+        }
+
         ProjectAction::List {
             completed,
             incomplete,
+            effective,
+            archived,
+            all,
+            fields,
+            sort,
+            limit,
+            offset,
         } => {
+            let (fields, sort) =
+                match list_view("project", project::COLUMNS, project::COLUMNS, fields, sort) {
+                    Ok(v) => v,
+                    Err(e) => return output::fail(&e),
+                };
             let filter = if completed {
                 Some(true)
             } else if incomplete {
@@ -632,41 +2553,61 @@ fn handle_project(conn: &rusqlite::Connection, action: ProjectAction, json: bool
             } else {
                 None
             };
-            project::list(conn, filter, json)
+            let archived = if archived {
+                project::Archived::Only
+            } else if all {
+                project::Archived::Include
+            } else {
+                project::Archived::Hide
+            };
+            project::list(
+                conn,
+                filter,
+                effective,
+                archived,
+                fields.as_deref(),
+                sort,
+                fields::Page { limit, offset },
+                json,
+            )
         }
 
-        ProjectAction::Show { project } => {
+        ProjectAction::Show {
+            project,
+            with_history,
+        } => {
             let id = match resolve::resolve_project(conn, &project) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            project::show(conn, id, json)
+            project::show(conn, id, with_history, json)
         }
 
-        ProjectAction::Rename { project, new_name } => {
+        ProjectAction::Rename {
+            project,
+            new_name,
+            update_references,
+            dry_run,
+        } => {
             let id = match resolve::resolve_project(conn, &project) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            project::rename(conn, id, &new_name, json)
+            project::rename(conn, id, &new_name, update_references, dry_run, json)
         }
 
         ProjectAction::SetDescription {
             project,
             description,
+            file,
         } => {
+            let description = match text_input(description, file) {
+                Ok(t) => t,
+                Err(e) => return output::fail(&e),
+            };
             let id = match resolve::resolve_project(conn, &project) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             project::set_description(conn, id, &description, json)
         }
@@ -674,213 +2615,392 @@ fn handle_project(conn: &rusqlite::Connection, action: ProjectAction, json: bool
         ProjectAction::SetPath { project, path } => {
             let id = match resolve::resolve_project(conn, &project) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             project::set_path(conn, id, &path, json)
         }
 
-        ProjectAction::Complete { project } => {
+        ProjectAction::Complete {
+            project,
+            force,
+            reason,
+        } => {
             let id = match resolve::resolve_project(conn, &project) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            project::set_completed(conn, id, true, json)
+            project::set_completed(conn, id, true, reason.as_deref(), force, json)
         }
 
-        ProjectAction::Reopen { project } => {
+        ProjectAction::Reopen { project, reason } => {
             let id = match resolve::resolve_project(conn, &project) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            project::set_completed(conn, id, false, json)
+            project::set_completed(conn, id, false, reason.as_deref(), false, json)
         }
 
-        ProjectAction::Remove { project, cascade } => {
-            let id = match resolve::resolve_project(conn, &project) {
-                Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+        ProjectAction::Archive { project } => match resolve::resolve_project(conn, &project) {
+            Ok(id) => project::set_archived(conn, id, true, json),
+            Err(e) => output::fail(&e),
+        },
+
+        ProjectAction::Unarchive { project } => match resolve::resolve_project(conn, &project) {
+            Ok(id) => project::set_archived(conn, id, false, json),
+            Err(e) => output::fail(&e),
+        },
+
+        ProjectAction::Remove {
+            project,
+            cascade,
+            soft,
+            hard,
+        } => {
+            let id = match resolve::resolve_project(conn, &project) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
             };
-            project::remove(conn, id, cascade, json)
+            project::remove(conn, id, cascade, trash::soft(soft, hard), json)
+        }
+
+        ProjectAction::LinkResearch {
+            project,
+            research,
+            force,
+        } => {
+            let id = resolve::resolve_project(conn, &project);
+            handle_entity_research(
+                conn,
+                "project",
+                id,
+                EntityResearch::Link(research, force),
+                json,
+            )
+        }
+
+        ProjectAction::UnlinkResearch { project, research } => {
+            let id = resolve::resolve_project(conn, &project);
+            handle_entity_research(conn, "project", id, EntityResearch::Unlink(research), json)
+        }
+
+        ProjectAction::ListResearch {
+            project,
+            include_archived,
+        } => {
+            let id = resolve::resolve_project(conn, &project);
+            handle_entity_research(
+                conn,
+                "project",
+                id,
+                EntityResearch::List(include_archived),
+                json,
+            )
         }
+
+        ProjectAction::NameHistory { project } => match resolve::resolve_project(conn, &project) {
+            Ok(id) => history::show(conn, "project", id, json),
+            Err(e) => output::fail(&e),
+        },
+        ProjectAction::History { project } => match resolve::resolve_project(conn, &project) {
+            Ok(id) => history::state_history(conn, "project", id, json),
+            Err(e) => output::fail(&e),
+        },
     }
 }
 
 // ── Module handler ────────────────────────────────────────────────────────────
 
 fn handle_module(conn: &rusqlite::Connection, action: ModuleAction, json: bool) -> i32 {
-    use models::module;
+    use models::{history, module, trash};
     match action {
         ModuleAction::Add {
             project,
             name,
             description,
+            path,
+            plan,
+            start,
+            state,
+            details,
         } => {
+            let start = match start_state(plan, start, state.as_deref()) {
+                Ok(s) => s,
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
             let pid = match resolve::resolve_project(conn, &project) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            module::add(conn, pid, &name, &description.unwrap_or_default(), json)
+            module::add(
+                conn,
+                pid,
+                &name,
+                &description.unwrap_or_default(),
+                &details.unwrap_or_default(),
+                &path.unwrap_or_default(),
+                start.as_ref(),
+                json,
+            )
         }
 
-        ModuleAction::List { project, state } => {
+        ModuleAction::List {
+            project,
+            state,
+            fields,
+            sort,
+            limit,
+            offset,
+        } => {
+            let (fields, sort) =
+                match list_view("module", module::COLUMNS, module::COLUMNS, fields, sort) {
+                    Ok(v) => v,
+                    Err(e) => return output::fail(&e),
+                };
             let pid = match resolve::resolve_project(conn, &project) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            module::list(conn, pid, state.as_deref(), json)
+            module::list(
+                conn,
+                pid,
+                state.as_deref(),
+                fields.as_deref(),
+                sort,
+                fields::Page { limit, offset },
+                json,
+            )
         }
 
-        ModuleAction::Show { module, project } => {
+        ModuleAction::Show {
+            module,
+            project,
+            with_history,
+        } => {
             let pid = match resolve_optional_project(conn, project.as_deref()) {
                 Ok(p) => p,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             let mid = match resolve::resolve_module(conn, &module, pid) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            module::show(conn, mid, json)
+            module::show(conn, mid, with_history, json)
         }
 
         ModuleAction::Rename {
             module,
             project,
             new_name,
+            update_references,
+            dry_run,
         } => {
             let pid = match resolve_optional_project(conn, project.as_deref()) {
                 Ok(p) => p,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             let mid = match resolve::resolve_module(conn, &module, pid) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            module::rename(conn, mid, &new_name, json)
+            module::rename(conn, mid, &new_name, update_references, dry_run, json)
         }
 
         ModuleAction::SetDescription {
             module,
             project,
             description,
+            file,
         } => {
+            let description = match text_input(description, file) {
+                Ok(t) => t,
+                Err(e) => return output::fail(&e),
+            };
             let pid = match resolve_optional_project(conn, project.as_deref()) {
                 Ok(p) => p,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             let mid = match resolve::resolve_module(conn, &module, pid) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             module::set_description(conn, mid, &description, json)
         }
 
+        ModuleAction::SetPath {
+            module,
+            project,
+            path,
+        } => {
+            let pid = match resolve_optional_project(conn, project.as_deref()) {
+                Ok(p) => p,
+                Err(e) => return output::fail(&e),
+            };
+            let mid = match resolve::resolve_module(conn, &module, pid) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            module::set_path(conn, mid, &path, json)
+        }
+
         ModuleAction::SetDetails {
             module,
             project,
             details,
+            file,
+            append,
+            prepend,
         } => {
+            let details = match text_input(details, file) {
+                Ok(t) => t,
+                Err(e) => return output::fail(&e),
+            };
             let pid = match resolve_optional_project(conn, project.as_deref()) {
                 Ok(p) => p,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             let mid = match resolve::resolve_module(conn, &module, pid) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            module::set_details(conn, mid, &details, json)
+            module::set_details(
+                conn,
+                mid,
+                &details,
+                Placement::from_flags(append, prepend),
+                json,
+            )
         }
 
         ModuleAction::Transition {
             module,
             project,
             state,
+            reason,
+            force,
         } => {
             let to_state = match state.parse::<state::State>() {
                 Ok(s) => s,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&Error::Invalid(e)),
             };
             let pid = match resolve_optional_project(conn, project.as_deref()) {
                 Ok(p) => p,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             let mid = match resolve::resolve_module(conn, &module, pid) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
+            };
+            module::transition(conn, mid, &to_state, reason.as_deref(), force, json)
+        }
+
+        ModuleAction::Lock { module, project } => {
+            let pid = match resolve_optional_project(conn, project.as_deref()) {
+                Ok(p) => p,
+                Err(e) => return output::fail(&e),
+            };
+            let mid = match resolve::resolve_module(conn, &module, pid) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            module::set_locked(conn, mid, true, json)
+        }
+
+        ModuleAction::Unlock { module, project } => {
+            let pid = match resolve_optional_project(conn, project.as_deref()) {
+                Ok(p) => p,
+                Err(e) => return output::fail(&e),
+            };
+            let mid = match resolve::resolve_module(conn, &module, pid) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
             };
-            module::transition(conn, mid, &to_state, json)
+            module::set_locked(conn, mid, false, json)
         }
 
         ModuleAction::Remove {
             module,
             project,
             cascade,
+            relink_research_to_parent,
+            soft,
+            hard,
         } => {
             let pid = match resolve_optional_project(conn, project.as_deref()) {
                 Ok(p) => p,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             let mid = match resolve::resolve_module(conn, &module, pid) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            module::remove(conn, mid, cascade, json)
+            module::remove(
+                conn,
+                mid,
+                cascade,
+                relink_research_to_parent,
+                trash::soft(soft, hard),
+                json,
+            )
+        }
+
+        ModuleAction::LinkResearch {
+            module,
+            project,
+            research,
+            force,
+        } => {
+            let id = resolve_optional_project(conn, project.as_deref())
+                .and_then(|pid| resolve::resolve_module(conn, &module, pid));
+            handle_entity_research(
+                conn,
+                "module",
+                id,
+                EntityResearch::Link(research, force),
+                json,
+            )
+        }
+
+        ModuleAction::UnlinkResearch {
+            module,
+            project,
+            research,
+        } => {
+            let id = resolve_optional_project(conn, project.as_deref())
+                .and_then(|pid| resolve::resolve_module(conn, &module, pid));
+            handle_entity_research(conn, "module", id, EntityResearch::Unlink(research), json)
+        }
+
+        ModuleAction::ListResearch {
+            module,
+            project,
+            include_archived,
+        } => {
+            let id = resolve_optional_project(conn, project.as_deref())
+                .and_then(|pid| resolve::resolve_module(conn, &module, pid));
+            handle_entity_research(
+                conn,
+                "module",
+                id,
+                EntityResearch::List(include_archived),
+                json,
+            )
+        }
+
+        ModuleAction::NameHistory { module, project } => {
+            match resolve_optional_project(conn, project.as_deref())
+                .and_then(|pid| resolve::resolve_module(conn, &module, pid))
+            {
+                Ok(id) => history::show(conn, "module", id, json),
+                Err(e) => output::fail(&e),
+            }
+        }
+        ModuleAction::History { module, project } => {
+            match resolve_optional_project(conn, project.as_deref())
+                .and_then(|pid| resolve::resolve_module(conn, &module, pid))
+            {
+                Ok(id) => history::state_history(conn, "module", id, json),
+                Err(e) => output::fail(&e),
+            }
         }
     }
 }
@@ -888,190 +3008,371 @@ fn handle_module(conn: &rusqlite::Connection, action: ModuleAction, json: bool)
 // ── Feature handler ───────────────────────────────────────────────────────────
 
 fn handle_feature(conn: &rusqlite::Connection, action: FeatureAction, json: bool) -> i32 {
-    use models::feature;
+    use models::{feature, history, lift, trash};
     match action {
         FeatureAction::Add {
             module,
             project,
             name,
             description,
+            plan,
+            start,
+            state,
+            details,
+            priority,
         } => {
+            let start = match start_state(plan, start, state.as_deref()) {
+                Ok(s) => s,
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
+            let priority = match parse_priority(priority.as_deref()) {
+                Ok(p) => p.unwrap_or(priority::Priority::Medium),
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
             let pid = match resolve_optional_project(conn, project.as_deref()) {
                 Ok(p) => p,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            let mid = match resolve::resolve_module(conn, &module, pid) {
+            let mid = match module_or_context(conn, module.as_deref(), pid) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
+            };
+            feature::add(
+                conn,
+                mid,
+                &name,
+                &description.unwrap_or_default(),
+                &details.unwrap_or_default(),
+                start.as_ref(),
+                priority,
+                json,
+            )
+        }
+
+        FeatureAction::Clone {
+            feature: f,
+            module,
+            to_module,
+            to_project,
+            name,
+            with_research_links,
+        } => {
+            let fid = match resolve_feature_scoped(conn, &f, module.as_deref()) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            let resolver = resolve::Resolver::new(conn);
+            let mid = match to_project
+                .as_deref()
+                .map(|p| resolver.project(p))
+                .transpose()
+                .and_then(|pid| resolver.module(&to_module, pid))
+                .and_then(|mid| {
+                    resolve::check_archived(conn, "module", mid)?;
+                    Ok(mid)
+                }) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
             };
-            feature::add(conn, mid, &name, &description.unwrap_or_default(), json)
+            feature::clone(&resolver, fid, mid, &name, with_research_links, json)
         }
 
         FeatureAction::List {
             module,
             project,
             state,
+            priority,
+            fields,
+            sort,
+            limit,
+            offset,
         } => {
+            let priority = match parse_priority(priority.as_deref()) {
+                Ok(p) => p,
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
+            let (fields, sort) =
+                match list_view("feature", feature::COLUMNS, feature::COLUMNS, fields, sort) {
+                    Ok(v) => v,
+                    Err(e) => return output::fail(&e),
+                };
+
             let pid = match resolve_optional_project(conn, project.as_deref()) {
                 Ok(p) => p,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            let mid = match resolve::resolve_module(conn, &module, pid) {
+            let mid = match module_or_context(conn, module.as_deref(), pid) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            feature::list(conn, mid, state.as_deref(), json)
+            feature::list(
+                conn,
+                mid,
+                state.as_deref(),
+                priority,
+                fields.as_deref(),
+                sort,
+                fields::Page { limit, offset },
+                json,
+            )
         }
 
         FeatureAction::Show {
             feature,
             module,
-            project: _,
+            project,
+            with_ancestors,
+            with_history,
         } => {
-            let mid = match resolve_optional_module(conn, module.as_deref()) {
-                Ok(m) => m,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
-            };
-            let fid = match resolve::resolve_feature(conn, &feature, mid) {
-                Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
-            };
-            feature::show(conn, fid, json)
+            let fid =
+                match resolve_feature_within(conn, &feature, module.as_deref(), project.as_deref())
+                {
+                    Ok(i) => i,
+                    Err(e) => return output::fail(&e),
+                };
+            feature::show(conn, fid, with_ancestors, with_history, json)
         }
 
         FeatureAction::Rename {
             feature,
             module,
             new_name,
+            update_references,
+            dry_run,
         } => {
-            let mid = match resolve_optional_module(conn, module.as_deref()) {
-                Ok(m) => m,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
-            };
-            let fid = match resolve::resolve_feature(conn, &feature, mid) {
+            let fid = match resolve_feature_scoped(conn, &feature, module.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            feature::rename(conn, fid, &new_name, json)
+            feature::rename(conn, fid, &new_name, update_references, dry_run, json)
         }
 
         FeatureAction::SetDescription {
             feature,
             module,
             description,
+            file,
         } => {
-            let mid = match resolve_optional_module(conn, module.as_deref()) {
-                Ok(m) => m,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+            let description = match text_input(description, file) {
+                Ok(t) => t,
+                Err(e) => return output::fail(&e),
             };
-            let fid = match resolve::resolve_feature(conn, &feature, mid) {
+            let fid = match resolve_feature_scoped(conn, &feature, module.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             feature::set_description(conn, fid, &description, json)
         }
 
+        FeatureAction::SetPriority {
+            feature,
+            module,
+            priority,
+        } => {
+            let priority = match priority.parse::<priority::Priority>() {
+                Ok(p) => p,
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
+            let fid = match resolve_feature_scoped(conn, &feature, module.as_deref()) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            feature::set_priority(conn, fid, priority, json)
+        }
+
         FeatureAction::SetDetails {
             feature,
             module,
             details,
+            file,
+            append,
+            prepend,
         } => {
-            let mid = match resolve_optional_module(conn, module.as_deref()) {
-                Ok(m) => m,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+            let details = match text_input(details, file) {
+                Ok(t) => t,
+                Err(e) => return output::fail(&e),
             };
-            let fid = match resolve::resolve_feature(conn, &feature, mid) {
+            let fid = match resolve_feature_scoped(conn, &feature, module.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
+            };
+            feature::set_details(
+                conn,
+                fid,
+                &details,
+                Placement::from_flags(append, prepend),
+                json,
+            )
+        }
+
+        FeatureAction::ExtractTodos {
+            feature,
+            module,
+            apply,
+        } => {
+            let fid = match resolve_feature_scoped(conn, &feature, module.as_deref()) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
             };
-            feature::set_details(conn, fid, &details, json)
+            models::todos::extract(conn, fid, apply, json)
         }
 
         FeatureAction::Transition {
             feature,
             module,
             state,
+            lift_parents,
+            reason,
+            force,
         } => {
             let to_state = match state.parse::<state::State>() {
                 Ok(s) => s,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&Error::Invalid(e)),
             };
-            let mid = match resolve_optional_module(conn, module.as_deref()) {
-                Ok(m) => m,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+            let parents = match lift::policy(lift_parents) {
+                Ok(p) => p,
+                Err(e) => return output::fail(&Error::Invalid(e)),
             };
-            let fid = match resolve::resolve_feature(conn, &feature, mid) {
+            let fid = match resolve_feature_scoped(conn, &feature, module.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
+            };
+            feature::transition(
+                conn,
+                fid,
+                &to_state,
+                parents,
+                reason.as_deref(),
+                force,
+                json,
+            )
+        }
+
+        FeatureAction::TransitionAll {
+            module,
+            project,
+            state,
+            lift_parents,
+            reason,
+            strict,
+        } => {
+            let (to_state, parents) = match state
+                .parse::<state::State>()
+                .and_then(|s| lift::policy(lift_parents).map(|p| (s, p)))
+            {
+                Ok(v) => v,
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
+            let resolver = resolve::Resolver::new(conn);
+            match project
+                .as_deref()
+                .map(|p| resolver.project(p))
+                .transpose()
+                .and_then(|pid| resolver.module(&module, pid))
+            {
+                Ok(mid) => models::bulk::transition_all(
+                    conn,
+                    "feature",
+                    mid,
+                    &to_state,
+                    parents,
+                    reason.as_deref(),
+                    strict,
+                    json,
+                ),
+                Err(e) => output::fail(&e),
+            }
+        }
+
+        FeatureAction::Lock { feature, module } => {
+            let fid = match resolve_feature_scoped(conn, &feature, module.as_deref()) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            feature::set_locked(conn, fid, true, json)
+        }
+
+        FeatureAction::Unlock { feature, module } => {
+            let fid = match resolve_feature_scoped(conn, &feature, module.as_deref()) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
             };
-            feature::transition(conn, fid, &to_state, json)
+            feature::set_locked(conn, fid, false, json)
         }
 
         FeatureAction::Remove {
             feature,
             module,
             cascade,
+            relink_research_to_parent,
+            soft,
+            hard,
         } => {
-            let mid = match resolve_optional_module(conn, module.as_deref()) {
-                Ok(m) => m,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
-            };
-            let fid = match resolve::resolve_feature(conn, &feature, mid) {
+            let fid = match resolve_feature_scoped(conn, &feature, module.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            feature::remove(conn, fid, cascade, json)
+            feature::remove(
+                conn,
+                fid,
+                cascade,
+                relink_research_to_parent,
+                trash::soft(soft, hard),
+                json,
+            )
+        }
+
+        FeatureAction::LinkResearch {
+            feature,
+            module,
+            research,
+            force,
+        } => {
+            let id = resolve_feature_scoped(conn, &feature, module.as_deref());
+            handle_entity_research(
+                conn,
+                "feature",
+                id,
+                EntityResearch::Link(research, force),
+                json,
+            )
+        }
+
+        FeatureAction::UnlinkResearch {
+            feature,
+            module,
+            research,
+        } => {
+            let id = resolve_feature_scoped(conn, &feature, module.as_deref());
+            handle_entity_research(conn, "feature", id, EntityResearch::Unlink(research), json)
+        }
+
+        FeatureAction::ListResearch {
+            feature,
+            module,
+            include_archived,
+        } => {
+            let id = resolve_feature_scoped(conn, &feature, module.as_deref());
+            handle_entity_research(
+                conn,
+                "feature",
+                id,
+                EntityResearch::List(include_archived),
+                json,
+            )
+        }
+
+        FeatureAction::NameHistory { feature, module } => {
+            match resolve_feature_scoped(conn, &feature, module.as_deref()) {
+                Ok(id) => history::show(conn, "feature", id, json),
+                Err(e) => output::fail(&e),
+            }
+        }
+        FeatureAction::History { feature, module } => {
+            match resolve_feature_scoped(conn, &feature, module.as_deref()) {
+                Ok(id) => history::state_history(conn, "feature", id, json),
+                Err(e) => output::fail(&e),
+            }
         }
     }
 }
@@ -1079,172 +3380,356 @@ fn handle_feature(conn: &rusqlite::Connection, action: FeatureAction, json: bool
 // ── Task handler ──────────────────────────────────────────────────────────────
 
 fn handle_task(conn: &rusqlite::Connection, action: TaskAction, json: bool) -> i32 {
-    use models::task;
+    use models::{history, lift, task, trash};
     match action {
         TaskAction::Add {
             feature,
-            module: _,
+            module,
             name,
             description,
+            plan,
+            start,
+            state,
+            details,
+            before,
+            after,
+            priority,
         } => {
-            let fid = match resolve::resolve_feature(conn, &feature, None) {
+            let start = match start_state(plan, start, state.as_deref()) {
+                Ok(s) => s,
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
+            let priority = match parse_priority(priority.as_deref()) {
+                Ok(p) => p.unwrap_or(priority::Priority::Medium),
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
+            let fid = match feature_or_context(conn, feature.as_deref(), module.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
+            };
+            let anchor = match (before, after) {
+                (Some(t), _) => resolve::resolve_task(conn, &t, Some(fid))
+                    .map(|id| Some(task::Anchor::Before(id))),
+                (_, Some(t)) => resolve::resolve_task(conn, &t, Some(fid))
+                    .map(|id| Some(task::Anchor::After(id))),
+                _ => Ok(None),
             };
-            task::add(conn, fid, &name, &description.unwrap_or_default(), json)
+            let anchor = match anchor {
+                Ok(a) => a,
+                Err(e) => return output::fail(&e),
+            };
+            task::add(
+                conn,
+                fid,
+                &name,
+                &description.unwrap_or_default(),
+                &details.unwrap_or_default(),
+                start.as_ref(),
+                anchor,
+                priority,
+                json,
+            )
         }
 
-        TaskAction::List {
+        TaskAction::Reorder {
             feature,
-            module: _,
-            state,
+            module,
+            tasks,
         } => {
-            let fid = match resolve::resolve_feature(conn, &feature, None) {
+            let fid = match feature_or_context(conn, feature.as_deref(), module.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            task::list(conn, fid, state.as_deref(), json)
+            let mut ids = Vec::new();
+            for t in &tasks {
+                match resolve::resolve_task(conn, t, Some(fid)) {
+                    Ok(id) => ids.push(id),
+                    Err(e) => return output::fail(&e),
+                }
+            }
+            task::reorder(conn, fid, &ids, json)
         }
 
-        TaskAction::Show {
-            task,
+        TaskAction::List {
             feature,
-            module: _,
+            module,
+            state,
+            ready,
+            priority,
+            fields,
+            sort,
+            limit,
+            offset,
         } => {
-            let fid = match resolve_optional_feature(conn, feature.as_deref()) {
-                Ok(f) => f,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+            let priority = match parse_priority(priority.as_deref()) {
+                Ok(p) => p,
+                Err(e) => return output::fail(&Error::Invalid(e)),
             };
-            let tid = match resolve::resolve_task(conn, &task, fid) {
+            let (fields, sort) = match list_view("task", task::COLUMNS, task::COLUMNS, fields, sort)
+            {
+                Ok(v) => v,
+                Err(e) => return output::fail(&e),
+            };
+
+            let fid = match feature_or_context(conn, feature.as_deref(), module.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            task::show(conn, tid, json)
+            task::list(
+                conn,
+                fid,
+                state.as_deref(),
+                priority,
+                ready,
+                fields.as_deref(),
+                sort,
+                fields::Page { limit, offset },
+                json,
+            )
         }
 
-        TaskAction::Rename {
+        TaskAction::Show {
             task,
             feature,
-            new_name,
+            module,
+            with_ancestors,
+            with_history,
         } => {
-            let fid = match resolve_optional_feature(conn, feature.as_deref()) {
-                Ok(f) => f,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+            let tid = match resolve_task_within(conn, &task, feature.as_deref(), module.as_deref())
+            {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
             };
-            let tid = match resolve::resolve_task(conn, &task, fid) {
+            task::show(conn, tid, with_ancestors, with_history, json)
+        }
+
+        TaskAction::Rename {
+            task,
+            feature,
+            new_name,
+            update_references,
+            dry_run,
+        } => {
+            let tid = match resolve_task_scoped(conn, &task, feature.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            task::rename(conn, tid, &new_name, json)
+            task::rename(conn, tid, &new_name, update_references, dry_run, json)
         }
 
         TaskAction::SetDescription {
             task,
             feature,
             description,
+            file,
         } => {
-            let fid = match resolve_optional_feature(conn, feature.as_deref()) {
-                Ok(f) => f,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+            let description = match text_input(description, file) {
+                Ok(t) => t,
+                Err(e) => return output::fail(&e),
             };
-            let tid = match resolve::resolve_task(conn, &task, fid) {
+            let tid = match resolve_task_scoped(conn, &task, feature.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             task::set_description(conn, tid, &description, json)
         }
 
+        TaskAction::SetPriority {
+            task,
+            feature,
+            priority,
+        } => {
+            let priority = match priority.parse::<priority::Priority>() {
+                Ok(p) => p,
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
+            let tid = match resolve_task_scoped(conn, &task, feature.as_deref()) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            task::set_priority(conn, tid, priority, json)
+        }
+
         TaskAction::SetDetails {
             task,
             feature,
             details,
+            file,
+            append,
+            prepend,
         } => {
-            let fid = match resolve_optional_feature(conn, feature.as_deref()) {
-                Ok(f) => f,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+            let details = match text_input(details, file) {
+                Ok(t) => t,
+                Err(e) => return output::fail(&e),
             };
-            let tid = match resolve::resolve_task(conn, &task, fid) {
+            let tid = match resolve_task_scoped(conn, &task, feature.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            task::set_details(conn, tid, &details, json)
+            task::set_details(
+                conn,
+                tid,
+                &details,
+                Placement::from_flags(append, prepend),
+                json,
+            )
         }
 
         TaskAction::Transition {
             task,
             feature,
             state,
+            lift_parents,
+            reason,
+            force,
         } => {
             let to_state = match state.parse::<state::State>() {
                 Ok(s) => s,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&Error::Invalid(e)),
             };
-            let fid = match resolve_optional_feature(conn, feature.as_deref()) {
-                Ok(f) => f,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+            let parents = match lift::policy(lift_parents) {
+                Ok(p) => p,
+                Err(e) => return output::fail(&Error::Invalid(e)),
             };
-            let tid = match resolve::resolve_task(conn, &task, fid) {
+            let tid = match resolve_task_scoped(conn, &task, feature.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            task::transition(conn, tid, &to_state, json)
+            task::transition(
+                conn,
+                tid,
+                &to_state,
+                parents,
+                reason.as_deref(),
+                force,
+                json,
+            )
         }
 
-        TaskAction::Remove { task, feature } => {
-            let fid = match resolve_optional_feature(conn, feature.as_deref()) {
-                Ok(f) => f,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+        TaskAction::TransitionAll {
+            feature,
+            module,
+            state,
+            lift_parents,
+            reason,
+            strict,
+        } => {
+            let (to_state, parents) = match state
+                .parse::<state::State>()
+                .and_then(|s| lift::policy(lift_parents).map(|p| (s, p)))
+            {
+                Ok(v) => v,
+                Err(e) => return output::fail(&Error::Invalid(e)),
+            };
+            // With --module, the module and then the feature in it are
+            // looked up through one resolver; without, the `use` context may
+            // settle an ambiguous name.
+            let resolver = resolve::Resolver::new(conn);
+            let fid = match module.as_deref() {
+                Some(m) => resolver
+                    .module(m, None)
+                    .and_then(|mid| resolver.feature(&feature, Some(mid))),
+                None => resolve_feature_scoped(conn, &feature, None),
             };
-            let tid = match resolve::resolve_task(conn, &task, fid) {
+            match fid {
+                Ok(fid) => models::bulk::transition_all(
+                    conn,
+                    "task",
+                    fid,
+                    &to_state,
+                    parents,
+                    reason.as_deref(),
+                    strict,
+                    json,
+                ),
+                Err(e) => output::fail(&e),
+            }
+        }
+
+        TaskAction::Depend {
+            task,
+            feature,
+            on,
+            on_feature,
+        } => match resolve_dependency(conn, &task, feature, &on, on_feature) {
+            Ok((tid, oid)) => models::dependency::depend(conn, tid, oid, json),
+            Err(e) => output::fail(&e),
+        },
+
+        TaskAction::Undepend {
+            task,
+            feature,
+            on,
+            on_feature,
+        } => match resolve_dependency(conn, &task, feature, &on, on_feature) {
+            Ok((tid, oid)) => models::dependency::undepend(conn, tid, oid, json),
+            Err(e) => output::fail(&e),
+        },
+
+        TaskAction::Remove {
+            task,
+            feature,
+            soft,
+            hard,
+        } => {
+            let tid = match resolve_task_scoped(conn, &task, feature.as_deref()) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            task::remove(conn, tid, json)
+            task::remove(conn, tid, trash::soft(soft, hard), json)
+        }
+
+        TaskAction::LinkResearch {
+            task,
+            feature,
+            research,
+            force,
+        } => {
+            let id = resolve_task_scoped(conn, &task, feature.as_deref());
+            handle_entity_research(
+                conn,
+                "task",
+                id,
+                EntityResearch::Link(research, force),
+                json,
+            )
+        }
+
+        TaskAction::UnlinkResearch {
+            task,
+            feature,
+            research,
+        } => {
+            let id = resolve_task_scoped(conn, &task, feature.as_deref());
+            handle_entity_research(conn, "task", id, EntityResearch::Unlink(research), json)
+        }
+
+        TaskAction::ListResearch {
+            task,
+            feature,
+            include_archived,
+        } => {
+            let id = resolve_task_scoped(conn, &task, feature.as_deref());
+            handle_entity_research(
+                conn,
+                "task",
+                id,
+                EntityResearch::List(include_archived),
+                json,
+            )
+        }
+
+        TaskAction::NameHistory { task, feature } => {
+            match resolve_task_scoped(conn, &task, feature.as_deref()) {
+                Ok(id) => history::show(conn, "task", id, json),
+                Err(e) => output::fail(&e),
+            }
+        }
+        TaskAction::History { task, feature } => {
+            match resolve_task_scoped(conn, &task, feature.as_deref()) {
+                Ok(id) => history::state_history(conn, "task", id, json),
+                Err(e) => output::fail(&e),
+            }
         }
     }
 }
@@ -1252,49 +3737,127 @@ fn handle_task(conn: &rusqlite::Connection, action: TaskAction, json: bool) -> i
 // ── Research handler ──────────────────────────────────────────────────────────
 
 fn handle_research(conn: &rusqlite::Connection, action: ResearchAction, json: bool) -> i32 {
-    use models::research;
+    use models::{attachment, history, research, trash};
     match action {
         ResearchAction::Add { name, description } => {
             research::add(conn, &name, &description.unwrap_or_default(), json)
         }
 
-        ResearchAction::List { stale_days } => research::list(conn, stale_days, json),
+        ResearchAction::Capture {
+            task,
+            feature,
+            name,
+            content,
+            also_feature,
+            append,
+        } => {
+            let tid = match resolve_task_scoped(conn, &task, feature.as_deref()) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            research::capture(conn, tid, &name, &content, also_feature, append, json)
+        }
+
+        ResearchAction::ImportDir {
+            dir,
+            recursive,
+            update,
+        } => models::notes::import_dir(conn, &dir, recursive, update, json),
 
-        ResearchAction::Show { research: r } => {
+        ResearchAction::List {
+            stale_days,
+            stale,
+            min_links,
+            unlinked,
+            group_by,
+            buckets,
+            include_archived,
+            fields,
+            sort,
+            limit,
+            offset,
+        } => {
+            let (fields, sort) = match list_view(
+                "research",
+                research::LIST_COLUMNS,
+                research::SORTABLE,
+                fields,
+                sort,
+            ) {
+                Ok(v) => v,
+                Err(e) => return output::fail(&e),
+            };
+            let threshold = research::stale_threshold(stale_days);
+            let filter = (stale || stale_days.is_some()).then_some(threshold);
+            let buckets = match group_by.as_deref() {
+                None => None,
+                Some("staleness") => match research::staleness_buckets(buckets.as_deref()) {
+                    Ok(b) => Some(b),
+                    Err(e) => return output::fail(&Error::Invalid(e)),
+                },
+                Some(other) => {
+                    return output::fail(&Error::Invalid(format!(
+                        "cannot group research by {}: only staleness is supported",
+                        other
+                    )));
+                }
+            };
+            research::list(
+                conn,
+                filter,
+                threshold,
+                min_links,
+                unlinked,
+                buckets,
+                include_archived,
+                fields.as_deref(),
+                sort,
+                fields::Page { limit, offset },
+                json,
+            )
+        }
+
+        ResearchAction::Show {
+            research: r,
+            timeline,
+            section,
+            links_limit,
+        } => {
             let rid = match resolve::resolve_research(conn, &r) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            research::show(conn, rid, json)
+            match section {
+                Some(name) => research::show_section(conn, rid, &name, json),
+                None => research::show(conn, rid, timeline, links_limit, json),
+            }
         }
 
         ResearchAction::Rename {
             research: r,
             new_name,
+            update_references,
+            dry_run,
         } => {
             let rid = match resolve::resolve_research(conn, &r) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            research::rename(conn, rid, &new_name, json)
+            research::rename(conn, rid, &new_name, update_references, dry_run, json)
         }
 
         ResearchAction::SetDescription {
             research: r,
             description,
+            file,
         } => {
+            let description = match text_input(description, file) {
+                Ok(t) => t,
+                Err(e) => return output::fail(&e),
+            };
             let rid = match resolve::resolve_research(conn, &r) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             research::set_description(conn, rid, &description, json)
         }
@@ -1302,16 +3865,66 @@ fn handle_research(conn: &rusqlite::Connection, action: ResearchAction, json: bo
         ResearchAction::SetContent {
             research: r,
             content,
+            file,
             no_update_date,
+            append,
+            prepend,
         } => {
+            let content = match text_input(content, file) {
+                Ok(t) => t,
+                Err(e) => return output::fail(&e),
+            };
             let rid = match resolve::resolve_research(conn, &r) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
+                Err(e) => return output::fail(&e),
+            };
+            research::set_content(
+                conn,
+                rid,
+                &content,
+                Placement::from_flags(append, prepend),
+                !no_update_date,
+                json,
+            )
+        }
+
+        ResearchAction::SetSection {
+            research: r,
+            section,
+            content,
+            file,
+            remove_section,
+            no_update_date,
+        } => {
+            let text = if remove_section {
+                None
+            } else {
+                match text_input(content, file) {
+                    Ok(t) => Some(t),
+                    Err(e) => return output::fail(&e),
                 }
             };
-            research::set_content(conn, rid, &content, !no_update_date, json)
+            let rid = match resolve::resolve_research(conn, &r) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            research::set_section(conn, rid, &section, text.as_deref(), !no_update_date, json)
+        }
+
+        ResearchAction::Archive { research: r } => {
+            let rid = match resolve::resolve_research(conn, &r) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            research::set_archived(conn, rid, true, json)
+        }
+
+        ResearchAction::Unarchive { research: r } => {
+            let rid = match resolve::resolve_research(conn, &r) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            research::set_archived(conn, rid, false, json)
         }
 
         ResearchAction::SetSource {
@@ -1320,10 +3933,7 @@ fn handle_research(conn: &rusqlite::Connection, action: ResearchAction, json: bo
         } => {
             let rid = match resolve::resolve_research(conn, &r) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             research::set_source(conn, rid, &source, json)
         }
@@ -1331,16 +3941,81 @@ fn handle_research(conn: &rusqlite::Connection, action: ResearchAction, json: bo
         ResearchAction::SetResearchedAt { research: r, date } => {
             let rid = match resolve::resolve_research(conn, &r) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             research::set_researched_at(conn, rid, &date, json)
         }
 
-        ResearchAction::Search { term, stale_days } => {
-            research::search(conn, &term, stale_days, json)
+        ResearchAction::Search {
+            term,
+            linked_to,
+            stale_days,
+            include_former_names,
+            deep,
+            include_archived,
+            limit,
+            offset,
+        } => research::search(
+            conn,
+            term.as_deref(),
+            linked_to.as_deref(),
+            stale_days,
+            include_former_names,
+            deep,
+            include_archived,
+            fields::Page { limit, offset },
+            json,
+        ),
+
+        ResearchAction::Bibliography { project, format } => {
+            let pid = match project_or_context(conn, project.as_deref()) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            models::bibliography::bibliography(conn, pid, &format, json)
+        }
+
+        ResearchAction::Select {
+            query,
+            max_chars,
+            project,
+            module,
+            feature,
+            task,
+            include_archived,
+        } => {
+            let count = [&project, &module, &feature, &task]
+                .iter()
+                .filter(|x| x.is_some())
+                .count();
+            if count > 1 {
+                return output::fail(&Error::Invalid(
+                    "at most one of --project, --module, --feature, --task may be provided".into(),
+                ));
+            }
+            let scope = if let Some(p) = project {
+                resolve::resolve_project(conn, &p).map(|id| Some(("project", id)))
+            } else if let Some(m) = module {
+                resolve::resolve_module(conn, &m, None).map(|id| Some(("module", id)))
+            } else if let Some(f) = feature {
+                resolve::resolve_feature(conn, &f, None).map(|id| Some(("feature", id)))
+            } else if let Some(t) = task {
+                resolve::resolve_task(conn, &t, None).map(|id| Some(("task", id)))
+            } else {
+                Ok(None)
+            };
+            let scope = match scope {
+                Ok(s) => s,
+                Err(e) => return output::fail(&e),
+            };
+            models::select::select(
+                conn,
+                &query,
+                max_chars as usize,
+                scope,
+                include_archived,
+                json,
+            )
         }
 
         ResearchAction::Link {
@@ -1349,60 +4024,45 @@ fn handle_research(conn: &rusqlite::Connection, action: ResearchAction, json: bo
             module,
             feature,
             task,
+            force,
         } => {
             let rid = match resolve::resolve_research(conn, &r) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             let count = [&project, &module, &feature, &task]
                 .iter()
                 .filter(|x| x.is_some())
                 .count();
             if count != 1 {
-                output::err(
-                    "exactly one of --project, --module, --feature, --task must be provided",
-                );
-                return 1;
+                return output::fail(&Error::Invalid(
+                    "exactly one of --project, --module, --feature, --task must be provided".into(),
+                ));
             }
             if let Some(p) = project {
                 let pid = match resolve::resolve_project(conn, &p) {
                     Ok(i) => i,
-                    Err(e) => {
-                        output::err(&e);
-                        return 1;
-                    }
+                    Err(e) => return output::fail(&e),
                 };
-                research::link_project(conn, rid, pid, json)
+                research::link(conn, "project", rid, pid, force, json)
             } else if let Some(m) = module {
                 let mid = match resolve::resolve_module(conn, &m, None) {
                     Ok(i) => i,
-                    Err(e) => {
-                        output::err(&e);
-                        return 1;
-                    }
+                    Err(e) => return output::fail(&e),
                 };
-                research::link_module(conn, rid, mid, json)
+                research::link(conn, "module", rid, mid, force, json)
             } else if let Some(f) = feature {
                 let fid = match resolve::resolve_feature(conn, &f, None) {
                     Ok(i) => i,
-                    Err(e) => {
-                        output::err(&e);
-                        return 1;
-                    }
+                    Err(e) => return output::fail(&e),
                 };
-                research::link_feature(conn, rid, fid, json)
+                research::link(conn, "feature", rid, fid, force, json)
             } else if let Some(t) = task {
                 let tid = match resolve::resolve_task(conn, &t, None) {
                     Ok(i) => i,
-                    Err(e) => {
-                        output::err(&e);
-                        return 1;
-                    }
+                    Err(e) => return output::fail(&e),
                 };
-                research::link_task(conn, rid, tid, json)
+                research::link(conn, "task", rid, tid, force, json)
             } else {
                 1
             }
@@ -1417,92 +4077,154 @@ fn handle_research(conn: &rusqlite::Connection, action: ResearchAction, json: bo
         } => {
             let rid = match resolve::resolve_research(conn, &r) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
             let count = [&project, &module, &feature, &task]
                 .iter()
                 .filter(|x| x.is_some())
                 .count();
             if count != 1 {
-                output::err(
-                    "exactly one of --project, --module, --feature, --task must be provided",
-                );
-                return 1;
+                return output::fail(&Error::Invalid(
+                    "exactly one of --project, --module, --feature, --task must be provided".into(),
+                ));
             }
             if let Some(p) = project {
                 let pid = match resolve::resolve_project(conn, &p) {
                     Ok(i) => i,
-                    Err(e) => {
-                        output::err(&e);
-                        return 1;
-                    }
+                    Err(e) => return output::fail(&e),
                 };
-                research::unlink_project(conn, rid, pid, json)
+                research::unlink(conn, "project", rid, pid, json)
             } else if let Some(m) = module {
                 let mid = match resolve::resolve_module(conn, &m, None) {
                     Ok(i) => i,
-                    Err(e) => {
-                        output::err(&e);
-                        return 1;
-                    }
+                    Err(e) => return output::fail(&e),
                 };
-                research::unlink_module(conn, rid, mid, json)
+                research::unlink(conn, "module", rid, mid, json)
             } else if let Some(f) = feature {
                 let fid = match resolve::resolve_feature(conn, &f, None) {
                     Ok(i) => i,
-                    Err(e) => {
-                        output::err(&e);
-                        return 1;
-                    }
+                    Err(e) => return output::fail(&e),
                 };
-                research::unlink_feature(conn, rid, fid, json)
+                research::unlink(conn, "feature", rid, fid, json)
             } else if let Some(t) = task {
                 let tid = match resolve::resolve_task(conn, &t, None) {
                     Ok(i) => i,
-                    Err(e) => {
-                        output::err(&e);
-                        return 1;
-                    }
+                    Err(e) => return output::fail(&e),
                 };
-                research::unlink_task(conn, rid, tid, json)
+                research::unlink(conn, "task", rid, tid, json)
             } else {
                 1
             }
         }
 
-        ResearchAction::Links { research: r } => {
+        ResearchAction::Links {
+            research: r,
+            dedupe,
+            kind,
+            limit,
+            offset,
+        } => {
             let rid = match resolve::resolve_research(conn, &r) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
             };
-            research::links(conn, rid, json)
+            research::links(conn, rid, dedupe, kind.as_deref(), limit, offset, json)
         }
 
-        ResearchAction::Remove { research: r } => {
+        ResearchAction::NormalizeLinks { research: r, yes } => {
             let rid = match resolve::resolve_research(conn, &r) {
                 Ok(i) => i,
-                Err(e) => {
-                    output::err(&e);
-                    return 1;
-                }
+                Err(e) => return output::fail(&e),
+            };
+            research::normalize_links(conn, rid, yes, json)
+        }
+
+        ResearchAction::Attach {
+            research: r,
+            path,
+            copy,
+        } => {
+            let rid = match resolve::resolve_research(conn, &r) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
             };
-            research::remove(conn, rid, json)
+            attachment::attach(conn, rid, &path, copy, json)
+        }
+
+        ResearchAction::Attachments { research: r } => {
+            let rid = match resolve::resolve_research(conn, &r) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            attachment::list(conn, rid, json)
+        }
+
+        ResearchAction::Remove {
+            research: r,
+            soft,
+            hard,
+        } => {
+            let rid = match resolve::resolve_research(conn, &r) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            research::remove(conn, rid, trash::soft(soft, hard), json)
+        }
+
+        ResearchAction::NameHistory { research } => {
+            match resolve::resolve_research(conn, &research) {
+                Ok(id) => history::show(conn, "research", id, json),
+                Err(e) => output::fail(&e),
+            }
         }
     }
 }
 
+// ── Entity-side research links ────────────────────────────────────────────────
+
+/// The link-research, unlink-research, and list-research subcommands shared by
+/// project, module, feature, and task.
+enum EntityResearch {
+    /// With --force
+    Link(String, bool),
+    Unlink(String),
+    /// With archived research too
+    List(bool),
+}
+
+/// Run an entity-side research subcommand. The entity is resolved first, with
+/// its own disambiguation flags, and only then the research.
+fn handle_entity_research(
+    conn: &rusqlite::Connection,
+    kind: &'static str,
+    entity: Result<i64, Error>,
+    op: EntityResearch,
+    json: bool,
+) -> i32 {
+    let id = match entity {
+        Ok(i) => i,
+        Err(e) => return output::fail(&e),
+    };
+    let (research, link, force) = match op {
+        EntityResearch::List(include_archived) => {
+            return models::research::entity_research(conn, kind, id, include_archived, json)
+        }
+        EntityResearch::Link(r, force) => (r, true, force),
+        EntityResearch::Unlink(r) => (r, false, false),
+    };
+    let rid = match resolve::resolve_research(conn, &research) {
+        Ok(i) => i,
+        Err(e) => return output::fail(&e),
+    };
+    models::research::entity_link(conn, kind, id, rid, link, force, json)
+}
+
 // ── Helper resolvers ──────────────────────────────────────────────────────────
 
 fn resolve_optional_project(
     conn: &rusqlite::Connection,
     s: Option<&str>,
-) -> Result<Option<i64>, String> {
+) -> Result<Option<i64>, Error> {
     match s {
         None => Ok(None),
         Some(p) => resolve::resolve_project(conn, p).map(Some),
@@ -1512,20 +4234,427 @@ fn resolve_optional_project(
 fn resolve_optional_module(
     conn: &rusqlite::Connection,
     s: Option<&str>,
-) -> Result<Option<i64>, String> {
+    project_id: Option<i64>,
+) -> Result<Option<i64>, Error> {
     match s {
         None => Ok(None),
-        Some(m) => resolve::resolve_module(conn, m, None).map(Some),
+        Some(m) => resolve::resolve_module(conn, m, project_id).map(Some),
     }
 }
 
-fn resolve_optional_feature(
+/// The project for commands that default to the `use` context: --project when
+/// given, otherwise the context's project.
+fn project_or_context(conn: &rusqlite::Connection, project: Option<&str>) -> Result<i64, Error> {
+    if let Some(p) = project {
+        return resolve::resolve_project(conn, p);
+    }
+    match context::current(conn) {
+        Some(c) => {
+            context::note("project", &c.project);
+            resolve::writable(conn, "project", c.project_id)
+        }
+        None => Err(Error::Invalid(
+            "--project is required when no project is set with `lopen-memory use`".into(),
+        )),
+    }
+}
+
+/// The parent module for feature add/list: --module when given, otherwise the
+/// module from the current `use` context or the current directory's module path.
+fn module_or_context(
     conn: &rusqlite::Connection,
-    s: Option<&str>,
-) -> Result<Option<i64>, String> {
-    match s {
-        None => Ok(None),
-        Some(f) => resolve::resolve_feature(conn, f, None).map(Some),
+    module: Option<&str>,
+    project_id: Option<i64>,
+) -> Result<i64, Error> {
+    if let Some(m) = module {
+        return resolve::resolve_module(conn, m, project_id);
+    }
+    match context::module_scope(conn)? {
+        Some(scope) => {
+            scope.note();
+            resolve::writable(conn, "module", scope.id)
+        }
+        None => Err(Error::Invalid(
+            "--module is required when no module is set with `lopen-memory use` or mapped to the current directory"
+                .into(),
+        )),
+    }
+}
+
+/// The parent feature for task add/list: --feature (narrowed by --module)
+/// when given, otherwise the feature from the current `use` context.
+fn feature_or_context(
+    conn: &rusqlite::Connection,
+    feature: Option<&str>,
+    module: Option<&str>,
+) -> Result<i64, Error> {
+    if let Some(f) = feature {
+        return resolve_feature_scoped(conn, f, module);
+    }
+    match context::current(conn).and_then(|c| c.feature_id.zip(c.feature)) {
+        Some((id, name)) => {
+            context::note("feature", &name);
+            resolve::writable(conn, "feature", id)
+        }
+        None => Err(Error::Invalid(
+            "--feature is required when no feature is set with `lopen-memory use`".into(),
+        )),
+    }
+}
+
+/// Resolve a feature name, narrowed by --module when given. Without --module the
+/// `use` context (or module path) module only steps in when the name alone is ambiguous.
+fn resolve_feature_scoped(
+    conn: &rusqlite::Connection,
+    name: &str,
+    module: Option<&str>,
+) -> Result<i64, Error> {
+    resolve_feature_within(conn, name, module, None)
+}
+
+/// `resolve_feature_scoped` with a --project as well, which narrows --module,
+/// or the feature itself across the project's modules when there is no --module.
+fn resolve_feature_within(
+    conn: &rusqlite::Connection,
+    name: &str,
+    module: Option<&str>,
+    project: Option<&str>,
+) -> Result<i64, Error> {
+    let pid = resolve_optional_project(conn, project)?;
+    let mid = resolve_optional_module(conn, module, pid)?;
+    if mid.is_some() {
+        return resolve::resolve_feature(conn, name, mid);
+    }
+    if let Some(pid) = pid {
+        return resolve::resolve_feature_in_project(conn, name, pid);
+    }
+    match resolve::resolve_feature(conn, name, None) {
+        Err(e @ Error::Ambiguous { .. }) => {
+            let Some(scope) = context::module_scope(conn)? else {
+                return Err(e);
+            };
+            let fid = resolve::resolve_feature(conn, name, Some(scope.id)).map_err(|_| e)?;
+            scope.note();
+            Ok(fid)
+        }
+        r => r,
+    }
+}
+
+/// Resolve a task name, narrowed by --feature when given. Without --feature the
+/// `use` context feature only steps in when the name alone is ambiguous.
+fn resolve_task_scoped(
+    conn: &rusqlite::Connection,
+    name: &str,
+    feature: Option<&str>,
+) -> Result<i64, Error> {
+    resolve_task_within(conn, name, feature, None)
+}
+
+/// `resolve_task_scoped` with a --module as well, which narrows --feature, or
+/// the task itself across the module's features when there is no --feature.
+fn resolve_task_within(
+    conn: &rusqlite::Connection,
+    name: &str,
+    feature: Option<&str>,
+    module: Option<&str>,
+) -> Result<i64, Error> {
+    if let Some(f) = feature {
+        let fid = resolve_feature_scoped(conn, f, module)?;
+        return resolve::resolve_task(conn, name, Some(fid));
+    }
+    if let Some(mid) = resolve_optional_module(conn, module, None)? {
+        return resolve::resolve_task_in_module(conn, name, mid);
+    }
+    match resolve::resolve_task(conn, name, None) {
+        Err(e @ Error::Ambiguous { .. }) => {
+            let Some((cid, cname)) =
+                context::current(conn).and_then(|c| c.feature_id.zip(c.feature))
+            else {
+                return Err(e);
+            };
+            let tid = resolve::resolve_task(conn, name, Some(cid)).map_err(|_| e)?;
+            context::note("feature", &cname);
+            Ok(tid)
+        }
+        r => r,
+    }
+}
+
+/// Map the --plan/--start flags on add actions to the state the new entity should reach.
+/// The text a setter stores: the positional argument, or the contents of
+/// --file, where `-` in either place reads stdin. Kept exactly as read, so
+/// long Markdown can be piped in with its trailing newlines intact.
+fn text_input(text: Option<String>, file: Option<String>) -> Result<String, Error> {
+    let path = match (text, file) {
+        (_, Some(path)) => path,
+        (Some(text), None) if text != "-" => return Ok(text),
+        _ => "-".to_string(),
+    };
+    if path == "-" {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|e| Error::Io(format!("could not read stdin: {}", e)))?;
+        return Ok(text);
+    }
+    std::fs::read_to_string(&path).map_err(|e| Error::Io(format!("could not read {}: {}", path, e)))
+}
+
+/// The two ends of `task depend`/`undepend`. `--on` is looked for in
+/// `--task`'s feature first, so sibling tasks need no --on-feature.
+fn resolve_dependency(
+    conn: &rusqlite::Connection,
+    task: &str,
+    feature: Option<String>,
+    on: &str,
+    on_feature: Option<String>,
+) -> Result<(i64, i64), Error> {
+    let tid = resolve_task_scoped(conn, task, feature.as_deref())?;
+    let oid = match on_feature {
+        Some(_) => resolve_task_scoped(conn, on, on_feature.as_deref())?,
+        None => {
+            let fid: i64 = conn.query_row(
+                "SELECT feature_id FROM tasks WHERE id=?1",
+                rusqlite::params![tid],
+                |r| r.get(0),
+            )?;
+            resolve::resolve_task(conn, on, Some(fid))
+                .or_else(|_| resolve::resolve_task(conn, on, None))?
+        }
+    };
+    Ok((tid, oid))
+}
+
+/// The state a new module, feature, or task starts in, from --plan, --start,
+/// or --state (which clap keeps exclusive). Only Draft, Planning, and Building
+/// are starting states.
+fn start_state(
+    plan: bool,
+    start: bool,
+    state: Option<&str>,
+) -> Result<Option<state::State>, String> {
+    if start {
+        return Ok(Some(state::State::Building));
+    }
+    if plan {
+        return Ok(Some(state::State::Planning));
+    }
+    let Some(s) = state else {
+        return Ok(None);
+    };
+    match s.parse::<state::State>()? {
+        st @ (state::State::Complete | state::State::Amending | state::State::Blocked) => Err(format!(
+            "--state {} is not a starting state; add it as Draft, Planning, or Building and transition it from there",
+            st
+        )),
+        st => Ok(Some(st)),
+    }
+}
+
+/// The priority given to --priority, if any.
+fn parse_priority(priority: Option<&str>) -> Result<Option<priority::Priority>, String> {
+    priority.map(str::parse).transpose()
+}
+
+/// A list's parsed --fields and --sort.
+type ListView = (Option<Vec<&'static str>>, Option<fields::Sort>);
+
+/// --fields and --sort, checked against the `entity`'s `columns` and the
+/// `sortable` ones among them.
+fn list_view(
+    entity: &str,
+    columns: &'static [&'static str],
+    sortable: &'static [&'static str],
+    fields_spec: Option<String>,
+    sort_spec: Option<String>,
+) -> Result<ListView, Error> {
+    let picked = fields_spec
+        .map(|f| fields::parse_fields(entity, columns, &f))
+        .transpose()?;
+    let sort = sort_spec
+        .map(|s| fields::parse_sort(entity, sortable, &s))
+        .transpose()?;
+    Ok((picked, sort))
+}
+
+// ── Db handler ────────────────────────────────────────────────────────────────
+
+fn handle_db(conn: &rusqlite::Connection, path: &str, action: DbAction, json: bool) -> i32 {
+    use models::{admin, backup};
+    match action {
+        DbAction::Stats => admin::stats(conn, path, json),
+
+        DbAction::Maintain { yes } => admin::maintain(conn, yes, json),
+
+        DbAction::Doctor { fix } => admin::doctor(conn, fix, json),
+
+        DbAction::Backup { to, keep } => {
+            backup::backup(conn, path, to.as_deref(), keep.map(|n| n as usize), json)
+        }
+
+        DbAction::Restore { from } => backup::restore(conn, path, &from, json),
+
+        DbAction::Profile { args } => {
+            let argv = std::iter::once("lopen-memory".to_string()).chain(args);
+            let matches = Cli::command()
+                .try_get_matches_from(argv)
+                .unwrap_or_else(|e| e.exit());
+            let inner = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+            if matches!(
+                inner.command,
+                Commands::Db {
+                    action: DbAction::Profile { .. }
+                }
+            ) {
+                return output::fail(&Error::Invalid("db profile cannot profile itself".into()));
+            }
+            resolve::refuse_archived(writes(&command_path(&matches)));
+            profile::start_recording();
+            let code = run(conn, path, inner.command, json || inner.json);
+            profile::report(conn);
+            code
+        }
+    }
+}
+
+// ── Lock handler ──────────────────────────────────────────────────────────────
+
+fn handle_lock(conn: &rusqlite::Connection, action: LockAction, json: bool) -> i32 {
+    use models::locks;
+    match action {
+        LockAction::Acquire { name, ttl, holder } => {
+            locks::acquire(conn, &name, ttl, &holder, json)
+        }
+        LockAction::Release { name, holder } => {
+            locks::release(conn, &name, holder.as_deref(), json)
+        }
+        LockAction::Status { name } => locks::status(conn, name.as_deref(), json),
+    }
+}
+
+// ── Trash handler ─────────────────────────────────────────────────────────────
+
+fn handle_trash(conn: &rusqlite::Connection, action: TrashAction, json: bool) -> i32 {
+    use models::trash;
+    match action {
+        TrashAction::List { kind } => {
+            if let Some(k) = kind.as_deref() {
+                if !models::recent::TYPES.contains(&k) {
+                    return output::fail(&Error::Invalid(format!(
+                        "unknown type '{}'; must be one of: {}",
+                        k,
+                        models::recent::TYPES.join(", ")
+                    )));
+                }
+            }
+            trash::list(conn, kind.as_deref(), json)
+        }
+
+        TrashAction::Restore {
+            project,
+            module,
+            feature,
+            task,
+            research,
+        } => {
+            let (kind, who) = [
+                ("project", project),
+                ("module", module),
+                ("feature", feature),
+                ("task", task),
+                ("research", research),
+            ]
+            .into_iter()
+            .find_map(|(kind, who)| who.map(|w| (kind, w)))
+            .unwrap_or_default();
+            trash::restore(conn, kind, &who, json)
+        }
+
+        TrashAction::Purge { older_than } => trash::purge(conn, older_than, json),
+    }
+}
+
+// ── Share handler ─────────────────────────────────────────────────────────────
+
+fn handle_share(conn: &rusqlite::Connection, action: ShareAction, json: bool) -> i32 {
+    use models::share;
+    match action {
+        ShareAction::Create { project, out } => {
+            match project_or_context(conn, project.as_deref()) {
+                Ok(pid) => share::create(conn, pid, &out, json),
+                Err(e) => output::fail(&e),
+            }
+        }
+
+        ShareAction::Open { file, args } => {
+            let argv = std::iter::once("lopen-memory".to_string()).chain(args);
+            let matches = Cli::command()
+                .try_get_matches_from(argv)
+                .unwrap_or_else(|e| e.exit());
+            let inner = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+            let command = command_path(&matches);
+            if !listed(&READS, &command) {
+                return output::fail(&Error::Invalid(format!(
+                    "share bundles are read-only: `{}` is not a read command; run it against a database instead",
+                    command
+                )));
+            }
+            let (shared, scratch) = match share::open(&file) {
+                Ok(s) => s,
+                Err(e) => return output::fail(&e),
+            };
+            resolve::refuse_archived(false);
+            let code = run(&shared, &file, inner.command, json || inner.json);
+            drop(shared);
+            drop(scratch);
+            code
+        }
+    }
+}
+
+// ── Snapshot handler ──────────────────────────────────────────────────────────
+
+fn handle_snapshot(conn: &rusqlite::Connection, action: SnapshotAction, json: bool) -> i32 {
+    use models::snapshot;
+    match action {
+        SnapshotAction::Create { project, name } => {
+            match project_or_context(conn, project.as_deref()) {
+                Ok(pid) => snapshot::create(conn, pid, &name, json),
+                Err(e) => output::fail(&e),
+            }
+        }
+        SnapshotAction::List { project } => {
+            let pid = match project
+                .as_deref()
+                .map(|p| resolve::resolve_project(conn, p))
+                .transpose()
+            {
+                Ok(p) => p,
+                Err(e) => return output::fail(&e),
+            };
+            snapshot::list(conn, pid, json)
+        }
+        SnapshotAction::Restore {
+            name,
+            replace,
+            as_name,
+        } => snapshot::restore(conn, &name, replace, as_name.as_deref(), json),
+        SnapshotAction::Drop { name } => snapshot::drop(conn, &name, json),
+    }
+}
+
+// ── Analytics handler ─────────────────────────────────────────────────────────
+
+fn handle_analytics(conn: &rusqlite::Connection, action: AnalyticsAction, json: bool) -> i32 {
+    match action {
+        AnalyticsAction::Activity { project, since } => {
+            let pid = match project_or_context(conn, project.as_deref()) {
+                Ok(i) => i,
+                Err(e) => return output::fail(&e),
+            };
+            models::analytics::activity(conn, pid, since.as_deref(), json)
+        }
     }
 }
 
@@ -1534,5 +4663,255 @@ fn resolve_optional_feature(
 fn handle_skill(action: SkillAction, json: bool) -> i32 {
     match action {
         SkillAction::Install { skills_dir } => skill::install(skills_dir.as_ref(), json),
+        SkillAction::Update { skills_dir } => skill::update(skills_dir.as_ref(), json),
+    }
+}
+
+// ── Setup handler ─────────────────────────────────────────────────────────────
+
+fn handle_setup(conn: &rusqlite::Connection, action: SetupAction, json: bool) -> i32 {
+    use models::setup;
+    match action {
+        SetupAction::Export { out, with_db } => setup::export(conn, &out, with_db, json),
+        SetupAction::Apply {
+            file,
+            overwrite,
+            merge,
+            map_path,
+            keep_paths,
+            skills_dir,
+            skip_db,
+        } => {
+            let mut map = Vec::new();
+            for m in &map_path {
+                match m.split_once('=') {
+                    Some((from, to)) if !from.is_empty() => {
+                        map.push((from.trim_end_matches('/').to_string(), to.to_string()))
+                    }
+                    _ => {
+                        return output::fail(&Error::Invalid(format!(
+                            "invalid --map-path '{}'; expected FROM=TO",
+                            m
+                        )));
+                    }
+                }
+            }
+            let on_existing = if overwrite {
+                setup::OnExisting::Overwrite
+            } else if merge {
+                setup::OnExisting::Merge
+            } else {
+                setup::OnExisting::Refuse
+            };
+            setup::apply(
+                conn,
+                &file,
+                on_existing,
+                &map,
+                keep_paths,
+                skills_dir.as_ref(),
+                skip_db,
+                json,
+            )
+        }
+    }
+}
+
+// ── Export handler ────────────────────────────────────────────────────────────
+
+fn handle_export(
+    conn: &rusqlite::Connection,
+    format: &str,
+    out: Option<String>,
+    file: Option<String>,
+    project: Option<String>,
+    json: bool,
+) -> i32 {
+    use models::export;
+    match format {
+        "md" | "markdown" => {
+            if file.is_some() || project.is_some() {
+                return output::fail(&Error::Invalid(
+                    "--file and --project apply to --format json only".into(),
+                ));
+            }
+            let Some(out) = out else {
+                return output::fail(&Error::Invalid("--format md needs --out <DIR>".into()));
+            };
+            export::markdown(conn, &out, json)
+        }
+        "json" => {
+            if out.is_some() {
+                return output::fail(&Error::Invalid(
+                    "--format json writes a file; use --file instead of --out".into(),
+                ));
+            }
+            let project_id = match project.as_deref() {
+                Some(p) => match resolve::resolve_project(conn, p) {
+                    Ok(id) => Some(id),
+                    Err(e) => return output::fail(&e),
+                },
+                None => None,
+            };
+            export::json_dump(conn, project_id, file.as_deref(), json)
+        }
+        _ => output::fail(&Error::Invalid(format!(
+            "unsupported export format: {} (expected md or json)",
+            format
+        ))),
+    }
+}
+
+// ── Progress handler ──────────────────────────────────────────────────────────
+
+fn handle_progress(
+    conn: &rusqlite::Connection,
+    task: Option<&str>,
+    feature: Option<&str>,
+    text: &str,
+    touch_state: bool,
+    lift_parents: bool,
+    json: bool,
+) -> i32 {
+    use models::{lift, task as tasks};
+    let parents = match lift::policy(lift_parents) {
+        Ok(p) => p,
+        Err(e) => return output::fail(&Error::Invalid(e)),
+    };
+    let tid = match task {
+        Some(t) => resolve_task_scoped(conn, t, feature),
+        None => feature_or_context(conn, feature, None).and_then(|f| tasks::current_in(conn, f)),
+    };
+    let tid = match tid {
+        Ok(i) => i,
+        Err(e) => return output::fail(&e),
+    };
+    let touch_state = touch_state || config::get_bool("touch_state", false);
+    tasks::progress(conn, tid, text, touch_state, parents, json)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_next(
+    conn: &rusqlite::Connection,
+    project: Option<&str>,
+    module: Option<&str>,
+    feature: Option<&str>,
+    count: usize,
+    ready: bool,
+    about: Option<&models::next::About>,
+    json: bool,
+) -> i32 {
+    let scope = if let Some(f) = feature {
+        resolve_feature_within(conn, f, module, project).map(|id| ("feature", id))
+    } else if let Some(m) = module {
+        resolve_optional_project(conn, project)
+            .and_then(|pid| resolve::resolve_module(conn, m, pid))
+            .map(|id| ("module", id))
+    } else {
+        project_or_context(conn, project).map(|id| ("project", id))
+    };
+    match scope {
+        Ok(scope) => models::next::next(conn, scope, count, ready, about, json),
+        Err(e) => output::fail(&e),
+    }
+}
+
+// ── Diff handler ──────────────────────────────────────────────────────────────
+
+fn handle_diff(
+    conn: &rusqlite::Connection,
+    given: [Vec<String>; 5],
+    field: &str,
+    context: usize,
+    json: bool,
+) -> i32 {
+    let kinds = ["project", "module", "feature", "task", "research"];
+    let mut sides: Vec<(&'static str, i64)> = Vec::new();
+    for (kind, values) in kinds.into_iter().zip(given) {
+        for v in values {
+            let id = match kind {
+                "project" => resolve::resolve_project(conn, &v),
+                "module" => resolve::resolve_module(conn, &v, None),
+                "feature" => resolve::resolve_feature(conn, &v, None),
+                "task" => resolve::resolve_task(conn, &v, None),
+                _ => resolve::resolve_research(conn, &v),
+            };
+            match id {
+                Ok(i) => sides.push((kind, i)),
+                Err(e) => return output::fail(&e),
+            }
+        }
+    }
+    if sides.len() != 2 {
+        return output::fail(&Error::Invalid(format!(
+            "diff compares exactly two entities; {} given",
+            sides.len()
+        )));
+    }
+    models::diff::diff(conn, sides[0], sides[1], field, context, json)
+}
+
+// ── Validate handler ──────────────────────────────────────────────────────────
+
+fn handle_validate(
+    conn: &rusqlite::Connection,
+    project: Option<String>,
+    building_days: Option<i64>,
+    json: bool,
+) -> i32 {
+    let project_id = match resolve_optional_project(conn, project.as_deref()) {
+        Ok(id) => id,
+        Err(e) => return output::fail(&e),
+    };
+    models::validate::validate(conn, project_id, building_days, json)
+}
+
+// ── Use handler ───────────────────────────────────────────────────────────────
+
+fn handle_use(
+    conn: &rusqlite::Connection,
+    project: Option<String>,
+    module: Option<String>,
+    feature: Option<String>,
+    clear: bool,
+    show: bool,
+    json: bool,
+) -> i32 {
+    if clear {
+        return context::clear(conn, json);
+    }
+    if show {
+        return context::show(conn, json);
+    }
+    // clap guarantees --project when neither --clear nor --show is given.
+    let pid = match resolve::resolve_project(conn, project.as_deref().unwrap_or_default()) {
+        Ok(i) => i,
+        Err(e) => return output::fail(&e),
+    };
+    let mid = match module {
+        Some(m) => match resolve::resolve_module(conn, &m, Some(pid)) {
+            Ok(i) => Some(i),
+            Err(e) => return output::fail(&e),
+        },
+        None => None,
+    };
+    let fid = match feature {
+        Some(f) => match resolve::resolve_feature(conn, &f, mid) {
+            Ok(i) => Some(i),
+            Err(e) => return output::fail(&e),
+        },
+        None => None,
+    };
+    context::set(conn, pid, mid, fid, json)
+}
+
+// ── Config handler ────────────────────────────────────────────────────────────
+
+fn handle_config(action: ConfigAction, json: bool) -> i32 {
+    match action {
+        ConfigAction::List => config::list(json),
+        ConfigAction::Get { key } => config::get(&key, json),
+        ConfigAction::Set { key, value } => config::set(&key, &value, json),
+        ConfigAction::Unset { key } => config::unset(&key, json),
     }
 }
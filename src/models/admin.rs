@@ -0,0 +1,710 @@
+use crate::content;
+use crate::db;
+use crate::error::Error;
+use crate::output;
+use crate::state::STATES;
+use crate::timestamp;
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection};
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+
+const TABLES: [&str; 15] = [
+    "projects",
+    "modules",
+    "features",
+    "tasks",
+    "research",
+    "research_projects",
+    "research_modules",
+    "research_features",
+    "research_tasks",
+    "contexts",
+    "name_history",
+    "research_attachments",
+    "locks",
+    "task_dependencies",
+    "state_history",
+];
+
+fn pragma_i64(conn: &Connection, name: &str) -> i64 {
+    conn.query_row(&format!("PRAGMA {}", name), [], |r| r.get(0))
+        .unwrap_or(0)
+}
+
+pub fn stats(conn: &Connection, path: &str, json: bool) -> i32 {
+    let mut rows: Vec<(&str, i64)> = Vec::new();
+    for table in TABLES {
+        let count: i64 =
+            match conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |r| r.get(0)) {
+                Ok(c) => c,
+                Err(e) => {
+                    output::err(&e.to_string());
+                    return 2;
+                }
+            };
+        rows.push((table, count));
+    }
+    let content_bytes: i64 = conn
+        .query_row(
+            "SELECT COALESCE(SUM(LENGTH(CAST(content AS BLOB))), 0) FROM research",
+            params![],
+            |r| r.get(0),
+        )
+        .unwrap_or(0);
+    let file_bytes = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let page_size = pragma_i64(conn, "page_size");
+    let page_count = pragma_i64(conn, "page_count");
+    let freelist = pragma_i64(conn, "freelist_count");
+    let schema_version = pragma_i64(conn, "user_version");
+
+    if json {
+        let mut counts = Map::new();
+        for (table, count) in &rows {
+            counts.insert(table.to_string(), json!(count));
+        }
+        output::print_json(&json!({
+            "path": path,
+            "file_bytes": file_bytes,
+            "page_size": page_size,
+            "page_count": page_count,
+            "freelist_pages": freelist,
+            "schema_version": schema_version,
+            "research_content_bytes": content_bytes,
+            "rows": Value::Object(counts),
+        }));
+    } else {
+        outln!("{}", output::field("path", path));
+        outln!("{}", output::field("file_bytes", &file_bytes.to_string()));
+        outln!(
+            "{}",
+            output::field("pages", &format!("{} x {} bytes", page_count, page_size))
+        );
+        outln!("{}", output::field("freelist_pages", &freelist.to_string()));
+        outln!(
+            "{}",
+            output::field("schema_version", &schema_version.to_string())
+        );
+        outln!(
+            "{}",
+            output::field("content_bytes", &content_bytes.to_string())
+        );
+        outln!();
+        outln!("rows:");
+        for (table, count) in &rows {
+            outln!("  {:<20} {}", table, count);
+        }
+    }
+    0
+}
+
+/// A research record whose content lives in an external file.
+struct External {
+    id: i64,
+    name: String,
+    path: PathBuf,
+    bytes: Option<u64>,
+}
+
+impl External {
+    fn to_json(&self) -> Value {
+        json!({"id": self.id, "name": self.name, "file": self.path.display().to_string(), "bytes": self.bytes})
+    }
+}
+
+fn externals(conn: &Connection) -> rusqlite::Result<Vec<External>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, content FROM research WHERE substr(content, 1, length(?1))=?1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map(params![content::MARKER], |r| {
+        Ok((
+            r.get::<_, i64>(0)?,
+            r.get::<_, String>(1)?,
+            r.get::<_, String>(2)?,
+        ))
+    })?;
+    let mut found = Vec::new();
+    for row in rows {
+        let (id, name, stored) = row?;
+        if let Some(path) = content::external_file(conn, &stored) {
+            let bytes = fs::metadata(&path).ok().map(|m| m.len());
+            found.push(External {
+                id,
+                name,
+                path,
+                bytes,
+            });
+        }
+    }
+    Ok(found)
+}
+
+/// Files in the content directory that no research record points at, left
+/// behind by records that shrank below the threshold or were removed while
+/// the file could not be deleted.
+fn orphans(conn: &Connection, referenced: &HashSet<PathBuf>) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(content::dir(conn)) else {
+        return Vec::new();
+    };
+    let mut found: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|x| x == "md") && !referenced.contains(p))
+        .collect();
+    found.sort();
+    found
+}
+
+fn confirm(reinline: &[&External], orphaned: &[PathBuf]) -> bool {
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+    for e in reinline {
+        outln!("  re-inline {} ({} bytes)", e.name, e.bytes.unwrap_or(0));
+    }
+    for p in orphaned {
+        outln!("  delete unreferenced {}", p.display());
+    }
+    out!("apply? [y/N] ");
+    std::io::stdout().flush().ok();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok();
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Check external research content: report records whose file is missing,
+/// move bodies that are now at or under the threshold (or every body, when
+/// no threshold is set) back into the database, and delete files nothing
+/// points at. Changes are confirmed like `cleanup`: --yes, a prompt on a
+/// terminal, otherwise only listed. Exits 1 while any file is missing.
+pub fn maintain(conn: &Connection, yes: bool, json: bool) -> i32 {
+    let found = match externals(conn) {
+        Ok(f) => f,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let threshold = content::threshold();
+    let missing: Vec<&External> = found.iter().filter(|e| e.bytes.is_none()).collect();
+    let reinline: Vec<&External> = found
+        .iter()
+        .filter(|e| match (e.bytes, threshold) {
+            (Some(b), Some(t)) => b as usize <= t,
+            (Some(_), None) => true,
+            (None, _) => false,
+        })
+        .collect();
+    let referenced: HashSet<PathBuf> = found.iter().map(|e| e.path.clone()).collect();
+    let orphaned = orphans(conn, &referenced);
+
+    let pending = !reinline.is_empty() || !orphaned.is_empty();
+    let applied = pending && (yes || (!json && confirm(&reinline, &orphaned)));
+    if applied {
+        let tx = match db::write_tx(conn) {
+            Ok(tx) => tx,
+            Err(e) => {
+                output::err(&e.to_string());
+                return 2;
+            }
+        };
+        for e in &reinline {
+            let body = match fs::read_to_string(&e.path) {
+                Ok(b) => b,
+                Err(err) => {
+                    return output::fail(&Error::Io(format!(
+                        "failed to read {}: {}",
+                        e.path.display(),
+                        err
+                    )));
+                }
+            };
+            if let Err(err) = tx.execute(
+                "UPDATE research SET content=?1 WHERE id=?2",
+                params![body, e.id],
+            ) {
+                output::err(&err.to_string());
+                return 2;
+            }
+        }
+        if let Err(e) = tx.commit() {
+            output::err(&e.to_string());
+            return 2;
+        }
+        // Files go only once the database no longer points at them.
+        for p in reinline.iter().map(|e| &e.path).chain(&orphaned) {
+            if let Err(e) = fs::remove_file(p) {
+                eprintln!("warning: could not delete {}: {}", p.display(), e);
+            }
+        }
+    }
+
+    if json {
+        output::print_json(&json!({
+            "external": found.len(),
+            "missing": missing.iter().map(|e| e.to_json()).collect::<Vec<_>>(),
+            "reinline": reinline.iter().map(|e| e.to_json()).collect::<Vec<_>>(),
+            "orphaned": orphaned.iter().map(|p| p.display().to_string()).collect::<Vec<_>>(),
+            "applied": applied,
+        }));
+    } else {
+        output::print_plain(&format!(
+            "{} research record(s) with external content",
+            found.len()
+        ));
+        if applied {
+            output::print_plain(&format!(
+                "re-inlined {} record(s), deleted {} unreferenced file(s)",
+                reinline.len(),
+                orphaned.len()
+            ));
+        } else if pending {
+            output::print_plain(&format!(
+                "would re-inline {} record(s) and delete {} unreferenced file(s)",
+                reinline.len(),
+                orphaned.len()
+            ));
+            for e in &reinline {
+                outln!("  re-inline {} ({} bytes)", e.name, e.bytes.unwrap_or(0));
+            }
+            for p in &orphaned {
+                outln!("  delete unreferenced {}", p.display());
+            }
+            output::print_plain("pass --yes to apply");
+        }
+    }
+    for e in &missing {
+        eprintln!(
+            "warning: research {} (id {}) points at missing file {}; restore it or replace the content with `research set-content`",
+            e.name,
+            e.id,
+            e.path.display()
+        );
+    }
+    if missing.is_empty() {
+        0
+    } else {
+        output::err(&format!(
+            "{} research record(s) point at missing content files",
+            missing.len()
+        ));
+        1
+    }
+}
+
+/// Rows whose parent is gone: the check's id, what it finds, its table, the
+/// columns identifying a row, and the condition picking out its orphans.
+/// Entity tables come first and in hierarchy order, so deleting them lets ON
+/// DELETE CASCADE take their children and links before those are checked.
+const ORPHAN_CHECKS: [(&str, &str, &str, &str, &str); 10] = [
+    (
+        "orphan-modules",
+        "modules without a project",
+        "modules",
+        "id, project_id",
+        "project_id NOT IN (SELECT id FROM projects)",
+    ),
+    (
+        "orphan-features",
+        "features without a module",
+        "features",
+        "id, module_id",
+        "module_id NOT IN (SELECT id FROM modules)",
+    ),
+    (
+        "orphan-tasks",
+        "tasks without a feature",
+        "tasks",
+        "id, feature_id",
+        "feature_id NOT IN (SELECT id FROM features)",
+    ),
+    (
+        "orphan-project-links",
+        "project research links to a missing end",
+        "research_projects",
+        "research_id, project_id",
+        "research_id NOT IN (SELECT id FROM research) OR project_id NOT IN (SELECT id FROM projects)",
+    ),
+    (
+        "orphan-module-links",
+        "module research links to a missing end",
+        "research_modules",
+        "research_id, module_id",
+        "research_id NOT IN (SELECT id FROM research) OR module_id NOT IN (SELECT id FROM modules)",
+    ),
+    (
+        "orphan-feature-links",
+        "feature research links to a missing end",
+        "research_features",
+        "research_id, feature_id",
+        "research_id NOT IN (SELECT id FROM research) OR feature_id NOT IN (SELECT id FROM features)",
+    ),
+    (
+        "orphan-task-links",
+        "task research links to a missing end",
+        "research_tasks",
+        "research_id, task_id",
+        "research_id NOT IN (SELECT id FROM research) OR task_id NOT IN (SELECT id FROM tasks)",
+    ),
+    (
+        "orphan-dependencies",
+        "task dependencies on a missing task",
+        "task_dependencies",
+        "task_id, depends_on_task_id",
+        "task_id NOT IN (SELECT id FROM tasks) OR depends_on_task_id NOT IN (SELECT id FROM tasks)",
+    ),
+    (
+        "orphan-attachments",
+        "attachments of a missing research record",
+        "research_attachments",
+        "id, research_id",
+        "research_id NOT IN (SELECT id FROM research)",
+    ),
+    (
+        "orphan-contexts",
+        "use contexts pointing at a missing entity",
+        "contexts",
+        "cwd, project_id",
+        "project_id NOT IN (SELECT id FROM projects) OR module_id NOT IN (SELECT id FROM modules)
+         OR feature_id NOT IN (SELECT id FROM features)",
+    ),
+];
+
+/// Tables whose names are meant to be unique, ignoring case and normal form,
+/// with the parent column they are unique under.
+const UNIQUE_NAMES: [(&str, Option<&str>); 5] = [
+    ("projects", None),
+    ("modules", Some("project_id")),
+    ("features", Some("module_id")),
+    ("tasks", Some("feature_id")),
+    ("research", None),
+];
+
+/// One problem `doctor` found: its severity (`error` or `warning`), a stable
+/// check id, the rows involved, and what `--fix` does about it, if anything.
+struct Finding {
+    severity: &'static str,
+    check: &'static str,
+    description: String,
+    table: String,
+    rows: Vec<Value>,
+    fix: Option<&'static str>,
+    fixed: bool,
+}
+
+impl Finding {
+    fn new(severity: &'static str, check: &'static str, description: &str, table: &str) -> Finding {
+        Finding {
+            severity,
+            check,
+            description: description.to_string(),
+            table: table.to_string(),
+            rows: Vec::new(),
+            fix: None,
+            fixed: false,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "severity": self.severity,
+            "check": self.check,
+            "description": self.description,
+            "table": self.table,
+            "count": self.rows.len(),
+            "rows": self.rows,
+            "fix": self.fix,
+            "fixed": self.fixed,
+        })
+    }
+
+    /// The first few rows, e.g. `id=4 project_id=9, id=5 project_id=9`.
+    fn sample(&self) -> String {
+        let shown: Vec<String> = self
+            .rows
+            .iter()
+            .take(5)
+            .map(|row| match row {
+                Value::Object(m) => m
+                    .iter()
+                    .map(|(k, v)| {
+                        format!("{}={}", k, v.as_str().map_or(v.to_string(), str::to_string))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            })
+            .collect();
+        let more = if self.rows.len() > shown.len() {
+            ", …"
+        } else {
+            ""
+        };
+        format!("{}{}", shown.join(", "), more)
+    }
+}
+
+/// Every row of `sql`, each as an object of its columns.
+fn rows_of(conn: &Connection, sql: &str) -> rusqlite::Result<Vec<Value>> {
+    let mut stmt = conn.prepare(sql)?;
+    let names: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let rows = stmt.query_map([], |r| {
+        let mut row = Map::new();
+        for (i, name) in names.iter().enumerate() {
+            let v = match r.get_ref(i)? {
+                ValueRef::Integer(n) => json!(n),
+                ValueRef::Real(f) => json!(f),
+                ValueRef::Text(t) => json!(String::from_utf8_lossy(t)),
+                ValueRef::Null | ValueRef::Blob(_) => Value::Null,
+            };
+            row.insert(name.clone(), v);
+        }
+        Ok(Value::Object(row))
+    })?;
+    rows.collect()
+}
+
+/// Run every check, in the order the report lists them within a severity.
+fn diagnose(conn: &Connection) -> rusqlite::Result<Vec<Finding>> {
+    let mut found = Vec::new();
+
+    let mut integrity = Finding::new("error", "integrity", "integrity check failures", "");
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    for line in stmt.query_map([], |r| r.get::<_, String>(0))? {
+        let line = line?;
+        if line != "ok" {
+            integrity.rows.push(json!(line));
+        }
+    }
+    found.push(integrity);
+
+    for (check, description, table, key, orphaned) in ORPHAN_CHECKS {
+        let mut f = Finding::new("error", check, description, table);
+        f.rows = rows_of(
+            conn,
+            &format!("SELECT {} FROM {} WHERE {}", key, table, orphaned),
+        )?;
+        f.fix = Some("delete");
+        found.push(f);
+    }
+
+    // Whatever else SQLite's own check turns up, outside the tables above.
+    let covered: Vec<&str> = ORPHAN_CHECKS.iter().map(|c| c.2).collect();
+    let mut foreign = Finding::new(
+        "error",
+        "foreign-key",
+        "rows referencing a missing parent",
+        "",
+    );
+    for row in rows_of(conn, "PRAGMA foreign_key_check")? {
+        if !covered.contains(&row["table"].as_str().unwrap_or("")) {
+            foreign.rows.push(row);
+        }
+    }
+    found.push(foreign);
+
+    let states: Vec<String> = STATES.iter().map(|s| format!("'{}'", s)).collect();
+    for table in ["modules", "features", "tasks"] {
+        let mut f = Finding::new(
+            "error",
+            "invalid-state",
+            "rows with an unknown state",
+            table,
+        );
+        f.rows = rows_of(
+            conn,
+            &format!(
+                "SELECT id, state FROM {} WHERE state NOT IN ({}) ORDER BY id",
+                table,
+                states.join(",")
+            ),
+        )?;
+        found.push(f);
+    }
+
+    for (table, scope) in UNIQUE_NAMES {
+        let group = scope.map(|c| format!("{}, ", c)).unwrap_or_default();
+        let mut f = Finding::new(
+            "error",
+            "duplicate-name",
+            "names that differ only in case or normal form",
+            table,
+        );
+        f.rows = rows_of(
+            conn,
+            &format!(
+                "SELECT group_concat(id, ',') AS ids, group_concat(name, ' / ') AS names
+                 FROM (SELECT * FROM {} ORDER BY id) GROUP BY {}fold(name) HAVING COUNT(*) > 1",
+                table, group
+            ),
+        )?;
+        found.push(f);
+    }
+
+    for (table, column) in db::TIMESTAMP_COLUMNS {
+        let mut bad = Finding::new(
+            "error",
+            "unparseable-timestamp",
+            "timestamps that do not parse",
+            &format!("{}.{}", table, column),
+        );
+        let mut odd = Finding::new(
+            "warning",
+            "noncanonical-timestamp",
+            "timestamps not in the canonical form",
+            &format!("{}.{}", table, column),
+        );
+        odd.fix = Some("rewrite");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT rowid, {} FROM {} WHERE {} NOT GLOB '[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]T[0-9][0-9]:[0-9][0-9]:[0-9][0-9]Z'",
+            column, table, column
+        ))?;
+        for row in stmt.query_map([], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))? {
+            let (rowid, value) = row?;
+            match timestamp::parse_tolerant(&value) {
+                Some(dt) => odd.rows.push(json!({
+                    "rowid": rowid,
+                    "value": value,
+                    "canonical": timestamp::canonical(&dt),
+                })),
+                None => bad.rows.push(json!({"rowid": rowid, "value": value})),
+            }
+        }
+        found.push(bad);
+        found.push(odd);
+    }
+
+    found.retain(|f| !f.rows.is_empty());
+    // Errors first; the order within each severity is the order checked.
+    found.sort_by_key(|f| f.severity != "error");
+    Ok(found)
+}
+
+/// Apply the repairs `diagnose` offered: delete orphaned rows and rewrite
+/// timestamps into the canonical form, in one transaction. Archived projects
+/// refuse changes under them, so their flag is lifted for the duration.
+fn repair(conn: &Connection, found: &mut [Finding]) -> rusqlite::Result<()> {
+    let tx = db::write_tx(conn)?;
+    let archived: Vec<i64> = {
+        let mut stmt = tx.prepare("SELECT id FROM projects WHERE archived=1")?;
+        let ids = stmt
+            .query_map([], |r| r.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        ids
+    };
+    tx.execute("UPDATE projects SET archived=0 WHERE archived=1", [])?;
+    for f in found.iter_mut().filter(|f| f.fix.is_some()) {
+        if let Some((_, _, table, _, orphaned)) = ORPHAN_CHECKS.iter().find(|c| c.0 == f.check) {
+            tx.execute(&format!("DELETE FROM {} WHERE {}", table, orphaned), [])?;
+        } else if let Some((table, column)) = f.table.split_once('.') {
+            for row in &f.rows {
+                tx.execute(
+                    &format!("UPDATE {} SET {}=?1 WHERE rowid=?2", table, column),
+                    params![row["canonical"].as_str(), row["rowid"].as_i64()],
+                )?;
+            }
+        }
+        f.fixed = true;
+    }
+    for id in archived {
+        tx.execute("UPDATE projects SET archived=1 WHERE id=?1", params![id])?;
+    }
+    tx.commit()
+}
+
+/// Check the store after a crash or an outside edit: SQLite's integrity and
+/// foreign key checks, then rows whose parent is gone (which joins silently
+/// skip), unknown states, timestamps that do not parse or are not canonical,
+/// and names that collide once case and normal form are ignored. The report
+/// lists errors before warnings. With `fix`, orphaned rows are deleted and
+/// timestamps rewritten, in one transaction; the rest needs a person. Exits
+/// 1 while any error remains.
+pub fn doctor(conn: &Connection, fix: bool, json: bool) -> i32 {
+    let mut found = match diagnose(conn) {
+        Ok(f) => f,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    if fix && found.iter().any(|f| f.fix.is_some()) {
+        if let Err(e) = repair(conn, &mut found) {
+            output::err(&format!("nothing was repaired: {}", e));
+            return 2;
+        }
+    }
+    let open = |severity: &str| {
+        found
+            .iter()
+            .filter(|f| f.severity == severity && !f.fixed)
+            .count()
+    };
+    let (errors, warnings) = (open("error"), open("warning"));
+    let fixed = found.iter().filter(|f| f.fixed).count();
+
+    if json {
+        output::print_json(&json!({
+            "findings": found.iter().map(Finding::to_json).collect::<Vec<_>>(),
+            "errors": errors,
+            "warnings": warnings,
+            "total": found.iter().map(|f| f.rows.len()).sum::<usize>(),
+            "fixed": fixed > 0,
+        }));
+    } else if found.is_empty() {
+        output::print_plain("no problems found");
+    } else {
+        for severity in ["error", "warning"] {
+            let group: Vec<&Finding> = found.iter().filter(|f| f.severity == severity).collect();
+            if group.is_empty() {
+                continue;
+            }
+            outln!("{}s:", severity);
+            for f in group {
+                let table = if f.table.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", f.table)
+                };
+                let fix = match (f.fix, f.fixed) {
+                    (Some(_), true) => " [fixed]",
+                    (Some("delete"), false) => " [--fix deletes]",
+                    (Some(_), false) => " [--fix rewrites]",
+                    (None, _) => "",
+                };
+                outln!(
+                    "  {} {}{}{}: {}",
+                    f.rows.len(),
+                    f.description,
+                    table,
+                    fix,
+                    f.sample()
+                );
+            }
+        }
+        let fixable = found.iter().filter(|f| f.fix.is_some() && !f.fixed).count();
+        let hint = if fixable > 0 {
+            format!("; pass --fix to repair {} of them", fixable)
+        } else {
+            String::new()
+        };
+        output::print_plain(&format!(
+            "{} error(s), {} warning(s) left{}{}",
+            errors,
+            warnings,
+            if fixed > 0 {
+                format!(" after repairing {}", fixed)
+            } else {
+                String::new()
+            },
+            hint
+        ));
+    }
+    if errors > 0 {
+        output::err(&format!("{} error(s) found by db doctor", errors));
+        return 1;
+    }
+    0
+}
@@ -0,0 +1,223 @@
+use crate::models::research::SUBTREE_RESEARCH_SQL_PROJECT;
+use crate::output;
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use rusqlite::{params, Connection};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Entity types counted, in the order they are reported.
+const TYPES: [&str; 5] = ["project", "module", "feature", "task", "research"];
+
+/// Days looked back when no --since is given: twelve full weeks.
+const DEFAULT_DAYS: i64 = 83;
+
+/// One `(type, day)` row per recorded mutation of the project or anything in
+/// it. Until there is an audit log the store only keeps when each entity was
+/// last touched, so an entity counts once, on that day, plus once per rename
+/// and, for research, once for its creation.
+fn events_sql() -> String {
+    format!(
+        "WITH sub_research(id) AS ({sub}),
+         events(type, day) AS (
+            SELECT 'project', date(updated_at) FROM projects WHERE id=?1
+            UNION ALL SELECT 'module', date(last_worked_on) FROM modules WHERE project_id=?1
+            UNION ALL SELECT 'feature', date(f.last_worked_on) FROM features f
+                JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1
+            UNION ALL SELECT 'task', date(t.last_worked_on) FROM tasks t
+                JOIN features f ON f.id=t.feature_id JOIN modules m ON m.id=f.module_id
+                WHERE m.project_id=?1
+            UNION ALL SELECT 'research', date(created_at) FROM research
+                WHERE id IN (SELECT id FROM sub_research)
+            UNION ALL SELECT 'research', date(updated_at) FROM research
+                WHERE id IN (SELECT id FROM sub_research) AND date(updated_at) <> date(created_at)
+            UNION ALL SELECT h.entity_type, date(h.renamed_at) FROM name_history h
+                WHERE (h.entity_type='project' AND h.entity_id=?1)
+                   OR (h.entity_type='module' AND h.entity_id IN
+                        (SELECT id FROM modules WHERE project_id=?1))
+                   OR (h.entity_type='feature' AND h.entity_id IN
+                        (SELECT f.id FROM features f JOIN modules m ON m.id=f.module_id
+                         WHERE m.project_id=?1))
+                   OR (h.entity_type='task' AND h.entity_id IN
+                        (SELECT t.id FROM tasks t JOIN features f ON f.id=t.feature_id
+                         JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1))
+                   OR (h.entity_type='research' AND h.entity_id IN (SELECT id FROM sub_research))
+         )
+         SELECT day, type, COUNT(*) FROM events
+         WHERE day BETWEEN ?2 AND ?3
+         GROUP BY day, type ORDER BY day",
+        sub = SUBTREE_RESEARCH_SQL_PROJECT
+    )
+}
+
+/// Counts per entity type on each day with any activity, in date order.
+fn load(
+    conn: &Connection,
+    project_id: i64,
+    since: NaiveDate,
+    until: NaiveDate,
+) -> rusqlite::Result<BTreeMap<NaiveDate, BTreeMap<String, i64>>> {
+    let mut stmt = conn.prepare(&events_sql())?;
+    let rows = stmt.query_map(
+        params![project_id, since.to_string(), until.to_string()],
+        |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, i64>(2)?,
+            ))
+        },
+    )?;
+    let mut days: BTreeMap<NaiveDate, BTreeMap<String, i64>> = BTreeMap::new();
+    for row in rows {
+        let (day, kind, n) = row?;
+        if let Ok(d) = NaiveDate::parse_from_str(&day, "%Y-%m-%d") {
+            days.entry(d).or_default().insert(kind, n);
+        }
+    }
+    Ok(days)
+}
+
+/// The shade for a day's total against the busiest day: a dot for nothing,
+/// then four steps up to a full block.
+fn shade(total: i64, max: i64) -> char {
+    if total == 0 || max == 0 {
+        return '·';
+    }
+    match (4 * total + max - 1) / max {
+        1 => '░',
+        2 => '▒',
+        3 => '▓',
+        _ => '█',
+    }
+}
+
+/// A calendar with one column per week and one row per weekday, Monday on
+/// top, shaded by each day's total.
+fn print_calendar(days: &BTreeMap<NaiveDate, i64>, since: NaiveDate, until: NaiveDate) {
+    let max = days.values().copied().max().unwrap_or(0);
+    let first_monday = since - Duration::days(since.weekday().num_days_from_monday() as i64);
+    let weeks = (until - first_monday).num_days() / 7 + 1;
+    for (row, label) in ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]
+        .iter()
+        .enumerate()
+    {
+        let mut line = format!("{} ", label);
+        for week in 0..weeks {
+            let day = first_monday + Duration::days(week * 7 + row as i64);
+            line.push(if day < since || day > until {
+                ' '
+            } else {
+                shade(days.get(&day).copied().unwrap_or(0), max)
+            });
+        }
+        outln!("{}", line.trim_end());
+    }
+}
+
+/// How much the project and everything in it was changed on each day from
+/// `since` (YYYY-MM-DD, default twelve weeks ago) through today, by entity
+/// type. JSON lists only days with activity; plain text draws a calendar.
+pub fn activity(conn: &Connection, project_id: i64, since: Option<&str>, json: bool) -> i32 {
+    let until = Utc::now().date_naive();
+    let since = match since {
+        Some(s) => match NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d") {
+            Ok(d) => d,
+            Err(_) => {
+                output::err(&format!(
+                    "invalid --since date: {} (expected YYYY-MM-DD)",
+                    s
+                ));
+                return 1;
+            }
+        },
+        None => until - Duration::days(DEFAULT_DAYS),
+    };
+    if since > until {
+        output::err(&format!("--since {} is in the future", since));
+        return 1;
+    }
+    let project: String = match conn.query_row(
+        "SELECT name FROM projects WHERE id=?1",
+        params![project_id],
+        |r| r.get(0),
+    ) {
+        Ok(p) => p,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let days = match load(conn, project_id, since, until) {
+        Ok(d) => d,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let mut totals: BTreeMap<&str, i64> = TYPES.iter().map(|t| (*t, 0)).collect();
+    for counts in days.values() {
+        for (kind, n) in counts {
+            if let Some(t) = totals.get_mut(kind.as_str()) {
+                *t += n;
+            }
+        }
+    }
+    let day_totals: BTreeMap<NaiveDate, i64> =
+        days.iter().map(|(d, c)| (*d, c.values().sum())).collect();
+    let total: i64 = day_totals.values().sum();
+
+    if json {
+        let counts = |c: &BTreeMap<String, i64>| -> Value {
+            let mut m = Map::new();
+            for t in TYPES {
+                m.insert(t.to_string(), json!(c.get(t).copied().unwrap_or(0)));
+            }
+            Value::Object(m)
+        };
+        let mut totals_json = Map::new();
+        for t in TYPES {
+            totals_json.insert(t.to_string(), json!(totals[t]));
+        }
+        totals_json.insert("total".to_string(), json!(total));
+        output::print_json(&json!({
+            "project": {"id": project_id, "name": project},
+            "since": since.to_string(),
+            "until": until.to_string(),
+            "days": days.iter().map(|(d, c)| json!({
+                "date": d.to_string(),
+                "total": day_totals[d],
+                "counts": counts(c),
+            })).collect::<Vec<_>>(),
+            "totals": Value::Object(totals_json),
+        }));
+        return 0;
+    }
+
+    outln!("{}: activity {} to {}", project, since, until);
+    print_calendar(&day_totals, since, until);
+    let span = (until - since).num_days() + 1;
+    match day_totals
+        .iter()
+        .max_by_key(|(d, n)| (**n, std::cmp::Reverse(**d)))
+    {
+        Some((day, n)) => outln!(
+            "{} change{} on {} of {} days; busiest {} ({})",
+            total,
+            if total == 1 { "" } else { "s" },
+            day_totals.len(),
+            span,
+            day,
+            n
+        ),
+        None => outln!("no changes in {} days", span),
+    }
+    outln!(
+        "{}",
+        TYPES
+            .iter()
+            .map(|t| format!("{} {}", t, totals[t]))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    0
+}
@@ -0,0 +1,108 @@
+use crate::error::Error;
+use crate::models::{feature, module, project};
+use crate::output;
+use rusqlite::{params, Connection};
+use serde_json::{Map, Value};
+
+/// One entity in a hierarchy chain.
+#[derive(Clone, PartialEq)]
+pub struct Crumb {
+    pub kind: &'static str,
+    pub id: i64,
+    pub name: String,
+}
+
+/// The chain from an entity's project down to the entity itself, project first.
+/// Empty when the entity does not exist or `kind` is not a work entity type.
+pub fn chain(conn: &Connection, kind: &str, id: i64) -> rusqlite::Result<Vec<Crumb>> {
+    // Each query returns (kind, id, name) rows from the project downwards.
+    let sql = match kind {
+        "project" => "SELECT 'project', id, name FROM projects WHERE id=?1",
+        "module" => {
+            "SELECT 'project', p.id, p.name FROM modules m JOIN projects p ON p.id=m.project_id WHERE m.id=?1
+             UNION ALL SELECT 'module', id, name FROM modules WHERE id=?1"
+        }
+        "feature" => {
+            "SELECT 'project', p.id, p.name FROM features f JOIN modules m ON m.id=f.module_id
+                 JOIN projects p ON p.id=m.project_id WHERE f.id=?1
+             UNION ALL SELECT 'module', m.id, m.name FROM features f JOIN modules m ON m.id=f.module_id WHERE f.id=?1
+             UNION ALL SELECT 'feature', id, name FROM features WHERE id=?1"
+        }
+        "task" => {
+            "SELECT 'project', p.id, p.name FROM tasks t JOIN features f ON f.id=t.feature_id
+                 JOIN modules m ON m.id=f.module_id JOIN projects p ON p.id=m.project_id WHERE t.id=?1
+             UNION ALL SELECT 'module', m.id, m.name FROM tasks t JOIN features f ON f.id=t.feature_id
+                 JOIN modules m ON m.id=f.module_id WHERE t.id=?1
+             UNION ALL SELECT 'feature', f.id, f.name FROM tasks t JOIN features f ON f.id=t.feature_id WHERE t.id=?1
+             UNION ALL SELECT 'task', id, name FROM tasks WHERE id=?1"
+        }
+        _ => return Ok(Vec::new()),
+    };
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params![id], |r| {
+        let kind: String = r.get(0)?;
+        Ok(Crumb {
+            kind: static_kind(&kind),
+            id: r.get(1)?,
+            name: r.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn static_kind(kind: &str) -> &'static str {
+    match kind {
+        "project" => "project",
+        "module" => "module",
+        "feature" => "feature",
+        _ => "task",
+    }
+}
+
+/// Names along the chain joined as `project > module > feature > task`.
+pub fn breadcrumb(conn: &Connection, kind: &str, id: i64) -> rusqlite::Result<String> {
+    Ok(chain(conn, kind, id)?
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect::<Vec<_>>()
+        .join(" > "))
+}
+
+/// Full records of everything above an entity, keyed by type (`project`,
+/// `module`, and for tasks `feature`), for `show --with-ancestors`.
+pub fn ancestors_json(conn: &Connection, kind: &str, id: i64) -> Result<Value, Error> {
+    let mut out = Map::new();
+    for c in chain(conn, kind, id)?.iter().filter(|c| c.kind != kind) {
+        let record = match c.kind {
+            "project" => project::record_json(conn, c.id)?,
+            "module" => module::record_json(conn, c.id)?,
+            _ => feature::record_json(conn, c.id)?,
+        };
+        out.insert(c.kind.to_string(), record);
+    }
+    Ok(Value::Object(out))
+}
+
+/// One labelled section per ancestor from [`ancestors_json`], project
+/// first, with the description, details, path, and state its own show view
+/// prints.
+pub fn print_ancestors(ancestors: &Value) {
+    for kind in ["project", "module", "feature"] {
+        let Some(record) = ancestors.get(kind) else {
+            continue;
+        };
+        let text = |field: &str| {
+            record
+                .get(field)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+        };
+        outln!();
+        outln!("{} {} ({}):", kind, text("name"), text("ref"));
+        for field in ["description", "details", "path", "state"] {
+            if let Some(v) = record.get(field).and_then(Value::as_str) {
+                outln!("{}", output::field(field, v));
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use crate::config;
+use crate::error::Error;
+use crate::output;
+use crate::timestamp;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A local file attached to a research record: either referenced where it
+/// lies, or (`copied`) a copy kept under the attachments directory.
+pub struct Attachment {
+    pub id: i64,
+    pub path: String,
+    pub copied: bool,
+    pub size: i64,
+    pub added_at: String,
+}
+
+impl Attachment {
+    pub fn exists(&self) -> bool {
+        Path::new(&self.path).is_file()
+    }
+}
+
+/// Where copies for one research record live: `attachments/<research-id>/`
+/// next to the config file (~/.lopen-memory by default).
+fn copy_dir(research_id: i64) -> PathBuf {
+    config::dir()
+        .join("attachments")
+        .join(research_id.to_string())
+}
+
+pub fn to_json(a: &Attachment) -> Value {
+    json!({
+        "id": a.id,
+        "path": a.path,
+        "copied": a.copied,
+        "size": a.size,
+        "added_at": a.added_at,
+        "exists": a.exists(),
+    })
+}
+
+/// Attachments of a research record, oldest first.
+pub fn for_research(conn: &Connection, research_id: i64) -> rusqlite::Result<Vec<Attachment>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, path, copied, size, added_at FROM research_attachments
+         WHERE research_id=?1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map(params![research_id], |r| {
+        Ok(Attachment {
+            id: r.get(0)?,
+            path: r.get(1)?,
+            copied: r.get::<_, i64>(2)? != 0,
+            size: r.get(3)?,
+            added_at: r.get(4)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// One line per attachment for plain output, flagging files that are gone.
+pub fn line(a: &Attachment) -> String {
+    format!(
+        "{:<4} {:<6} {:>10}  {}{}",
+        a.id,
+        if a.copied { "copy" } else { "linked" },
+        a.size,
+        a.path,
+        if a.exists() { "" } else { "  [missing]" }
+    )
+}
+
+fn research_name(conn: &Connection, research_id: i64) -> Result<String, String> {
+    conn.query_row(
+        "SELECT name FROM research WHERE id=?1",
+        params![research_id],
+        |r| r.get(0),
+    )
+    .map_err(|_| format!("research not found: {}", research_id))
+}
+
+/// Attach a file to a research record, by absolute path or (with `copy`) as a
+/// copy under the attachments directory. Attaching the same file twice is a no-op.
+pub fn attach(conn: &Connection, research_id: i64, path: &str, copy: bool, json: bool) -> i32 {
+    let name = match research_name(conn, research_id) {
+        Ok(n) => n,
+        Err(e) => {
+            output::err(&e);
+            return 1;
+        }
+    };
+    let source = match fs::canonicalize(path) {
+        Ok(p) if p.is_file() => p,
+        Ok(_) => {
+            output::err(&format!("not a file: {}", path));
+            return 1;
+        }
+        Err(_) => {
+            return output::fail(&Error::not_found(
+                "file",
+                format!("path not found: {}", path),
+            ));
+        }
+    };
+    let size = fs::metadata(&source).map(|m| m.len() as i64).unwrap_or(0);
+    let stored = if copy {
+        let dir = copy_dir(research_id);
+        let dest = dir.join(source.file_name().unwrap_or_default());
+        if dest.exists() {
+            output::err(&format!(
+                "research {} already has a copied attachment named {}",
+                name,
+                dest.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            return 1;
+        }
+        if let Err(e) = fs::create_dir_all(&dir).and_then(|_| fs::copy(&source, &dest)) {
+            return output::fail(&Error::Io(format!(
+                "could not copy {}: {}",
+                source.display(),
+                e
+            )));
+        }
+        dest
+    } else {
+        source
+    };
+    let stored = stored.display().to_string();
+    let added = match conn.execute(
+        "INSERT OR IGNORE INTO research_attachments (research_id, path, copied, size, added_at)
+         VALUES (?1,?2,?3,?4,?5)",
+        params![research_id, stored, copy as i64, size, timestamp::now()],
+    ) {
+        Ok(n) => n > 0,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let a = match for_research(conn, research_id) {
+        Ok(all) => all.into_iter().find(|a| a.path == stored),
+        Err(e) => return output::fail(&e.into()),
+    };
+    let Some(a) = a else {
+        output::err("attachment was not saved");
+        return 2;
+    };
+    if json {
+        let mut v = to_json(&a);
+        v["research_id"] = json!(research_id);
+        v["created"] = json!(added);
+        output::print_json(&v);
+    } else if added {
+        output::print_plain(&format!(
+            "attached {} to research {}{}",
+            a.path,
+            name,
+            if copy { " (copied)" } else { "" }
+        ));
+    } else {
+        output::print_plain(&format!(
+            "{} is already attached to research {}",
+            a.path, name
+        ));
+    }
+    0
+}
+
+/// List a research record's attachments, checking each file is still there.
+pub fn list(conn: &Connection, research_id: i64, json: bool) -> i32 {
+    let name = match research_name(conn, research_id) {
+        Ok(n) => n,
+        Err(e) => {
+            output::err(&e);
+            return 1;
+        }
+    };
+    let attachments = match for_research(conn, research_id) {
+        Ok(a) => a,
+        Err(e) => return output::fail(&e.into()),
+    };
+    if json {
+        output::print_json(&Value::Array(attachments.iter().map(to_json).collect()));
+    } else if attachments.is_empty() {
+        output::print_empty(&format!("no attachments for research {}", name));
+    } else {
+        for a in &attachments {
+            outln!("{}", line(a));
+        }
+    }
+    0
+}
+
+/// Delete the copies kept for a research record. Referenced files are never
+/// touched; they belong to whoever put them there.
+pub fn remove_copies(research_id: i64) -> std::io::Result<()> {
+    let dir = copy_dir(research_id);
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use crate::content;
+use crate::db;
+use crate::error::Error;
+use crate::models::share::Scratch;
+use crate::output;
+use rusqlite::{params, Connection, DatabaseName};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Tables a file must have to be taken for a lopen-memory database.
+const REQUIRED: [&str; 5] = ["projects", "modules", "features", "tasks", "research"];
+
+/// The stem timestamped backups of `path` share, e.g. `lopen-memory-backup-`.
+fn prefix(path: &str) -> String {
+    let stem = Path::new(path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "lopen-memory".to_string());
+    format!("{}-backup-", stem)
+}
+
+/// A new timestamped backup file for `path` in `dir`, e.g.
+/// `lopen-memory-backup-20261016T130042.123Z.db`. The names sort oldest first.
+fn timestamped(path: &str, dir: &Path) -> PathBuf {
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ");
+    dir.join(format!("{}{}.db", prefix(path), stamp))
+}
+
+/// The directory the database lives in, for backups written beside it.
+fn beside(path: &str) -> PathBuf {
+    match Path::new(path).parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Write a consistent copy of the database to `to` with VACUUM INTO, which
+/// reads one snapshot whether or not writers are active in WAL mode. Bodies
+/// kept in external files are written into the copy, so restoring it never
+/// pairs its rows with whatever those files hold by then.
+fn write_copy(conn: &Connection, to: &Path) -> Result<u64, Error> {
+    if to.exists() {
+        return Err(Error::Conflict(format!(
+            "{} already exists; remove it or choose another path",
+            to.display()
+        )));
+    }
+    conn.execute("VACUUM INTO ?1", params![to.to_string_lossy()])
+        .and_then(|_| content::inline_into(conn, &Connection::open(to)?))
+        .map_err(|e| Error::Io(format!("failed to write {}: {}", to.display(), e)))?;
+    Ok(fs::metadata(to).map(|m| m.len()).unwrap_or(0))
+}
+
+/// Delete all but the newest `keep` timestamped backups of `path` in `dir`,
+/// returning what was removed.
+fn rotate(path: &str, dir: &Path, keep: usize) -> Result<Vec<PathBuf>, String> {
+    let prefix = prefix(path);
+    let mut found: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("cannot read {}: {}", dir.display(), e))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".db"))
+        })
+        .collect();
+    found.sort();
+    let excess = found.len().saturating_sub(keep);
+    let removed: Vec<PathBuf> = found.into_iter().take(excess).collect();
+    for p in &removed {
+        fs::remove_file(p).map_err(|e| format!("cannot remove {}: {}", p.display(), e))?;
+    }
+    Ok(removed)
+}
+
+/// Snapshot the database to `to`, or to a timestamped file beside it. `to`
+/// may name a directory, which then gets the timestamped file. With `keep`,
+/// only the newest `keep` timestamped backups in that directory are kept.
+pub fn backup(
+    conn: &Connection,
+    path: &str,
+    to: Option<&str>,
+    keep: Option<usize>,
+    json: bool,
+) -> i32 {
+    let (target, dir) = match to {
+        Some(t) if Path::new(t).is_dir() => (timestamped(path, Path::new(t)), PathBuf::from(t)),
+        Some(t) => {
+            if keep.is_some() {
+                output::err(
+                    "--keep rotates timestamped backups; give --to a directory or leave it out",
+                );
+                return 1;
+            }
+            (PathBuf::from(t), beside(t))
+        }
+        None => (timestamped(path, &beside(path)), beside(path)),
+    };
+    let bytes = match write_copy(conn, &target) {
+        Ok(b) => b,
+        Err(e) => return output::fail(&e),
+    };
+    let removed = match keep {
+        Some(n) => match rotate(path, &dir, n) {
+            Ok(r) => r,
+            Err(e) => {
+                output::err(&e);
+                return 2;
+            }
+        },
+        None => Vec::new(),
+    };
+
+    let removed: Vec<String> = removed.iter().map(|p| p.display().to_string()).collect();
+    if json {
+        output::print_json(&json!({
+            "path": target.display().to_string(),
+            "bytes": bytes,
+            "schema_version": db::schema_version(),
+            "removed": removed,
+        }));
+    } else {
+        output::print_plain(&format!(
+            "wrote backup {} ({} bytes)",
+            target.display(),
+            bytes
+        ));
+        for r in &removed {
+            outln!("removed old backup {}", r);
+        }
+    }
+    0
+}
+
+/// Check that `copy`, a scratch copy of `from`, is an intact lopen-memory
+/// database this binary can open, returning its schema version. The check
+/// runs on the copy since SQLite's integrity check of the search index needs
+/// to write, and the backup itself is left untouched.
+fn verify(from: &str, copy: &Scratch) -> Result<i64, String> {
+    let src = Connection::open(copy.path()).map_err(|e| format!("cannot open {}: {}", from, e))?;
+    let not_ours = |e: rusqlite::Error| format!("{} is not a SQLite database: {}", from, e);
+    let check: String = src
+        .query_row("PRAGMA quick_check", [], |r| r.get(0))
+        .map_err(not_ours)?;
+    if check != "ok" {
+        return Err(format!("{} is damaged: {}", from, check));
+    }
+    let version: i64 = src
+        .query_row("PRAGMA user_version", [], |r| r.get(0))
+        .map_err(not_ours)?;
+    for table in REQUIRED {
+        let present: bool = src
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type='table' AND name=?1",
+                params![table],
+                |r| r.get(0),
+            )
+            .map_err(not_ours)?;
+        if !present {
+            return Err(format!(
+                "{} is not a lopen-memory database: no {} table",
+                from, table
+            ));
+        }
+    }
+    if version < 1 {
+        return Err(format!(
+            "{} is not a lopen-memory database: it has no schema version",
+            from
+        ));
+    }
+    if version > db::schema_version() {
+        return Err(format!(
+            "{} has schema version {}, newer than this binary's ({}); upgrade lopen-memory",
+            from,
+            version,
+            db::schema_version()
+        ));
+    }
+    Ok(version)
+}
+
+/// Replace the database at `path` with the backup `from`, after checking it
+/// and saving the current contents as a timestamped backup beside it. An
+/// older schema is migrated once it is in place.
+pub fn restore(conn: &Connection, path: &str, from: &str, json: bool) -> i32 {
+    let same = fs::canonicalize(from)
+        .ok()
+        .zip(fs::canonicalize(path).ok())
+        .is_some_and(|(a, b)| a == b);
+    if same {
+        output::err(&format!("{} is the live database", from));
+        return 1;
+    }
+    if !Path::new(from).is_file() {
+        output::err(&format!("{} is not a file", from));
+        return 1;
+    }
+    let copy = Scratch::new();
+    if let Err(e) = fs::copy(from, copy.path()) {
+        output::err(&format!("cannot read {}: {}", from, e));
+        return 1;
+    }
+    let version = match verify(from, &copy) {
+        Ok(v) => v,
+        Err(e) => {
+            output::err(&e);
+            return 1;
+        }
+    };
+    let previous = timestamped(path, &beside(path));
+    if let Err(e) = write_copy(conn, &previous) {
+        output::err(&format!("not restoring: {}", e));
+        return 2;
+    }
+    let copied = Connection::open(path).and_then(|mut dst| {
+        dst.restore(
+            DatabaseName::Main,
+            copy.path(),
+            None::<fn(rusqlite::backup::Progress)>,
+        )
+    });
+    if let Err(e) = copied.and_then(|_| db::open(path, false).map(drop)) {
+        output::err(&format!(
+            "failed to restore {}: {}; the previous database is in {}",
+            from,
+            e,
+            previous.display()
+        ));
+        return 2;
+    }
+    let bytes = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+
+    if json {
+        output::print_json(&json!({
+            "path": path,
+            "from": from,
+            "bytes": bytes,
+            "schema_version": db::schema_version(),
+            "migrated_from": (version < db::schema_version()).then_some(version),
+            "previous": previous.display().to_string(),
+        }));
+    } else {
+        output::print_plain(&format!(
+            "restored {} from {} ({} bytes); the previous database is in {}",
+            path,
+            from,
+            bytes,
+            previous.display()
+        ));
+    }
+    0
+}
@@ -0,0 +1,116 @@
+use crate::models::research::SUBTREE_RESEARCH_SQL_PROJECT;
+use crate::names;
+use crate::output;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// One distinct source and the research records citing it.
+struct Entry {
+    source: String,
+    url: bool,
+    cited_by: Vec<String>,
+}
+
+/// The URL after an http or https scheme, if `s` is one.
+fn without_scheme(s: &str) -> Option<&str> {
+    let lower = s.get(..8).unwrap_or(s).to_ascii_lowercase();
+    if lower.starts_with("https://") {
+        Some(&s[8..])
+    } else if lower.starts_with("http://") {
+        Some(&s[7..])
+    } else {
+        None
+    }
+}
+
+/// What two citations of the same source have in common: for URLs, the part
+/// after the scheme with the host lowercased and trailing slashes dropped, so
+/// `http://Example.com/a/` and `https://example.com/a` collapse; anything
+/// else compares trimmed and ignoring case.
+fn key(source: &str) -> String {
+    let Some(rest) = without_scheme(source) else {
+        return format!("text:{}", names::fold(source));
+    };
+    let rest = rest.trim_end_matches('/');
+    let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    format!("url:{}{}", host.to_lowercase(), path)
+}
+
+/// Distinct non-empty sources of research linked anywhere under the project,
+/// ordered by source. Of several spellings of one URL the https one is shown.
+fn entries(conn: &Connection, project_id: i64) -> rusqlite::Result<Vec<Entry>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT name, TRIM(source) FROM research
+         WHERE id IN ({}) AND TRIM(source) <> ''
+         ORDER BY name_key(name), fold(name), name",
+        SUBTREE_RESEARCH_SQL_PROJECT
+    ))?;
+    let rows = stmt.query_map(params![project_id], |r| {
+        Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?))
+    })?;
+    let mut by_key: BTreeMap<String, Entry> = BTreeMap::new();
+    for row in rows {
+        let (name, source) = row?;
+        let url = without_scheme(&source).is_some();
+        let entry = by_key.entry(key(&source)).or_insert_with(|| Entry {
+            source: source.clone(),
+            url,
+            cited_by: Vec::new(),
+        });
+        if url && !entry.source.to_ascii_lowercase().starts_with("https://") {
+            entry.source = source.trim_end_matches('/').to_string();
+        }
+        entry.cited_by.push(name);
+    }
+    let mut out: Vec<Entry> = by_key.into_values().collect();
+    out.sort_by_key(|e| names::fold(without_scheme(&e.source).unwrap_or(&e.source)));
+    Ok(out)
+}
+
+/// Every source cited by research linked to the project or anything in it,
+/// deduplicated, with the records citing each. `format` is `plain` or `md`
+/// (a Markdown reference list, URLs as links).
+pub fn bibliography(conn: &Connection, project_id: i64, format: &str, json: bool) -> i32 {
+    if !matches!(format, "plain" | "md") {
+        output::err(&format!(
+            "unsupported bibliography format: {} (expected plain or md)",
+            format
+        ));
+        return 1;
+    }
+    let list = match entries(conn, project_id) {
+        Ok(l) => l,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    if json {
+        output::print_json(&Value::Array(
+            list.iter()
+                .map(|e| json!({"source": e.source, "url": e.url, "cited_by": e.cited_by}))
+                .collect(),
+        ));
+        return 0;
+    }
+    if list.is_empty() {
+        output::print_empty("no sources cited by research linked to this project");
+        return 0;
+    }
+    for (i, e) in list.iter().enumerate() {
+        let cited = e.cited_by.join(", ");
+        if format == "md" {
+            let source = if e.url {
+                format!("[{}]({})", e.source, e.source)
+            } else {
+                e.source.clone()
+            };
+            outln!("{}. {} — cited by {}", i + 1, source, cited);
+        } else {
+            outln!("{}", e.source);
+            outln!("  cited by: {}", cited);
+        }
+    }
+    0
+}
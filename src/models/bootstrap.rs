@@ -0,0 +1,416 @@
+use crate::db;
+use crate::names;
+use crate::output;
+use crate::resolve::Resolver;
+use crate::timestamp;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where `project bootstrap` takes its proposed modules from.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ModuleSource {
+    /// Top-level directories of the repository
+    TopDirs,
+    /// Workspace members declared in Cargo.toml or package.json
+    Workspace,
+    /// Workspace members when a workspace is detected, top-level directories otherwise
+    Auto,
+}
+
+impl std::str::FromStr for ModuleSource {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top-dirs" => Ok(ModuleSource::TopDirs),
+            "workspace" => Ok(ModuleSource::Workspace),
+            "auto" => Ok(ModuleSource::Auto),
+            _ => Err(format!(
+                "unknown module source '{}': expected top-dirs, workspace, or auto",
+                s
+            )),
+        }
+    }
+}
+
+/// Top-level directories that hold build output, dependencies, or tooling rather than code.
+const IGNORED_DIRS: [&str; 7] = [
+    "target",
+    "node_modules",
+    "dist",
+    "build",
+    "out",
+    "vendor",
+    "coverage",
+];
+
+/// A module proposed from the repository layout: its slug and the directory it
+/// came from, relative to the repository root.
+struct Proposal {
+    name: String,
+    dir: String,
+}
+
+/// Lowercase slug from a directory name: runs of anything that is not
+/// alphanumeric, '-' or '_' collapse to a single '-'.
+pub fn slugify(s: &str) -> String {
+    let mut out = String::new();
+    // Compose first, so an accent typed as a combining mark stays with its letter.
+    for c in names::normalize(s).chars() {
+        if c.is_alphanumeric() || c == '_' || c == '-' {
+            out.extend(c.to_lowercase());
+        } else if !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    out.trim_matches('-').to_string()
+}
+
+fn top_dirs(root: &Path) -> Vec<String> {
+    let mut dirs: Vec<String> = fs::read_dir(root)
+        .map(|rd| {
+            rd.filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|n| !n.starts_with('.') && !IGNORED_DIRS.contains(&n.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+/// The `members = [...]` array of a Cargo.toml `[workspace]` table. A small
+/// line-based reader is enough for the shapes cargo itself writes.
+fn cargo_members(root: &Path) -> Option<Vec<String>> {
+    let text = fs::read_to_string(root.join("Cargo.toml")).ok()?;
+    let mut in_workspace = false;
+    let mut collecting = false;
+    let mut members = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.starts_with('[') && !collecting {
+            in_workspace = line == "[workspace]";
+            continue;
+        }
+        let rest = if collecting {
+            line
+        } else if in_workspace && line.starts_with("members") {
+            match line.split_once('=') {
+                Some((_, v)) => {
+                    collecting = true;
+                    v.trim().trim_start_matches('[')
+                }
+                None => continue,
+            }
+        } else {
+            continue;
+        };
+        let (items, done) = match rest.split_once(']') {
+            Some((items, _)) => (items, true),
+            None => (rest, false),
+        };
+        members.extend(
+            items
+                .split(',')
+                .map(|m| m.trim().trim_matches('"').trim_matches('\'').to_string())
+                .filter(|m| !m.is_empty()),
+        );
+        if done {
+            return Some(members);
+        }
+    }
+    if collecting {
+        Some(members)
+    } else {
+        None
+    }
+}
+
+/// The `workspaces` of a package.json, either an array or `{ "packages": [...] }`.
+fn npm_members(root: &Path) -> Option<Vec<String>> {
+    let text = fs::read_to_string(root.join("package.json")).ok()?;
+    let v: Value = serde_json::from_str(&text).ok()?;
+    let ws = v.get("workspaces")?;
+    let list = ws.as_array().or_else(|| ws.get("packages")?.as_array())?;
+    Some(
+        list.iter()
+            .filter_map(|m| m.as_str().map(|s| s.to_string()))
+            .collect(),
+    )
+}
+
+/// Expand workspace member patterns. Only a trailing `/*` is supported, which
+/// covers the usual `crates/*` and `packages/*` layouts.
+fn expand_members(root: &Path, patterns: &[String]) -> Vec<String> {
+    let mut dirs = Vec::new();
+    for p in patterns {
+        let p = p.trim_end_matches('/');
+        if let Some(parent) = p.strip_suffix("/*") {
+            for d in top_dirs(&root.join(parent)) {
+                dirs.push(format!("{}/{}", parent, d));
+            }
+        } else if root.join(p).is_dir() {
+            dirs.push(p.to_string());
+        }
+    }
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+fn workspace_dirs(root: &Path) -> Option<Vec<String>> {
+    let patterns = cargo_members(root).or_else(|| npm_members(root))?;
+    Some(expand_members(root, &patterns))
+}
+
+/// A directory left out of the proposals, and why.
+struct Skipped {
+    dir: String,
+    reason: String,
+}
+
+/// Proposed modules, plus directories left out because their slug was empty
+/// or taken by an earlier directory.
+fn propose(root: &Path, source: ModuleSource) -> Result<(Vec<Proposal>, Vec<Skipped>), String> {
+    let dirs = match source {
+        ModuleSource::TopDirs => top_dirs(root),
+        ModuleSource::Workspace => workspace_dirs(root).ok_or_else(|| {
+            format!(
+                "no Cargo.toml or package.json workspace found in {}",
+                root.display()
+            )
+        })?,
+        ModuleSource::Auto => workspace_dirs(root).unwrap_or_else(|| top_dirs(root)),
+    };
+    let mut proposals: Vec<Proposal> = Vec::new();
+    let mut skipped = Vec::new();
+    for dir in dirs {
+        let base = dir.rsplit('/').next().unwrap_or(&dir);
+        let name = slugify(base);
+        // Two directories that slugify alike would collide on the module name; keep the first.
+        if name.is_empty() {
+            skipped.push(Skipped {
+                dir,
+                reason: "no usable slug".to_string(),
+            });
+        } else if let Some(first) = proposals.iter().find(|p| p.name == name) {
+            let reason = format!("slug {} already taken by {}", name, first.dir);
+            skipped.push(Skipped { dir, reason });
+        } else {
+            proposals.push(Proposal { name, dir });
+        }
+    }
+    Ok((proposals, skipped))
+}
+
+/// One module in a bootstrap plan, with its id when it already exists.
+struct PlannedModule {
+    name: String,
+    dir: String,
+    existing: Option<i64>,
+}
+
+/// Everything `bootstrap` would do, worked out with reads only. `errors` are
+/// problems that stop the plan from being executed; a dry run prints them
+/// alongside the plan so the whole input is checked in one pass.
+struct Plan {
+    name: String,
+    root: String,
+    existing: Option<i64>,
+    modules: Vec<PlannedModule>,
+    skipped: Vec<Skipped>,
+    errors: Vec<String>,
+}
+
+/// Resolve the repository, proposals, and what already exists into a plan.
+/// Err is for input that leaves nothing to plan (no such directory).
+fn plan(
+    conn: &Connection,
+    path: &str,
+    name: Option<&str>,
+    source: Option<ModuleSource>,
+    if_not_exists: bool,
+) -> Result<Plan, String> {
+    let root: PathBuf = match fs::canonicalize(path) {
+        Ok(p) if p.is_dir() => p,
+        _ => return Err(format!("not a directory: {}", path)),
+    };
+    let name = match name {
+        Some(n) => names::normalize(n),
+        None => slugify(
+            &root
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        ),
+    };
+    let mut errors = Vec::new();
+    if name.is_empty() {
+        errors.push("name must not be empty".to_string());
+    }
+    let (proposals, skipped) = match source {
+        Some(s) => propose(&root, s).unwrap_or_else(|e| {
+            errors.push(e);
+            (Vec::new(), Vec::new())
+        }),
+        None => (Vec::new(), Vec::new()),
+    };
+    // The project's modules are read once for every proposal, not once each.
+    let resolver = Resolver::new(conn);
+    let existing = resolver
+        .named("project", None, &name)
+        .map_err(|e| e.to_string())?;
+    if existing.is_some() && !if_not_exists {
+        errors.push(format!(
+            "project already exists: {} (use --if-not-exists to fill in missing modules)",
+            name
+        ));
+    }
+    let mut modules = Vec::new();
+    for p in proposals {
+        let found = match existing {
+            Some(pid) => resolver
+                .named("module", Some(pid), &p.name)
+                .map_err(|e| e.to_string())?,
+            None => None,
+        };
+        modules.push(PlannedModule {
+            name: p.name,
+            dir: p.dir,
+            existing: found,
+        });
+    }
+    Ok(Plan {
+        name,
+        root: root.display().to_string(),
+        existing,
+        modules,
+        skipped,
+        errors,
+    })
+}
+
+/// Carry out a plan with no errors in one transaction. Returns the project id
+/// and the id of every planned module.
+fn execute(conn: &Connection, plan: &Plan) -> rusqlite::Result<(i64, Vec<i64>)> {
+    let tx = db::write_tx(conn)?;
+    let ts = timestamp::now();
+    let project_id = match plan.existing {
+        Some(id) => id,
+        None => {
+            tx.execute(
+                "INSERT INTO projects (name, path, updated_at) VALUES (?1,?2,?3)",
+                params![plan.name, plan.root, ts],
+            )?;
+            tx.last_insert_rowid()
+        }
+    };
+    let mut ids = Vec::new();
+    for m in &plan.modules {
+        if let Some(id) = m.existing {
+            ids.push(id);
+            continue;
+        }
+        tx.execute(
+            "INSERT INTO modules (project_id, name, details, path, last_worked_on) VALUES (?1,?2,?3,?4,?5)",
+            params![project_id, m.name, format!("path: {}", m.dir), m.dir, ts],
+        )?;
+        ids.push(tx.last_insert_rowid());
+    }
+    tx.commit()?;
+    Ok((project_id, ids))
+}
+
+/// Register a project for an existing repository and, with `source`, propose
+/// one Draft module per directory (with its module path set). The plan is
+/// validated before anything is written and then executed in one transaction;
+/// with `if_not_exists` an existing project is reused and only missing modules
+/// are added. `dry_run` prints the plan and any errors without writing, and
+/// fails the same way the real run would.
+pub fn bootstrap(
+    conn: &Connection,
+    path: &str,
+    name: Option<&str>,
+    source: Option<ModuleSource>,
+    if_not_exists: bool,
+    dry_run: bool,
+    json: bool,
+) -> i32 {
+    let plan = match plan(conn, path, name, source, if_not_exists) {
+        Ok(p) => p,
+        Err(e) => {
+            output::err(&e);
+            return 1;
+        }
+    };
+    let failed = !plan.errors.is_empty();
+    let (project_id, module_ids) = if dry_run || failed {
+        (
+            plan.existing,
+            plan.modules.iter().map(|m| m.existing).collect(),
+        )
+    } else {
+        match execute(conn, &plan) {
+            Ok((pid, ids)) => (Some(pid), ids.into_iter().map(Some).collect::<Vec<_>>()),
+            Err(e) => {
+                output::err(&e.to_string());
+                return 2;
+            }
+        }
+    };
+
+    let created = |b: bool| if b { "create" } else { "exists" };
+    if json {
+        output::print_json(&json!({
+            "dry_run": dry_run,
+            "project": {
+                "id": project_id,
+                "name": plan.name,
+                "path": plan.root,
+                "created": plan.existing.is_none(),
+            },
+            "modules": plan.modules.iter().zip(&module_ids).map(|(m, id)| json!({
+                "id": id,
+                "name": m.name,
+                "path": m.dir,
+                "created": m.existing.is_none(),
+            })).collect::<Vec<_>>(),
+            "skipped": plan.skipped.iter().map(|s| json!({
+                "path": s.dir, "reason": s.reason,
+            })).collect::<Vec<_>>(),
+            "errors": plan.errors,
+        }));
+    } else {
+        let verb = if dry_run || failed {
+            "would bootstrap"
+        } else {
+            "bootstrapped"
+        };
+        output::print_plain(&format!("{} project {} ({})", verb, plan.name, plan.root));
+        outln!(
+            "  {:<7} project {}",
+            created(plan.existing.is_none()),
+            plan.name
+        );
+        for m in &plan.modules {
+            outln!(
+                "  {:<7} module  {:<24} {}",
+                created(m.existing.is_none()),
+                m.name,
+                m.dir
+            );
+        }
+        for s in &plan.skipped {
+            outln!("  {:<7} {:<32} {}", "skip", s.dir, s.reason);
+        }
+    }
+    if failed {
+        for e in &plan.errors {
+            output::err(e);
+        }
+        return 1;
+    }
+    0
+}
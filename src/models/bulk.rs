@@ -0,0 +1,296 @@
+use crate::db;
+use crate::error::Error;
+use crate::models::dependency::{self, Dep};
+use crate::models::history;
+use crate::models::lift::{self, ParentPolicy};
+use crate::models::progress;
+use crate::models::reason;
+use crate::output;
+use crate::state::{validate_transition, State};
+use crate::timestamp;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+/// One child of the parent and what became of it.
+struct Outcome {
+    id: i64,
+    name: String,
+    from: String,
+    details: String,
+    /// `transitioned`, `no-op`, or `invalid`; None while a task waits on a
+    /// dependency that may still complete in the same batch.
+    result: Option<&'static str>,
+    error: Option<String>,
+}
+
+impl Outcome {
+    fn invalid(&mut self, error: String) {
+        self.result = Some("invalid");
+        self.error = Some(error);
+    }
+}
+
+/// Why `o` cannot make the move, apart from task dependencies, which are
+/// settled in the transaction.
+fn check(
+    conn: &Connection,
+    kind: &str,
+    o: &Outcome,
+    locked: bool,
+    to: &State,
+) -> rusqlite::Result<Option<String>> {
+    if locked && *to != State::Amending {
+        return Ok(Some(format!(
+            "{} {} is locked; unlock it before a transition to {}",
+            kind, o.name, to
+        )));
+    }
+    if let Err(e) = validate_transition(&o.from, to) {
+        return Ok(Some(e.to_string()));
+    }
+    if kind == "feature" && *to == State::Complete {
+        let left = progress::outstanding(conn, kind, o.id)?;
+        if !left.is_empty() {
+            let list: Vec<String> = left.iter().map(|(n, s)| format!("{} [{}]", n, s)).collect();
+            return Ok(Some(format!(
+                "has {} incomplete task(s): {}",
+                left.len(),
+                list.join(", ")
+            )));
+        }
+    }
+    Ok(None)
+}
+
+/// Move every task of a feature (`kind` "task") or every feature of a module
+/// (`kind` "feature") to `to` in one transaction. A child already there is a
+/// no-op; one the move is invalid for (a refused transition, a lock, a
+/// feature with tasks left, a task waiting on an incomplete dependency) is
+/// reported and left alone, unless `strict`, which changes nothing at all.
+/// Tasks depending on others in the same batch go once those have moved.
+/// Parents are handled per `parents`, as for a single transition.
+#[allow(clippy::too_many_arguments)]
+pub fn transition_all(
+    conn: &Connection,
+    kind: &'static str,
+    parent_id: i64,
+    to: &State,
+    parents: ParentPolicy,
+    reason: Option<&str>,
+    strict: bool,
+    json: bool,
+) -> i32 {
+    let (table, parent_kind, parent_table, parent_column, order) = if kind == "task" {
+        ("tasks", "feature", "features", "feature_id", "position, id")
+    } else {
+        ("features", "module", "modules", "module_id", "id")
+    };
+    let parent_name: String = match conn.query_row(
+        &format!("SELECT name FROM {} WHERE id=?1", parent_table),
+        params![parent_id],
+        |r| r.get(0),
+    ) {
+        Ok(n) => n,
+        Err(_) => {
+            return output::fail(&Error::not_found(
+                parent_kind,
+                format!("{} not found: {}", parent_kind, parent_id),
+            ));
+        }
+    };
+    let locked_column = if kind == "task" { "0" } else { "locked" };
+    let rows: rusqlite::Result<Vec<(Outcome, bool)>> = conn
+        .prepare(&format!(
+            "SELECT id, name, state, details, {} FROM {} WHERE {}=?1 ORDER BY {}",
+            locked_column, table, parent_column, order
+        ))
+        .and_then(|mut stmt| {
+            stmt.query_map(params![parent_id], |r| {
+                Ok((
+                    Outcome {
+                        id: r.get(0)?,
+                        name: r.get(1)?,
+                        from: r.get(2)?,
+                        details: r.get(3)?,
+                        result: None,
+                        error: None,
+                    },
+                    r.get::<_, i64>(4)? != 0,
+                ))
+            })?
+            .collect()
+        });
+    let rows: Vec<(Outcome, bool)> = match rows {
+        Ok(rows) => rows,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let mut outcomes: Vec<Outcome> = Vec::new();
+    for (mut o, locked) in rows {
+        match check(conn, kind, &o, locked, to) {
+            Ok(Some(e)) => o.invalid(e),
+            Ok(None) if o.from == to.to_string() => o.result = Some("no-op"),
+            Ok(None) => {}
+            Err(e) => return output::fail(&e.into()),
+        }
+        outcomes.push(o);
+    }
+
+    let ts = timestamp::now();
+    let reason = reason::given(reason);
+    let tx = match db::write_tx(conn) {
+        Ok(tx) => tx,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let mut lifted = Vec::new();
+    if let Some(first) = outcomes.iter().find(|o| o.result.is_none()) {
+        let behind = match lift::behind(conn, kind, first.id, to) {
+            Ok(b) => b,
+            Err(e) => return output::fail(&e.into()),
+        };
+        match parents {
+            ParentPolicy::Lift => lifted = behind,
+            ParentPolicy::Warn => lift::warn(&behind),
+            ParentPolicy::Ignore => {}
+        }
+    }
+    if let Err(e) = lift::apply(&tx, &lifted, &ts) {
+        return output::fail(&e);
+    }
+    let gated = kind == "task" && matches!(to, State::Building | State::Complete);
+    // Passes until nothing more can move: a task waiting on another in the
+    // batch goes in the pass after that one has.
+    loop {
+        let mut moved = false;
+        for o in outcomes.iter_mut().filter(|o| o.result.is_none()) {
+            if gated {
+                match dependency::depends_on(&tx, o.id) {
+                    Ok(deps) if deps.iter().any(|d| d.state != "Complete") => continue,
+                    Ok(_) => {}
+                    Err(e) => return output::fail(&e.into()),
+                }
+            }
+            let details = reason::append(&o.details, &o.from, to, reason, &ts);
+            let written = tx
+                .execute(
+                    &format!(
+                        "UPDATE {} SET state=?1, last_worked_on=?2, details=?3, last_transition_reason=?4 WHERE id=?5",
+                        table
+                    ),
+                    params![to.to_string(), ts, details, reason.unwrap_or(""), o.id],
+                )
+                .and_then(|_| history::record_state(&tx, kind, o.id, &o.from, &to.to_string(), reason, &ts));
+            if let Err(e) = written {
+                output::err(&e.to_string());
+                return 2;
+            }
+            o.result = Some("transitioned");
+            moved = true;
+        }
+        if !moved {
+            break;
+        }
+    }
+    for o in outcomes.iter_mut().filter(|o| o.result.is_none()) {
+        let deps = match dependency::depends_on(&tx, o.id) {
+            Ok(d) => d,
+            Err(e) => return output::fail(&e.into()),
+        };
+        let unmet: Vec<String> = deps
+            .iter()
+            .filter(|d| d.state != "Complete")
+            .map(Dep::label)
+            .collect();
+        o.invalid(format!(
+            "depends on {} incomplete task(s): {}",
+            unmet.len(),
+            unmet.join(", ")
+        ));
+    }
+
+    let invalid = outcomes
+        .iter()
+        .filter(|o| o.result == Some("invalid"))
+        .count();
+    if strict && invalid > 0 {
+        drop(tx);
+        for o in outcomes.iter().filter(|o| o.result == Some("invalid")) {
+            output::err(&format!(
+                "{} {}: {}",
+                kind,
+                o.name,
+                o.error.as_deref().unwrap_or("")
+            ));
+        }
+        output::err(&format!(
+            "{} of {} {}(s) in {} cannot move to {}; nothing was changed (--strict)",
+            invalid,
+            outcomes.len(),
+            kind,
+            parent_name,
+            to
+        ));
+        return 1;
+    }
+    if let Err(e) = tx.commit() {
+        output::err(&e.to_string());
+        return 2;
+    }
+    for o in outcomes.iter().filter(|o| o.result == Some("transitioned")) {
+        reason::warn_if_missing(kind, &o.name, to, reason);
+    }
+
+    if json {
+        output::print_json(&Value::Array(
+            outcomes
+                .iter()
+                .map(|o| {
+                    let mut v = json!({
+                        kind: o.name,
+                        "id": o.id,
+                        "from": o.from,
+                        "to": to.to_string(),
+                        "result": o.result,
+                    });
+                    if let Some(e) = &o.error {
+                        v["error"] = json!(e);
+                    }
+                    v
+                })
+                .collect(),
+        ));
+    } else {
+        lift::report(&lifted);
+        for o in &outcomes {
+            match (o.result, &o.error) {
+                (Some("transitioned"), _) => {
+                    outln!("{} {}: {} → {}", kind, o.name, o.from, to)
+                }
+                (Some("no-op"), _) => outln!("{} {}: already {}", kind, o.name, to),
+                (_, e) => outln!(
+                    "{} {}: invalid: {}",
+                    kind,
+                    o.name,
+                    e.as_deref().unwrap_or("")
+                ),
+            }
+        }
+        let count = |r: &str| outcomes.iter().filter(|o| o.result == Some(r)).count();
+        output::print_plain(&format!(
+            "{} {}(s) in {}: {} transitioned, {} already {}, {} invalid",
+            outcomes.len(),
+            kind,
+            parent_name,
+            count("transitioned"),
+            count("no-op"),
+            to,
+            invalid
+        ));
+    }
+    0
+}
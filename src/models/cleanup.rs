@@ -0,0 +1,228 @@
+use crate::db;
+use crate::models::ancestry;
+use crate::output;
+use crate::resolve;
+use crate::timestamp;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::io::{IsTerminal, Write};
+
+/// Which abandoned drafts to look for. By default only the emptiest qualify:
+/// Draft, no description or details, no children, no research links, not
+/// locked or under a locked parent, and untouched for `older_than_days`.
+pub struct Criteria {
+    pub project_id: Option<i64>,
+    pub older_than_days: i64,
+    /// Also match drafts that have a description or details
+    pub include_described: bool,
+    /// Also match drafts linked to research; the links go with them
+    pub include_linked: bool,
+}
+
+/// A draft that matched, with enough to show where it sits.
+pub struct Candidate {
+    pub kind: &'static str,
+    pub id: i64,
+    pub name: String,
+    pub last_worked_on: String,
+    /// `project > module > ...` down to the candidate.
+    pub breadcrumb: String,
+}
+
+// Each query binds ?1 project id (or NULL), ?2 cutoff, ?3 include_described,
+// ?4 include_linked. Children are never relaxed: a draft with anything under
+// it is not abandoned.
+const TASKS_SQL: &str = "SELECT t.id, t.name, t.last_worked_on FROM tasks t
+     JOIN features f ON f.id = t.feature_id
+     JOIN modules m ON m.id = f.module_id
+     WHERE t.state = 'Draft' AND t.last_worked_on <= ?2 AND f.locked = 0
+       AND (?1 IS NULL OR m.project_id = ?1)
+       AND (?3 OR (TRIM(t.description) = '' AND TRIM(t.details) = ''))
+       AND (?4 OR NOT EXISTS (SELECT 1 FROM research_tasks WHERE task_id = t.id))
+     ORDER BY t.id";
+
+const FEATURES_SQL: &str = "SELECT f.id, f.name, f.last_worked_on FROM features f
+     JOIN modules m ON m.id = f.module_id
+     WHERE f.state = 'Draft' AND f.last_worked_on <= ?2 AND f.locked = 0 AND m.locked = 0
+       AND (?1 IS NULL OR m.project_id = ?1)
+       AND (?3 OR (TRIM(f.description) = '' AND TRIM(f.details) = ''))
+       AND (?4 OR NOT EXISTS (SELECT 1 FROM research_features WHERE feature_id = f.id))
+       AND NOT EXISTS (SELECT 1 FROM tasks WHERE feature_id = f.id)
+     ORDER BY f.id";
+
+const MODULES_SQL: &str = "SELECT m.id, m.name, m.last_worked_on FROM modules m
+     WHERE m.state = 'Draft' AND m.last_worked_on <= ?2 AND m.locked = 0
+       AND (?1 IS NULL OR m.project_id = ?1)
+       AND (?3 OR (TRIM(m.description) = '' AND TRIM(m.details) = ''))
+       AND (?4 OR NOT EXISTS (SELECT 1 FROM research_modules WHERE module_id = m.id))
+       AND NOT EXISTS (SELECT 1 FROM features WHERE module_id = m.id)
+     ORDER BY m.id";
+
+/// Every draft matching the criteria: tasks, then features, then modules.
+pub fn candidates(conn: &Connection, c: &Criteria) -> rusqlite::Result<Vec<Candidate>> {
+    let cutoff =
+        timestamp::canonical(&(chrono::Utc::now() - chrono::Duration::days(c.older_than_days)));
+    let mut out = Vec::new();
+    for (kind, sql) in [
+        ("task", TASKS_SQL),
+        ("feature", FEATURES_SQL),
+        ("module", MODULES_SQL),
+    ] {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(
+            params![c.project_id, cutoff, c.include_described, c.include_linked],
+            |r| {
+                Ok(Candidate {
+                    kind,
+                    id: r.get(0)?,
+                    name: r.get(1)?,
+                    last_worked_on: r.get(2)?,
+                    breadcrumb: String::new(),
+                })
+            },
+        )?;
+        for row in rows {
+            let mut c = row?;
+            c.breadcrumb = ancestry::breadcrumb(conn, kind, c.id)?;
+            out.push(c);
+        }
+    }
+    Ok(out)
+}
+
+fn to_json(c: &Candidate) -> Value {
+    json!({
+        "type": c.kind,
+        "id": c.id,
+        "ref": resolve::short_ref(c.kind, c.id),
+        "name": c.name,
+        "breadcrumb": c.breadcrumb,
+        "last_worked_on": c.last_worked_on,
+    })
+}
+
+fn line(c: &Candidate) -> String {
+    format!(
+        "  {:<10} {:<5} {:<24} {}  (last worked on {})",
+        c.kind,
+        resolve::short_ref(c.kind, c.id),
+        c.name,
+        c.breadcrumb,
+        c.last_worked_on
+    )
+}
+
+fn confirm(found: &[Candidate]) -> bool {
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+    outln!("{} abandoned draft(s):", found.len());
+    for c in found {
+        outln!("{}", line(c));
+    }
+    out!("remove them? [y/N] ");
+    std::io::stdout().flush().ok();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok();
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// "2 task(s), 1 feature(s)"; kinds with nothing are left out.
+fn summary(found: &[Candidate]) -> String {
+    ["task", "feature", "module"]
+        .iter()
+        .map(|k| (k, found.iter().filter(|c| c.kind == *k).count()))
+        .filter(|(_, n)| *n > 0)
+        .map(|(k, n)| format!("{} {}(s)", n, k))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Find abandoned drafts and remove them in one transaction. With `dry_run`
+/// they are only listed; without `yes`, an interactive terminal is asked to
+/// confirm and anywhere else they are only listed.
+pub fn cleanup(
+    conn: &Connection,
+    criteria: &Criteria,
+    dry_run: bool,
+    yes: bool,
+    json: bool,
+) -> i32 {
+    let found = match candidates(conn, criteria) {
+        Ok(f) => f,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    if found.is_empty() {
+        if json {
+            output::print_json(&json!({"dry_run": dry_run, "removed": []}));
+        } else {
+            output::print_empty("no abandoned drafts found");
+        }
+        return 0;
+    }
+
+    let confirmed = !dry_run && (yes || (!json && confirm(&found)));
+    if !confirmed {
+        if json {
+            output::print_json(&json!({
+                "dry_run": true,
+                "candidates": found.iter().map(to_json).collect::<Vec<_>>(),
+            }));
+        } else if dry_run || !std::io::stdin().is_terminal() {
+            output::print_plain(&format!(
+                "would remove {} abandoned draft(s): {}",
+                found.len(),
+                summary(&found)
+            ));
+            for c in &found {
+                outln!("{}", line(c));
+            }
+            if !dry_run {
+                output::print_plain("pass --yes to remove them");
+            }
+        }
+        return 0;
+    }
+
+    // Breadcrumbs are read before anything goes, while the parents still resolve.
+    let removed: Vec<Value> = found.iter().map(to_json).collect();
+    let lines: Vec<String> = found.iter().map(line).collect();
+    let tx = match db::write_tx(conn) {
+        Ok(tx) => tx,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    for c in &found {
+        let table = match c.kind {
+            "task" => "tasks",
+            "feature" => "features",
+            _ => "modules",
+        };
+        if let Err(e) = tx.execute(&format!("DELETE FROM {} WHERE id=?1", table), params![c.id]) {
+            output::err(&e.to_string());
+            return 2;
+        }
+    }
+    if let Err(e) = tx.commit() {
+        output::err(&e.to_string());
+        return 2;
+    }
+    if json {
+        output::print_json(&json!({"dry_run": false, "removed": removed}));
+    } else {
+        output::print_plain(&format!(
+            "removed {} abandoned draft(s): {}",
+            found.len(),
+            summary(&found)
+        ));
+        for l in &lines {
+            outln!("{}", l);
+        }
+    }
+    0
+}
@@ -0,0 +1,228 @@
+use crate::output;
+use crate::timestamp;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// The sticky project/module/feature recorded by `lopen-memory use` for one
+/// working directory. Task and feature commands fall back to it when their
+/// scoping flags are omitted.
+pub struct Context {
+    pub cwd: String,
+    pub project_id: i64,
+    pub project: String,
+    pub module_id: Option<i64>,
+    pub module: Option<String>,
+    pub feature_id: Option<i64>,
+    pub feature: Option<String>,
+    pub updated_at: String,
+}
+
+/// Contexts are keyed by the working directory so separate sessions in
+/// separate checkouts do not pick up each other's defaults.
+fn cwd() -> String {
+    std::env::current_dir()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| ".".to_string())
+}
+
+/// The context for the current directory, if one has been set.
+pub fn current(conn: &Connection) -> Option<Context> {
+    conn.query_row(
+        "SELECT c.cwd, c.project_id, p.name, c.module_id, m.name, c.feature_id, f.name, c.updated_at
+         FROM contexts c
+         JOIN projects p ON p.id = c.project_id
+         LEFT JOIN modules m ON m.id = c.module_id
+         LEFT JOIN features f ON f.id = c.feature_id
+         WHERE c.cwd = ?1",
+        params![cwd()],
+        |r| {
+            Ok(Context {
+                cwd: r.get(0)?,
+                project_id: r.get(1)?,
+                project: r.get(2)?,
+                module_id: r.get(3)?,
+                module: r.get(4)?,
+                feature_id: r.get(5)?,
+                feature: r.get(6)?,
+                updated_at: r.get(7)?,
+            })
+        },
+    )
+    .optional()
+    .unwrap_or(None)
+}
+
+/// Tell the user a scope came from the `use` context rather than a flag. Goes
+/// to stderr so it never mixes into JSON output.
+pub fn note(kind: &str, name: &str) {
+    eprintln!("using {} {} from `lopen-memory use` context", kind, name);
+}
+
+/// The module whose path (under its project's path) contains the current
+/// directory, deepest first: (id, name, module path). Limited to one project
+/// when given.
+pub fn module_from_cwd(
+    conn: &Connection,
+    project_id: Option<i64>,
+) -> rusqlite::Result<Option<(i64, String, String)>> {
+    let Ok(dir) = std::env::current_dir() else {
+        return Ok(None);
+    };
+    let dir = std::fs::canonicalize(&dir).unwrap_or(dir);
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.name, m.path, p.path FROM modules m JOIN projects p ON p.id = m.project_id
+         WHERE m.path <> '' AND (?1 IS NULL OR m.project_id = ?1)",
+    )?;
+    let rows = stmt
+        .query_map(params![project_id], |r| {
+            Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+        })?
+        .collect::<rusqlite::Result<Vec<(i64, String, String, String)>>>()?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(id, name, path, root)| {
+            let full = Path::new(&root).join(&path);
+            let full = std::fs::canonicalize(&full).unwrap_or(full);
+            dir.starts_with(&full)
+                .then(|| (full.components().count(), (id, name, path)))
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, m)| m))
+}
+
+/// A module picked without a --module flag, and where it came from: the
+/// `use` context, or a module path containing the current directory.
+pub struct ModuleScope {
+    pub id: i64,
+    pub name: String,
+    pub path: Option<String>,
+}
+
+impl ModuleScope {
+    /// Tell the user where the module came from, on stderr like `note`.
+    pub fn note(&self) {
+        match &self.path {
+            Some(path) => eprintln!("using module {} from module path {}", self.name, path),
+            None => note("module", &self.name),
+        }
+    }
+}
+
+/// The module to fall back to when no --module is given: the `use` context
+/// module, else the module whose path contains the current directory (within
+/// the context project, if one is set).
+pub fn module_scope(conn: &Connection) -> rusqlite::Result<Option<ModuleScope>> {
+    let ctx = current(conn);
+    if let Some((id, name)) = ctx.as_ref().and_then(|c| c.module_id.zip(c.module.clone())) {
+        return Ok(Some(ModuleScope {
+            id,
+            name,
+            path: None,
+        }));
+    }
+    let found = module_from_cwd(conn, ctx.map(|c| c.project_id))?;
+    Ok(found.map(|(id, name, path)| ModuleScope {
+        id,
+        name,
+        path: Some(path),
+    }))
+}
+
+pub fn context_to_json(c: &Context) -> Value {
+    json!({
+        "cwd": c.cwd,
+        "project_id": c.project_id,
+        "project": c.project,
+        "module_id": c.module_id,
+        "module": c.module,
+        "feature_id": c.feature_id,
+        "feature": c.feature,
+        "updated_at": c.updated_at,
+    })
+}
+
+fn print_context(c: &Context) {
+    outln!("{}", output::field("cwd", &c.cwd));
+    outln!("{}", output::field("project", &c.project));
+    outln!(
+        "{}",
+        output::field("module", c.module.as_deref().unwrap_or("-"))
+    );
+    outln!(
+        "{}",
+        output::field("feature", c.feature.as_deref().unwrap_or("-"))
+    );
+}
+
+pub fn set(
+    conn: &Connection,
+    project_id: i64,
+    module_id: Option<i64>,
+    feature_id: Option<i64>,
+    json: bool,
+) -> i32 {
+    if let Err(e) = conn.execute(
+        "INSERT INTO contexts (cwd, project_id, module_id, feature_id, updated_at)
+         VALUES (?1,?2,?3,?4,?5)
+         ON CONFLICT(cwd) DO UPDATE SET project_id=excluded.project_id,
+             module_id=excluded.module_id, feature_id=excluded.feature_id,
+             updated_at=excluded.updated_at",
+        params![cwd(), project_id, module_id, feature_id, timestamp::now()],
+    ) {
+        output::err(&e.to_string());
+        return 2;
+    }
+    let c = match current(conn) {
+        Some(c) => c,
+        None => {
+            output::err("context was not saved");
+            return 2;
+        }
+    };
+    if json {
+        output::print_json(&context_to_json(&c));
+    } else {
+        let mut scope = format!("project {}", c.project);
+        if let Some(m) = &c.module {
+            scope.push_str(&format!(", module {}", m));
+        }
+        if let Some(f) = &c.feature {
+            scope.push_str(&format!(", feature {}", f));
+        }
+        output::print_plain(&format!("using {} in {}", scope, c.cwd));
+    }
+    0
+}
+
+pub fn show(conn: &Connection, json: bool) -> i32 {
+    let c = current(conn);
+    if json {
+        output::print_json(&c.as_ref().map(context_to_json).unwrap_or(Value::Null));
+        return 0;
+    }
+    match c {
+        Some(c) => print_context(&c),
+        None => output::print_plain(&format!("no context set for {}", cwd())),
+    }
+    0
+}
+
+pub fn clear(conn: &Connection, json: bool) -> i32 {
+    let dir = cwd();
+    let removed = match conn.execute("DELETE FROM contexts WHERE cwd=?1", params![dir]) {
+        Ok(n) => n > 0,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    if json {
+        output::print_json(&json!({ "cwd": dir, "cleared": removed }));
+    } else if removed {
+        output::print_plain(&format!("cleared context for {}", dir));
+    } else {
+        output::print_plain(&format!("no context set for {}", dir));
+    }
+    0
+}
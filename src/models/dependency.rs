@@ -0,0 +1,196 @@
+use crate::error::Error;
+use crate::output;
+use crate::resolve;
+use crate::state::State;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+/// A task on the other end of a dependency.
+pub struct Dep {
+    pub id: i64,
+    pub name: String,
+    pub state: String,
+}
+
+impl Dep {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id, "ref": resolve::short_ref("task", self.id),
+            "name": self.name, "state": self.state,
+        })
+    }
+
+    /// `wire-up [Draft]`
+    pub fn label(&self) -> String {
+        format!("{} [{}]", self.name, self.state)
+    }
+}
+
+/// A task whose dependencies are all Complete and which is not Complete
+/// itself, as SQL over a `tasks` row aliased `t`.
+pub const READY_SQL: &str = "t.state <> 'Complete' AND NOT EXISTS (
+         SELECT 1 FROM task_dependencies d JOIN tasks b ON b.id = d.depends_on_task_id
+         WHERE d.task_id = t.id AND b.state <> 'Complete')";
+
+fn query(conn: &Connection, sql: &str, id: i64) -> rusqlite::Result<Vec<Dep>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params![id], |r| {
+        Ok(Dep {
+            id: r.get(0)?,
+            name: r.get(1)?,
+            state: r.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// The tasks `id` waits on, in work order.
+pub fn depends_on(conn: &Connection, id: i64) -> rusqlite::Result<Vec<Dep>> {
+    query(
+        conn,
+        "SELECT t.id, t.name, t.state FROM task_dependencies d
+         JOIN tasks t ON t.id = d.depends_on_task_id
+         WHERE d.task_id = ?1 ORDER BY t.feature_id, t.position, t.id",
+        id,
+    )
+}
+
+/// The tasks waiting on `id`, in work order.
+pub fn blocks(conn: &Connection, id: i64) -> rusqlite::Result<Vec<Dep>> {
+    query(
+        conn,
+        "SELECT t.id, t.name, t.state FROM task_dependencies d
+         JOIN tasks t ON t.id = d.task_id
+         WHERE d.depends_on_task_id = ?1 ORDER BY t.feature_id, t.position, t.id",
+        id,
+    )
+}
+
+fn task_name(conn: &Connection, id: i64) -> String {
+    conn.query_row("SELECT name FROM tasks WHERE id=?1", params![id], |r| {
+        r.get(0)
+    })
+    .unwrap_or_default()
+}
+
+/// Whether `from` already waits on `to`, directly or through other tasks.
+fn reaches(conn: &Connection, from: i64, to: i64) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "WITH RECURSIVE chain(id) AS (
+             SELECT ?1
+             UNION SELECT d.depends_on_task_id FROM task_dependencies d JOIN chain c ON d.task_id = c.id
+         )
+         SELECT COUNT(*) > 0 FROM chain WHERE id = ?2",
+        params![from, to],
+        |r| r.get(0),
+    )
+}
+
+/// Record that `task` cannot start until `on` is Complete. Refused when `on`
+/// already waits on `task`, directly or not, since neither could ever start.
+pub fn depend(conn: &Connection, task: i64, on: i64, json: bool) -> i32 {
+    let (name, on_name) = (task_name(conn, task), task_name(conn, on));
+    if task == on {
+        output::err(&format!("task {} cannot depend on itself", name));
+        return 1;
+    }
+    match reaches(conn, on, task) {
+        Ok(true) => {
+            return output::fail(&Error::Conflict(format!(
+                "task {} already depends on {}; adding this would make a cycle",
+                on_name, name
+            )));
+        }
+        Ok(false) => {}
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    }
+    let added = match conn.execute(
+        "INSERT OR IGNORE INTO task_dependencies (task_id, depends_on_task_id) VALUES (?1,?2)",
+        params![task, on],
+    ) {
+        Ok(n) => n > 0,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    if json {
+        output::print_json(&json!({
+            "task": name, "depends_on": on_name, "added": added,
+        }));
+    } else if added {
+        output::print_plain(&format!("task {} now depends on {}", name, on_name));
+    } else {
+        output::print_plain(&format!("task {} already depends on {}", name, on_name));
+    }
+    0
+}
+
+pub fn undepend(conn: &Connection, task: i64, on: i64, json: bool) -> i32 {
+    let (name, on_name) = (task_name(conn, task), task_name(conn, on));
+    match conn.execute(
+        "DELETE FROM task_dependencies WHERE task_id=?1 AND depends_on_task_id=?2",
+        params![task, on],
+    ) {
+        Ok(0) => output::fail(&Error::not_found(
+            "dependency",
+            format!("task {} does not depend on {}", name, on_name),
+        )),
+        Ok(_) => {
+            if json {
+                output::print_json(&json!({
+                    "task": name, "depends_on": on_name, "removed": true,
+                }));
+            } else {
+                output::print_plain(&format!("task {} no longer depends on {}", name, on_name));
+            }
+            0
+        }
+        Err(e) => {
+            output::err(&e.to_string());
+            2
+        }
+    }
+}
+
+/// Refuse moving a task to Building or Complete while something it depends
+/// on is not Complete, unless `force`, which returns the warning to give
+/// instead.
+pub fn guard(
+    conn: &Connection,
+    id: i64,
+    name: &str,
+    to: &State,
+    force: bool,
+) -> Result<Option<String>, Error> {
+    if !matches!(to, State::Building | State::Complete) {
+        return Ok(None);
+    }
+    let unmet: Vec<String> = depends_on(conn, id)?
+        .iter()
+        .filter(|d| d.state != "Complete")
+        .map(Dep::label)
+        .collect();
+    if unmet.is_empty() {
+        return Ok(None);
+    }
+    if force {
+        return Ok(Some(format!(
+            "moving task {} to {} with {} incomplete dependenc{}: {}",
+            name,
+            to,
+            unmet.len(),
+            if unmet.len() == 1 { "y" } else { "ies" },
+            unmet.join(", ")
+        )));
+    }
+    Err(Error::InvalidState(format!(
+        "task {} depends on {} incomplete task(s): {}; complete them first or pass --force",
+        name,
+        unmet.len(),
+        unmet.join(", ")
+    )))
+}
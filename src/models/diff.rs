@@ -0,0 +1,269 @@
+use crate::output;
+use crate::resolve;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+/// Text fields each entity type has, as named by `--field`.
+fn fields(kind: &str) -> &'static [&'static str] {
+    match kind {
+        "project" => &["name", "description", "path"],
+        "module" => &["name", "description", "details", "path"],
+        "feature" | "task" => &["name", "description", "details"],
+        _ => &["name", "description", "content", "source"],
+    }
+}
+
+fn table(kind: &str) -> &'static str {
+    match kind {
+        "project" => "projects",
+        "module" => "modules",
+        "feature" => "features",
+        "task" => "tasks",
+        _ => "research",
+    }
+}
+
+/// One side of the comparison.
+struct Side {
+    kind: &'static str,
+    id: i64,
+    name: String,
+    text: String,
+}
+
+impl Side {
+    fn label(&self, field: &str) -> String {
+        format!(
+            "{} {} ({}) {}",
+            self.kind,
+            self.name,
+            resolve::short_ref(self.kind, self.id),
+            field
+        )
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "type": self.kind,
+            "id": self.id,
+            "ref": resolve::short_ref(self.kind, self.id),
+            "name": self.name,
+            "text": self.text,
+        })
+    }
+}
+
+fn read(conn: &Connection, kind: &'static str, id: i64, field: &str) -> Result<Side, String> {
+    if !fields(kind).contains(&field) {
+        return Err(format!(
+            "a {} has no {} field (expected one of: {})",
+            kind,
+            field,
+            fields(kind).join(", ")
+        ));
+    }
+    let column = if field == "content" {
+        "content_body(content)"
+    } else {
+        field
+    };
+    conn.query_row(
+        &format!("SELECT name, {} FROM {} WHERE id=?1", column, table(kind)),
+        params![id],
+        |r| {
+            Ok(Side {
+                kind,
+                id,
+                name: r.get(0)?,
+                text: r.get(1)?,
+            })
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Same,
+    Removed,
+    Added,
+}
+
+impl Op {
+    fn sign(&self) -> char {
+        match self {
+            Op::Same => ' ',
+            Op::Removed => '-',
+            Op::Added => '+',
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Op::Same => "context",
+            Op::Removed => "removed",
+            Op::Added => "added",
+        }
+    }
+}
+
+/// Every line of both texts in order, each kept, removed, or added, from the
+/// longest common subsequence of lines. Details and descriptions are a few
+/// hundred lines at most, so the quadratic table is fine.
+fn edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let (n, m) = (old.len(), new.len());
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::with_capacity(n + m);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            out.push((Op::Same, old[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push((Op::Removed, old[i]));
+            i += 1;
+        } else {
+            out.push((Op::Added, new[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+struct Hunk<'a> {
+    old_start: usize,
+    old_lines: usize,
+    new_start: usize,
+    new_lines: usize,
+    lines: Vec<(Op, &'a str)>,
+}
+
+impl Hunk<'_> {
+    /// `@@ -l,s +l,s @@`, with the count left out when it is 1 and the start
+    /// one before the hunk when it is empty, as `diff -u` writes them.
+    fn header(&self) -> String {
+        fn range(start: usize, lines: usize) -> String {
+            match lines {
+                0 => format!("{},0", start.saturating_sub(1)),
+                1 => start.to_string(),
+                _ => format!("{},{}", start, lines),
+            }
+        }
+        format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_lines),
+            range(self.new_start, self.new_lines)
+        )
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "old_start": self.old_start,
+            "old_lines": self.old_lines,
+            "new_start": self.new_start,
+            "new_lines": self.new_lines,
+            "lines": self.lines.iter().map(|(op, text)| json!({"op": op.as_str(), "text": text})).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Group the edits into hunks of changes with up to `context` unchanged lines
+/// around them; changes closer than twice that share a hunk.
+fn hunks<'a>(edits: &[(Op, &'a str)], context: usize) -> Vec<Hunk<'a>> {
+    let changed: Vec<usize> = (0..edits.len())
+        .filter(|&k| edits[k].0 != Op::Same)
+        .collect();
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &k in &changed {
+        let start = k.saturating_sub(context);
+        let end = (k + context + 1).min(edits.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+    // Line numbers (1-based) each edit starts at on either side.
+    let mut old_no = Vec::with_capacity(edits.len());
+    let mut new_no = Vec::with_capacity(edits.len());
+    let (mut o, mut n) = (1, 1);
+    for (op, _) in edits {
+        old_no.push(o);
+        new_no.push(n);
+        if *op != Op::Added {
+            o += 1;
+        }
+        if *op != Op::Removed {
+            n += 1;
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let lines = edits[start..end].to_vec();
+            Hunk {
+                old_start: old_no[start],
+                old_lines: lines.iter().filter(|(op, _)| *op != Op::Added).count(),
+                new_start: new_no[start],
+                new_lines: lines.iter().filter(|(op, _)| *op != Op::Removed).count(),
+                lines,
+            }
+        })
+        .collect()
+}
+
+/// Compare one text field of two entities, of the same type or not, as a
+/// unified diff with `context` lines around each change. Identical texts
+/// print nothing.
+pub fn diff(
+    conn: &Connection,
+    a: (&'static str, i64),
+    b: (&'static str, i64),
+    field: &str,
+    context: usize,
+    json: bool,
+) -> i32 {
+    let (old, new) = match (read(conn, a.0, a.1, field), read(conn, b.0, b.1, field)) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            output::err(&e);
+            return 1;
+        }
+    };
+    let old_lines: Vec<&str> = old.text.lines().collect();
+    let new_lines: Vec<&str> = new.text.lines().collect();
+    let edits = edits(&old_lines, &new_lines);
+    let hunks = hunks(&edits, context);
+
+    if json {
+        output::print_json(&json!({
+            "field": field,
+            "old": old.to_json(),
+            "new": new.to_json(),
+            "identical": hunks.is_empty(),
+            "hunks": hunks.iter().map(Hunk::to_json).collect::<Vec<_>>(),
+        }));
+        return 0;
+    }
+    if hunks.is_empty() {
+        return 0;
+    }
+    outln!("--- {}", old.label(field));
+    outln!("+++ {}", new.label(field));
+    for h in &hunks {
+        outln!("{}", h.header());
+        for (op, text) in &h.lines {
+            outln!("{}{}", op.sign(), text);
+        }
+    }
+    0
+}
@@ -0,0 +1,625 @@
+use crate::changes;
+use crate::error::Error;
+use crate::models::research;
+use crate::output;
+use crate::timestamp;
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// A module, feature, or task as written to Markdown.
+struct Node {
+    id: i64,
+    name: String,
+    description: String,
+    details: String,
+    state: String,
+    last_worked_on: String,
+}
+
+struct ProjectRow {
+    id: i64,
+    name: String,
+    path: String,
+    description: String,
+    completed: bool,
+    updated_at: String,
+}
+
+struct ResearchRow {
+    id: i64,
+    name: String,
+    description: String,
+    content: String,
+    source: String,
+    researched_at: String,
+}
+
+/// File name stem for a slug: anything outside `[A-Za-z0-9._-]` becomes '-'.
+/// Names that collide once lowercased (case-insensitive filesystems) get the
+/// row id appended; rows are visited in id order, so the result is stable.
+fn file_stem(name: &str, id: i64, taken: &mut HashSet<String>) -> String {
+    let mut stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if stem.is_empty() || stem.starts_with('.') {
+        stem = format!("_{}", stem);
+    }
+    if !taken.insert(stem.to_lowercase()) {
+        stem = format!("{}-{}", stem, id);
+        taken.insert(stem.to_lowercase());
+    }
+    stem
+}
+
+fn nodes(conn: &Connection, sql: &str, parent: i64) -> rusqlite::Result<Vec<Node>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt
+        .query_map(params![parent], |r| {
+            Ok(Node {
+                id: r.get(0)?,
+                name: r.get(1)?,
+                description: r.get(2)?,
+                details: r.get(3)?,
+                state: r.get(4)?,
+                last_worked_on: r.get(5)?,
+            })
+        })?
+        .collect();
+    rows
+}
+
+/// Push a text block followed by a blank line, skipping empty text.
+fn block(out: &mut String, text: &str) {
+    let text = text.trim_end();
+    if !text.is_empty() {
+        out.push_str(text);
+        out.push_str("\n\n");
+    }
+}
+
+/// Stable anchor for an entity inside its project file. Ids, not names, so
+/// renames and state changes do not break links from research files.
+fn anchor(kind: &str, id: i64) -> String {
+    format!("{}-{}", kind, id)
+}
+
+/// Write the whole store as Markdown under `out`: one file per project with
+/// the hierarchy as nested headings and tasks as checklists, one file per
+/// research record, and an index. Output depends only on the data, so
+/// successive exports diff cleanly.
+pub fn markdown(conn: &Connection, out: &str, json: bool) -> i32 {
+    match write_markdown(conn, Path::new(out)) {
+        Ok((projects, research)) => {
+            if json {
+                output::print_json(&json!({
+                    "format": "md",
+                    "out": out,
+                    "projects": projects,
+                    "research": research,
+                }));
+            } else {
+                output::print_plain(&format!(
+                    "exported {} project(s) and {} research record(s) to {}",
+                    projects.len(),
+                    research.len(),
+                    out
+                ));
+            }
+            0
+        }
+        Err(e) => {
+            output::err(&e);
+            2
+        }
+    }
+}
+
+fn write_markdown(conn: &Connection, out: &Path) -> Result<(Vec<String>, Vec<String>), String> {
+    let db = |e: rusqlite::Error| e.to_string();
+    let projects: Vec<ProjectRow> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, name, path, description, completed, updated_at FROM projects ORDER BY id",
+            )
+            .map_err(db)?;
+        let rows = stmt
+            .query_map([], |r| {
+                Ok(ProjectRow {
+                    id: r.get(0)?,
+                    name: r.get(1)?,
+                    path: r.get(2)?,
+                    description: r.get(3)?,
+                    completed: r.get::<_, i64>(4)? != 0,
+                    updated_at: r.get(5)?,
+                })
+            })
+            .map_err(db)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db)?;
+        rows
+    };
+    let research: Vec<ResearchRow> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, name, description, content_body(content), source, researched_at FROM research ORDER BY id",
+            )
+            .map_err(db)?;
+        let rows = stmt
+            .query_map([], |r| {
+                Ok(ResearchRow {
+                    id: r.get(0)?,
+                    name: r.get(1)?,
+                    description: r.get(2)?,
+                    content: r.get(3)?,
+                    source: r.get(4)?,
+                    researched_at: r.get(5)?,
+                })
+            })
+            .map_err(db)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db)?;
+        rows
+    };
+
+    let mut taken = HashSet::new();
+    let project_files: HashMap<i64, String> = projects
+        .iter()
+        .map(|p| (p.id, format!("{}.md", file_stem(&p.name, p.id, &mut taken))))
+        .collect();
+    let mut taken = HashSet::new();
+    let research_files: HashMap<i64, String> = research
+        .iter()
+        .map(|r| (r.id, format!("{}.md", file_stem(&r.name, r.id, &mut taken))))
+        .collect();
+    let research_names: HashMap<i64, &str> =
+        research.iter().map(|r| (r.id, r.name.as_str())).collect();
+
+    // Every link as (research id, kind, entity id, entity name, project id), ordered by
+    // research, then hierarchy level, then entity.
+    let links: Vec<(i64, String, i64, String, i64)> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT rp.research_id, 'project', p.id, p.name, p.id, 0
+                 FROM research_projects rp JOIN projects p ON p.id = rp.project_id
+                 UNION ALL
+                 SELECT rm.research_id, 'module', m.id, m.name, m.project_id, 1
+                 FROM research_modules rm JOIN modules m ON m.id = rm.module_id
+                 UNION ALL
+                 SELECT rf.research_id, 'feature', f.id, f.name, m.project_id, 2
+                 FROM research_features rf JOIN features f ON f.id = rf.feature_id
+                 JOIN modules m ON m.id = f.module_id
+                 UNION ALL
+                 SELECT rt.research_id, 'task', t.id, t.name, m.project_id, 3
+                 FROM research_tasks rt JOIN tasks t ON t.id = rt.task_id
+                 JOIN features f ON f.id = t.feature_id JOIN modules m ON m.id = f.module_id
+                 ORDER BY 1, 6, 3",
+            )
+            .map_err(db)?;
+        let rows = stmt
+            .query_map([], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?))
+            })
+            .map_err(db)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db)?;
+        rows
+    };
+    let research_line = |kind: &str, id: i64| -> Option<String> {
+        let refs: Vec<String> = links
+            .iter()
+            .filter(|l| l.1 == kind && l.2 == id)
+            .map(|l| {
+                format!(
+                    "[{}](../research/{})",
+                    research_names.get(&l.0).copied().unwrap_or(""),
+                    research_files.get(&l.0).map(String::as_str).unwrap_or("")
+                )
+            })
+            .collect();
+        if refs.is_empty() {
+            None
+        } else {
+            Some(format!("Research: {}\n\n", refs.join(", ")))
+        }
+    };
+
+    let project_dir = out.join("projects");
+    let research_dir = out.join("research");
+    for dir in [&project_dir, &research_dir] {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+
+    for p in &projects {
+        let mut md = format!("# Project: {}\n\n", p.name);
+        md.push_str(&format!("- Path: `{}`\n", p.path));
+        md.push_str(&format!(
+            "- Status: {}\n",
+            if p.completed {
+                "completed"
+            } else {
+                "incomplete"
+            }
+        ));
+        md.push_str(&format!("- Updated: {}\n\n", p.updated_at));
+        block(&mut md, &p.description);
+        if let Some(line) = research_line("project", p.id) {
+            md.push_str(&line);
+        }
+        let modules = nodes(
+            conn,
+            "SELECT id, name, description, details, state, last_worked_on FROM modules WHERE project_id=?1 ORDER BY id",
+            p.id,
+        )
+        .map_err(db)?;
+        for m in &modules {
+            md.push_str(&format!(
+                "<a id=\"{}\"></a>\n\n## Module: {} ({})\n\n",
+                anchor("module", m.id),
+                m.name,
+                m.state
+            ));
+            md.push_str(&format!("Last worked on: {}\n\n", m.last_worked_on));
+            block(&mut md, &m.description);
+            block(&mut md, &m.details);
+            if let Some(line) = research_line("module", m.id) {
+                md.push_str(&line);
+            }
+            let features = nodes(
+                conn,
+                "SELECT id, name, description, details, state, last_worked_on FROM features WHERE module_id=?1 ORDER BY id",
+                m.id,
+            )
+            .map_err(db)?;
+            for f in &features {
+                md.push_str(&format!(
+                    "<a id=\"{}\"></a>\n\n### Feature: {} ({})\n\n",
+                    anchor("feature", f.id),
+                    f.name,
+                    f.state
+                ));
+                md.push_str(&format!("Last worked on: {}\n\n", f.last_worked_on));
+                block(&mut md, &f.description);
+                block(&mut md, &f.details);
+                if let Some(line) = research_line("feature", f.id) {
+                    md.push_str(&line);
+                }
+                let tasks = nodes(
+                    conn,
+                    "SELECT id, name, description, details, state, last_worked_on FROM tasks WHERE feature_id=?1 ORDER BY position, id",
+                    f.id,
+                )
+                .map_err(db)?;
+                if tasks.is_empty() {
+                    continue;
+                }
+                md.push_str("#### Tasks\n\n");
+                for t in &tasks {
+                    let check = if t.state == "Complete" { "x" } else { " " };
+                    md.push_str(&format!(
+                        "- [{}] <a id=\"{}\"></a>**{}** ({})",
+                        check,
+                        anchor("task", t.id),
+                        t.name,
+                        t.state
+                    ));
+                    if !t.description.trim().is_empty() {
+                        md.push_str(&format!(" — {}", t.description.trim()));
+                    }
+                    md.push('\n');
+                    // Indented so details stay part of the checklist item.
+                    for line in t.details.trim_end().lines() {
+                        if !line.is_empty() {
+                            md.push_str("  ");
+                        }
+                        md.push_str(line);
+                        md.push('\n');
+                    }
+                    if let Some(line) = research_line("task", t.id) {
+                        md.push_str(&format!("  {}", line.trim_end()));
+                        md.push('\n');
+                    }
+                }
+                md.push('\n');
+            }
+        }
+        let file = project_dir.join(&project_files[&p.id]);
+        fs::write(&file, md.trim_end().to_string() + "\n")
+            .map_err(|e| format!("{}: {}", file.display(), e))?;
+    }
+
+    for r in &research {
+        let mut md = format!("# Research: {}\n\n", r.name);
+        if !r.source.is_empty() {
+            md.push_str(&format!("- Source: {}\n", r.source));
+        }
+        md.push_str(&format!("- Researched: {}\n\n", r.researched_at));
+        block(&mut md, &r.description);
+        if !r.content.trim().is_empty() {
+            md.push_str("## Content\n\n");
+            block(&mut md, &r.content);
+        }
+        let linked: Vec<&(i64, String, i64, String, i64)> =
+            links.iter().filter(|l| l.0 == r.id).collect();
+        if !linked.is_empty() {
+            md.push_str("## Linked to\n\n");
+            for (_, kind, id, name, project_id) in linked {
+                let file = project_files
+                    .get(project_id)
+                    .map(String::as_str)
+                    .unwrap_or("");
+                let target = if kind == "project" {
+                    format!("../projects/{}", file)
+                } else {
+                    format!("../projects/{}#{}", file, anchor(kind, *id))
+                };
+                md.push_str(&format!("- {} [{}]({})\n", kind, name, target));
+            }
+        }
+        let file = research_dir.join(&research_files[&r.id]);
+        fs::write(&file, md.trim_end().to_string() + "\n")
+            .map_err(|e| format!("{}: {}", file.display(), e))?;
+    }
+
+    // Drop files left by an earlier export for records that no longer exist.
+    for (dir, keep) in [
+        (&project_dir, &project_files),
+        (&research_dir, &research_files),
+    ] {
+        let keep: HashSet<&str> = keep.values().map(String::as_str).collect();
+        if let Ok(entries) = fs::read_dir(dir) {
+            for e in entries.filter_map(|e| e.ok()) {
+                let name = e.file_name().to_string_lossy().to_string();
+                if name.ends_with(".md") && !keep.contains(name.as_str()) {
+                    fs::remove_file(e.path()).ok();
+                }
+            }
+        }
+    }
+
+    let mut index = String::from("# lopen-memory export\n\n## Projects\n\n");
+    for p in &projects {
+        index.push_str(&format!(
+            "- [{}](projects/{})\n",
+            p.name, project_files[&p.id]
+        ));
+    }
+    index.push_str("\n## Research\n\n");
+    for r in &research {
+        index.push_str(&format!(
+            "- [{}](research/{})",
+            r.name, research_files[&r.id]
+        ));
+        if !r.description.is_empty() {
+            index.push_str(&format!(" — {}", r.description));
+        }
+        index.push('\n');
+    }
+    let file = out.join("index.md");
+    fs::write(&file, index).map_err(|e| format!("{}: {}", file.display(), e))?;
+
+    let mut p_files: Vec<String> = projects
+        .iter()
+        .map(|p| format!("projects/{}", project_files[&p.id]))
+        .collect();
+    p_files.sort();
+    let mut r_files: Vec<String> = research
+        .iter()
+        .map(|r| format!("research/{}", research_files[&r.id]))
+        .collect();
+    r_files.sort();
+    Ok((p_files, r_files))
+}
+
+/// Version of the JSON export document, bumped when its shape changes in a
+/// way an importer has to know about.
+pub const JSON_FORMAT_VERSION: i64 = 1;
+
+/// Columns stored as 0/1 that the document carries as booleans.
+const BOOL_COLUMNS: [&str; 4] = ["completed", "locked", "copied", "archived"];
+
+/// Every row of a query as a JSON object keyed by column name.
+fn rows(
+    conn: &Connection,
+    sql: &str,
+    args: impl rusqlite::Params,
+) -> rusqlite::Result<Vec<Map<String, Value>>> {
+    let mut stmt = conn.prepare(sql)?;
+    let names: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut out = Vec::new();
+    let mut raw = stmt.query(args)?;
+    while let Some(r) = raw.next()? {
+        let mut obj = Map::new();
+        for (i, name) in names.iter().enumerate() {
+            let v = match r.get_ref(i)? {
+                ValueRef::Null => Value::Null,
+                ValueRef::Integer(n) if BOOL_COLUMNS.contains(&name.as_str()) => json!(n != 0),
+                ValueRef::Integer(n) => json!(n),
+                ValueRef::Real(f) => json!(f),
+                ValueRef::Text(t) | ValueRef::Blob(t) => json!(String::from_utf8_lossy(t)),
+            };
+            obj.insert(name.clone(), v);
+        }
+        out.push(obj);
+    }
+    Ok(out)
+}
+
+/// The whole store, or one project's subtree and the research linked into
+/// it, as one nested document. Ids are kept so links can be rebuilt; an
+/// importer is expected to remap them.
+pub fn document(conn: &Connection, project_id: Option<i64>) -> rusqlite::Result<Value> {
+    let mut projects = rows(
+        conn,
+        "SELECT id, name, path, description, completed, updated_at FROM projects
+         WHERE ?1 IS NULL OR id=?1 ORDER BY id",
+        params![project_id],
+    )?;
+    for p in &mut projects {
+        let mut modules = rows(
+            conn,
+            "SELECT id, name, description, details, state, last_worked_on, locked, path,
+                    last_transition_reason
+             FROM modules WHERE project_id=?1 ORDER BY id",
+            params![p["id"].as_i64()],
+        )?;
+        for m in &mut modules {
+            let mut features = rows(
+                conn,
+                "SELECT id, name, description, details, state, last_worked_on, locked,
+                        last_transition_reason, priority
+                 FROM features WHERE module_id=?1 ORDER BY id",
+                params![m["id"].as_i64()],
+            )?;
+            for f in &mut features {
+                let tasks = rows(
+                    conn,
+                    "SELECT id, name, description, details, state, last_worked_on,
+                            last_transition_reason, priority
+                     FROM tasks WHERE feature_id=?1 ORDER BY position, id",
+                    params![f["id"].as_i64()],
+                )?;
+                f.insert("tasks".into(), json!(tasks));
+            }
+            m.insert("features".into(), json!(features));
+        }
+        p.insert("modules".into(), json!(modules));
+    }
+
+    let mut research = rows(
+        conn,
+        &format!(
+            "SELECT id, name, description, content_body(content) AS content, source, researched_at, created_at, updated_at,
+                    archived
+             FROM research WHERE ?1 IS NULL OR id IN ({}) ORDER BY id",
+            research::SUBTREE_RESEARCH_SQL_PROJECT
+        ),
+        params![project_id],
+    )?;
+    for r in &mut research {
+        let attachments = rows(
+            conn,
+            "SELECT path, copied, size, added_at FROM research_attachments
+             WHERE research_id=?1 ORDER BY id",
+            params![r["id"].as_i64()],
+        )?;
+        r.insert("attachments".into(), json!(attachments));
+    }
+
+    let links = json!({
+        "projects": rows(
+            conn,
+            "SELECT research_id, project_id FROM research_projects
+             WHERE ?1 IS NULL OR project_id=?1 ORDER BY research_id, project_id",
+            params![project_id],
+        )?,
+        "modules": rows(
+            conn,
+            "SELECT rm.research_id, rm.module_id FROM research_modules rm
+             JOIN modules m ON m.id = rm.module_id
+             WHERE ?1 IS NULL OR m.project_id=?1 ORDER BY rm.research_id, rm.module_id",
+            params![project_id],
+        )?,
+        "features": rows(
+            conn,
+            "SELECT rf.research_id, rf.feature_id FROM research_features rf
+             JOIN features f ON f.id = rf.feature_id JOIN modules m ON m.id = f.module_id
+             WHERE ?1 IS NULL OR m.project_id=?1 ORDER BY rf.research_id, rf.feature_id",
+            params![project_id],
+        )?,
+        "tasks": rows(
+            conn,
+            "SELECT rt.research_id, rt.task_id FROM research_tasks rt
+             JOIN tasks t ON t.id = rt.task_id JOIN features f ON f.id = t.feature_id
+             JOIN modules m ON m.id = f.module_id
+             WHERE ?1 IS NULL OR m.project_id=?1 ORDER BY rt.research_id, rt.task_id",
+            params![project_id],
+        )?,
+    });
+    // Dependencies whose waiting task is in scope; import skips any whose
+    // other end was left out.
+    let dependencies = rows(
+        conn,
+        "SELECT d.task_id, d.depends_on_task_id FROM task_dependencies d
+         JOIN tasks t ON t.id = d.task_id JOIN features f ON f.id = t.feature_id
+         JOIN modules m ON m.id = f.module_id
+         WHERE ?1 IS NULL OR m.project_id=?1 ORDER BY d.task_id, d.depends_on_task_id",
+        params![project_id],
+    )?;
+    let schema_version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+
+    Ok(json!({
+        "format": "lopen-memory",
+        "format_version": JSON_FORMAT_VERSION,
+        "version": changes::current(),
+        "schema_version": schema_version,
+        "exported_at": timestamp::now(),
+        "project_id": project_id,
+        "projects": projects,
+        "research": research,
+        "links": links,
+        "dependencies": dependencies,
+    }))
+}
+
+/// Write the JSON document to `file`, or print it when there is none. With a
+/// file, the confirmation is what is printed (as JSON with `json`).
+pub fn json_dump(
+    conn: &Connection,
+    project_id: Option<i64>,
+    file: Option<&str>,
+    json: bool,
+) -> i32 {
+    let doc = match document(conn, project_id) {
+        Ok(d) => d,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let Some(file) = file else {
+        output::print_json(&doc);
+        return 0;
+    };
+    let text = serde_json::to_string_pretty(&doc).unwrap_or_default() + "\n";
+    if let Some(parent) = Path::new(file).parent() {
+        if !parent.as_os_str().is_empty() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                return output::fail(&Error::Io(format!(
+                    "failed to create {}: {}",
+                    parent.display(),
+                    e
+                )));
+            }
+        }
+    }
+    if let Err(e) = fs::write(file, text) {
+        return output::fail(&Error::Io(format!("failed to write {}: {}", file, e)));
+    }
+    let projects = doc["projects"].as_array().map(Vec::len).unwrap_or(0);
+    let research = doc["research"].as_array().map(Vec::len).unwrap_or(0);
+    if json {
+        output::print_json(&json!({
+            "format": "json",
+            "file": file,
+            "projects": projects,
+            "research": research,
+        }));
+    } else {
+        output::print_plain(&format!(
+            "exported {} project(s) and {} research record(s) to {}",
+            projects, research, file
+        ));
+    }
+    0
+}
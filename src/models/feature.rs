@@ -1,8 +1,32 @@
+use crate::db;
+use crate::error::Error;
+use crate::fields::{self, Page, Sort};
+use crate::models::ancestry;
+use crate::models::dependency::{self, Dep};
+use crate::models::history;
+use crate::models::lift::{self, ParentPolicy};
+use crate::models::module;
+use crate::models::placement::Placement;
+use crate::models::progress;
+use crate::models::reason;
+use crate::models::references::{self, Renamed};
+use crate::models::research;
+use crate::models::task;
+use crate::models::transition::Transitioned;
+use crate::models::trash::{self, Removed};
+use crate::names;
 use crate::output;
-use crate::state::{validate_transition, State};
+use crate::priority::{self, Priority};
+use crate::resolve::{self, Resolver};
+use crate::state::{self, validate_transition, State};
+use crate::timestamp;
 use rusqlite::{params, Connection};
+use serde::Serialize;
 use serde_json::{json, Value};
 
+/// Serializes to the record `feature show --json` starts from.
+#[derive(Debug, Clone, Serialize)]
+#[serde(into = "Value")]
 pub struct Feature {
     pub id: i64,
     pub module_id: i64,
@@ -11,32 +35,60 @@ pub struct Feature {
     pub details: String,
     pub state: String,
     pub last_worked_on: String,
+    pub locked: bool,
+    pub priority: String,
 }
 
-fn now() -> String {
-    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
-}
-
-fn load(conn: &Connection, id: i64) -> Result<Feature, String> {
+/// The feature with this id.
+pub fn get(conn: &Connection, id: i64) -> Result<Feature, Error> {
     conn.query_row(
-        "SELECT id, module_id, name, description, details, state, last_worked_on FROM features WHERE id=?1",
+        "SELECT id, module_id, name, description, details, state, last_worked_on, locked, priority FROM features WHERE id=?1",
         params![id],
         |r| Ok(Feature {
             id: r.get(0)?, module_id: r.get(1)?, name: r.get(2)?,
             description: r.get(3)?, details: r.get(4)?, state: r.get(5)?, last_worked_on: r.get(6)?,
+            locked: r.get::<_, i64>(7)? != 0, priority: r.get(8)?,
         }),
     )
-    .map_err(|_| format!("feature not found: {}", id))
+    .map_err(|e| Error::missing(e, "feature", id))
 }
 
+/// The keys of a listed feature's JSON, in order: the columns of `feature list`
+/// under csv and tsv.
+pub const COLUMNS: &[&str] = &[
+    "description",
+    "details",
+    "id",
+    "last_worked_on",
+    "locked",
+    "module_id",
+    "name",
+    "priority",
+    "ref",
+    "state",
+];
+
 fn feature_to_json(f: &Feature) -> Value {
     json!({
-        "id": f.id, "module_id": f.module_id, "name": f.name,
+        "id": f.id, "ref": resolve::short_ref("feature", f.id), "module_id": f.module_id, "name": f.name,
         "description": f.description, "details": f.details,
         "state": f.state, "last_worked_on": f.last_worked_on,
+        "locked": f.locked, "priority": f.priority,
     })
 }
 
+impl From<Feature> for Value {
+    fn from(f: Feature) -> Value {
+        feature_to_json(&f)
+    }
+}
+
+/// The feature's full JSON record, as `feature show` prints it before adding
+/// related entities.
+pub fn record_json(conn: &Connection, id: i64) -> Result<Value, Error> {
+    get(conn, id).map(|x| feature_to_json(&x))
+}
+
 fn module_name(conn: &Connection, module_id: i64) -> String {
     conn.query_row(
         "SELECT name FROM modules WHERE id=?1",
@@ -46,297 +98,1003 @@ fn module_name(conn: &Connection, module_id: i64) -> String {
     .unwrap_or_default()
 }
 
-pub fn add(conn: &Connection, module_id: i64, name: &str, description: &str, json: bool) -> i32 {
-    let name = name.trim();
+/// How an add or rename reports a feature name already used in the module.
+fn taken(conn: &Connection, module_id: i64, name: &str) -> String {
+    names::taken(
+        "feature",
+        name,
+        Some(("module", &module_name(conn, module_id))),
+    )
+}
+
+/// Why a locked feature refused an operation.
+fn locked_message(f: &Feature, action: &str) -> String {
+    format!(
+        "feature {} is locked; cannot {} (unlock it first)",
+        f.name, action
+    )
+}
+
+/// Add a feature to a module, starting in Draft or walked forward to `start`.
+/// Refused with Locked while the module is locked.
+pub fn create(
+    conn: &Connection,
+    module_id: i64,
+    name: &str,
+    description: &str,
+    details: &str,
+    start: Option<&State>,
+    priority: Priority,
+) -> Result<Feature, Error> {
+    let name = names::normalize(name);
+    if name.is_empty() {
+        return Err(Error::Invalid("name must not be empty".into()));
+    }
+    if let Some(existing) = names::clash(
+        conn,
+        "features",
+        Some(("module_id", module_id)),
+        &name,
+        None,
+    )? {
+        return Err(Error::Conflict(taken(conn, module_id, &existing)));
+    }
+    if let Some(mname) = module::locked_name(conn, module_id) {
+        return Err(Error::Locked(format!(
+            "module {} is locked; cannot add feature (unlock it first)",
+            mname
+        )));
+    }
+    let walked = state::start_walk(start, "feature", &name)?;
+    let state = walked.last().unwrap().to_string();
+    let ts = timestamp::now();
+    let tx = db::write_tx(conn)?;
+    match tx.execute(
+        "INSERT INTO features (module_id, name, description, details, state, last_worked_on, priority) VALUES (?1,?2,?3,?4,?5,?6,?7)",
+        params![module_id, name, description, details, state, ts, priority.to_string()],
+    ) {
+        Ok(_) => {}
+        Err(e) if db::name_taken(&e) => return Err(Error::Conflict(taken(conn, module_id, &name))),
+        Err(e) => return Err(e.into()),
+    }
+    let id = tx.last_insert_rowid();
+    history::record_walk(&tx, "feature", id, &walked, &ts)?;
+    tx.commit()?;
+    Ok(Feature {
+        id,
+        module_id,
+        name,
+        description: description.to_string(),
+        details: details.to_string(),
+        state,
+        last_worked_on: ts,
+        locked: false,
+        priority: priority.to_string(),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn add(
+    conn: &Connection,
+    module_id: i64,
+    name: &str,
+    description: &str,
+    details: &str,
+    start: Option<&State>,
+    priority: Priority,
+    json: bool,
+) -> i32 {
+    let f = match create(conn, module_id, name, description, details, start, priority) {
+        Ok(f) => f,
+        Err(e) => return output::fail(&e),
+    };
+    if json {
+        output::print_json(&feature_to_json(&f));
+    } else {
+        let walk = state::start_walk(start, "feature", &f.name)
+            .map(|w| state::walk_note(&w))
+            .unwrap_or_default();
+        output::print_plain(&format!(
+            "added feature {}: {} (module: {}){}",
+            f.id,
+            f.name,
+            module_name(conn, module_id),
+            walk
+        ));
+    }
+    0
+}
+
+/// Write the copy for `clone`: the new feature's id, its tasks' ids in work
+/// order, and how many research links were copied.
+fn copy_feature(
+    tx: &Connection,
+    src: &Feature,
+    module_id: i64,
+    name: &str,
+    ts: &str,
+    with_research_links: bool,
+) -> rusqlite::Result<(i64, Vec<i64>, usize)> {
+    tx.execute(
+        "INSERT INTO features (module_id, name, description, details, state, last_worked_on, priority) VALUES (?1,?2,?3,?4,'Draft',?5,?6)",
+        params![module_id, name, src.description, src.details, ts, src.priority],
+    )?;
+    let new_id = tx.last_insert_rowid();
+    let tasks: Vec<i64> = tx
+        .prepare("SELECT id FROM tasks WHERE feature_id=?1 ORDER BY position, id")?
+        .query_map(params![src.id], |r| r.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut links = 0;
+    if with_research_links {
+        links += tx.execute(
+            "INSERT OR IGNORE INTO research_features (research_id, feature_id)
+             SELECT research_id, ?2 FROM research_features WHERE feature_id=?1",
+            params![src.id, new_id],
+        )?;
+    }
+    let mut new_tasks = Vec::with_capacity(tasks.len());
+    for old in tasks {
+        tx.execute(
+            "INSERT INTO tasks (feature_id, name, description, details, state, last_worked_on, position, priority)
+             SELECT ?2, name, description, details, 'Draft', ?3, position, priority FROM tasks WHERE id=?1",
+            params![old, new_id, ts],
+        )?;
+        let new_task = tx.last_insert_rowid();
+        if with_research_links {
+            links += tx.execute(
+                "INSERT OR IGNORE INTO research_tasks (research_id, task_id)
+                 SELECT research_id, ?2 FROM research_tasks WHERE task_id=?1",
+                params![old, new_task],
+            )?;
+        }
+        new_tasks.push(new_task);
+    }
+    Ok((new_id, new_tasks, links))
+}
+
+/// Copy a feature as a template: its description, details, and priority, and
+/// every task with its own, into `module_id` as `name`, all reset to Draft, in
+/// one transaction. With `with_research_links`, research linked to the feature
+/// or one of its tasks is linked to the copy too. A name already taken in the
+/// target module is refused before anything is written. The name is checked
+/// through `resolver`, which then knows the copy.
+pub fn clone(
+    resolver: &Resolver,
+    id: i64,
+    module_id: i64,
+    name: &str,
+    with_research_links: bool,
+    json: bool,
+) -> i32 {
+    let conn = resolver.conn();
+    let src = match get(conn, id) {
+        Ok(f) => f,
+        Err(e) => return output::fail(&e),
+    };
+    let name = &names::normalize(name);
     if name.is_empty() {
         output::err("name must not be empty");
         return 1;
     }
-    let mname = module_name(conn, module_id);
-    let ts = now();
-    match conn.execute(
-        "INSERT INTO features (module_id, name, description, last_worked_on) VALUES (?1,?2,?3,?4)",
-        params![module_id, name, description, ts],
-    ) {
-        Ok(_) => {
-            let id = conn.last_insert_rowid();
-            if json {
-                output::print_json(&load(conn, id).map(|f| feature_to_json(&f)).unwrap());
-            } else {
-                output::print_plain(&format!(
-                    "added feature {}: {} (module: {})",
-                    id, name, mname
-                ));
-            }
-            0
+    let existing = match resolver.clash("feature", Some(module_id), name) {
+        Ok(existing) => existing,
+        Err(e) => return output::fail(&e),
+    };
+    if let Some(existing) = existing {
+        return output::fail(&Error::Conflict(taken(conn, module_id, &existing)));
+    }
+    if let Some(mname) = module::locked_name(conn, module_id) {
+        output::err(&format!(
+            "module {} is locked; cannot add feature (unlock it first)",
+            mname
+        ));
+        return output::EXIT_LOCKED;
+    }
+    let ts = timestamp::now();
+    let tx = match db::write_tx(conn) {
+        Ok(tx) => tx,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let copied = copy_feature(&tx, &src, module_id, name, &ts, with_research_links);
+    let (new_id, new_tasks, links) = match copied.and_then(|c| tx.commit().map(|_| c)) {
+        Ok(c) => c,
+        Err(e) if db::name_taken(&e) => {
+            return output::fail(&Error::Conflict(taken(conn, module_id, name)));
         }
         Err(e) => {
             output::err(&e.to_string());
-            2
+            return 2;
         }
+    };
+    resolver.remember("feature", Some(module_id), name, new_id);
+    if json {
+        let mut v = record_json(conn, new_id).unwrap_or_default();
+        v["cloned_from"] = json!({"id": src.id, "name": src.name});
+        v["tasks"] = Value::Array(
+            new_tasks
+                .iter()
+                .filter_map(|t| task::record_json(conn, *t).ok())
+                .collect(),
+        );
+        if with_research_links {
+            v["research_links"] = json!(links);
+        }
+        output::print_json(&v);
+    } else {
+        output::print_plain(&format!(
+            "cloned feature {} as {}: {} (module: {}) with {} task(s)",
+            src.name,
+            new_id,
+            name,
+            module_name(conn, module_id),
+            new_tasks.len()
+        ));
+        if with_research_links {
+            output::print_plain(&format!("copied {} research link(s)", links));
+        }
+    }
+    0
+}
+
+/// The SQL behind each of `COLUMNS`, for `--sort`: states in lifecycle order
+/// and priorities most urgent first.
+fn sort_sql(field: &str) -> String {
+    match field {
+        "priority" => priority::rank_sql("priority"),
+        "state" => state::rank_sql("state"),
+        "name" => names::order_sql("name"),
+        "ref" => "id".to_string(),
+        field => field.to_string(),
     }
 }
 
-pub fn list(conn: &Connection, module_id: i64, state_filter: Option<&str>, json: bool) -> i32 {
-    let features: Vec<Feature> = if let Some(s) = state_filter {
-        let mut stmt = conn.prepare(
-            "SELECT id, module_id, name, description, details, state, last_worked_on FROM features WHERE module_id=?1 AND state=?2 ORDER BY id"
-        ).unwrap();
-        stmt.query_map(params![module_id, s], |r| {
-            Ok(Feature {
-                id: r.get(0)?,
-                module_id: r.get(1)?,
-                name: r.get(2)?,
-                description: r.get(3)?,
-                details: r.get(4)?,
-                state: r.get(5)?,
-                last_worked_on: r.get(6)?,
-            })
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
-    } else {
-        let mut stmt = conn.prepare(
-            "SELECT id, module_id, name, description, details, state, last_worked_on FROM features WHERE module_id=?1 ORDER BY id"
-        ).unwrap();
-        stmt.query_map(params![module_id], |r| {
-            Ok(Feature {
-                id: r.get(0)?,
-                module_id: r.get(1)?,
-                name: r.get(2)?,
-                description: r.get(3)?,
-                details: r.get(4)?,
-                state: r.get(5)?,
-                last_worked_on: r.get(6)?,
-            })
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
+/// The query behind `all`, ordered but not paged; `?1` is the module, `?2`
+/// the state filter and `?3` the priority filter.
+fn all_sql(sort: Option<Sort>) -> String {
+    let order = match sort {
+        Some(sort) => sort.order_by(&sort_sql(sort.field), "id"),
+        None => "id".to_string(),
+    };
+    format!(
+        "SELECT id, module_id, name, description, details, state, last_worked_on, locked, priority
+             FROM features
+             WHERE module_id=?1 AND (?2 IS NULL OR state=?2) AND (?3 IS NULL OR priority=?3)
+             ORDER BY {}",
+        order
+    )
+}
+
+/// The module's features by id, or by `sort`, optionally only those in one
+/// state or of one priority, and only `page` of them.
+pub fn all(
+    conn: &Connection,
+    module_id: i64,
+    state_filter: Option<&str>,
+    priority_filter: Option<Priority>,
+    sort: Option<Sort>,
+    page: Page,
+) -> Result<Vec<Feature>, Error> {
+    let mut stmt = conn.prepare(&format!("{}{}", all_sql(sort), page.sql()))?;
+    let features = stmt
+        .query_map(
+            params![
+                module_id,
+                state_filter,
+                priority_filter.map(|p| p.to_string())
+            ],
+            |r| {
+                Ok(Feature {
+                    id: r.get(0)?,
+                    module_id: r.get(1)?,
+                    name: r.get(2)?,
+                    description: r.get(3)?,
+                    details: r.get(4)?,
+                    state: r.get(5)?,
+                    last_worked_on: r.get(6)?,
+                    locked: r.get::<_, i64>(7)? != 0,
+                    priority: r.get(8)?,
+                })
+            },
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(features)
+}
+
+/// How many features `all` returns without a page.
+pub fn count(
+    conn: &Connection,
+    module_id: i64,
+    state_filter: Option<&str>,
+    priority_filter: Option<Priority>,
+) -> Result<i64, Error> {
+    Ok(fields::count(
+        conn,
+        &all_sql(None),
+        params![
+            module_id,
+            state_filter,
+            priority_filter.map(|p| p.to_string())
+        ],
+    )?)
+}
+
+/// `all`, printed, with only `fields` when given and, when paged, the total.
+#[allow(clippy::too_many_arguments)]
+pub fn list(
+    conn: &Connection,
+    module_id: i64,
+    state_filter: Option<&str>,
+    priority_filter: Option<Priority>,
+    fields: Option<&[&str]>,
+    sort: Option<Sort>,
+    page: Page,
+    json: bool,
+) -> i32 {
+    let listed = all(conn, module_id, state_filter, priority_filter, sort, page).and_then(|f| {
+        let total = fields::wants_total(page)
+            .then(|| count(conn, module_id, state_filter, priority_filter))
+            .transpose()?;
+        Ok((f, total))
+    });
+    let (features, total) = match listed {
+        Ok(l) => l,
+        Err(e) => return output::fail(&e),
     };
-    if features.is_empty() {
-        output::print_plain("no features found");
+    if features.is_empty() && fields::empty_in_prose(json, total) {
+        output::print_empty("no features found");
         return 0;
     }
-    if json {
-        output::print_json(&Value::Array(
-            features.iter().map(feature_to_json).collect(),
-        ));
+    if json || fields.is_some() {
+        let rows = features.iter().map(feature_to_json).collect();
+        fields::print_list(COLUMNS, fields, rows, total, json);
     } else {
         for f in &features {
-            println!(
-                "{:<4} {:<20} {:<12} {}",
-                f.id, f.name, f.state, f.last_worked_on
+            outln!(
+                "{:<5} {:<20} {:<12} {:<9} {}{}{}",
+                resolve::short_ref("feature", f.id),
+                f.name,
+                f.state,
+                f.priority,
+                f.last_worked_on,
+                if f.locked { "  [locked]" } else { "" },
+                reason::blocked_tag(conn, "features", f.id, &f.state)
             );
         }
     }
+    if !json {
+        fields::print_more(page, features.len(), total);
+    }
     0
 }
 
-pub fn show(conn: &Connection, id: i64, json: bool) -> i32 {
-    let f = match load(conn, id) {
+/// With `with_ancestors`, the full records of the module and project above
+/// the feature follow: nested under `ancestors` in JSON, as labelled sections
+/// in plain text.
+pub fn show(
+    conn: &Connection,
+    id: i64,
+    with_ancestors: bool,
+    with_history: bool,
+    json: bool,
+) -> i32 {
+    let f = match get(conn, id) {
         Ok(f) => f,
-        Err(e) => {
-            output::err(&e);
-            return 1;
+        Err(e) => return output::fail(&e),
+    };
+    let ancestors = if with_ancestors {
+        match ancestry::ancestors_json(conn, "feature", id) {
+            Ok(a) => Some(a),
+            Err(e) => return output::fail(&e),
+        }
+    } else {
+        None
+    };
+    let changes = if with_history {
+        match history::changes(conn, "feature", id) {
+            Ok(c) => Some(c),
+            Err(e) => return output::fail(&e.into()),
         }
+    } else {
+        None
     };
+    let last_reason = reason::last(conn, "features", id);
+    let blocked = reason::blocked(conn, "features", id, &f.state);
     let mname = module_name(conn, f.module_id);
 
-    let mut tstmt = conn
-        .prepare("SELECT id, name, state FROM tasks WHERE feature_id=?1 ORDER BY id")
-        .unwrap();
-    let tasks: Vec<(i64, String, String)> = tstmt
-        .query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect();
-
-    let mut rstmt = conn
-        .prepare(
-            "SELECT r.id, r.name, r.description FROM research r
+    let mut tstmt = match conn.prepare(
+        "SELECT id, name, state, priority FROM tasks WHERE feature_id=?1 ORDER BY position, id",
+    ) {
+        Ok(s) => s,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let tasks: Vec<(i64, String, String, String)> = match tstmt.query_map(params![id], |r| {
+        Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+    }) {
+        Ok(rows) => match rows.collect() {
+            Ok(t) => t,
+            Err(e) => return output::fail(&e.into()),
+        },
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    // What each task waits on, in the same order.
+    let deps: Vec<Vec<Dep>> = match tasks
+        .iter()
+        .map(|(tid, ..)| dependency::depends_on(conn, *tid))
+        .collect()
+    {
+        Ok(d) => d,
+        Err(e) => return output::fail(&e.into()),
+    };
+
+    let research = match db::triples(
+        conn,
+        "SELECT r.id, r.name, r.description FROM research r
          JOIN research_features rf ON rf.research_id=r.id
          WHERE rf.feature_id=?1 ORDER BY r.id",
-        )
-        .unwrap();
-    let research: Vec<(i64, String, String)> = rstmt
-        .query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect();
+        id,
+    ) {
+        Ok(r) => r,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
 
     if json {
         let mut v = feature_to_json(&f);
+        v["last_transition_reason"] = json!(last_reason);
+        v["blocked_reason"] = json!(blocked);
+        v["allowed_transitions"] = json!(state::allowed_names(&f.state));
         v["module"] = Value::String(mname);
         v["tasks"] = Value::Array(
             tasks
                 .iter()
-                .map(|(id, name, state)| json!({"id": id, "name": name, "state": state}))
+                .zip(&deps)
+                .map(|((id, name, state, priority), deps)| {
+                    json!({
+                        "id": id, "ref": resolve::short_ref("task", *id),
+                        "name": name, "state": state, "priority": priority,
+                        "blocked_reason": reason::blocked(conn, "tasks", *id, state),
+                        "depends_on": deps.iter().map(Dep::to_json).collect::<Vec<_>>(),
+                    })
+                })
                 .collect(),
         );
         v["research"] = Value::Array(
             research
                 .iter()
-                .map(|(id, name, desc)| json!({"id": id, "name": name, "description": desc}))
+                .map(|(id, name, desc)| {
+                    json!({
+                        "id": id, "ref": resolve::short_ref("research", *id),
+                        "name": name, "description": desc,
+                    })
+                })
                 .collect(),
         );
+        if let Some(a) = ancestors {
+            v["ancestors"] = a;
+        }
+        if let Some(changes) = &changes {
+            v["history"] = history::timeline_json(changes);
+        }
         output::print_json(&v);
     } else {
-        println!("{}", output::field("id", &f.id.to_string()));
-        println!("{}", output::field("name", &f.name));
-        println!("{}", output::field("module", &mname));
-        println!("{}", output::field("description", &f.description));
-        println!("{}", output::field("details", &f.details));
-        println!("{}", output::field("state", &f.state));
-        println!("{}", output::field("last_worked_on", &f.last_worked_on));
+        outln!("{}", output::field("id", &f.id.to_string()));
+        outln!(
+            "{}",
+            output::field("ref", &resolve::short_ref("feature", f.id))
+        );
+        outln!("{}", output::field("name", &f.name));
+        outln!("{}", output::field("module", &mname));
+        outln!("{}", output::field("description", &f.description));
+        outln!("{}", output::field("details", &f.details));
+        outln!("{}", output::field("state", &f.state));
+        let next = state::allowed_names(&f.state);
+        if !next.is_empty() {
+            outln!("{}", output::field("next", &next.join(" | ")));
+        }
+        if let Some(r) = &blocked {
+            outln!("{}", output::field("blocked", reason::blocked_text(r)));
+        }
+        outln!("{}", output::field("priority", &f.priority));
+        if let Some(r) = &last_reason {
+            outln!("{}", output::field("last_transition_reason", r));
+        }
+        outln!("{}", output::field("last_worked_on", &f.last_worked_on));
+        outln!(
+            "{}",
+            output::field("locked", if f.locked { "true" } else { "false" })
+        );
         if !tasks.is_empty() {
-            println!();
-            println!("tasks:");
-            for (tid, tname, tstate) in &tasks {
-                println!("  {:<4} {:<20} {}", tid, tname, tstate);
+            outln!();
+            outln!("tasks:");
+            for ((tid, tname, tstate, tpriority), deps) in tasks.iter().zip(&deps) {
+                let after = if deps.is_empty() {
+                    String::new()
+                } else {
+                    let names: Vec<&str> = deps.iter().map(|d| d.name.as_str()).collect();
+                    format!("  (after {})", names.join(", "))
+                };
+                outln!(
+                    "  {:<5} {:<20} {:<9} {}{}{}",
+                    resolve::short_ref("task", *tid),
+                    tname,
+                    tstate,
+                    tpriority,
+                    after,
+                    reason::blocked_tag(conn, "tasks", *tid, tstate)
+                );
             }
         }
         if !research.is_empty() {
-            println!();
-            println!("research:");
+            outln!();
+            outln!("research:");
             for (rid, rname, rdesc) in &research {
-                println!("  {:<4} {:<20} {}", rid, rname, rdesc);
+                outln!(
+                    "  {:<5} {:<20} {}",
+                    resolve::short_ref("research", *rid),
+                    rname,
+                    rdesc
+                );
             }
         }
+        if let Some(a) = &ancestors {
+            ancestry::print_ancestors(a);
+        }
+        if let Some(changes) = &changes {
+            outln!();
+            outln!("history:");
+            history::print_timeline(changes);
+        }
     }
     0
 }
 
-pub fn rename(conn: &Connection, id: i64, new_name: &str, json: bool) -> i32 {
-    let new_name = new_name.trim();
+/// Rename a feature, and with `update_refs` rewrite whole-word mentions of
+/// the old name across its project. A dry run finds the references and
+/// writes nothing.
+pub fn rename_to(
+    conn: &Connection,
+    id: i64,
+    new_name: &str,
+    update_refs: bool,
+    dry_run: bool,
+) -> Result<Renamed<Feature>, Error> {
+    let new_name = names::normalize(new_name);
     if new_name.is_empty() {
-        output::err("name must not be empty");
-        return 1;
+        return Err(Error::Invalid("name must not be empty".into()));
     }
-    let old = match load(conn, id) {
-        Ok(f) => f,
-        Err(e) => {
-            output::err(&e);
-            return 1;
+    let mut f = get(conn, id)?;
+    if let Some(existing) = names::clash(
+        conn,
+        "features",
+        Some(("module_id", f.module_id)),
+        &new_name,
+        Some(id),
+    )? {
+        return Err(Error::Conflict(taken(conn, f.module_id, &existing)));
+    }
+    if f.locked {
+        return Err(Error::Locked(locked_message(&f, "rename")));
+    }
+    let (hits, touched) = match references::rename(
+        conn,
+        "feature",
+        id,
+        &f.name,
+        &new_name,
+        update_refs,
+        dry_run,
+    ) {
+        Ok(renamed) => renamed,
+        Err(e) if db::name_taken(&e) => {
+            return Err(Error::Conflict(taken(conn, f.module_id, &new_name)))
         }
+        Err(e) => return Err(e.into()),
     };
-    conn.execute(
-        "UPDATE features SET name=?1, last_worked_on=?2 WHERE id=?3",
-        params![new_name, now(), id],
-    )
-    .unwrap();
+    let old_name = f.name.clone();
+    if let Some(ts) = touched {
+        f.name = new_name;
+        f.last_worked_on = ts;
+    }
+    Ok(Renamed {
+        entity: f,
+        old_name,
+        hits,
+    })
+}
+
+pub fn rename(
+    conn: &Connection,
+    id: i64,
+    new_name: &str,
+    update_refs: bool,
+    dry_run: bool,
+    json: bool,
+) -> i32 {
+    let r = match rename_to(conn, id, new_name, update_refs, dry_run) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
+    };
+    let f = &r.entity;
+    if dry_run {
+        references::print_preview(
+            "feature",
+            id,
+            &r.old_name,
+            &names::normalize(new_name),
+            &r.hits,
+            json,
+        );
+        return 0;
+    }
     if json {
-        output::print_json(&load(conn, id).map(|f| feature_to_json(&f)).unwrap());
+        let mut v = feature_to_json(f);
+        if update_refs {
+            v["references"] = references::hits_to_json(&r.hits);
+        }
+        output::print_json(&v);
     } else {
+        let refs = if update_refs {
+            references::summary(&r.hits)
+        } else {
+            String::new()
+        };
         output::print_plain(&format!(
-            "renamed feature {}: {} → {}",
-            id, old.name, new_name
+            "renamed feature {}: {} → {}{}",
+            id, r.old_name, f.name, refs
         ));
     }
     0
 }
 
+/// Replace the feature's description.
+pub fn update_description(conn: &Connection, id: i64, desc: &str) -> Result<Feature, Error> {
+    let mut f = get(conn, id)?;
+    if f.locked {
+        return Err(Error::Locked(locked_message(&f, "change description")));
+    }
+    let ts = timestamp::now();
+    conn.execute(
+        "UPDATE features SET description=?1, last_worked_on=?2 WHERE id=?3",
+        params![desc, ts, id],
+    )?;
+    f.description = desc.to_string();
+    f.last_worked_on = ts;
+    Ok(f)
+}
+
 pub fn set_description(conn: &Connection, id: i64, desc: &str, json: bool) -> i32 {
-    let f = match load(conn, id) {
+    let f = match update_description(conn, id, desc) {
         Ok(f) => f,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+        Err(e) => return output::fail(&e),
     };
-    conn.execute(
-        "UPDATE features SET description=?1, last_worked_on=?2 WHERE id=?3",
-        params![desc, now(), id],
-    )
-    .unwrap();
     if json {
-        output::print_json(&load(conn, id).map(|f| feature_to_json(&f)).unwrap());
+        output::print_json(&feature_to_json(&f));
     } else {
         output::print_plain(&format!("updated description for feature: {}", f.name));
     }
     0
 }
 
-pub fn set_details(conn: &Connection, id: i64, details: &str, json: bool) -> i32 {
-    let f = match load(conn, id) {
-        Ok(f) => f,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
-    };
+/// Set the feature's priority. Returns the feature and the priority it had.
+pub fn update_priority(
+    conn: &Connection,
+    id: i64,
+    priority: Priority,
+) -> Result<(Feature, String), Error> {
+    let mut f = get(conn, id)?;
+    let ts = timestamp::now();
     conn.execute(
-        "UPDATE features SET details=?1, last_worked_on=?2 WHERE id=?3",
-        params![details, now(), id],
-    )
-    .unwrap();
+        "UPDATE features SET priority=?1, last_worked_on=?2 WHERE id=?3",
+        params![priority.to_string(), ts, id],
+    )?;
+    let old = std::mem::replace(&mut f.priority, priority.to_string());
+    f.last_worked_on = ts;
+    Ok((f, old))
+}
+
+pub fn set_priority(conn: &Connection, id: i64, priority: Priority, json: bool) -> i32 {
+    let (f, old) = match update_priority(conn, id, priority) {
+        Ok(changed) => changed,
+        Err(e) => return output::fail(&e),
+    };
     if json {
-        output::print_json(&load(conn, id).map(|f| feature_to_json(&f)).unwrap());
+        output::print_json(&feature_to_json(&f));
     } else {
-        output::print_plain(&format!("updated details for feature: {}", f.name));
+        output::print_plain(&format!(
+            "feature {}: priority {} → {}",
+            f.name, old, f.priority
+        ));
     }
     0
 }
 
-pub fn transition(conn: &Connection, id: i64, to_state: &State, json: bool) -> i32 {
-    let f = match load(conn, id) {
+/// Replace the details, or add `details` after or before them per `placement`.
+pub fn update_details(
+    conn: &Connection,
+    id: i64,
+    details: &str,
+    placement: Placement,
+) -> Result<Feature, Error> {
+    let mut f = get(conn, id)?;
+    let details = placement.merge(&f.details, details);
+    let ts = timestamp::now();
+    conn.execute(
+        "UPDATE features SET details=?1, last_worked_on=?2 WHERE id=?3",
+        params![details, ts, id],
+    )?;
+    f.details = details;
+    f.last_worked_on = ts;
+    Ok(f)
+}
+
+pub fn set_details(
+    conn: &Connection,
+    id: i64,
+    details: &str,
+    placement: Placement,
+    json: bool,
+) -> i32 {
+    let f = match update_details(conn, id, details, placement) {
         Ok(f) => f,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+        Err(e) => return output::fail(&e),
     };
-    match validate_transition(&f.state, to_state) {
-        Err(e) => {
-            output::err(&format!("{} for feature {}", e, f.name));
-            return 1;
-        }
-        Ok(false) => return 0,
-        Ok(true) => {}
+    if json {
+        output::print_json(&feature_to_json(&f));
+    } else {
+        output::print_plain(&format!(
+            "{} details for feature: {}",
+            placement.verb(),
+            f.name
+        ));
+    }
+    0
+}
+
+/// Move a feature to `to_state`. Moving into Planning or Building also looks at
+/// parents still in an earlier stage and, per `parents`, reports them or lifts
+/// them in the same transaction. A `reason` is kept as the last_transition_reason
+/// and appended to the details with a timestamp. Moving to the state it is in
+/// changes nothing.
+pub fn transition_to(
+    conn: &Connection,
+    id: i64,
+    to_state: &State,
+    parents: ParentPolicy,
+    reason: Option<&str>,
+    force: bool,
+) -> Result<Transitioned<Feature>, Error> {
+    let mut f = get(conn, id)?;
+    // Amending is the sanctioned way to reopen locked work; every other move is refused.
+    if f.locked && *to_state != State::Amending {
+        return Err(Error::Locked(locked_message(
+            &f,
+            &format!("transition to {}", to_state),
+        )));
     }
     let from = f.state.clone();
-    conn.execute(
-        "UPDATE features SET state=?1, last_worked_on=?2 WHERE id=?3",
-        params![to_state.to_string(), now(), id],
-    )
-    .unwrap();
+    let moves = validate_transition(&from, to_state)
+        .map_err(|e| Error::InvalidTransition(format!("{} for feature {}", e, f.name)))?;
+    if !moves {
+        return Ok(Transitioned::new(f, from));
+    }
+    let forced = if *to_state == State::Complete {
+        progress::guard_complete(conn, "feature", id, &f.name, force)?
+    } else {
+        None
+    };
+    let ts = timestamp::now();
+    let reason = reason::given(reason);
+    let details = reason::append(&f.details, &from, to_state, reason, &ts);
+    let behind = lift::behind(conn, "feature", id, to_state)?;
+    let (lifted, behind) = match parents {
+        ParentPolicy::Lift => (behind, Vec::new()),
+        ParentPolicy::Warn => (Vec::new(), behind),
+        ParentPolicy::Ignore => (Vec::new(), Vec::new()),
+    };
+    let tx = db::write_tx(conn)?;
+    lift::apply(&tx, &lifted, &ts)?;
+    tx.execute(
+        "UPDATE features SET state=?1, last_worked_on=?2, details=?3, last_transition_reason=?4 WHERE id=?5",
+        params![to_state.to_string(), ts, details, reason.unwrap_or(""), id],
+    )?;
+    history::record_state(
+        &tx,
+        "feature",
+        id,
+        &from,
+        &to_state.to_string(),
+        reason,
+        &ts,
+    )?;
+    tx.commit()?;
+    f.state = to_state.to_string();
+    f.last_worked_on = ts;
+    f.details = details;
+    let mut t = Transitioned::new(f, from);
+    t.moved = true;
+    t.reason = reason.map(str::to_string);
+    t.lifted = lifted;
+    t.behind = behind;
+    t.forced.extend(forced);
+    // Only a move to Complete changes what is left of the parent, so only then say so.
+    if *to_state == State::Complete {
+        t.parent_progress = progress::of_parent(conn, "feature", id)?;
+    }
+    Ok(t)
+}
+
+pub fn transition(
+    conn: &Connection,
+    id: i64,
+    to_state: &State,
+    parents: ParentPolicy,
+    reason: Option<&str>,
+    force: bool,
+    json: bool,
+) -> i32 {
+    let t = match transition_to(conn, id, to_state, parents, reason, force) {
+        Ok(t) => t,
+        Err(e) => return output::fail(&e),
+    };
+    if !t.moved {
+        return 0;
+    }
+    let f = &t.entity;
+    t.warn("feature", &f.name, to_state);
     if json {
-        output::print_json(&load(conn, id).map(|f| feature_to_json(&f)).unwrap());
+        let mut v = feature_to_json(f);
+        v["reason"] = json!(t.reason);
+        if let Some(p) = &t.parent_progress {
+            v["parent_progress"] = p.to_json();
+        }
+        if !t.lifted.is_empty() {
+            v["lifted_parents"] =
+                json!(t.lifted.iter().map(lift::Lift::to_json).collect::<Vec<_>>());
+        }
+        output::print_json(&v);
     } else {
-        output::print_plain(&format!("feature {}: {} → {}", f.name, from, to_state));
+        lift::report(&t.lifted);
+        let summary = t
+            .parent_progress
+            .as_ref()
+            .map(|p| format!(" ({})", p.summary()))
+            .unwrap_or_default();
+        output::print_plain(&format!(
+            "feature {}: {} → {}{}",
+            f.name, t.from, to_state, summary
+        ));
     }
     0
 }
 
-pub fn remove(conn: &Connection, id: i64, cascade: bool, json: bool) -> i32 {
-    let f = match load(conn, id) {
-        Ok(f) => f,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
-    };
-    let count: i64 = conn
-        .query_row(
-            "SELECT COUNT(*) FROM tasks WHERE feature_id=?1",
-            params![id],
-            |r| r.get(0),
-        )
-        .unwrap_or(0);
+/// Delete a feature, and with `cascade` its tasks; `soft` puts it in the
+/// trash. With `relink_research`, research linked to it or its descendants
+/// is first linked to the parent module in the same transaction.
+pub fn delete(
+    conn: &Connection,
+    id: i64,
+    cascade: bool,
+    relink_research: bool,
+    soft: bool,
+) -> Result<Removed<Feature>, Error> {
+    let f = get(conn, id)?;
+    if f.locked {
+        return Err(Error::Locked(locked_message(&f, "remove")));
+    }
+    if let Some(mname) = module::locked_name(conn, f.module_id) {
+        return Err(Error::Locked(format!(
+            "module {} is locked; cannot remove feature {} (unlock it first)",
+            mname, f.name
+        )));
+    }
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM tasks WHERE feature_id=?1",
+        params![id],
+        |r| r.get(0),
+    )?;
     if count > 0 && !cascade {
-        output::err(&format!(
+        return Err(Error::InvalidState(format!(
             "feature has {} task(s); pass --cascade to remove them",
             count
-        ));
-        return 1;
+        )));
     }
-    conn.execute("DELETE FROM features WHERE id=?1", params![id])
-        .unwrap();
+    let tx = db::write_tx(conn)?;
+    if soft {
+        trash::bury(&tx, "feature", id)?;
+    }
+    let relinked = if relink_research {
+        Some(research::relink_to_parent(&tx, "feature", id, f.module_id)?)
+    } else {
+        None
+    };
+    tx.execute("DELETE FROM features WHERE id=?1", params![id])?;
+    tx.commit()?;
+    Ok(Removed {
+        entity: f,
+        trashed: soft,
+        relinked,
+        warnings: Vec::new(),
+    })
+}
+
+pub fn remove(
+    conn: &Connection,
+    id: i64,
+    cascade: bool,
+    relink_research: bool,
+    soft: bool,
+    json: bool,
+) -> i32 {
+    let removed = match delete(conn, id, cascade, relink_research, soft) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
+    };
+    let f = &removed.entity;
     if json {
-        output::print_json(&json!({"deleted": true, "id": id}));
+        let mut v = json!({"deleted": true, "id": id, "trashed": soft});
+        if let Some((created, existing)) = removed.relinked {
+            v["research_relinked"] = json!(created);
+            v["research_already_linked"] = json!(existing);
+            v["module_id"] = json!(f.module_id);
+        }
+        output::print_json(&v);
     } else {
         output::print_plain(&format!("removed feature {}: {}", id, f.name));
+        if soft {
+            output::print_plain(&trash::buried_note("feature", id));
+        }
+        if let Some((created, existing)) = removed.relinked {
+            output::print_plain(&format!(
+                "relinked {} research record(s) to the parent module ({} already linked)",
+                created, existing
+            ));
+        }
+    }
+    0
+}
+
+/// Lock or unlock the feature.
+pub fn update_locked(conn: &Connection, id: i64, locked: bool) -> Result<Feature, Error> {
+    let mut f = get(conn, id)?;
+    conn.execute(
+        "UPDATE features SET locked=?1 WHERE id=?2",
+        params![locked as i64, id],
+    )?;
+    f.locked = locked;
+    Ok(f)
+}
+
+pub fn set_locked(conn: &Connection, id: i64, locked: bool, json: bool) -> i32 {
+    let f = match update_locked(conn, id, locked) {
+        Ok(f) => f,
+        Err(e) => return output::fail(&e),
+    };
+    if json {
+        output::print_json(&feature_to_json(&f));
+    } else {
+        let verb = if locked { "locked" } else { "unlocked" };
+        output::print_plain(&format!("feature {} {}", f.name, verb));
     }
     0
 }
+
+/// Returns the feature's name if it is locked, for guarding changes to its tasks.
+pub fn locked_name(conn: &Connection, id: i64) -> Option<String> {
+    conn.query_row(
+        "SELECT name FROM features WHERE id=?1 AND locked=1",
+        params![id],
+        |r| r.get(0),
+    )
+    .ok()
+}
@@ -0,0 +1,424 @@
+use crate::fields;
+use crate::models::ancestry;
+use crate::models::history;
+use crate::models::research::SUBTREE_RESEARCH_SQL_PROJECT;
+use crate::names;
+use crate::output;
+use crate::resolve;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+// Scoring weights, loosely after fzf: every matched character scores, runs of
+// consecutive matches and matches at word starts score more, and skipped
+// characters between matches cost a little: more to open a gap than to
+// extend one, so two word-start matches far apart still beat a scattered pair.
+const SCORE_MATCH: i64 = 16;
+const BONUS_CONSECUTIVE: i64 = 12;
+const BONUS_BOUNDARY: i64 = 10;
+const BONUS_FIRST_CHAR: i64 = 8;
+const BONUS_CASE: i64 = 1;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTEND: i64 = 1;
+const BONUS_PREFIX: i64 = 40;
+const BONUS_EXACT: i64 = 80;
+
+/// Every entity with what `find` reports about it and the text it searches:
+/// (type, id, name, state, recency, project id, description, details). The
+/// last column is a research record's content; projects have none.
+const NAMES_SQL: &str = "
+    SELECT 'project', id, name, CASE completed WHEN 1 THEN 'completed' ELSE 'active' END, updated_at,
+        id, description, '' FROM projects
+    UNION ALL SELECT 'module', id, name, state, last_worked_on, project_id, description, details FROM modules
+    UNION ALL SELECT 'feature', f.id, f.name, f.state, f.last_worked_on, m.project_id, f.description, f.details
+        FROM features f JOIN modules m ON m.id = f.module_id
+    UNION ALL SELECT 'task', t.id, t.name, t.state, t.last_worked_on, m.project_id, t.description, t.details
+        FROM tasks t JOIN features f ON f.id = t.feature_id JOIN modules m ON m.id = f.module_id
+    UNION ALL SELECT 'research', id, name, '', updated_at, NULL, description, content_body(content) FROM research";
+
+/// Matches shown without `--limit`.
+pub const DEFAULT_LIMIT: i64 = 10;
+
+/// What `--type` accepts.
+const TYPES: [&str; 5] = ["project", "module", "feature", "task", "research"];
+
+/// What `--state` accepts: a lifecycle state, or for projects active or completed.
+const STATES: [&str; 8] = [
+    "Draft",
+    "Planning",
+    "Building",
+    "Complete",
+    "Amending",
+    "Blocked",
+    "active",
+    "completed",
+];
+
+fn is_boundary(prev: Option<char>) -> bool {
+    match prev {
+        None => true,
+        Some(c) => !c.is_alphanumeric(),
+    }
+}
+
+/// Fuzzy-match `query` against `name` as a case-insensitive subsequence and
+/// return the best score over all alignments, or None if it does not match.
+/// Alignments are compared by dynamic programming rather than taking the
+/// leftmost match, so a later run on word starts beats an earlier scattered
+/// one. Names starting with the query get an extra boost, and an exact
+/// (case-insensitive) match more again.
+pub fn score(query: &str, name: &str) -> Option<i64> {
+    let q: Vec<char> = query.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    if q.is_empty() {
+        return Some(0);
+    }
+    if q.len() > n.len() {
+        return None;
+    }
+    let eq = |a: char, b: char| a.to_lowercase().eq(b.to_lowercase());
+
+    // best[j]: best score with the current query char matched at name position j.
+    let mut prev: Vec<Option<i64>> = vec![None; n.len()];
+    for (i, &qc) in q.iter().enumerate() {
+        let mut cur: Vec<Option<i64>> = vec![None; n.len()];
+        // Running max of prev[k] + k * PENALTY_GAP_EXTEND over k < j - 1, from
+        // which a gap of (j - k - 1) skipped characters is charged below.
+        let mut gapped: Option<i64> = None;
+        for j in 0..n.len() {
+            if j >= 2 {
+                if let Some(p) = prev[j - 2] {
+                    let v = p + (j as i64 - 2) * PENALTY_GAP_EXTEND;
+                    gapped = Some(gapped.map_or(v, |g| g.max(v)));
+                }
+            }
+            if !eq(qc, n[j]) {
+                continue;
+            }
+            let mut here = SCORE_MATCH;
+            if is_boundary(if j == 0 { None } else { Some(n[j - 1]) }) {
+                here += BONUS_BOUNDARY;
+            }
+            if qc == n[j] {
+                here += BONUS_CASE;
+            }
+            let best_prev = if i == 0 {
+                // Leading characters skipped before the first match cost one each.
+                Some(if j == 0 {
+                    BONUS_FIRST_CHAR
+                } else {
+                    -(j as i64)
+                })
+            } else {
+                let adjacent = if j >= 1 {
+                    prev[j - 1].map(|p| p + BONUS_CONSECUTIVE)
+                } else {
+                    None
+                };
+                let gap =
+                    gapped.map(|g| g - PENALTY_GAP_START - (j as i64 - 2) * PENALTY_GAP_EXTEND);
+                match (adjacent, gap) {
+                    (Some(a), Some(g)) => Some(a.max(g)),
+                    (a, g) => a.or(g),
+                }
+            };
+            cur[j] = best_prev.map(|p| p + here);
+        }
+        prev = cur;
+    }
+    let mut best = prev.into_iter().flatten().max()?;
+    let (ql, nl) = (query.to_lowercase(), name.to_lowercase());
+    if nl == ql {
+        best += BONUS_EXACT;
+    } else if nl.starts_with(&ql) {
+        best += BONUS_PREFIX;
+    }
+    Some(best)
+}
+
+/// A named entity considered by `find`.
+struct Candidate {
+    kind: String,
+    id: i64,
+    name: String,
+    state: String,
+    recency: String,
+    /// None for research, which belongs to projects through links.
+    project_id: Option<i64>,
+    description: String,
+    details: String,
+}
+
+impl Candidate {
+    /// The columns other than the name holding `needle` (already lowercase).
+    fn text_matches(&self, needle: &str) -> Vec<&'static str> {
+        let details = if self.kind == "research" {
+            "content"
+        } else {
+            "details"
+        };
+        columns_containing(
+            needle,
+            &[("description", &self.description), (details, &self.details)],
+        )
+    }
+}
+
+/// The columns whose text holds `needle` (already lowercase), ignoring case,
+/// in the order given. `find` and `next --about` both match text this way.
+pub fn columns_containing(needle: &str, columns: &[(&'static str, &str)]) -> Vec<&'static str> {
+    columns
+        .iter()
+        .filter(|(_, text)| text.to_lowercase().contains(needle))
+        .map(|(column, _)| *column)
+        .collect()
+}
+
+/// A candidate that matched, by name or text.
+struct Hit {
+    /// The name score; None when only text matched.
+    score: Option<i64>,
+    candidate: Candidate,
+    /// A former name that scored better than the current one.
+    former: Option<String>,
+    /// Columns that matched: name, description, details, or content.
+    matched: Vec<&'static str>,
+}
+
+/// Narrowing for `find`: `--type`, `--project`, and `--state`.
+pub struct Filter<'a> {
+    pub kind: Option<&'a str>,
+    pub project_id: Option<i64>,
+    pub state: Option<&'a str>,
+}
+
+impl Filter<'_> {
+    fn check(&self) -> Result<(), String> {
+        if let Some(k) = self.kind {
+            if !TYPES.contains(&k) {
+                return Err(format!(
+                    "unknown type: {} (expected one of: {})",
+                    k,
+                    TYPES.join(", ")
+                ));
+            }
+        }
+        if let Some(s) = self.state {
+            if !STATES.iter().any(|st| st.eq_ignore_ascii_case(s)) {
+                return Err(format!(
+                    "unknown state: {} (expected one of: {})",
+                    s,
+                    STATES.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Research ids linked anywhere under the project, when scoped to one.
+    fn project_research(&self, conn: &Connection) -> rusqlite::Result<HashSet<i64>> {
+        let Some(pid) = self.project_id else {
+            return Ok(HashSet::new());
+        };
+        let mut stmt = conn.prepare(SUBTREE_RESEARCH_SQL_PROJECT)?;
+        let ids = stmt.query_map(params![pid], |r| r.get(0))?;
+        ids.collect()
+    }
+
+    fn keeps(&self, c: &Candidate, project_research: &HashSet<i64>) -> bool {
+        self.kind.is_none_or(|k| c.kind == k)
+            && self.state.is_none_or(|s| c.state.eq_ignore_ascii_case(s))
+            && self.project_id.is_none_or(|pid| match c.project_id {
+                Some(p) => p == pid,
+                None => project_research.contains(&c.id),
+            })
+    }
+}
+
+/// Find entities by name and text: the query fuzzy-matches the names of every
+/// project, module, feature, task, and research record, and is looked for,
+/// ignoring case, in their descriptions and details (research content).
+/// Name matches come first, best first, then text-only matches; ties go to the
+/// most recently touched. Each hit says which columns matched. With
+/// `include_former_names`, names from before a rename count too, and a hit
+/// that only (or best) matches a former name says which one. `page` is the
+/// window of hits shown, the first [`DEFAULT_LIMIT`] without a limit.
+pub fn find(
+    conn: &Connection,
+    query: &str,
+    filter: &Filter,
+    page: fields::Page,
+    include_former_names: bool,
+    json: bool,
+) -> i32 {
+    if let Err(e) = filter.check() {
+        output::err(&e);
+        return 1;
+    }
+    let query = &names::normalize(query);
+    let needle = query.to_lowercase();
+    let project_research = match filter.project_research(conn) {
+        Ok(ids) => ids,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let mut stmt = match conn.prepare(NAMES_SQL) {
+        Ok(s) => s,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let candidates = stmt
+        .query_map([], |r| {
+            Ok(Candidate {
+                kind: r.get(0)?,
+                id: r.get(1)?,
+                name: r.get(2)?,
+                state: r.get(3)?,
+                recency: r.get(4)?,
+                project_id: r.get(5)?,
+                description: r.get(6)?,
+                details: r.get(7)?,
+            })
+        })
+        .and_then(|rows| rows.collect::<rusqlite::Result<Vec<Candidate>>>());
+    let candidates = match candidates {
+        Ok(c) => c,
+        Err(e) => return output::fail(&e.into()),
+    };
+
+    let former = if include_former_names {
+        match history::former_names(conn) {
+            Ok(f) => f,
+            Err(e) => return output::fail(&e.into()),
+        }
+    } else {
+        Default::default()
+    };
+    let mut hits: Vec<Hit> = candidates
+        .into_iter()
+        .filter(|c| filter.keeps(c, &project_research))
+        .filter_map(|c| {
+            let current = score(query, &c.name);
+            let best_former = former
+                .get(&(c.kind.clone(), c.id))
+                .into_iter()
+                .flatten()
+                .filter_map(|old| score(query, old).map(|s| (s, old.clone())))
+                .max_by_key(|(s, _)| *s)
+                .filter(|(s, _)| current.is_none_or(|cur| *s > cur));
+            let (score, former) = match best_former {
+                Some((s, old)) => (Some(s), Some(old)),
+                None => (current, None),
+            };
+            let mut matched = Vec::new();
+            if score.is_some() {
+                matched.push("name");
+            }
+            if !needle.is_empty() {
+                matched.extend(c.text_matches(&needle));
+            }
+            (!matched.is_empty()).then_some(Hit {
+                score,
+                candidate: c,
+                former,
+                matched,
+            })
+        })
+        .collect();
+    // Canonical timestamps compare as strings, so recency is a plain reverse
+    // sort. None sorts below every score, putting text-only hits last.
+    hits.sort_by(|a, b| {
+        let (ca, cb) = (&a.candidate, &b.candidate);
+        b.score
+            .cmp(&a.score)
+            .then_with(|| cb.recency.cmp(&ca.recency))
+            .then_with(|| ca.id.cmp(&cb.id))
+    });
+    let total = hits.len() as i64;
+    let hits: Vec<Hit> = hits
+        .into_iter()
+        .skip(page.offset as usize)
+        .take(page.limit.unwrap_or(DEFAULT_LIMIT) as usize)
+        .collect();
+
+    let crumbs: Vec<String> = match hits
+        .iter()
+        .map(|h| {
+            let c = &h.candidate;
+            if c.kind == "research" {
+                Ok(c.name.clone())
+            } else {
+                ancestry::breadcrumb(conn, &c.kind, c.id)
+            }
+        })
+        .collect()
+    {
+        Ok(c) => c,
+        Err(e) => return output::fail(&e.into()),
+    };
+
+    if json {
+        let items: Vec<Value> = hits
+            .iter()
+            .zip(&crumbs)
+            .map(|(h, crumb)| {
+                let c = &h.candidate;
+                let mut v = json!({
+                    "score": h.score, "type": c.kind, "id": c.id,
+                    "ref": resolve::short_ref(&c.kind, c.id), "name": c.name,
+                    "state": c.state, "breadcrumb": crumb, "matched": h.matched,
+                });
+                if let Some(old) = &h.former {
+                    v["matched_former_name"] = json!(old);
+                }
+                v
+            })
+            .collect();
+        if fields::wants_total(page) && output::format() == output::Format::Json {
+            output::print_json(&json!({
+                "items": items,
+                "returned": items.len(),
+                "total": total,
+            }));
+        } else {
+            output::print_json(&Value::Array(items));
+        }
+    } else if hits.is_empty() && total == 0 {
+        output::print_plain(&format!(
+            "no names match '{}', and no description or details contain it",
+            query
+        ));
+    } else {
+        for (h, crumb) in hits.iter().zip(&crumbs) {
+            let c = &h.candidate;
+            let mut notes: Vec<String> = h
+                .matched
+                .iter()
+                .filter(|m| **m != "name")
+                .map(|m| m.to_string())
+                .collect();
+            if let Some(old) = &h.former {
+                notes.push(format!("matched former name '{}'", old));
+            }
+            let note = if notes.is_empty() {
+                String::new()
+            } else {
+                format!("  ({})", notes.join(", "))
+            };
+            outln!(
+                "{:<8} {:<5} {:<9} {}{}",
+                c.kind,
+                resolve::short_ref(&c.kind, c.id),
+                c.state,
+                crumb,
+                note
+            );
+        }
+        fields::print_more(page, hits.len(), Some(total));
+    }
+    0
+}
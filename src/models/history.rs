@@ -0,0 +1,276 @@
+use crate::error::Error;
+use crate::output;
+use crate::state::State;
+use crate::timestamp;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Entity table for each type whose renames are recorded.
+fn table(kind: &str) -> &'static str {
+    match kind {
+        "project" => "projects",
+        "module" => "modules",
+        "feature" => "features",
+        "task" => "tasks",
+        _ => "research",
+    }
+}
+
+/// Record a rename. Call on the rename's own transaction so the history row
+/// and the new name land together.
+pub fn record(
+    conn: &Connection,
+    kind: &str,
+    id: i64,
+    old_name: &str,
+    new_name: &str,
+    at: &str,
+) -> rusqlite::Result<()> {
+    if old_name == new_name {
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO name_history (entity_type, entity_id, old_name, new_name, renamed_at)
+         VALUES (?1,?2,?3,?4,?5)",
+        params![kind, id, old_name, new_name, at],
+    )?;
+    Ok(())
+}
+
+/// Former names of every entity of every type, keyed by (type, id), oldest first.
+pub fn former_names(conn: &Connection) -> rusqlite::Result<HashMap<(String, i64), Vec<String>>> {
+    let mut map: HashMap<(String, i64), Vec<String>> = HashMap::new();
+    let mut stmt =
+        conn.prepare("SELECT entity_type, entity_id, old_name FROM name_history ORDER BY id")?;
+    for row in stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))? {
+        let (kind, id, old) = row?;
+        map.entry((kind, id)).or_default().push(old);
+    }
+    Ok(map)
+}
+
+/// Every rename of one entity as (old name, new name, when), oldest first.
+fn renames(
+    conn: &Connection,
+    kind: &str,
+    id: i64,
+) -> rusqlite::Result<Vec<(String, String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT old_name, new_name, renamed_at FROM name_history
+         WHERE entity_type=?1 AND entity_id=?2 ORDER BY id",
+    )?;
+    let rows = stmt.query_map(params![kind, id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?;
+    rows.collect()
+}
+
+/// Print the rename history of one entity, oldest first.
+pub fn show(conn: &Connection, kind: &'static str, id: i64, json: bool) -> i32 {
+    let name: String = match conn.query_row(
+        &format!("SELECT name FROM {} WHERE id=?1", table(kind)),
+        params![id],
+        |r| r.get(0),
+    ) {
+        Ok(n) => n,
+        Err(_) => {
+            return output::fail(&Error::not_found(
+                kind,
+                format!("{} not found: {}", kind, id),
+            ));
+        }
+    };
+    let renames = match renames(conn, kind, id) {
+        Ok(r) => r,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+
+    if json {
+        output::print_json(&json!({
+            "type": kind,
+            "id": id,
+            "name": name,
+            "history": renames.iter().map(|(old, new, at)| json!({
+                "old_name": old, "new_name": new, "renamed_at": at,
+            })).collect::<Vec<Value>>(),
+        }));
+    } else if renames.is_empty() {
+        output::print_plain(&format!("{} {} has never been renamed", kind, name));
+    } else {
+        for (old, new, at) in &renames {
+            outln!("{}  {} → {}", at, old, new);
+        }
+    }
+    0
+}
+
+/// Record a change of state: a lifecycle transition, or a project marked
+/// complete or reopened, with the reason given for it. Call on the change's
+/// own transaction.
+pub fn record_state(
+    conn: &Connection,
+    kind: &str,
+    id: i64,
+    from: &str,
+    to: &str,
+    reason: Option<&str>,
+    at: &str,
+) -> rusqlite::Result<()> {
+    if from == to {
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO state_history (entity_type, entity_id, from_state, to_state, reason, at)
+         VALUES (?1,?2,?3,?4,?5,?6)",
+        params![kind, id, from, to, reason.unwrap_or(""), at],
+    )?;
+    Ok(())
+}
+
+/// Record each step of `walked`, the states a new entity passed through on
+/// its way to the one it was created in (see `state::start_walk`).
+pub fn record_walk(
+    conn: &Connection,
+    kind: &str,
+    id: i64,
+    walked: &[State],
+    at: &str,
+) -> rusqlite::Result<()> {
+    for step in walked.windows(2) {
+        record_state(
+            conn,
+            kind,
+            id,
+            &step[0].to_string(),
+            &step[1].to_string(),
+            None,
+            at,
+        )?;
+    }
+    Ok(())
+}
+
+/// One recorded change of state.
+pub struct Change {
+    pub from: String,
+    pub to: String,
+    /// Empty when none was given.
+    pub reason: String,
+    pub at: String,
+}
+
+/// Every change of state of one entity, oldest first.
+pub fn changes(conn: &Connection, kind: &str, id: i64) -> rusqlite::Result<Vec<Change>> {
+    let mut stmt = conn.prepare(
+        "SELECT from_state, to_state, reason, at FROM state_history
+         WHERE entity_type=?1 AND entity_id=?2 ORDER BY id",
+    )?;
+    let rows = stmt.query_map(params![kind, id], |r| {
+        Ok(Change {
+            from: r.get(0)?,
+            to: r.get(1)?,
+            reason: r.get(2)?,
+            at: r.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Seconds between two stored timestamps, when both parse.
+fn seconds_between(from: &str, to: &str) -> Option<i64> {
+    let (a, b) = (
+        timestamp::parse_tolerant(from)?,
+        timestamp::parse_tolerant(to)?,
+    );
+    Some((b - a).num_seconds().max(0))
+}
+
+/// A duration in its two largest units, e.g. `3d 4h` or `12m`.
+fn span(seconds: i64) -> String {
+    let (d, h, m) = (
+        seconds / 86_400,
+        seconds % 86_400 / 3_600,
+        seconds % 3_600 / 60,
+    );
+    match (d, h, m) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, _) => format!("{}m", m),
+        (0, _, _) => format!("{}h {}m", h, m),
+        _ => format!("{}d {}h", d, h),
+    }
+}
+
+/// The changes as JSON, each with its reason (null when none was given) and
+/// how long the entity had been in `from_state` (null for the first, whose
+/// start was not recorded).
+pub fn timeline_json(changes: &[Change]) -> Value {
+    let mut prev: Option<&str> = None;
+    let mut out = Vec::new();
+    for c in changes {
+        out.push(json!({
+            "from_state": c.from,
+            "to_state": c.to,
+            "reason": (!c.reason.is_empty()).then_some(&c.reason),
+            "at": c.at,
+            "seconds_in_from_state": prev.and_then(|p| seconds_between(p, &c.at)),
+        }));
+        prev = Some(&c.at);
+    }
+    json!(out)
+}
+
+/// One line per change, oldest first, with how long the state before it
+/// lasted and the reason given for it.
+pub fn print_timeline(changes: &[Change]) {
+    let mut prev: Option<&str> = None;
+    for c in changes {
+        let stay = prev
+            .and_then(|p| seconds_between(p, &c.at))
+            .map(|s| format!("  (after {} in {})", span(s), c.from))
+            .unwrap_or_default();
+        let why = if c.reason.is_empty() {
+            String::new()
+        } else {
+            format!(": {}", c.reason)
+        };
+        outln!("  {}  {} → {}{}{}", c.at, c.from, c.to, stay, why);
+        prev = Some(&c.at);
+    }
+}
+
+/// Print the state timeline of one entity, oldest first.
+pub fn state_history(conn: &Connection, kind: &'static str, id: i64, json: bool) -> i32 {
+    let name: String = match conn.query_row(
+        &format!("SELECT name FROM {} WHERE id=?1", table(kind)),
+        params![id],
+        |r| r.get(0),
+    ) {
+        Ok(n) => n,
+        Err(_) => {
+            return output::fail(&Error::not_found(
+                kind,
+                format!("{} not found: {}", kind, id),
+            ));
+        }
+    };
+    let changes = match changes(conn, kind, id) {
+        Ok(c) => c,
+        Err(e) => return output::fail(&e.into()),
+    };
+    if json {
+        output::print_json(&json!({
+            "type": kind,
+            "id": id,
+            "name": name,
+            "history": timeline_json(&changes),
+        }));
+    } else if changes.is_empty() {
+        output::print_plain(&format!("{} {} has no recorded state changes", kind, name));
+    } else {
+        output::print_plain(&format!("{} {}:", kind, name));
+        print_timeline(&changes);
+    }
+    0
+}
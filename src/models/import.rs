@@ -0,0 +1,577 @@
+use crate::content;
+use crate::db;
+use crate::error::Error;
+use crate::models::export::JSON_FORMAT_VERSION;
+use crate::names;
+use crate::output;
+use crate::priority::Priority;
+use crate::resolve::Resolver;
+use crate::state::State;
+use crate::timestamp;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+
+/// What `import` does when a project or research record of the same name
+/// already exists.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OnConflict {
+    /// Leave the existing one alone. A skipped project's subtree is not
+    /// imported; links to a skipped research record go to the existing one.
+    Skip,
+    /// Import it under the first free `<name>-2`, `<name>-3`, ...
+    Rename,
+    /// Abort the import; nothing is written.
+    Fail,
+}
+
+impl std::str::FromStr for OnConflict {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(OnConflict::Skip),
+            "rename" => Ok(OnConflict::Rename),
+            "fail" => Ok(OnConflict::Fail),
+            _ => Err(format!(
+                "unknown conflict strategy '{}': expected skip, rename, or fail",
+                s
+            )),
+        }
+    }
+}
+
+impl OnConflict {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OnConflict::Skip => "skip",
+            OnConflict::Rename => "rename",
+            OnConflict::Fail => "fail",
+        }
+    }
+}
+
+enum Failure {
+    /// The file is not an export document, or a conflict under `fail`.
+    Invalid(String),
+    Db(rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for Failure {
+    fn from(e: rusqlite::Error) -> Self {
+        Failure::Db(e)
+    }
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Db(e) => Failure::Db(e),
+            e => Failure::Invalid(e.to_string()),
+        }
+    }
+}
+
+const KINDS: [&str; 8] = [
+    "projects",
+    "modules",
+    "features",
+    "tasks",
+    "research",
+    "attachments",
+    "links",
+    "dependencies",
+];
+
+/// Created and skipped rows per kind, plus every rename made.
+#[derive(Default)]
+struct Tally {
+    created: HashMap<&'static str, usize>,
+    skipped: HashMap<&'static str, usize>,
+    renamed: Vec<Value>,
+}
+
+impl Tally {
+    fn create(&mut self, kind: &'static str) {
+        *self.created.entry(kind).or_default() += 1;
+    }
+
+    fn skip(&mut self, kind: &'static str, n: usize) {
+        *self.skipped.entry(kind).or_default() += n;
+    }
+
+    fn counts(map: &HashMap<&'static str, usize>) -> Value {
+        let mut obj = serde_json::Map::new();
+        for k in KINDS {
+            obj.insert(k.to_string(), json!(map.get(k).copied().unwrap_or(0)));
+        }
+        Value::Object(obj)
+    }
+
+    fn summary(map: &HashMap<&'static str, usize>) -> String {
+        let parts: Vec<String> = KINDS
+            .iter()
+            .filter_map(|k| {
+                map.get(k)
+                    .filter(|n| **n > 0)
+                    .map(|n| format!("{} {}", n, k))
+            })
+            .collect();
+        if parts.is_empty() {
+            "nothing".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// Old id to new id, per table.
+#[derive(Default)]
+struct Ids {
+    projects: HashMap<i64, i64>,
+    modules: HashMap<i64, i64>,
+    features: HashMap<i64, i64>,
+    tasks: HashMap<i64, i64>,
+    research: HashMap<i64, i64>,
+}
+
+fn text<'a>(v: &'a Value, key: &str) -> &'a str {
+    v[key].as_str().unwrap_or("")
+}
+
+/// A timestamp from the document in the canonical form, or now when it has
+/// none. Anything `timestamp::parse_tolerant` cannot read is refused, since
+/// every stored timestamp must compare and slice as the canonical form does.
+fn stamp(v: &Value, key: &str, ts: &str) -> Result<String, Failure> {
+    let Some(raw) = v[key].as_str().filter(|s| !s.trim().is_empty()) else {
+        return Ok(ts.to_string());
+    };
+    timestamp::parse_tolerant(raw)
+        .map(|dt| timestamp::canonical(&dt))
+        .ok_or_else(|| {
+            let owner = v["name"].as_str().or(v["path"].as_str()).unwrap_or("?");
+            Failure::Invalid(format!(
+                "{} of '{}' is not a timestamp: '{}'; expected e.g. 2024-03-04T05:06:07Z",
+                key, owner, raw
+            ))
+        })
+}
+
+fn items<'a>(v: &'a Value, key: &str) -> &'a [Value] {
+    v[key].as_array().map(Vec::as_slice).unwrap_or(&[])
+}
+
+fn id(v: &Value, what: &str) -> Result<i64, Failure> {
+    v["id"]
+        .as_i64()
+        .ok_or_else(|| Failure::Invalid(format!("{} without an id", what)))
+}
+
+fn name(v: &Value, what: &str) -> Result<String, Failure> {
+    let n = names::normalize(text(v, "name"));
+    if n.is_empty() {
+        return Err(Failure::Invalid(format!("{} without a name", what)));
+    }
+    Ok(n)
+}
+
+fn state(v: &Value) -> Result<String, Failure> {
+    let s = v["state"].as_str().unwrap_or("Draft");
+    s.parse::<State>()
+        .map(|s| s.to_string())
+        .map_err(Failure::Invalid)
+}
+
+fn priority(v: &Value) -> Result<String, Failure> {
+    let p = v["priority"].as_str().unwrap_or("medium");
+    p.parse::<Priority>()
+        .map(|p| p.to_string())
+        .map_err(Failure::Invalid)
+}
+
+/// Modules, features, and tasks under a project, for the skipped counts.
+fn subtree_sizes(p: &Value) -> (usize, usize, usize) {
+    let modules = items(p, "modules");
+    let features: Vec<&Value> = modules.iter().flat_map(|m| items(m, "features")).collect();
+    let tasks = features.iter().map(|f| items(f, "tasks").len()).sum();
+    (modules.len(), features.len(), tasks)
+}
+
+/// The name to import under, or None to skip. `kind` is unique across the
+/// database by name.
+fn resolve_name(
+    resolver: &Resolver,
+    kind: &'static str,
+    wanted: &str,
+    on_conflict: OnConflict,
+    tally: &mut Tally,
+) -> Result<Option<String>, Failure> {
+    let Some(existing) = resolver.clash(kind, None, wanted)? else {
+        return Ok(Some(wanted.to_string()));
+    };
+    match on_conflict {
+        OnConflict::Skip => Ok(None),
+        OnConflict::Fail => Err(Failure::Invalid(format!(
+            "{} already exists: {} (pass --on-conflict skip or rename)",
+            kind, existing
+        ))),
+        OnConflict::Rename => {
+            let mut n = 2;
+            let free = loop {
+                let candidate = format!("{}-{}", wanted, n);
+                if resolver.clash(kind, None, &candidate)?.is_none() {
+                    break candidate;
+                }
+                n += 1;
+            };
+            tally
+                .renamed
+                .push(json!({"type": kind, "from": wanted, "to": free}));
+            Ok(Some(free))
+        }
+    }
+}
+
+fn import_project(
+    resolver: &Resolver,
+    p: &Value,
+    on_conflict: OnConflict,
+    ids: &mut Ids,
+    tally: &mut Tally,
+    ts: &str,
+) -> Result<(), Failure> {
+    let conn = resolver.conn();
+    let old = id(p, "project")?;
+    let wanted = name(p, "project")?;
+    let Some(project_name) = resolve_name(resolver, "project", &wanted, on_conflict, tally)? else {
+        let (m, f, t) = subtree_sizes(p);
+        tally.skip("projects", 1);
+        tally.skip("modules", m);
+        tally.skip("features", f);
+        tally.skip("tasks", t);
+        return Ok(());
+    };
+    conn.execute(
+        "INSERT INTO projects (name, path, description, completed, updated_at) VALUES (?1,?2,?3,?4,?5)",
+        params![
+            project_name,
+            text(p, "path"),
+            text(p, "description"),
+            p["completed"].as_bool().unwrap_or(false),
+            stamp(p, "updated_at", ts)?
+        ],
+    )?;
+    let project_id = conn.last_insert_rowid();
+    resolver.remember("project", None, &project_name, project_id);
+    ids.projects.insert(old, project_id);
+    tally.create("projects");
+
+    for m in items(p, "modules") {
+        conn.execute(
+            "INSERT INTO modules (project_id, name, description, details, state, last_worked_on, locked, path, last_transition_reason)
+             VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9)",
+            params![
+                project_id,
+                name(m, "module")?,
+                text(m, "description"),
+                text(m, "details"),
+                state(m)?,
+                stamp(m, "last_worked_on", ts)?,
+                m["locked"].as_bool().unwrap_or(false),
+                text(m, "path"),
+                text(m, "last_transition_reason")
+            ],
+        )?;
+        let module_id = conn.last_insert_rowid();
+        ids.modules.insert(id(m, "module")?, module_id);
+        tally.create("modules");
+
+        for f in items(m, "features") {
+            conn.execute(
+                "INSERT INTO features (module_id, name, description, details, state, last_worked_on, locked, last_transition_reason, priority)
+                 VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9)",
+                params![
+                    module_id,
+                    name(f, "feature")?,
+                    text(f, "description"),
+                    text(f, "details"),
+                    state(f)?,
+                    stamp(f, "last_worked_on", ts)?,
+                    f["locked"].as_bool().unwrap_or(false),
+                    text(f, "last_transition_reason"),
+                    priority(f)?
+                ],
+            )?;
+            let feature_id = conn.last_insert_rowid();
+            ids.features.insert(id(f, "feature")?, feature_id);
+            tally.create("features");
+
+            for t in items(f, "tasks") {
+                conn.execute(
+                    "INSERT INTO tasks (feature_id, name, description, details, state, last_worked_on, last_transition_reason, priority, position)
+                     VALUES (?1,?2,?3,?4,?5,?6,?7,?8,
+                             (SELECT COALESCE(MAX(position), 0) + 1 FROM tasks WHERE feature_id=?1))",
+                    params![
+                        feature_id,
+                        name(t, "task")?,
+                        text(t, "description"),
+                        text(t, "details"),
+                        state(t)?,
+                        stamp(t, "last_worked_on", ts)?,
+                        text(t, "last_transition_reason"),
+                        priority(t)?
+                    ],
+                )?;
+                ids.tasks.insert(id(t, "task")?, conn.last_insert_rowid());
+                tally.create("tasks");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn import_research(
+    resolver: &Resolver,
+    r: &Value,
+    on_conflict: OnConflict,
+    ids: &mut Ids,
+    tally: &mut Tally,
+    ts: &str,
+) -> Result<(), Failure> {
+    let conn = resolver.conn();
+    let old = id(r, "research record")?;
+    let wanted = name(r, "research record")?;
+    let Some(research_name) = resolve_name(resolver, "research", &wanted, on_conflict, tally)?
+    else {
+        let existing = resolver
+            .named("research", None, &wanted)?
+            .ok_or_else(|| Failure::Invalid(format!("research record not found: {}", wanted)))?;
+        ids.research.insert(old, existing);
+        tally.skip("research", 1);
+        tally.skip("attachments", items(r, "attachments").len());
+        return Ok(());
+    };
+    conn.execute(
+        "INSERT INTO research (name, description, source, researched_at, created_at, updated_at, archived)
+         VALUES (?1,?2,?3,?4,?5,?6,?7)",
+        params![
+            research_name,
+            text(r, "description"),
+            text(r, "source"),
+            stamp(r, "researched_at", ts)?,
+            stamp(r, "created_at", ts)?,
+            stamp(r, "updated_at", ts)?,
+            r["archived"].as_bool().unwrap_or(false)
+        ],
+    )?;
+    let research_id = conn.last_insert_rowid();
+    resolver.remember("research", None, &research_name, research_id);
+    content::save(conn, research_id, text(r, "content"))?;
+    ids.research.insert(old, research_id);
+    tally.create("research");
+
+    // Copies belong to the record they were made for, so the new record only
+    // references them and never deletes them on removal.
+    for a in items(r, "attachments") {
+        conn.execute(
+            "INSERT OR IGNORE INTO research_attachments (research_id, path, copied, size, added_at)
+             VALUES (?1,?2,0,?3,?4)",
+            params![
+                research_id,
+                text(a, "path"),
+                a["size"].as_i64().unwrap_or(0),
+                stamp(a, "added_at", ts)?
+            ],
+        )?;
+        tally.create("attachments");
+    }
+    Ok(())
+}
+
+fn import_links(
+    conn: &Connection,
+    doc: &Value,
+    ids: &Ids,
+    tally: &mut Tally,
+) -> Result<(), Failure> {
+    let tables: [(&str, &str, &str, &HashMap<i64, i64>); 4] = [
+        ("projects", "research_projects", "project_id", &ids.projects),
+        ("modules", "research_modules", "module_id", &ids.modules),
+        ("features", "research_features", "feature_id", &ids.features),
+        ("tasks", "research_tasks", "task_id", &ids.tasks),
+    ];
+    for (key, table, column, map) in tables {
+        for l in items(&doc["links"], key) {
+            let research = l["research_id"].as_i64().and_then(|i| ids.research.get(&i));
+            let entity = l[column].as_i64().and_then(|i| map.get(&i));
+            let (Some(research), Some(entity)) = (research, entity) else {
+                tally.skip("links", 1);
+                continue;
+            };
+            let n = conn.execute(
+                &format!(
+                    "INSERT OR IGNORE INTO {} (research_id, {}) VALUES (?1,?2)",
+                    table, column
+                ),
+                params![research, entity],
+            )?;
+            if n > 0 {
+                tally.create("links");
+            } else {
+                tally.skip("links", 1);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Task dependencies between tasks the document created; one whose other
+/// end was outside the export is skipped.
+fn import_dependencies(
+    conn: &Connection,
+    doc: &Value,
+    ids: &Ids,
+    tally: &mut Tally,
+) -> Result<(), Failure> {
+    for d in items(doc, "dependencies") {
+        let task = d["task_id"].as_i64().and_then(|i| ids.tasks.get(&i));
+        let on = d["depends_on_task_id"]
+            .as_i64()
+            .and_then(|i| ids.tasks.get(&i));
+        let (Some(task), Some(on)) = (task, on) else {
+            tally.skip("dependencies", 1);
+            continue;
+        };
+        let n = conn.execute(
+            "INSERT OR IGNORE INTO task_dependencies (task_id, depends_on_task_id) VALUES (?1,?2)",
+            params![task, on],
+        )?;
+        if n > 0 {
+            tally.create("dependencies");
+        } else {
+            tally.skip("dependencies", 1);
+        }
+    }
+    Ok(())
+}
+
+fn import_document(
+    conn: &Connection,
+    doc: &Value,
+    on_conflict: OnConflict,
+    tally: &mut Tally,
+) -> Result<(), Failure> {
+    if doc["format"].as_str() != Some("lopen-memory") {
+        return Err(Failure::Invalid(
+            "not a lopen-memory export (expected \"format\": \"lopen-memory\")".to_string(),
+        ));
+    }
+    match doc["format_version"].as_i64() {
+        Some(v) if v <= JSON_FORMAT_VERSION => {}
+        Some(v) => {
+            return Err(Failure::Invalid(format!(
+                "export format_version {} is newer than this binary understands ({}); upgrade lopen-memory",
+                v, JSON_FORMAT_VERSION
+            )))
+        }
+        None => return Err(Failure::Invalid("export has no format_version".to_string())),
+    }
+    let ts = timestamp::now();
+    let mut ids = Ids::default();
+    // Each project or record is checked against the names already taken,
+    // which are read once rather than once per item.
+    let resolver = Resolver::new(conn);
+    for p in items(doc, "projects") {
+        import_project(&resolver, p, on_conflict, &mut ids, tally, &ts)?;
+    }
+    for r in items(doc, "research") {
+        import_research(&resolver, r, on_conflict, &mut ids, tally, &ts)?;
+    }
+    import_links(conn, doc, &ids, tally)?;
+    import_dependencies(conn, doc, &ids, tally)
+}
+
+/// Load a document inside the caller's transaction, for `snapshot restore`.
+/// Projects are created under the names the document gives, which the caller
+/// has made sure are free; research that already exists by name is linked to
+/// rather than duplicated. Returns the created counts.
+pub fn restore(conn: &Connection, doc: &Value) -> Result<Value, Error> {
+    let mut tally = Tally::default();
+    match import_document(conn, doc, OnConflict::Skip, &mut tally) {
+        Ok(()) => Ok(Tally::counts(&tally.created)),
+        Err(Failure::Invalid(e)) => Err(Error::Invalid(e)),
+        Err(Failure::Db(e)) => Err(Error::Db(e)),
+    }
+}
+
+/// Load a document written by `export --format json`. Everything is written
+/// in one transaction, so a conflict under `fail` or a bad row part way
+/// through leaves the database as it was. Ids in the file are remapped to new
+/// ones and links follow them; name history, contexts, and locks are not part
+/// of an export and are not touched.
+pub fn import(conn: &Connection, file: &str, on_conflict: OnConflict, json: bool) -> i32 {
+    let raw = match fs::read_to_string(file) {
+        Ok(t) => t,
+        Err(e) => {
+            return output::fail(&Error::Io(format!("failed to read {}: {}", file, e)));
+        }
+    };
+    let doc: Value = match serde_json::from_str(&raw) {
+        Ok(d) => d,
+        Err(e) => {
+            output::err(&format!("{} is not valid JSON: {}", file, e));
+            return 1;
+        }
+    };
+    let tx = match db::write_tx(conn) {
+        Ok(tx) => tx,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let mut tally = Tally::default();
+    match import_document(&tx, &doc, on_conflict, &mut tally) {
+        Ok(()) => {}
+        Err(Failure::Invalid(e)) => {
+            output::err(&format!("{}; nothing was imported", e));
+            return 1;
+        }
+        Err(Failure::Db(e)) => {
+            output::err(&format!("{}; nothing was imported", e));
+            return 2;
+        }
+    }
+    if let Err(e) = tx.commit() {
+        output::err(&e.to_string());
+        return 2;
+    }
+
+    if json {
+        output::print_json(&json!({
+            "file": file,
+            "on_conflict": on_conflict.as_str(),
+            "created": Tally::counts(&tally.created),
+            "skipped": Tally::counts(&tally.skipped),
+            "renamed": tally.renamed,
+        }));
+    } else {
+        output::print_plain(&format!(
+            "imported {} from {}",
+            Tally::summary(&tally.created),
+            file
+        ));
+        if !tally.skipped.is_empty() {
+            output::print_plain(&format!("skipped {}", Tally::summary(&tally.skipped)));
+        }
+        for r in &tally.renamed {
+            outln!(
+                "  renamed {} {} to {}",
+                text(r, "type"),
+                text(r, "from"),
+                text(r, "to")
+            );
+        }
+    }
+    0
+}
@@ -0,0 +1,178 @@
+use crate::config;
+use crate::error::Error;
+use crate::models::ancestry;
+use crate::models::history;
+use crate::resolve;
+use crate::state::{forward_path, validate_transition, State};
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+const POLICY_KEY: &str = "parent_policy";
+const POLICY_ENV: &str = "LOPEN_MEMORY_PARENT_POLICY";
+
+/// What a task or feature transition into Planning or Building does about
+/// parents still in an earlier stage.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ParentPolicy {
+    /// Say which parents are behind and how to move them (the default)
+    Warn,
+    /// Move them forward in the same transaction
+    Lift,
+    /// Say nothing
+    Ignore,
+}
+
+impl std::str::FromStr for ParentPolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "warn" => Ok(ParentPolicy::Warn),
+            "lift" => Ok(ParentPolicy::Lift),
+            "ignore" => Ok(ParentPolicy::Ignore),
+            other => Err(format!(
+                "unknown parent policy '{}': expected warn, lift, or ignore",
+                other
+            )),
+        }
+    }
+}
+
+/// The effective policy: lift with --lift-parents, else LOPEN_MEMORY_PARENT_POLICY,
+/// else `parent_policy` in the config, else warn. Unknown values are an error
+/// rather than a silent fallback.
+pub fn policy(lift_parents: bool) -> Result<ParentPolicy, String> {
+    if lift_parents {
+        return Ok(ParentPolicy::Lift);
+    }
+    match std::env::var(POLICY_ENV)
+        .ok()
+        .or_else(|| config::get_str(POLICY_KEY))
+    {
+        Some(v) => v.parse(),
+        None => Ok(ParentPolicy::Warn),
+    }
+}
+
+/// A parent that is behind the child's new state, and the steps that bring it level.
+pub struct Lift {
+    pub kind: &'static str,
+    pub id: i64,
+    pub name: String,
+    pub from: String,
+    pub steps: Vec<State>,
+    locked: bool,
+}
+
+impl Lift {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "type": self.kind,
+            "id": self.id,
+            "ref": resolve::short_ref(self.kind, self.id),
+            "name": self.name,
+            "from": self.from,
+            "to": self.steps.last().map(|s| s.to_string()),
+            "steps": self.steps.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+        })
+    }
+
+    fn path(&self) -> String {
+        let mut states = vec![self.from.clone()];
+        states.extend(self.steps.iter().map(|s| s.to_string()));
+        states.join(" → ")
+    }
+}
+
+/// Module and feature ancestors of a task or feature that are behind `to`,
+/// outermost first. Only Planning and Building pull parents along; a parent
+/// already at or beyond `to` (including Complete and Amending) is never listed.
+pub fn behind(conn: &Connection, kind: &str, id: i64, to: &State) -> rusqlite::Result<Vec<Lift>> {
+    if !matches!(to, State::Planning | State::Building) {
+        return Ok(Vec::new());
+    }
+    let mut lifts = Vec::new();
+    for c in ancestry::chain(conn, kind, id)? {
+        if !(c.kind == "module" || c.kind == "feature") || c.kind == kind {
+            continue;
+        }
+        let table = if c.kind == "module" {
+            "modules"
+        } else {
+            "features"
+        };
+        let (state, locked): (String, bool) = conn.query_row(
+            &format!("SELECT state, locked FROM {} WHERE id=?1", table),
+            params![c.id],
+            |r| Ok((r.get(0)?, r.get::<_, i64>(1)? != 0)),
+        )?;
+        let Some(steps) = state.parse().ok().and_then(|s| forward_path(&s, to)) else {
+            continue;
+        };
+        lifts.push(Lift {
+            kind: c.kind,
+            id: c.id,
+            name: c.name,
+            from: state,
+            steps,
+            locked,
+        });
+    }
+    Ok(lifts)
+}
+
+/// Tell the user which parents are behind and how to bring them level.
+pub fn warn(lifts: &[Lift]) {
+    for l in lifts {
+        eprintln!(
+            "warning: parent {} {} ({}) is still {}; move it {} or rerun with --lift-parents",
+            l.kind,
+            l.name,
+            resolve::short_ref(l.kind, l.id),
+            l.from,
+            l.path()
+        );
+    }
+}
+
+/// Walk each parent through its steps, outermost first. Run on the child's own
+/// transaction: an error leaves every parent as it was once that is dropped.
+pub fn apply(conn: &Connection, lifts: &[Lift], ts: &str) -> Result<(), Error> {
+    for l in lifts {
+        if l.locked {
+            return Err(Error::Locked(format!(
+                "{} {} is locked; cannot lift it to {} (unlock it first)",
+                l.kind,
+                l.name,
+                l.steps.last().map(|s| s.to_string()).unwrap_or_default()
+            )));
+        }
+        let table = if l.kind == "module" {
+            "modules"
+        } else {
+            "features"
+        };
+        let mut from = l.from.clone();
+        for step in &l.steps {
+            validate_transition(&from, step).map_err(|e| {
+                Error::InvalidTransition(format!("{} for {} {}", e, l.kind, l.name))
+            })?;
+            conn.execute(
+                &format!(
+                    "UPDATE {} SET state=?1, last_worked_on=?2, last_transition_reason='' WHERE id=?3",
+                    table
+                ),
+                params![step.to_string(), ts, l.id],
+            )
+            .and_then(|_| history::record_state(conn, l.kind, l.id, &from, &step.to_string(), None, ts))?;
+            from = step.to_string();
+        }
+    }
+    Ok(())
+}
+
+/// One line per lifted parent for plain output.
+pub fn report(lifts: &[Lift]) {
+    for l in lifts {
+        outln!("lifted {} {}: {}", l.kind, l.name, l.path());
+    }
+}
@@ -0,0 +1,217 @@
+use crate::error::Error;
+use crate::output;
+use crate::timestamp;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+
+/// A named advisory lock. Nothing in lopen-memory checks these; they exist so
+/// agents sharing one database file can agree on who goes next.
+pub struct Lock {
+    pub name: String,
+    pub holder: String,
+    pub acquired_at: String,
+    pub expires_at: String,
+}
+
+impl Lock {
+    /// Whole seconds until the lock expires, never negative.
+    pub fn remaining_secs(&self, now: &DateTime<Utc>) -> i64 {
+        timestamp::parse_tolerant(&self.expires_at)
+            .map(|t| (t - *now).num_seconds().max(0))
+            .unwrap_or(0)
+    }
+
+    fn to_json(&self, now: &DateTime<Utc>) -> Value {
+        json!({
+            "name": self.name,
+            "held": true,
+            "holder": self.holder,
+            "acquired_at": self.acquired_at,
+            "expires_at": self.expires_at,
+            "remaining_secs": self.remaining_secs(now),
+        })
+    }
+
+    fn holder_label(&self) -> &str {
+        if self.holder.is_empty() {
+            "(no holder given)"
+        } else {
+            &self.holder
+        }
+    }
+}
+
+/// The unexpired lock of that name, if any.
+fn held(conn: &Connection, name: &str, now: &str) -> rusqlite::Result<Option<Lock>> {
+    conn.query_row(
+        "SELECT name, holder, acquired_at, expires_at FROM locks WHERE name=?1 AND expires_at > ?2",
+        params![name, now],
+        |r| {
+            Ok(Lock {
+                name: r.get(0)?,
+                holder: r.get(1)?,
+                acquired_at: r.get(2)?,
+                expires_at: r.get(3)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Take the named lock for `ttl_secs`. Expired locks are reaped first; the
+/// insert itself only succeeds when no row of that name is left, so two
+/// callers racing for the same name cannot both win. A lock that is held,
+/// even by the same holder, is refused with exit 4.
+pub fn acquire(conn: &Connection, name: &str, ttl_secs: i64, holder: &str, json: bool) -> i32 {
+    let now = Utc::now();
+    let ts = timestamp::canonical(&now);
+    let expires = timestamp::canonical(&(now + Duration::seconds(ttl_secs)));
+    let inserted = conn
+        .execute("DELETE FROM locks WHERE expires_at <= ?1", params![ts])
+        .and_then(|_| {
+            conn.execute(
+                "INSERT OR IGNORE INTO locks (name, holder, acquired_at, expires_at) VALUES (?1,?2,?3,?4)",
+                params![name, holder, ts, expires],
+            )
+        });
+    let acquired = match inserted {
+        Ok(n) => n > 0,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let lock = match held(conn, name, &ts) {
+        Ok(Some(l)) => l,
+        Ok(None) => {
+            output::err(&format!("lock {} was not saved", name));
+            return 2;
+        }
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    if json {
+        let mut v = lock.to_json(&now);
+        v["acquired"] = json!(acquired);
+        output::print_json(&v);
+    } else if acquired {
+        output::print_plain(&format!(
+            "acquired lock {} for {}s (until {})",
+            name, ttl_secs, lock.expires_at
+        ));
+    }
+    if acquired {
+        return 0;
+    }
+    output::err(&format!(
+        "lock {} is held by {} for another {}s",
+        name,
+        lock.holder_label(),
+        lock.remaining_secs(&now)
+    ));
+    output::EXIT_LOCKED
+}
+
+/// Give up the named lock. With `holder`, only that holder's lock is released.
+/// Releasing a lock nobody holds (never taken, expired, or already released) exits 1.
+pub fn release(conn: &Connection, name: &str, holder: Option<&str>, json: bool) -> i32 {
+    let now = Utc::now();
+    let ts = timestamp::canonical(&now);
+    let lock = match held(conn, name, &ts) {
+        Ok(l) => l,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let Some(lock) = lock else {
+        if json {
+            output::print_json(&json!({"name": name, "released": false}));
+        }
+        return output::fail(&Error::InvalidState(format!("lock {} is not held", name)));
+    };
+    if let Some(h) = holder {
+        if h != lock.holder {
+            output::err(&format!(
+                "lock {} is held by {}, not {}",
+                name,
+                lock.holder_label(),
+                h
+            ));
+            return output::EXIT_LOCKED;
+        }
+    }
+    if let Err(e) = conn.execute("DELETE FROM locks WHERE name=?1", params![name]) {
+        output::err(&e.to_string());
+        return 2;
+    }
+    if json {
+        output::print_json(&json!({"name": name, "released": true, "holder": lock.holder}));
+    } else {
+        output::print_plain(&format!("released lock {}", name));
+    }
+    0
+}
+
+/// Show the named lock, or every unexpired lock.
+pub fn status(conn: &Connection, name: Option<&str>, json: bool) -> i32 {
+    let now = Utc::now();
+    let ts = timestamp::canonical(&now);
+    let locks: rusqlite::Result<Vec<Lock>> = match name {
+        Some(n) => held(conn, n, &ts).map(|l| l.into_iter().collect()),
+        None => conn
+            .prepare(
+                "SELECT name, holder, acquired_at, expires_at FROM locks WHERE expires_at > ?1 ORDER BY name_key(name), fold(name), name",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_map(params![ts], |r| {
+                    Ok(Lock {
+                        name: r.get(0)?,
+                        holder: r.get(1)?,
+                        acquired_at: r.get(2)?,
+                        expires_at: r.get(3)?,
+                    })
+                })?
+                .collect()
+            }),
+    };
+    let locks = match locks {
+        Ok(l) => l,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    if json {
+        match name {
+            Some(n) => output::print_json(
+                &locks
+                    .first()
+                    .map(|l| l.to_json(&now))
+                    .unwrap_or_else(|| json!({"name": n, "held": false})),
+            ),
+            None => output::print_json(&Value::Array(
+                locks.iter().map(|l| l.to_json(&now)).collect(),
+            )),
+        }
+    } else if locks.is_empty() {
+        match name {
+            Some(n) => output::print_plain(&format!("lock {} is not held", n)),
+            None => output::print_plain("no locks held"),
+        }
+    } else {
+        for l in &locks {
+            outln!(
+                "{:<24} {:<24} {:>6}s left  (since {})",
+                l.name,
+                l.holder_label(),
+                l.remaining_secs(&now),
+                l.acquired_at
+            );
+        }
+    }
+    0
+}
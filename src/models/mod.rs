@@ -1,5 +1,41 @@
+pub mod admin;
+pub mod analytics;
+pub mod ancestry;
+pub mod attachment;
+pub mod backup;
+pub mod bibliography;
+pub mod bootstrap;
+pub mod bulk;
+pub mod cleanup;
+pub mod context;
+pub mod dependency;
+pub mod diff;
+pub mod export;
 pub mod feature;
+pub mod find;
+pub mod history;
+pub mod import;
+pub mod lift;
+pub mod locks;
 pub mod module;
+pub mod next;
+pub mod notes;
+pub mod placement;
+pub mod progress;
 pub mod project;
+pub mod reason;
+pub mod recent;
+pub mod references;
 pub mod research;
+pub mod sections;
+pub mod select;
+pub mod setup;
+pub mod share;
+pub mod snapshot;
+pub mod status;
 pub mod task;
+pub mod todos;
+pub mod transition;
+pub mod trash;
+pub mod tree;
+pub mod validate;
@@ -1,8 +1,27 @@
+use crate::db;
+use crate::error::Error;
+use crate::fields::{self, Page, Sort};
+use crate::models::history;
+use crate::models::placement::Placement;
+use crate::models::progress;
+use crate::models::reason;
+use crate::models::references::{self, Renamed};
+use crate::models::research;
+use crate::models::transition::Transitioned;
+use crate::models::trash::{self, Removed};
+use crate::names;
 use crate::output;
-use crate::state::{validate_transition, State};
+use crate::resolve;
+use crate::state::{self, validate_transition, State};
+use crate::timestamp;
 use rusqlite::{params, Connection};
+use serde::Serialize;
 use serde_json::{json, Value};
+use std::path::{Component, Path};
 
+/// Serializes to the record `module show --json` starts from.
+#[derive(Debug, Clone, Serialize)]
+#[serde(into = "Value")]
 pub struct Module {
     pub id: i64,
     pub project_id: i64,
@@ -11,15 +30,15 @@ pub struct Module {
     pub details: String,
     pub state: String,
     pub last_worked_on: String,
+    pub locked: bool,
+    /// Subdirectory of the project path this module's work lives in; empty if unmapped.
+    pub path: String,
 }
 
-fn now() -> String {
-    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
-}
-
-fn load(conn: &Connection, id: i64) -> Result<Module, String> {
+/// The module with this id.
+pub fn get(conn: &Connection, id: i64) -> Result<Module, Error> {
     conn.query_row(
-        "SELECT id, project_id, name, description, details, state, last_worked_on FROM modules WHERE id=?1",
+        "SELECT id, project_id, name, description, details, state, last_worked_on, locked, path FROM modules WHERE id=?1",
         params![id],
         |r| Ok(Module {
             id: r.get(0)?,
@@ -29,85 +48,249 @@ fn load(conn: &Connection, id: i64) -> Result<Module, String> {
             details: r.get(4)?,
             state: r.get(5)?,
             last_worked_on: r.get(6)?,
+            locked: r.get::<_, i64>(7)? != 0,
+            path: r.get(8)?,
         }),
     )
-    .map_err(|_| format!("module not found: {}", id))
+    .map_err(|e| Error::missing(e, "module", id))
 }
 
+/// The keys of a listed module's JSON, in order: the columns of `module list`
+/// under csv and tsv.
+pub const COLUMNS: &[&str] = &[
+    "description",
+    "details",
+    "id",
+    "last_worked_on",
+    "locked",
+    "name",
+    "path",
+    "project_id",
+    "ref",
+    "state",
+];
+
 fn module_to_json(m: &Module) -> Value {
     json!({
         "id": m.id,
+        "ref": resolve::short_ref("module", m.id),
         "project_id": m.project_id,
         "name": m.name,
         "description": m.description,
         "details": m.details,
         "state": m.state,
         "last_worked_on": m.last_worked_on,
+        "locked": m.locked,
+        "path": m.path,
     })
 }
 
-pub fn add(conn: &Connection, project_id: i64, name: &str, description: &str, json: bool) -> i32 {
-    let name = name.trim();
-    if name.is_empty() {
-        output::err("name must not be empty");
-        return 1;
+impl From<Module> for Value {
+    fn from(m: Module) -> Value {
+        module_to_json(&m)
     }
-    // get project name for output
-    let project_name: String = conn
+}
+
+/// The module's full JSON record, as `module show` prints it before adding
+/// related entities.
+pub fn record_json(conn: &Connection, id: i64) -> Result<Value, Error> {
+    get(conn, id).map(|x| module_to_json(&x))
+}
+
+/// Why a locked module refused an operation.
+fn locked_message(m: &Module, action: &str) -> String {
+    format!(
+        "module {} is locked; cannot {} (unlock it first)",
+        m.name, action
+    )
+}
+
+/// How an add or rename reports a module name already used in the project.
+fn taken(conn: &Connection, project_id: i64, name: &str) -> String {
+    let project: String = conn
         .query_row(
             "SELECT name FROM projects WHERE id=?1",
             params![project_id],
             |r| r.get(0),
         )
         .unwrap_or_default();
-    let ts = now();
-    match conn.execute(
-        "INSERT INTO modules (project_id, name, description, last_worked_on) VALUES (?1,?2,?3,?4)",
-        params![project_id, name, description, ts],
+    names::taken("module", name, Some(("project", &project)))
+}
+
+/// Tidy a module path as given on the command line: relative to the project,
+/// without `./` prefixes or trailing separators. Empty clears the mapping.
+fn normalize_path(path: &str) -> Result<String, String> {
+    let path = path.trim();
+    if Path::new(path).is_absolute() {
+        return Err(format!(
+            "module path must be relative to the project path: {}",
+            path
+        ));
+    }
+    if Path::new(path)
+        .components()
+        .any(|c| c == Component::ParentDir)
+    {
+        return Err(format!(
+            "module path must stay inside the project path: {}",
+            path
+        ));
+    }
+    let parts: Vec<&str> = path
+        .split(['/', '\\'])
+        .filter(|p| !p.is_empty() && *p != ".")
+        .collect();
+    Ok(parts.join("/"))
+}
+
+/// Warn on stderr when a module path does not name a directory under the
+/// project root. The path is still recorded: the checkout may live elsewhere.
+fn warn_if_missing(conn: &Connection, project_id: i64, path: &str) {
+    if path.is_empty() {
+        return;
+    }
+    let root: String = conn
+        .query_row(
+            "SELECT path FROM projects WHERE id=?1",
+            params![project_id],
+            |r| r.get(0),
+        )
+        .unwrap_or_default();
+    if !Path::new(&root).join(path).is_dir() {
+        eprintln!(
+            "warning: module path {} does not exist under project root {}",
+            path, root
+        );
+    }
+}
+
+/// Add a module to a project, starting in Draft or walked forward to `start`.
+/// The name is stored in normal form and the path tidied as `normalize_path`
+/// does.
+pub fn create(
+    conn: &Connection,
+    project_id: i64,
+    name: &str,
+    description: &str,
+    details: &str,
+    path: &str,
+    start: Option<&State>,
+) -> Result<Module, Error> {
+    let name = names::normalize(name);
+    if name.is_empty() {
+        return Err(Error::Invalid("name must not be empty".into()));
+    }
+    if let Some(existing) = names::clash(
+        conn,
+        "modules",
+        Some(("project_id", project_id)),
+        &name,
+        None,
+    )? {
+        return Err(Error::Conflict(taken(conn, project_id, &existing)));
+    }
+    let path = normalize_path(path).map_err(Error::Invalid)?;
+    let walked = state::start_walk(start, "module", &name)?;
+    let state = walked.last().unwrap().to_string();
+    let ts = timestamp::now();
+    let tx = db::write_tx(conn)?;
+    match tx.execute(
+        "INSERT INTO modules (project_id, name, description, details, state, last_worked_on, path) VALUES (?1,?2,?3,?4,?5,?6,?7)",
+        params![project_id, name, description, details, state, ts, path],
     ) {
-        Ok(_) => {
-            let id = conn.last_insert_rowid();
-            if json {
-                output::print_json(&load(conn, id).map(|m| module_to_json(&m)).unwrap());
-            } else {
-                output::print_plain(&format!(
-                    "added module {}: {} (project: {})",
-                    id, name, project_name
-                ));
-            }
-            0
-        }
-        Err(e) => {
-            output::err(&e.to_string());
-            2
-        }
+        Ok(_) => {}
+        Err(e) if db::name_taken(&e) => return Err(Error::Conflict(taken(conn, project_id, &name))),
+        Err(e) => return Err(e.into()),
     }
+    let id = tx.last_insert_rowid();
+    history::record_walk(&tx, "module", id, &walked, &ts)?;
+    tx.commit()?;
+    Ok(Module {
+        id,
+        project_id,
+        name,
+        description: description.to_string(),
+        details: details.to_string(),
+        state,
+        last_worked_on: ts,
+        locked: false,
+        path,
+    })
 }
 
-pub fn list(conn: &Connection, project_id: i64, state_filter: Option<&str>, json: bool) -> i32 {
-    let modules: Vec<Module> = if let Some(s) = state_filter {
-        let mut stmt = conn.prepare(
-            "SELECT id, project_id, name, description, details, state, last_worked_on FROM modules WHERE project_id=?1 AND state=?2 ORDER BY id"
-        ).unwrap();
-        stmt.query_map(params![project_id, s], |r| {
-            Ok(Module {
-                id: r.get(0)?,
-                project_id: r.get(1)?,
-                name: r.get(2)?,
-                description: r.get(3)?,
-                details: r.get(4)?,
-                state: r.get(5)?,
-                last_worked_on: r.get(6)?,
-            })
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
+#[allow(clippy::too_many_arguments)]
+pub fn add(
+    conn: &Connection,
+    project_id: i64,
+    name: &str,
+    description: &str,
+    details: &str,
+    path: &str,
+    start: Option<&State>,
+    json: bool,
+) -> i32 {
+    let m = match create(conn, project_id, name, description, details, path, start) {
+        Ok(m) => m,
+        Err(e) => return output::fail(&e),
+    };
+    warn_if_missing(conn, project_id, &m.path);
+    if json {
+        output::print_json(&module_to_json(&m));
     } else {
-        let mut stmt = conn.prepare(
-            "SELECT id, project_id, name, description, details, state, last_worked_on FROM modules WHERE project_id=?1 ORDER BY id"
-        ).unwrap();
-        stmt.query_map(params![project_id], |r| {
+        let project_name: String = conn
+            .query_row(
+                "SELECT name FROM projects WHERE id=?1",
+                params![project_id],
+                |r| r.get(0),
+            )
+            .unwrap_or_default();
+        let walk = state::start_walk(start, "module", &m.name)
+            .map(|w| state::walk_note(&w))
+            .unwrap_or_default();
+        output::print_plain(&format!(
+            "added module {}: {} (project: {}){}",
+            m.id, m.name, project_name, walk
+        ));
+    }
+    0
+}
+
+/// The SQL behind each of `COLUMNS`, for `--sort`: states in lifecycle order
+/// and priorities most urgent first.
+fn sort_sql(field: &str) -> String {
+    match field {
+        "state" => state::rank_sql("state"),
+        "name" => names::order_sql("name"),
+        "ref" => "id".to_string(),
+        field => field.to_string(),
+    }
+}
+
+/// The query behind `all`, ordered but not paged; `?1` is the project and
+/// `?2` the state filter.
+fn all_sql(sort: Option<Sort>) -> String {
+    let order = match sort {
+        Some(sort) => sort.order_by(&sort_sql(sort.field), "id"),
+        None => "id".to_string(),
+    };
+    format!(
+        "SELECT id, project_id, name, description, details, state, last_worked_on, locked, path FROM modules WHERE project_id=?1 AND (?2 IS NULL OR state=?2) ORDER BY {}",
+        order
+    )
+}
+
+/// The modules of a project in id order, or by `sort`, optionally only those
+/// in one state, and only `page` of them.
+pub fn all(
+    conn: &Connection,
+    project_id: i64,
+    state_filter: Option<&str>,
+    sort: Option<Sort>,
+    page: Page,
+) -> Result<Vec<Module>, Error> {
+    let mut stmt = conn.prepare(&format!("{}{}", all_sql(sort), page.sql()))?;
+    let modules = stmt
+        .query_map(params![project_id, state_filter], |r| {
             Ok(Module {
                 id: r.get(0)?,
                 project_id: r.get(1)?,
@@ -116,37 +299,89 @@ pub fn list(conn: &Connection, project_id: i64, state_filter: Option<&str>, json
                 details: r.get(4)?,
                 state: r.get(5)?,
                 last_worked_on: r.get(6)?,
+                locked: r.get::<_, i64>(7)? != 0,
+                path: r.get(8)?,
             })
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(modules)
+}
+
+/// How many modules `all` returns without a page.
+pub fn count(conn: &Connection, project_id: i64, state_filter: Option<&str>) -> Result<i64, Error> {
+    Ok(fields::count(
+        conn,
+        &all_sql(None),
+        params![project_id, state_filter],
+    )?)
+}
+
+/// `all`, printed, with only `fields` when given and, when paged, the total.
+pub fn list(
+    conn: &Connection,
+    project_id: i64,
+    state_filter: Option<&str>,
+    fields: Option<&[&str]>,
+    sort: Option<Sort>,
+    page: Page,
+    json: bool,
+) -> i32 {
+    let listed = all(conn, project_id, state_filter, sort, page).and_then(|m| {
+        let total = fields::wants_total(page)
+            .then(|| count(conn, project_id, state_filter))
+            .transpose()?;
+        Ok((m, total))
+    });
+    let (modules, total) = match listed {
+        Ok(l) => l,
+        Err(e) => return output::fail(&e),
     };
-    if modules.is_empty() {
-        output::print_plain("no modules found");
+    if modules.is_empty() && fields::empty_in_prose(json, total) {
+        output::print_empty("no modules found");
         return 0;
     }
-    if json {
-        output::print_json(&Value::Array(modules.iter().map(module_to_json).collect()));
+    if json || fields.is_some() {
+        let rows = modules.iter().map(module_to_json).collect();
+        fields::print_list(COLUMNS, fields, rows, total, json);
     } else {
         for m in &modules {
-            println!(
-                "{:<4} {:<20} {:<12} {}",
-                m.id, m.name, m.state, m.last_worked_on
+            outln!(
+                "{:<5} {:<20} {:<12} {}{}{}{}",
+                resolve::short_ref("module", m.id),
+                m.name,
+                m.state,
+                m.last_worked_on,
+                if m.path.is_empty() {
+                    String::new()
+                } else {
+                    format!("  {}/", m.path)
+                },
+                if m.locked { "  [locked]" } else { "" },
+                reason::blocked_tag(conn, "modules", m.id, &m.state)
             );
         }
     }
+    if !json {
+        fields::print_more(page, modules.len(), total);
+    }
     0
 }
 
-pub fn show(conn: &Connection, id: i64, json: bool) -> i32 {
-    let m = match load(conn, id) {
+pub fn show(conn: &Connection, id: i64, with_history: bool, json: bool) -> i32 {
+    let m = match get(conn, id) {
         Ok(m) => m,
-        Err(e) => {
-            output::err(&e);
-            return 1;
+        Err(e) => return output::fail(&e),
+    };
+    let changes = if with_history {
+        match history::changes(conn, "module", id) {
+            Ok(c) => Some(c),
+            Err(e) => return output::fail(&e.into()),
         }
+    } else {
+        None
     };
+    let last_reason = reason::last(conn, "modules", id);
+    let blocked = reason::blocked(conn, "modules", id, &m.state);
     let project_name: String = conn
         .query_row(
             "SELECT name FROM projects WHERE id=?1",
@@ -155,199 +390,516 @@ pub fn show(conn: &Connection, id: i64, json: bool) -> i32 {
         )
         .unwrap_or_default();
 
-    let mut fstmt = conn
-        .prepare("SELECT id, name, state FROM features WHERE module_id=?1 ORDER BY id")
-        .unwrap();
-    let features: Vec<(i64, String, String)> = fstmt
-        .query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect();
+    let features = match db::triples(
+        conn,
+        "SELECT id, name, state FROM features WHERE module_id=?1 ORDER BY id",
+        id,
+    ) {
+        Ok(r) => r,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
 
-    let mut rstmt = conn
-        .prepare(
-            "SELECT r.id, r.name, r.description FROM research r
+    let research = match db::triples(
+        conn,
+        "SELECT r.id, r.name, r.description FROM research r
          JOIN research_modules rm ON rm.research_id=r.id
          WHERE rm.module_id=?1 ORDER BY r.id",
-        )
-        .unwrap();
-    let research: Vec<(i64, String, String)> = rstmt
-        .query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect();
+        id,
+    ) {
+        Ok(r) => r,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
 
     if json {
         let mut v = module_to_json(&m);
+        v["last_transition_reason"] = json!(last_reason);
+        v["blocked_reason"] = json!(blocked);
+        v["allowed_transitions"] = json!(state::allowed_names(&m.state));
         v["project"] = Value::String(project_name);
         v["features"] = Value::Array(
             features
                 .iter()
-                .map(|(id, name, state)| json!({"id": id, "name": name, "state": state}))
+                .map(|(id, name, state)| {
+                    json!({
+                        "id": id, "ref": resolve::short_ref("feature", *id),
+                        "name": name, "state": state,
+                        "blocked_reason": reason::blocked(conn, "features", *id, state),
+                    })
+                })
                 .collect(),
         );
         v["research"] = Value::Array(
             research
                 .iter()
-                .map(|(id, name, desc)| json!({"id": id, "name": name, "description": desc}))
+                .map(|(id, name, desc)| {
+                    json!({
+                        "id": id, "ref": resolve::short_ref("research", *id),
+                        "name": name, "description": desc,
+                    })
+                })
                 .collect(),
         );
+        if let Some(changes) = &changes {
+            v["history"] = history::timeline_json(changes);
+        }
         output::print_json(&v);
     } else {
-        println!("{}", output::field("id", &m.id.to_string()));
-        println!("{}", output::field("name", &m.name));
-        println!("{}", output::field("project", &project_name));
-        println!("{}", output::field("description", &m.description));
-        println!("{}", output::field("details", &m.details));
-        println!("{}", output::field("state", &m.state));
-        println!("{}", output::field("last_worked_on", &m.last_worked_on));
+        outln!("{}", output::field("id", &m.id.to_string()));
+        outln!(
+            "{}",
+            output::field("ref", &resolve::short_ref("module", m.id))
+        );
+        outln!("{}", output::field("name", &m.name));
+        outln!("{}", output::field("project", &project_name));
+        outln!("{}", output::field("description", &m.description));
+        outln!("{}", output::field("details", &m.details));
+        outln!("{}", output::field("path", &m.path));
+        outln!("{}", output::field("state", &m.state));
+        let next = state::allowed_names(&m.state);
+        if !next.is_empty() {
+            outln!("{}", output::field("next", &next.join(" | ")));
+        }
+        if let Some(r) = &blocked {
+            outln!("{}", output::field("blocked", reason::blocked_text(r)));
+        }
+        if let Some(r) = &last_reason {
+            outln!("{}", output::field("last_transition_reason", r));
+        }
+        outln!("{}", output::field("last_worked_on", &m.last_worked_on));
+        outln!(
+            "{}",
+            output::field("locked", if m.locked { "true" } else { "false" })
+        );
         if !features.is_empty() {
-            println!();
-            println!("features:");
+            outln!();
+            outln!("features:");
             for (fid, fname, fstate) in &features {
-                println!("  {:<4} {:<20} {}", fid, fname, fstate);
+                outln!(
+                    "  {:<5} {:<20} {}{}",
+                    resolve::short_ref("feature", *fid),
+                    fname,
+                    fstate,
+                    reason::blocked_tag(conn, "features", *fid, fstate)
+                );
             }
         }
         if !research.is_empty() {
-            println!();
-            println!("research:");
+            outln!();
+            outln!("research:");
             for (rid, rname, rdesc) in &research {
-                println!("  {:<4} {:<20} {}", rid, rname, rdesc);
+                outln!(
+                    "  {:<5} {:<20} {}",
+                    resolve::short_ref("research", *rid),
+                    rname,
+                    rdesc
+                );
             }
         }
+        if let Some(changes) = &changes {
+            outln!();
+            outln!("history:");
+            history::print_timeline(changes);
+        }
     }
     0
 }
 
-pub fn rename(conn: &Connection, id: i64, new_name: &str, json: bool) -> i32 {
-    let new_name = new_name.trim();
+/// Rename a module, and with `update_refs` rewrite whole-word mentions of
+/// the old name across its project. A dry run finds the references and
+/// writes nothing.
+pub fn rename_to(
+    conn: &Connection,
+    id: i64,
+    new_name: &str,
+    update_refs: bool,
+    dry_run: bool,
+) -> Result<Renamed<Module>, Error> {
+    let new_name = names::normalize(new_name);
     if new_name.is_empty() {
-        output::err("name must not be empty");
-        return 1;
+        return Err(Error::Invalid("name must not be empty".into()));
     }
-    let old = match load(conn, id) {
-        Ok(m) => m,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+    let mut m = get(conn, id)?;
+    if let Some(existing) = names::clash(
+        conn,
+        "modules",
+        Some(("project_id", m.project_id)),
+        &new_name,
+        Some(id),
+    )? {
+        return Err(Error::Conflict(taken(conn, m.project_id, &existing)));
+    }
+    if m.locked {
+        return Err(Error::Locked(locked_message(&m, "rename")));
+    }
+    let (hits, touched) =
+        match references::rename(conn, "module", id, &m.name, &new_name, update_refs, dry_run) {
+            Ok(renamed) => renamed,
+            Err(e) if db::name_taken(&e) => {
+                return Err(Error::Conflict(taken(conn, m.project_id, &new_name)))
+            }
+            Err(e) => return Err(e.into()),
+        };
+    let old_name = m.name.clone();
+    if let Some(ts) = touched {
+        m.name = new_name;
+        m.last_worked_on = ts;
+    }
+    Ok(Renamed {
+        entity: m,
+        old_name,
+        hits,
+    })
+}
+
+pub fn rename(
+    conn: &Connection,
+    id: i64,
+    new_name: &str,
+    update_refs: bool,
+    dry_run: bool,
+    json: bool,
+) -> i32 {
+    let r = match rename_to(conn, id, new_name, update_refs, dry_run) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
     };
-    conn.execute(
-        "UPDATE modules SET name=?1, last_worked_on=?2 WHERE id=?3",
-        params![new_name, now(), id],
-    )
-    .unwrap();
+    let m = &r.entity;
+    if dry_run {
+        references::print_preview(
+            "module",
+            id,
+            &r.old_name,
+            &names::normalize(new_name),
+            &r.hits,
+            json,
+        );
+        return 0;
+    }
     if json {
-        output::print_json(&load(conn, id).map(|m| module_to_json(&m)).unwrap());
+        let mut v = module_to_json(m);
+        if update_refs {
+            v["references"] = references::hits_to_json(&r.hits);
+        }
+        output::print_json(&v);
     } else {
+        let refs = if update_refs {
+            references::summary(&r.hits)
+        } else {
+            String::new()
+        };
         output::print_plain(&format!(
-            "renamed module {}: {} → {}",
-            id, old.name, new_name
+            "renamed module {}: {} → {}{}",
+            id, r.old_name, m.name, refs
         ));
     }
     0
 }
 
+/// Replace the module's description.
+pub fn update_description(conn: &Connection, id: i64, desc: &str) -> Result<Module, Error> {
+    let mut m = get(conn, id)?;
+    if m.locked {
+        return Err(Error::Locked(locked_message(&m, "change description")));
+    }
+    let ts = timestamp::now();
+    conn.execute(
+        "UPDATE modules SET description=?1, last_worked_on=?2 WHERE id=?3",
+        params![desc, ts, id],
+    )?;
+    m.description = desc.to_string();
+    m.last_worked_on = ts;
+    Ok(m)
+}
+
 pub fn set_description(conn: &Connection, id: i64, desc: &str, json: bool) -> i32 {
-    let m = match load(conn, id) {
+    let m = match update_description(conn, id, desc) {
         Ok(m) => m,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+        Err(e) => return output::fail(&e),
     };
-    conn.execute(
-        "UPDATE modules SET description=?1, last_worked_on=?2 WHERE id=?3",
-        params![desc, now(), id],
-    )
-    .unwrap();
     if json {
-        output::print_json(&load(conn, id).map(|m| module_to_json(&m)).unwrap());
+        output::print_json(&module_to_json(&m));
     } else {
         output::print_plain(&format!("updated description for module: {}", m.name));
     }
     0
 }
 
-pub fn set_details(conn: &Connection, id: i64, details: &str, json: bool) -> i32 {
-    let m = match load(conn, id) {
+/// Set the module's path, relative to its project's root; empty clears it.
+pub fn update_path(conn: &Connection, id: i64, path: &str) -> Result<Module, Error> {
+    let mut m = get(conn, id)?;
+    let path = normalize_path(path).map_err(Error::Invalid)?;
+    let ts = timestamp::now();
+    conn.execute(
+        "UPDATE modules SET path=?1, last_worked_on=?2 WHERE id=?3",
+        params![path, ts, id],
+    )?;
+    m.path = path;
+    m.last_worked_on = ts;
+    Ok(m)
+}
+
+pub fn set_path(conn: &Connection, id: i64, path: &str, json: bool) -> i32 {
+    let m = match update_path(conn, id, path) {
         Ok(m) => m,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+        Err(e) => return output::fail(&e),
     };
-    conn.execute(
-        "UPDATE modules SET details=?1, last_worked_on=?2 WHERE id=?3",
-        params![details, now(), id],
-    )
-    .unwrap();
+    warn_if_missing(conn, m.project_id, &m.path);
     if json {
-        output::print_json(&load(conn, id).map(|m| module_to_json(&m)).unwrap());
+        output::print_json(&module_to_json(&m));
+    } else if m.path.is_empty() {
+        output::print_plain(&format!("cleared path for module: {}", m.name));
     } else {
-        output::print_plain(&format!("updated details for module: {}", m.name));
+        output::print_plain(&format!("set path for module {}: {}", m.name, m.path));
     }
     0
 }
 
-pub fn transition(conn: &Connection, id: i64, to_state: &State, json: bool) -> i32 {
-    let m = match load(conn, id) {
+/// Replace the details, or add `details` after or before them per `placement`.
+pub fn update_details(
+    conn: &Connection,
+    id: i64,
+    details: &str,
+    placement: Placement,
+) -> Result<Module, Error> {
+    let mut m = get(conn, id)?;
+    let details = placement.merge(&m.details, details);
+    let ts = timestamp::now();
+    conn.execute(
+        "UPDATE modules SET details=?1, last_worked_on=?2 WHERE id=?3",
+        params![details, ts, id],
+    )?;
+    m.details = details;
+    m.last_worked_on = ts;
+    Ok(m)
+}
+
+pub fn set_details(
+    conn: &Connection,
+    id: i64,
+    details: &str,
+    placement: Placement,
+    json: bool,
+) -> i32 {
+    let m = match update_details(conn, id, details, placement) {
         Ok(m) => m,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+        Err(e) => return output::fail(&e),
     };
-    match validate_transition(&m.state, to_state) {
-        Err(e) => {
-            output::err(&format!("{} for module {}", e, m.name));
-            return 1;
-        }
-        Ok(false) => return 0, // no-op
-        Ok(true) => {}
+    if json {
+        output::print_json(&module_to_json(&m));
+    } else {
+        output::print_plain(&format!(
+            "{} details for module: {}",
+            placement.verb(),
+            m.name
+        ));
+    }
+    0
+}
+
+/// Move a module to another state. A `reason` is kept as the module's
+/// last_transition_reason and appended to its details with a timestamp.
+/// Moving to the state it is in changes nothing.
+pub fn transition_to(
+    conn: &Connection,
+    id: i64,
+    to_state: &State,
+    reason: Option<&str>,
+    force: bool,
+) -> Result<Transitioned<Module>, Error> {
+    let mut m = get(conn, id)?;
+    // Amending is the sanctioned way to reopen locked work; every other move is refused.
+    if m.locked && *to_state != State::Amending {
+        return Err(Error::Locked(locked_message(
+            &m,
+            &format!("transition to {}", to_state),
+        )));
     }
     let from = m.state.clone();
-    conn.execute(
-        "UPDATE modules SET state=?1, last_worked_on=?2 WHERE id=?3",
-        params![to_state.to_string(), now(), id],
-    )
-    .unwrap();
+    let moves = validate_transition(&from, to_state)
+        .map_err(|e| Error::InvalidTransition(format!("{} for module {}", e, m.name)))?;
+    if !moves {
+        return Ok(Transitioned::new(m, from));
+    }
+    let forced = if *to_state == State::Complete {
+        progress::guard_complete(conn, "module", id, &m.name, force)?
+    } else {
+        None
+    };
+    let ts = timestamp::now();
+    let reason = reason::given(reason);
+    let details = reason::append(&m.details, &from, to_state, reason, &ts);
+    let tx = db::write_tx(conn)?;
+    tx.execute(
+        "UPDATE modules SET state=?1, last_worked_on=?2, details=?3, last_transition_reason=?4 WHERE id=?5",
+        params![to_state.to_string(), ts, details, reason.unwrap_or(""), id],
+    )?;
+    history::record_state(&tx, "module", id, &from, &to_state.to_string(), reason, &ts)?;
+    tx.commit()?;
+    m.state = to_state.to_string();
+    m.last_worked_on = ts;
+    m.details = details;
+    let mut t = Transitioned::new(m, from);
+    t.moved = true;
+    t.reason = reason.map(str::to_string);
+    t.forced.extend(forced);
+    Ok(t)
+}
+
+pub fn transition(
+    conn: &Connection,
+    id: i64,
+    to_state: &State,
+    reason: Option<&str>,
+    force: bool,
+    json: bool,
+) -> i32 {
+    let t = match transition_to(conn, id, to_state, reason, force) {
+        Ok(t) => t,
+        Err(e) => return output::fail(&e),
+    };
+    if !t.moved {
+        return 0;
+    }
+    let m = &t.entity;
+    t.warn("module", &m.name, to_state);
     if json {
-        output::print_json(&load(conn, id).map(|m| module_to_json(&m)).unwrap());
+        let mut v = module_to_json(m);
+        v["reason"] = json!(t.reason);
+        output::print_json(&v);
     } else {
-        output::print_plain(&format!("module {}: {} → {}", m.name, from, to_state));
+        output::print_plain(&format!("module {}: {} → {}", m.name, t.from, to_state));
     }
     0
 }
 
-pub fn remove(conn: &Connection, id: i64, cascade: bool, json: bool) -> i32 {
-    let m = match load(conn, id) {
-        Ok(m) => m,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
-    };
-    let count: i64 = conn
-        .query_row(
-            "SELECT COUNT(*) FROM features WHERE module_id=?1",
-            params![id],
-            |r| r.get(0),
-        )
-        .unwrap_or(0);
+/// Delete a module, and with `cascade` everything under it; `soft` puts it
+/// in the trash. With `relink_research`, research linked to it or its
+/// descendants is first linked to the parent project in the same transaction.
+pub fn delete(
+    conn: &Connection,
+    id: i64,
+    cascade: bool,
+    relink_research: bool,
+    soft: bool,
+) -> Result<Removed<Module>, Error> {
+    let m = get(conn, id)?;
+    if m.locked {
+        return Err(Error::Locked(locked_message(&m, "remove")));
+    }
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM features WHERE module_id=?1",
+        params![id],
+        |r| r.get(0),
+    )?;
+    let locked_features: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM features WHERE module_id=?1 AND locked=1",
+        params![id],
+        |r| r.get(0),
+    )?;
+    if locked_features > 0 {
+        return Err(Error::Locked(format!(
+            "module {} has {} locked feature(s); unlock them before removing",
+            m.name, locked_features
+        )));
+    }
     if count > 0 && !cascade {
-        output::err(&format!(
+        return Err(Error::InvalidState(format!(
             "module has {} feature(s); pass --cascade to remove them",
             count
-        ));
-        return 1;
+        )));
     }
-    conn.execute("DELETE FROM modules WHERE id=?1", params![id])
-        .unwrap();
+    let tx = db::write_tx(conn)?;
+    if soft {
+        trash::bury(&tx, "module", id)?;
+    }
+    let relinked = if relink_research {
+        Some(research::relink_to_parent(&tx, "module", id, m.project_id)?)
+    } else {
+        None
+    };
+    tx.execute("DELETE FROM modules WHERE id=?1", params![id])?;
+    tx.commit()?;
+    Ok(Removed {
+        entity: m,
+        trashed: soft,
+        relinked,
+        warnings: Vec::new(),
+    })
+}
+
+pub fn remove(
+    conn: &Connection,
+    id: i64,
+    cascade: bool,
+    relink_research: bool,
+    soft: bool,
+    json: bool,
+) -> i32 {
+    let removed = match delete(conn, id, cascade, relink_research, soft) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
+    };
+    let m = &removed.entity;
     if json {
-        output::print_json(&json!({"deleted": true, "id": id}));
+        let mut v = json!({"deleted": true, "id": id, "trashed": soft});
+        if let Some((created, existing)) = removed.relinked {
+            v["research_relinked"] = json!(created);
+            v["research_already_linked"] = json!(existing);
+            v["project_id"] = json!(m.project_id);
+        }
+        output::print_json(&v);
     } else {
         output::print_plain(&format!("removed module {}: {}", id, m.name));
+        if soft {
+            output::print_plain(&trash::buried_note("module", id));
+        }
+        if let Some((created, existing)) = removed.relinked {
+            output::print_plain(&format!(
+                "relinked {} research record(s) to the parent project ({} already linked)",
+                created, existing
+            ));
+        }
+    }
+    0
+}
+
+/// Lock or unlock the module.
+pub fn update_locked(conn: &Connection, id: i64, locked: bool) -> Result<Module, Error> {
+    let mut m = get(conn, id)?;
+    conn.execute(
+        "UPDATE modules SET locked=?1 WHERE id=?2",
+        params![locked as i64, id],
+    )?;
+    m.locked = locked;
+    Ok(m)
+}
+
+pub fn set_locked(conn: &Connection, id: i64, locked: bool, json: bool) -> i32 {
+    let m = match update_locked(conn, id, locked) {
+        Ok(m) => m,
+        Err(e) => return output::fail(&e),
+    };
+    if json {
+        output::print_json(&module_to_json(&m));
+    } else {
+        let verb = if locked { "locked" } else { "unlocked" };
+        output::print_plain(&format!("module {} {}", m.name, verb));
     }
     0
 }
+
+/// Returns the module's name if it is locked, for guarding changes to its children.
+pub fn locked_name(conn: &Connection, id: i64) -> Option<String> {
+    conn.query_row(
+        "SELECT name FROM modules WHERE id=?1 AND locked=1",
+        params![id],
+        |r| r.get(0),
+    )
+    .ok()
+}
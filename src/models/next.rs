@@ -0,0 +1,338 @@
+use crate::models::dependency;
+use crate::models::find;
+use crate::output;
+use crate::priority::{self, Priority};
+use crate::resolve;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+/// Which tier a candidate came from, best first; the SQL below returns the
+/// same numbers.
+const REASONS: [&str; 3] = ["in progress", "Planning, feature under way", "Draft"];
+
+/// What an `--about` term is worth in each column: a name says most about
+/// what a task is, details the least.
+const ABOUT_WEIGHTS: [(&str, i64); 3] = [("name", 3), ("description", 2), ("details", 1)];
+
+/// Tasks worth picking up under the scope, ranked: anything in Building or
+/// Amending (most recently worked on first), then Planning tasks whose
+/// feature is in Planning or Building (oldest first), then Draft tasks
+/// (oldest first), the more urgent first within each tier. Nothing under a
+/// Complete module or feature is offered. `{scope}` is a column of the joined
+/// rows compared with ?1; `{ready}`, when ?3 is set, keeps only tasks whose
+/// dependencies are all Complete; `{rank}` ranks the task's priority.
+const CANDIDATES_SQL: &str =
+    "SELECT t.id, t.name, t.state, t.description, t.details, t.feature_id, f.module_id,
+            p.name || ' > ' || m.name || ' > ' || f.name || ' > ' || t.name,
+            CASE
+                WHEN t.state IN ('Building', 'Amending') THEN 0
+                WHEN t.state = 'Planning' AND f.state IN ('Planning', 'Building') THEN 1
+                ELSE 2
+            END AS tier,
+            t.priority
+     FROM tasks t
+     JOIN features f ON f.id = t.feature_id
+     JOIN modules m ON m.id = f.module_id
+     JOIN projects p ON p.id = m.project_id
+     WHERE {scope} = ?1
+       AND f.state <> 'Complete' AND m.state <> 'Complete'
+       AND (t.state IN ('Building', 'Amending', 'Draft')
+            OR (t.state = 'Planning' AND f.state IN ('Planning', 'Building')))
+       AND (?3 = 0 OR ({ready}))
+     ORDER BY tier, {rank},
+              CASE WHEN t.state IN ('Building', 'Amending') THEN t.last_worked_on END DESC,
+              t.id
+     LIMIT ?2";
+
+/// Terms from `--about` that reorder candidates within their tier, best
+/// match first. Without `require_match` a candidate matching nothing is
+/// only moved back, never dropped.
+pub struct About {
+    terms: Vec<String>,
+    require_match: bool,
+}
+
+impl About {
+    pub fn new(text: &str, require_match: bool) -> Result<Self, String> {
+        let terms: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+        if terms.is_empty() {
+            return Err("--about needs at least one term".to_string());
+        }
+        Ok(About {
+            terms,
+            require_match,
+        })
+    }
+
+    /// Each term found in the candidate, with the columns holding it.
+    fn matches(&self, c: &Candidate) -> Vec<(String, Vec<&'static str>)> {
+        let columns = [
+            ("name", c.name.as_str()),
+            ("description", c.description.as_str()),
+            ("details", c.details.as_str()),
+        ];
+        self.terms
+            .iter()
+            .map(|t| (t.clone(), find::columns_containing(t, &columns)))
+            .filter(|(_, found)| !found.is_empty())
+            .collect()
+    }
+}
+
+/// How a candidate matched `--about`.
+struct Relevance {
+    score: i64,
+    matches: Vec<(String, Vec<&'static str>)>,
+}
+
+impl Relevance {
+    fn of(matches: Vec<(String, Vec<&'static str>)>) -> Self {
+        let score = matches
+            .iter()
+            .flat_map(|(_, columns)| columns)
+            .map(|c| {
+                ABOUT_WEIGHTS
+                    .iter()
+                    .find(|(column, _)| column == c)
+                    .map_or(0, |(_, w)| *w)
+            })
+            .sum();
+        Relevance { score, matches }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "score": self.score,
+            "matches": self.matches.iter()
+                .map(|(term, columns)| json!({"term": term, "in": columns}))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    fn summary(&self) -> String {
+        if self.matches.is_empty() {
+            return "no match".to_string();
+        }
+        self.matches
+            .iter()
+            .map(|(term, columns)| format!("{} ({})", term, columns.join(", ")))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+struct Candidate {
+    id: i64,
+    name: String,
+    state: String,
+    priority: String,
+    description: String,
+    details: String,
+    breadcrumb: String,
+    tier: usize,
+    feature_id: i64,
+    module_id: i64,
+    /// (research name, what it is linked to: task, feature, or module)
+    research: Vec<(String, &'static str)>,
+    /// Set when `--about` was given.
+    relevance: Option<Relevance>,
+}
+
+impl Candidate {
+    fn to_json(&self) -> Value {
+        let mut v = json!({
+            "id": self.id,
+            "ref": resolve::short_ref("task", self.id),
+            "name": self.name,
+            "state": self.state,
+            "priority": self.priority,
+            "breadcrumb": self.breadcrumb,
+            "reason": REASONS[self.tier],
+            "description": self.description,
+            "details": self.details,
+            "research": self.research.iter()
+                .map(|(name, via)| json!({"name": name, "linked_to": via}))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(r) = &self.relevance {
+            v["about"] = r.to_json();
+        }
+        v
+    }
+
+    fn print(&self) {
+        // Only a priority other than the default is worth the noise.
+        let priority = if self.priority == Priority::Medium.to_string() {
+            String::new()
+        } else {
+            format!(", {}", self.priority)
+        };
+        outln!(
+            "{} ({}) [{}{}] — {}",
+            self.breadcrumb,
+            resolve::short_ref("task", self.id),
+            self.state,
+            priority,
+            REASONS[self.tier]
+        );
+        if let Some(r) = &self.relevance {
+            outln!("{}", output::field("about", &r.summary()));
+        }
+        if !self.description.is_empty() {
+            outln!("{}", output::field("description", &self.description));
+        }
+        if !self.research.is_empty() {
+            let names: Vec<String> = self
+                .research
+                .iter()
+                .map(|(name, via)| format!("{} ({})", name, via))
+                .collect();
+            outln!("{}", output::field("research", &names.join(", ")));
+        }
+        if !self.details.is_empty() {
+            outln!("details:");
+            outln!("{}", output::indent_content(&self.details));
+        }
+    }
+}
+
+/// Research linked to the task, then to its feature, then to its module,
+/// each name once, at the closest level it is linked.
+fn research(
+    conn: &Connection,
+    task_id: i64,
+    feature_id: i64,
+    module_id: i64,
+) -> rusqlite::Result<Vec<(String, &'static str)>> {
+    let mut out: Vec<(String, &'static str)> = Vec::new();
+    for (via, table, column, id) in [
+        ("task", "research_tasks", "task_id", task_id),
+        ("feature", "research_features", "feature_id", feature_id),
+        ("module", "research_modules", "module_id", module_id),
+    ] {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT r.name FROM research r JOIN {} l ON l.research_id = r.id
+             WHERE l.{} = ?1 ORDER BY name_key(r.name), fold(r.name), r.name",
+            table, column
+        ))?;
+        for name in stmt.query_map(params![id], |r| r.get::<_, String>(0))? {
+            let name = name?;
+            if !out.iter().any(|(n, _)| *n == name) {
+                out.push((name, via));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Candidates in order, at most `limit` of them (all when negative), without
+/// their research.
+fn candidates(
+    conn: &Connection,
+    scope: (&str, i64),
+    limit: i64,
+    ready: bool,
+) -> rusqlite::Result<Vec<Candidate>> {
+    let sql = CANDIDATES_SQL
+        .replace("{scope}", scope.0)
+        .replace("{ready}", dependency::READY_SQL)
+        .replace("{rank}", &priority::rank_sql("t.priority"));
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params![scope.1, limit, ready], |r| {
+        Ok(Candidate {
+            id: r.get(0)?,
+            name: r.get(1)?,
+            state: r.get(2)?,
+            priority: r.get(9)?,
+            description: r.get(3)?,
+            details: r.get(4)?,
+            breadcrumb: r.get(7)?,
+            tier: r.get::<_, i64>(8)? as usize,
+            feature_id: r.get(5)?,
+            module_id: r.get(6)?,
+            research: Vec::new(),
+            relevance: None,
+        })
+    })?;
+    rows.collect()
+}
+
+/// The first `count` candidates, reordered by `about` when given, with
+/// their research.
+fn pick(
+    conn: &Connection,
+    scope: (&str, i64),
+    count: usize,
+    ready: bool,
+    about: Option<&About>,
+) -> rusqlite::Result<Vec<Candidate>> {
+    let mut list = match about {
+        None => candidates(conn, scope, count as i64, ready)?,
+        Some(about) => {
+            let mut all = candidates(conn, scope, -1, ready)?;
+            for c in &mut all {
+                c.relevance = Some(Relevance::of(about.matches(c)));
+            }
+            let score = |c: &Candidate| c.relevance.as_ref().map_or(0, |r| r.score);
+            if about.require_match {
+                all.retain(|c| score(c) > 0);
+            }
+            // Stable, so ties keep the tier's own order; the tier still comes first.
+            all.sort_by_key(|c| (c.tier, -score(c)));
+            all.truncate(count);
+            all
+        }
+    };
+    for c in &mut list {
+        c.research = research(conn, c.id, c.feature_id, c.module_id)?;
+    }
+    Ok(list)
+}
+
+/// Suggest the next `count` tasks to work on within a project, module, or
+/// feature (`scope` is `"project"`, `"module"`, or `"feature"` with its id),
+/// each with its breadcrumb, description, details, and linked research. With
+/// `ready`, tasks still waiting on a dependency are left out. With `about`,
+/// candidates matching its terms come first within each tier, and each
+/// says what it matched.
+pub fn next(
+    conn: &Connection,
+    scope: (&str, i64),
+    count: usize,
+    ready: bool,
+    about: Option<&About>,
+    json: bool,
+) -> i32 {
+    let column = match scope.0 {
+        "project" => "m.project_id",
+        "module" => "f.module_id",
+        _ => "t.feature_id",
+    };
+    let list = match pick(conn, (column, scope.1), count, ready, about) {
+        Ok(l) => l,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    if json {
+        output::print_json(&Value::Array(list.iter().map(Candidate::to_json).collect()));
+        return 0;
+    }
+    if list.is_empty() {
+        let matching = if about.is_some_and(|a| a.require_match) {
+            " matching --about"
+        } else {
+            ""
+        };
+        output::print_empty(&format!("no open tasks in this {}{}", scope.0, matching));
+        return 0;
+    }
+    for (i, c) in list.iter().enumerate() {
+        if i > 0 {
+            outln!();
+        }
+        c.print();
+    }
+    0
+}
@@ -0,0 +1,234 @@
+use crate::content;
+use crate::models::bootstrap::slugify;
+use crate::names;
+use crate::output;
+use crate::timestamp;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What importing one file did.
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    Created,
+    Updated,
+    Skipped,
+    Failed,
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Created => "created",
+            Action::Updated => "updated",
+            Action::Skipped => "skipped",
+            Action::Failed => "failed",
+        }
+    }
+}
+
+struct Outcome {
+    path: String,
+    name: String,
+    action: Action,
+    reason: Option<String>,
+}
+
+/// A note read from disk, ready to store.
+struct Note {
+    name: String,
+    description: String,
+    content: String,
+    researched_at: String,
+}
+
+fn is_markdown(p: &Path) -> bool {
+    p.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("md") || e.eq_ignore_ascii_case("markdown"))
+}
+
+/// Markdown files under `dir`, sorted so repeated runs visit them in the same
+/// order. Hidden files and directories are left alone.
+fn markdown_files(dir: &Path, recursive: bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(d) = dirs.pop() {
+        let Ok(rd) = fs::read_dir(&d) else { continue };
+        for e in rd.filter_map(|e| e.ok()) {
+            if e.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let p = e.path();
+            if p.is_dir() {
+                if recursive {
+                    dirs.push(p);
+                }
+            } else if is_markdown(&p) {
+                files.push(p);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// The first heading's text, else the first non-empty line.
+fn describe(body: &str) -> String {
+    let lines = || body.lines().map(str::trim).filter(|l| !l.is_empty());
+    lines()
+        .find(|l| l.starts_with('#'))
+        .map(|h| h.trim_start_matches('#').trim())
+        .or_else(|| lines().next())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn read_note(path: &Path, name: String) -> Result<Note, String> {
+    if name.is_empty() {
+        return Err("file name has no usable slug".to_string());
+    }
+    let body = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let researched_at = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(|t| timestamp::canonical(&t.into()))
+        .unwrap_or_else(|_| timestamp::now());
+    Ok(Note {
+        name,
+        description: describe(&body),
+        content: body.trim_end().to_string(),
+        researched_at,
+    })
+}
+
+/// Store one note: create it, or with `update` refresh an existing record of
+/// the same name when the file differs from what is stored.
+fn import_note(
+    conn: &Connection,
+    note: &Note,
+    source: &str,
+    update: bool,
+) -> rusqlite::Result<Action> {
+    let existing: Option<(i64, String, String)> = conn
+        .query_row(
+            "SELECT id, description, content_body(content) FROM research WHERE fold(name)=?1",
+            params![names::fold(&note.name)],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        )
+        .optional()?;
+    match existing {
+        None => {
+            conn.execute(
+                "INSERT INTO research (name, description, source, researched_at, created_at, updated_at)
+                 VALUES (?1,?2,?3,?4,?5,?5)",
+                params![note.name, note.description, source, note.researched_at, timestamp::now()],
+            )?;
+            content::save(conn, conn.last_insert_rowid(), &note.content)?;
+            Ok(Action::Created)
+        }
+        Some(_) if !update => Ok(Action::Skipped),
+        Some((_, description, content))
+            if description == note.description && content == note.content =>
+        {
+            Ok(Action::Skipped)
+        }
+        Some((id, _, _)) => {
+            conn.execute(
+                "UPDATE research SET description=?1, source=?2, researched_at=?3, updated_at=?4
+                 WHERE id=?5",
+                params![
+                    note.description,
+                    source,
+                    note.researched_at,
+                    timestamp::now(),
+                    id
+                ],
+            )?;
+            content::save(conn, id, &note.content)?;
+            Ok(Action::Updated)
+        }
+    }
+}
+
+/// Import every Markdown file in a directory as a research record: name from
+/// the file name, description from the first heading (or first line), content
+/// from the body, researched_at from the file's modification time, and the
+/// file's path as the source. Existing names are skipped unless `update`, and
+/// unchanged files are skipped even then, so a re-run only does what is left.
+/// Each file is written on its own; one bad file is reported and the rest go on.
+pub fn import_dir(conn: &Connection, dir: &str, recursive: bool, update: bool, json: bool) -> i32 {
+    let root = match fs::canonicalize(dir) {
+        Ok(p) if p.is_dir() => p,
+        _ => {
+            output::err(&format!("not a directory: {}", dir));
+            return 1;
+        }
+    };
+    let mut outcomes: Vec<Outcome> = Vec::new();
+    for path in markdown_files(&root, recursive) {
+        let shown = path.display().to_string();
+        let name = path
+            .file_stem()
+            .map(|s| slugify(&s.to_string_lossy()))
+            .unwrap_or_default();
+        let result = read_note(&path, name.clone())
+            .and_then(|note| import_note(conn, &note, &shown, update).map_err(|e| e.to_string()));
+        let (action, reason) = match result {
+            Ok(Action::Skipped) if update => (Action::Skipped, Some("unchanged".to_string())),
+            Ok(Action::Skipped) => (Action::Skipped, Some("already exists".to_string())),
+            Ok(a) => (a, None),
+            Err(e) => (Action::Failed, Some(e)),
+        };
+        outcomes.push(Outcome {
+            path: shown,
+            name,
+            action,
+            reason,
+        });
+    }
+
+    let count = |a: Action| outcomes.iter().filter(|o| o.action == a).count();
+    let failed = count(Action::Failed);
+    if json {
+        output::print_json(&json!({
+            "created": count(Action::Created),
+            "updated": count(Action::Updated),
+            "skipped": count(Action::Skipped),
+            "failed": failed,
+            "files": outcomes.iter().map(|o| json!({
+                "path": o.path,
+                "name": o.name,
+                "action": o.action.as_str(),
+                "reason": o.reason,
+            })).collect::<Vec<Value>>(),
+        }));
+    } else {
+        for o in &outcomes {
+            let reason = o
+                .reason
+                .as_ref()
+                .map(|r| format!("  ({})", r))
+                .unwrap_or_default();
+            outln!(
+                "{:<8} {:<24} {}{}",
+                o.action.as_str(),
+                o.name,
+                o.path,
+                reason
+            );
+        }
+        output::print_plain(&format!(
+            "{} created, {} updated, {} skipped, {} failed",
+            count(Action::Created),
+            count(Action::Updated),
+            count(Action::Skipped),
+            failed
+        ));
+    }
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
@@ -0,0 +1,41 @@
+/// Where new details or content go relative to what is already stored.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Placement {
+    Replace,
+    Append,
+    Prepend,
+}
+
+impl Placement {
+    /// From the --append and --prepend flags, which clap keeps exclusive.
+    pub fn from_flags(append: bool, prepend: bool) -> Placement {
+        if append {
+            Placement::Append
+        } else if prepend {
+            Placement::Prepend
+        } else {
+            Placement::Replace
+        }
+    }
+
+    /// The text to store: `new` after or before `existing` with a blank line
+    /// between them, or just `new` when replacing or when nothing is stored yet.
+    pub fn merge(self, existing: &str, new: &str) -> String {
+        if self == Placement::Replace || existing.trim().is_empty() {
+            return new.to_string();
+        }
+        match self {
+            Placement::Prepend => format!("{}\n\n{}", new.trim_end(), existing),
+            _ => format!("{}\n\n{}", existing.trim_end(), new),
+        }
+    }
+
+    /// `updated`, `appended to`, or `prepended to`, for the confirmation line.
+    pub fn verb(self) -> &'static str {
+        match self {
+            Placement::Replace => "updated",
+            Placement::Append => "appended to",
+            Placement::Prepend => "prepended to",
+        }
+    }
+}
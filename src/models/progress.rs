@@ -0,0 +1,146 @@
+use crate::error::Error;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+
+/// How far a parent has got once one of its children is complete: how many of
+/// its children are Complete and which are not, by name in id order.
+pub struct Progress {
+    pub kind: &'static str,
+    pub id: i64,
+    pub name: String,
+    pub children: &'static str,
+    pub complete: usize,
+    pub total: usize,
+    pub remaining: Vec<String>,
+}
+
+/// Progress of the feature owning a task (`kind` "task") or the module owning
+/// a feature (`kind` "feature"). None for other types or a missing entity.
+pub fn of_parent(conn: &Connection, kind: &str, id: i64) -> rusqlite::Result<Option<Progress>> {
+    let (parent_kind, children, parent_sql, children_sql) = match kind {
+        "task" => (
+            "feature",
+            "tasks",
+            "SELECT f.id, f.name FROM tasks t JOIN features f ON f.id = t.feature_id WHERE t.id=?1",
+            "SELECT name, state FROM tasks WHERE feature_id=?1 ORDER BY position, id",
+        ),
+        "feature" => (
+            "module",
+            "features",
+            "SELECT m.id, m.name FROM features f JOIN modules m ON m.id = f.module_id WHERE f.id=?1",
+            "SELECT name, state FROM features WHERE module_id=?1 ORDER BY id",
+        ),
+        _ => return Ok(None),
+    };
+    let Some((parent_id, parent_name)): Option<(i64, String)> = conn
+        .query_row(parent_sql, params![id], |r| Ok((r.get(0)?, r.get(1)?)))
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    let mut stmt = conn.prepare(children_sql)?;
+    let rows: Vec<(String, String)> = stmt
+        .query_map(params![parent_id], |r| Ok((r.get(0)?, r.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let remaining: Vec<String> = rows
+        .iter()
+        .filter(|(_, state)| state != "Complete")
+        .map(|(name, _)| name.clone())
+        .collect();
+    Ok(Some(Progress {
+        kind: parent_kind,
+        id: parent_id,
+        name: parent_name,
+        children,
+        complete: rows.len() - remaining.len(),
+        total: rows.len(),
+        remaining,
+    }))
+}
+
+impl Progress {
+    /// e.g. `feature login: 5/6 tasks complete; remaining: add-metrics`
+    pub fn summary(&self) -> String {
+        let mut s = format!(
+            "{} {}: {}/{} {} complete",
+            self.kind, self.name, self.complete, self.total, self.children
+        );
+        if !self.remaining.is_empty() {
+            s.push_str(&format!("; remaining: {}", self.remaining.join(", ")));
+        }
+        s
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "type": self.kind,
+            "id": self.id,
+            "name": self.name,
+            "complete": self.complete,
+            "total": self.total,
+            "remaining": self.remaining,
+        })
+    }
+}
+
+/// Children of a project (modules), module (features), or feature (tasks)
+/// that are not Complete, as (name, state) in id order (tasks in work order).
+pub fn outstanding(
+    conn: &Connection,
+    kind: &str,
+    id: i64,
+) -> rusqlite::Result<Vec<(String, String)>> {
+    let sql = match kind {
+        "project" => "SELECT name, state FROM modules WHERE project_id=?1 AND state != 'Complete' ORDER BY id",
+        "module" => "SELECT name, state FROM features WHERE module_id=?1 AND state != 'Complete' ORDER BY id",
+        "feature" => "SELECT name, state FROM tasks WHERE feature_id=?1 AND state != 'Complete' ORDER BY position, id",
+        _ => return Ok(Vec::new()),
+    };
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?)))?;
+    rows.collect()
+}
+
+/// Refuse completing an entity whose children are not all Complete, unless
+/// `force`: the error names each one with its state. A forced completion
+/// returns the warning to give instead.
+pub fn guard_complete(
+    conn: &Connection,
+    kind: &str,
+    id: i64,
+    name: &str,
+    force: bool,
+) -> Result<Option<String>, Error> {
+    let left = outstanding(conn, kind, id)?;
+    if left.is_empty() {
+        return Ok(None);
+    }
+    let children = match kind {
+        "project" => "module(s)",
+        "module" => "feature(s)",
+        _ => "task(s)",
+    };
+    let list = left
+        .iter()
+        .map(|(n, s)| format!("{} [{}]", n, s))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if force {
+        return Ok(Some(format!(
+            "completing {} {} with {} incomplete {}: {}",
+            kind,
+            name,
+            left.len(),
+            children,
+            list
+        )));
+    }
+    Err(Error::InvalidState(format!(
+        "{} {} has {} incomplete {}: {}; complete them first or pass --force",
+        kind,
+        name,
+        left.len(),
+        children,
+        list
+    )))
+}
@@ -1,7 +1,22 @@
+use crate::db;
+use crate::error::Error;
+use crate::fields::{self, Page, Sort};
+use crate::models::history;
+use crate::models::progress;
+use crate::models::references::{self, Renamed};
+use crate::models::transition::Transitioned;
+use crate::models::trash::{self, Removed};
+use crate::names;
 use crate::output;
+use crate::resolve;
+use crate::timestamp;
 use rusqlite::{params, Connection};
+use serde::Serialize;
 use serde_json::{json, Value};
 
+/// Serializes to the record `project show --json` starts from.
+#[derive(Debug, Clone, Serialize)]
+#[serde(into = "Value")]
 pub struct Project {
     pub id: i64,
     pub name: String,
@@ -9,94 +24,211 @@ pub struct Project {
     pub description: String,
     pub completed: bool,
     pub updated_at: String,
+    /// Hidden from `project list` and read-only until unarchived.
+    pub archived: bool,
+    /// Completion as the modules have it: every one Complete, or, for a
+    /// project without modules, the completed flag.
+    pub effective: bool,
 }
 
-fn now() -> String {
-    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+impl Project {
+    /// Whether the completed flag disagrees with the module states.
+    pub fn state_mismatch(&self) -> bool {
+        self.completed != self.effective
+    }
+
+    fn from_row(r: &rusqlite::Row) -> rusqlite::Result<Project> {
+        Ok(Project {
+            id: r.get(0)?,
+            name: r.get(1)?,
+            path: r.get(2)?,
+            description: r.get(3)?,
+            completed: r.get::<_, i64>(4)? != 0,
+            updated_at: r.get(5)?,
+            effective: r.get::<_, i64>(6)? != 0,
+            archived: r.get::<_, i64>(7)? != 0,
+        })
+    }
 }
 
-fn load(conn: &Connection, id: i64) -> Result<Project, String> {
+/// Projects with their effective completion, from module counts grouped per
+/// project; filter with a WHERE on `p` and `effective`.
+const PROJECTS_SQL: &str = "SELECT p.id, p.name, p.path, p.description, p.completed, p.updated_at,
+            CASE WHEN ms.total IS NULL THEN p.completed ELSE ms.total = ms.complete END AS effective,
+            p.archived
+     FROM projects p
+     LEFT JOIN (SELECT project_id, COUNT(*) AS total, SUM(state = 'Complete') AS complete
+                FROM modules GROUP BY project_id) ms ON ms.project_id = p.id";
+
+/// The project with this id.
+pub fn get(conn: &Connection, id: i64) -> Result<Project, Error> {
     conn.query_row(
-        "SELECT id, name, path, description, completed, updated_at FROM projects WHERE id=?1",
+        &format!("{} WHERE p.id=?1", PROJECTS_SQL),
         params![id],
-        |r| {
-            Ok(Project {
-                id: r.get(0)?,
-                name: r.get(1)?,
-                path: r.get(2)?,
-                description: r.get(3)?,
-                completed: r.get::<_, i64>(4)? != 0,
-                updated_at: r.get(5)?,
-            })
-        },
+        Project::from_row,
     )
-    .map_err(|_| format!("project not found: {}", id))
+    .map_err(|e| Error::missing(e, "project", id))
 }
 
-pub fn add(conn: &Connection, name: &str, path: &str, description: &str, json: bool) -> i32 {
-    let name = name.trim();
+/// Add a project. The name is stored in normal form; an empty one, or one
+/// another project has in any case, is refused.
+pub fn create(
+    conn: &Connection,
+    name: &str,
+    path: &str,
+    description: &str,
+) -> Result<Project, Error> {
+    let name = names::normalize(name);
     if name.is_empty() {
-        output::err("name must not be empty");
-        return 1;
+        return Err(Error::Invalid("name must not be empty".into()));
+    }
+    if let Some(existing) = names::clash(conn, "projects", None, &name, None)? {
+        return Err(Error::Conflict(names::taken("project", &existing, None)));
     }
-    let ts = now();
+    let ts = timestamp::now();
     match conn.execute(
         "INSERT INTO projects (name, path, description, updated_at) VALUES (?1,?2,?3,?4)",
         params![name, path, description, ts],
     ) {
-        Ok(_) => {
-            let id = conn.last_insert_rowid();
-            if json {
-                let p = load(conn, id).unwrap();
-                output::print_json(&project_to_json(&p));
-            } else {
-                output::print_plain(&format!("added project {}: {}", id, name));
-            }
-            0
-        }
-        Err(e) => {
-            output::err(&e.to_string());
-            2
-        }
+        Ok(_) => Ok(Project {
+            id: conn.last_insert_rowid(),
+            name,
+            path: path.to_string(),
+            description: description.to_string(),
+            completed: false,
+            updated_at: ts,
+            effective: false,
+            archived: false,
+        }),
+        Err(e) if db::name_taken(&e) => Err(Error::Conflict(names::taken("project", &name, None))),
+        Err(e) => Err(e.into()),
     }
 }
 
-pub fn list(conn: &Connection, filter: Option<bool>, json: bool) -> i32 {
-    let sql = match filter {
-        Some(true)  => "SELECT id, name, path, description, completed, updated_at FROM projects WHERE completed=1 ORDER BY id",
-        Some(false) => "SELECT id, name, path, description, completed, updated_at FROM projects WHERE completed=0 ORDER BY id",
-        None        => "SELECT id, name, path, description, completed, updated_at FROM projects ORDER BY id",
+pub fn add(conn: &Connection, name: &str, path: &str, description: &str, json: bool) -> i32 {
+    let p = match create(conn, name, path, description) {
+        Ok(p) => p,
+        Err(e) => return output::fail(&e),
     };
-    let mut stmt = match conn.prepare(sql) {
-        Ok(s) => s,
-        Err(e) => {
-            output::err(&e.to_string());
-            return 2;
-        }
+    if json {
+        output::print_json(&project_to_json(&p));
+    } else {
+        output::print_plain(&format!("added project {}: {}", p.id, p.name));
+    }
+    0
+}
+
+/// Which projects `project list` shows by their archived flag.
+#[derive(Clone, Copy)]
+pub enum Archived {
+    /// Only those not archived, the default.
+    Hide,
+    /// Only archived ones.
+    Only,
+    /// Both.
+    Include,
+}
+
+/// The query behind `all`, ordered but not paged; `?1` is the filter.
+fn all_sql(effective: bool, archived: Archived, sort: Option<Sort>) -> String {
+    let column = if effective {
+        "effective"
+    } else {
+        "p.completed"
+    };
+    let archived = match archived {
+        Archived::Hide => " AND p.archived = 0",
+        Archived::Only => " AND p.archived = 1",
+        Archived::Include => "",
+    };
+    let order = match sort {
+        Some(sort) => sort.order_by(&sort_sql(sort.field), "p.id"),
+        None => "p.id".to_string(),
+    };
+    format!(
+        "{} WHERE (?1 IS NULL OR {} = ?1){} ORDER BY {}",
+        PROJECTS_SQL, column, archived, order
+    )
+}
+
+/// Every project, or only completed (`Some(true)`) or incomplete ones, judged
+/// by the completed flag or, with `effective`, by the module states, and by
+/// `archived`; in id order, or by `sort`, and only `page` of them.
+pub fn all(
+    conn: &Connection,
+    filter: Option<bool>,
+    effective: bool,
+    archived: Archived,
+    sort: Option<Sort>,
+    page: Page,
+) -> Result<Vec<Project>, Error> {
+    let sql = format!("{}{}", all_sql(effective, archived, sort), page.sql());
+    let mut stmt = conn.prepare(&sql)?;
+    let projects = stmt
+        .query_map(params![filter], Project::from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(projects)
+}
+
+/// How many projects `all` returns without a page.
+pub fn count(
+    conn: &Connection,
+    filter: Option<bool>,
+    effective: bool,
+    archived: Archived,
+) -> Result<i64, Error> {
+    let sql = all_sql(effective, archived, None);
+    Ok(fields::count(conn, &sql, params![filter])?)
+}
+
+/// The SQL behind each of `COLUMNS`, for `--sort`.
+fn sort_sql(field: &str) -> String {
+    if field == "name" {
+        return names::order_sql("p.name");
+    }
+    match field {
+        "archived" => "p.archived",
+        "completed" => "p.completed",
+        "description" => "p.description",
+        "effectively_completed" => "effective",
+        "path" => "p.path",
+        "state_mismatch" => "p.completed != effective",
+        "updated_at" => "p.updated_at",
+        _ => "p.id",
+    }
+    .to_string()
+}
+
+/// `all`, printed, with only `fields` when given and, when paged, the total.
+#[allow(clippy::too_many_arguments)]
+pub fn list(
+    conn: &Connection,
+    filter: Option<bool>,
+    effective: bool,
+    archived: Archived,
+    fields: Option<&[&str]>,
+    sort: Option<Sort>,
+    page: Page,
+    json: bool,
+) -> i32 {
+    let listed = all(conn, filter, effective, archived, sort, page).and_then(|p| {
+        let total = fields::wants_total(page)
+            .then(|| count(conn, filter, effective, archived))
+            .transpose()?;
+        Ok((p, total))
+    });
+    let (projects, total) = match listed {
+        Ok(l) => l,
+        Err(e) => return output::fail(&e),
     };
-    let projects: Vec<Project> = stmt
-        .query_map([], |r| {
-            Ok(Project {
-                id: r.get(0)?,
-                name: r.get(1)?,
-                path: r.get(2)?,
-                description: r.get(3)?,
-                completed: r.get::<_, i64>(4)? != 0,
-                updated_at: r.get(5)?,
-            })
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect();
 
-    if projects.is_empty() {
-        output::print_plain("no projects found");
+    if projects.is_empty() && fields::empty_in_prose(json, total) {
+        output::print_empty("no projects found");
         return 0;
     }
-    if json {
-        output::print_json(&Value::Array(
-            projects.iter().map(project_to_json).collect(),
-        ));
+    if json || fields.is_some() {
+        let rows = projects.iter().map(project_to_json).collect();
+        fields::print_list(COLUMNS, fields, rows, total, json);
     } else {
         for p in &projects {
             let status = if p.completed {
@@ -104,186 +236,348 @@ pub fn list(conn: &Connection, filter: Option<bool>, json: bool) -> i32 {
             } else {
                 "incomplete"
             };
-            println!("{:<4} {:<20} {:<40} {}", p.id, p.name, p.path, status);
+            outln!(
+                "{:<5} {:<20} {:<40} {}{}{}",
+                resolve::short_ref("project", p.id),
+                p.name,
+                p.path,
+                status,
+                if p.archived { "  [archived]" } else { "" },
+                if p.state_mismatch() {
+                    format!("  [state mismatch: {}]", mismatch_note(p))
+                } else {
+                    String::new()
+                }
+            );
         }
     }
+    if !json {
+        fields::print_more(page, projects.len(), total);
+    }
     0
 }
 
-pub fn show(conn: &Connection, id: i64, json: bool) -> i32 {
-    let p = match load(conn, id) {
+pub fn show(conn: &Connection, id: i64, with_history: bool, json: bool) -> i32 {
+    let p = match get(conn, id) {
         Ok(p) => p,
-        Err(e) => {
-            output::err(&e);
-            return 1;
+        Err(e) => return output::fail(&e),
+    };
+    let changes = if with_history {
+        match history::changes(conn, "project", id) {
+            Ok(c) => Some(c),
+            Err(e) => return output::fail(&e.into()),
         }
+    } else {
+        None
     };
 
-    // Load modules
-    let mut mstmt = conn
-        .prepare("SELECT id, name, state FROM modules WHERE project_id=?1 ORDER BY id")
-        .unwrap();
-    let modules: Vec<(i64, String, String)> = mstmt
-        .query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect();
-
-    // Load linked research
-    let mut rstmt = conn
-        .prepare(
+    // Load modules and linked research
+    let loaded = db::triples(
+        conn,
+        "SELECT id, name, state FROM modules WHERE project_id=?1 ORDER BY id",
+        id,
+    )
+    .and_then(|modules| {
+        let research = db::triples(
+            conn,
             "SELECT r.id, r.name, r.description FROM research r
-         JOIN research_projects rp ON rp.research_id=r.id
-         WHERE rp.project_id=?1 ORDER BY r.id",
-        )
-        .unwrap();
-    let research: Vec<(i64, String, String)> = rstmt
-        .query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect();
+             JOIN research_projects rp ON rp.research_id=r.id
+             WHERE rp.project_id=?1 ORDER BY r.id",
+            id,
+        )?;
+        Ok((modules, research))
+    });
+    let (modules, research) = match loaded {
+        Ok(l) => l,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
 
     if json {
         let mut v = project_to_json(&p);
         v["modules"] = Value::Array(
             modules
                 .iter()
-                .map(|(id, name, state)| json!({"id": id, "name": name, "state": state}))
+                .map(|(id, name, state)| {
+                    json!({
+                        "id": id, "ref": resolve::short_ref("module", *id),
+                        "name": name, "state": state,
+                    })
+                })
                 .collect(),
         );
         v["research"] = Value::Array(
             research
                 .iter()
-                .map(|(id, name, desc)| json!({"id": id, "name": name, "description": desc}))
+                .map(|(id, name, desc)| {
+                    json!({
+                        "id": id, "ref": resolve::short_ref("research", *id),
+                        "name": name, "description": desc,
+                    })
+                })
                 .collect(),
         );
+        if let Some(changes) = &changes {
+            v["history"] = history::timeline_json(changes);
+        }
         output::print_json(&v);
     } else {
-        println!("{}", output::field("id", &p.id.to_string()));
-        println!("{}", output::field("name", &p.name));
-        println!("{}", output::field("path", &p.path));
-        println!("{}", output::field("description", &p.description));
-        println!(
+        outln!("{}", output::field("id", &p.id.to_string()));
+        outln!(
+            "{}",
+            output::field("ref", &resolve::short_ref("project", p.id))
+        );
+        outln!("{}", output::field("name", &p.name));
+        if p.archived {
+            outln!("{}", output::field("archived", "yes"));
+        }
+        outln!("{}", output::field("path", &p.path));
+        outln!("{}", output::field("description", &p.description));
+        outln!(
             "{}",
             output::field("completed", if p.completed { "true" } else { "false" })
         );
-        println!("{}", output::field("updated_at", &p.updated_at));
+        if p.state_mismatch() {
+            outln!("{}", output::field("state_mismatch", &mismatch_note(&p)));
+        }
+        outln!("{}", output::field("updated_at", &p.updated_at));
         if !modules.is_empty() {
-            println!();
-            println!("modules:");
+            outln!();
+            outln!("modules:");
             for (mid, mname, mstate) in &modules {
-                println!("  {:<4} {:<20} {}", mid, mname, mstate);
+                outln!(
+                    "  {:<5} {:<20} {}",
+                    resolve::short_ref("module", *mid),
+                    mname,
+                    mstate
+                );
             }
         }
         if !research.is_empty() {
-            println!();
-            println!("research:");
+            outln!();
+            outln!("research:");
             for (rid, rname, rdesc) in &research {
-                println!("  {:<4} {:<20} {}", rid, rname, rdesc);
+                outln!(
+                    "  {:<5} {:<20} {}",
+                    resolve::short_ref("research", *rid),
+                    rname,
+                    rdesc
+                );
             }
         }
+        if let Some(changes) = &changes {
+            outln!();
+            outln!("history:");
+            history::print_timeline(changes);
+        }
     }
     0
 }
 
-pub fn rename(conn: &Connection, id: i64, new_name: &str, json: bool) -> i32 {
-    let new_name = new_name.trim();
+/// Rename a project, and with `update_refs` rewrite whole-word mentions of
+/// the old name across it. A dry run finds the references and writes
+/// nothing.
+pub fn rename_to(
+    conn: &Connection,
+    id: i64,
+    new_name: &str,
+    update_refs: bool,
+    dry_run: bool,
+) -> Result<Renamed<Project>, Error> {
+    let new_name = names::normalize(new_name);
     if new_name.is_empty() {
-        output::err("name must not be empty");
-        return 1;
+        return Err(Error::Invalid("name must not be empty".into()));
     }
-    let old = match load(conn, id) {
-        Ok(p) => p,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
-    };
-    match conn.execute(
-        "UPDATE projects SET name=?1, updated_at=?2 WHERE id=?3",
-        params![new_name, now(), id],
+    let mut p = get(conn, id)?;
+    if let Some(existing) = names::clash(conn, "projects", None, &new_name, Some(id))? {
+        return Err(Error::Conflict(names::taken("project", &existing, None)));
+    }
+    let (hits, touched) = match references::rename(
+        conn,
+        "project",
+        id,
+        &p.name,
+        &new_name,
+        update_refs,
+        dry_run,
     ) {
-        Ok(_) => {
-            if json {
-                output::print_json(&load(conn, id).map(|p| project_to_json(&p)).unwrap());
-            } else {
-                output::print_plain(&format!(
-                    "renamed project {}: {} → {}",
-                    id, old.name, new_name
-                ));
-            }
-            0
-        }
-        Err(e) => {
-            output::err(&e.to_string());
-            2
+        Ok(renamed) => renamed,
+        Err(e) if db::name_taken(&e) => {
+            return Err(Error::Conflict(names::taken("project", &new_name, None)))
         }
+        Err(e) => return Err(e.into()),
+    };
+    let old_name = p.name.clone();
+    if let Some(ts) = touched {
+        p.name = new_name;
+        p.updated_at = ts;
     }
+    Ok(Renamed {
+        entity: p,
+        old_name,
+        hits,
+    })
 }
 
-pub fn set_description(conn: &Connection, id: i64, desc: &str, json: bool) -> i32 {
-    match load(conn, id) {
-        Ok(p) => p,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+pub fn rename(
+    conn: &Connection,
+    id: i64,
+    new_name: &str,
+    update_refs: bool,
+    dry_run: bool,
+    json: bool,
+) -> i32 {
+    let r = match rename_to(conn, id, new_name, update_refs, dry_run) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
     };
-    conn.execute(
-        "UPDATE projects SET description=?1, updated_at=?2 WHERE id=?3",
-        params![desc, now(), id],
-    )
-    .unwrap();
+    let p = &r.entity;
+    if dry_run {
+        references::print_preview(
+            "project",
+            id,
+            &r.old_name,
+            &names::normalize(new_name),
+            &r.hits,
+            json,
+        );
+        return 0;
+    }
     if json {
-        output::print_json(&load(conn, id).map(|p| project_to_json(&p)).unwrap());
+        let mut v = project_to_json(p);
+        if update_refs {
+            v["references"] = references::hits_to_json(&r.hits);
+        }
+        output::print_json(&v);
     } else {
+        let refs = if update_refs {
+            references::summary(&r.hits)
+        } else {
+            String::new()
+        };
         output::print_plain(&format!(
-            "updated description for project: {}",
-            load(conn, id).unwrap().name
+            "renamed project {}: {} → {}{}",
+            id, r.old_name, p.name, refs
         ));
     }
     0
 }
 
-pub fn set_path(conn: &Connection, id: i64, path: &str, json: bool) -> i32 {
-    match load(conn, id) {
+/// Replace the project's description.
+pub fn update_description(conn: &Connection, id: i64, desc: &str) -> Result<Project, Error> {
+    let mut p = get(conn, id)?;
+    let ts = timestamp::now();
+    conn.execute(
+        "UPDATE projects SET description=?1, updated_at=?2 WHERE id=?3",
+        params![desc, ts, id],
+    )?;
+    p.description = desc.to_string();
+    p.updated_at = ts;
+    Ok(p)
+}
+
+pub fn set_description(conn: &Connection, id: i64, desc: &str, json: bool) -> i32 {
+    let p = match update_description(conn, id, desc) {
         Ok(p) => p,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+        Err(e) => return output::fail(&e),
     };
+    if json {
+        output::print_json(&project_to_json(&p));
+    } else {
+        output::print_plain(&format!("updated description for project: {}", p.name));
+    }
+    0
+}
+
+/// Set the project's root path.
+pub fn update_path(conn: &Connection, id: i64, path: &str) -> Result<Project, Error> {
+    let mut p = get(conn, id)?;
+    let ts = timestamp::now();
     conn.execute(
         "UPDATE projects SET path=?1, updated_at=?2 WHERE id=?3",
-        params![path, now(), id],
-    )
-    .unwrap();
+        params![path, ts, id],
+    )?;
+    p.path = path.to_string();
+    p.updated_at = ts;
+    Ok(p)
+}
+
+pub fn set_path(conn: &Connection, id: i64, path: &str, json: bool) -> i32 {
+    let p = match update_path(conn, id, path) {
+        Ok(p) => p,
+        Err(e) => return output::fail(&e),
+    };
     if json {
-        output::print_json(&load(conn, id).map(|p| project_to_json(&p)).unwrap());
+        output::print_json(&project_to_json(&p));
     } else {
-        output::print_plain(&format!(
-            "updated path for project: {}",
-            load(conn, id).unwrap().name
-        ));
+        output::print_plain(&format!("updated path for project: {}", p.name));
     }
     0
 }
 
-pub fn set_completed(conn: &Connection, id: i64, completed: bool, json: bool) -> i32 {
-    let p = match load(conn, id) {
-        Ok(p) => p,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+/// Mark the project complete or reopen it. Completing it with modules that
+/// are not Complete is refused unless `force`. Its states are `complete` and
+/// `incomplete`, as recorded in its state history with `reason`.
+pub fn update_completed(
+    conn: &Connection,
+    id: i64,
+    completed: bool,
+    reason: Option<&str>,
+    force: bool,
+) -> Result<Transitioned<Project>, Error> {
+    let mut p = get(conn, id)?;
+    let forced = if completed {
+        progress::guard_complete(conn, "project", id, &p.name, force)?
+    } else {
+        None
     };
-    conn.execute(
+    let ts = timestamp::now();
+    let label = |c: bool| if c { "complete" } else { "incomplete" };
+    let tx = db::write_tx(conn)?;
+    tx.execute(
         "UPDATE projects SET completed=?1, updated_at=?2 WHERE id=?3",
-        params![completed as i64, now(), id],
-    )
-    .unwrap();
+        params![completed as i64, ts, id],
+    )?;
+    history::record_state(
+        &tx,
+        "project",
+        id,
+        label(p.completed),
+        label(completed),
+        reason,
+        &ts,
+    )?;
+    tx.commit()?;
+    let from = label(p.completed).to_string();
+    p.completed = completed;
+    p.updated_at = ts;
+    let mut t = Transitioned::new(p, from);
+    t.moved = true;
+    t.reason = reason.map(str::to_string);
+    t.forced.extend(forced);
+    Ok(t)
+}
+
+pub fn set_completed(
+    conn: &Connection,
+    id: i64,
+    completed: bool,
+    reason: Option<&str>,
+    force: bool,
+    json: bool,
+) -> i32 {
+    let t = match update_completed(conn, id, completed, reason, force) {
+        Ok(t) => t,
+        Err(e) => return output::fail(&e),
+    };
+    for w in &t.forced {
+        eprintln!("warning: {}", w);
+    }
+    let p = &t.entity;
     if json {
-        output::print_json(&load(conn, id).map(|p| project_to_json(&p)).unwrap());
+        output::print_json(&project_to_json(p));
     } else {
         let verb = if completed {
             "marked complete"
@@ -295,52 +589,155 @@ pub fn set_completed(conn: &Connection, id: i64, completed: bool, json: bool) ->
     0
 }
 
-pub fn remove(conn: &Connection, id: i64, cascade: bool, json: bool) -> i32 {
-    let p = match load(conn, id) {
+/// Archive or unarchive a project. An archived project keeps everything
+/// under it but drops out of `project list`, and nothing in it can be changed
+/// until it is unarchived. Setting the state it already has changes nothing.
+pub fn update_archived(conn: &Connection, id: i64, archived: bool) -> Result<Project, Error> {
+    let mut p = get(conn, id)?;
+    if p.archived == archived {
+        return Ok(p);
+    }
+    let ts = timestamp::now();
+    conn.execute(
+        "UPDATE projects SET archived=?1, updated_at=?2 WHERE id=?3",
+        params![archived, ts, id],
+    )?;
+    p.archived = archived;
+    p.updated_at = ts;
+    Ok(p)
+}
+
+pub fn set_archived(conn: &Connection, id: i64, archived: bool, json: bool) -> i32 {
+    let was = match get(conn, id) {
+        Ok(p) => p.archived,
+        Err(e) => return output::fail(&e),
+    };
+    let p = match update_archived(conn, id, archived) {
         Ok(p) => p,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+        Err(e) => return output::fail(&e),
     };
-    // Check for modules
-    let count: i64 = conn
-        .query_row(
-            "SELECT COUNT(*) FROM modules WHERE project_id=?1",
-            params![id],
-            |r| r.get(0),
-        )
-        .unwrap_or(0);
+    let verb = if archived { "archived" } else { "unarchived" };
+    if json {
+        output::print_json(&project_to_json(&p));
+    } else if was == archived {
+        output::print_plain(&format!("project already {}: {}", verb, p.name));
+    } else {
+        output::print_plain(&format!("{} project: {}", verb, p.name));
+    }
+    0
+}
+
+/// Delete a project, and with `cascade` everything in it; `soft` puts it in
+/// the trash.
+pub fn delete(
+    conn: &Connection,
+    id: i64,
+    cascade: bool,
+    soft: bool,
+) -> Result<Removed<Project>, Error> {
+    let p = get(conn, id)?;
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM modules WHERE project_id=?1",
+        params![id],
+        |r| r.get(0),
+    )?;
+    let locked: i64 = conn.query_row(
+        "SELECT (SELECT COUNT(*) FROM modules WHERE project_id=?1 AND locked=1)
+              + (SELECT COUNT(*) FROM features f JOIN modules m ON m.id=f.module_id
+                 WHERE m.project_id=?1 AND f.locked=1)",
+        params![id],
+        |r| r.get(0),
+    )?;
+    if locked > 0 {
+        return Err(Error::Locked(format!(
+            "project {} contains {} locked module(s)/feature(s); unlock them before removing",
+            p.name, locked
+        )));
+    }
     if count > 0 && !cascade {
-        output::err(&format!(
+        return Err(Error::InvalidState(format!(
             "project has {} module(s); pass --cascade to remove them",
             count
-        ));
-        return 1;
+        )));
     }
-    match conn.execute("DELETE FROM projects WHERE id=?1", params![id]) {
-        Ok(_) => {
-            if json {
-                output::print_json(&json!({"deleted": true, "id": id}));
-            } else {
-                output::print_plain(&format!("removed project {}: {}", id, p.name));
-            }
-            0
-        }
-        Err(e) => {
-            output::err(&e.to_string());
-            2
+    let tx = db::write_tx(conn)?;
+    if soft {
+        trash::bury(&tx, "project", id)?;
+    }
+    tx.execute("DELETE FROM projects WHERE id=?1", params![id])?;
+    tx.commit()?;
+    Ok(Removed {
+        entity: p,
+        trashed: soft,
+        relinked: None,
+        warnings: Vec::new(),
+    })
+}
+
+pub fn remove(conn: &Connection, id: i64, cascade: bool, soft: bool, json: bool) -> i32 {
+    let removed = match delete(conn, id, cascade, soft) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
+    };
+    if json {
+        output::print_json(&json!({"deleted": true, "id": id, "trashed": soft}));
+    } else {
+        output::print_plain(&format!("removed project {}: {}", id, removed.entity.name));
+        if soft {
+            output::print_plain(&trash::buried_note("project", id));
         }
     }
+    0
 }
 
+/// The keys of a listed project's JSON, in order: the columns of `project list`
+/// under csv and tsv.
+pub const COLUMNS: &[&str] = &[
+    "archived",
+    "completed",
+    "description",
+    "effectively_completed",
+    "id",
+    "name",
+    "path",
+    "ref",
+    "state_mismatch",
+    "updated_at",
+];
+
 fn project_to_json(p: &Project) -> Value {
     json!({
         "id": p.id,
+        "ref": resolve::short_ref("project", p.id),
         "name": p.name,
         "path": p.path,
         "description": p.description,
         "completed": p.completed,
+        "effectively_completed": p.effective,
+        "state_mismatch": p.state_mismatch(),
         "updated_at": p.updated_at,
+        "archived": p.archived,
     })
 }
+
+impl From<Project> for Value {
+    fn from(p: Project) -> Value {
+        project_to_json(&p)
+    }
+}
+
+/// `every module is Complete but the project is not marked completed`, or the
+/// reverse.
+fn mismatch_note(p: &Project) -> String {
+    if p.effective {
+        "every module is Complete but the project is not marked completed".to_string()
+    } else {
+        "marked completed but not every module is Complete".to_string()
+    }
+}
+
+/// The project's full JSON record, as `project show` prints it before adding
+/// related entities.
+pub fn record_json(conn: &Connection, id: i64) -> Result<Value, Error> {
+    get(conn, id).map(|x| project_to_json(&x))
+}
@@ -0,0 +1,68 @@
+use crate::state::State;
+use rusqlite::{params, Connection};
+
+/// A reason as given on the command line; blank counts as none.
+pub fn given(reason: Option<&str>) -> Option<&str> {
+    reason.map(str::trim).filter(|r| !r.is_empty())
+}
+
+/// Details with a timestamped line recording the transition and its reason
+/// appended, or unchanged when no reason was given. Kept in the details so
+/// the reasons read as a timeline alongside the rest of the entity's notes.
+pub fn append(details: &str, from: &str, to: &State, reason: Option<&str>, ts: &str) -> String {
+    let Some(reason) = reason else {
+        return details.to_string();
+    };
+    let line = format!("[{}] {} → {}: {}", ts, from, to, reason);
+    if details.trim().is_empty() {
+        line
+    } else {
+        format!("{}\n{}", details.trim_end(), line)
+    }
+}
+
+/// Amending reopens finished work, and a week later nobody remembers why;
+/// Blocked is only useful with what it waits on. Say so when either happens
+/// without a reason.
+pub fn warn_if_missing(kind: &str, name: &str, to: &State, reason: Option<&str>) {
+    if matches!(to, State::Amending | State::Blocked) && reason.is_none() {
+        eprintln!(
+            "warning: {} {} moved to {} without a reason; pass --reason to record why",
+            kind, name, to
+        );
+    }
+}
+
+/// What a Blocked entity waits on: the reason given when it was blocked, or
+/// empty when none was. None when `state` is not Blocked.
+pub fn blocked(conn: &Connection, table: &str, id: i64, state: &str) -> Option<String> {
+    (state == "Blocked").then(|| last(conn, table, id).unwrap_or_default())
+}
+
+/// `waiting on the API key`, or a note that no reason was given.
+pub fn blocked_text(reason: &str) -> &str {
+    if reason.is_empty() {
+        "no reason given"
+    } else {
+        reason
+    }
+}
+
+/// `  [blocked: waiting on the API key]` to end a list line with, or empty
+/// when the entity is not Blocked.
+pub fn blocked_tag(conn: &Connection, table: &str, id: i64, state: &str) -> String {
+    blocked(conn, table, id, state)
+        .map(|r| format!("  [blocked: {}]", blocked_text(&r)))
+        .unwrap_or_default()
+}
+
+/// The reason given with the entity's most recent transition, if any.
+pub fn last(conn: &Connection, table: &str, id: i64) -> Option<String> {
+    conn.query_row(
+        &format!("SELECT last_transition_reason FROM {} WHERE id=?1", table),
+        params![id],
+        |r| r.get::<_, String>(0),
+    )
+    .ok()
+    .filter(|r| !r.is_empty())
+}
@@ -0,0 +1,113 @@
+use crate::models::ancestry;
+use crate::output;
+use crate::resolve;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+/// Types `last` can list, in the order they are shown when no type is given.
+pub const TYPES: [&str; 5] = ["project", "module", "feature", "task", "research"];
+
+/// Newest-first rows of one type: (id, name, state). Only research records
+/// when they were created; the rest fall back to the id, which only grows.
+fn newest_sql(kind: &str) -> &'static str {
+    match kind {
+        "project" => {
+            "SELECT id, name, CASE completed WHEN 1 THEN 'completed' ELSE 'active' END
+             FROM projects ORDER BY id DESC LIMIT ?1"
+        }
+        "module" => "SELECT id, name, state FROM modules ORDER BY id DESC LIMIT ?1",
+        "feature" => "SELECT id, name, state FROM features ORDER BY id DESC LIMIT ?1",
+        "task" => "SELECT id, name, state FROM tasks ORDER BY id DESC LIMIT ?1",
+        _ => "SELECT id, name, '' FROM research ORDER BY created_at DESC, id DESC LIMIT ?1",
+    }
+}
+
+struct Entry {
+    kind: &'static str,
+    id: i64,
+    name: String,
+    state: String,
+}
+
+fn newest(conn: &Connection, kind: &'static str, n: usize) -> rusqlite::Result<Vec<Entry>> {
+    let mut stmt = conn.prepare(newest_sql(kind))?;
+    let rows = stmt.query_map(params![n as i64], |r| {
+        Ok(Entry {
+            kind,
+            id: r.get(0)?,
+            name: r.get(1)?,
+            state: r.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// The `n` most recently created entities of one type, newest first, or of
+/// every type in hierarchy order when `kind` is None.
+pub fn last(conn: &Connection, kind: Option<&str>, n: usize, json: bool) -> i32 {
+    let kinds: Vec<&'static str> = match kind {
+        None => TYPES.to_vec(),
+        Some(k) => match TYPES.iter().find(|t| **t == k) {
+            Some(t) => vec![*t],
+            None => {
+                output::err(&format!(
+                    "unknown type '{}'; must be one of: {}",
+                    k,
+                    TYPES.join(", ")
+                ));
+                return 1;
+            }
+        },
+    };
+    let mut entries = Vec::new();
+    for k in kinds {
+        match newest(conn, k, n) {
+            Ok(e) => entries.extend(e),
+            Err(e) => {
+                output::err(&e.to_string());
+                return 2;
+            }
+        }
+    }
+
+    let crumbs: Vec<String> = match entries
+        .iter()
+        .map(|e| ancestry::breadcrumb(conn, e.kind, e.id))
+        .collect()
+    {
+        Ok(c) => c,
+        Err(e) => return output::fail(&e.into()),
+    };
+
+    if json {
+        output::print_json(&Value::Array(
+            entries
+                .iter()
+                .zip(&crumbs)
+                .map(|(e, crumb)| {
+                    json!({
+                        "type": e.kind,
+                        "id": e.id,
+                        "ref": resolve::short_ref(e.kind, e.id),
+                        "name": e.name,
+                        "state": e.state,
+                        "breadcrumb": crumb,
+                    })
+                })
+                .collect(),
+        ));
+    } else if entries.is_empty() {
+        output::print_plain("nothing created yet");
+    } else {
+        for (e, crumb) in entries.iter().zip(&crumbs) {
+            outln!(
+                "{:<8} {:<5} {:<9} {}",
+                e.kind,
+                resolve::short_ref(e.kind, e.id),
+                e.state,
+                crumb
+            );
+        }
+    }
+    0
+}
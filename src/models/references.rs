@@ -0,0 +1,375 @@
+use crate::content;
+use crate::db;
+use crate::models::history;
+use crate::output;
+use crate::timestamp;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+/// A text field containing whole-word references to a name being renamed.
+pub struct FieldHit {
+    pub kind: &'static str,
+    pub table: &'static str,
+    pub id: i64,
+    pub name: String,
+    pub column: &'static str,
+    pub occurrences: usize,
+    pub replaced: String,
+    /// (1-based line number, original line) for every line containing a reference.
+    pub lines: Vec<(usize, String)>,
+}
+
+/// What a rename did, or on a dry run would do.
+pub struct Renamed<T> {
+    /// The entity under its new name; unchanged on a dry run.
+    pub entity: T,
+    pub old_name: String,
+    /// The references rewritten; always empty without --update-references.
+    pub hits: Vec<FieldHit>,
+}
+
+/// Characters that can appear inside a slug. A reference only matches when it is
+/// not bordered by one of these, so renaming `login-flow` leaves `login-flow-v2` alone.
+fn is_slug_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Replace whole-word occurrences of `old` with `new`, returning the new text
+/// and the number of replacements made.
+pub fn replace_word(text: &str, old: &str, new: &str) -> (String, usize) {
+    if old.is_empty() {
+        return (text.to_string(), 0);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut count = 0;
+    let mut i = 0;
+    while let Some(pos) = text[i..].find(old) {
+        let start = i + pos;
+        let end = start + old.len();
+        let bounded_before = text[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !is_slug_char(c));
+        let bounded_after = text[end..].chars().next().is_none_or(|c| !is_slug_char(c));
+        out.push_str(&text[i..start]);
+        if bounded_before && bounded_after {
+            out.push_str(new);
+            count += 1;
+        } else {
+            out.push_str(old);
+        }
+        i = end;
+    }
+    out.push_str(&text[i..]);
+    (out, count)
+}
+
+/// Projects whose contents a rename of the given entity may be referenced from.
+/// Research is not owned by a project, so its scope is every project it is linked into.
+pub fn project_scope(conn: &Connection, kind: &str, id: i64) -> rusqlite::Result<Vec<i64>> {
+    let sql = match kind {
+        "project" => "SELECT ?1",
+        "module" => "SELECT project_id FROM modules WHERE id=?1",
+        "feature" => {
+            "SELECT m.project_id FROM features f JOIN modules m ON m.id=f.module_id WHERE f.id=?1"
+        }
+        "task" => {
+            "SELECT m.project_id FROM tasks t JOIN features f ON f.id=t.feature_id
+             JOIN modules m ON m.id=f.module_id WHERE t.id=?1"
+        }
+        _ => {
+            "SELECT project_id FROM research_projects WHERE research_id=?1
+             UNION SELECT m.project_id FROM research_modules rm
+                   JOIN modules m ON m.id=rm.module_id WHERE rm.research_id=?1
+             UNION SELECT m.project_id FROM research_features rf
+                   JOIN features f ON f.id=rf.feature_id
+                   JOIN modules m ON m.id=f.module_id WHERE rf.research_id=?1
+             UNION SELECT m.project_id FROM research_tasks rt
+                   JOIN tasks t ON t.id=rt.task_id JOIN features f ON f.id=t.feature_id
+                   JOIN modules m ON m.id=f.module_id WHERE rt.research_id=?1"
+        }
+    };
+    let mut stmt = conn.prepare(sql)?;
+    let ids = stmt.query_map(params![id], |r| r.get(0))?;
+    ids.collect()
+}
+
+/// Every text field in the given projects (and the research linked into them)
+/// that contains a whole-word reference to `old`. `extra_research` adds research
+/// records outside that scope, such as a research record being renamed itself.
+pub fn find_hits(
+    conn: &Connection,
+    projects: &[i64],
+    extra_research: &[i64],
+    old: &str,
+    new: &str,
+) -> rusqlite::Result<Vec<FieldHit>> {
+    // (kind, table, column, query returning id, name, text for one project id)
+    let sources: [(&str, &str, &str, &str); 8] = [
+        (
+            "project",
+            "projects",
+            "description",
+            "SELECT id, name, description FROM projects WHERE id=?1",
+        ),
+        (
+            "module",
+            "modules",
+            "description",
+            "SELECT id, name, description FROM modules WHERE project_id=?1",
+        ),
+        (
+            "module",
+            "modules",
+            "details",
+            "SELECT id, name, details FROM modules WHERE project_id=?1",
+        ),
+        (
+            "feature",
+            "features",
+            "description",
+            "SELECT f.id, f.name, f.description FROM features f
+             JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1",
+        ),
+        (
+            "feature",
+            "features",
+            "details",
+            "SELECT f.id, f.name, f.details FROM features f
+             JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1",
+        ),
+        (
+            "task",
+            "tasks",
+            "description",
+            "SELECT t.id, t.name, t.description FROM tasks t JOIN features f ON f.id=t.feature_id
+             JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1",
+        ),
+        (
+            "task",
+            "tasks",
+            "details",
+            "SELECT t.id, t.name, t.details FROM tasks t JOIN features f ON f.id=t.feature_id
+             JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1",
+        ),
+        (
+            "research",
+            "research",
+            "description",
+            "SELECT id, name, description FROM research WHERE id IN (
+                SELECT research_id FROM research_projects WHERE project_id=?1
+                UNION SELECT rm.research_id FROM research_modules rm
+                      JOIN modules m ON m.id=rm.module_id WHERE m.project_id=?1
+                UNION SELECT rf.research_id FROM research_features rf
+                      JOIN features f ON f.id=rf.feature_id
+                      JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1
+                UNION SELECT rt.research_id FROM research_tasks rt
+                      JOIN tasks t ON t.id=rt.task_id JOIN features f ON f.id=t.feature_id
+                      JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1)",
+        ),
+    ];
+
+    let mut rows: Vec<(
+        &'static str,
+        &'static str,
+        &'static str,
+        i64,
+        String,
+        String,
+    )> = Vec::new();
+    for (kind, table, column, sql) in sources {
+        let mut stmt = conn.prepare(sql)?;
+        for pid in projects {
+            for row in stmt.query_map(params![pid], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))? {
+                let (id, name, text) = row?;
+                rows.push((kind, table, column, id, name, text));
+            }
+        }
+    }
+    // Research content lives in its own column; gather it for the same records.
+    let mut research_ids: Vec<i64> = rows
+        .iter()
+        .filter(|r| r.1 == "research")
+        .map(|r| r.3)
+        .collect();
+    research_ids.extend_from_slice(extra_research);
+    research_ids.sort_unstable();
+    research_ids.dedup();
+    for rid in &research_ids {
+        let record: Option<(String, String, String)> = conn
+            .query_row(
+                "SELECT name, description, content_body(content) FROM research WHERE id=?1",
+                params![rid],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .ok();
+        if let Some((name, description, content)) = record {
+            if extra_research.contains(rid)
+                && !rows.iter().any(|r| r.1 == "research" && r.3 == *rid)
+            {
+                rows.push((
+                    "research",
+                    "research",
+                    "description",
+                    *rid,
+                    name.clone(),
+                    description,
+                ));
+            }
+            rows.push(("research", "research", "content", *rid, name, content));
+        }
+    }
+
+    let mut hits: Vec<FieldHit> = Vec::new();
+    for (kind, table, column, id, name, text) in rows {
+        if hits
+            .iter()
+            .any(|h| h.table == table && h.column == column && h.id == id)
+        {
+            continue;
+        }
+        let (replaced, occurrences) = replace_word(&text, old, new);
+        if occurrences == 0 {
+            continue;
+        }
+        let lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| replace_word(l, old, new).1 > 0)
+            .map(|(n, l)| (n + 1, l.to_string()))
+            .collect();
+        hits.push(FieldHit {
+            kind,
+            table,
+            id,
+            name,
+            column,
+            occurrences,
+            replaced,
+            lines,
+        });
+    }
+    Ok(hits)
+}
+
+/// Write the rewritten text of every hit. Run inside the rename's transaction.
+pub fn apply(conn: &Connection, hits: &[FieldHit]) -> rusqlite::Result<()> {
+    for h in hits {
+        if h.table == "research" && h.column == "content" {
+            content::save(conn, h.id, &h.replaced)?;
+            continue;
+        }
+        conn.execute(
+            &format!("UPDATE {} SET {}=?1 WHERE id=?2", h.table, h.column),
+            params![h.replaced, h.id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Rename the `kind` with this id from `old` to `new` in one transaction:
+/// with `update_refs`, find the references to rewrite, then, unless
+/// `dry_run`, set the name and touch the entity, record the old name, and
+/// rewrite the references. References are found inside the transaction so
+/// the rewrite sees exactly the rows it updates. Returns the references and,
+/// when the rename was written, the time it was stamped with.
+pub fn rename(
+    conn: &Connection,
+    kind: &str,
+    id: i64,
+    old: &str,
+    new: &str,
+    update_refs: bool,
+    dry_run: bool,
+) -> rusqlite::Result<(Vec<FieldHit>, Option<String>)> {
+    let (table, touched) = match kind {
+        "project" => ("projects", "updated_at"),
+        "module" => ("modules", "last_worked_on"),
+        "feature" => ("features", "last_worked_on"),
+        "task" => ("tasks", "last_worked_on"),
+        _ => ("research", "updated_at"),
+    };
+    // A research record's own content may name it, wherever it is linked.
+    let extra_research: &[i64] = if table == "research" { &[id] } else { &[] };
+    let tx = db::write_tx(conn)?;
+    let hits = if update_refs {
+        project_scope(&tx, kind, id)
+            .and_then(|scope| find_hits(&tx, &scope, extra_research, old, new))?
+    } else {
+        Vec::new()
+    };
+    if dry_run {
+        return Ok((hits, None));
+    }
+    let ts = timestamp::now();
+    tx.execute(
+        &format!("UPDATE {} SET name=?1, {}=?2 WHERE id=?3", table, touched),
+        params![new, ts, id],
+    )?;
+    history::record(&tx, kind, id, old, new, &ts)?;
+    apply(&tx, &hits)?;
+    tx.commit()?;
+    Ok((hits, Some(ts)))
+}
+
+pub fn hits_to_json(hits: &[FieldHit]) -> Value {
+    Value::Array(
+        hits.iter()
+            .map(|h| {
+                json!({
+                    "type": h.kind,
+                    "id": h.id,
+                    "name": h.name,
+                    "field": h.column,
+                    "occurrences": h.occurrences,
+                    "lines": h.lines.iter()
+                        .map(|(n, l)| json!({"line": n, "text": l}))
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Print what a rename with --update-references would rewrite, without writing anything.
+pub fn print_preview(kind: &str, id: i64, old: &str, new: &str, hits: &[FieldHit], json: bool) {
+    if json {
+        output::print_json(&json!({
+            "dry_run": true,
+            "type": kind,
+            "id": id,
+            "old_name": old,
+            "new_name": new,
+            "references": hits_to_json(hits),
+        }));
+        return;
+    }
+    output::print_plain(&format!("would rename {} {}: {} → {}", kind, id, old, new));
+    if hits.is_empty() {
+        output::print_empty("no references found");
+        return;
+    }
+    for h in hits {
+        outln!(
+            "{} {} {} ({}): {} reference(s)",
+            h.kind,
+            h.id,
+            h.name,
+            h.column,
+            h.occurrences
+        );
+        for (n, line) in &h.lines {
+            outln!("  {:>4}: {}", n, line);
+        }
+    }
+}
+
+/// Summary appended to plain rename output when references were rewritten.
+pub fn summary(hits: &[FieldHit]) -> String {
+    let occurrences: usize = hits.iter().map(|h| h.occurrences).sum();
+    format!(
+        " (rewrote {} reference(s) in {} field(s))",
+        occurrences,
+        hits.len()
+    )
+}
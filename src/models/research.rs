@@ -1,7 +1,28 @@
+use crate::config;
+use crate::content;
+use crate::db;
+use crate::error::Error;
+use crate::fields::{self, Page, Sort};
+use crate::limits;
+use crate::models::ancestry::{self, Crumb};
+use crate::models::attachment;
+use crate::models::placement::Placement;
+use crate::models::references::{self, Renamed};
+use crate::models::sections::{self, Sections};
+use crate::models::trash::{self, Removed};
+use crate::names;
 use crate::output;
+use crate::resolve;
+use crate::timestamp;
 use rusqlite::{params, Connection};
+use serde::Serialize;
 use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
 
+/// Serializes to the record `research show --json` starts from.
+#[derive(Debug, Clone, Serialize)]
+#[serde(into = "Value")]
 pub struct Research {
     pub id: i64,
     pub name: String,
@@ -11,34 +32,116 @@ pub struct Research {
     pub researched_at: String,
     pub created_at: String,
     pub updated_at: String,
+    /// Kept for history but left out of search, list, and select by default
+    pub archived: bool,
 }
 
-fn now() -> String {
-    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
-}
-
-fn load(conn: &Connection, id: i64) -> Result<Research, String> {
+/// The research with this id.
+pub fn get(conn: &Connection, id: i64) -> Result<Research, Error> {
     conn.query_row(
-        "SELECT id, name, description, content, source, researched_at, created_at, updated_at FROM research WHERE id=?1",
+        "SELECT id, name, description, content_body(content), source, researched_at, created_at, updated_at, archived FROM research WHERE id=?1",
         params![id],
         |r| Ok(Research {
             id: r.get(0)?, name: r.get(1)?, description: r.get(2)?,
             content: r.get(3)?, source: r.get(4)?, researched_at: r.get(5)?,
-            created_at: r.get(6)?, updated_at: r.get(7)?,
+            created_at: r.get(6)?, updated_at: r.get(7)?, archived: r.get(8)?,
         }),
     )
-    .map_err(|_| format!("research not found: {}", id))
+    .map_err(|e| Error::missing(e, "research", id))
 }
 
+/// The keys of a listed record's JSON, in order: the columns of `research
+/// list` under csv and tsv.
+pub const LIST_COLUMNS: &[&str] = &[
+    "age_days",
+    "archived",
+    "content",
+    "created_at",
+    "description",
+    "id",
+    "is_stale",
+    "link_counts",
+    "links",
+    "name",
+    "ref",
+    "researched_at",
+    "source",
+    "updated_at",
+];
+
+/// The columns of `research search` under csv and tsv: a record's keys plus
+/// every match detail, empty where the search did not produce it.
+pub const SEARCH_COLUMNS: &[&str] = &[
+    "age_days",
+    "archived",
+    "content",
+    "created_at",
+    "description",
+    "id",
+    "matched_former_name",
+    "matched_links",
+    "name",
+    "rank",
+    "ref",
+    "researched_at",
+    "source",
+    "updated_at",
+];
+
 fn research_to_json(r: &Research) -> Value {
     json!({
-        "id": r.id, "name": r.name, "description": r.description,
+        "id": r.id, "ref": resolve::short_ref("research", r.id), "name": r.name,
+        "description": r.description,
         "content": r.content, "source": r.source,
         "researched_at": r.researched_at,
         "created_at": r.created_at, "updated_at": r.updated_at,
+        "archived": r.archived,
+        "age_days": {
+            "researched_at": age_days(&r.researched_at),
+            "created_at": age_days(&r.created_at),
+            "updated_at": age_days(&r.updated_at),
+        },
     })
 }
 
+impl From<Research> for Value {
+    fn from(r: Research) -> Value {
+        research_to_json(&r)
+    }
+}
+
+/// Whole days from a stored timestamp to now; negative when it is in the
+/// future, None when it does not parse.
+fn age_days(ts: &str) -> Option<i64> {
+    timestamp::parse_tolerant(ts).map(|t| (chrono::Utc::now() - t).num_days())
+}
+
+/// Earliest researched_at taken as plausible; anything before is likely a typo.
+const EARLIEST_RESEARCHED: &str = "1990-01-01T00:00:00Z";
+
+/// What looks wrong about a record's researched_at: before 1990, in the
+/// future, or later than the record's last update (which setting it bumps).
+/// An old researched_at on a recent record is normal for imported research.
+fn timestamp_warnings(r: &Research) -> Vec<String> {
+    let mut out = Vec::new();
+    let now = timestamp::now();
+    if r.researched_at.as_str() < EARLIEST_RESEARCHED {
+        out.push(format!("researched_at {} is before 1990", r.researched_at));
+    }
+    if r.researched_at > now {
+        out.push(format!(
+            "researched_at {} is in the future",
+            r.researched_at
+        ));
+    } else if r.researched_at > r.updated_at {
+        out.push(format!(
+            "researched_at {} is later than updated_at {}",
+            r.researched_at, r.updated_at
+        ));
+    }
+    out
+}
+
 fn parse_date(s: &str) -> Result<String, String> {
     // Accept YYYY-MM-DD or YYYY-MM-DDTHH:MM:SSZ
     if s.len() == 10 {
@@ -55,863 +158,2346 @@ fn parse_date(s: &str) -> Result<String, String> {
     ))
 }
 
-pub fn add(conn: &Connection, name: &str, description: &str, json: bool) -> i32 {
-    let name = name.trim();
+/// Add an empty research record, researched now.
+pub fn create(conn: &Connection, name: &str, description: &str) -> Result<Research, Error> {
+    let name = names::normalize(name);
     if name.is_empty() {
-        output::err("name must not be empty");
-        return 1;
+        return Err(Error::Invalid("name must not be empty".into()));
+    }
+    if let Some(existing) = names::clash(conn, "research", None, &name, None)? {
+        return Err(Error::Conflict(names::taken(
+            "research record",
+            &existing,
+            None,
+        )));
     }
-    let ts = now();
+    let ts = timestamp::now();
     match conn.execute(
         "INSERT INTO research (name, description, researched_at, created_at, updated_at) VALUES (?1,?2,?3,?3,?3)",
         params![name, description, ts],
     ) {
-        Ok(_) => {
-            let id = conn.last_insert_rowid();
-            if json { output::print_json(&load(conn, id).map(|r| research_to_json(&r)).unwrap()); }
-            else { output::print_plain(&format!("added research {}: {}", id, name)); }
-            0
-        }
-        Err(e) => { output::err(&e.to_string()); 2 }
-    }
-}
-
-pub fn list(conn: &Connection, stale_days: Option<i64>, json: bool) -> i32 {
-    let records: Vec<Research> = if let Some(days) = stale_days {
-        let mut stmt = conn.prepare(
-            "SELECT id, name, description, content, source, researched_at, created_at, updated_at
-             FROM research
-             WHERE researched_at < datetime('now', ?1)
-             ORDER BY id"
-        ).unwrap();
-        let cutoff = format!("-{} days", days);
-        stmt.query_map(params![cutoff], |r| {
-            Ok(Research {
-                id: r.get(0)?,
-                name: r.get(1)?,
-                description: r.get(2)?,
-                content: r.get(3)?,
-                source: r.get(4)?,
-                researched_at: r.get(5)?,
-                created_at: r.get(6)?,
-                updated_at: r.get(7)?,
-            })
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
-    } else {
-        let mut stmt = conn.prepare(
-            "SELECT id, name, description, content, source, researched_at, created_at, updated_at FROM research ORDER BY id"
-        ).unwrap();
-        stmt.query_map([], |r| {
-            Ok(Research {
-                id: r.get(0)?,
-                name: r.get(1)?,
-                description: r.get(2)?,
-                content: r.get(3)?,
-                source: r.get(4)?,
-                researched_at: r.get(5)?,
-                created_at: r.get(6)?,
-                updated_at: r.get(7)?,
-            })
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
-    };
-
-    if records.is_empty() {
-        output::print_plain("no research found");
-        return 0;
-    }
-    if json {
-        output::print_json(&Value::Array(
-            records.iter().map(research_to_json).collect(),
-        ));
-    } else {
-        for r in &records {
-            let date = &r.researched_at[..10];
-            println!("{:<4} {:<24} {}  {}", r.id, r.name, date, r.description);
-        }
+        Ok(_) => Ok(Research {
+            id: conn.last_insert_rowid(),
+            name,
+            description: description.to_string(),
+            content: String::new(),
+            source: String::new(),
+            researched_at: ts.clone(),
+            created_at: ts.clone(),
+            updated_at: ts,
+            archived: false,
+        }),
+        Err(e) if db::name_taken(&e) => Err(Error::Conflict(names::taken(
+            "research record",
+            &name,
+            None,
+        ))),
+        Err(e) => Err(e.into()),
     }
-    0
 }
 
-pub fn show(conn: &Connection, id: i64, json: bool) -> i32 {
-    let r = match load(conn, id) {
+pub fn add(conn: &Connection, name: &str, description: &str, json: bool) -> i32 {
+    let r = match create(conn, name, description) {
         Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+        Err(e) => return output::fail(&e),
     };
-
-    // Gather all links
-    struct Link {
-        kind: String,
-        entity_id: i64,
-        name: String,
-        context: String,
-    }
-    let mut links: Vec<Link> = Vec::new();
-
-    // projects
-    let mut stmt = conn.prepare(
-        "SELECT p.id, p.name FROM projects p JOIN research_projects rp ON rp.project_id=p.id WHERE rp.research_id=?1"
-    ).unwrap();
-    for row in stmt
-        .query_map(params![id], |row| {
-            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-    {
-        links.push(Link {
-            kind: "project".into(),
-            entity_id: row.0,
-            name: row.1,
-            context: String::new(),
-        });
-    }
-
-    // modules
-    let mut stmt = conn.prepare(
-        "SELECT m.id, m.name, p.name FROM modules m JOIN research_modules rm ON rm.module_id=m.id JOIN projects p ON p.id=m.project_id WHERE rm.research_id=?1"
-    ).unwrap();
-    for row in stmt
-        .query_map(params![id], |row| {
-            Ok((
-                row.get::<_, i64>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-            ))
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-    {
-        links.push(Link {
-            kind: "module".into(),
-            entity_id: row.0,
-            name: row.1,
-            context: row.2,
-        });
-    }
-
-    // features
-    let mut stmt = conn
-        .prepare(
-            "SELECT f.id, f.name, m.name, p.name FROM features f
-         JOIN research_features rf ON rf.feature_id=f.id
-         JOIN modules m ON m.id=f.module_id
-         JOIN projects p ON p.id=m.project_id
-         WHERE rf.research_id=?1",
-        )
-        .unwrap();
-    for row in stmt
-        .query_map(params![id], |row| {
-            Ok((
-                row.get::<_, i64>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                row.get::<_, String>(3)?,
-            ))
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-    {
-        links.push(Link {
-            kind: "feature".into(),
-            entity_id: row.0,
-            name: row.1,
-            context: format!("{} > {}", row.3, row.2),
-        });
-    }
-
-    // tasks
-    let mut stmt = conn
-        .prepare(
-            "SELECT t.id, t.name, f.name, m.name, p.name FROM tasks t
-         JOIN research_tasks rt ON rt.task_id=t.id
-         JOIN features f ON f.id=t.feature_id
-         JOIN modules m ON m.id=f.module_id
-         JOIN projects p ON p.id=m.project_id
-         WHERE rt.research_id=?1",
-        )
-        .unwrap();
-    for row in stmt
-        .query_map(params![id], |row| {
-            Ok((
-                row.get::<_, i64>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                row.get::<_, String>(3)?,
-                row.get::<_, String>(4)?,
-            ))
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-    {
-        links.push(Link {
-            kind: "task".into(),
-            entity_id: row.0,
-            name: row.1,
-            context: format!("{} > {} > {}", row.4, row.3, row.2),
-        });
-    }
-
     if json {
-        let mut v = research_to_json(&r);
-        v["linked_to"] = Value::Array(
-            links
-                .iter()
-                .map(|l| {
-                    json!({
-                        "type": l.kind, "id": l.entity_id, "name": l.name, "context": l.context
-                    })
-                })
-                .collect(),
-        );
-        output::print_json(&v);
+        output::print_json(&research_to_json(&r));
     } else {
-        println!("{}", output::field("id", &r.id.to_string()));
-        println!("{}", output::field("name", &r.name));
-        println!("{}", output::field("description", &r.description));
-        println!("{}", output::field("source", &r.source));
-        println!("{}", output::field("researched_at", &r.researched_at));
-        println!("{}", output::field("created_at", &r.created_at));
-        println!("{}", output::field("updated_at", &r.updated_at));
-        if !r.content.is_empty() {
-            println!();
-            println!("content:");
-            println!("{}", output::indent_content(&r.content));
-        }
-        if !links.is_empty() {
-            println!();
-            println!("linked to:");
-            for l in &links {
-                if l.context.is_empty() {
-                    println!("  {:<10} {:<4} {}", l.kind, l.entity_id, l.name);
-                } else {
-                    println!(
-                        "  {:<10} {:<4} {:<24} ({})",
-                        l.kind, l.entity_id, l.name, l.context
-                    );
-                }
-            }
-        }
+        output::print_plain(&format!("added research {}: {}", r.id, r.name));
     }
     0
 }
 
-pub fn rename(conn: &Connection, id: i64, new_name: &str, json: bool) -> i32 {
-    let new_name = new_name.trim();
-    if new_name.is_empty() {
+/// Create (or with `append`, extend) a research record and link it to a task,
+/// and optionally the task's feature, in one transaction. The quick path for
+/// recording a small finding mid-task.
+pub fn capture(
+    conn: &Connection,
+    task_id: i64,
+    name: &str,
+    content: &str,
+    also_feature: bool,
+    append: bool,
+    json: bool,
+) -> i32 {
+    let name = &names::normalize(name);
+    if name.is_empty() {
         output::err("name must not be empty");
         return 1;
     }
-    let old = match load(conn, id) {
-        Ok(r) => r,
+    let (task_name, feature_id, feature_name): (String, i64, String) = match conn.query_row(
+        "SELECT t.name, f.id, f.name FROM tasks t JOIN features f ON f.id = t.feature_id WHERE t.id=?1",
+        params![task_id],
+        |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+    ) {
+        Ok(row) => row,
+        Err(_) => {
+            return output::fail(&Error::not_found("task", format!("task not found: {}", task_id)));
+        }
+    };
+    let existing: Option<(i64, String)> = conn
+        .query_row(
+            "SELECT id, content_body(content) FROM research WHERE fold(name)=?1",
+            params![names::fold(name)],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .ok();
+    if existing.is_some() && !append {
+        return output::fail(&Error::Conflict(format!(
+            "{} (use --append to add to it)",
+            names::taken("research record", name, None)
+        )));
+    }
+
+    let ts = timestamp::now();
+    let tx = match db::write_tx(conn) {
+        Ok(tx) => tx,
         Err(e) => {
-            output::err(&e);
-            return 1;
+            output::err(&e.to_string());
+            return 2;
         }
     };
-    match conn.execute(
-        "UPDATE research SET name=?1, updated_at=?2 WHERE id=?3",
-        params![new_name, now(), id],
-    ) {
-        Ok(_) => {
-            if json {
-                output::print_json(&load(conn, id).map(|r| research_to_json(&r)).unwrap());
+    let id = match &existing {
+        Some((id, old)) => {
+            let joined = if old.trim().is_empty() {
+                content.to_string()
             } else {
-                output::print_plain(&format!(
-                    "renamed research {}: {} → {}",
-                    id, old.name, new_name
-                ));
+                format!("{}\n\n{}", old.trim_end(), content)
+            };
+            if let Err(e) = tx.execute(
+                "UPDATE research SET researched_at=?1, updated_at=?1 WHERE id=?2",
+                params![ts, id],
+            ) {
+                output::err(&e.to_string());
+                return 2;
             }
-            0
+            if let Err(e) = content::save(&tx, *id, &joined) {
+                output::err(&e.to_string());
+                return 2;
+            }
+            *id
+        }
+        None => {
+            match tx.execute(
+                "INSERT INTO research (name, researched_at, created_at, updated_at) VALUES (?1,?2,?2,?2)",
+                params![name, ts],
+            ) {
+                Ok(_) => {}
+                Err(e) if db::name_taken(&e) => {
+                    return output::fail(&Error::Conflict(names::taken("research record", name, None)));
+                }
+                Err(e) => {
+                    output::err(&e.to_string());
+                    return 2;
+                }
+            }
+            let id = tx.last_insert_rowid();
+            if let Err(e) = content::save(&tx, id, content) {
+                output::err(&e.to_string());
+                return 2;
+            }
+            id
         }
+    };
+    // (kind, entity id, entity name, created)
+    let mut links: Vec<(&str, i64, String, bool)> = Vec::new();
+    let created = match tx.execute(
+        "INSERT OR IGNORE INTO research_tasks (research_id, task_id) VALUES (?1,?2)",
+        params![id, task_id],
+    ) {
+        Ok(n) => n > 0,
         Err(e) => {
             output::err(&e.to_string());
-            2
+            return 2;
         }
+    };
+    links.push(("task", task_id, task_name, created));
+    if also_feature {
+        let created = match tx.execute(
+            "INSERT OR IGNORE INTO research_features (research_id, feature_id) VALUES (?1,?2)",
+            params![id, feature_id],
+        ) {
+            Ok(n) => n > 0,
+            Err(e) => {
+                output::err(&e.to_string());
+                return 2;
+            }
+        };
+        links.push(("feature", feature_id, feature_name, created));
+    }
+    if let Err(e) = tx.commit() {
+        output::err(&e.to_string());
+        return 2;
     }
-}
 
-pub fn set_description(conn: &Connection, id: i64, desc: &str, json: bool) -> i32 {
-    let r = match load(conn, id) {
-        Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
-    };
-    conn.execute(
-        "UPDATE research SET description=?1, updated_at=?2 WHERE id=?3",
-        params![desc, now(), id],
-    )
-    .unwrap();
     if json {
-        output::print_json(&load(conn, id).map(|r| research_to_json(&r)).unwrap());
+        output::print_json(&json!({
+            "id": id,
+            "name": name,
+            "created": existing.is_none(),
+            "appended": existing.is_some(),
+            "links": links.iter().map(|(kind, eid, ename, created)| json!({
+                "type": kind, "id": eid, "name": ename, "created": created,
+            })).collect::<Vec<_>>(),
+        }));
     } else {
-        output::print_plain(&format!("updated description for research: {}", r.name));
+        let verb = if existing.is_some() {
+            "appended to"
+        } else {
+            "captured"
+        };
+        let linked = links
+            .iter()
+            .map(|(kind, _, ename, created)| {
+                format!(
+                    "{}: {}{}",
+                    kind,
+                    ename,
+                    if *created { "" } else { " (already linked)" }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        output::print_plain(&format!("{} research {}: {} → {}", verb, id, name, linked));
     }
     0
 }
 
-pub fn set_content(
-    conn: &Connection,
-    id: i64,
-    content: &str,
-    update_date: bool,
-    json: bool,
-) -> i32 {
-    let r = match load(conn, id) {
-        Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
-    };
-    let ts = now();
-    if update_date {
-        conn.execute(
-            "UPDATE research SET content=?1, researched_at=?2, updated_at=?2 WHERE id=?3",
-            params![content, ts, id],
-        )
-        .unwrap();
-    } else {
-        conn.execute(
-            "UPDATE research SET content=?1, updated_at=?2 WHERE id=?3",
-            params![content, ts, id],
-        )
-        .unwrap();
-    }
-    if json {
-        output::print_json(&load(conn, id).map(|r| research_to_json(&r)).unwrap());
-    } else {
-        output::print_plain(&format!("updated content for research: {}", r.name));
+/// Number of work entities a research record is linked to, per bridge table.
+struct LinkCounts {
+    project: i64,
+    module: i64,
+    feature: i64,
+    task: i64,
+}
+
+impl LinkCounts {
+    fn total(&self) -> i64 {
+        self.project + self.module + self.feature + self.task
     }
-    0
 }
 
-pub fn set_source(conn: &Connection, id: i64, source: &str, json: bool) -> i32 {
-    let r = match load(conn, id) {
-        Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
-    };
-    conn.execute(
-        "UPDATE research SET source=?1, updated_at=?2 WHERE id=?3",
-        params![source, now(), id],
+/// Every filter is applied in this one query; each correlated count is answered
+/// from its bridge table's (research_id, …) primary key index.
+/// Config key and environment variable for the default stale threshold in days.
+const STALE_DAYS_KEY: &str = "stale_days";
+const STALE_DAYS_ENV: &str = "LOPEN_MEMORY_STALE_DAYS";
+const DEFAULT_STALE_DAYS: i64 = 90;
+
+/// The effective stale threshold: the --stale-days flag, else
+/// LOPEN_MEMORY_STALE_DAYS, else `stale_days` in the config, else 90 days.
+/// A setting out of range is warned about and skipped.
+pub fn stale_threshold(flag: Option<i64>) -> i64 {
+    flag.or_else(|| {
+        limits::DAYS.setting(
+            STALE_DAYS_ENV,
+            std::env::var(STALE_DAYS_ENV)
+                .ok()
+                .and_then(|v| v.trim().parse().ok()),
+        )
+    })
+    .or_else(|| limits::DAYS.setting(STALE_DAYS_KEY, config::get_i64(STALE_DAYS_KEY)))
+    .unwrap_or(DEFAULT_STALE_DAYS)
+}
+
+const LIST_SQL: &str = "
+    SELECT id, name, description, content_body(content), source, researched_at, created_at, updated_at,
+           n_projects, n_modules, n_features, n_tasks,
+           researched_at < strftime('%Y-%m-%dT%H:%M:%SZ', 'now', ?4) AS is_stale, archived
+    FROM (
+        SELECT r.*,
+               (SELECT COUNT(*) FROM research_projects rp WHERE rp.research_id = r.id) AS n_projects,
+               (SELECT COUNT(*) FROM research_modules  rm WHERE rm.research_id = r.id) AS n_modules,
+               (SELECT COUNT(*) FROM research_features rf WHERE rf.research_id = r.id) AS n_features,
+               (SELECT COUNT(*) FROM research_tasks    rt WHERE rt.research_id = r.id) AS n_tasks
+        FROM research r
+        WHERE (?1 IS NULL OR r.researched_at < strftime('%Y-%m-%dT%H:%M:%SZ', 'now', ?1))
+          AND (?5 OR r.archived = 0)
     )
-    .unwrap();
-    if json {
-        output::print_json(&load(conn, id).map(|r| research_to_json(&r)).unwrap());
-    } else {
-        output::print_plain(&format!("updated source for research: {}", r.name));
+    WHERE (?2 IS NULL OR n_projects + n_modules + n_features + n_tasks >= ?2)
+      AND (?3 = 0 OR n_projects + n_modules + n_features + n_tasks = 0)
+    ORDER BY ";
+
+/// The fields of `LIST_COLUMNS` that `--sort` takes: all but the nested ones.
+pub const SORTABLE: &[&str] = &[
+    "archived",
+    "content",
+    "created_at",
+    "description",
+    "id",
+    "is_stale",
+    "links",
+    "name",
+    "ref",
+    "researched_at",
+    "source",
+    "updated_at",
+];
+
+/// The SQL behind each of `SORTABLE`, over `LIST_SQL`.
+fn sort_sql(field: &str) -> String {
+    if field == "name" {
+        return names::order_sql("name");
     }
-    0
+    match field {
+        "archived" => "archived",
+        "content" => "content",
+        "created_at" => "created_at",
+        "description" => "description",
+        "is_stale" => "is_stale",
+        "links" => "n_projects + n_modules + n_features + n_tasks",
+        "researched_at" => "researched_at",
+        "source" => "source",
+        "updated_at" => "updated_at",
+        _ => "id",
+    }
+    .to_string()
 }
 
-pub fn set_researched_at(conn: &Connection, id: i64, date_str: &str, json: bool) -> i32 {
-    let r = match load(conn, id) {
-        Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+/// One row of `LIST_SQL`: the record, its link counts, and whether it is stale.
+fn list_row(r: &rusqlite::Row) -> rusqlite::Result<(Research, LinkCounts, bool)> {
+    Ok((
+        Research {
+            id: r.get(0)?,
+            name: r.get(1)?,
+            description: r.get(2)?,
+            content: r.get(3)?,
+            source: r.get(4)?,
+            researched_at: r.get(5)?,
+            created_at: r.get(6)?,
+            updated_at: r.get(7)?,
+            archived: r.get(13)?,
+        },
+        LinkCounts {
+            project: r.get(8)?,
+            module: r.get(9)?,
+            feature: r.get(10)?,
+            task: r.get(11)?,
+        },
+        r.get(12)?,
+    ))
+}
+
+/// List research with link counts. `stale_days` filters to stale records;
+/// `threshold` is the effective stale threshold used to mark every record.
+/// With `buckets`, records are grouped by staleness at those boundaries.
+/// Archived records are left out unless `include_archived`. Records come in
+/// id order, or by `sort`, with only `fields` when given, and only `page` of
+/// them.
+#[allow(clippy::too_many_arguments)]
+pub fn list(
+    conn: &Connection,
+    stale_days: Option<i64>,
+    threshold: i64,
+    min_links: Option<i64>,
+    unlinked: bool,
+    buckets: Option<[i64; 3]>,
+    include_archived: bool,
+    fields: Option<&[&str]>,
+    sort: Option<Sort>,
+    page: Page,
+    json: bool,
+) -> i32 {
+    let cutoff = stale_days.map(|days| format!("-{} days", days));
+    let stale_cutoff = format!("-{} days", threshold);
+    let order = match sort {
+        Some(sort) => sort.order_by(&sort_sql(sort.field), "id"),
+        None => "id".to_string(),
     };
-    let ts = match parse_date(date_str) {
-        Ok(t) => t,
-        Err(e) => {
-            output::err(&e);
-            return 1;
+    let sql = format!("{}{}", LIST_SQL, order);
+    let total = if fields::wants_total(page) {
+        match fields::count(
+            conn,
+            &sql,
+            params![cutoff, min_links, unlinked, stale_cutoff, include_archived],
+        ) {
+            Ok(n) => Some(n),
+            Err(e) => return output::fail(&e.into()),
         }
-    };
-    conn.execute(
-        "UPDATE research SET researched_at=?1, updated_at=?2 WHERE id=?3",
-        params![ts, now(), id],
-    )
-    .unwrap();
-    if json {
-        output::print_json(&load(conn, id).map(|r| research_to_json(&r)).unwrap());
     } else {
-        output::print_plain(&format!(
-            "updated researched_at for research: {} → {}",
-            r.name, ts
-        ));
+        None
+    };
+    let mut stmt = match conn.prepare(&format!("{}{}", sql, page.sql())) {
+        Ok(s) => s,
+        Err(e) => return output::fail(&e.into()),
+    };
+    let rows = match stmt.query_map(
+        params![cutoff, min_links, unlinked, stale_cutoff, include_archived],
+        list_row,
+    ) {
+        Ok(rows) => rows,
+        Err(e) => return output::fail(&e.into()),
+    };
+    // Streamed as they are read, so a large table never sits in memory; a
+    // row that fails to read ends the stream and the command.
+    if output::format().per_row() && buckets.is_none() {
+        let mut failed = None;
+        let entries = rows
+            .map_while(|row| row.map_err(|e| failed = Some(e)).ok())
+            .map(|(r, c, stale)| list_entry_json(&r, &c, stale));
+        match fields {
+            Some(fields) => output::print_rows(fields, entries.map(|v| fields::pick(fields, &v))),
+            None => output::print_rows(LIST_COLUMNS, entries),
+        }
+        return match failed {
+            Some(e) => output::fail(&e.into()),
+            None => 0,
+        };
     }
-    0
-}
-
-pub fn search(conn: &Connection, term: &str, stale_days: Option<i64>, json: bool) -> i32 {
-    let pattern = format!("%{}%", term.to_lowercase());
-    let records: Vec<Research> = if let Some(days) = stale_days {
-        let cutoff = format!("-{} days", days);
-        let mut stmt = conn.prepare(
-            "SELECT id, name, description, content, source, researched_at, created_at, updated_at
-             FROM research
-             WHERE (LOWER(name) LIKE ?1 OR LOWER(description) LIKE ?1 OR LOWER(content) LIKE ?1 OR LOWER(source) LIKE ?1)
-               AND researched_at < datetime('now', ?2)
-             ORDER BY id"
-        ).unwrap();
-        stmt.query_map(params![pattern, cutoff], |r| {
-            Ok(Research {
-                id: r.get(0)?,
-                name: r.get(1)?,
-                description: r.get(2)?,
-                content: r.get(3)?,
-                source: r.get(4)?,
-                researched_at: r.get(5)?,
-                created_at: r.get(6)?,
-                updated_at: r.get(7)?,
-            })
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
-    } else {
-        let mut stmt = conn.prepare(
-            "SELECT id, name, description, content, source, researched_at, created_at, updated_at
-             FROM research
-             WHERE LOWER(name) LIKE ?1 OR LOWER(description) LIKE ?1 OR LOWER(content) LIKE ?1 OR LOWER(source) LIKE ?1
-             ORDER BY id"
-        ).unwrap();
-        stmt.query_map(params![pattern], |r| {
-            Ok(Research {
-                id: r.get(0)?,
-                name: r.get(1)?,
-                description: r.get(2)?,
-                content: r.get(3)?,
-                source: r.get(4)?,
-                researched_at: r.get(5)?,
-                created_at: r.get(6)?,
-                updated_at: r.get(7)?,
-            })
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
+    let records: Vec<(Research, LinkCounts, bool)> = match rows.collect() {
+        Ok(records) => records,
+        Err(e) => return output::fail(&e.into()),
     };
 
-    if records.is_empty() {
-        output::print_plain(&format!("no research found matching: {}", term));
+    if records.is_empty() && (buckets.is_some() || fields::empty_in_prose(json, total)) {
+        output::print_empty("no research found");
         return 0;
     }
-    if json {
-        output::print_json(&Value::Array(
-            records.iter().map(research_to_json).collect(),
-        ));
+    if let Some(bounds) = buckets {
+        print_by_staleness(&records, bounds, json);
+        return 0;
+    }
+    if json || fields.is_some() {
+        let entries = records
+            .iter()
+            .map(|(r, c, stale)| list_entry_json(r, c, *stale))
+            .collect();
+        fields::print_list(LIST_COLUMNS, fields, entries, total, json);
     } else {
-        for r in &records {
-            let date = &r.researched_at[..10];
-            println!("{:<4} {:<24} {}  {}", r.id, r.name, date, r.description);
+        for (r, c, stale) in &records {
+            print_list_row(r, c, *stale);
         }
+        fields::print_more(page, records.len(), total);
     }
     0
 }
 
-pub fn link_project(conn: &Connection, research_id: i64, project_id: i64, json: bool) -> i32 {
-    let r = match load(conn, research_id) {
-        Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
-    };
-    let pname: String = conn
-        .query_row(
-            "SELECT name FROM projects WHERE id=?1",
-            params![project_id],
-            |r| r.get(0),
-        )
-        .unwrap_or_default();
-    conn.execute(
-        "INSERT OR IGNORE INTO research_projects (research_id, project_id) VALUES (?1,?2)",
-        params![research_id, project_id],
-    )
-    .unwrap();
-    if json {
-        output::print_json(&json!({"linked": true, "research": r.name, "project": pname}));
-    } else {
-        output::print_plain(&format!("linked research {} → project: {}", r.name, pname));
-    }
-    0
+fn list_entry_json(r: &Research, c: &LinkCounts, stale: bool) -> Value {
+    let mut v = research_to_json(r);
+    v["is_stale"] = json!(stale);
+    v["links"] = json!(c.total());
+    v["link_counts"] = json!({
+        "project": c.project,
+        "module": c.module,
+        "feature": c.feature,
+        "task": c.task,
+    });
+    v
 }
 
-pub fn link_module(conn: &Connection, research_id: i64, module_id: i64, json: bool) -> i32 {
-    let r = match load(conn, research_id) {
-        Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
-    };
-    let mname: String = conn
-        .query_row(
-            "SELECT name FROM modules WHERE id=?1",
-            params![module_id],
-            |r| r.get(0),
-        )
-        .unwrap_or_default();
-    conn.execute(
-        "INSERT OR IGNORE INTO research_modules (research_id, module_id) VALUES (?1,?2)",
-        params![research_id, module_id],
-    )
-    .unwrap();
-    if json {
-        output::print_json(&json!({"linked": true, "research": r.name, "module": mname}));
+/// `  [archived]` after an archived record's line, else nothing.
+fn archived_marker(r: &Research) -> &'static str {
+    if r.archived {
+        "  [archived]"
     } else {
-        output::print_plain(&format!("linked research {} → module: {}", r.name, mname));
+        ""
     }
-    0
 }
 
-pub fn link_feature(conn: &Connection, research_id: i64, feature_id: i64, json: bool) -> i32 {
-    let r = match load(conn, research_id) {
-        Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+fn print_list_row(r: &Research, c: &LinkCounts, stale: bool) {
+    let date = timestamp::date(&r.researched_at);
+    // `!` after the date marks records past the stale threshold.
+    outln!(
+        "{:<5} {:<24} {}{} {:>3}  {}{}",
+        resolve::short_ref("research", r.id),
+        r.name,
+        date,
+        if stale { "!" } else { " " },
+        c.total(),
+        r.description,
+        archived_marker(r)
+    );
+}
+
+/// Config key for the staleness bucket boundaries in days.
+const BUCKETS_KEY: &str = "staleness_buckets";
+const DEFAULT_BUCKETS: [i64; 3] = [30, 90, 365];
+/// Staleness buckets by age since researched_at, split at the three boundaries.
+const BUCKET_NAMES: [&str; 4] = ["fresh", "aging", "stale", "ancient"];
+
+/// The staleness bucket boundaries in days: --buckets as `30,90,365`, else
+/// `staleness_buckets` in the config (the same string, or a JSON array), else
+/// 30, 90, and 365. Three positive, strictly ascending numbers.
+pub fn staleness_buckets(flag: Option<&str>) -> Result<[i64; 3], String> {
+    let raw: Option<Vec<Option<i64>>> = match flag {
+        Some(f) => Some(f.split(',').map(|n| n.trim().parse().ok()).collect()),
+        None => config::load().get(BUCKETS_KEY).map(|v| match v {
+            Value::Array(a) => a.iter().map(Value::as_i64).collect(),
+            Value::String(s) => s.split(',').map(|n| n.trim().parse().ok()).collect(),
+            _ => vec![None],
+        }),
     };
-    let fname: String = conn
-        .query_row(
-            "SELECT name FROM features WHERE id=?1",
-            params![feature_id],
-            |r| r.get(0),
-        )
-        .unwrap_or_default();
-    conn.execute(
-        "INSERT OR IGNORE INTO research_features (research_id, feature_id) VALUES (?1,?2)",
-        params![research_id, feature_id],
-    )
-    .unwrap();
-    if json {
-        output::print_json(&json!({"linked": true, "research": r.name, "feature": fname}));
-    } else {
-        output::print_plain(&format!("linked research {} → feature: {}", r.name, fname));
+    let Some(raw) = raw else {
+        return Ok(DEFAULT_BUCKETS);
+    };
+    let bounds: Vec<i64> = raw
+        .into_iter()
+        .collect::<Option<_>>()
+        .ok_or("staleness buckets must be whole numbers of days, e.g. 30,90,365")?;
+    match bounds[..] {
+        [a, b, c] if 0 < a && a < b && b < c => Ok([a, b, c]),
+        _ => Err(format!(
+            "staleness buckets must be three positive, ascending day counts (got {})",
+            bounds
+                .iter()
+                .map(i64::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        )),
     }
-    0
 }
 
-pub fn link_task(conn: &Connection, research_id: i64, task_id: i64, json: bool) -> i32 {
-    let r = match load(conn, research_id) {
+/// Label for each bucket, e.g. `aging (30–90d)`.
+fn bucket_ranges(bounds: [i64; 3]) -> [String; 4] {
+    [
+        format!("<{}d", bounds[0]),
+        format!("{}–{}d", bounds[0], bounds[1]),
+        format!("{}–{}d", bounds[1], bounds[2]),
+        format!(">{}d", bounds[2]),
+    ]
+}
+
+/// The listed records in sections by how long ago they were researched.
+fn print_by_staleness(records: &[(Research, LinkCounts, bool)], bounds: [i64; 3], json: bool) {
+    let mut groups: [Vec<&(Research, LinkCounts, bool)>; 4] = Default::default();
+    for rec in records {
+        let age = age_days(&rec.0.researched_at).unwrap_or(0);
+        let bucket = bounds.iter().filter(|b| age >= **b).count();
+        groups[bucket].push(rec);
+    }
+    let ranges = bucket_ranges(bounds);
+    if json {
+        let mut out = serde_json::Map::new();
+        for (i, group) in groups.iter().enumerate() {
+            out.insert(
+                BUCKET_NAMES[i].to_string(),
+                json!({
+                    "range": ranges[i],
+                    "count": group.len(),
+                    "records": group
+                        .iter()
+                        .map(|(r, c, stale)| list_entry_json(r, c, *stale))
+                        .collect::<Vec<_>>(),
+                }),
+            );
+        }
+        output::print_json(&Value::Object(out));
+        return;
+    }
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            outln!();
+        }
+        outln!("{} ({}): {}", BUCKET_NAMES[i], ranges[i], group.len());
+        for (r, c, stale) in group {
+            print_list_row(r, c, *stale);
+        }
+    }
+}
+
+/// A work entity a research record is linked to, as listed by `show` and `links`.
+struct Link {
+    kind: String,
+    entity_id: i64,
+    name: String,
+    description: String,
+    context: String,
+    /// From the project down to the entity itself.
+    chain: Vec<Crumb>,
+}
+
+impl Link {
+    fn to_json(&self) -> Value {
+        json!({
+            "type": self.kind, "id": self.entity_id, "ref": resolve::short_ref(&self.kind, self.entity_id),
+            "name": self.name, "description": self.description, "context": self.context
+        })
+    }
+
+    /// The nearest ancestor of this entity that is itself among `linked`,
+    /// every (kind, id) the record is linked to.
+    fn linked_ancestor(&self, linked: &[(String, i64)]) -> Option<&Crumb> {
+        let above = &self.chain[..self.chain.len().saturating_sub(1)];
+        above
+            .iter()
+            .rev()
+            .find(|a| linked.iter().any(|(k, i)| k == a.kind && *i == a.id))
+    }
+
+    /// The link row, then the description cut to one line beneath the name.
+    fn print(&self, indent: &str, suffix: &str) {
+        if self.context.is_empty() {
+            outln!(
+                "{}{:<10} {:<5} {}{}",
+                indent,
+                self.kind,
+                resolve::short_ref(&self.kind, self.entity_id),
+                self.name,
+                suffix
+            );
+        } else {
+            outln!(
+                "{}{:<10} {:<5} {:<24} ({}){}",
+                indent,
+                self.kind,
+                resolve::short_ref(&self.kind, self.entity_id),
+                self.name,
+                self.context,
+                suffix
+            );
+        }
+        if !self.description.trim().is_empty() {
+            outln!(
+                "{}{:<10} {:<5} {}",
+                indent,
+                "",
+                "",
+                output::one_line(&self.description, LINK_DESCRIPTION_CHARS)
+            );
+        }
+    }
+}
+
+/// Width a linked entity's description is cut to in plain output.
+const LINK_DESCRIPTION_CHARS: usize = 72;
+
+/// Every entity a research record (?1) is linked to, with its description,
+/// where it sits, and its breadcrumb as (folded name, id) sort keys from the
+/// project down; levels below the entity are NULL and so sort first.
+const LINKS_SQL: &str = "
+    SELECT 'project' AS kind, p.id AS id, p.name AS name, p.description AS description,
+           '' AS context, fold(p.name) AS k1, p.id AS i1, NULL AS k2, NULL AS i2,
+           NULL AS k3, NULL AS i3, NULL AS k4, NULL AS i4
+      FROM projects p JOIN research_projects rp ON rp.project_id=p.id
+     WHERE rp.research_id=?1
+    UNION ALL
+    SELECT 'module', m.id, m.name, m.description, p.name,
+           fold(p.name), p.id, fold(m.name), m.id, NULL, NULL, NULL, NULL
+      FROM modules m JOIN research_modules rm ON rm.module_id=m.id
+      JOIN projects p ON p.id=m.project_id
+     WHERE rm.research_id=?1
+    UNION ALL
+    SELECT 'feature', f.id, f.name, f.description, p.name || ' > ' || m.name,
+           fold(p.name), p.id, fold(m.name), m.id, fold(f.name), f.id, NULL, NULL
+      FROM features f JOIN research_features rf ON rf.feature_id=f.id
+      JOIN modules m ON m.id=f.module_id
+      JOIN projects p ON p.id=m.project_id
+     WHERE rf.research_id=?1
+    UNION ALL
+    SELECT 'task', t.id, t.name, t.description, p.name || ' > ' || m.name || ' > ' || f.name,
+           fold(p.name), p.id, fold(m.name), m.id, fold(f.name), f.id, fold(t.name), t.id
+      FROM tasks t JOIN research_tasks rt ON rt.task_id=t.id
+      JOIN features f ON f.id=t.feature_id
+      JOIN modules m ON m.id=f.module_id
+      JOIN projects p ON p.id=m.project_id
+     WHERE rt.research_id=?1";
+
+/// Entities a research record is linked to, optionally only of one `kind`,
+/// in breadcrumb order: by project, module, feature, then task name
+/// (case-folded, ids breaking ties), so an entity comes straight after its
+/// linked ancestors and before its linked children. `limit` and `offset`
+/// page through them in that order; breadcrumbs are only looked up for the
+/// page.
+fn links_of(
+    conn: &Connection,
+    id: i64,
+    kind: Option<&str>,
+    limit: Option<i64>,
+    offset: i64,
+) -> rusqlite::Result<Vec<Link>> {
+    let sql = format!(
+        "SELECT kind, id, name, description, context FROM ({})
+         WHERE ?2 IS NULL OR kind=?2
+         ORDER BY k1, i1, k2, i2, k3, i3, k4, i4
+         LIMIT ?3 OFFSET ?4",
+        LINKS_SQL
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let mut links: Vec<Link> = stmt
+        .query_map(params![id, kind, limit.unwrap_or(-1), offset], |row| {
+            Ok(Link {
+                kind: row.get(0)?,
+                entity_id: row.get(1)?,
+                name: row.get(2)?,
+                description: row.get(3)?,
+                context: row.get(4)?,
+                chain: Vec::new(),
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    for l in &mut links {
+        l.chain = ancestry::chain(conn, &l.kind, l.entity_id)?;
+    }
+    Ok(links)
+}
+
+/// How many entities of each type a research record is linked to, counted
+/// as `links_of` lists them.
+fn link_counts(conn: &Connection, id: i64) -> rusqlite::Result<Vec<(&'static str, i64)>> {
+    let mut counts = vec![("project", 0), ("module", 0), ("feature", 0), ("task", 0)];
+    let mut stmt = conn.prepare(&format!(
+        "SELECT kind, COUNT(*) FROM ({}) GROUP BY kind",
+        LINKS_SQL
+    ))?;
+    let rows = stmt.query_map(params![id], |r| {
+        Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?))
+    })?;
+    for row in rows {
+        let (kind, n) = row?;
+        if let Some(c) = counts.iter_mut().find(|(k, _)| *k == kind) {
+            c.1 = n;
+        }
+    }
+    Ok(counts)
+}
+
+/// `link_counts` as JSON, with the total.
+fn link_counts_json(counts: &[(&str, i64)]) -> Value {
+    let mut v = json!({});
+    for (kind, n) in counts {
+        v[*kind] = json!(n);
+    }
+    v["total"] = json!(counts.iter().map(|(_, n)| n).sum::<i64>());
+    v
+}
+
+/// Default for `research show --links-limit`.
+pub const SHOW_LINKS_LIMIT: i64 = 50;
+
+/// A record with its attachments and at most `links_limit` of its links;
+/// the rest are counted and left to `research links`.
+pub fn show(conn: &Connection, id: i64, timeline: bool, links_limit: i64, json: bool) -> i32 {
+    let r = match get(conn, id) {
         Ok(r) => r,
+        Err(e) => return output::fail(&e),
+    };
+    if timeline {
+        return show_timeline(&r, json);
+    }
+
+    let (links, counts) = match links_of(conn, id, None, Some(links_limit), 0)
+        .and_then(|links| Ok((links, link_counts(conn, id)?)))
+    {
+        Ok(l) => l,
         Err(e) => {
-            output::err(&e);
-            return 1;
+            output::err(&e.to_string());
+            return 2;
         }
     };
-    let tname: String = conn
-        .query_row(
-            "SELECT name FROM tasks WHERE id=?1",
-            params![task_id],
-            |r| r.get(0),
-        )
-        .unwrap_or_default();
-    conn.execute(
-        "INSERT OR IGNORE INTO research_tasks (research_id, task_id) VALUES (?1,?2)",
-        params![research_id, task_id],
-    )
-    .unwrap();
+    let omitted = counts.iter().map(|(_, n)| n).sum::<i64>() - links.len() as i64;
+    let linked = if links.is_empty() {
+        Vec::new()
+    } else {
+        match linked_entities(conn, id) {
+            Ok(l) => l,
+            Err(e) => {
+                output::err(&e.to_string());
+                return 2;
+            }
+        }
+    };
+
+    let attachments = match attachment::for_research(conn, id) {
+        Ok(a) => a,
+        Err(e) => return output::fail(&e.into()),
+    };
+
     if json {
-        output::print_json(&json!({"linked": true, "research": r.name, "task": tname}));
+        let mut v = research_to_json(&r);
+        v["attachments"] = Value::Array(attachments.iter().map(attachment::to_json).collect());
+        v["sections"] = json!(Sections::parse(&r.content).names());
+        v["linked_to"] = Value::Array(
+            links
+                .iter()
+                .map(|l| {
+                    let mut j = l.to_json();
+                    j["under"] = match l.linked_ancestor(&linked) {
+                        Some(a) => json!({"type": a.kind, "id": a.id, "name": a.name}),
+                        None => Value::Null,
+                    };
+                    j
+                })
+                .collect(),
+        );
+        v["link_counts"] = link_counts_json(&counts);
+        v["links_omitted"] = json!(omitted);
+        output::print_json(&v);
     } else {
-        output::print_plain(&format!("linked research {} → task: {}", r.name, tname));
+        outln!("{}", output::field("id", &r.id.to_string()));
+        outln!(
+            "{}",
+            output::field("ref", &resolve::short_ref("research", r.id))
+        );
+        outln!("{}", output::field("name", &r.name));
+        if r.archived {
+            outln!("{}", output::field("archived", "yes"));
+        }
+        outln!("{}", output::field("description", &r.description));
+        outln!("{}", output::field("source", &r.source));
+        outln!("{}", output::field("researched_at", &r.researched_at));
+        outln!("{}", output::field("created_at", &r.created_at));
+        outln!("{}", output::field("updated_at", &r.updated_at));
+        let names = Sections::parse(&r.content).names().join(", ");
+        if !names.is_empty() {
+            outln!("{}", output::field("sections", &names));
+        }
+        if !r.content.is_empty() {
+            outln!();
+            outln!("content:");
+            outln!("{}", output::indent_content(&r.content));
+        }
+        if !attachments.is_empty() {
+            outln!();
+            outln!("attachments:");
+            for a in &attachments {
+                outln!("  {}", attachment::line(a));
+            }
+        }
+        if !links.is_empty() {
+            outln!();
+            outln!("linked to:");
+            // Grouped under each project in turn; links_of already sorts by breadcrumb.
+            let mut project: Option<&Crumb> = None;
+            for l in &links {
+                let Some(top) = l.chain.first() else {
+                    continue;
+                };
+                if project.map(|p| p.id) != Some(top.id) {
+                    outln!("  {}", top.name);
+                    project = Some(top);
+                }
+                let suffix = match l.linked_ancestor(&linked) {
+                    Some(a) => format!("  (under linked {} {})", a.kind, a.name),
+                    None => String::new(),
+                };
+                l.print("    ", &suffix);
+            }
+        }
+        if omitted > 0 {
+            if links.is_empty() {
+                outln!();
+                outln!("linked to:");
+            }
+            outln!(
+                "  and {} more link(s) (use research links --research {})",
+                omitted,
+                r.name
+            );
+        }
     }
     0
 }
 
-pub fn unlink_project(conn: &Connection, research_id: i64, project_id: i64, json: bool) -> i32 {
-    let r = match load(conn, research_id) {
-        Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
+/// The three timestamps of a record oldest first, each with its age, then
+/// anything that looks wrong about them.
+fn show_timeline(r: &Research, json: bool) -> i32 {
+    let mut stamps = [
+        ("researched_at", &r.researched_at),
+        ("created_at", &r.created_at),
+        ("updated_at", &r.updated_at),
+    ];
+    stamps.sort_by(|a, b| a.1.cmp(b.1));
+    let warnings = timestamp_warnings(r);
+    if json {
+        output::print_json(&json!({
+            "id": r.id,
+            "ref": resolve::short_ref("research", r.id),
+            "name": r.name,
+            "timeline": stamps.iter().map(|(field, at)| json!({
+                "field": field, "at": at, "age_days": age_days(at),
+            })).collect::<Vec<_>>(),
+            "warnings": warnings,
+        }));
+        return 0;
+    }
+    outln!("{}", r.name);
+    for (field, at) in &stamps {
+        let age = match age_days(at) {
+            Some(d) if d < 0 => format!("in {} days", -d),
+            Some(d) => format!("{} days ago", d),
+            None => "unparseable".to_string(),
+        };
+        outln!("  {:<14} {}  ({})", field, at, age);
+    }
+    for w in &warnings {
+        outln!("  ! {}", w);
+    }
+    0
+}
+
+/// Rename a research record, and with `update_refs` rewrite whole-word
+/// mentions of the old name across the projects it is linked into. A dry
+/// run finds the references and writes nothing.
+pub fn rename_to(
+    conn: &Connection,
+    id: i64,
+    new_name: &str,
+    update_refs: bool,
+    dry_run: bool,
+) -> Result<Renamed<Research>, Error> {
+    let new_name = names::normalize(new_name);
+    if new_name.is_empty() {
+        return Err(Error::Invalid("name must not be empty".into()));
+    }
+    let mut r = get(conn, id)?;
+    if let Some(existing) = names::clash(conn, "research", None, &new_name, Some(id))? {
+        return Err(Error::Conflict(names::taken(
+            "research record",
+            &existing,
+            None,
+        )));
+    }
+    let (hits, touched) = match references::rename(
+        conn,
+        "research",
+        id,
+        &r.name,
+        &new_name,
+        update_refs,
+        dry_run,
+    ) {
+        Ok(renamed) => renamed,
+        Err(e) if db::name_taken(&e) => {
+            return Err(Error::Conflict(names::taken(
+                "research record",
+                &new_name,
+                None,
+            )))
         }
+        Err(e) => return Err(e.into()),
     };
-    let pname: String = conn
-        .query_row(
-            "SELECT name FROM projects WHERE id=?1",
-            params![project_id],
-            |r| r.get(0),
-        )
-        .unwrap_or_default();
-    conn.execute(
-        "DELETE FROM research_projects WHERE research_id=?1 AND project_id=?2",
-        params![research_id, project_id],
-    )
-    .unwrap();
+    let old_name = r.name.clone();
+    if let Some(ts) = touched {
+        r.name = new_name;
+        r.updated_at = ts;
+    }
+    Ok(Renamed {
+        entity: r,
+        old_name,
+        hits,
+    })
+}
+
+pub fn rename(
+    conn: &Connection,
+    id: i64,
+    new_name: &str,
+    update_refs: bool,
+    dry_run: bool,
+    json: bool,
+) -> i32 {
+    let renamed = match rename_to(conn, id, new_name, update_refs, dry_run) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
+    };
+    let r = &renamed.entity;
+    if dry_run {
+        references::print_preview(
+            "research",
+            id,
+            &renamed.old_name,
+            &names::normalize(new_name),
+            &renamed.hits,
+            json,
+        );
+        return 0;
+    }
     if json {
-        output::print_json(&json!({"unlinked": true, "research": r.name, "project": pname}));
+        let mut v = research_to_json(r);
+        if update_refs {
+            v["references"] = references::hits_to_json(&renamed.hits);
+        }
+        output::print_json(&v);
     } else {
+        let refs = if update_refs {
+            references::summary(&renamed.hits)
+        } else {
+            String::new()
+        };
         output::print_plain(&format!(
-            "unlinked research {} from project: {}",
-            r.name, pname
+            "renamed research {}: {} → {}{}",
+            id, renamed.old_name, r.name, refs
         ));
     }
     0
 }
 
-pub fn unlink_module(conn: &Connection, research_id: i64, module_id: i64, json: bool) -> i32 {
-    let r = match load(conn, research_id) {
+/// Replace the record's description.
+pub fn update_description(conn: &Connection, id: i64, desc: &str) -> Result<Research, Error> {
+    let mut r = get(conn, id)?;
+    let ts = timestamp::now();
+    conn.execute(
+        "UPDATE research SET description=?1, updated_at=?2 WHERE id=?3",
+        params![desc, ts, id],
+    )?;
+    r.description = desc.to_string();
+    r.updated_at = ts;
+    Ok(r)
+}
+
+pub fn set_description(conn: &Connection, id: i64, desc: &str, json: bool) -> i32 {
+    let r = match update_description(conn, id, desc) {
         Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+        Err(e) => return output::fail(&e),
+    };
+    if json {
+        output::print_json(&research_to_json(&r));
+    } else {
+        output::print_plain(&format!("updated description for research: {}", r.name));
+    }
+    0
+}
+
+/// Replace the content, or add `content` after or before it per `placement`.
+/// With `update_date`, researched_at moves to now as well.
+pub fn update_content(
+    conn: &Connection,
+    id: i64,
+    content: &str,
+    placement: Placement,
+    update_date: bool,
+) -> Result<Research, Error> {
+    let mut r = get(conn, id)?;
+    let content = placement.merge(&r.content, content);
+    let ts = timestamp::now();
+    content::save(conn, id, &content)?;
+    if update_date {
+        conn.execute(
+            "UPDATE research SET researched_at=?1, updated_at=?1 WHERE id=?2",
+            params![ts, id],
+        )?;
+        r.researched_at = ts.clone();
+    } else {
+        conn.execute(
+            "UPDATE research SET updated_at=?1 WHERE id=?2",
+            params![ts, id],
+        )?;
+    }
+    r.content = content;
+    r.updated_at = ts;
+    Ok(r)
+}
+
+pub fn set_content(
+    conn: &Connection,
+    id: i64,
+    content: &str,
+    placement: Placement,
+    update_date: bool,
+    json: bool,
+) -> i32 {
+    let r = match update_content(conn, id, content, placement, update_date) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
     };
-    let mname: String = conn
-        .query_row(
-            "SELECT name FROM modules WHERE id=?1",
-            params![module_id],
-            |r| r.get(0),
-        )
-        .unwrap_or_default();
-    conn.execute(
-        "DELETE FROM research_modules WHERE research_id=?1 AND module_id=?2",
-        params![research_id, module_id],
-    )
-    .unwrap();
     if json {
-        output::print_json(&json!({"unlinked": true, "research": r.name, "module": mname}));
+        output::print_json(&research_to_json(&r));
     } else {
         output::print_plain(&format!(
-            "unlinked research {} from module: {}",
-            r.name, mname
+            "{} content for research: {}",
+            placement.verb(),
+            r.name
         ));
     }
     0
 }
 
-pub fn unlink_feature(conn: &Connection, research_id: i64, feature_id: i64, json: bool) -> i32 {
-    let r = match load(conn, research_id) {
+/// `research X has no section 'name'`, listing the sections it does have.
+fn no_section(r: &Research, doc: &Sections, name: &str) -> String {
+    let names = doc.names();
+    if names.is_empty() {
+        format!("research {} has no sections", r.name)
+    } else {
+        format!(
+            "research {} has no section '{}' (sections: {})",
+            r.name,
+            name,
+            names.join(", ")
+        )
+    }
+}
+
+/// Print one section of a record's content.
+pub fn show_section(conn: &Connection, id: i64, name: &str, json: bool) -> i32 {
+    let r = match get(conn, id) {
         Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
+        Err(e) => return output::fail(&e),
+    };
+    let doc = Sections::parse(&r.content);
+    let Some(text) = doc.get(name) else {
+        output::err(&no_section(&r, &doc, name));
+        return 1;
+    };
+    if json {
+        output::print_json(&json!({
+            "research": r.name,
+            "research_id": r.id,
+            "section": name,
+            "content": text,
+        }));
+    } else {
+        output::print_plain(text);
+    }
+    0
+}
+
+/// What storing or removing one section did.
+pub struct SectionUpdate {
+    pub research: Research,
+    /// `added`, `updated`, or `removed`.
+    pub action: &'static str,
+    /// The record's section names afterwards, in order.
+    pub sections: Vec<String>,
+}
+
+/// Store the text of one section (`text`), or remove it (None), leaving the
+/// rest of the content as it was. A new section goes at the end.
+pub fn update_section(
+    conn: &Connection,
+    id: i64,
+    name: &str,
+    text: Option<&str>,
+    update_date: bool,
+) -> Result<SectionUpdate, Error> {
+    sections::validate_name(name).map_err(Error::Invalid)?;
+    let mut r = get(conn, id)?;
+    let mut doc = Sections::parse(&r.content);
+    let action = match text {
+        Some(text) => {
+            if text.trim().is_empty() {
+                return Err(Error::Invalid(
+                    "section text is empty; pass --remove-section to delete a section".into(),
+                ));
+            }
+            sections::check_text(text).map_err(Error::Invalid)?;
+            if doc.set(name, text) {
+                "updated"
+            } else {
+                "added"
+            }
+        }
+        None => {
+            if !doc.remove(name) {
+                return Err(Error::Invalid(no_section(&r, &doc, name)));
+            }
+            "removed"
         }
     };
-    let fname: String = conn
-        .query_row(
-            "SELECT name FROM features WHERE id=?1",
-            params![feature_id],
-            |r| r.get(0),
-        )
-        .unwrap_or_default();
-    conn.execute(
-        "DELETE FROM research_features WHERE research_id=?1 AND feature_id=?2",
-        params![research_id, feature_id],
-    )
-    .unwrap();
+    let ts = timestamp::now();
+    let content = doc.render();
+    content::save(conn, id, &content)?;
+    let sql = if update_date {
+        r.researched_at = ts.clone();
+        "UPDATE research SET researched_at=?1, updated_at=?1 WHERE id=?2"
+    } else {
+        "UPDATE research SET updated_at=?1 WHERE id=?2"
+    };
+    conn.execute(sql, params![ts, id])?;
+    r.content = content;
+    r.updated_at = ts;
+    Ok(SectionUpdate {
+        research: r,
+        action,
+        sections: doc.names().into_iter().map(str::to_string).collect(),
+    })
+}
+
+pub fn set_section(
+    conn: &Connection,
+    id: i64,
+    name: &str,
+    text: Option<&str>,
+    update_date: bool,
+    json: bool,
+) -> i32 {
+    let u = match update_section(conn, id, name, text, update_date) {
+        Ok(u) => u,
+        Err(e) => return output::fail(&e),
+    };
     if json {
-        output::print_json(&json!({"unlinked": true, "research": r.name, "feature": fname}));
+        output::print_json(&json!({
+            "research": u.research.name,
+            "research_id": u.research.id,
+            "section": name,
+            "action": u.action,
+            "sections": u.sections,
+        }));
     } else {
         output::print_plain(&format!(
-            "unlinked research {} from feature: {}",
-            r.name, fname
+            "{} section {} of research: {}",
+            u.action, name, u.research.name
         ));
     }
     0
 }
 
-pub fn unlink_task(conn: &Connection, research_id: i64, task_id: i64, json: bool) -> i32 {
-    let r = match load(conn, research_id) {
+/// Archive or unarchive a record. Its links and content stay as they are; an
+/// archived record only drops out of search, list, and select unless asked
+/// for. Setting the state it already has is a no-op.
+pub fn update_archived(conn: &Connection, id: i64, archived: bool) -> Result<Research, Error> {
+    let mut r = get(conn, id)?;
+    if r.archived == archived {
+        return Ok(r);
+    }
+    let ts = timestamp::now();
+    conn.execute(
+        "UPDATE research SET archived=?1, updated_at=?2 WHERE id=?3",
+        params![archived, ts, id],
+    )?;
+    r.archived = archived;
+    r.updated_at = ts;
+    Ok(r)
+}
+
+pub fn set_archived(conn: &Connection, id: i64, archived: bool, json: bool) -> i32 {
+    let was = match get(conn, id) {
+        Ok(r) => r.archived,
+        Err(e) => return output::fail(&e),
+    };
+    let r = match update_archived(conn, id, archived) {
         Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+        Err(e) => return output::fail(&e),
     };
-    let tname: String = conn
-        .query_row(
-            "SELECT name FROM tasks WHERE id=?1",
-            params![task_id],
-            |r| r.get(0),
-        )
-        .unwrap_or_default();
+    let verb = if archived { "archived" } else { "unarchived" };
+    if json {
+        output::print_json(&research_to_json(&r));
+    } else if was == archived {
+        output::print_plain(&format!("research already {}: {}", verb, r.name));
+    } else {
+        output::print_plain(&format!("{} research: {}", verb, r.name));
+    }
+    0
+}
+
+/// Replace the record's source.
+pub fn update_source(conn: &Connection, id: i64, source: &str) -> Result<Research, Error> {
+    let mut r = get(conn, id)?;
+    let ts = timestamp::now();
     conn.execute(
-        "DELETE FROM research_tasks WHERE research_id=?1 AND task_id=?2",
-        params![research_id, task_id],
-    )
-    .unwrap();
+        "UPDATE research SET source=?1, updated_at=?2 WHERE id=?3",
+        params![source, ts, id],
+    )?;
+    r.source = source.to_string();
+    r.updated_at = ts;
+    Ok(r)
+}
+
+pub fn set_source(conn: &Connection, id: i64, source: &str, json: bool) -> i32 {
+    let r = match update_source(conn, id, source) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
+    };
     if json {
-        output::print_json(&json!({"unlinked": true, "research": r.name, "task": tname}));
+        output::print_json(&research_to_json(&r));
+    } else {
+        output::print_plain(&format!("updated source for research: {}", r.name));
+    }
+    0
+}
+
+/// Set researched_at from `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM:SSZ`. The
+/// returned warnings say when the date is implausibly early or in the future.
+pub fn update_researched_at(
+    conn: &Connection,
+    id: i64,
+    date_str: &str,
+) -> Result<(Research, Vec<String>), Error> {
+    let mut r = get(conn, id)?;
+    let ts = parse_date(date_str).map_err(Error::Invalid)?;
+    let updated = timestamp::now();
+    conn.execute(
+        "UPDATE research SET researched_at=?1, updated_at=?2 WHERE id=?3",
+        params![ts, updated, id],
+    )?;
+    r.researched_at = ts;
+    r.updated_at = updated;
+    let warnings = timestamp_warnings(&r);
+    Ok((r, warnings))
+}
+
+pub fn set_researched_at(conn: &Connection, id: i64, date_str: &str, json: bool) -> i32 {
+    let (r, warnings) = match update_researched_at(conn, id, date_str) {
+        Ok(updated) => updated,
+        Err(e) => return output::fail(&e),
+    };
+    for w in warnings {
+        eprintln!("warning: research {}: {}", r.name, w);
+    }
+    if json {
+        output::print_json(&research_to_json(&r));
     } else {
         output::print_plain(&format!(
-            "unlinked research {} from task: {}",
-            r.name, tname
+            "updated researched_at for research: {} → {}",
+            r.name, r.researched_at
         ));
     }
     0
 }
 
-pub fn links(conn: &Connection, id: i64, json: bool) -> i32 {
-    match load(conn, id) {
-        Ok(r) => r,
-        Err(e) => {
-            output::err(&e);
-            return 1;
+/// Names of every entity a research record is linked to, across all four bridge tables.
+/// Linked entity names per research record. Former names from `name_history`
+/// are included (with `former` set) only when ?4 is true.
+const LINKED_CTE: &str = "WITH linked(research_id, kind, name, former) AS (
+        SELECT rp.research_id, 'project', p.name, 0 FROM research_projects rp JOIN projects p ON p.id=rp.project_id
+        UNION ALL
+        SELECT rm.research_id, 'module', m.name, 0 FROM research_modules rm JOIN modules m ON m.id=rm.module_id
+        UNION ALL
+        SELECT rf.research_id, 'feature', f.name, 0 FROM research_features rf JOIN features f ON f.id=rf.feature_id
+        UNION ALL
+        SELECT rt.research_id, 'task', t.name, 0 FROM research_tasks rt JOIN tasks t ON t.id=rt.task_id
+        UNION ALL
+        SELECT l.research_id, h.entity_type, h.old_name, 1 FROM (
+            SELECT research_id, 'project' AS kind, project_id AS entity_id FROM research_projects
+            UNION ALL SELECT research_id, 'module', module_id FROM research_modules
+            UNION ALL SELECT research_id, 'feature', feature_id FROM research_features
+            UNION ALL SELECT research_id, 'task', task_id FROM research_tasks
+        ) l JOIN name_history h ON h.entity_type=l.kind AND h.entity_id=l.entity_id
+        WHERE ?4
+    )";
+
+/// The FTS5 query for a search term: every word must appear, each matched
+/// as a prefix, so `jwt rot` finds "JWT rotation". None when the term has no
+/// word characters for the index to match.
+fn fts_query(term: &str) -> Option<String> {
+    let words: Vec<String> = term
+        .split_whitespace()
+        .filter(|w| w.chars().any(char::is_alphanumeric))
+        .map(|w| format!("\"{}\"*", w.replace('"', "\"\"")))
+        .collect();
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" "))
+    }
+}
+
+/// Whether the full-text index exists; it is missing when this SQLite was
+/// built without FTS5.
+fn has_fts(conn: &Connection) -> bool {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type='table' AND name='research_fts'",
+        [],
+        |r| r.get(0),
+    )
+    .unwrap_or(false)
+}
+
+/// bm25 weights for name, description, content, and source: a hit in the
+/// name says most about what a record is.
+const FTS_WEIGHTS: &str = "10.0, 5.0, 1.0, 2.0";
+
+/// One search hit.
+pub struct Found {
+    pub research: Research,
+    /// With `linked_to`: the links that matched, as `{type, name, former}`
+    /// objects; null otherwise.
+    pub matched_links: Value,
+    /// The former name the term matched, with `include_former_names`.
+    pub matched_former_name: Option<String>,
+    /// bm25 rank under the full-text index, lower is better; None for a
+    /// substring match.
+    pub rank: Option<f64>,
+}
+
+/// Research matching `term` in its text, linked to an entity whose name
+/// contains `linked_to`, and researched over `stale_days` ago; each filter
+/// left as None matches everything. Ranked hits come first. With a `page`
+/// other than the whole list, the total number of matches comes back too.
+#[allow(clippy::too_many_arguments)]
+pub fn matching(
+    conn: &Connection,
+    term: Option<&str>,
+    linked_to: Option<&str>,
+    stale_days: Option<i64>,
+    include_former_names: bool,
+    deep: bool,
+    include_archived: bool,
+    page: Page,
+) -> Result<(Vec<Found>, Option<i64>), Error> {
+    let pattern = term.map(|t| format!("%{}%", names::fold(t)));
+    let link_pattern = linked_to.map(|t| format!("%{}%", names::fold(t)));
+    let cutoff = stale_days.map(|days| format!("-{} days", days));
+    let query = term.and_then(fts_query).filter(|_| has_fts(conn));
+    // With the full-text index, a term matches records holding all its words,
+    // ranked by bm25 (lower is better); external bodies under --deep and
+    // former names still match by substring and rank after. Without it, every
+    // column is a substring match and results come in id order.
+    let (ranked_cte, rank, text_match) = if query.is_some() {
+        (
+            format!(
+                ", ranked(research_id, rank) AS (
+                    SELECT rowid, bm25(research_fts, {}) FROM research_fts WHERE research_fts MATCH ?6)",
+                FTS_WEIGHTS
+            ),
+            "(SELECT rank FROM ranked WHERE research_id=research.id)",
+            format!(
+                "id IN (SELECT research_id FROM ranked)
+                OR (?5 AND instr(content, '{}') = 1 AND fold(content_searchable(content, 1)) LIKE ?1)",
+                content::MARKER
+            ),
+        )
+    } else {
+        (
+            String::new(),
+            "NULL",
+            "fold(name) LIKE ?1 OR fold(description) LIKE ?1
+                OR fold(content_searchable(content, ?5)) LIKE ?1 OR fold(source) LIKE ?1"
+                .to_string(),
+        )
+    };
+    // Each filter is skipped when its parameter is NULL, so one statement serves
+    // every combination of text term, --linked-to, and --stale-days.
+    let sql = format!(
+        "{}{}
+         SELECT id, name, description, content_body(content), source, researched_at, created_at, updated_at,
+                (SELECT json_group_array(json_object('type', l.kind, 'name', l.name,
+                                                     'former', json(CASE l.former WHEN 1 THEN 'true' ELSE 'false' END)))
+                   FROM linked l WHERE l.research_id=research.id AND fold(l.name) LIKE ?2),
+                (SELECT h.old_name FROM name_history h
+                  WHERE ?4 AND ?1 IS NOT NULL AND h.entity_type='research' AND h.entity_id=research.id
+                    AND fold(h.old_name) LIKE ?1
+                  ORDER BY h.id LIMIT 1),
+                {} AS score, archived
+         FROM research
+         WHERE (?1 IS NULL OR {}
+                OR EXISTS (SELECT 1 FROM name_history h
+                           WHERE ?4 AND h.entity_type='research' AND h.entity_id=research.id
+                             AND fold(h.old_name) LIKE ?1))
+           AND (?2 IS NULL OR EXISTS (SELECT 1 FROM linked l
+                                      WHERE l.research_id=research.id AND fold(l.name) LIKE ?2))
+           AND (?3 IS NULL OR researched_at < strftime('%Y-%m-%dT%H:%M:%SZ', 'now', ?3))
+           {}
+         ORDER BY score IS NULL, score, id",
+        LINKED_CTE,
+        ranked_cte,
+        rank,
+        text_match,
+        if include_archived {
+            ""
+        } else {
+            "AND archived = 0"
         }
+    );
+    let all = params![
+        pattern,
+        link_pattern,
+        cutoff,
+        include_former_names,
+        deep,
+        query
+    ];
+    let bound: &[&dyn rusqlite::ToSql] = if query.is_some() { all } else { &all[..5] };
+    let total = if page.is_whole() {
+        None
+    } else {
+        Some(fields::count(conn, &sql, bound)?)
+    };
+    let mut stmt = conn.prepare(&format!("{}{}", sql, page.sql()))?;
+    let found = stmt
+        .query_map(bound, |r| {
+            let via: Option<String> = r.get(8)?;
+            Ok(Found {
+                research: Research {
+                    id: r.get(0)?,
+                    name: r.get(1)?,
+                    description: r.get(2)?,
+                    content: r.get(3)?,
+                    source: r.get(4)?,
+                    researched_at: r.get(5)?,
+                    created_at: r.get(6)?,
+                    updated_at: r.get(7)?,
+                    archived: r.get(11)?,
+                },
+                matched_links: via
+                    .and_then(|v| serde_json::from_str(&v).ok())
+                    .unwrap_or(Value::Null),
+                matched_former_name: r.get(9)?,
+                rank: r.get(10)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok((found, total))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn search(
+    conn: &Connection,
+    term: Option<&str>,
+    linked_to: Option<&str>,
+    stale_days: Option<i64>,
+    include_former_names: bool,
+    deep: bool,
+    include_archived: bool,
+    page: Page,
+    json: bool,
+) -> i32 {
+    let (records, total) = match matching(
+        conn,
+        term,
+        linked_to,
+        stale_days,
+        include_former_names,
+        deep,
+        include_archived,
+        page,
+    ) {
+        Ok(found) => found,
+        Err(e) => return output::fail(&e),
     };
-    // Reuse show's logic but only print links section
-    // Build link list same as show
-    struct Link {
-        kind: String,
-        entity_id: i64,
-        name: String,
-        context: String,
+    let total = total.filter(|_| fields::wants_total(page));
+
+    if records.is_empty() && fields::empty_in_prose(json, total) {
+        let what = match (term, linked_to) {
+            (Some(t), Some(l)) => format!("{} (linked to: {})", t, l),
+            (Some(t), None) => t.to_string(),
+            (None, Some(l)) => format!("linked to: {}", l),
+            (None, None) => String::new(),
+        };
+        output::print_empty(&format!("no research found matching: {}", what));
+        return 0;
+    }
+    if json {
+        let rows = records
+            .iter()
+            .map(|f| {
+                let mut v = research_to_json(&f.research);
+                if linked_to.is_some() {
+                    v["matched_links"] = f.matched_links.clone();
+                }
+                if term.is_some() {
+                    v["rank"] = json!(f.rank);
+                }
+                if let Some(old) = &f.matched_former_name {
+                    v["matched_former_name"] = json!(old);
+                }
+                v
+            })
+            .collect();
+        fields::print_list(SEARCH_COLUMNS, None, rows, total, json);
+    } else {
+        for f in &records {
+            let r = &f.research;
+            let date = timestamp::date(&r.researched_at);
+            let matched = match f.matched_links.as_array() {
+                Some(links) if linked_to.is_some() => {
+                    let names: Vec<String> = links
+                        .iter()
+                        .map(|l| {
+                            let note = if l["former"].as_bool().unwrap_or(false) {
+                                " (former name)"
+                            } else {
+                                ""
+                            };
+                            format!(
+                                "{} {}{}",
+                                l["type"].as_str().unwrap_or_default(),
+                                l["name"].as_str().unwrap_or_default(),
+                                note
+                            )
+                        })
+                        .collect();
+                    format!("  (linked: {})", names.join(", "))
+                }
+                _ => String::new(),
+            };
+            let former = f
+                .matched_former_name
+                .as_ref()
+                .map(|old| format!("  (matched former name '{}')", old))
+                .unwrap_or_default();
+            outln!(
+                "{:<5} {:<24} {}  {}{}{}{}",
+                resolve::short_ref("research", r.id),
+                r.name,
+                date,
+                r.description,
+                matched,
+                former,
+                archived_marker(r)
+            );
+        }
+        fields::print_more(page, records.len(), total);
     }
-    let mut lnks: Vec<Link> = Vec::new();
+    0
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT p.id, p.name FROM projects p JOIN research_projects rp ON rp.project_id=p.id WHERE rp.research_id=?1"
-    ).unwrap();
-    for row in stmt
-        .query_map(params![id], |row| {
-            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
+/// JSON shared by link and unlink results: both ids, both names, and the entity type.
+fn link_json(r: &Research, kind: &str, entity_id: i64, entity_name: &str) -> Value {
+    let mut v = json!({
+        "research": r.name,
+        "research_id": r.id,
+        "type": kind,
+    });
+    v[kind] = json!(entity_name);
+    v[format!("{}_id", kind)] = json!(entity_id);
+    v
+}
+
+/// Config key that, when true, links to finished work without a warning.
+const ALLOW_COMPLETE_KEY: &str = "allow_complete_links";
+
+/// Where the link target stands: `completed` or `active` for a project, the
+/// workflow state for a module, feature, or task.
+fn target_state(conn: &Connection, kind: &str, entity_id: i64) -> String {
+    let (_, _, entity_table) = bridge(kind);
+    let column = if kind == "project" {
+        "CASE completed WHEN 0 THEN 'active' ELSE 'completed' END"
+    } else {
+        "state"
+    };
+    conn.query_row(
+        &format!("SELECT {} FROM {} WHERE id=?1", column, entity_table),
+        params![entity_id],
+        |r| r.get(0),
+    )
+    .unwrap_or_default()
+}
+
+/// New research on finished work usually belongs to its follow-up. The
+/// warning for that, unless `force` or the config says it was meant.
+fn finished_warning(
+    r: &Research,
+    kind: &str,
+    entity_name: &str,
+    state: &str,
+    force: bool,
+) -> Option<String> {
+    if force
+        || !matches!(state, "Complete" | "completed")
+        || config::get_bool(ALLOW_COMPLETE_KEY, false)
     {
-        lnks.push(Link {
-            kind: "project".into(),
-            entity_id: row.0,
-            name: row.1,
-            context: String::new(),
-        });
+        return None;
     }
+    Some(format!(
+        "linking research {} to {} {}, which is {}; did you mean its follow-up? (pass --force if not)",
+        r.name, kind, entity_name, state
+    ))
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT m.id, m.name, p.name FROM modules m JOIN research_modules rm ON rm.module_id=m.id JOIN projects p ON p.id=m.project_id WHERE rm.research_id=?1"
-    ).unwrap();
-    for row in stmt
-        .query_map(params![id], |row| {
-            Ok((
-                row.get::<_, i64>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-            ))
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-    {
-        lnks.push(Link {
-            kind: "module".into(),
-            entity_id: row.0,
-            name: row.1,
-            context: row.2,
-        });
+/// What linking or unlinking research and an entity did.
+pub struct Linked {
+    pub research: Research,
+    /// The entity type: project, module, feature, or task.
+    pub kind: &'static str,
+    pub entity_id: i64,
+    pub entity_name: String,
+    /// Whether a link was created or removed; false when it already stood as
+    /// asked.
+    pub changed: bool,
+    /// Where the target stands, after a link: `completed` or `active` for a
+    /// project, the workflow state otherwise; empty after an unlink.
+    pub target_state: String,
+    /// The warning about linking to finished work, unless forced or allowed
+    /// by the config.
+    pub warning: Option<String>,
+}
+
+fn print_link_result(l: &Linked, entity_side: bool, json: bool) {
+    if let Some(w) = &l.warning {
+        eprintln!("warning: {}", w);
+    }
+    let (r, kind, entity_name) = (&l.research, l.kind, &l.entity_name);
+    if json {
+        let mut v = link_json(r, kind, l.entity_id, entity_name);
+        v["linked"] = json!(true);
+        v["created"] = json!(l.changed);
+        v["target_state"] = json!(l.target_state);
+        v["warning"] = json!(l.warning);
+        output::print_json(&v);
+    } else if entity_side {
+        let state = if l.changed {
+            "linked"
+        } else {
+            "already linked"
+        };
+        output::print_plain(&format!(
+            "{} {} {} → research {}",
+            state, kind, entity_name, r.name
+        ));
+    } else if l.changed {
+        output::print_plain(&format!(
+            "linked research {} → {}: {}",
+            r.name, kind, entity_name
+        ));
+    } else {
+        output::print_plain(&format!(
+            "already linked research {} → {}: {}",
+            r.name, kind, entity_name
+        ));
     }
+}
 
-    let mut stmt = conn
-        .prepare(
-            "SELECT f.id, f.name, m.name, p.name FROM features f
-         JOIN research_features rf ON rf.feature_id=f.id
-         JOIN modules m ON m.id=f.module_id
-         JOIN projects p ON p.id=m.project_id
-         WHERE rf.research_id=?1",
-        )
-        .unwrap();
-    for row in stmt
-        .query_map(params![id], |row| {
-            Ok((
-                row.get::<_, i64>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                row.get::<_, String>(3)?,
-            ))
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-    {
-        lnks.push(Link {
-            kind: "feature".into(),
-            entity_id: row.0,
-            name: row.1,
-            context: format!("{} > {}", row.3, row.2),
-        });
+fn print_unlink_result(l: &Linked, entity_side: bool, json: bool) {
+    let (r, kind, entity_name) = (&l.research, l.kind, &l.entity_name);
+    if json {
+        let mut v = link_json(r, kind, l.entity_id, entity_name);
+        v["unlinked"] = json!(true);
+        v["removed"] = json!(l.changed);
+        output::print_json(&v);
+    } else if entity_side {
+        let msg = if l.changed {
+            format!("unlinked {} {} from research {}", kind, entity_name, r.name)
+        } else {
+            format!(
+                "{} {} was not linked to research {}",
+                kind, entity_name, r.name
+            )
+        };
+        output::print_plain(&msg);
+    } else if l.changed {
+        output::print_plain(&format!(
+            "unlinked research {} from {}: {}",
+            r.name, kind, entity_name
+        ));
+    } else {
+        output::print_plain(&format!(
+            "research {} was not linked to {}: {}",
+            r.name, kind, entity_name
+        ));
+    }
+}
+
+/// Bridge table, its entity column, and the entity table for a linkable entity type.
+pub fn bridge(kind: &str) -> (&'static str, &'static str, &'static str) {
+    match kind {
+        "project" => ("research_projects", "project_id", "projects"),
+        "module" => ("research_modules", "module_id", "modules"),
+        "feature" => ("research_features", "feature_id", "features"),
+        "task" => ("research_tasks", "task_id", "tasks"),
+        _ => unreachable!("not a linkable entity type: {}", kind),
     }
+}
 
-    let mut stmt = conn
-        .prepare(
-            "SELECT t.id, t.name, f.name, m.name, p.name FROM tasks t
-         JOIN research_tasks rt ON rt.task_id=t.id
-         JOIN features f ON f.id=t.feature_id
-         JOIN modules m ON m.id=f.module_id
-         JOIN projects p ON p.id=m.project_id
-         WHERE rt.research_id=?1",
+/// Add (`link`) or remove a research link, leaving `target_state` and
+/// `warning` for the caller.
+fn set_link(
+    conn: &Connection,
+    kind: &str,
+    research_id: i64,
+    entity_id: i64,
+    link: bool,
+) -> Result<Linked, Error> {
+    let Some(&kind) = LINK_TYPES.iter().find(|k| **k == kind) else {
+        return Err(Error::Invalid(format!(
+            "cannot link research to {} (expected one of: {})",
+            kind,
+            LINK_TYPES.join(", ")
+        )));
+    };
+    let r = get(conn, research_id)?;
+    let (table, column, entity_table) = bridge(kind);
+    let name: String = conn
+        .query_row(
+            &format!("SELECT name FROM {} WHERE id=?1", entity_table),
+            params![entity_id],
+            |r| r.get(0),
         )
-        .unwrap();
-    for row in stmt
-        .query_map(params![id], |row| {
-            Ok((
-                row.get::<_, i64>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                row.get::<_, String>(3)?,
-                row.get::<_, String>(4)?,
-            ))
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-    {
-        lnks.push(Link {
-            kind: "task".into(),
-            entity_id: row.0,
-            name: row.1,
-            context: format!("{} > {} > {}", row.4, row.3, row.2),
+        .map_err(|e| Error::missing(e, kind, entity_id))?;
+    // INSERT OR IGNORE leaves an existing link alone; the change count tells them apart.
+    let sql = if link {
+        format!(
+            "INSERT OR IGNORE INTO {} (research_id, {}) VALUES (?1,?2)",
+            table, column
+        )
+    } else {
+        format!(
+            "DELETE FROM {} WHERE research_id=?1 AND {}=?2",
+            table, column
+        )
+    };
+    let changed = conn.execute(&sql, params![research_id, entity_id])? > 0;
+    Ok(Linked {
+        research: r,
+        kind,
+        entity_id,
+        entity_name: name,
+        changed,
+        target_state: String::new(),
+        warning: None,
+    })
+}
+
+/// Link research to a project, module, feature, or task (`kind`). A finished
+/// target comes back with a warning unless `force`.
+pub fn link_to(
+    conn: &Connection,
+    kind: &str,
+    research_id: i64,
+    entity_id: i64,
+    force: bool,
+) -> Result<Linked, Error> {
+    let mut l = set_link(conn, kind, research_id, entity_id, true)?;
+    l.target_state = target_state(conn, l.kind, entity_id);
+    l.warning = finished_warning(&l.research, l.kind, &l.entity_name, &l.target_state, force);
+    Ok(l)
+}
+
+/// Remove a link between research and a project, module, feature, or task
+/// (`kind`).
+pub fn unlink_from(
+    conn: &Connection,
+    kind: &str,
+    research_id: i64,
+    entity_id: i64,
+) -> Result<Linked, Error> {
+    set_link(conn, kind, research_id, entity_id, false)
+}
+
+/// Link research to a project, module, feature, or task (`kind`). A finished
+/// target gets a warning on stderr unless `force`.
+pub fn link(
+    conn: &Connection,
+    kind: &str,
+    research_id: i64,
+    entity_id: i64,
+    force: bool,
+    json: bool,
+) -> i32 {
+    match link_to(conn, kind, research_id, entity_id, force) {
+        Ok(l) => {
+            print_link_result(&l, false, json);
+            0
+        }
+        Err(e) => output::fail(&e),
+    }
+}
+
+/// Remove a link between research and a project, module, feature, or task (`kind`).
+pub fn unlink(conn: &Connection, kind: &str, research_id: i64, entity_id: i64, json: bool) -> i32 {
+    match unlink_from(conn, kind, research_id, entity_id) {
+        Ok(l) => {
+            print_unlink_result(&l, false, json);
+            0
+        }
+        Err(e) => output::fail(&e),
+    }
+}
+
+/// Link or unlink research from the entity's side: same effect, JSON, and
+/// finished-target warning as `link`/`unlink`, with plain output phrased
+/// from the entity's perspective.
+pub fn entity_link(
+    conn: &Connection,
+    kind: &str,
+    entity_id: i64,
+    research_id: i64,
+    link: bool,
+    force: bool,
+    json: bool,
+) -> i32 {
+    let result = if link {
+        link_to(conn, kind, research_id, entity_id, force)
+    } else {
+        unlink_from(conn, kind, research_id, entity_id)
+    };
+    match result {
+        Ok(l) if link => {
+            print_link_result(&l, true, json);
+            0
+        }
+        Ok(l) => {
+            print_unlink_result(&l, true, json);
+            0
+        }
+        Err(e) => output::fail(&e),
+    }
+}
+
+/// Research linked directly to one project, module, feature, or task.
+/// Research linked only to an ancestor is not included.
+pub fn entity_research(
+    conn: &Connection,
+    kind: &'static str,
+    entity_id: i64,
+    include_archived: bool,
+    json: bool,
+) -> i32 {
+    let (table, column, entity_table) = bridge(kind);
+    let name: String = match conn.query_row(
+        &format!("SELECT name FROM {} WHERE id=?1", entity_table),
+        params![entity_id],
+        |r| r.get(0),
+    ) {
+        Ok(n) => n,
+        Err(_) => {
+            return output::fail(&Error::not_found(
+                kind,
+                format!("{} not found: {}", kind, entity_id),
+            ));
+        }
+    };
+    let linked = conn
+        .prepare(&format!(
+            "SELECT r.id, r.name, r.description, r.archived FROM research r
+             JOIN {} l ON l.research_id=r.id
+             WHERE l.{}=?1 AND (?2 OR r.archived = 0) ORDER BY r.id",
+            table, column
+        ))
+        .and_then(|mut stmt| {
+            let rows = stmt.query_map(params![entity_id, include_archived], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+            })?;
+            rows.collect()
         });
+    let research: Vec<(i64, String, String, bool)> = match linked {
+        Ok(r) => r,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+
+    if json {
+        let mut v = json!({ "type": kind });
+        v[kind] = json!(name);
+        v[format!("{}_id", kind)] = json!(entity_id);
+        v["research"] = Value::Array(
+            research
+                .iter()
+                .map(|(id, name, desc, archived)| {
+                    json!({
+                        "id": id, "ref": resolve::short_ref("research", *id),
+                        "name": name, "description": desc, "archived": archived,
+                    })
+                })
+                .collect(),
+        );
+        output::print_json(&v);
+    } else if research.is_empty() {
+        output::print_empty(&format!("no research linked to {} {}", kind, name));
+    } else {
+        for (rid, rname, rdesc, archived) in &research {
+            outln!(
+                "{:<5} {:<24} {}{}",
+                resolve::short_ref("research", *rid),
+                rname,
+                rdesc,
+                if *archived { "  [archived]" } else { "" }
+            );
+        }
     }
+    0
+}
 
-    if lnks.is_empty() {
-        output::print_plain("no links found for this research");
-        return 0;
+/// Research linked to a project, module, or feature or to anything beneath it.
+pub const SUBTREE_RESEARCH_SQL_PROJECT: &str = "
+    SELECT research_id FROM research_projects WHERE project_id=?1
+    UNION SELECT rm.research_id FROM research_modules rm
+        JOIN modules m ON m.id=rm.module_id WHERE m.project_id=?1
+    UNION SELECT rf.research_id FROM research_features rf
+        JOIN features f ON f.id=rf.feature_id JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1
+    UNION SELECT rt.research_id FROM research_tasks rt
+        JOIN tasks t ON t.id=rt.task_id JOIN features f ON f.id=t.feature_id
+        JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1";
+const SUBTREE_RESEARCH_SQL_MODULE: &str = "
+    SELECT research_id FROM research_modules WHERE module_id=?1
+    UNION SELECT rf.research_id FROM research_features rf
+        JOIN features f ON f.id=rf.feature_id WHERE f.module_id=?1
+    UNION SELECT rt.research_id FROM research_tasks rt
+        JOIN tasks t ON t.id=rt.task_id JOIN features f ON f.id=t.feature_id WHERE f.module_id=?1";
+const SUBTREE_RESEARCH_SQL_FEATURE: &str = "
+    SELECT research_id FROM research_features WHERE feature_id=?1
+    UNION SELECT rt.research_id FROM research_tasks rt
+        JOIN tasks t ON t.id=rt.task_id WHERE t.feature_id=?1";
+
+/// Before a module or feature (`kind`) is removed, link every research record
+/// linked to it or to any descendant to its parent: the project for a module,
+/// the module for a feature. Existing parent links are kept as they are.
+/// Returns (links created, research already linked to the parent). Runs on the
+/// caller's transaction so the relink and the delete land together.
+pub fn relink_to_parent(
+    conn: &Connection,
+    kind: &str,
+    id: i64,
+    parent_id: i64,
+) -> rusqlite::Result<(usize, usize)> {
+    let (subtree, parent_kind) = match kind {
+        "module" => (SUBTREE_RESEARCH_SQL_MODULE, "project"),
+        _ => (SUBTREE_RESEARCH_SQL_FEATURE, "module"),
+    };
+    let (table, column, _) = bridge(parent_kind);
+    let research: Vec<i64> = {
+        let mut stmt = conn.prepare(subtree)?;
+        let rows = stmt.query_map(params![id], |r| r.get(0))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    let mut insert = conn.prepare(&format!(
+        "INSERT OR IGNORE INTO {} (research_id, {}) VALUES (?1,?2)",
+        table, column
+    ))?;
+    let mut created = 0;
+    for rid in &research {
+        created += insert.execute(params![rid, parent_id])?;
     }
+    Ok((created, research.len() - created))
+}
+
+/// Types a research record can be linked to, in hierarchy order.
+const LINK_TYPES: [&str; 4] = ["project", "module", "feature", "task"];
+
+/// A record's links in breadcrumb order, optionally only one `kind`, paged
+/// with `limit` and `offset`. With `dedupe`, links made redundant by a linked
+/// descendant are dropped before paging.
+#[allow(clippy::too_many_arguments)]
+pub fn links(
+    conn: &Connection,
+    id: i64,
+    dedupe: bool,
+    kind: Option<&str>,
+    limit: Option<i64>,
+    offset: i64,
+    json: bool,
+) -> i32 {
+    if let Some(k) = kind {
+        if !LINK_TYPES.contains(&k) {
+            output::err(&format!(
+                "unknown type '{}'; must be one of: {}",
+                k,
+                LINK_TYPES.join(", ")
+            ));
+            return 1;
+        }
+    }
+    match get(conn, id) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
+    };
+    // With --dedupe, ancestor links fold into the most specific linked descendant.
+    let redundant = if dedupe {
+        match redundant_links(conn, id) {
+            Ok(r) => r,
+            Err(e) => {
+                output::err(&e.to_string());
+                return 2;
+            }
+        }
+    } else {
+        Vec::new()
+    };
+    let is_redundant = |l: &Link| {
+        redundant
+            .iter()
+            .any(|(a, _)| a.kind == l.kind && a.id == l.entity_id)
+    };
+    // Redundant links are only known once every link is listed, so --dedupe
+    // pages after filtering; otherwise the query does it.
+    let paged = if dedupe {
+        links_of(conn, id, kind, None, 0).map(|all| {
+            let all: Vec<Link> = all.into_iter().filter(|l| !is_redundant(l)).collect();
+            let total = all.len() as i64;
+            let page = all
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit.map_or(usize::MAX, |n| n as usize))
+                .collect();
+            (page, total)
+        })
+    } else {
+        link_counts(conn, id).and_then(|counts| {
+            let total = counts
+                .iter()
+                .filter(|(k, _)| kind.is_none_or(|want| want == *k))
+                .map(|(_, n)| n)
+                .sum::<i64>();
+            Ok((links_of(conn, id, kind, limit, offset)?, total))
+        })
+    };
+    let (lnks, total) = match paged {
+        Ok(p) => p,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+
+    let collapsed = |l: &Link| -> Vec<&Crumb> {
+        redundant
+            .iter()
+            .filter(|(_, by)| by.kind == l.kind && by.id == l.entity_id)
+            .map(|(a, _)| a)
+            .collect()
+    };
     if json {
         output::print_json(&Value::Array(
             lnks.iter()
                 .map(|l| {
-                    json!({
-                        "type": l.kind, "id": l.entity_id, "name": l.name, "context": l.context
-                    })
+                    let mut v = l.to_json();
+                    if dedupe {
+                        v["collapsed"] = Value::Array(
+                            collapsed(l)
+                                .iter()
+                                .map(|a| json!({"type": a.kind, "id": a.id, "name": a.name}))
+                                .collect(),
+                        );
+                    }
+                    v
                 })
                 .collect(),
         ));
-    } else {
-        for l in &lnks {
-            if l.context.is_empty() {
-                println!("  {:<10} {:<4} {}", l.kind, l.entity_id, l.name);
-            } else {
-                println!(
-                    "  {:<10} {:<4} {:<24} ({})",
-                    l.kind, l.entity_id, l.name, l.context
-                );
-            }
+        return 0;
+    }
+    if lnks.is_empty() {
+        if total == 0 {
+            output::print_plain("no links found for this research");
+        } else {
+            output::print_plain(&format!("no links at offset {} ({} in all)", offset, total));
         }
+        return 0;
+    }
+    for l in &lnks {
+        let folded = collapsed(l)
+            .iter()
+            .map(|a| format!("{} {}", a.kind, a.name))
+            .collect::<Vec<_>>();
+        let suffix = if folded.is_empty() {
+            String::new()
+        } else {
+            format!("  [also linked: {}]", folded.join(", "))
+        };
+        l.print("  ", &suffix);
+    }
+    if (lnks.len() as i64) < total {
+        output::print_plain(&format!(
+            "showing {}-{} of {} link(s)",
+            offset + 1,
+            offset + lnks.len() as i64,
+            total
+        ));
     }
     0
 }
 
-pub fn remove(conn: &Connection, id: i64, json: bool) -> i32 {
-    let r = match load(conn, id) {
+/// Every (kind, id) the research record is linked to directly.
+fn linked_entities(conn: &Connection, id: i64) -> rusqlite::Result<Vec<(String, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT 'project', project_id FROM research_projects WHERE research_id=?1
+         UNION ALL SELECT 'module', module_id FROM research_modules WHERE research_id=?1
+         UNION ALL SELECT 'feature', feature_id FROM research_features WHERE research_id=?1
+         UNION ALL SELECT 'task', task_id FROM research_tasks WHERE research_id=?1",
+    )?;
+    let linked = stmt.query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?)))?;
+    linked.collect()
+}
+
+/// Links to an entity that is an ancestor of another linked entity, each paired
+/// with the most specific linked descendant that makes it redundant.
+fn redundant_links(conn: &Connection, id: i64) -> rusqlite::Result<Vec<(Crumb, Crumb)>> {
+    let linked = linked_entities(conn, id)?;
+    let mut chains: Vec<Vec<Crumb>> = Vec::new();
+    for (kind, eid) in &linked {
+        let chain = ancestry::chain(conn, kind, *eid)?;
+        if !chain.is_empty() {
+            chains.push(chain);
+        }
+    }
+    // Deepest chains first, so an ancestor is credited to its most specific descendant.
+    chains.sort_by_key(|c| std::cmp::Reverse(c.len()));
+    let mut redundant: Vec<(Crumb, Crumb)> = Vec::new();
+    for c in &chains {
+        let entity = &c[c.len() - 1];
+        for a in &c[..c.len() - 1] {
+            let is_linked = linked.iter().any(|(k, i)| k == a.kind && *i == a.id);
+            if is_linked && !redundant.iter().any(|(r, _)| r == a) {
+                redundant.push((a.clone(), entity.clone()));
+            }
+        }
+    }
+    Ok(redundant)
+}
+
+/// Remove links made redundant by a link to one of the entity's descendants.
+/// Without `yes`, an interactive terminal is asked to confirm; anywhere else
+/// the removals are only previewed.
+pub fn normalize_links(conn: &Connection, id: i64, yes: bool, json: bool) -> i32 {
+    let r = match get(conn, id) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
+    };
+    let redundant = match redundant_links(conn, id) {
         Ok(r) => r,
         Err(e) => {
-            output::err(&e);
-            return 1;
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let mut crumbs: HashMap<(&str, i64), String> = HashMap::new();
+    for (_, by) in &redundant {
+        match ancestry::breadcrumb(conn, by.kind, by.id) {
+            Ok(c) => {
+                crumbs.insert((by.kind, by.id), c);
+            }
+            Err(e) => return output::fail(&e.into()),
         }
+    }
+    let preview = |a: &Crumb, by: &Crumb| {
+        format!(
+            "  {:<10} {:<5} {:<24} covered by {} {} ({})",
+            a.kind,
+            resolve::short_ref(a.kind, a.id),
+            a.name,
+            by.kind,
+            by.name,
+            crumbs[&(by.kind, by.id)]
+        )
     };
-    conn.execute("DELETE FROM research WHERE id=?1", params![id])
-        .unwrap();
+    if redundant.is_empty() {
+        if json {
+            output::print_json(&json!({"research": r.name, "removed": []}));
+        } else {
+            output::print_plain(&format!("no redundant links for research {}", r.name));
+        }
+        return 0;
+    }
+
+    let confirmed = yes || (!json && confirm(&r.name, &redundant, &preview));
+    if !confirmed {
+        if json {
+            output::print_json(&json!({
+                "research": r.name,
+                "dry_run": true,
+                "redundant": redundant.iter().map(|(a, by)| json!({
+                    "type": a.kind, "id": a.id, "name": a.name,
+                    "covered_by": {"type": by.kind, "id": by.id, "name": by.name},
+                })).collect::<Vec<_>>(),
+            }));
+        } else if !std::io::stdin().is_terminal() {
+            output::print_plain(&format!(
+                "would remove {} redundant link(s) from research {}:",
+                redundant.len(),
+                r.name
+            ));
+            for (a, by) in &redundant {
+                outln!("{}", preview(a, by));
+            }
+            output::print_plain("pass --yes to remove them");
+        }
+        return 0;
+    }
+
+    let tx = match db::write_tx(conn) {
+        Ok(tx) => tx,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    for (a, _) in &redundant {
+        let (table, column) = match a.kind {
+            "project" => ("research_projects", "project_id"),
+            "module" => ("research_modules", "module_id"),
+            "feature" => ("research_features", "feature_id"),
+            _ => ("research_tasks", "task_id"),
+        };
+        if let Err(e) = tx.execute(
+            &format!(
+                "DELETE FROM {} WHERE research_id=?1 AND {}=?2",
+                table, column
+            ),
+            params![id, a.id],
+        ) {
+            output::err(&e.to_string());
+            return 2;
+        }
+    }
+    if let Err(e) = tx.commit() {
+        output::err(&e.to_string());
+        return 2;
+    }
     if json {
-        output::print_json(&json!({"deleted": true, "id": id}));
+        output::print_json(&json!({
+            "research": r.name,
+            "removed": redundant.iter().map(|(a, _)| json!({
+                "type": a.kind, "id": a.id, "name": a.name,
+            })).collect::<Vec<_>>(),
+        }));
     } else {
-        output::print_plain(&format!("removed research {}: {}", id, r.name));
+        output::print_plain(&format!(
+            "removed {} redundant link(s) from research {}",
+            redundant.len(),
+            r.name
+        ));
+        for (a, by) in &redundant {
+            outln!("{}", preview(a, by));
+        }
+    }
+    0
+}
+
+/// Ask on the terminal before removing links. Non-interactive input never confirms.
+fn confirm(
+    name: &str,
+    redundant: &[(Crumb, Crumb)],
+    preview: &dyn Fn(&Crumb, &Crumb) -> String,
+) -> bool {
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+    outln!(
+        "research {} has {} redundant link(s):",
+        name,
+        redundant.len()
+    );
+    for (a, by) in redundant {
+        outln!("{}", preview(a, by));
+    }
+    out!("remove them? [y/N] ");
+    std::io::stdout().flush().ok();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok();
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Delete a research record, and its copied attachments and content file
+/// unless `soft` puts it in the trash, where they wait for a purge. Files
+/// that could not be deleted come back as warnings.
+pub fn delete(conn: &Connection, id: i64, soft: bool) -> Result<Removed<Research>, Error> {
+    let r = get(conn, id)?;
+    let tx = db::write_tx(conn)?;
+    if soft {
+        trash::bury(&tx, "research", id)?;
+    }
+    tx.execute("DELETE FROM research WHERE id=?1", params![id])?;
+    tx.commit()?;
+    let mut warnings = Vec::new();
+    // In the trash, the files stay for a restore.
+    if !soft {
+        if let Err(e) = attachment::remove_copies(id) {
+            warnings.push(format!(
+                "could not delete copied attachments for research {}: {}",
+                r.name, e
+            ));
+        }
+        if let Err(e) = content::remove(conn, id) {
+            warnings.push(format!(
+                "could not delete the content file for research {}: {}",
+                r.name, e
+            ));
+        }
+    }
+    Ok(Removed {
+        entity: r,
+        trashed: soft,
+        relinked: None,
+        warnings,
+    })
+}
+
+pub fn remove(conn: &Connection, id: i64, soft: bool, json: bool) -> i32 {
+    let removed = match delete(conn, id, soft) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
+    };
+    for w in &removed.warnings {
+        eprintln!("warning: {}", w);
+    }
+    if json {
+        output::print_json(&json!({"deleted": true, "id": id, "trashed": soft}));
+    } else {
+        output::print_plain(&format!("removed research {}: {}", id, removed.entity.name));
+        if soft {
+            output::print_plain(&trash::buried_note("research", id));
+        }
     }
     0
 }
@@ -0,0 +1,199 @@
+/// Named sections of a research record's content. A section starts at a line
+/// `## <name>` whose name is a valid section slug, outside fenced code, and
+/// runs to the next such line. Other `##` headings (`## Summary`, `## Open
+/// questions`) are ordinary text inside whatever surrounds them, so content
+/// written without sections parses as all preamble and is never rewritten.
+pub struct Sections {
+    /// Text before the first section, exactly as stored.
+    preamble: String,
+    sections: Vec<Section>,
+    /// A code fence was still open at the end.
+    unclosed_fence: bool,
+}
+
+struct Section {
+    name: String,
+    /// The heading line, newline included, exactly as stored.
+    heading: String,
+    body: String,
+}
+
+/// Longest section name accepted.
+const MAX_NAME: usize = 64;
+
+/// Check a section name: lowercase letters and digits in runs joined by
+/// single hyphens, e.g. `benchmarks` or `open-questions`.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let ok = !name.is_empty()
+        && name.len() <= MAX_NAME
+        && name.split('-').all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        });
+    if ok {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid section name '{}': use up to {} lowercase letters, digits, and single hyphens, e.g. benchmarks or open-questions",
+            name, MAX_NAME
+        ))
+    }
+}
+
+/// The fence a line opens or closes: its character and run length.
+fn fence(line: &str) -> Option<(char, usize)> {
+    let t = line.trim_start();
+    let c = t.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let run = t.chars().take_while(|x| *x == c).count();
+    (run >= 3).then_some((c, run))
+}
+
+/// The section a line starts, if it is a `## <name>` heading with a valid name.
+fn heading(line: &str) -> Option<&str> {
+    let name = line.strip_prefix("## ")?.trim_end();
+    validate_name(name).ok().map(|_| name)
+}
+
+impl Sections {
+    pub fn parse(content: &str) -> Sections {
+        let mut s = Sections {
+            preamble: String::new(),
+            sections: Vec::new(),
+            unclosed_fence: false,
+        };
+        let mut open: Option<(char, usize)> = None;
+        for line in content.split_inclusive('\n') {
+            if let Some((c, run)) = fence(line) {
+                open = match open {
+                    None => Some((c, run)),
+                    // A closing fence is the same character, at least as long.
+                    Some((oc, orun)) if oc == c && run >= orun => None,
+                    still => still,
+                };
+            } else if open.is_none() {
+                if let Some(name) = heading(line) {
+                    s.sections.push(Section {
+                        name: name.to_string(),
+                        heading: line.to_string(),
+                        body: String::new(),
+                    });
+                    continue;
+                }
+            }
+            match s.sections.last_mut() {
+                Some(sec) => sec.body.push_str(line),
+                None => s.preamble.push_str(line),
+            }
+        }
+        s.unclosed_fence = open.is_some();
+        s
+    }
+
+    /// The content again; `render(parse(x)) == x` for any `x`.
+    pub fn render(&self) -> String {
+        let mut out = self.preamble.clone();
+        for sec in &self.sections {
+            out.push_str(&sec.heading);
+            out.push_str(&sec.body);
+        }
+        out
+    }
+
+    /// Section names in the order they appear.
+    pub fn names(&self) -> Vec<&str> {
+        self.sections.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    /// The text of a section, without the blank lines around it. When a name
+    /// appears twice, the first one counts.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.sections
+            .iter()
+            .find(|s| s.name == name)
+            .map(|s| trim_blank_lines(&s.body))
+    }
+
+    /// Replace the text of a section, or add it at the end. Returns whether it
+    /// was already there. `text` must have passed `check_text`.
+    pub fn set(&mut self, name: &str, text: &str) -> bool {
+        let text = trim_blank_lines(text);
+        let last = self.sections.len().saturating_sub(1);
+        if let Some(i) = self.sections.iter().position(|s| s.name == name) {
+            // Keep a blank line before the next heading.
+            let gap = if i == last { "" } else { "\n" };
+            self.sections[i].body = format!("\n{}\n{}", text, gap);
+            return true;
+        }
+        let before = match self.sections.last_mut() {
+            Some(sec) => &mut sec.body,
+            None => &mut self.preamble,
+        };
+        if !before.trim().is_empty() {
+            let trimmed = before.trim_end().len();
+            before.truncate(trimmed);
+            before.push_str("\n\n");
+        } else {
+            before.clear();
+        }
+        self.sections.push(Section {
+            name: name.to_string(),
+            heading: format!("## {}\n", name),
+            body: format!("\n{}\n", text),
+        });
+        false
+    }
+
+    /// Drop a section, heading and text. Returns whether it was there.
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.sections.iter().position(|s| s.name == name) {
+            Some(i) => {
+                self.sections.remove(i);
+                if i == self.sections.len() {
+                    // The new end loses the blank line that led into the old one.
+                    let end = match self.sections.last_mut() {
+                        Some(sec) => &mut sec.body,
+                        None => &mut self.preamble,
+                    };
+                    let trimmed = end.trim_end().len();
+                    if trimmed > 0 {
+                        end.truncate(trimmed);
+                        end.push('\n');
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Text for a section must stay one section when stored: no line of its own
+/// that reads as a section heading, and no code fence left open to swallow
+/// the headings after it.
+pub fn check_text(text: &str) -> Result<(), String> {
+    let parsed = Sections::parse(text);
+    if let Some(sec) = parsed.sections.first() {
+        return Err(format!(
+            "the text has a line '{}' that would start a section of its own; use ### for headings inside a section",
+            sec.heading.trim_end()
+        ));
+    }
+    if parsed.unclosed_fence {
+        return Err(
+            "the text leaves a code fence open; close it so the sections after it still parse"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+fn trim_blank_lines(text: &str) -> &str {
+    let start = text
+        .split_inclusive('\n')
+        .take_while(|l| l.trim().is_empty())
+        .map(str::len)
+        .sum::<usize>();
+    text[start..].trim_end()
+}
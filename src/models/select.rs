@@ -0,0 +1,309 @@
+use crate::models::ancestry;
+use crate::models::research;
+use crate::output;
+use crate::resolve;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+// Relevance weights per query term: a hit in the name says most about what a
+// record is for, the description next, and each mention in the content a
+// little, capped so one long record cannot drown out the rest.
+const WEIGHT_NAME: i64 = 3;
+const WEIGHT_DESCRIPTION: i64 = 2;
+const WEIGHT_CONTENT: i64 = 1;
+const MAX_CONTENT_HITS: i64 = 5;
+
+/// Below this many characters of remaining budget a truncated record is more
+/// noise than context, so it is skipped instead.
+const MIN_TRUNCATED_CHARS: usize = 80;
+const ELLIPSIS: &str = "…";
+
+/// Words too common to say anything about relevance.
+const STOPWORDS: [&str; 24] = [
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "how", "in", "into", "is",
+    "it", "of", "on", "or", "that", "the", "to", "we", "what", "with",
+];
+
+/// A research record under consideration, with what is needed to score and pack it.
+pub struct Candidate {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    pub content: String,
+    pub updated_at: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Decision {
+    /// The full content fit.
+    Included,
+    /// The content did not fit but the description did.
+    Summary,
+    /// Cut to the remaining budget.
+    Truncated,
+    Skipped,
+}
+
+impl Decision {
+    fn as_str(self) -> &'static str {
+        match self {
+            Decision::Included => "included",
+            Decision::Summary => "summary",
+            Decision::Truncated => "truncated",
+            Decision::Skipped => "skipped",
+        }
+    }
+}
+
+/// What packing decided for one candidate. `text` is what went into the
+/// budget, empty when skipped.
+pub struct Selection<'a> {
+    pub candidate: &'a Candidate,
+    pub score: i64,
+    pub decision: Decision,
+    pub reason: Option<&'static str>,
+    pub text: String,
+}
+
+/// Lowercased, de-duplicated query terms of two or more characters, without stopwords.
+pub fn terms(query: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .map(|t| t.to_lowercase())
+        .filter(|t| t.chars().count() >= 2 && !STOPWORDS.contains(&t.as_str()))
+        .filter(|t| seen.insert(t.clone()))
+        .collect()
+}
+
+/// Term-overlap relevance of a record to the query terms; 0 means no overlap.
+pub fn relevance(terms: &[String], c: &Candidate) -> i64 {
+    let (name, description, content) = (
+        c.name.to_lowercase(),
+        c.description.to_lowercase(),
+        c.content.to_lowercase(),
+    );
+    terms
+        .iter()
+        .map(|t| {
+            let mut s = 0;
+            if name.contains(t.as_str()) {
+                s += WEIGHT_NAME;
+            }
+            if description.contains(t.as_str()) {
+                s += WEIGHT_DESCRIPTION;
+            }
+            s + WEIGHT_CONTENT * (content.matches(t.as_str()).count() as i64).min(MAX_CONTENT_HITS)
+        })
+        .sum()
+}
+
+fn char_len(s: &str) -> usize {
+    s.chars().count()
+}
+
+/// Cut `s` to at most `max` characters, marking the cut with an ellipsis.
+fn truncate(s: &str, max: usize) -> String {
+    let keep = max.saturating_sub(char_len(ELLIPSIS));
+    let mut out: String = s.chars().take(keep).collect();
+    out.push_str(ELLIPSIS);
+    out
+}
+
+/// Score every candidate against the query and greedily pack the best into
+/// `max_chars`, highest score first (ties to the most recently updated). Each
+/// record gets its full content if that fits, else its description, else as
+/// much content as the remaining budget allows; records with no overlap or no
+/// room left are skipped. Every candidate appears in the result, in score order.
+pub fn pack<'a>(query: &str, candidates: &'a [Candidate], max_chars: usize) -> Vec<Selection<'a>> {
+    let terms = terms(query);
+    let mut scored: Vec<(i64, &Candidate)> = candidates
+        .iter()
+        .map(|c| (relevance(&terms, c), c))
+        .collect();
+    // Canonical timestamps compare as strings, so recency is a plain reverse sort.
+    scored.sort_by(|(sa, a), (sb, b)| {
+        sb.cmp(sa)
+            .then_with(|| b.updated_at.cmp(&a.updated_at))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
+    let mut used = 0;
+    scored
+        .into_iter()
+        .map(|(score, c)| {
+            let remaining = max_chars - used;
+            let body = if c.content.trim().is_empty() {
+                &c.description
+            } else {
+                &c.content
+            };
+            let (decision, reason, text) = if score == 0 {
+                (
+                    Decision::Skipped,
+                    Some("no overlap with query"),
+                    String::new(),
+                )
+            } else if body.trim().is_empty() {
+                (Decision::Skipped, Some("no content"), String::new())
+            } else if char_len(body) <= remaining {
+                (Decision::Included, None, body.clone())
+            } else if !c.description.trim().is_empty() && char_len(&c.description) <= remaining {
+                (Decision::Summary, None, c.description.clone())
+            } else if remaining >= MIN_TRUNCATED_CHARS {
+                (Decision::Truncated, None, truncate(body, remaining))
+            } else {
+                (Decision::Skipped, Some("budget exhausted"), String::new())
+            };
+            used += char_len(&text);
+            Selection {
+                candidate: c,
+                score,
+                decision,
+                reason,
+                text,
+            }
+        })
+        .collect()
+}
+
+/// Research linked to the entity or to any of its ancestors; every record when
+/// no entity is given. Archived records only with `include_archived`.
+fn candidates(
+    conn: &Connection,
+    scope: Option<(&str, i64)>,
+    include_archived: bool,
+) -> rusqlite::Result<Vec<Candidate>> {
+    let linked: Option<HashSet<i64>> = match scope {
+        None => None,
+        Some((kind, id)) => {
+            let mut ids = HashSet::new();
+            for crumb in ancestry::chain(conn, kind, id)? {
+                let (table, column, _) = research::bridge(crumb.kind);
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT research_id FROM {} WHERE {}=?1",
+                    table, column
+                ))?;
+                for rid in stmt.query_map(params![crumb.id], |r| r.get::<_, i64>(0))? {
+                    ids.insert(rid?);
+                }
+            }
+            Some(ids)
+        }
+    };
+    let mut stmt = conn.prepare(
+        "SELECT id, name, description, content_body(content), updated_at FROM research
+         WHERE ?1 OR archived = 0 ORDER BY id",
+    )?;
+    let rows = stmt.query_map(params![include_archived], |r| {
+        Ok(Candidate {
+            id: r.get(0)?,
+            name: r.get(1)?,
+            description: r.get(2)?,
+            content: r.get(3)?,
+            updated_at: r.get(4)?,
+        })
+    })?;
+    let mut out = Vec::new();
+    for c in rows {
+        let c = c?;
+        if linked.as_ref().is_none_or(|ids| ids.contains(&c.id)) {
+            out.push(c);
+        }
+    }
+    Ok(out)
+}
+
+/// Assemble research relevant to `query` within a character budget, from the
+/// records linked to `scope` and its ancestors (or all records), and report
+/// what was included, cut down, or left out.
+pub fn select(
+    conn: &Connection,
+    query: &str,
+    max_chars: usize,
+    scope: Option<(&str, i64)>,
+    include_archived: bool,
+    json: bool,
+) -> i32 {
+    if terms(query).is_empty() {
+        output::err("query has no searchable terms");
+        return 1;
+    }
+    let candidates = match candidates(conn, scope, include_archived) {
+        Ok(c) => c,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let crumb = match scope {
+        Some((kind, id)) => match ancestry::breadcrumb(conn, kind, id) {
+            Ok(c) => Some(c),
+            Err(e) => return output::fail(&e.into()),
+        },
+        None => None,
+    };
+    let selections = pack(query, &candidates, max_chars);
+    let used: usize = selections.iter().map(|s| char_len(&s.text)).sum();
+    let packed = selections
+        .iter()
+        .filter(|s| s.decision != Decision::Skipped)
+        .count();
+
+    if json {
+        output::print_json(&json!({
+            "query": query,
+            "max_chars": max_chars,
+            "used_chars": used,
+            "scope": scope.map(|(kind, id)| json!({
+                "type": kind, "id": id, "breadcrumb": crumb,
+            })),
+            "records": selections.iter().map(|s| json!({
+                "id": s.candidate.id,
+                "ref": resolve::short_ref("research", s.candidate.id),
+                "name": s.candidate.name,
+                "score": s.score,
+                "decision": s.decision.as_str(),
+                "reason": s.reason,
+                "chars": char_len(&s.text),
+                "full_chars": char_len(&s.candidate.content),
+                "text": s.text,
+            })).collect::<Vec<Value>>(),
+        }));
+        return 0;
+    }
+
+    for s in selections
+        .iter()
+        .filter(|s| s.decision != Decision::Skipped)
+    {
+        outln!(
+            "== {} {} ({}, score {}) ==",
+            resolve::short_ref("research", s.candidate.id),
+            s.candidate.name,
+            s.decision.as_str(),
+            s.score
+        );
+        outln!("{}", s.text);
+        outln!();
+    }
+    output::print_plain(&format!(
+        "packed {} of {} research record(s) into {}/{} chars",
+        packed,
+        selections.len(),
+        used,
+        max_chars
+    ));
+    for s in selections
+        .iter()
+        .filter(|s| s.decision == Decision::Skipped)
+    {
+        outln!(
+            "  skipped {} ({})",
+            s.candidate.name,
+            s.reason.unwrap_or_default()
+        );
+    }
+    0
+}
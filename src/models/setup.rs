@@ -0,0 +1,658 @@
+use crate::changes;
+use crate::config;
+use crate::db;
+use crate::error::Error;
+use crate::models::{export, import};
+use crate::output;
+use crate::skill;
+use crate::timestamp;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::Connection;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Read;
+
+/// The `format` a bundle's manifest must name.
+pub const FORMAT: &str = "lopen-memory-setup";
+
+/// Version of the bundle layout. Bumped only when a reader of the previous
+/// version would misread a bundle; new optional members do not bump it.
+pub const FORMAT_VERSION: i64 = 1;
+
+pub const MANIFEST: &str = "manifest.json";
+const CONFIG: &str = "config.json";
+const SKILL: &str = "skill/SKILL.md";
+const DATABASE: &str = "database.json";
+
+/// Largest member read out of a bundle, so a corrupt size cannot exhaust memory.
+const MAX_MEMBER: u64 = 512 * 1024 * 1024;
+
+fn home() -> String {
+    std::env::var("HOME").unwrap_or_default()
+}
+
+pub fn pretty(v: &Value) -> Vec<u8> {
+    let mut text = serde_json::to_string_pretty(v).unwrap_or_default();
+    text.push('\n');
+    text.into_bytes()
+}
+
+/// Write `members` as a gzipped tar, manifest first. Share bundles use the
+/// same layout.
+pub fn write_bundle(out: &str, members: &[(&str, Vec<u8>)]) -> std::io::Result<()> {
+    let file = fs::File::create(out)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    let mtime = chrono::Utc::now().timestamp().max(0) as u64;
+    for (name, data) in members {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data.as_slice())?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Package the config file, the skill file, and with `with_db` the whole
+/// store as `export --format json` writes it, into one gzipped tar at `out`.
+/// The skill is the installed SKILL.md when there is one, so local edits
+/// travel; otherwise the copy embedded in this binary.
+pub fn export(conn: &Connection, out: &str, with_db: bool, json: bool) -> i32 {
+    let mut members: Vec<(&str, Vec<u8>)> = Vec::new();
+    let mut contents = Vec::new();
+
+    if config::path().exists() {
+        members.push((CONFIG, pretty(&Value::Object(config::load()))));
+        contents.push(CONFIG);
+    }
+    let (skill_text, skill_source) = match fs::read_to_string(skill::installed_path(None)) {
+        Ok(t) => (t, "installed"),
+        Err(_) => (skill::embedded().to_string(), "embedded"),
+    };
+    members.push((SKILL, skill_text.into_bytes()));
+    contents.push(SKILL);
+
+    let mut counts = None;
+    if with_db {
+        let doc = match export::document(conn, None) {
+            Ok(d) => d,
+            Err(e) => {
+                output::err(&e.to_string());
+                return 2;
+            }
+        };
+        counts = Some(json!({
+            "projects": items(&doc, "projects").len(),
+            "research": items(&doc, "research").len(),
+        }));
+        members.push((DATABASE, pretty(&doc)));
+        contents.push(DATABASE);
+    }
+
+    let manifest = json!({
+        "format": FORMAT,
+        "format_version": FORMAT_VERSION,
+        "version": changes::current(),
+        "created_at": timestamp::now(),
+        "home": home(),
+        "skill_source": skill_source,
+        "contents": contents,
+    });
+    members.insert(0, (MANIFEST, pretty(&manifest)));
+
+    if let Err(e) = write_bundle(out, &members) {
+        return output::fail(&Error::Io(format!("failed to write {}: {}", out, e)));
+    }
+
+    if json {
+        output::print_json(&json!({
+            "file": out,
+            "format_version": FORMAT_VERSION,
+            "contents": contents,
+            "skill_source": skill_source,
+            "database": counts,
+        }));
+    } else {
+        let mut parts = Vec::new();
+        if contents.contains(&CONFIG) {
+            parts.push("config".to_string());
+        }
+        parts.push(format!("skill ({})", skill_source));
+        if let Some(c) = &counts {
+            parts.push(format!(
+                "database ({} project(s), {} research)",
+                c["projects"], c["research"]
+            ));
+        }
+        output::print_plain(&format!("wrote setup bundle {}: {}", out, parts.join(", ")));
+    }
+    0
+}
+
+/// Every regular file in the bundle by path, with the manifest checked.
+fn read_bundle(file: &str) -> Result<(Value, HashMap<String, Vec<u8>>), String> {
+    read_members(file, FORMAT, FORMAT_VERSION, "setup bundle")
+}
+
+/// Every regular file in a gzipped tar written by `write_bundle`, with its
+/// manifest's `format` checked against `format` and its `format_version`
+/// against the newest this binary reads. `what` names the kind of bundle in
+/// errors.
+pub fn read_members(
+    file: &str,
+    format: &str,
+    format_version: i64,
+    what: &str,
+) -> Result<(Value, HashMap<String, Vec<u8>>), String> {
+    let f = fs::File::open(file).map_err(|e| format!("failed to read {}: {}", file, e))?;
+    let bad = |e: std::io::Error| format!("{} is not a {}: {}", file, what, e);
+    let mut archive = tar::Archive::new(GzDecoder::new(f));
+    let mut members = HashMap::new();
+    for entry in archive.entries().map_err(bad)? {
+        let mut entry = entry.map_err(bad)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path().map_err(bad)?.to_string_lossy().into_owned();
+        if entry.size() > MAX_MEMBER {
+            return Err(format!("{}: member {} is too large", file, name));
+        }
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).map_err(bad)?;
+        members.insert(name, data);
+    }
+    let manifest: Value = members
+        .get(MANIFEST)
+        .and_then(|m| serde_json::from_slice(m).ok())
+        .ok_or_else(|| format!("{} is not a {}: no {}", file, what, MANIFEST))?;
+    if manifest["format"].as_str() != Some(format) {
+        return Err(format!(
+            "{} is not a {} (expected \"format\": \"{}\")",
+            file, what, format
+        ));
+    }
+    match manifest["format_version"].as_i64() {
+        Some(v) if v <= format_version => Ok((manifest, members)),
+        Some(v) => Err(format!(
+            "bundle format_version {} is newer than this binary understands ({}); upgrade lopen-memory",
+            v, format_version
+        )),
+        None => Err(format!("{} has no format_version", file)),
+    }
+}
+
+/// What `setup apply` does where this machine already has a config or data
+/// that differs from the bundle's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnExisting {
+    /// Stop before changing anything.
+    Refuse,
+    /// Replace it with the bundle's.
+    Overwrite,
+    /// Keep what is here and add only what is missing.
+    Merge,
+}
+
+/// Path prefixes to rewrite, first match wins. A prefix matches the whole
+/// value or up to a `/`, so /home/ann does not match /home/anna.
+struct PathMap {
+    pairs: Vec<(String, String)>,
+    rewritten: Vec<Value>,
+}
+
+impl PathMap {
+    fn rewrite(&mut self, place: String, value: &mut String) {
+        for (from, to) in &self.pairs {
+            let rest = match value.strip_prefix(from.as_str()) {
+                Some(r) if r.is_empty() || r.starts_with('/') => r,
+                _ => continue,
+            };
+            let new = format!("{}{}", to, rest);
+            self.rewritten
+                .push(json!({"where": place, "from": value.clone(), "to": new}));
+            *value = new;
+            return;
+        }
+    }
+
+    fn rewrite_value(&mut self, place: &str, v: &mut Value) {
+        match v {
+            Value::String(s) => self.rewrite(place.to_string(), s),
+            Value::Array(a) => {
+                for x in a {
+                    self.rewrite_value(place, x);
+                }
+            }
+            Value::Object(o) => {
+                for (k, x) in o.iter_mut() {
+                    self.rewrite_value(&format!("{}.{}", place, k), x);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn rewrite_field(&mut self, place: String, row: &mut Value, key: &str) {
+        if let Some(Value::String(s)) = row.get_mut(key) {
+            self.rewrite(place, s);
+        }
+    }
+}
+
+fn items<'a>(v: &'a Value, key: &str) -> &'a [Value] {
+    v[key].as_array().map(|a| a.as_slice()).unwrap_or(&[])
+}
+
+fn name(v: &Value) -> String {
+    v["name"].as_str().unwrap_or("").to_string()
+}
+
+/// The document with each id replaced by the names leading to its row, so
+/// two stores holding the same content compare equal whatever ids they
+/// were given. Projects and research are keyed by name; children keep the
+/// order the document lists them in.
+fn canonical(doc: &Value) -> Value {
+    let strip = |v: &Value, drop: &[&str]| {
+        let mut m = v.as_object().cloned().unwrap_or_default();
+        for k in drop {
+            m.remove(*k);
+        }
+        Value::Object(m)
+    };
+    let mut keys: [HashMap<i64, String>; 5] = Default::default();
+    let [project_keys, module_keys, feature_keys, task_keys, research_keys] = &mut keys;
+    let mut projects = BTreeMap::new();
+    for p in items(doc, "projects") {
+        let pk = name(p);
+        project_keys.insert(p["id"].as_i64().unwrap_or(0), pk.clone());
+        let mut modules = Vec::new();
+        for m in items(p, "modules") {
+            let mk = format!("{}/{}", pk, name(m));
+            module_keys.insert(m["id"].as_i64().unwrap_or(0), mk.clone());
+            let mut features = Vec::new();
+            for f in items(m, "features") {
+                let fk = format!("{}/{}", mk, name(f));
+                feature_keys.insert(f["id"].as_i64().unwrap_or(0), fk.clone());
+                let mut tasks = Vec::new();
+                for t in items(f, "tasks") {
+                    task_keys.insert(t["id"].as_i64().unwrap_or(0), format!("{}/{}", fk, name(t)));
+                    tasks.push(strip(t, &["id"]));
+                }
+                let mut fv = strip(f, &["id"]);
+                fv["tasks"] = json!(tasks);
+                features.push(fv);
+            }
+            let mut mv = strip(m, &["id"]);
+            mv["features"] = json!(features);
+            modules.push(mv);
+        }
+        let mut pv = strip(p, &["id"]);
+        pv["modules"] = json!(modules);
+        projects.insert(pk, pv);
+    }
+    let mut research = BTreeMap::new();
+    for r in items(doc, "research") {
+        research_keys.insert(r["id"].as_i64().unwrap_or(0), name(r));
+        research.insert(name(r), strip(r, &["id"]));
+    }
+    let key = |map: &HashMap<i64, String>, v: &Value| {
+        v.as_i64()
+            .and_then(|i| map.get(&i))
+            .cloned()
+            .unwrap_or_default()
+    };
+    let mut links = Vec::new();
+    for (kind, column, map) in [
+        ("projects", "project_id", &keys[0]),
+        ("modules", "module_id", &keys[1]),
+        ("features", "feature_id", &keys[2]),
+        ("tasks", "task_id", &keys[3]),
+    ] {
+        for l in items(&doc["links"], kind) {
+            links.push(vec![
+                kind.to_string(),
+                key(&keys[4], &l["research_id"]),
+                key(map, &l[column]),
+            ]);
+        }
+    }
+    links.sort();
+    let mut dependencies: Vec<Vec<String>> = items(doc, "dependencies")
+        .iter()
+        .map(|d| {
+            vec![
+                key(&keys[3], &d["task_id"]),
+                key(&keys[3], &d["depends_on_task_id"]),
+            ]
+        })
+        .collect();
+    dependencies.sort();
+    json!({
+        "projects": projects,
+        "research": research,
+        "links": links,
+        "dependencies": dependencies,
+    })
+}
+
+/// How each part of the bundle lands on this machine.
+#[derive(Default)]
+struct Plan {
+    config: Option<(&'static str, Map<String, Value>)>,
+    skill: Option<&'static str>,
+    database: Option<&'static str>,
+    conflicts: Vec<String>,
+    kept: Vec<String>,
+    added: Vec<String>,
+}
+
+fn plan_config(bundle: Map<String, Value>, on_existing: OnExisting, plan: &mut Plan) {
+    let path = config::path();
+    if !path.exists() {
+        plan.added = bundle.keys().cloned().collect();
+        plan.config = Some(("installed", bundle));
+        return;
+    }
+    let local = config::load();
+    let differing: Vec<String> = bundle
+        .iter()
+        .filter(|(k, v)| local.get(*k).is_some_and(|l| l != *v))
+        .map(|(k, _)| k.clone())
+        .collect();
+    let missing: Vec<String> = bundle
+        .keys()
+        .filter(|k| !local.contains_key(*k))
+        .cloned()
+        .collect();
+    if differing.is_empty() && missing.is_empty() {
+        plan.config = Some(("unchanged", local));
+        return;
+    }
+    match on_existing {
+        OnExisting::Refuse => plan.conflicts.push(format!(
+            "config {} already exists and differs from the bundle's ({})",
+            path.display(),
+            differing
+                .iter()
+                .chain(missing.iter())
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        OnExisting::Overwrite => plan.config = Some(("overwritten", bundle)),
+        OnExisting::Merge if missing.is_empty() => {
+            plan.kept = differing;
+            plan.config = Some(("unchanged", local));
+        }
+        OnExisting::Merge => {
+            let mut merged = local;
+            for k in &missing {
+                merged.insert(k.clone(), bundle[k].clone());
+            }
+            plan.kept = differing;
+            plan.added = missing;
+            plan.config = Some(("merged", merged));
+        }
+    }
+}
+
+fn store_is_empty(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT (SELECT COUNT(*) FROM projects) + (SELECT COUNT(*) FROM research)",
+        [],
+        |r| r.get::<_, i64>(0),
+    )
+    .map(|n| n == 0)
+}
+
+/// Load the bundle's database in one transaction: into an empty store as
+/// is, replacing everything with `Overwrite`, or skipping projects and
+/// research whose names already exist with `Merge`. Returns what was done
+/// and the created counts.
+fn apply_database(
+    conn: &Connection,
+    doc: &Value,
+    on_existing: OnExisting,
+    plan: &mut Plan,
+) -> Result<Option<(&'static str, Value)>, Error> {
+    let local = export::document(conn, None)?;
+    if canonical(&local) == canonical(doc) {
+        return Ok(Some(("unchanged", json!({}))));
+    }
+    let empty = store_is_empty(conn)?;
+    if !empty && on_existing == OnExisting::Refuse {
+        plan.conflicts.push(
+            "the database already holds projects or research that differ from the bundle's"
+                .to_string(),
+        );
+        return Ok(None);
+    }
+    if !plan.conflicts.is_empty() {
+        return Ok(None);
+    }
+    let tx = db::write_tx(conn)?;
+    let action = if empty {
+        "imported"
+    } else if on_existing == OnExisting::Overwrite {
+        let locked: i64 = tx.query_row(
+            "SELECT (SELECT COUNT(*) FROM modules WHERE locked=1)
+                      + (SELECT COUNT(*) FROM features WHERE locked=1)",
+            [],
+            |r| r.get(0),
+        )?;
+        if locked > 0 {
+            return Err(Error::Locked(format!(
+                "the database contains {} locked module(s)/feature(s); unlock them before overwriting it",
+                locked
+            )));
+        }
+        tx.execute_batch("DELETE FROM projects; DELETE FROM research;")?;
+        "overwritten"
+    } else {
+        "merged"
+    };
+    let created = import::restore(&tx, doc)?;
+    let nothing = created
+        .as_object()
+        .is_none_or(|c| c.values().all(|n| n.as_i64() == Some(0)));
+    if action == "merged" && nothing {
+        return Ok(Some(("unchanged", created)));
+    }
+    tx.commit()?;
+    Ok(Some((action, created)))
+}
+
+/// Install a bundle written by `setup export`: its config, its skill file,
+/// and, unless `skip_db`, its database. Paths under the exporting machine's
+/// home directory are moved under this one's (not with `keep_paths`), after
+/// any explicit `map` prefixes. An existing config or store that differs
+/// from the bundle's is a conflict settled by `on_existing`; with conflicts
+/// and no choice nothing is changed. Applying the same bundle again with
+/// the same choice changes nothing.
+#[allow(clippy::too_many_arguments)]
+pub fn apply(
+    conn: &Connection,
+    file: &str,
+    on_existing: OnExisting,
+    map: &[(String, String)],
+    keep_paths: bool,
+    skills_dir: Option<&String>,
+    skip_db: bool,
+    json: bool,
+) -> i32 {
+    let (manifest, members) = match read_bundle(file) {
+        Ok(b) => b,
+        Err(e) => {
+            output::err(&e);
+            return 1;
+        }
+    };
+    let mut paths = PathMap {
+        pairs: map.to_vec(),
+        rewritten: Vec::new(),
+    };
+    let (from_home, to_home) = (manifest["home"].as_str().unwrap_or(""), home());
+    if !keep_paths && !from_home.is_empty() && !to_home.is_empty() && from_home != to_home {
+        paths.pairs.push((from_home.to_string(), to_home));
+    }
+
+    let parse = |member: &str| -> Result<Option<Value>, String> {
+        members
+            .get(member)
+            .map(|m| {
+                serde_json::from_slice(m)
+                    .map_err(|e| format!("{}: {} is not valid JSON: {}", file, member, e))
+            })
+            .transpose()
+    };
+    let (config_doc, mut db_doc) = match (parse(CONFIG), parse(DATABASE)) {
+        (Ok(c), Ok(d)) => (c, d),
+        (Err(e), _) | (_, Err(e)) => {
+            output::err(&e);
+            return 1;
+        }
+    };
+
+    let mut plan = Plan::default();
+    if let Some(mut c) = config_doc {
+        paths.rewrite_value("config", &mut c);
+        match c {
+            Value::Object(m) => plan_config(m, on_existing, &mut plan),
+            _ => {
+                output::err(&format!("{}: {} is not a JSON object", file, CONFIG));
+                return 1;
+            }
+        }
+    }
+
+    let skill_path = skill::installed_path(skills_dir);
+    let skill_text = members
+        .get(SKILL)
+        .map(|s| String::from_utf8_lossy(s).into_owned());
+    if let Some(text) = &skill_text {
+        plan.skill = Some(match fs::read_to_string(&skill_path) {
+            Ok(current) if &current == text => "unchanged",
+            Ok(_) => "updated",
+            Err(_) => "installed",
+        });
+    }
+
+    let mut created = Value::Null;
+    if skip_db {
+        db_doc = None;
+    }
+    if let Some(doc) = &mut db_doc {
+        if let Some(projects) = doc["projects"].as_array_mut() {
+            for p in projects {
+                let pname = name(p);
+                paths.rewrite_field(format!("project {}", pname), p, "path");
+                if let Some(modules) = p["modules"].as_array_mut() {
+                    for m in modules {
+                        let place = format!("module {}/{}", pname, name(m));
+                        paths.rewrite_field(place, m, "path");
+                    }
+                }
+            }
+        }
+        match apply_database(conn, doc, on_existing, &mut plan) {
+            Ok(Some((action, c))) => {
+                plan.database = Some(action);
+                created = c;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                output::err(&format!("{}; nothing was applied", e));
+                return e.exit_code();
+            }
+        }
+    }
+
+    if !plan.conflicts.is_empty() {
+        return output::fail(&Error::Conflict(format!(
+            "{} conflicts with this machine: {}; pass --overwrite to take the bundle's, or --merge to keep what is here and add what is missing; nothing was applied",
+            file,
+            plan.conflicts.join("; ")
+        )));
+    }
+
+    if let Some((action, map)) = &plan.config {
+        if *action != "unchanged" {
+            if let Err(e) = config::save(map) {
+                output::err(&e);
+                return 2;
+            }
+        }
+    }
+    if let (Some(action), Some(text)) = (plan.skill, &skill_text) {
+        if action != "unchanged" {
+            let written = skill_path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&skill_path, text));
+            if let Err(e) = written {
+                return output::fail(&Error::Io(format!(
+                    "failed to write {}: {}",
+                    skill_path.display(),
+                    e
+                )));
+            }
+        }
+    }
+
+    let config_action = plan.config.as_ref().map(|(a, _)| *a);
+    let db_action = if skip_db && members.contains_key(DATABASE) {
+        Some("skipped")
+    } else {
+        plan.database
+    };
+    if json {
+        output::print_json(&json!({
+            "file": file,
+            "config": {
+                "action": config_action,
+                "path": config::path().display().to_string(),
+                "added": plan.added,
+                "kept": plan.kept,
+            },
+            "skill": {
+                "action": plan.skill,
+                "path": skill_path.display().to_string(),
+            },
+            "database": {
+                "action": db_action,
+                "created": created,
+            },
+            "paths_rewritten": paths.rewritten,
+        }));
+    } else {
+        let line = |label: &str, action: Option<&str>, detail: String| {
+            output::print_plain(&format!(
+                "{}: {}{}",
+                label,
+                action.unwrap_or("not in bundle"),
+                detail
+            ));
+        };
+        let mut detail = format!(" ({})", config::path().display());
+        if !plan.kept.is_empty() {
+            detail.push_str(&format!("; kept local {}", plan.kept.join(", ")));
+        }
+        line("config", config_action, detail);
+        line("skill", plan.skill, format!(" ({})", skill_path.display()));
+        line("database", db_action, String::new());
+        for r in &paths.rewritten {
+            outln!(
+                "  path {}: {} → {}",
+                r["where"].as_str().unwrap_or(""),
+                r["from"].as_str().unwrap_or(""),
+                r["to"].as_str().unwrap_or("")
+            );
+        }
+    }
+    0
+}
@@ -0,0 +1,229 @@
+use crate::changes;
+use crate::content;
+use crate::db;
+use crate::error::Error;
+use crate::models::research;
+use crate::models::setup;
+use crate::output;
+use crate::timestamp;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+
+/// The `format` a share bundle's manifest must name.
+pub const FORMAT: &str = "lopen-memory-share";
+
+/// Version of the share bundle layout, bumped as for setup bundles. The
+/// database inside carries its own schema version, and an older one is
+/// migrated when the bundle is opened.
+pub const FORMAT_VERSION: i64 = 1;
+
+const DATABASE: &str = "memory.db";
+
+/// Entity types with a table of their own, for dropping history rows that
+/// point at entities left out of a bundle.
+const ENTITY_TABLES: [(&str, &str); 5] = [
+    ("project", "projects"),
+    ("module", "modules"),
+    ("feature", "features"),
+    ("task", "tasks"),
+    ("research", "research"),
+];
+
+/// A scratch copy of a database, deleted when dropped.
+pub struct Scratch(PathBuf);
+
+impl Scratch {
+    pub fn new() -> Scratch {
+        let nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        Scratch(std::env::temp_dir().join(format!(
+            "lopen-memory-scratch-{}-{}.db",
+            std::process::id(),
+            nanos
+        )))
+    }
+
+    pub fn path(&self) -> String {
+        self.0.to_string_lossy().into_owned()
+    }
+}
+
+impl Default for Scratch {
+    fn default() -> Scratch {
+        Scratch::new()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        for suffix in ["", "-journal", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", self.path(), suffix));
+        }
+    }
+}
+
+/// Cut a copy of the database down to one project's subtree and the research
+/// linked into it. Everything else goes: other projects, unlinked research,
+/// their snapshots and rename history, and what only made sense on the
+/// sharer's machine (`use` contexts, locks, timestamp repairs, the trash).
+fn prune(conn: &Connection, project_id: i64) -> rusqlite::Result<()> {
+    let tx = db::write_tx(conn)?;
+    // Archived projects refuse deletes under them; lift the flag on the way out.
+    tx.execute(
+        "UPDATE projects SET archived=0 WHERE id<>?1 AND archived=1",
+        params![project_id],
+    )?;
+    tx.execute(
+        &format!(
+            "DELETE FROM research WHERE id NOT IN ({})",
+            research::SUBTREE_RESEARCH_SQL_PROJECT
+        ),
+        params![project_id],
+    )?;
+    tx.execute("DELETE FROM projects WHERE id<>?1", params![project_id])?;
+    tx.execute(
+        "DELETE FROM snapshots WHERE project_id<>?1",
+        params![project_id],
+    )?;
+    tx.execute_batch(
+        "DELETE FROM contexts; DELETE FROM locks; DELETE FROM timestamp_issues; DELETE FROM trash;",
+    )?;
+    for (kind, table) in ENTITY_TABLES {
+        for history in ["name_history", "state_history"] {
+            tx.execute(
+                &format!(
+                    "DELETE FROM {} WHERE entity_type=?1 AND entity_id NOT IN (SELECT id FROM {})",
+                    history, table
+                ),
+                params![kind],
+            )?;
+        }
+    }
+    tx.commit()?;
+    // One self-contained file: no WAL beside it, and no free pages.
+    conn.execute_batch("PRAGMA journal_mode=DELETE; VACUUM;")
+}
+
+/// Rows per kind in a pruned copy, in the order they nest.
+fn counts(conn: &Connection) -> rusqlite::Result<Value> {
+    let mut out = serde_json::Map::new();
+    for (kind, table) in [
+        ("modules", "modules"),
+        ("features", "features"),
+        ("tasks", "tasks"),
+        ("research", "research"),
+    ] {
+        let n: i64 =
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |r| r.get(0))?;
+        out.insert(kind.to_string(), json!(n));
+    }
+    Ok(Value::Object(out))
+}
+
+/// The database file of the bundle: a copy of this one cut down by `prune`,
+/// with every research body inline.
+fn bundle_database(conn: &Connection, project_id: i64) -> Result<(Vec<u8>, Value), String> {
+    let scratch = Scratch::new();
+    conn.execute("VACUUM INTO ?1", params![scratch.path()])
+        .map_err(|e| format!("failed to copy the database: {}", e))?;
+    let copy = db::open(&scratch.path(), false).map_err(|e| e.to_string())?;
+    content::inline_into(conn, &copy).map_err(|e| e.to_string())?;
+    prune(&copy, project_id).map_err(|e| e.to_string())?;
+    let counts = counts(&copy).map_err(|e| e.to_string())?;
+    drop(copy);
+    let data = fs::read(&scratch.0).map_err(|e| format!("failed to read the copy: {}", e))?;
+    Ok((data, counts))
+}
+
+/// Write a read-only share bundle of one project to `out`: a gzipped tar
+/// holding a manifest and a SQLite database with the project's subtree, the
+/// research linked into it, and their history, ids unchanged.
+pub fn create(conn: &Connection, project_id: i64, out: &str, json: bool) -> i32 {
+    let project: String = match conn.query_row(
+        "SELECT name FROM projects WHERE id=?1",
+        params![project_id],
+        |r| r.get(0),
+    ) {
+        Ok(n) => n,
+        Err(_) => {
+            return output::fail(&Error::not_found(
+                "project",
+                format!("project not found: {}", project_id),
+            ));
+        }
+    };
+    let (data, counts) = match bundle_database(conn, project_id) {
+        Ok(d) => d,
+        Err(e) => {
+            output::err(&e);
+            return 2;
+        }
+    };
+    let manifest = json!({
+        "format": FORMAT,
+        "format_version": FORMAT_VERSION,
+        "version": changes::current(),
+        "schema_version": db::schema_version(),
+        "created_at": timestamp::now(),
+        "project": project,
+        "project_id": project_id,
+        "counts": counts,
+    });
+    let members = [
+        (setup::MANIFEST, setup::pretty(&manifest)),
+        (DATABASE, data),
+    ];
+    if let Err(e) = setup::write_bundle(out, &members) {
+        return output::fail(&Error::Io(format!("failed to write {}: {}", out, e)));
+    }
+
+    if json {
+        output::print_json(&json!({
+            "file": out,
+            "format_version": FORMAT_VERSION,
+            "project": project,
+            "project_id": project_id,
+            "counts": counts,
+        }));
+    } else {
+        output::print_plain(&format!(
+            "wrote share bundle {}: project {} ({} modules, {} features, {} tasks, {} research)",
+            out,
+            project,
+            counts["modules"],
+            counts["features"],
+            counts["tasks"],
+            counts["research"]
+        ));
+    }
+    0
+}
+
+/// Open a share bundle as a database no statement can write to. The data
+/// lives in a scratch file until the returned guard is dropped; a bundle from
+/// an older schema is migrated there first.
+pub fn open(file: &str) -> Result<(Connection, Scratch), Error> {
+    let (manifest, mut members) = setup::read_members(file, FORMAT, FORMAT_VERSION, "share bundle")
+        .map_err(Error::Invalid)?;
+    let schema = manifest["schema_version"].as_i64().unwrap_or_default();
+    if schema > db::schema_version() {
+        return Err(Error::Invalid(format!(
+            "{} was made by lopen-memory {} with schema version {}, newer than this binary's ({}); upgrade lopen-memory",
+            file,
+            manifest["version"].as_str().unwrap_or("?"),
+            schema,
+            db::schema_version()
+        )));
+    }
+    let data = members.remove(DATABASE).ok_or_else(|| {
+        Error::Invalid(format!("{} is not a share bundle: no {}", file, DATABASE))
+    })?;
+    let scratch = Scratch::new();
+    fs::write(&scratch.0, data)
+        .map_err(|e| Error::Io(format!("failed to unpack {}: {}", file, e)))?;
+    let conn = db::open(&scratch.path(), false)
+        .map_err(|e| Error::Invalid(format!("{} is damaged: {}", file, e)))?;
+    conn.execute_batch("PRAGMA query_only=ON;")?;
+    Ok((conn, scratch))
+}
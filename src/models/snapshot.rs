@@ -0,0 +1,354 @@
+use crate::db;
+use crate::error::Error;
+use crate::models::export;
+use crate::models::import;
+use crate::names;
+use crate::output;
+use crate::timestamp;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Map, Value};
+
+/// A saved copy of one project's subtree and the research linked into it, as
+/// the document `export --format json --project` writes.
+struct Snapshot {
+    name: String,
+    project_id: i64,
+    project: String,
+    created_at: String,
+    document: Value,
+}
+
+impl Snapshot {
+    /// Rows per kind in the document, in the order they nest.
+    fn counts(&self) -> Vec<(&'static str, usize)> {
+        let items = |v: &Value, key: &str| v[key].as_array().cloned().unwrap_or_default();
+        let projects = items(&self.document, "projects");
+        let modules: Vec<Value> = projects.iter().flat_map(|p| items(p, "modules")).collect();
+        let features: Vec<Value> = modules.iter().flat_map(|m| items(m, "features")).collect();
+        let tasks = features.iter().map(|f| items(f, "tasks").len()).sum();
+        vec![
+            ("modules", modules.len()),
+            ("features", features.len()),
+            ("tasks", tasks),
+            ("research", items(&self.document, "research").len()),
+        ]
+    }
+
+    fn to_json(&self) -> Value {
+        let mut counts = Map::new();
+        for (kind, n) in self.counts() {
+            counts.insert(kind.to_string(), json!(n));
+        }
+        json!({
+            "name": self.name,
+            "project": self.project,
+            "project_id": self.project_id,
+            "created_at": self.created_at,
+            "counts": Value::Object(counts),
+        })
+    }
+
+    /// `3 modules, 5 features, 8 tasks, 2 research`
+    fn summary(&self) -> String {
+        self.counts()
+            .iter()
+            .map(|(kind, n)| format!("{} {}", n, kind))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn load(conn: &Connection, name: &str) -> Result<Snapshot, String> {
+    let row: Option<(String, i64, String, String, String)> = conn
+        .query_row(
+            "SELECT name, project_id, project_name, created_at, document FROM snapshots WHERE name=?1",
+            params![name],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some((name, project_id, project, created_at, document)) = row else {
+        return Err(format!("snapshot not found: {}", name));
+    };
+    let document = serde_json::from_str(&document)
+        .map_err(|e| format!("snapshot {} is damaged: {}", name, e))?;
+    Ok(Snapshot {
+        name,
+        project_id,
+        project,
+        created_at,
+        document,
+    })
+}
+
+/// Save the project's subtree and the research linked into it under `name`.
+/// Snapshot names are unique across the database.
+pub fn create(conn: &Connection, project_id: i64, name: &str, json: bool) -> i32 {
+    let name = name.trim();
+    if name.is_empty() {
+        output::err("snapshot name must not be empty");
+        return 1;
+    }
+    let exists: bool = conn
+        .query_row(
+            "SELECT COUNT(*) > 0 FROM snapshots WHERE name=?1",
+            params![name],
+            |r| r.get(0),
+        )
+        .unwrap_or(false);
+    if exists {
+        return output::fail(&Error::Conflict(format!(
+            "snapshot already exists: {} (drop it first to reuse the name)",
+            name
+        )));
+    }
+    let document = match export::document(conn, Some(project_id)) {
+        Ok(d) => d,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let project = document["projects"][0]["name"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let snapshot = Snapshot {
+        name: name.to_string(),
+        project_id,
+        project,
+        created_at: timestamp::now(),
+        document,
+    };
+    if let Err(e) = conn.execute(
+        "INSERT INTO snapshots (name, project_id, project_name, created_at, document)
+         VALUES (?1,?2,?3,?4,?5)",
+        params![
+            snapshot.name,
+            snapshot.project_id,
+            snapshot.project,
+            snapshot.created_at,
+            snapshot.document.to_string()
+        ],
+    ) {
+        output::err(&e.to_string());
+        return 2;
+    }
+    if json {
+        output::print_json(&snapshot.to_json());
+    } else {
+        output::print_plain(&format!(
+            "created snapshot {} of project {}: {}",
+            snapshot.name,
+            snapshot.project,
+            snapshot.summary()
+        ));
+    }
+    0
+}
+
+/// Every snapshot, or those taken of one project, oldest first.
+pub fn list(conn: &Connection, project_id: Option<i64>, json: bool) -> i32 {
+    let names: Vec<String> = match conn
+        .prepare(
+            "SELECT name FROM snapshots WHERE ?1 IS NULL OR project_id=?1
+             ORDER BY created_at, id",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![project_id], |r| r.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()
+        }) {
+        Ok(n) => n,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let mut snapshots = Vec::new();
+    for name in names {
+        match load(conn, &name) {
+            Ok(s) => snapshots.push(s),
+            Err(e) => {
+                output::err(&e);
+                return 2;
+            }
+        }
+    }
+    if json {
+        output::print_json(&Value::Array(
+            snapshots.iter().map(Snapshot::to_json).collect(),
+        ));
+        return 0;
+    }
+    if snapshots.is_empty() {
+        output::print_empty("no snapshots");
+        return 0;
+    }
+    for s in &snapshots {
+        outln!(
+            "{:<24} {:<20} {}  {}",
+            s.name,
+            s.project,
+            s.created_at,
+            s.summary()
+        );
+    }
+    0
+}
+
+/// Reinstate a snapshot in one transaction. With `replace`, the project it
+/// was taken of is removed first, subtree and all, and the snapshot comes
+/// back under its original name; with `as_name` it is restored beside the
+/// current project under that name. Research that still exists is linked to
+/// as it is now; research removed since is recreated.
+pub fn restore(
+    conn: &Connection,
+    name: &str,
+    replace: bool,
+    as_name: Option<&str>,
+    json: bool,
+) -> i32 {
+    let snapshot = match load(conn, name) {
+        Ok(s) => s,
+        Err(e) => {
+            output::err(&e);
+            return 1;
+        }
+    };
+    let target = names::normalize(as_name.unwrap_or(&snapshot.project));
+    if target.is_empty() {
+        output::err("project name must not be empty");
+        return 1;
+    }
+    let tx = match db::write_tx(conn) {
+        Ok(tx) => tx,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let mut replaced: Option<i64> = None;
+    if replace {
+        // The project it was taken of, or, when that was itself restored
+        // since and got a new id, the one now holding its name.
+        let current: Option<(i64, String)> = tx
+            .query_row(
+                "SELECT id, name FROM projects WHERE id=?1 OR fold(name)=?2
+                 ORDER BY id=?1 DESC LIMIT 1",
+                params![snapshot.project_id, names::fold(&target)],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .optional()
+            .unwrap_or(None);
+        if let Some((current_id, current)) = current {
+            let locked: i64 = tx
+                .query_row(
+                    "SELECT (SELECT COUNT(*) FROM modules WHERE project_id=?1 AND locked=1)
+                          + (SELECT COUNT(*) FROM features f JOIN modules m ON m.id=f.module_id
+                             WHERE m.project_id=?1 AND f.locked=1)",
+                    params![current_id],
+                    |r| r.get(0),
+                )
+                .unwrap_or(0);
+            if locked > 0 {
+                output::err(&format!(
+                    "project {} contains {} locked module(s)/feature(s); unlock them before replacing it",
+                    current, locked
+                ));
+                return output::EXIT_LOCKED;
+            }
+            if let Err(e) = tx.execute("DELETE FROM projects WHERE id=?1", params![current_id]) {
+                output::err(&e.to_string());
+                return 2;
+            }
+            replaced = Some(current_id);
+        }
+    }
+    let existing = match names::clash(&tx, "projects", None, &target, None) {
+        Ok(existing) => existing,
+        Err(e) => return output::fail(&e),
+    };
+    if let Some(existing) = existing {
+        return output::fail(&Error::Conflict(format!(
+            "project already exists: {} (pass --replace to overwrite the snapshotted project, or --as <name> to restore beside it)",
+            existing
+        )));
+    }
+    let mut document = snapshot.document.clone();
+    document["projects"][0]["name"] = json!(target);
+    let created = match import::restore(&tx, &document) {
+        Ok(c) => c,
+        Err(e) => {
+            output::err(&format!("{}; nothing was restored", e));
+            return e.exit_code();
+        }
+    };
+    let project_id: i64 = match tx.query_row(
+        "SELECT id FROM projects WHERE name=?1",
+        params![target],
+        |r| r.get(0),
+    ) {
+        Ok(id) => id,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    // Snapshots of the replaced project now belong to its replacement.
+    if let Some(old) = replaced {
+        if let Err(e) = tx.execute(
+            "UPDATE snapshots SET project_id=?1 WHERE project_id=?2",
+            params![project_id, old],
+        ) {
+            output::err(&e.to_string());
+            return 2;
+        }
+    }
+    if let Err(e) = tx.commit() {
+        output::err(&e.to_string());
+        return 2;
+    }
+    if json {
+        output::print_json(&json!({
+            "snapshot": snapshot.name,
+            "project": {"id": project_id, "name": target},
+            "replaced": replaced.is_some(),
+            "created": created,
+        }));
+    } else {
+        output::print_plain(&format!(
+            "restored snapshot {} as project {}{}: {}",
+            snapshot.name,
+            target,
+            if replaced.is_some() {
+                ", replacing it"
+            } else {
+                ""
+            },
+            snapshot.summary()
+        ));
+    }
+    0
+}
+
+/// Delete a snapshot. The project it was taken of is not touched.
+pub fn drop(conn: &Connection, name: &str, json: bool) -> i32 {
+    match conn.execute("DELETE FROM snapshots WHERE name=?1", params![name]) {
+        Ok(0) => output::fail(&Error::not_found(
+            "snapshot",
+            format!("snapshot not found: {}", name),
+        )),
+        Ok(_) => {
+            if json {
+                output::print_json(&json!({"name": name, "dropped": true}));
+            } else {
+                output::print_plain(&format!("dropped snapshot: {}", name));
+            }
+            0
+        }
+        Err(e) => {
+            output::err(&e.to_string());
+            2
+        }
+    }
+}
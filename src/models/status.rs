@@ -0,0 +1,262 @@
+use crate::output;
+use crate::resolve;
+use rusqlite::{params, Connection};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// States in lifecycle order, for counts.
+const STATES: [&str; 6] = [
+    "Draft", "Planning", "Building", "Complete", "Amending", "Blocked",
+];
+
+/// Work entity types counted, with the plural used as their key.
+const KINDS: [(&str, &str); 3] = [
+    ("module", "modules"),
+    ("feature", "features"),
+    ("task", "tasks"),
+];
+
+/// How many recently worked-on items each project lists; on a tie the
+/// deepest item, the one actually being worked on, comes first.
+const RECENT: i64 = 5;
+
+/// Every module, feature, and task with its project, breadcrumb, state, and
+/// last_worked_on, as one row source the queries below aggregate; ?1 is the
+/// project to keep, or NULL for every project not marked completed.
+const ITEMS_CTE: &str = "WITH items(project_id, kind, id, name, breadcrumb, state, last_worked_on) AS (
+        SELECT m.project_id, 'module', m.id, m.name, p.name || ' > ' || m.name, m.state, m.last_worked_on
+        FROM modules m JOIN projects p ON p.id = m.project_id
+        WHERE (?1 IS NULL AND p.completed = 0) OR p.id = ?1
+        UNION ALL
+        SELECT m.project_id, 'feature', f.id, f.name, p.name || ' > ' || m.name || ' > ' || f.name, f.state, f.last_worked_on
+        FROM features f JOIN modules m ON m.id = f.module_id JOIN projects p ON p.id = m.project_id
+        WHERE (?1 IS NULL AND p.completed = 0) OR p.id = ?1
+        UNION ALL
+        SELECT m.project_id, 'task', t.id, t.name, p.name || ' > ' || m.name || ' > ' || f.name || ' > ' || t.name, t.state, t.last_worked_on
+        FROM tasks t JOIN features f ON f.id = t.feature_id JOIN modules m ON m.id = f.module_id
+        JOIN projects p ON p.id = m.project_id
+        WHERE (?1 IS NULL AND p.completed = 0) OR p.id = ?1
+    )";
+
+/// A module, feature, or task listed as in progress or recently worked on.
+struct Item {
+    kind: String,
+    id: i64,
+    breadcrumb: String,
+    state: String,
+    last_worked_on: String,
+}
+
+impl Item {
+    fn to_json(&self) -> Value {
+        json!({
+            "type": self.kind,
+            "id": self.id,
+            "ref": resolve::short_ref(&self.kind, self.id),
+            "breadcrumb": self.breadcrumb,
+            "state": self.state,
+            "last_worked_on": self.last_worked_on,
+        })
+    }
+
+    fn print(&self) {
+        outln!(
+            "    {:<8} {:<5} {:<9} {}  {}",
+            self.kind,
+            resolve::short_ref(&self.kind, self.id),
+            self.state,
+            self.last_worked_on
+                .get(..10)
+                .unwrap_or(&self.last_worked_on),
+            self.breadcrumb
+        );
+    }
+}
+
+#[derive(Default)]
+struct ProjectStatus {
+    name: String,
+    /// (type, state) → count
+    counts: BTreeMap<(String, String), i64>,
+    in_progress: Vec<Item>,
+    recent: Vec<Item>,
+}
+
+impl ProjectStatus {
+    fn counts_json(&self) -> Value {
+        let mut out = Map::new();
+        for (kind, plural) in KINDS {
+            let mut by_state = Map::new();
+            for state in STATES {
+                let n = self
+                    .counts
+                    .get(&(kind.to_string(), state.to_string()))
+                    .copied()
+                    .unwrap_or(0);
+                by_state.insert(state.to_string(), json!(n));
+            }
+            out.insert(plural.to_string(), Value::Object(by_state));
+        }
+        Value::Object(out)
+    }
+
+    /// `Draft 1, Building 2`, leaving out states with none.
+    fn counts_line(&self, kind: &str) -> String {
+        let parts: Vec<String> = STATES
+            .iter()
+            .filter_map(|state| {
+                self.counts
+                    .get(&(kind.to_string(), state.to_string()))
+                    .map(|n| format!("{} {}", state, n))
+            })
+            .collect();
+        if parts.is_empty() {
+            "none".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+fn item(r: &rusqlite::Row, from: usize) -> rusqlite::Result<Item> {
+    Ok(Item {
+        kind: r.get(from)?,
+        id: r.get(from + 1)?,
+        breadcrumb: r.get(from + 2)?,
+        state: r.get(from + 3)?,
+        last_worked_on: r.get(from + 4)?,
+    })
+}
+
+/// Per project: counts by state, what is in Building or Amending, and the
+/// most recently worked-on items. Three aggregate queries, however many
+/// projects there are.
+fn load(
+    conn: &Connection,
+    project_id: Option<i64>,
+) -> rusqlite::Result<BTreeMap<i64, ProjectStatus>> {
+    let mut projects: BTreeMap<i64, ProjectStatus> = BTreeMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT id, name FROM projects
+         WHERE (?1 IS NULL AND completed = 0) OR id = ?1 ORDER BY id",
+    )?;
+    for row in stmt.query_map(params![project_id], |r| Ok((r.get(0)?, r.get(1)?)))? {
+        let (id, name): (i64, String) = row?;
+        projects.insert(
+            id,
+            ProjectStatus {
+                name,
+                ..Default::default()
+            },
+        );
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "{} SELECT project_id, kind, state, COUNT(*) FROM items GROUP BY project_id, kind, state",
+        ITEMS_CTE
+    ))?;
+    let rows = stmt.query_map(params![project_id], |r| {
+        Ok((
+            r.get::<_, i64>(0)?,
+            r.get::<_, String>(1)?,
+            r.get::<_, String>(2)?,
+            r.get::<_, i64>(3)?,
+        ))
+    })?;
+    for row in rows {
+        let (pid, kind, state, n) = row?;
+        if let Some(p) = projects.get_mut(&pid) {
+            p.counts.insert((kind, state), n);
+        }
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "{} SELECT project_id, kind, id, breadcrumb, state, last_worked_on FROM items
+         WHERE state IN ('Building', 'Amending')
+         ORDER BY project_id, last_worked_on DESC, kind, id",
+        ITEMS_CTE
+    ))?;
+    let rows = stmt.query_map(params![project_id], |r| {
+        Ok((r.get::<_, i64>(0)?, item(r, 1)?))
+    })?;
+    for row in rows {
+        let (pid, it) = row?;
+        if let Some(p) = projects.get_mut(&pid) {
+            p.in_progress.push(it);
+        }
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "{} SELECT project_id, kind, id, breadcrumb, state, last_worked_on FROM (
+             SELECT *, ROW_NUMBER() OVER (
+                 PARTITION BY project_id ORDER BY last_worked_on DESC,
+                 CASE kind WHEN 'task' THEN 0 WHEN 'feature' THEN 1 ELSE 2 END, id DESC) AS n
+             FROM items)
+         WHERE n <= ?2
+         ORDER BY project_id, n",
+        ITEMS_CTE
+    ))?;
+    let rows = stmt.query_map(params![project_id, RECENT], |r| {
+        Ok((r.get::<_, i64>(0)?, item(r, 1)?))
+    })?;
+    for row in rows {
+        let (pid, it) = row?;
+        if let Some(p) = projects.get_mut(&pid) {
+            p.recent.push(it);
+        }
+    }
+    Ok(projects)
+}
+
+/// Where work stands, per project (one, or every project not marked
+/// completed): modules, features, and tasks counted by state, everything in
+/// Building or Amending, and the most recently worked-on items.
+pub fn status(conn: &Connection, project_id: Option<i64>, json: bool) -> i32 {
+    let projects = match load(conn, project_id) {
+        Ok(p) => p,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+
+    if json {
+        output::print_json(&json!({
+            "projects": projects.iter().map(|(id, p)| json!({
+                "id": id,
+                "ref": resolve::short_ref("project", *id),
+                "name": p.name,
+                "counts": p.counts_json(),
+                "in_progress": p.in_progress.iter().map(Item::to_json).collect::<Vec<_>>(),
+                "recent": p.recent.iter().map(Item::to_json).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+        }));
+        return 0;
+    }
+    if projects.is_empty() {
+        output::print_plain("no active projects");
+        return 0;
+    }
+    for (i, (id, p)) in projects.iter().enumerate() {
+        if i > 0 {
+            outln!();
+        }
+        outln!("{} ({})", p.name, resolve::short_ref("project", *id));
+        for (kind, plural) in KINDS {
+            outln!("  {:<9} {}", plural, p.counts_line(kind));
+        }
+        if !p.in_progress.is_empty() {
+            outln!("  in progress:");
+            for it in &p.in_progress {
+                it.print();
+            }
+        }
+        if !p.recent.is_empty() {
+            outln!("  recently worked on:");
+            for it in &p.recent {
+                it.print();
+            }
+        }
+    }
+    0
+}
@@ -1,8 +1,30 @@
+use crate::db;
+use crate::error::Error;
+use crate::fields::{self, Page, Sort};
+use crate::models::ancestry;
+use crate::models::dependency::{self, Dep};
+use crate::models::feature;
+use crate::models::history;
+use crate::models::lift::{self, ParentPolicy};
+use crate::models::placement::Placement;
+use crate::models::progress;
+use crate::models::reason;
+use crate::models::references::{self, Renamed};
+use crate::models::transition::Transitioned;
+use crate::models::trash::{self, Removed};
+use crate::names;
 use crate::output;
-use crate::state::{validate_transition, State};
+use crate::priority::{self, Priority};
+use crate::resolve;
+use crate::state::{self, validate_transition, State};
+use crate::timestamp;
 use rusqlite::{params, Connection};
+use serde::Serialize;
 use serde_json::{json, Value};
 
+/// Serializes to the record `task show --json` starts from.
+#[derive(Debug, Clone, Serialize)]
+#[serde(into = "Value")]
 pub struct Task {
     pub id: i64,
     pub feature_id: i64,
@@ -11,32 +33,67 @@ pub struct Task {
     pub details: String,
     pub state: String,
     pub last_worked_on: String,
+    /// Work order within the feature; lower first, ids breaking ties.
+    pub position: i64,
+    pub priority: String,
 }
 
-fn now() -> String {
-    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+/// Where `task add --before/--after` puts the new task among its siblings.
+pub enum Anchor {
+    Before(i64),
+    After(i64),
 }
 
-fn load(conn: &Connection, id: i64) -> Result<Task, String> {
+/// The task with this id.
+pub fn get(conn: &Connection, id: i64) -> Result<Task, Error> {
     conn.query_row(
-        "SELECT id, feature_id, name, description, details, state, last_worked_on FROM tasks WHERE id=?1",
+        "SELECT id, feature_id, name, description, details, state, last_worked_on, position, priority FROM tasks WHERE id=?1",
         params![id],
         |r| Ok(Task {
             id: r.get(0)?, feature_id: r.get(1)?, name: r.get(2)?,
             description: r.get(3)?, details: r.get(4)?, state: r.get(5)?, last_worked_on: r.get(6)?,
+            position: r.get(7)?, priority: r.get(8)?,
         }),
     )
-    .map_err(|_| format!("task not found: {}", id))
+    .map_err(|e| Error::missing(e, "task", id))
 }
 
+/// The keys of a listed task's JSON, in order: the columns of `task list`
+/// under csv and tsv.
+pub const COLUMNS: &[&str] = &[
+    "description",
+    "details",
+    "feature_id",
+    "id",
+    "last_worked_on",
+    "name",
+    "position",
+    "priority",
+    "ref",
+    "state",
+];
+
 fn task_to_json(t: &Task) -> Value {
     json!({
-        "id": t.id, "feature_id": t.feature_id, "name": t.name,
+        "id": t.id, "ref": resolve::short_ref("task", t.id), "feature_id": t.feature_id, "name": t.name,
         "description": t.description, "details": t.details,
-        "state": t.state, "last_worked_on": t.last_worked_on,
+        "state": t.state, "last_worked_on": t.last_worked_on, "position": t.position,
+        "priority": t.priority,
     })
 }
 
+impl From<Task> for Value {
+    fn from(t: Task) -> Value {
+        task_to_json(&t)
+    }
+}
+
+/// The task's full JSON record, as `task show` prints it before adding
+/// related entities.
+pub fn record_json(conn: &Connection, id: i64) -> Result<Value, Error> {
+    get(conn, id).map(|t| task_to_json(&t))
+}
+
 fn feature_name(conn: &Connection, feature_id: i64) -> String {
     conn.query_row(
         "SELECT name FROM features WHERE id=?1",
@@ -46,253 +103,1044 @@ fn feature_name(conn: &Connection, feature_id: i64) -> String {
     .unwrap_or_default()
 }
 
-pub fn add(conn: &Connection, feature_id: i64, name: &str, description: &str, json: bool) -> i32 {
-    let name = name.trim();
+/// How an add or rename reports a task name already used in the feature.
+fn taken(conn: &Connection, feature_id: i64, name: &str) -> String {
+    names::taken(
+        "task",
+        name,
+        Some(("feature", &feature_name(conn, feature_id))),
+    )
+}
+
+/// Add a task at the end of the feature's work order, or just before or
+/// after the sibling named by `anchor`, shifting the tasks behind it along.
+/// It starts in Draft or is walked forward to `start`. Refused with Locked
+/// while the feature is locked.
+#[allow(clippy::too_many_arguments)]
+pub fn create(
+    conn: &Connection,
+    feature_id: i64,
+    name: &str,
+    description: &str,
+    details: &str,
+    start: Option<&State>,
+    anchor: Option<Anchor>,
+    priority: Priority,
+) -> Result<Task, Error> {
+    let name = names::normalize(name);
     if name.is_empty() {
-        output::err("name must not be empty");
-        return 1;
+        return Err(Error::Invalid("name must not be empty".into()));
+    }
+    if let Some(existing) =
+        names::clash(conn, "tasks", Some(("feature_id", feature_id)), &name, None)?
+    {
+        return Err(Error::Conflict(taken(conn, feature_id, &existing)));
     }
-    let fname = feature_name(conn, feature_id);
-    let ts = now();
-    match conn.execute(
-        "INSERT INTO tasks (feature_id, name, description, last_worked_on) VALUES (?1,?2,?3,?4)",
-        params![feature_id, name, description, ts],
+    if let Some(fname) = feature::locked_name(conn, feature_id) {
+        return Err(Error::Locked(format!(
+            "feature {} is locked; cannot add task (unlock it first)",
+            fname
+        )));
+    }
+    let walked = state::start_walk(start, "task", &name)?;
+    let state = walked.last().unwrap().to_string();
+    let ts = timestamp::now();
+    let tx = db::write_tx(conn)?;
+    let position = make_room(&tx, feature_id, anchor.as_ref())?;
+    match tx.execute(
+        "INSERT INTO tasks (feature_id, name, description, details, state, last_worked_on, position, priority) VALUES (?1,?2,?3,?4,?5,?6,?7,?8)",
+        params![feature_id, name, description, details, state, ts, position, priority.to_string()],
     ) {
-        Ok(_) => {
-            let id = conn.last_insert_rowid();
-            if json {
-                output::print_json(&load(conn, id).map(|t| task_to_json(&t)).unwrap());
-            } else {
-                output::print_plain(&format!("added task {}: {} (feature: {})", id, name, fname));
-            }
-            0
+        Ok(_) => {}
+        Err(e) if db::name_taken(&e) => return Err(Error::Conflict(taken(conn, feature_id, &name))),
+        Err(e) => return Err(e.into()),
+    }
+    let id = tx.last_insert_rowid();
+    history::record_walk(&tx, "task", id, &walked, &ts)?;
+    tx.commit()?;
+    Ok(Task {
+        id,
+        feature_id,
+        name,
+        description: description.to_string(),
+        details: details.to_string(),
+        state,
+        last_worked_on: ts,
+        position,
+        priority: priority.to_string(),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn add(
+    conn: &Connection,
+    feature_id: i64,
+    name: &str,
+    description: &str,
+    details: &str,
+    start: Option<&State>,
+    anchor: Option<Anchor>,
+    priority: Priority,
+    json: bool,
+) -> i32 {
+    let t = match create(
+        conn,
+        feature_id,
+        name,
+        description,
+        details,
+        start,
+        anchor,
+        priority,
+    ) {
+        Ok(t) => t,
+        Err(e) => return output::fail(&e),
+    };
+    if json {
+        output::print_json(&task_to_json(&t));
+    } else {
+        let walk = state::start_walk(start, "task", &t.name)
+            .map(|w| state::walk_note(&w))
+            .unwrap_or_default();
+        output::print_plain(&format!(
+            "added task {}: {} (feature: {}){}",
+            t.id,
+            t.name,
+            feature_name(conn, feature_id),
+            walk
+        ));
+    }
+    0
+}
+
+/// The position a new task takes: after every sibling, or where `anchor`
+/// says, in which case the siblings from that point on move back one.
+fn make_room(conn: &Connection, feature_id: i64, anchor: Option<&Anchor>) -> rusqlite::Result<i64> {
+    let (sibling, after) = match anchor {
+        None => {
+            return conn.query_row(
+                "SELECT COALESCE(MAX(position), 0) + 1 FROM tasks WHERE feature_id=?1",
+                params![feature_id],
+                |r| r.get(0),
+            )
         }
+        Some(Anchor::Before(id)) => (*id, false),
+        Some(Anchor::After(id)) => (*id, true),
+    };
+    // Renumber the whole feature, leaving a gap beside the anchor, so tasks
+    // that shared a position or were spaced apart still keep their order.
+    let order: Vec<i64> = conn
+        .prepare("SELECT id FROM tasks WHERE feature_id=?1 ORDER BY position, id")?
+        .query_map(params![feature_id], |r| r.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut next = 1;
+    let mut gap = next;
+    for id in order {
+        if id == sibling && !after {
+            gap = next;
+            next += 1;
+        }
+        conn.execute(
+            "UPDATE tasks SET position=?1 WHERE id=?2",
+            params![next, id],
+        )?;
+        next += 1;
+        if id == sibling && after {
+            gap = next;
+            next += 1;
+        }
+    }
+    Ok(gap)
+}
+
+/// Put the given tasks of a feature first, in the order given, followed by
+/// the rest in their current order, and renumber the feature from 1.
+pub fn reorder(conn: &Connection, feature_id: i64, first: &[i64], json: bool) -> i32 {
+    if let Some(i) = (1..first.len()).find(|&i| first[..i].contains(&first[i])) {
+        let name: String = conn
+            .query_row(
+                "SELECT name FROM tasks WHERE id=?1",
+                params![first[i]],
+                |r| r.get(0),
+            )
+            .unwrap_or_default();
+        output::err(&format!("task {} is named more than once", name));
+        return 1;
+    }
+    if let Some(fname) = feature::locked_name(conn, feature_id) {
+        output::err(&format!(
+            "feature {} is locked; cannot reorder its tasks (unlock it first)",
+            fname
+        ));
+        return output::EXIT_LOCKED;
+    }
+    let current: Vec<(i64, String)> = match conn
+        .prepare("SELECT id, name FROM tasks WHERE feature_id=?1 ORDER BY position, id")
+        .and_then(|mut stmt| {
+            stmt.query_map(params![feature_id], |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        }) {
+        Ok(c) => c,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let mut order: Vec<&(i64, String)> = first
+        .iter()
+        .filter_map(|id| current.iter().find(|(t, _)| t == id))
+        .collect();
+    order.extend(current.iter().filter(|(t, _)| !first.contains(t)));
+    let tx = match db::write_tx(conn) {
+        Ok(tx) => tx,
         Err(e) => {
             output::err(&e.to_string());
-            2
+            return 2;
         }
+    };
+    for (i, (id, _)) in order.iter().enumerate() {
+        if let Err(e) = tx.execute(
+            "UPDATE tasks SET position=?1 WHERE id=?2",
+            params![i as i64 + 1, id],
+        ) {
+            output::err(&e.to_string());
+            return 2;
+        }
+    }
+    if let Err(e) = tx.commit() {
+        output::err(&e.to_string());
+        return 2;
     }
+    if json {
+        output::print_json(&Value::Array(
+            order
+                .iter()
+                .enumerate()
+                .map(|(i, (id, name))| {
+                    json!({"position": i + 1, "id": id, "ref": resolve::short_ref("task", *id), "name": name})
+                })
+                .collect(),
+        ));
+    } else {
+        output::print_plain(&format!(
+            "reordered tasks in feature {}:",
+            feature_name(conn, feature_id)
+        ));
+        for (i, (id, name)) in order.iter().enumerate() {
+            outln!(
+                "{:>3}. {:<5} {}",
+                i + 1,
+                resolve::short_ref("task", *id),
+                name
+            );
+        }
+    }
+    0
 }
 
-pub fn list(conn: &Connection, feature_id: i64, state_filter: Option<&str>, json: bool) -> i32 {
-    let tasks: Vec<Task> = if let Some(s) = state_filter {
-        let mut stmt = conn.prepare(
-            "SELECT id, feature_id, name, description, details, state, last_worked_on FROM tasks WHERE feature_id=?1 AND state=?2 ORDER BY id"
-        ).unwrap();
-        stmt.query_map(params![feature_id, s], |r| {
-            Ok(Task {
-                id: r.get(0)?,
-                feature_id: r.get(1)?,
-                name: r.get(2)?,
-                description: r.get(3)?,
-                details: r.get(4)?,
-                state: r.get(5)?,
-                last_worked_on: r.get(6)?,
-            })
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
-    } else {
-        let mut stmt = conn.prepare(
-            "SELECT id, feature_id, name, description, details, state, last_worked_on FROM tasks WHERE feature_id=?1 ORDER BY id"
-        ).unwrap();
-        stmt.query_map(params![feature_id], |r| {
-            Ok(Task {
-                id: r.get(0)?,
-                feature_id: r.get(1)?,
-                name: r.get(2)?,
-                description: r.get(3)?,
-                details: r.get(4)?,
-                state: r.get(5)?,
-                last_worked_on: r.get(6)?,
-            })
-        })
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect()
+/// The SQL behind each of `COLUMNS`, for `--sort`: states in lifecycle order
+/// and priorities most urgent first.
+fn sort_sql(field: &str) -> String {
+    match field {
+        "priority" => priority::rank_sql("priority"),
+        "state" => state::rank_sql("state"),
+        "name" => names::order_sql("name"),
+        "ref" => "id".to_string(),
+        field => field.to_string(),
+    }
+}
+
+/// The query behind `all`, ordered but not paged; `?1` is the feature, `?2`
+/// the state filter, `?3` the ready flag and `?4` the priority filter.
+fn all_sql(sort: Option<Sort>) -> String {
+    let order = match sort {
+        Some(sort) => sort.order_by(&sort_sql(sort.field), "position, id"),
+        None => "position, id".to_string(),
+    };
+    format!(
+        "SELECT id, feature_id, name, description, details, state, last_worked_on, position, priority
+             FROM tasks t
+             WHERE feature_id=?1 AND (?2 IS NULL OR state=?2) AND (?3 = 0 OR ({}))
+               AND (?4 IS NULL OR priority=?4)
+             ORDER BY {}",
+        dependency::READY_SQL,
+        order
+    )
+}
+
+/// The feature's tasks in work order, or by `sort` with work order breaking
+/// ties, optionally only those in one state or of one priority, and
+/// with `ready` only those not Complete whose dependencies all are; only
+/// `page` of them.
+pub fn all(
+    conn: &Connection,
+    feature_id: i64,
+    state_filter: Option<&str>,
+    priority_filter: Option<Priority>,
+    ready: bool,
+    sort: Option<Sort>,
+    page: Page,
+) -> Result<Vec<Task>, Error> {
+    let mut stmt = conn.prepare(&format!("{}{}", all_sql(sort), page.sql()))?;
+    let tasks = stmt
+        .query_map(
+            params![
+                feature_id,
+                state_filter,
+                ready,
+                priority_filter.map(|p| p.to_string())
+            ],
+            |r| {
+                Ok(Task {
+                    id: r.get(0)?,
+                    feature_id: r.get(1)?,
+                    name: r.get(2)?,
+                    description: r.get(3)?,
+                    details: r.get(4)?,
+                    state: r.get(5)?,
+                    last_worked_on: r.get(6)?,
+                    position: r.get(7)?,
+                    priority: r.get(8)?,
+                })
+            },
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(tasks)
+}
+
+/// How many tasks `all` returns without a page.
+pub fn count(
+    conn: &Connection,
+    feature_id: i64,
+    state_filter: Option<&str>,
+    priority_filter: Option<Priority>,
+    ready: bool,
+) -> Result<i64, Error> {
+    Ok(fields::count(
+        conn,
+        &all_sql(None),
+        params![
+            feature_id,
+            state_filter,
+            ready,
+            priority_filter.map(|p| p.to_string())
+        ],
+    )?)
+}
+
+/// `all`, printed, with only `fields` when given and, when paged, the total.
+#[allow(clippy::too_many_arguments)]
+pub fn list(
+    conn: &Connection,
+    feature_id: i64,
+    state_filter: Option<&str>,
+    priority_filter: Option<Priority>,
+    ready: bool,
+    fields: Option<&[&str]>,
+    sort: Option<Sort>,
+    page: Page,
+    json: bool,
+) -> i32 {
+    let listed = all(
+        conn,
+        feature_id,
+        state_filter,
+        priority_filter,
+        ready,
+        sort,
+        page,
+    )
+    .and_then(|t| {
+        let total = fields::wants_total(page)
+            .then(|| count(conn, feature_id, state_filter, priority_filter, ready))
+            .transpose()?;
+        Ok((t, total))
+    });
+    let (tasks, total) = match listed {
+        Ok(l) => l,
+        Err(e) => return output::fail(&e),
     };
-    if tasks.is_empty() {
-        output::print_plain("no tasks found");
+    if tasks.is_empty() && fields::empty_in_prose(json, total) {
+        output::print_empty("no tasks found");
         return 0;
     }
-    if json {
-        output::print_json(&Value::Array(tasks.iter().map(task_to_json).collect()));
+    if json || fields.is_some() {
+        let rows = tasks.iter().map(task_to_json).collect();
+        fields::print_list(COLUMNS, fields, rows, total, json);
     } else {
         for t in &tasks {
-            println!(
-                "{:<4} {:<20} {:<12} {}",
-                t.id, t.name, t.state, t.last_worked_on
+            outln!(
+                "{:<5} {:<20} {:<12} {:<9} {}{}",
+                resolve::short_ref("task", t.id),
+                t.name,
+                t.state,
+                t.priority,
+                t.last_worked_on,
+                reason::blocked_tag(conn, "tasks", t.id, &t.state)
             );
         }
     }
+    if !json {
+        fields::print_more(page, tasks.len(), total);
+    }
     0
 }
 
-pub fn show(conn: &Connection, id: i64, json: bool) -> i32 {
-    let t = match load(conn, id) {
+/// With `with_ancestors`, the full records of the feature, module, and
+/// project above the task follow: nested under `ancestors` in JSON, as
+/// labelled sections in plain text.
+pub fn show(
+    conn: &Connection,
+    id: i64,
+    with_ancestors: bool,
+    with_history: bool,
+    json: bool,
+) -> i32 {
+    let t = match get(conn, id) {
         Ok(t) => t,
-        Err(e) => {
-            output::err(&e);
-            return 1;
+        Err(e) => return output::fail(&e),
+    };
+    let ancestors = if with_ancestors {
+        match ancestry::ancestors_json(conn, "task", id) {
+            Ok(a) => Some(a),
+            Err(e) => return output::fail(&e),
         }
+    } else {
+        None
     };
+    let changes = if with_history {
+        match history::changes(conn, "task", id) {
+            Ok(c) => Some(c),
+            Err(e) => return output::fail(&e.into()),
+        }
+    } else {
+        None
+    };
+    let last_reason = reason::last(conn, "tasks", id);
+    let blocked = reason::blocked(conn, "tasks", id, &t.state);
     let fname = feature_name(conn, t.feature_id);
 
-    let mut rstmt = conn
-        .prepare(
-            "SELECT r.id, r.name, r.description FROM research r
+    let research = match db::triples(
+        conn,
+        "SELECT r.id, r.name, r.description FROM research r
          JOIN research_tasks rt ON rt.research_id=r.id
          WHERE rt.task_id=?1 ORDER BY r.id",
-        )
-        .unwrap();
-    let research: Vec<(i64, String, String)> = rstmt
-        .query_map(params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
-        .unwrap()
-        .filter_map(|r| r.ok())
-        .collect();
+        id,
+    ) {
+        Ok(r) => r,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+
+    // Every task in the same feature, this one included, in work order.
+    let siblings = match db::triples(
+        conn,
+        "SELECT id, name, state FROM tasks WHERE feature_id=?1 ORDER BY position, id",
+        t.feature_id,
+    ) {
+        Ok(r) => r,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+
+    let depends_on = match dependency::depends_on(conn, id) {
+        Ok(d) => d,
+        Err(e) => return output::fail(&e.into()),
+    };
+    let blocks = match dependency::blocks(conn, id) {
+        Ok(b) => b,
+        Err(e) => return output::fail(&e.into()),
+    };
 
     if json {
         let mut v = task_to_json(&t);
+        v["last_transition_reason"] = json!(last_reason);
+        v["blocked_reason"] = json!(blocked);
+        v["allowed_transitions"] = json!(state::allowed_names(&t.state));
         v["feature"] = Value::String(fname);
+        v["depends_on"] = Value::Array(depends_on.iter().map(Dep::to_json).collect());
+        v["blocks"] = Value::Array(blocks.iter().map(Dep::to_json).collect());
+        v["siblings"] = Value::Array(
+            siblings
+                .iter()
+                .enumerate()
+                .map(|(i, (sid, name, state))| {
+                    json!({
+                        "position": i + 1, "id": sid, "ref": resolve::short_ref("task", *sid),
+                        "name": name, "state": state, "current": *sid == t.id,
+                    })
+                })
+                .collect(),
+        );
         v["research"] = Value::Array(
             research
                 .iter()
-                .map(|(id, name, desc)| json!({"id": id, "name": name, "description": desc}))
+                .map(|(id, name, desc)| {
+                    json!({
+                        "id": id, "ref": resolve::short_ref("research", *id),
+                        "name": name, "description": desc,
+                    })
+                })
                 .collect(),
         );
+        if let Some(a) = ancestors {
+            v["ancestors"] = a;
+        }
+        if let Some(changes) = &changes {
+            v["history"] = history::timeline_json(changes);
+        }
         output::print_json(&v);
     } else {
-        println!("{}", output::field("id", &t.id.to_string()));
-        println!("{}", output::field("name", &t.name));
-        println!("{}", output::field("feature", &fname));
-        println!("{}", output::field("description", &t.description));
-        println!("{}", output::field("details", &t.details));
-        println!("{}", output::field("state", &t.state));
-        println!("{}", output::field("last_worked_on", &t.last_worked_on));
+        outln!("{}", output::field("id", &t.id.to_string()));
+        outln!(
+            "{}",
+            output::field("ref", &resolve::short_ref("task", t.id))
+        );
+        outln!("{}", output::field("name", &t.name));
+        outln!("{}", output::field("feature", &fname));
+        outln!("{}", output::field("description", &t.description));
+        outln!("{}", output::field("details", &t.details));
+        outln!("{}", output::field("state", &t.state));
+        let next = state::allowed_names(&t.state);
+        if !next.is_empty() {
+            outln!("{}", output::field("next", &next.join(" | ")));
+        }
+        if let Some(r) = &blocked {
+            outln!("{}", output::field("blocked", reason::blocked_text(r)));
+        }
+        outln!("{}", output::field("priority", &t.priority));
+        if let Some(r) = &last_reason {
+            outln!("{}", output::field("last_transition_reason", r));
+        }
+        outln!("{}", output::field("last_worked_on", &t.last_worked_on));
+        for (label, deps) in [("depends_on", &depends_on), ("blocks", &blocks)] {
+            if !deps.is_empty() {
+                let list: Vec<String> = deps.iter().map(Dep::label).collect();
+                outln!("{}", output::field(label, &list.join(", ")));
+            }
+        }
+        if siblings.len() > 1 {
+            outln!();
+            outln!("siblings:");
+            for (i, (sid, name, state)) in siblings.iter().enumerate() {
+                let marker = if *sid == t.id { ">" } else { " " };
+                outln!(
+                    "{} {:>3}. {:<5} {:<20} {}",
+                    marker,
+                    i + 1,
+                    resolve::short_ref("task", *sid),
+                    name,
+                    state
+                );
+            }
+        }
         if !research.is_empty() {
-            println!();
-            println!("research:");
+            outln!();
+            outln!("research:");
             for (rid, rname, rdesc) in &research {
-                println!("  {:<4} {:<20} {}", rid, rname, rdesc);
+                outln!(
+                    "  {:<5} {:<20} {}",
+                    resolve::short_ref("research", *rid),
+                    rname,
+                    rdesc
+                );
             }
         }
+        if let Some(a) = &ancestors {
+            ancestry::print_ancestors(a);
+        }
+        if let Some(changes) = &changes {
+            outln!();
+            outln!("history:");
+            history::print_timeline(changes);
+        }
     }
     0
 }
 
-pub fn rename(conn: &Connection, id: i64, new_name: &str, json: bool) -> i32 {
-    let new_name = new_name.trim();
+/// Rename a task, and with `update_refs` rewrite whole-word mentions of
+/// the old name across its project. A dry run finds the references and
+/// writes nothing.
+pub fn rename_to(
+    conn: &Connection,
+    id: i64,
+    new_name: &str,
+    update_refs: bool,
+    dry_run: bool,
+) -> Result<Renamed<Task>, Error> {
+    let new_name = names::normalize(new_name);
     if new_name.is_empty() {
-        output::err("name must not be empty");
-        return 1;
+        return Err(Error::Invalid("name must not be empty".into()));
     }
-    let old = match load(conn, id) {
-        Ok(t) => t,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+    let mut t = get(conn, id)?;
+    if let Some(existing) = names::clash(
+        conn,
+        "tasks",
+        Some(("feature_id", t.feature_id)),
+        &new_name,
+        Some(id),
+    )? {
+        return Err(Error::Conflict(taken(conn, t.feature_id, &existing)));
+    }
+    let (hits, touched) =
+        match references::rename(conn, "task", id, &t.name, &new_name, update_refs, dry_run) {
+            Ok(renamed) => renamed,
+            Err(e) if db::name_taken(&e) => {
+                return Err(Error::Conflict(taken(conn, t.feature_id, &new_name)))
+            }
+            Err(e) => return Err(e.into()),
+        };
+    let old_name = t.name.clone();
+    if let Some(ts) = touched {
+        t.name = new_name;
+        t.last_worked_on = ts;
+    }
+    Ok(Renamed {
+        entity: t,
+        old_name,
+        hits,
+    })
+}
+
+pub fn rename(
+    conn: &Connection,
+    id: i64,
+    new_name: &str,
+    update_refs: bool,
+    dry_run: bool,
+    json: bool,
+) -> i32 {
+    let r = match rename_to(conn, id, new_name, update_refs, dry_run) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
     };
-    conn.execute(
-        "UPDATE tasks SET name=?1, last_worked_on=?2 WHERE id=?3",
-        params![new_name, now(), id],
-    )
-    .unwrap();
+    let t = &r.entity;
+    if dry_run {
+        references::print_preview(
+            "task",
+            id,
+            &r.old_name,
+            &names::normalize(new_name),
+            &r.hits,
+            json,
+        );
+        return 0;
+    }
     if json {
-        output::print_json(&load(conn, id).map(|t| task_to_json(&t)).unwrap());
+        let mut v = task_to_json(t);
+        if update_refs {
+            v["references"] = references::hits_to_json(&r.hits);
+        }
+        output::print_json(&v);
     } else {
-        output::print_plain(&format!("renamed task {}: {} → {}", id, old.name, new_name));
+        let refs = if update_refs {
+            references::summary(&r.hits)
+        } else {
+            String::new()
+        };
+        output::print_plain(&format!(
+            "renamed task {}: {} → {}{}",
+            id, r.old_name, t.name, refs
+        ));
     }
     0
 }
 
+/// Replace the task's description.
+pub fn update_description(conn: &Connection, id: i64, desc: &str) -> Result<Task, Error> {
+    let mut t = get(conn, id)?;
+    let ts = timestamp::now();
+    conn.execute(
+        "UPDATE tasks SET description=?1, last_worked_on=?2 WHERE id=?3",
+        params![desc, ts, id],
+    )?;
+    t.description = desc.to_string();
+    t.last_worked_on = ts;
+    Ok(t)
+}
+
 pub fn set_description(conn: &Connection, id: i64, desc: &str, json: bool) -> i32 {
-    let t = match load(conn, id) {
+    let t = match update_description(conn, id, desc) {
         Ok(t) => t,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+        Err(e) => return output::fail(&e),
     };
-    conn.execute(
-        "UPDATE tasks SET description=?1, last_worked_on=?2 WHERE id=?3",
-        params![desc, now(), id],
-    )
-    .unwrap();
     if json {
-        output::print_json(&load(conn, id).map(|t| task_to_json(&t)).unwrap());
+        output::print_json(&task_to_json(&t));
     } else {
         output::print_plain(&format!("updated description for task: {}", t.name));
     }
     0
 }
 
-pub fn set_details(conn: &Connection, id: i64, details: &str, json: bool) -> i32 {
-    let t = match load(conn, id) {
-        Ok(t) => t,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+/// Set the task's priority. Returns the task and the priority it had.
+pub fn update_priority(
+    conn: &Connection,
+    id: i64,
+    priority: Priority,
+) -> Result<(Task, String), Error> {
+    let mut t = get(conn, id)?;
+    let ts = timestamp::now();
+    conn.execute(
+        "UPDATE tasks SET priority=?1, last_worked_on=?2 WHERE id=?3",
+        params![priority.to_string(), ts, id],
+    )?;
+    let old = std::mem::replace(&mut t.priority, priority.to_string());
+    t.last_worked_on = ts;
+    Ok((t, old))
+}
+
+pub fn set_priority(conn: &Connection, id: i64, priority: Priority, json: bool) -> i32 {
+    let (t, old) = match update_priority(conn, id, priority) {
+        Ok(changed) => changed,
+        Err(e) => return output::fail(&e),
     };
+    if json {
+        output::print_json(&task_to_json(&t));
+    } else {
+        output::print_plain(&format!(
+            "task {}: priority {} → {}",
+            t.name, old, t.priority
+        ));
+    }
+    0
+}
+
+/// Replace the details, or add `details` after or before them per `placement`.
+pub fn update_details(
+    conn: &Connection,
+    id: i64,
+    details: &str,
+    placement: Placement,
+) -> Result<Task, Error> {
+    let mut t = get(conn, id)?;
+    let details = placement.merge(&t.details, details);
+    let ts = timestamp::now();
     conn.execute(
         "UPDATE tasks SET details=?1, last_worked_on=?2 WHERE id=?3",
-        params![details, now(), id],
-    )
-    .unwrap();
+        params![details, ts, id],
+    )?;
+    t.details = details;
+    t.last_worked_on = ts;
+    Ok(t)
+}
+
+pub fn set_details(
+    conn: &Connection,
+    id: i64,
+    details: &str,
+    placement: Placement,
+    json: bool,
+) -> i32 {
+    let t = match update_details(conn, id, details, placement) {
+        Ok(t) => t,
+        Err(e) => return output::fail(&e),
+    };
     if json {
-        output::print_json(&load(conn, id).map(|t| task_to_json(&t)).unwrap());
+        output::print_json(&task_to_json(&t));
     } else {
-        output::print_plain(&format!("updated details for task: {}", t.name));
+        output::print_plain(&format!(
+            "{} details for task: {}",
+            placement.verb(),
+            t.name
+        ));
     }
     0
 }
 
-pub fn transition(conn: &Connection, id: i64, to_state: &State, json: bool) -> i32 {
-    let t = match load(conn, id) {
+/// Move a task to `to_state`. Moving into Planning or Building also looks at
+/// parents still in an earlier stage and, per `parents`, reports them or lifts
+/// them in the same transaction. A `reason` is kept as the last_transition_reason
+/// and appended to the details with a timestamp. Moving into Building or
+/// Complete is refused while a dependency is not Complete, unless `force`.
+/// Moving to the state it is in changes nothing.
+pub fn transition_to(
+    conn: &Connection,
+    id: i64,
+    to_state: &State,
+    parents: ParentPolicy,
+    reason: Option<&str>,
+    force: bool,
+) -> Result<Transitioned<Task>, Error> {
+    let mut t = get(conn, id)?;
+    let from = t.state.clone();
+    let moves = validate_transition(&from, to_state)
+        .map_err(|e| Error::InvalidTransition(format!("{} for task {}", e, t.name)))?;
+    if !moves {
+        return Ok(Transitioned::new(t, from));
+    }
+    let forced = dependency::guard(conn, id, &t.name, to_state, force)?;
+    let ts = timestamp::now();
+    let reason = reason::given(reason);
+    let details = reason::append(&t.details, &from, to_state, reason, &ts);
+    let behind = lift::behind(conn, "task", id, to_state)?;
+    let (lifted, behind) = match parents {
+        ParentPolicy::Lift => (behind, Vec::new()),
+        ParentPolicy::Warn => (Vec::new(), behind),
+        ParentPolicy::Ignore => (Vec::new(), Vec::new()),
+    };
+    let tx = db::write_tx(conn)?;
+    lift::apply(&tx, &lifted, &ts)?;
+    tx.execute(
+        "UPDATE tasks SET state=?1, last_worked_on=?2, details=?3, last_transition_reason=?4 WHERE id=?5",
+        params![to_state.to_string(), ts, details, reason.unwrap_or(""), id],
+    )?;
+    history::record_state(&tx, "task", id, &from, &to_state.to_string(), reason, &ts)?;
+    tx.commit()?;
+    t.state = to_state.to_string();
+    t.last_worked_on = ts;
+    t.details = details;
+    let mut moved = Transitioned::new(t, from);
+    moved.moved = true;
+    moved.reason = reason.map(str::to_string);
+    moved.lifted = lifted;
+    moved.behind = behind;
+    moved.forced.extend(forced);
+    // Only a move to Complete changes what is left of the parent, so only then say so.
+    if *to_state == State::Complete {
+        moved.parent_progress = progress::of_parent(conn, "task", id)?;
+    }
+    Ok(moved)
+}
+
+pub fn transition(
+    conn: &Connection,
+    id: i64,
+    to_state: &State,
+    parents: ParentPolicy,
+    reason: Option<&str>,
+    force: bool,
+    json: bool,
+) -> i32 {
+    let moved = match transition_to(conn, id, to_state, parents, reason, force) {
+        Ok(m) => m,
+        Err(e) => return output::fail(&e),
+    };
+    if !moved.moved {
+        return 0;
+    }
+    let t = &moved.entity;
+    moved.warn("task", &t.name, to_state);
+    if json {
+        let mut v = task_to_json(t);
+        v["reason"] = json!(moved.reason);
+        if let Some(p) = &moved.parent_progress {
+            v["parent_progress"] = p.to_json();
+        }
+        if !moved.lifted.is_empty() {
+            v["lifted_parents"] = json!(moved
+                .lifted
+                .iter()
+                .map(lift::Lift::to_json)
+                .collect::<Vec<_>>());
+        }
+        output::print_json(&v);
+    } else {
+        lift::report(&moved.lifted);
+        let summary = moved
+            .parent_progress
+            .as_ref()
+            .map(|p| format!(" ({})", p.summary()))
+            .unwrap_or_default();
+        output::print_plain(&format!(
+            "task {}: {} → {}{}",
+            t.name, moved.from, to_state, summary
+        ));
+    }
+    0
+}
+
+/// The task to log progress on when none is named: of the tasks in the `use`
+/// context feature still in Building, the one worked on most recently.
+pub fn current_in(conn: &Connection, feature_id: i64) -> Result<i64, Error> {
+    conn.query_row(
+        "SELECT id FROM tasks WHERE feature_id=?1 AND state='Building'
+         ORDER BY last_worked_on DESC, id DESC LIMIT 1",
+        params![feature_id],
+        |r| r.get(0),
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => Error::not_found(
+            "task",
+            format!(
+                "no task in Building under feature {}; pass --task",
+                feature_name(conn, feature_id)
+            ),
+        ),
+        e => Error::Db(e),
+    })
+}
+
+/// Log progress on a task in one step: append a timestamped `text` line to
+/// its details and mark it, its feature, and its module as worked on now.
+/// With `touch_state`, a Draft or Planning task also moves to Building, its
+/// parents handled per `parents` as in `transition`. All in one transaction.
+pub fn progress(
+    conn: &Connection,
+    id: i64,
+    text: &str,
+    touch_state: bool,
+    parents: ParentPolicy,
+    json: bool,
+) -> i32 {
+    let mut t = match get(conn, id) {
         Ok(t) => t,
-        Err(e) => {
-            output::err(&e);
-            return 1;
+        Err(e) => return output::fail(&e),
+    };
+    let text = text.trim();
+    if text.is_empty() {
+        output::err("progress text is empty");
+        return 1;
+    }
+    let ts = timestamp::now();
+    let line = format!("[{}] {}", ts, text);
+    let details = if t.details.trim().is_empty() {
+        line
+    } else {
+        format!(
+            "{}
+{}",
+            t.details.trim_end(),
+            line
+        )
+    };
+    let from = t.state.clone();
+    let moved = touch_state && matches!(from.as_str(), "Draft" | "Planning");
+    if moved {
+        // Logging work is never refused; unmet dependencies are only warned about.
+        match dependency::guard(conn, id, &t.name, &State::Building, true) {
+            Ok(Some(w)) => eprintln!("warning: {}", w),
+            Ok(None) => {}
+            Err(e) => return output::fail(&e),
+        }
+    }
+    let lifted = if moved {
+        let behind = match lift::behind(conn, "task", id, &State::Building) {
+            Ok(b) => b,
+            Err(e) => return output::fail(&e.into()),
+        };
+        match parents {
+            ParentPolicy::Lift => behind,
+            ParentPolicy::Warn => {
+                lift::warn(&behind);
+                Vec::new()
+            }
+            ParentPolicy::Ignore => Vec::new(),
         }
+    } else {
+        Vec::new()
+    };
+    let state = if moved {
+        State::Building.to_string()
+    } else {
+        from.clone()
     };
-    match validate_transition(&t.state, to_state) {
+    let crumb = match ancestry::breadcrumb(conn, "task", id) {
+        Ok(c) => c,
+        Err(e) => return output::fail(&e.into()),
+    };
+    let tx = match db::write_tx(conn) {
+        Ok(tx) => tx,
         Err(e) => {
-            output::err(&format!("{} for task {}", e, t.name));
-            return 1;
+            output::err(&e.to_string());
+            return 2;
         }
-        Ok(false) => return 0,
-        Ok(true) => {}
+    };
+    if let Err(e) = lift::apply(&tx, &lifted, &ts) {
+        return output::fail(&e);
     }
-    let from = t.state.clone();
-    conn.execute(
-        "UPDATE tasks SET state=?1, last_worked_on=?2 WHERE id=?3",
-        params![to_state.to_string(), now(), id],
-    )
-    .unwrap();
+    if let Err(e) = tx
+        .execute(
+            "UPDATE tasks SET details=?1, state=?2, last_worked_on=?3 WHERE id=?4",
+            params![details, state, ts, id],
+        )
+        .and_then(|_| history::record_state(&tx, "task", id, &from, &state, None, &ts))
+        .and_then(|_| {
+            tx.execute(
+                "UPDATE features SET last_worked_on=?1 WHERE id=?2",
+                params![ts, t.feature_id],
+            )
+        })
+        .and_then(|_| {
+            tx.execute(
+                "UPDATE modules SET last_worked_on=?1
+                 WHERE id=(SELECT module_id FROM features WHERE id=?2)",
+                params![ts, t.feature_id],
+            )
+        })
+        .and_then(|_| tx.commit())
+    {
+        output::err(&e.to_string());
+        return 2;
+    }
+    t.details = details;
+    t.state = state;
+    t.last_worked_on = ts;
     if json {
-        output::print_json(&load(conn, id).map(|t| task_to_json(&t)).unwrap());
+        let mut v = task_to_json(&t);
+        v["breadcrumb"] = json!(crumb);
+        v["details_length"] = json!(t.details.chars().count());
+        v["moved"] = json!(moved.then(|| json!({"from": from, "to": t.state})));
+        if !lifted.is_empty() {
+            v["lifted_parents"] = json!(lifted.iter().map(lift::Lift::to_json).collect::<Vec<_>>());
+        }
+        output::print_json(&v);
     } else {
-        output::print_plain(&format!("task {}: {} → {}", t.name, from, to_state));
+        lift::report(&lifted);
+        let moved = if moved {
+            format!(", {} → {}", from, t.state)
+        } else {
+            String::new()
+        };
+        output::print_plain(&format!(
+            "progress on {} [{}{}]: details now {} chars",
+            crumb,
+            t.state,
+            moved,
+            t.details.chars().count()
+        ));
     }
     0
 }
 
-pub fn remove(conn: &Connection, id: i64, json: bool) -> i32 {
-    let t = match load(conn, id) {
-        Ok(t) => t,
-        Err(e) => {
-            output::err(&e);
-            return 1;
-        }
+/// Delete a task; `soft` puts it in the trash.
+pub fn delete(conn: &Connection, id: i64, soft: bool) -> Result<Removed<Task>, Error> {
+    let t = get(conn, id)?;
+    if let Some(fname) = feature::locked_name(conn, t.feature_id) {
+        return Err(Error::Locked(format!(
+            "feature {} is locked; cannot remove task {} (unlock it first)",
+            fname, t.name
+        )));
+    }
+    let tx = db::write_tx(conn)?;
+    if soft {
+        trash::bury(&tx, "task", id)?;
+    }
+    tx.execute("DELETE FROM tasks WHERE id=?1", params![id])?;
+    tx.commit()?;
+    Ok(Removed {
+        entity: t,
+        trashed: soft,
+        relinked: None,
+        warnings: Vec::new(),
+    })
+}
+
+pub fn remove(conn: &Connection, id: i64, soft: bool, json: bool) -> i32 {
+    let removed = match delete(conn, id, soft) {
+        Ok(r) => r,
+        Err(e) => return output::fail(&e),
     };
-    conn.execute("DELETE FROM tasks WHERE id=?1", params![id])
-        .unwrap();
     if json {
-        output::print_json(&json!({"deleted": true, "id": id}));
+        output::print_json(&json!({"deleted": true, "id": id, "trashed": soft}));
     } else {
-        output::print_plain(&format!("removed task {}: {}", id, t.name));
+        output::print_plain(&format!("removed task {}: {}", id, removed.entity.name));
+        if soft {
+            output::print_plain(&trash::buried_note("task", id));
+        }
     }
     0
 }
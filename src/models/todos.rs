@@ -0,0 +1,272 @@
+use crate::db;
+use crate::models::bootstrap::slugify;
+use crate::models::feature;
+use crate::names;
+use crate::output;
+use crate::resolve;
+use crate::timestamp;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+/// Longest slug taken from a TODO line; the rest stays in the description.
+const NAME_CHARS: usize = 48;
+
+/// A TODO or FIXME line found in a feature's details, proposed as a task.
+struct Todo {
+    /// Zero-based line index in the details.
+    line: usize,
+    name: String,
+    /// The line without its list marker or indentation.
+    description: String,
+    /// Set once the task is created.
+    id: Option<i64>,
+}
+
+impl Todo {
+    fn to_json(&self) -> Value {
+        json!({
+            "line": self.line + 1,
+            "name": self.name,
+            "description": self.description,
+            "id": self.id,
+            "ref": self.id.map(|id| resolve::short_ref("task", id)),
+        })
+    }
+}
+
+/// The text of a line without its indentation and markdown list prefix
+/// (bullet or number, checkbox, blockquote).
+fn list_item(line: &str) -> &str {
+    let mut rest = line.trim_start();
+    loop {
+        let before = rest;
+        if let Some(r) = rest.strip_prefix("> ") {
+            rest = r;
+        }
+        for bullet in ["- ", "* ", "+ "] {
+            if let Some(r) = rest.strip_prefix(bullet) {
+                rest = r;
+            }
+        }
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits > 0 {
+            if let Some(r) = rest[digits..]
+                .strip_prefix(". ")
+                .or_else(|| rest[digits..].strip_prefix(") "))
+            {
+                rest = r;
+            }
+        }
+        for checkbox in ["[ ] ", "[x] ", "[X] "] {
+            if let Some(r) = rest.strip_prefix(checkbox) {
+                rest = r;
+            }
+        }
+        rest = rest.trim_start();
+        if rest == before {
+            break;
+        }
+    }
+    rest
+}
+
+/// What a TODO or FIXME item asks for, e.g. `handle token refresh` from
+/// `**TODO(ana):** handle token refresh`; None when the text is no such item.
+fn todo_text(item: &str) -> Option<&str> {
+    let item = item.trim_start_matches(['*', '_']);
+    let marker = ["TODO", "FIXME"]
+        .into_iter()
+        .find(|m| item.starts_with(m))?;
+    let rest = &item[marker.len()..];
+    // TODOS, FIXMEd, and the like are words, not markers.
+    if rest.chars().next().is_some_and(|c| c.is_alphanumeric()) {
+        return None;
+    }
+    let mut rest = rest.trim_start_matches(['*', '_']);
+    if rest.starts_with('(') {
+        rest = rest.split_once(')').map(|(_, r)| r).unwrap_or("");
+    }
+    Some(rest.trim_start_matches(['*', '_', ':', '-', ' ']).trim())
+}
+
+/// Whether the line already points at a task by short ref, as `(task T12)`.
+fn references_task(line: &str) -> bool {
+    line.split(|c: char| !c.is_alphanumeric())
+        .any(|w| w.starts_with('T') && resolve::parse_ref(w).is_some())
+}
+
+/// A slug from the TODO text, cut at a word boundary to `NAME_CHARS`.
+fn name_for(text: &str) -> String {
+    let slug = slugify(text);
+    if slug.chars().count() <= NAME_CHARS {
+        return slug;
+    }
+    let cut: String = slug.chars().take(NAME_CHARS).collect();
+    match cut.rfind('-') {
+        Some(i) if i > 0 => cut[..i].to_string(),
+        _ => cut,
+    }
+}
+
+/// TODO and FIXME lines outside code fences that do not yet reference a task,
+/// named so they clash with neither an existing task nor each other.
+fn scan(details: &str, taken: &[String]) -> Vec<Todo> {
+    let mut out: Vec<Todo> = Vec::new();
+    let mut fenced = false;
+    for (line, text) in details.lines().enumerate() {
+        let item = list_item(text);
+        if item.starts_with("```") || item.starts_with("~~~") {
+            fenced = !fenced;
+            continue;
+        }
+        if fenced || references_task(text) {
+            continue;
+        }
+        let Some(what) = todo_text(item) else {
+            continue;
+        };
+        let base = name_for(what);
+        if base.is_empty() {
+            continue;
+        }
+        let free = |n: &str| {
+            !taken.iter().any(|t| names::fold(t) == names::fold(n))
+                && !out.iter().any(|t| t.name == n)
+        };
+        let name = (1..)
+            .map(|i| {
+                if i == 1 {
+                    base.clone()
+                } else {
+                    format!("{}-{}", base, i)
+                }
+            })
+            .find(|n| free(n))
+            .unwrap_or(base);
+        out.push(Todo {
+            line,
+            name,
+            description: item.trim_end().to_string(),
+            id: None,
+        });
+    }
+    out
+}
+
+/// List the TODO and FIXME lines in a feature's details as proposed tasks.
+/// With `apply`, create them as Draft tasks at the end of the feature's work
+/// order and mark each line with its task's ref, in one transaction, so a
+/// second run finds nothing new.
+pub fn extract(conn: &Connection, feature_id: i64, apply: bool, json: bool) -> i32 {
+    let row: rusqlite::Result<(String, String)> = conn.query_row(
+        "SELECT name, details FROM features WHERE id=?1",
+        params![feature_id],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    );
+    let (fname, details) = match row {
+        Ok(r) => r,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let taken: Vec<String> = match conn
+        .prepare("SELECT name FROM tasks WHERE feature_id=?1")
+        .and_then(|mut stmt| {
+            stmt.query_map(params![feature_id], |r| r.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()
+        }) {
+        Ok(t) => t,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let mut todos = scan(&details, &taken);
+    if apply && !todos.is_empty() {
+        if let Some(name) = feature::locked_name(conn, feature_id) {
+            output::err(&format!(
+                "feature {} is locked; cannot add tasks (unlock it first)",
+                name
+            ));
+            return output::EXIT_LOCKED;
+        }
+        if let Err(e) = create(conn, feature_id, &details, &mut todos) {
+            output::err(&e.to_string());
+            return 2;
+        }
+    }
+    if json {
+        output::print_json(&json!({
+            "feature": fname,
+            "applied": apply,
+            "tasks": todos.iter().map(Todo::to_json).collect::<Vec<_>>(),
+        }));
+        return 0;
+    }
+    if todos.is_empty() {
+        output::print_plain(&format!(
+            "no untracked TODO or FIXME lines in the details of feature {}",
+            fname
+        ));
+        return 0;
+    }
+    if apply {
+        output::print_plain(&format!(
+            "created {} task(s) in feature {} from its details:",
+            todos.len(),
+            fname
+        ));
+    } else {
+        output::print_plain(&format!(
+            "would create {} task(s) in feature {} from its details (pass --apply to create them):",
+            todos.len(),
+            fname
+        ));
+    }
+    for t in &todos {
+        let id =
+            t.id.map(|id| format!("{:<5} ", resolve::short_ref("task", id)))
+                .unwrap_or_default();
+        outln!("  line {:<4} {}{}", t.line + 1, id, t.name);
+        outln!("            {}", output::one_line(&t.description, 72));
+    }
+    0
+}
+
+/// Insert the tasks and rewrite their lines as `<line> (task T12)`.
+fn create(
+    conn: &Connection,
+    feature_id: i64,
+    details: &str,
+    todos: &mut [Todo],
+) -> rusqlite::Result<()> {
+    let ts = timestamp::now();
+    let tx = db::write_tx(conn)?;
+    let mut lines: Vec<String> = details.lines().map(str::to_string).collect();
+    for t in todos.iter_mut() {
+        tx.execute(
+            "INSERT INTO tasks (feature_id, name, description, last_worked_on, position)
+             VALUES (?1,?2,?3,?4,
+                     (SELECT COALESCE(MAX(position), 0) + 1 FROM tasks WHERE feature_id=?1))",
+            params![feature_id, t.name, t.description, ts],
+        )?;
+        let id = tx.last_insert_rowid();
+        t.id = Some(id);
+        let line = &mut lines[t.line];
+        *line = format!(
+            "{} (task {})",
+            line.trim_end(),
+            resolve::short_ref("task", id)
+        );
+    }
+    let mut rewritten = lines.join("\n");
+    if details.ends_with('\n') {
+        rewritten.push('\n');
+    }
+    tx.execute(
+        "UPDATE features SET details=?1, last_worked_on=?2 WHERE id=?3",
+        params![rewritten, ts, feature_id],
+    )?;
+    tx.commit()
+}
@@ -0,0 +1,50 @@
+use crate::models::lift::{self, Lift};
+use crate::models::progress::Progress;
+use crate::models::reason;
+use crate::state::State;
+
+/// What a transition did, or that it found nothing to do.
+pub struct Transitioned<T> {
+    /// The entity in its new state, or as it was when it was already there.
+    pub entity: T,
+    /// The state it left; its own state when nothing moved.
+    pub from: String,
+    /// Whether the state changed.
+    pub moved: bool,
+    /// The reason recorded with the move; a blank one counts as none.
+    pub reason: Option<String>,
+    /// Parents moved forward with it, under `ParentPolicy::Lift`.
+    pub lifted: Vec<Lift>,
+    /// Parents still in an earlier stage, under `ParentPolicy::Warn`.
+    pub behind: Vec<Lift>,
+    /// What `force` went past: children or dependencies not Complete.
+    pub forced: Vec<String>,
+    /// How far the parent has got, after a feature or task moved to Complete.
+    pub parent_progress: Option<Progress>,
+}
+
+impl<T> Transitioned<T> {
+    /// `entity`, left in `from`, with nothing else to say yet.
+    pub fn new(entity: T, from: String) -> Transitioned<T> {
+        Transitioned {
+            entity,
+            from,
+            moved: false,
+            reason: None,
+            lifted: Vec::new(),
+            behind: Vec::new(),
+            forced: Vec::new(),
+            parent_progress: None,
+        }
+    }
+
+    /// Print on stderr what the CLI warns about after `kind` `name` moved to
+    /// `to`: what `force` went past, a missing reason, and parents behind.
+    pub fn warn(&self, kind: &str, name: &str, to: &State) {
+        for w in &self.forced {
+            eprintln!("warning: {}", w);
+        }
+        reason::warn_if_missing(kind, name, to, self.reason.as_deref());
+        lift::warn(&self.behind);
+    }
+}
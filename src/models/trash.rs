@@ -0,0 +1,654 @@
+use crate::config;
+use crate::content;
+use crate::db;
+use crate::error::Error;
+use crate::models::ancestry;
+use crate::models::attachment;
+use crate::names;
+use crate::output;
+use crate::resolve;
+use crate::timestamp;
+use rusqlite::types::{Value as Sql, ValueRef};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde_json::{json, Map, Value};
+
+/// Config key making every `remove` soft unless `--hard` is given.
+pub const SOFT_DELETE_KEY: &str = "soft_delete";
+
+/// Whether a remove goes to the trash: `--soft`, or the `soft_delete`
+/// setting without `--hard`.
+pub fn soft(soft: bool, hard: bool) -> bool {
+    soft || (!hard && config::get_bool(SOFT_DELETE_KEY, false))
+}
+
+fn table(kind: &str) -> &'static str {
+    match kind {
+        "project" => "projects",
+        "module" => "modules",
+        "feature" => "features",
+        "task" => "tasks",
+        _ => "research",
+    }
+}
+
+/// The parent column of an entity and the kind it points at.
+fn parent_of(kind: &str) -> Option<(&'static str, &'static str)> {
+    match kind {
+        "module" => Some(("project_id", "project")),
+        "feature" => Some(("module_id", "module")),
+        "task" => Some(("feature_id", "feature")),
+        _ => None,
+    }
+}
+
+/// Selects nothing, but still binds ?1 like its siblings.
+const NONE: &str = "SELECT NULL WHERE ?1 IS NULL";
+
+/// SQL over ?1 selecting the ids of each entity type in the subtree of one
+/// `kind` entity: projects, modules, features, tasks, research.
+fn subtree(kind: &str) -> [&'static str; 5] {
+    match kind {
+        "project" => [
+            "SELECT ?1",
+            "SELECT id FROM modules WHERE project_id=?1",
+            "SELECT f.id FROM features f JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1",
+            "SELECT t.id FROM tasks t JOIN features f ON f.id=t.feature_id
+             JOIN modules m ON m.id=f.module_id WHERE m.project_id=?1",
+            NONE,
+        ],
+        "module" => [
+            NONE,
+            "SELECT ?1",
+            "SELECT id FROM features WHERE module_id=?1",
+            "SELECT t.id FROM tasks t JOIN features f ON f.id=t.feature_id WHERE f.module_id=?1",
+            NONE,
+        ],
+        "feature" => [
+            NONE,
+            NONE,
+            "SELECT ?1",
+            "SELECT id FROM tasks WHERE feature_id=?1",
+            NONE,
+        ],
+        "task" => [NONE, NONE, NONE, "SELECT ?1", NONE],
+        _ => [NONE, NONE, NONE, NONE, "SELECT ?1"],
+    }
+}
+
+/// Every table holding rows of a subtree, in the order they are restored,
+/// with the rows that belong to it in terms of `{P}`, `{M}`, `{F}`, `{T}`,
+/// and `{R}`, the ids from `subtree`. Entities come first so the links and
+/// history after them find what they point at.
+const TABLES: [(&str, &str); 13] = [
+    ("projects", "id IN ({P})"),
+    ("modules", "id IN ({M})"),
+    ("features", "id IN ({F})"),
+    ("tasks", "id IN ({T})"),
+    ("research", "id IN ({R})"),
+    ("research_attachments", "research_id IN ({R})"),
+    (
+        "research_projects",
+        "project_id IN ({P}) OR research_id IN ({R})",
+    ),
+    ("research_modules", "module_id IN ({M}) OR research_id IN ({R})"),
+    (
+        "research_features",
+        "feature_id IN ({F}) OR research_id IN ({R})",
+    ),
+    ("research_tasks", "task_id IN ({T}) OR research_id IN ({R})"),
+    (
+        "task_dependencies",
+        "task_id IN ({T}) OR depends_on_task_id IN ({T})",
+    ),
+    (
+        "state_history",
+        "(entity_type='project' AND entity_id IN ({P})) OR (entity_type='module' AND entity_id IN ({M}))
+         OR (entity_type='feature' AND entity_id IN ({F})) OR (entity_type='task' AND entity_id IN ({T}))",
+    ),
+    (
+        "name_history",
+        "(entity_type='project' AND entity_id IN ({P})) OR (entity_type='module' AND entity_id IN ({M}))
+         OR (entity_type='feature' AND entity_id IN ({F})) OR (entity_type='task' AND entity_id IN ({T}))
+         OR (entity_type='research' AND entity_id IN ({R}))",
+    ),
+];
+
+/// Tables whose rows only tie others together. Restoring one whose other end
+/// is gone skips it rather than failing the restore.
+const TIES: [&str; 5] = [
+    "research_projects",
+    "research_modules",
+    "research_features",
+    "research_tasks",
+    "task_dependencies",
+];
+
+/// The entity tables counted in a trash entry, in the order they nest.
+const COUNTED: [&str; 4] = ["modules", "features", "tasks", "research"];
+
+fn dump_rows(conn: &Connection, sql: &str, id: i64) -> rusqlite::Result<Vec<Value>> {
+    let mut stmt = conn.prepare(sql)?;
+    let names: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows = stmt.query(params![id])?;
+    let mut out = Vec::new();
+    while let Some(r) = rows.next()? {
+        let mut obj = Map::new();
+        for (i, name) in names.iter().enumerate() {
+            let v = match r.get_ref(i)? {
+                ValueRef::Null => Value::Null,
+                ValueRef::Integer(n) => json!(n),
+                ValueRef::Real(f) => json!(f),
+                ValueRef::Text(t) | ValueRef::Blob(t) => json!(String::from_utf8_lossy(t)),
+            };
+            obj.insert(name.clone(), v);
+        }
+        out.push(Value::Object(obj));
+    }
+    Ok(out)
+}
+
+/// Every row of the subtree of `kind` `id`, by table, exactly as stored.
+fn dump(conn: &Connection, kind: &str, id: i64) -> rusqlite::Result<Map<String, Value>> {
+    let [p, m, f, t, r] = subtree(kind);
+    let mut tables = Map::new();
+    for (name, rows) in TABLES {
+        let filter = rows
+            .replace("{P}", p)
+            .replace("{M}", m)
+            .replace("{F}", f)
+            .replace("{T}", t)
+            .replace("{R}", r);
+        let found = dump_rows(
+            conn,
+            &format!("SELECT * FROM {} WHERE {} ORDER BY rowid", name, filter),
+            id,
+        )?;
+        if !found.is_empty() {
+            tables.insert(name.to_string(), Value::Array(found));
+        }
+    }
+    Ok(tables)
+}
+
+/// Copy the subtree of `kind` `id` into the trash, before the caller deletes
+/// it as a hard remove would. Run inside the caller's transaction, ahead of
+/// anything else the remove changes, so the entry holds the subtree as it was.
+pub fn bury(conn: &Connection, kind: &str, id: i64) -> rusqlite::Result<()> {
+    let name: String = conn.query_row(
+        &format!("SELECT name FROM {} WHERE id=?1", table(kind)),
+        params![id],
+        |r| r.get(0),
+    )?;
+    let breadcrumb = if kind == "research" {
+        name.clone()
+    } else {
+        ancestry::breadcrumb(conn, kind, id)?
+    };
+    let document = json!({ "tables": dump(conn, kind, id)? });
+    conn.execute(
+        "INSERT INTO trash (entity_type, entity_id, name, breadcrumb, deleted_at, document)
+         VALUES (?1,?2,?3,?4,?5,?6)",
+        params![
+            kind,
+            id,
+            name,
+            breadcrumb,
+            timestamp::now(),
+            document.to_string()
+        ],
+    )?;
+    Ok(())
+}
+
+/// What a remove did.
+pub struct Removed<T> {
+    /// The entity as it was before it was removed.
+    pub entity: T,
+    /// Whether it went to the trash rather than being deleted for good.
+    pub trashed: bool,
+    /// With research relinked to the parent: the links created there, and
+    /// the research that was linked there already.
+    pub relinked: Option<(usize, usize)>,
+    /// Files that belonged to it and could not be deleted.
+    pub warnings: Vec<String>,
+}
+
+/// The plain-text note a soft remove prints after its usual line.
+pub fn buried_note(kind: &str, id: i64) -> String {
+    format!(
+        "moved to the trash; `trash restore --{} {}` brings it back",
+        kind, id
+    )
+}
+
+/// One entry in the trash.
+struct Entry {
+    id: i64,
+    kind: String,
+    entity_id: i64,
+    name: String,
+    breadcrumb: String,
+    deleted_at: String,
+    tables: Map<String, Value>,
+}
+
+impl Entry {
+    fn from_row(r: &rusqlite::Row) -> rusqlite::Result<Entry> {
+        let document: String = r.get(6)?;
+        let tables = serde_json::from_str::<Value>(&document)
+            .ok()
+            .and_then(|d| d["tables"].as_object().cloned())
+            .unwrap_or_default();
+        Ok(Entry {
+            id: r.get(0)?,
+            kind: r.get(1)?,
+            entity_id: r.get(2)?,
+            name: r.get(3)?,
+            breadcrumb: r.get(4)?,
+            deleted_at: r.get(5)?,
+            tables,
+        })
+    }
+
+    fn rows(&self, table: &str) -> &[Value] {
+        self.tables
+            .get(table)
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The row of the entity itself.
+    fn top(&self) -> Option<&Value> {
+        self.rows(table(&self.kind))
+            .iter()
+            .find(|r| r["id"].as_i64() == Some(self.entity_id))
+    }
+
+    /// Rows per counted table, the entity itself left out.
+    fn counts(&self) -> Vec<(&'static str, usize)> {
+        COUNTED
+            .iter()
+            .map(|t| {
+                let n = self.rows(t).len();
+                (
+                    *t,
+                    if *t == table(&self.kind) {
+                        n - n.min(1)
+                    } else {
+                        n
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn to_json(&self) -> Value {
+        let mut contains = Map::new();
+        for (t, n) in self.counts() {
+            contains.insert(t.to_string(), json!(n));
+        }
+        json!({
+            "type": self.kind,
+            "id": self.entity_id,
+            "ref": resolve::short_ref(&self.kind, self.entity_id),
+            "name": self.name,
+            "breadcrumb": self.breadcrumb,
+            "deleted_at": self.deleted_at,
+            "contains": contains,
+        })
+    }
+
+    /// `2 features, 5 tasks`, or empty when the entity stands alone.
+    fn summary(&self) -> String {
+        self.counts()
+            .iter()
+            .filter(|(_, n)| *n > 0)
+            .map(|(t, n)| format!("{} {}", n, t))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+const ENTRY_SQL: &str =
+    "SELECT id, entity_type, entity_id, name, breadcrumb, deleted_at, document FROM trash";
+
+fn entries(conn: &Connection, filter: &str, args: Vec<Sql>) -> rusqlite::Result<Vec<Entry>> {
+    let mut stmt = conn.prepare(&format!("{} {} ORDER BY id DESC", ENTRY_SQL, filter))?;
+    let rows = stmt.query_map(params_from_iter(args), Entry::from_row)?;
+    rows.collect()
+}
+
+/// Everything in the trash, newest first, or only one entity type.
+pub fn list(conn: &Connection, kind: Option<&str>, json: bool) -> i32 {
+    let found = match kind {
+        Some(k) => entries(conn, "WHERE entity_type=?1", vec![Sql::Text(k.to_string())]),
+        None => entries(conn, "", Vec::new()),
+    };
+    let found = match found {
+        Ok(f) => f,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    if json {
+        output::print_json(&Value::Array(found.iter().map(Entry::to_json).collect()));
+    } else if found.is_empty() {
+        output::print_plain("the trash is empty");
+    } else {
+        for e in &found {
+            let summary = e.summary();
+            outln!(
+                "{:<5} {:<8} {:<30} {}{}",
+                resolve::short_ref(&e.kind, e.entity_id),
+                e.kind,
+                e.breadcrumb,
+                e.deleted_at,
+                if summary.is_empty() {
+                    String::new()
+                } else {
+                    format!("  ({})", summary)
+                }
+            );
+        }
+    }
+    0
+}
+
+/// The trash entry for `who`: an id, a short ref, or a name. The newest
+/// entry wins only when they are all the same entity.
+fn find(conn: &Connection, kind: &str, who: &str) -> Result<Entry, String> {
+    let id = who.parse::<i64>().ok().or_else(|| {
+        resolve::parse_ref(who)
+            .filter(|(k, _)| *k == kind)
+            .map(|(_, id)| id)
+    });
+    let mut found = entries(
+        conn,
+        "WHERE entity_type=?1 AND (entity_id=?2 OR name=?3)",
+        vec![
+            Sql::Text(kind.to_string()),
+            id.map(Sql::Integer).unwrap_or(Sql::Null),
+            Sql::Text(names::normalize(who)),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    // An id match is exact; a name may have been removed more than once.
+    if let Some(i) = found.iter().position(|e| Some(e.entity_id) == id) {
+        return Ok(found.swap_remove(i));
+    }
+    match found.len() {
+        0 => Err(format!("no {} in the trash matches {}", kind, who)),
+        1 => Ok(found.swap_remove(0)),
+        n => {
+            let refs: Vec<String> = found
+                .iter()
+                .map(|e| {
+                    format!(
+                        "{} ({})",
+                        resolve::short_ref(kind, e.entity_id),
+                        e.deleted_at
+                    )
+                })
+                .collect();
+            Err(format!(
+                "{} trashed {}s are named {}: {}; restore one by id",
+                n,
+                kind,
+                who,
+                refs.join(", ")
+            ))
+        }
+    }
+}
+
+/// Why the entity of `e` cannot go back yet, if its parent is not there: the
+/// trash entry holding the parent, or word that the parent was purged.
+fn missing_parent(conn: &Connection, e: &Entry, parent_kind: &str, parent_id: i64) -> String {
+    let holder = conn
+        .query_row(
+            &format!(
+                "{} WHERE EXISTS (SELECT 1 FROM json_each(document, '$.tables.{}')
+                     WHERE json_extract(value, '$.id') = ?1) ORDER BY id DESC",
+                ENTRY_SQL,
+                table(parent_kind)
+            ),
+            params![parent_id],
+            Entry::from_row,
+        )
+        .optional()
+        .ok()
+        .flatten();
+    let parent = resolve::short_ref(parent_kind, parent_id);
+    match holder {
+        Some(h) if h.kind == parent_kind => format!(
+            "cannot restore {} {}: its {} {} is in the trash; restore it first (trash restore --{} {})",
+            e.kind, e.name, parent_kind, parent, h.kind, h.entity_id
+        ),
+        Some(h) => format!(
+            "cannot restore {} {}: its {} {} is in the trash with {} {}; restore that first (trash restore --{} {})",
+            e.kind, e.name, parent_kind, parent, h.kind, h.name, h.kind, h.entity_id
+        ),
+        None => format!(
+            "cannot restore {} {}: its {} {} was purged from the trash",
+            e.kind, e.name, parent_kind, parent
+        ),
+    }
+}
+
+fn to_sql(v: &Value) -> Sql {
+    match v {
+        Value::Null => Sql::Null,
+        Value::Bool(b) => Sql::Integer(*b as i64),
+        Value::Number(n) => n
+            .as_i64()
+            .map(Sql::Integer)
+            .unwrap_or_else(|| Sql::Real(n.as_f64().unwrap_or_default())),
+        Value::String(s) => Sql::Text(s.clone()),
+        other => Sql::Text(other.to_string()),
+    }
+}
+
+/// Put every row of `e` back, ids unchanged. Returns the links and
+/// dependencies skipped because their other end is gone.
+fn put_back(conn: &Connection, e: &Entry) -> rusqlite::Result<usize> {
+    let mut skipped = 0;
+    for (name, _) in TABLES {
+        let tie = TIES.contains(&name);
+        for row in e.rows(name) {
+            let Some(obj) = row.as_object() else {
+                continue;
+            };
+            let columns: Vec<&str> = obj.keys().map(String::as_str).collect();
+            let marks: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+            let sql = format!(
+                "INSERT {}INTO {} ({}) VALUES ({})",
+                if tie { "OR IGNORE " } else { "" },
+                name,
+                columns.join(", "),
+                marks.join(", ")
+            );
+            match conn.execute(&sql, params_from_iter(obj.values().map(to_sql))) {
+                Ok(_) => {}
+                Err(err)
+                    if tie
+                        && err.sqlite_error_code()
+                            == Some(rusqlite::ErrorCode::ConstraintViolation) =>
+                {
+                    skipped += 1
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    Ok(skipped)
+}
+
+/// Bring a trashed entity and everything removed with it back, ids
+/// unchanged. Refused while its parent is missing or archived, or while
+/// another entity has taken its name. Links and dependencies whose other
+/// end has since gone are skipped and counted.
+pub fn restore(conn: &Connection, kind: &str, who: &str, json: bool) -> i32 {
+    let e = match find(conn, kind, who) {
+        Ok(e) => e,
+        Err(msg) => {
+            output::err(&msg);
+            return 1;
+        }
+    };
+    let Some(top) = e.top() else {
+        output::err(&format!(
+            "trash entry for {} {} is damaged: it does not hold the {} itself",
+            kind, e.name, kind
+        ));
+        return 2;
+    };
+    let mut scope = None;
+    if let Some((column, parent_kind)) = parent_of(kind) {
+        let parent_id = top[column].as_i64().unwrap_or_default();
+        let exists: bool = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) > 0 FROM {} WHERE id=?1",
+                    table(parent_kind)
+                ),
+                params![parent_id],
+                |r| r.get(0),
+            )
+            .unwrap_or(false);
+        if !exists {
+            return output::fail(&Error::not_found(
+                parent_kind,
+                missing_parent(conn, &e, parent_kind, parent_id),
+            ));
+        }
+        if let Err(e) = resolve::check_archived(conn, parent_kind, parent_id) {
+            return output::fail(&e);
+        }
+        scope = Some((column, parent_id));
+    }
+    let existing = match names::clash(conn, table(kind), scope, &e.name, None) {
+        Ok(existing) => existing,
+        Err(e) => return output::fail(&e),
+    };
+    if let Some(existing) = existing {
+        let place = match parent_of(kind) {
+            Some((_, parent_kind)) => format!(" in that {}", parent_kind),
+            None => String::new(),
+        };
+        return output::fail(&Error::Conflict(format!(
+            "cannot restore {} {}: another {} is named {}{}; rename it first",
+            kind, e.name, kind, existing, place
+        )));
+    }
+
+    let tx = match db::write_tx(conn) {
+        Ok(tx) => tx,
+        Err(err) => {
+            output::err(&err.to_string());
+            return 2;
+        }
+    };
+    let skipped = match put_back(&tx, &e).and_then(|s| {
+        tx.execute("DELETE FROM trash WHERE id=?1", params![e.id])
+            .map(|_| s)
+    }) {
+        Ok(s) => s,
+        Err(err) => {
+            output::err(&format!("failed to restore {} {}: {}", kind, e.name, err));
+            return 2;
+        }
+    };
+    if let Err(err) = tx.commit() {
+        output::err(&err.to_string());
+        return 2;
+    }
+
+    if json {
+        let mut v = e.to_json();
+        v["restored"] = json!(true);
+        v["skipped_links"] = json!(skipped);
+        output::print_json(&v);
+    } else {
+        let summary = e.summary();
+        output::print_plain(&format!(
+            "restored {} {}: {}{}",
+            kind,
+            e.entity_id,
+            e.breadcrumb,
+            if summary.is_empty() {
+                String::new()
+            } else {
+                format!(" with {}", summary)
+            }
+        ));
+        if skipped > 0 {
+            output::print_plain(&format!(
+                "skipped {} link(s) or dependencies whose other end is gone",
+                skipped
+            ));
+        }
+    }
+    0
+}
+
+/// Delete trash entries for good: all of them, or those trashed more than
+/// `older_than` days ago. Copied attachments and external content of purged
+/// research go with them.
+pub fn purge(conn: &Connection, older_than: Option<i64>, json: bool) -> i32 {
+    let found = match older_than {
+        Some(days) => {
+            let cutoff = timestamp::canonical(&(chrono::Utc::now() - chrono::Duration::days(days)));
+            entries(conn, "WHERE deleted_at < ?1", vec![Sql::Text(cutoff)])
+        }
+        None => entries(conn, "", Vec::new()),
+    };
+    let found = match found {
+        Ok(f) => f,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let tx = match db::write_tx(conn) {
+        Ok(tx) => tx,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    for e in &found {
+        if let Err(err) = tx.execute("DELETE FROM trash WHERE id=?1", params![e.id]) {
+            output::err(&err.to_string());
+            return 2;
+        }
+    }
+    if let Err(err) = tx.commit() {
+        output::err(&err.to_string());
+        return 2;
+    }
+    for e in &found {
+        for r in e.rows("research") {
+            let id = r["id"].as_i64().unwrap_or_default();
+            if let Err(err) = attachment::remove_copies(id).and_then(|_| content::remove(conn, id))
+            {
+                eprintln!(
+                    "warning: could not delete the files of purged research {}: {}",
+                    r["name"].as_str().unwrap_or_default(),
+                    err
+                );
+            }
+        }
+    }
+
+    if json {
+        output::print_json(&json!({
+            "purged": found.len(),
+            "entries": found.iter().map(Entry::to_json).collect::<Vec<_>>(),
+        }));
+    } else {
+        output::print_plain(&format!("purged {} item(s) from the trash", found.len()));
+    }
+    0
+}
@@ -0,0 +1,161 @@
+use crate::output;
+use crate::state::State;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+/// A module, feature, or task and whatever is under it.
+struct Node {
+    id: i64,
+    name: String,
+    state: String,
+    children: Vec<Node>,
+}
+
+impl Node {
+    /// Keep only branches holding something in `state` down to `depth`
+    /// levels; a node in that state keeps its whole visible subtree.
+    fn prune(mut self, state: &str, depth: usize) -> Option<Node> {
+        if self.state == state {
+            return Some(self);
+        }
+        if depth <= 1 {
+            return None;
+        }
+        self.children = self
+            .children
+            .into_iter()
+            .filter_map(|c| c.prune(state, depth - 1))
+            .collect();
+        (!self.children.is_empty()).then_some(self)
+    }
+
+    /// `keys` name the children level by level, features then tasks; the
+    /// deepest level shown has none left and no children key.
+    fn to_json(&self, keys: &[&str]) -> Value {
+        let mut v = json!({"id": self.id, "name": self.name, "state": self.state});
+        if let Some((key, rest)) = keys.split_first() {
+            v[*key] = Value::Array(self.children.iter().map(|c| c.to_json(rest)).collect());
+        }
+        v
+    }
+
+    fn print(&self, prefix: &str, last: bool, depth: usize) {
+        let (branch, indent) = if last {
+            ("└─ ", "   ")
+        } else {
+            ("├─ ", "│  ")
+        };
+        outln!("{}{}{} [{}]", prefix, branch, self.name, self.state);
+        if depth <= 1 {
+            return;
+        }
+        let prefix = format!("{}{}", prefix, indent);
+        for (i, c) in self.children.iter().enumerate() {
+            c.print(&prefix, i + 1 == self.children.len(), depth - 1);
+        }
+    }
+}
+
+/// Every module of the project with its features and tasks, from one query.
+fn load(conn: &Connection, project_id: i64) -> rusqlite::Result<Vec<Node>> {
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.name, m.state, f.id, f.name, f.state, t.id, t.name, t.state
+         FROM modules m
+         LEFT JOIN features f ON f.module_id = m.id
+         LEFT JOIN tasks t ON t.feature_id = f.id
+         WHERE m.project_id = ?1
+         ORDER BY m.id, f.id, t.position, t.id",
+    )?;
+    let mut rows = stmt.query(params![project_id])?;
+    let mut modules: Vec<Node> = Vec::new();
+    while let Some(r) = rows.next()? {
+        let module_id: i64 = r.get(0)?;
+        if modules.last().map(|m| m.id) != Some(module_id) {
+            modules.push(Node {
+                id: module_id,
+                name: r.get(1)?,
+                state: r.get(2)?,
+                children: Vec::new(),
+            });
+        }
+        let Some(feature_id) = r.get::<_, Option<i64>>(3)? else {
+            continue;
+        };
+        let features = &mut modules.last_mut().unwrap().children;
+        if features.last().map(|f| f.id) != Some(feature_id) {
+            features.push(Node {
+                id: feature_id,
+                name: r.get(4)?,
+                state: r.get(5)?,
+                children: Vec::new(),
+            });
+        }
+        let Some(task_id) = r.get::<_, Option<i64>>(6)? else {
+            continue;
+        };
+        features.last_mut().unwrap().children.push(Node {
+            id: task_id,
+            name: r.get(7)?,
+            state: r.get(8)?,
+            children: Vec::new(),
+        });
+    }
+    Ok(modules)
+}
+
+/// Print the project's modules, features, and tasks as an indented tree,
+/// `depth` levels deep (1 modules, 2 features, 3 tasks). With `state`, only
+/// branches that hold something in that state within those levels are shown.
+pub fn tree(
+    conn: &Connection,
+    project_id: i64,
+    state: Option<&State>,
+    depth: usize,
+    json: bool,
+) -> i32 {
+    if !(1..=3).contains(&depth) {
+        output::err("--depth must be 1 (modules), 2 (features), or 3 (tasks)");
+        return 1;
+    }
+    let project: (String, String) = match conn.query_row(
+        "SELECT name, path FROM projects WHERE id=?1",
+        params![project_id],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    ) {
+        Ok(p) => p,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    let mut modules = match load(conn, project_id) {
+        Ok(m) => m,
+        Err(e) => {
+            output::err(&e.to_string());
+            return 2;
+        }
+    };
+    if let Some(s) = state {
+        let s = s.to_string();
+        modules = modules
+            .into_iter()
+            .filter_map(|m| m.prune(&s, depth))
+            .collect();
+    }
+
+    if json {
+        let keys = &["features", "tasks"][..depth - 1];
+        output::print_json(&json!({
+            "id": project_id,
+            "name": project.0,
+            "path": project.1,
+            "modules": modules.iter().map(|m| m.to_json(keys)).collect::<Vec<_>>(),
+        }));
+        return 0;
+    }
+    outln!("{}", project.0);
+    for (i, m) in modules.iter().enumerate() {
+        m.print("", i + 1 == modules.len(), depth);
+    }
+    0
+}
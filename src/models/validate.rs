@@ -0,0 +1,264 @@
+use crate::config;
+use crate::limits;
+use crate::models::ancestry;
+use crate::output;
+use crate::resolve;
+use crate::timestamp;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+/// Config key listing rule ids to skip.
+const SUPPRESS_KEY: &str = "validate_suppress";
+/// Config key for how long a task may sit in Building before it is reported.
+const BUILDING_DAYS_KEY: &str = "validate_building_days";
+const DEFAULT_BUILDING_DAYS: i64 = 14;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A workflow consistency rule. `sql` returns (entity type, id) rows for the
+/// offending entities; ?1 is the project to scope to (NULL for all) and, where
+/// used, ?2 is the Building cutoff timestamp.
+struct Rule {
+    id: &'static str,
+    severity: Severity,
+    message: &'static str,
+    sql: &'static str,
+}
+
+const RULES: [Rule; 8] = [
+    Rule {
+        id: "feature-complete-open-tasks",
+        severity: Severity::Error,
+        message: "feature is Complete but has tasks that are not",
+        sql: "SELECT DISTINCT 'feature', f.id FROM features f
+              JOIN modules m ON m.id = f.module_id
+              JOIN tasks t ON t.feature_id = f.id
+              WHERE f.state = 'Complete' AND t.state <> 'Complete'
+                AND (?1 IS NULL OR m.project_id = ?1)
+              ORDER BY f.id",
+    },
+    Rule {
+        id: "project-completed-open-modules",
+        severity: Severity::Error,
+        message: "project is marked completed but has modules that are not Complete",
+        sql: "SELECT DISTINCT 'project', p.id FROM projects p
+              JOIN modules m ON m.project_id = p.id
+              WHERE p.completed = 1 AND m.state <> 'Complete'
+                AND (?1 IS NULL OR p.id = ?1)
+              ORDER BY p.id",
+    },
+    Rule {
+        id: "project-incomplete-modules-complete",
+        severity: Severity::Warning,
+        message: "every module is Complete but the project is not marked completed",
+        sql: "SELECT 'project', p.id FROM projects p
+              JOIN (SELECT project_id, COUNT(*) AS total, SUM(state = 'Complete') AS complete
+                    FROM modules GROUP BY project_id) ms ON ms.project_id = p.id
+              WHERE p.completed = 0 AND ms.total = ms.complete
+                AND (?1 IS NULL OR p.id = ?1)
+              ORDER BY p.id",
+    },
+    Rule {
+        id: "module-building-no-features",
+        severity: Severity::Warning,
+        message: "module is Building but has no features",
+        sql: "SELECT 'module', m.id FROM modules m
+              WHERE m.state = 'Building'
+                AND NOT EXISTS (SELECT 1 FROM features f WHERE f.module_id = m.id)
+                AND (?1 IS NULL OR m.project_id = ?1)
+              ORDER BY m.id",
+    },
+    Rule {
+        id: "feature-building-no-tasks",
+        severity: Severity::Warning,
+        message: "feature is Building but has no tasks",
+        sql: "SELECT 'feature', f.id FROM features f
+              JOIN modules m ON m.id = f.module_id
+              WHERE f.state = 'Building'
+                AND NOT EXISTS (SELECT 1 FROM tasks t WHERE t.feature_id = f.id)
+                AND (?1 IS NULL OR m.project_id = ?1)
+              ORDER BY f.id",
+    },
+    Rule {
+        id: "task-building-stale",
+        severity: Severity::Warning,
+        message: "task has been Building without being worked on past the threshold",
+        sql: "SELECT 'task', t.id FROM tasks t
+              JOIN features f ON f.id = t.feature_id
+              JOIN modules m ON m.id = f.module_id
+              WHERE t.state = 'Building' AND t.last_worked_on < ?2
+                AND (?1 IS NULL OR m.project_id = ?1)
+              ORDER BY t.id",
+    },
+    Rule {
+        id: "empty-description",
+        severity: Severity::Warning,
+        message: "entity has no description",
+        sql: "SELECT 'project', p.id FROM projects p
+              WHERE TRIM(p.description) = '' AND (?1 IS NULL OR p.id = ?1)
+              UNION ALL
+              SELECT 'module', m.id FROM modules m
+              WHERE TRIM(m.description) = '' AND (?1 IS NULL OR m.project_id = ?1)
+              UNION ALL
+              SELECT 'feature', f.id FROM features f JOIN modules m ON m.id = f.module_id
+              WHERE TRIM(f.description) = '' AND (?1 IS NULL OR m.project_id = ?1)
+              UNION ALL
+              SELECT 'task', t.id FROM tasks t JOIN features f ON f.id = t.feature_id
+              JOIN modules m ON m.id = f.module_id
+              WHERE TRIM(t.description) = '' AND (?1 IS NULL OR m.project_id = ?1)",
+    },
+    Rule {
+        id: "research-researched-at-implausible",
+        severity: Severity::Warning,
+        message:
+            "research researched_at is before 1990, in the future, or later than its last update",
+        sql: "SELECT 'research', r.id FROM research r
+              WHERE (r.researched_at < '1990-01-01T00:00:00Z'
+                     OR r.researched_at > strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+                     OR r.researched_at > r.updated_at)
+                AND (?1 IS NULL OR r.id IN (
+                    SELECT research_id FROM research_projects WHERE project_id = ?1
+                    UNION SELECT rm.research_id FROM research_modules rm
+                        JOIN modules m ON m.id = rm.module_id WHERE m.project_id = ?1
+                    UNION SELECT rf.research_id FROM research_features rf
+                        JOIN features f ON f.id = rf.feature_id
+                        JOIN modules m ON m.id = f.module_id WHERE m.project_id = ?1
+                    UNION SELECT rt.research_id FROM research_tasks rt
+                        JOIN tasks t ON t.id = rt.task_id JOIN features f ON f.id = t.feature_id
+                        JOIN modules m ON m.id = f.module_id WHERE m.project_id = ?1))
+              ORDER BY r.id",
+    },
+];
+
+struct Violation {
+    rule: &'static Rule,
+    entities: Vec<(String, i64, String)>,
+}
+
+/// Check workflow consistency across the hierarchy (or one project) and report
+/// every rule that fires. Storage integrity is out of scope here.
+pub fn validate(
+    conn: &Connection,
+    project_id: Option<i64>,
+    building_days: Option<i64>,
+    json: bool,
+) -> i32 {
+    let settings = config::load();
+    let suppressed: Vec<&str> = settings
+        .get(SUPPRESS_KEY)
+        .and_then(|v| v.as_array())
+        .map(|a| a.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+    let days = building_days
+        .or_else(|| limits::DAYS.setting(BUILDING_DAYS_KEY, config::get_i64(BUILDING_DAYS_KEY)))
+        .unwrap_or(DEFAULT_BUILDING_DAYS);
+    let cutoff = timestamp::canonical(&(chrono::Utc::now() - chrono::Duration::days(days)));
+
+    let mut violations: Vec<Violation> = Vec::new();
+    for rule in RULES.iter().filter(|r| !suppressed.contains(&r.id)) {
+        let mut stmt = match conn.prepare(rule.sql) {
+            Ok(s) => s,
+            Err(e) => {
+                output::err(&e.to_string());
+                return 2;
+            }
+        };
+        let rows = if stmt.parameter_count() == 2 {
+            stmt.query_map(params![project_id, cutoff], |r| Ok((r.get(0)?, r.get(1)?)))
+                .and_then(|rows| rows.collect::<rusqlite::Result<Vec<(String, i64)>>>())
+        } else {
+            stmt.query_map(params![project_id], |r| Ok((r.get(0)?, r.get(1)?)))
+                .and_then(|rows| rows.collect::<rusqlite::Result<Vec<(String, i64)>>>())
+        };
+        let rows = match rows {
+            Ok(r) => r,
+            Err(e) => {
+                output::err(&e.to_string());
+                return 2;
+            }
+        };
+        if rows.is_empty() {
+            continue;
+        }
+        let entities = rows
+            .into_iter()
+            .map(|(kind, id)| {
+                // Research sits outside the hierarchy; its name stands in.
+                let crumb = if kind == "research" {
+                    conn.query_row("SELECT name FROM research WHERE id=?1", params![id], |r| {
+                        r.get(0)
+                    })
+                } else {
+                    ancestry::breadcrumb(conn, &kind, id)
+                };
+                crumb.map(|c| (kind, id, c))
+            })
+            .collect::<rusqlite::Result<_>>();
+        let entities = match entities {
+            Ok(e) => e,
+            Err(e) => return output::fail(&e.into()),
+        };
+        violations.push(Violation { rule, entities });
+    }
+
+    let errors = violations
+        .iter()
+        .filter(|v| v.rule.severity == Severity::Error)
+        .count();
+    let warnings = violations.len() - errors;
+
+    if json {
+        output::print_json(&json!({
+            "errors": errors,
+            "warnings": warnings,
+            "suppressed": suppressed,
+            "violations": violations.iter().map(|v| json!({
+                "rule": v.rule.id,
+                "severity": v.rule.severity.as_str(),
+                "message": v.rule.message,
+                "entities": v.entities.iter().map(|(kind, id, crumb)| json!({
+                    "type": kind, "id": id, "ref": resolve::short_ref(kind, *id), "breadcrumb": crumb,
+                })).collect::<Vec<Value>>(),
+            })).collect::<Vec<Value>>(),
+        }));
+    } else if violations.is_empty() {
+        output::print_plain("no violations found");
+    } else {
+        for v in &violations {
+            outln!(
+                "{:<8} {}: {}",
+                v.rule.severity.as_str(),
+                v.rule.id,
+                v.rule.message
+            );
+            for (kind, id, crumb) in &v.entities {
+                outln!(
+                    "  {:<8} {:<5} {}",
+                    kind,
+                    resolve::short_ref(kind, *id),
+                    crumb
+                );
+            }
+        }
+        outln!();
+        output::print_plain(&format!("{} error(s), {} warning(s)", errors, warnings));
+    }
+    if errors > 0 {
+        output::EXIT_VIOLATIONS
+    } else {
+        0
+    }
+}
@@ -0,0 +1,85 @@
+use crate::error::Error;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, Connection, OptionalExtension};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// The stored form of a name: trimmed and in Unicode NFC, so `café` typed as
+/// one code point or as `e` plus a combining accent is the same name. Applied
+/// on every write and to every name looked up.
+pub fn normalize(name: &str) -> String {
+    name.trim().nfc().collect()
+}
+
+/// The form two names are compared in when case does not matter: normalized
+/// and lowercased with Unicode rules, so `ÉCLAIR` and `éclair` agree where
+/// SQLite's own LOWER() and NOCASE only fold ASCII.
+pub fn fold(name: &str) -> String {
+    normalize(name).to_lowercase()
+}
+
+/// Sort key for names: folded, with accents dropped so `éclair` sorts among
+/// the e's rather than after z. Ties fall back to the folded name.
+pub fn sort_key(name: &str) -> String {
+    fold(name)
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect()
+}
+
+/// ORDER BY terms sorting `column` as names sort everywhere: by `name_key`,
+/// then folded, then as stored.
+pub fn order_sql(column: &str) -> String {
+    format!("name_key({0}), fold({0}), {0}", column)
+}
+
+/// Make `fold(text)` and `name_key(text)` available to SQL on this connection,
+/// for case-insensitive matching and ordering that agree with the Rust side.
+pub fn register(conn: &Connection) -> rusqlite::Result<()> {
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    conn.create_scalar_function("fold", 1, flags, |ctx| {
+        Ok(ctx.get::<Option<String>>(0)?.map(|s| fold(&s)))
+    })?;
+    conn.create_scalar_function("name_key", 1, flags, |ctx| {
+        Ok(ctx.get::<Option<String>>(0)?.map(|s| sort_key(&s)))
+    })
+}
+
+/// The existing name in `table` that `name` would duplicate, ignoring case
+/// and normal form. `scope` is the parent column and id the name must be
+/// unique under (None for tables unique across the database); `except` is the
+/// entity being renamed, which may keep its own name in another case.
+pub fn clash(
+    conn: &Connection,
+    table: &str,
+    scope: Option<(&str, i64)>,
+    name: &str,
+    except: Option<i64>,
+) -> Result<Option<String>, Error> {
+    let within = scope
+        .map(|(column, _)| format!(" AND {}=?2", column))
+        .unwrap_or_default();
+    conn.query_row(
+        &format!(
+            "SELECT name FROM {} WHERE fold(name)=?1{} AND id IS NOT ?3 LIMIT 1",
+            table, within
+        ),
+        params![fold(name), scope.map(|(_, id)| id), except],
+        |r| r.get(0),
+    )
+    .optional()
+    .map_err(Error::from)
+}
+
+/// How an add or rename reports a name already in use, e.g. `a module named
+/// 'auth' already exists in project 'myapp'`. `parent` is the kind and name
+/// of what the name must be unique under.
+pub fn taken(kind: &str, name: &str, parent: Option<(&str, &str)>) -> String {
+    match parent {
+        Some((parent_kind, parent_name)) => format!(
+            "a {} named '{}' already exists in {} '{}'",
+            kind, name, parent_kind, parent_name
+        ),
+        None => format!("a {} named '{}' already exists", kind, name),
+    }
+}
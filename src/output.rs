@@ -1,20 +1,357 @@
+use crate::db;
+use crate::error::{self, Error};
 use serde_json::Value;
+use std::io::{self, ErrorKind, Write};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+
+/// `println!` and `print!` for everything the crate's CLI functions print,
+/// through [`write_stdout`] so a closed pipe never panics (this module is
+/// declared first, with `#[macro_use]`). Named apart from the std macros,
+/// which still print as they always do.
+macro_rules! outln {
+    () => {
+        $crate::output::write_stdout(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::write_stdout(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::output::write_stdout(format_args!($($arg)*))
+    };
+}
+
+const STDOUT_OPEN: u8 = 0;
+const STDOUT_CLOSED: u8 = 1;
+const STDOUT_FAILED: u8 = 2;
+
+static STDOUT: AtomicU8 = AtomicU8::new(STDOUT_OPEN);
+
+/// Write to stdout without panicking when it fails. Once a reader went away
+/// early (`| head`) or a write failed, later writes are dropped and
+/// [`stdout_exit`] says how the run should end; the first other error is
+/// reported. Rust ignores SIGPIPE, so Unix and Windows both land here.
+pub fn write_stdout(args: std::fmt::Arguments) {
+    if STDOUT.load(Ordering::Relaxed) != STDOUT_OPEN {
+        return;
+    }
+    if let Err(e) = io::stdout().lock().write_fmt(args) {
+        if e.kind() == ErrorKind::BrokenPipe {
+            STDOUT.store(STDOUT_CLOSED, Ordering::Relaxed);
+            return;
+        }
+        STDOUT.store(STDOUT_FAILED, Ordering::Relaxed);
+        fail(&Error::Io(format!("failed writing to stdout: {}", e)));
+    }
+}
+
+/// The exit code stdout decides, whatever the command returned: 0 once the
+/// reader went away, since whoever closed the pipe already has what they
+/// wanted; 2 once a write failed. None while every write went through.
+pub fn stdout_exit() -> Option<i32> {
+    match STDOUT.load(Ordering::Relaxed) {
+        STDOUT_CLOSED => Some(0),
+        STDOUT_FAILED => Some(2),
+        _ => None,
+    }
+}
 
 pub fn print_plain(s: &str) {
-    println!("{}", s);
+    outln!("{}", s);
+}
+
+/// How results are printed, chosen once in main from `--json` and `--format`.
+/// Handlers only see whether output is structured; `print_json`,
+/// `print_rows`, and `print_empty` apply the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Plain,
+    /// One pretty-printed JSON document.
+    Json,
+    /// One compact JSON value per line: each element of a list, or a shown
+    /// object on its own.
+    Ndjson,
+    /// A header row of column names, then one row per list entry.
+    Csv,
+    /// As `Csv`, tab-separated.
+    Tsv,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Result<Format, String> {
+        match s {
+            "plain" => Ok(Format::Plain),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            other => Err(format!(
+                "unknown format '{}' (expected plain, json, ndjson, csv, or tsv)",
+                other
+            )),
+        }
+    }
+
+    /// Whether a list is printed row by row, so it can be written as it is
+    /// read and an empty one prints no prose.
+    pub fn per_row(self) -> bool {
+        matches!(self, Format::Ndjson | Format::Csv | Format::Tsv)
+    }
 }
 
+static FORMAT: AtomicU8 = AtomicU8::new(Format::Plain as u8);
+
+pub fn set_format(format: Format) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn format() -> Format {
+    match FORMAT.load(Ordering::Relaxed) {
+        1 => Format::Json,
+        2 => Format::Ndjson,
+        3 => Format::Csv,
+        4 => Format::Tsv,
+        _ => Format::Plain,
+    }
+}
+
+/// Print a JSON result in the chosen format. Under csv and tsv the columns
+/// of a list are the keys its rows have, so a list with a fixed set of
+/// columns should go through [`print_rows`] instead.
 pub fn print_json(v: &Value) {
-    println!("{}", serde_json::to_string_pretty(v).unwrap_or_default());
+    match (format(), v) {
+        (Format::Ndjson, Value::Array(rows)) => rows.iter().for_each(print_line),
+        (Format::Ndjson, v) => print_line(v),
+        (Format::Csv | Format::Tsv, v) => {
+            let rows = match v {
+                Value::Array(rows) => rows.as_slice(),
+                v => std::slice::from_ref(v),
+            };
+            let mut columns: Vec<&str> = Vec::new();
+            for row in rows {
+                if let Value::Object(map) = row {
+                    for key in map.keys() {
+                        if !columns.contains(&key.as_str()) {
+                            columns.push(key);
+                        }
+                    }
+                }
+            }
+            if columns.is_empty() {
+                rows.iter().for_each(|v| outln!("{}", cell(v)));
+                return;
+            }
+            columns.sort_unstable();
+            print_table(&columns, rows.iter().map(|r| cells(&columns, r)));
+        }
+        _ => outln!("{}", serde_json::to_string_pretty(v).unwrap_or_default()),
+    }
 }
 
-pub fn err(msg: &str) {
+/// Print a list whose rows are JSON objects with the keys in `columns`:
+/// as one array under json, and row by row, as they come, under ndjson, csv,
+/// and tsv, where `columns` is the header even when there are no rows.
+pub fn print_rows<I: IntoIterator<Item = Value>>(columns: &[&str], rows: I) {
+    match format() {
+        Format::Ndjson => print_ndjson(rows),
+        Format::Csv | Format::Tsv => {
+            print_table(columns, rows.into_iter().map(|r| cells(columns, &r)))
+        }
+        _ => print_json(&Value::Array(rows.into_iter().collect())),
+    }
+}
+
+/// Write a header and rows as CSV (RFC 4180: a field holding a comma, quote,
+/// or line break is quoted, its quotes doubled) or as TSV (tabs, line breaks,
+/// and backslashes escaped as `\t`, `\n`, `\r`, `\\`).
+pub fn print_table<I: IntoIterator<Item = Vec<String>>>(header: &[&str], rows: I) {
+    let tsv = format() == Format::Tsv;
+    let line = |fields: &mut dyn Iterator<Item = &str>| -> String {
+        let escaped: Vec<String> = fields
+            .map(|f| if tsv { tsv_field(f) } else { csv_field(f) })
+            .collect();
+        escaped.join(if tsv { "\t" } else { "," })
+    };
+    outln!("{}", line(&mut header.iter().copied()));
+    for row in rows {
+        outln!("{}", line(&mut row.iter().map(String::as_str)));
+    }
+}
+
+fn csv_field(f: &str) -> String {
+    if f.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", f.replace('"', "\"\""))
+    } else {
+        f.to_string()
+    }
+}
+
+fn tsv_field(f: &str) -> String {
+    f.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// A JSON value as one table cell: strings as they are, null as nothing, and
+/// anything nested as compact JSON.
+pub fn cell(v: &Value) -> String {
+    match v {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => serde_json::to_string(v).unwrap_or_default(),
+    }
+}
+
+/// Rows of cells as plain text, each column padded to its widest cell and
+/// the last left ragged.
+pub fn print_columns(rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (i, c) in row.iter().enumerate() {
+            let w = c.chars().count();
+            match widths.get_mut(i) {
+                Some(max) => *max = (*max).max(w),
+                None => widths.push(w),
+            }
+        }
+    }
+    for row in rows {
+        let last = row.len().saturating_sub(1);
+        let line: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                if i == last {
+                    c.clone()
+                } else {
+                    format!("{:<1$}", c, widths[i])
+                }
+            })
+            .collect();
+        outln!("{}", line.join("  "));
+    }
+}
+
+fn cells(columns: &[&str], row: &Value) -> Vec<String> {
+    columns.iter().map(|c| cell(&row[*c])).collect()
+}
+
+/// Print rows as NDJSON as they come, without collecting them first.
+pub fn print_ndjson<I: IntoIterator<Item = Value>>(rows: I) {
+    for row in rows {
+        print_line(&row);
+    }
+}
+
+fn print_line(v: &Value) {
+    outln!("{}", serde_json::to_string(v).unwrap_or_default());
+}
+
+/// What a list prints when nothing matched: `msg`, or nothing at all under
+/// the per-row formats, where no rows is the empty result.
+pub fn print_empty(msg: &str) {
+    if !format().per_row() {
+        print_plain(msg);
+    }
+}
+
+/// Exit code for operations refused because the target, or the parent it
+/// would change, is locked.
+pub const EXIT_LOCKED: i32 = 4;
+
+/// Exit code when a name matches more than one entity and a parent would
+/// narrow it.
+pub const EXIT_AMBIGUOUS: i32 = 3;
+
+/// Exit code when another process held the database's write lock for the
+/// whole busy timeout.
+pub const EXIT_BUSY: i32 = 5;
+
+/// Exit code from `validate` when at least one error-severity rule fires.
+pub const EXIT_VIOLATIONS: i32 = 6;
+
+/// Exit code from `changes --check` when a breaking change shipped after the
+/// given version.
+pub const EXIT_BREAKING: i32 = 7;
+
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Errors held back under `--json` until [`finish`] knows the exit code:
+/// `(code, entity, message)`, the code unknown for plain `err` calls.
+type Pending = (Option<&'static str>, Option<&'static str>, String);
+static PENDING: Mutex<Vec<Pending>> = Mutex::new(Vec::new());
+
+/// Report errors as `{"error": {...}}` objects instead of `error:` lines.
+pub fn json_errors(on: bool) {
+    JSON_ERRORS.store(on, Ordering::Relaxed);
+}
+
+fn report(code: Option<&'static str>, entity: Option<&'static str>, msg: &str) {
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        if let Ok(mut pending) = PENDING.lock() {
+            pending.push((code, entity, msg.to_string()));
+            return;
+        }
+    }
+    if db::gave_up() && busy_covers(code) {
+        return;
+    }
     eprintln!("error: {}", msg);
 }
 
-/// Format a labelled field line, padding the label to align values.
+/// Whether the busy report main prints once the database gave up already
+/// says what went wrong: for database errors, and for errors reported
+/// without a type, which take `db` from the busy exit code.
+fn busy_covers(code: Option<&'static str>) -> bool {
+    code.unwrap_or_else(|| error::code_for_exit(EXIT_BUSY)) == "db"
+}
+
+pub fn err(msg: &str) {
+    report(None, None, msg);
+}
+
+/// Report a library error as `err` does and return the exit code it maps to.
+pub fn fail(e: &Error) -> i32 {
+    report(Some(e.code()), e.entity(), &e.to_string());
+    e.exit_code()
+}
+
+/// Emit the errors held back under `--json`, each as
+/// `{"error": {"code", "message", "entity"}}`. Errors reported without a
+/// type take their code from `exit` (see [`error::code_for_exit`]). When the
+/// run gave up on a busy database, its own report already covers the
+/// database errors.
+pub fn finish(exit: i32) {
+    let pending = match PENDING.lock() {
+        Ok(mut pending) => std::mem::take(&mut *pending),
+        Err(_) => return,
+    };
+    for (code, entity, message) in pending {
+        if exit == EXIT_BUSY && busy_covers(code) {
+            continue;
+        }
+        err_json(&serde_json::json!({
+            "code": code.unwrap_or_else(|| error::code_for_exit(exit)),
+            "message": message,
+            "entity": entity,
+        }));
+    }
+}
+
+/// An error for `--json` runs: `{"error": {...}}` on stderr, one line, so a
+/// wrapper can parse it without mixing it into the stdout document.
+pub fn err_json(error: &Value) {
+    eprintln!("{}", serde_json::json!({ "error": error }));
+}
+
+/// Format a labelled field line, padding the label to align values. Labels
+/// too long to align still get a space before the value.
 pub fn field(label: &str, value: &str) -> String {
-    format!("{:<16}{}", format!("{}:", label), value)
+    format!("{:<15} {}", format!("{}:", label), value)
 }
 
 /// Indent multi-line content for show views.
@@ -28,3 +365,18 @@ pub fn indent_content(content: &str) -> String {
         .collect::<Vec<_>>()
         .join("\n")
 }
+
+/// The first line of `s`, cut to `max` characters with an ellipsis when
+/// anything was left out.
+pub fn one_line(s: &str, max: usize) -> String {
+    let trimmed = s.trim();
+    let first = trimmed.lines().next().unwrap_or_default().trim_end();
+    let cut = first.chars().count() > max;
+    if !cut && first.len() == trimmed.len() {
+        return first.to_string();
+    }
+    let keep = if cut { max.saturating_sub(1) } else { max };
+    let mut out: String = first.chars().take(keep).collect();
+    out.push('…');
+    out
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How much a task or feature matters, lowest first. Stored by name in the
+/// `priority` column of tasks and features; new rows are `medium`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Priority::Low => write!(f, "low"),
+            Priority::Medium => write!(f, "medium"),
+            Priority::High => write!(f, "high"),
+            Priority::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "low" => Ok(Priority::Low),
+            "medium" => Ok(Priority::Medium),
+            "high" => Ok(Priority::High),
+            "critical" => Ok(Priority::Critical),
+            _ => Err(format!(
+                "invalid priority '{}'; must be one of: low, medium, high, critical",
+                s
+            )),
+        }
+    }
+}
+
+/// SQL ranking the priority in `column` for ORDER BY, most urgent first.
+pub fn rank_sql(column: &str) -> String {
+    format!(
+        "CASE {} WHEN 'critical' THEN 0 WHEN 'high' THEN 1 WHEN 'medium' THEN 2 ELSE 3 END",
+        column
+    )
+}
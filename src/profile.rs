@@ -0,0 +1,139 @@
+use crate::db;
+use rusqlite::Connection;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Queries slower than this log a warning to stderr. Override with
+/// LOPEN_MEMORY_SLOW_QUERY_MS; 0 disables the warning.
+const DEFAULT_SLOW_QUERY_MS: u64 = 250;
+
+static SLOW_QUERY_MS: AtomicU64 = AtomicU64::new(DEFAULT_SLOW_QUERY_MS);
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static RECORDED: Mutex<Vec<(String, Duration)>> = Mutex::new(Vec::new());
+static OPEN_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// Attach the query-timing layer to a connection. Every statement run on the
+/// connection is timed by SQLite itself, so no call site needs its own timer.
+pub fn install(conn: &mut Connection) {
+    if let Ok(v) = std::env::var("LOPEN_MEMORY_SLOW_QUERY_MS") {
+        if let Ok(ms) = v.trim().parse::<u64>() {
+            SLOW_QUERY_MS.store(ms, Ordering::Relaxed);
+        }
+    }
+    conn.profile(Some(on_query));
+}
+
+/// Remember how long opening the database took, schema checks included, for `report`.
+pub fn opened(elapsed: Duration) {
+    OPEN_MICROS.store(elapsed.as_micros() as u64, Ordering::Relaxed);
+}
+
+/// SQLite's profile clock has millisecond resolution, so sub-millisecond
+/// queries report as 0ms; that is precise enough to find the slow ones.
+fn on_query(sql: &str, elapsed: Duration) {
+    if internal(sql) {
+        return;
+    }
+    if RECORDING.load(Ordering::Relaxed) {
+        if let Ok(mut rec) = RECORDED.lock() {
+            rec.push((sql.to_string(), elapsed));
+        }
+    }
+    // Time spent waiting out another process's lock says nothing about the
+    // query itself; the busy report covers it.
+    let running = elapsed.saturating_sub(db::take_busy_wait());
+    let threshold = SLOW_QUERY_MS.load(Ordering::Relaxed);
+    if threshold > 0 && running.as_millis() as u64 > threshold {
+        eprintln!(
+            "warning: slow query took {}ms (threshold {}ms): {}",
+            running.as_millis(),
+            threshold,
+            condense(sql)
+        );
+    }
+}
+
+/// Statements the full-text index runs on its own shadow tables when a
+/// trigger updates it. They always name the schema in quotes, which ours
+/// never do, and their time is already part of the statement that fired them.
+fn internal(sql: &str) -> bool {
+    sql.contains("'main'.")
+}
+
+/// Start collecting every executed statement for a later `report`.
+pub fn start_recording() {
+    if let Ok(mut rec) = RECORDED.lock() {
+        rec.clear();
+    }
+    RECORDING.store(true, Ordering::Relaxed);
+}
+
+/// Stop collecting and print per-query timings with their query plans to stderr.
+pub fn report(conn: &Connection) {
+    RECORDING.store(false, Ordering::Relaxed);
+    let recorded: Vec<(String, Duration)> = match RECORDED.lock() {
+        Ok(mut rec) => std::mem::take(&mut *rec),
+        Err(_) => return,
+    };
+    let total: Duration = recorded.iter().map(|(_, d)| *d).sum();
+    eprintln!();
+    eprintln!(
+        "profile: database opened in {}us",
+        OPEN_MICROS.load(Ordering::Relaxed)
+    );
+    eprintln!(
+        "profile: {} quer{}, {}ms total",
+        recorded.len(),
+        if recorded.len() == 1 { "y" } else { "ies" },
+        total.as_millis()
+    );
+    for (sql, elapsed) in &recorded {
+        eprintln!("  {:>6}ms  {}", elapsed.as_millis(), condense(sql));
+        for line in query_plan(conn, sql) {
+            eprintln!("            {}", line);
+        }
+    }
+}
+
+/// EXPLAIN QUERY PLAN for a recorded statement. Only data statements have a
+/// plan; pragmas, DDL, and transaction control are skipped.
+fn query_plan(conn: &Connection, sql: &str) -> Vec<String> {
+    let head = sql.trim_start().to_ascii_uppercase();
+    let planned = ["SELECT", "INSERT", "UPDATE", "DELETE", "WITH"]
+        .iter()
+        .any(|k| head.starts_with(k));
+    if !planned {
+        return Vec::new();
+    }
+    let mut stmt = match conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql)) {
+        Ok(s) => s,
+        Err(e) => return vec![format!("(no plan: {})", e)],
+    };
+    // Parameters are left unbound (NULL); the plan does not depend on their values.
+    let mut rows: Vec<(i64, i64, String)> = Vec::new();
+    let mut raw = stmt.raw_query();
+    while let Ok(Some(r)) = raw.next() {
+        if let (Ok(id), Ok(parent), Ok(detail)) = (r.get(0), r.get(1), r.get(3)) {
+            rows.push((id, parent, detail));
+        }
+    }
+    // Indent each plan node under its parent, mirroring the sqlite3 shell.
+    let mut depth: Vec<(i64, usize)> = Vec::new();
+    rows.into_iter()
+        .map(|(id, parent, detail)| {
+            let level = depth
+                .iter()
+                .find(|(pid, _)| *pid == parent)
+                .map(|(_, l)| l + 1)
+                .unwrap_or(0);
+            depth.push((id, level));
+            format!("{}{}", "  ".repeat(level), detail)
+        })
+        .collect()
+}
+
+/// Collapse whitespace so multi-line SQL fits on one log line.
+fn condense(sql: &str) -> String {
+    sql.split_whitespace().collect::<Vec<_>>().join(" ")
+}
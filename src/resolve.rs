@@ -1,12 +1,285 @@
-use rusqlite::{params, Connection, Result};
+use crate::error::Error;
+use crate::names;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-fn is_id(s: &str) -> bool {
-    s.parse::<i64>().is_ok()
+/// Set once per run when the command writes, so resolving anything in an
+/// archived project is refused before the command gets to it.
+static REFUSE_ARCHIVED: AtomicBool = AtomicBool::new(false);
+
+/// Refuse (`on`) or allow resolving entities in archived projects.
+pub fn refuse_archived(on: bool) {
+    REFUSE_ARCHIVED.store(on, Ordering::Relaxed);
+}
+
+/// Entity type for each short-ref prefix, as in `P3`, `M7`, `F21`, `T104`, `R9`.
+const REF_PREFIXES: [(char, &str); 5] = [
+    ('P', "project"),
+    ('M', "module"),
+    ('F', "feature"),
+    ('T', "task"),
+    ('R', "research"),
+];
+
+/// The short reference for an entity, e.g. `T104` for task 104. Shown next to
+/// names in output and accepted wherever a name or ID is.
+pub fn short_ref(kind: &str, id: i64) -> String {
+    let prefix = REF_PREFIXES
+        .iter()
+        .find(|(_, k)| *k == kind)
+        .map(|(p, _)| *p)
+        .unwrap_or('?');
+    format!("{}{}", prefix, id)
+}
+
+/// Parse a short reference (case-insensitive) into its entity type and ID.
+pub fn parse_ref(s: &str) -> Option<(&'static str, i64)> {
+    let mut chars = s.chars();
+    let prefix = chars.next()?.to_ascii_uppercase();
+    let digits = chars.as_str();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let kind = REF_PREFIXES.iter().find(|(p, _)| *p == prefix)?.1;
+    digits.parse().ok().map(|id| (kind, id))
+}
+
+fn table(kind: &str) -> &'static str {
+    match kind {
+        "project" => "projects",
+        "module" => "modules",
+        "feature" => "features",
+        "task" => "tasks",
+        _ => "research",
+    }
+}
+
+/// Whether some `kind` entity is literally named `s`.
+fn is_name(conn: &Connection, s: &str, kind: &str) -> bool {
+    conn.query_row(
+        &format!("SELECT COUNT(*) FROM {} WHERE name=?1", table(kind)),
+        params![names::normalize(s)],
+        |r| r.get::<_, i64>(0),
+    )
+    .map(|c| c > 0)
+    .unwrap_or(false)
+}
+
+/// The ID named by a numeric ID, an `id:`-prefixed ID, or a short ref of the
+/// expected type; None when `s` should be looked up as a name. A numeric or
+/// ref-shaped string that is also the literal name of a `kind` entity stays a
+/// name, so a project called `2024` is reachable as `2024`; `id:2024` always
+/// means the ID. A ref to another entity type is an error rather than a
+/// silent lookup of the wrong thing.
+fn id_of(conn: &Connection, s: &str, kind: &str) -> Result<Option<i64>, Error> {
+    if s.get(..3).is_some_and(|p| p.eq_ignore_ascii_case("id:")) {
+        return s[3..]
+            .trim()
+            .parse::<i64>()
+            .map(Some)
+            .map_err(|_| Error::Invalid(format!("invalid id: {} (expected id:<number>)", s)));
+    }
+    if let Ok(id) = s.parse::<i64>() {
+        return Ok((!is_name(conn, s, kind)).then_some(id));
+    }
+    let Some((ref_kind, id)) = parse_ref(s) else {
+        return Ok(None);
+    };
+    if is_name(conn, s, kind) {
+        return Ok(None);
+    }
+    if ref_kind != kind {
+        return Err(Error::Invalid(format!(
+            "{} is a {}; --{} expects a {}",
+            s.to_uppercase(),
+            ref_kind,
+            kind,
+            kind
+        )));
+    }
+    Ok(Some(id))
+}
+
+/// The name of the archived project row `id` of `table` sits in, or None
+/// when it is not in one (research belongs to no project).
+fn archived_owner(conn: &Connection, table: &str, id: i64) -> Option<String> {
+    let project = match table {
+        "projects" => "?1",
+        "modules" => "(SELECT project_id FROM modules WHERE id=?1)",
+        "features" => {
+            "(SELECT m.project_id FROM features f JOIN modules m ON m.id=f.module_id WHERE f.id=?1)"
+        }
+        "tasks" => {
+            "(SELECT m.project_id FROM tasks t JOIN features f ON f.id=t.feature_id
+              JOIN modules m ON m.id=f.module_id WHERE t.id=?1)"
+        }
+        _ => return None,
+    };
+    conn.query_row(
+        &format!(
+            "SELECT name FROM projects WHERE archived=1 AND id={}",
+            project
+        ),
+        params![id],
+        |r| r.get(0),
+    )
+    .ok()
+}
+
+/// An error telling the user to unarchive first when `kind` `id` is in an
+/// archived project.
+pub fn check_archived(conn: &Connection, kind: &str, id: i64) -> Result<(), Error> {
+    match archived_owner(conn, table(kind), id) {
+        Some(project) => Err(Error::InvalidState(format!(
+            "project {} is archived; unarchive it first (project unarchive --project {})",
+            project, project
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// `id` once resolved, unless the command writes and it is in an archived project.
+pub fn writable(conn: &Connection, kind: &str, id: i64) -> Result<i64, Error> {
+    if REFUSE_ARCHIVED.load(Ordering::Relaxed) {
+        check_archived(conn, kind, id)?;
+    }
+    Ok(id)
+}
+
+/// IDs of the rows in `table` named `name`, optionally only under one parent
+/// (`scope` is the parent column, or an expression over the row yielding an
+/// ancestor's id, and that id). Names compare in normal form; an
+/// exact match wins, and failing that a match ignoring case, so `Café` finds
+/// `café` but never shadows an entity actually named `Café`.
+fn ids_named(
+    conn: &Connection,
+    table: &str,
+    name: &str,
+    scope: Option<(&str, i64)>,
+) -> Result<Vec<i64>, Error> {
+    let name = names::normalize(name);
+    let within = within(scope, 2);
+    for (column, value) in [("name", name.clone()), ("fold(name)", names::fold(&name))] {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id FROM {} WHERE {}=?1{}",
+            table, column, within
+        ))?;
+        let mut args: Vec<Value> = vec![Value::Text(value)];
+        if let Some((_, id)) = scope {
+            args.push(Value::Integer(id));
+        }
+        let ids: Vec<i64> = stmt
+            .query_map(params_from_iter(args), |r| r.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        if ids.len() < 2 {
+            if !ids.is_empty() {
+                return Ok(ids);
+            }
+            continue;
+        }
+        // Entities in archived projects only count when nothing else matches.
+        let active: Vec<i64> = ids
+            .iter()
+            .copied()
+            .filter(|id| archived_owner(conn, table, *id).is_none())
+            .collect();
+        return Ok(if active.is_empty() { ids } else { active });
+    }
+    Ok(Vec::new())
+}
+
+/// The ` AND <column>=?n` narrowing a query to `scope`, bound as parameter `n`.
+fn within(scope: Option<(&str, i64)>, n: usize) -> String {
+    scope
+        .map(|(column, _)| format!(" AND {}=?{}", column, n))
+        .unwrap_or_default()
+}
+
+/// Character edits turning `a` into `b`: inserting, deleting, or replacing a
+/// character, or swapping two neighbours, the usual slip when typing a slug.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// `; did you mean: a, b?` naming up to three rows of `table` in the same
+/// scope whose names are a few edits from `name` or start with it, closest
+/// first; empty when nothing is close. Only run once a lookup has failed.
+fn suggestions(
+    conn: &Connection,
+    table: &str,
+    name: &str,
+    scope: Option<(&str, i64)>,
+) -> rusqlite::Result<String> {
+    let wanted = names::fold(&names::normalize(name));
+    let mut stmt = conn.prepare(&format!(
+        "SELECT name FROM {} WHERE 1=1{}",
+        table,
+        within(scope, 1)
+    ))?;
+    let args: Vec<Value> = scope
+        .map(|(_, id)| Value::Integer(id))
+        .into_iter()
+        .collect();
+    let rows = stmt
+        .query_map(params_from_iter(args), |r| r.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    // One edit for very short names, else two or a third of the length.
+    let allowed = match wanted.chars().count() {
+        0..=3 => 1,
+        n => (n / 3).max(2),
+    };
+    let mut close: Vec<(usize, String)> = rows
+        .into_iter()
+        .filter_map(|candidate| {
+            let folded = names::fold(&candidate);
+            let distance = edit_distance(&wanted, &folded);
+            if distance <= allowed {
+                Some((distance, candidate))
+            } else if !wanted.is_empty() && folded.starts_with(&wanted) {
+                Some((allowed + 1, candidate))
+            } else {
+                None
+            }
+        })
+        .collect();
+    close.sort();
+    close.dedup_by(|a, b| a.1 == b.1);
+    if close.is_empty() {
+        return Ok(String::new());
+    }
+    let names: Vec<String> = close.into_iter().take(3).map(|(_, n)| n).collect();
+    Ok(format!("; did you mean: {}?", names.join(", ")))
+}
+
+pub fn resolve_project(conn: &Connection, name_or_id: &str) -> Result<i64, Error> {
+    find_project(conn, name_or_id).and_then(|id| writable(conn, "project", id))
 }
 
-pub fn resolve_project(conn: &Connection, name_or_id: &str) -> Result<i64, String> {
-    if is_id(name_or_id) {
-        let id: i64 = name_or_id.parse().unwrap();
+fn find_project(conn: &Connection, name_or_id: &str) -> Result<i64, Error> {
+    if let Some(id) = id_of(conn, name_or_id, "project")? {
         let exists: bool = conn
             .query_row(
                 "SELECT COUNT(*) FROM projects WHERE id=?1",
@@ -18,21 +291,27 @@ pub fn resolve_project(conn: &Connection, name_or_id: &str) -> Result<i64, Strin
         if exists {
             Ok(id)
         } else {
-            Err(format!("project not found: {}", name_or_id))
+            Err(Error::not_found(
+                "project",
+                format!("project not found: {}", name_or_id),
+            ))
         }
     } else {
-        let mut stmt = conn
-            .prepare("SELECT id FROM projects WHERE name=?1")
-            .map_err(|e| e.to_string())?;
-        let ids: Vec<i64> = stmt
-            .query_map(params![name_or_id], |r| r.get(0))
-            .map_err(|e| e.to_string())?
-            .filter_map(|r| r.ok())
-            .collect();
+        let ids = ids_named(conn, "projects", name_or_id, None)?;
         match ids.len() {
-            0 => Err(format!("project not found: {}", name_or_id)),
+            0 => Err(Error::not_found(
+                "project",
+                format!(
+                    "project not found: {}{}",
+                    name_or_id,
+                    suggestions(conn, "projects", name_or_id, None)?
+                ),
+            )),
             1 => Ok(ids[0]),
-            _ => Err(format!("project name '{}' is ambiguous", name_or_id)),
+            _ => Err(Error::ambiguous(
+                "project",
+                format!("project name \'{}\' is ambiguous", name_or_id),
+            )),
         }
     }
 }
@@ -41,9 +320,12 @@ pub fn resolve_module(
     conn: &Connection,
     name_or_id: &str,
     project_id: Option<i64>,
-) -> Result<i64, String> {
-    if is_id(name_or_id) {
-        let id: i64 = name_or_id.parse().unwrap();
+) -> Result<i64, Error> {
+    find_module(conn, name_or_id, project_id).and_then(|id| writable(conn, "module", id))
+}
+
+fn find_module(conn: &Connection, name_or_id: &str, project_id: Option<i64>) -> Result<i64, Error> {
+    if let Some(id) = id_of(conn, name_or_id, "module")? {
         let exists: bool = conn
             .query_row(
                 "SELECT COUNT(*) FROM modules WHERE id=?1",
@@ -55,36 +337,30 @@ pub fn resolve_module(
         if exists {
             Ok(id)
         } else {
-            Err(format!("module not found: {}", name_or_id))
+            Err(Error::not_found(
+                "module",
+                format!("module not found: {}", name_or_id),
+            ))
         }
     } else {
-        let ids: Vec<i64> = if let Some(pid) = project_id {
-            let mut stmt = conn
-                .prepare("SELECT id FROM modules WHERE name=?1 AND project_id=?2")
-                .map_err(|e| e.to_string())?;
-            let x = stmt
-                .query_map(params![name_or_id, pid], |r| r.get(0))
-                .map_err(|e| e.to_string())?
-                .filter_map(|r| r.ok())
-                .collect();
-            x
-        } else {
-            let mut stmt = conn
-                .prepare("SELECT id FROM modules WHERE name=?1")
-                .map_err(|e| e.to_string())?;
-            let x = stmt
-                .query_map(params![name_or_id], |r| r.get(0))
-                .map_err(|e| e.to_string())?
-                .filter_map(|r| r.ok())
-                .collect();
-            x
-        };
+        let scope = project_id.map(|id| ("project_id", id));
+        let ids = ids_named(conn, "modules", name_or_id, scope)?;
         match ids.len() {
-            0 => Err(format!("module not found: {}", name_or_id)),
+            0 => Err(Error::not_found(
+                "module",
+                format!(
+                    "module not found: {}{}",
+                    name_or_id,
+                    suggestions(conn, "modules", name_or_id, scope)?
+                ),
+            )),
             1 => Ok(ids[0]),
-            _ => Err(format!(
-                "module name '{}' is ambiguous; specify --project to narrow scope",
-                name_or_id
+            _ => Err(Error::ambiguous(
+                "module",
+                format!(
+                    "module name '{}' is ambiguous; specify --project to narrow scope",
+                    name_or_id
+                ),
             )),
         }
     }
@@ -94,9 +370,41 @@ pub fn resolve_feature(
     conn: &Connection,
     name_or_id: &str,
     module_id: Option<i64>,
-) -> Result<i64, String> {
-    if is_id(name_or_id) {
-        let id: i64 = name_or_id.parse().unwrap();
+) -> Result<i64, Error> {
+    feature_within(conn, name_or_id, module_id.map(|id| ("module_id", id)))
+}
+
+/// Resolve a feature name among the features of every module of one project,
+/// for when the project is known but the module is not.
+pub fn resolve_feature_in_project(
+    conn: &Connection,
+    name_or_id: &str,
+    project_id: i64,
+) -> Result<i64, Error> {
+    feature_within(
+        conn,
+        name_or_id,
+        Some((
+            "(SELECT project_id FROM modules WHERE modules.id=features.module_id)",
+            project_id,
+        )),
+    )
+}
+
+fn feature_within(
+    conn: &Connection,
+    name_or_id: &str,
+    scope: Option<(&str, i64)>,
+) -> Result<i64, Error> {
+    find_feature(conn, name_or_id, scope).and_then(|id| writable(conn, "feature", id))
+}
+
+fn find_feature(
+    conn: &Connection,
+    name_or_id: &str,
+    scope: Option<(&str, i64)>,
+) -> Result<i64, Error> {
+    if let Some(id) = id_of(conn, name_or_id, "feature")? {
         let exists: bool = conn
             .query_row(
                 "SELECT COUNT(*) FROM features WHERE id=?1",
@@ -108,36 +416,29 @@ pub fn resolve_feature(
         if exists {
             Ok(id)
         } else {
-            Err(format!("feature not found: {}", name_or_id))
+            Err(Error::not_found(
+                "feature",
+                format!("feature not found: {}", name_or_id),
+            ))
         }
     } else {
-        let ids: Vec<i64> = if let Some(mid) = module_id {
-            let mut stmt = conn
-                .prepare("SELECT id FROM features WHERE name=?1 AND module_id=?2")
-                .map_err(|e| e.to_string())?;
-            let x = stmt
-                .query_map(params![name_or_id, mid], |r| r.get(0))
-                .map_err(|e| e.to_string())?
-                .filter_map(|r| r.ok())
-                .collect();
-            x
-        } else {
-            let mut stmt = conn
-                .prepare("SELECT id FROM features WHERE name=?1")
-                .map_err(|e| e.to_string())?;
-            let x = stmt
-                .query_map(params![name_or_id], |r| r.get(0))
-                .map_err(|e| e.to_string())?
-                .filter_map(|r| r.ok())
-                .collect();
-            x
-        };
+        let ids = ids_named(conn, "features", name_or_id, scope)?;
         match ids.len() {
-            0 => Err(format!("feature not found: {}", name_or_id)),
+            0 => Err(Error::not_found(
+                "feature",
+                format!(
+                    "feature not found: {}{}",
+                    name_or_id,
+                    suggestions(conn, "features", name_or_id, scope)?
+                ),
+            )),
             1 => Ok(ids[0]),
-            _ => Err(format!(
-                "feature name '{}' is ambiguous; specify --module to narrow scope",
-                name_or_id
+            _ => Err(Error::ambiguous(
+                "feature",
+                format!(
+                    "feature name '{}' is ambiguous; specify --module to narrow scope",
+                    name_or_id
+                ),
             )),
         }
     }
@@ -147,9 +448,41 @@ pub fn resolve_task(
     conn: &Connection,
     name_or_id: &str,
     feature_id: Option<i64>,
-) -> Result<i64, String> {
-    if is_id(name_or_id) {
-        let id: i64 = name_or_id.parse().unwrap();
+) -> Result<i64, Error> {
+    task_within(conn, name_or_id, feature_id.map(|id| ("feature_id", id)))
+}
+
+/// Resolve a task name among the tasks of every feature of one module, for
+/// when the module is known but the feature is not.
+pub fn resolve_task_in_module(
+    conn: &Connection,
+    name_or_id: &str,
+    module_id: i64,
+) -> Result<i64, Error> {
+    task_within(
+        conn,
+        name_or_id,
+        Some((
+            "(SELECT module_id FROM features WHERE features.id=tasks.feature_id)",
+            module_id,
+        )),
+    )
+}
+
+fn task_within(
+    conn: &Connection,
+    name_or_id: &str,
+    scope: Option<(&str, i64)>,
+) -> Result<i64, Error> {
+    find_task(conn, name_or_id, scope).and_then(|id| writable(conn, "task", id))
+}
+
+fn find_task(
+    conn: &Connection,
+    name_or_id: &str,
+    scope: Option<(&str, i64)>,
+) -> Result<i64, Error> {
+    if let Some(id) = id_of(conn, name_or_id, "task")? {
         let exists: bool = conn
             .query_row("SELECT COUNT(*) FROM tasks WHERE id=?1", params![id], |r| {
                 r.get::<_, i64>(0)
@@ -159,44 +492,36 @@ pub fn resolve_task(
         if exists {
             Ok(id)
         } else {
-            Err(format!("task not found: {}", name_or_id))
+            Err(Error::not_found(
+                "task",
+                format!("task not found: {}", name_or_id),
+            ))
         }
     } else {
-        let ids: Vec<i64> = if let Some(fid) = feature_id {
-            let mut stmt = conn
-                .prepare("SELECT id FROM tasks WHERE name=?1 AND feature_id=?2")
-                .map_err(|e| e.to_string())?;
-            let x = stmt
-                .query_map(params![name_or_id, fid], |r| r.get(0))
-                .map_err(|e| e.to_string())?
-                .filter_map(|r| r.ok())
-                .collect();
-            x
-        } else {
-            let mut stmt = conn
-                .prepare("SELECT id FROM tasks WHERE name=?1")
-                .map_err(|e| e.to_string())?;
-            let x = stmt
-                .query_map(params![name_or_id], |r| r.get(0))
-                .map_err(|e| e.to_string())?
-                .filter_map(|r| r.ok())
-                .collect();
-            x
-        };
+        let ids = ids_named(conn, "tasks", name_or_id, scope)?;
         match ids.len() {
-            0 => Err(format!("task not found: {}", name_or_id)),
+            0 => Err(Error::not_found(
+                "task",
+                format!(
+                    "task not found: {}{}",
+                    name_or_id,
+                    suggestions(conn, "tasks", name_or_id, scope)?
+                ),
+            )),
             1 => Ok(ids[0]),
-            _ => Err(format!(
-                "task name '{}' is ambiguous; specify --feature to narrow scope",
-                name_or_id
+            _ => Err(Error::ambiguous(
+                "task",
+                format!(
+                    "task name '{}' is ambiguous; specify --feature to narrow scope",
+                    name_or_id
+                ),
             )),
         }
     }
 }
 
-pub fn resolve_research(conn: &Connection, name_or_id: &str) -> Result<i64, String> {
-    if is_id(name_or_id) {
-        let id: i64 = name_or_id.parse().unwrap();
+pub fn resolve_research(conn: &Connection, name_or_id: &str) -> Result<i64, Error> {
+    if let Some(id) = id_of(conn, name_or_id, "research")? {
         let exists: bool = conn
             .query_row(
                 "SELECT COUNT(*) FROM research WHERE id=?1",
@@ -208,14 +533,336 @@ pub fn resolve_research(conn: &Connection, name_or_id: &str) -> Result<i64, Stri
         if exists {
             Ok(id)
         } else {
-            Err(format!("research not found: {}", name_or_id))
+            Err(Error::not_found(
+                "research",
+                format!("research not found: {}", name_or_id),
+            ))
         }
     } else {
-        conn.query_row(
-            "SELECT id FROM research WHERE name=?1",
-            params![name_or_id],
-            |r| r.get(0),
-        )
-        .map_err(|_| format!("research not found: {}", name_or_id))
+        match ids_named(conn, "research", name_or_id, None)?[..] {
+            [id] => Ok(id),
+            [] => Err(Error::not_found(
+                "research",
+                format!(
+                    "research not found: {}{}",
+                    name_or_id,
+                    suggestions(conn, "research", name_or_id, None)?
+                ),
+            )),
+            _ => Err(Error::ambiguous(
+                "research",
+                format!("research name \'{}\' is ambiguous", name_or_id),
+            )),
+        }
+    }
+}
+
+/// Entity types a [`Resolver`] serves: the table, and the column holding the
+/// parent's id for types named uniquely per parent.
+const SCOPES: [(&str, &str, Option<&str>); 5] = [
+    ("project", "projects", None),
+    ("module", "modules", Some("project_id")),
+    ("feature", "features", Some("module_id")),
+    ("task", "tasks", Some("feature_id")),
+    ("research", "research", None),
+];
+
+/// The types a remove of `kind` takes with it.
+fn below(kind: &str) -> &'static [&'static str] {
+    match kind {
+        "project" => &["module", "feature", "task"],
+        "module" => &["feature", "task"],
+        "feature" => &["task"],
+        _ => &[],
+    }
+}
+
+/// The entities of one scope by folded name: the name as stored, and the id.
+type Scope = HashMap<String, Vec<(String, i64)>>;
+
+/// What a name matched in one scope.
+enum Match {
+    One(i64),
+    None,
+    /// More than one entity, e.g. modules of that name in several projects.
+    Several,
+}
+
+/// Name lookups for a run that resolves many names, as a batch of operations
+/// does: the names under one parent (all of them, for projects and research)
+/// are read once and then answered from memory. A name not found reads its
+/// scope again before giving up, so entities created since are found. IDs,
+/// refs, names without their parent, and names matching several entities go
+/// to the plain resolvers, so errors read the same.
+///
+/// Renames and removes made through [`Resolver::rename`] and
+/// [`Resolver::remove`] drop the names they change; ones made around it
+/// leave it stale, which [`Resolver::clear`] fixes.
+pub struct Resolver<'c> {
+    conn: &'c Connection,
+    /// Per type and parent id, or None for all of the type.
+    scopes: RefCell<HashMap<(&'static str, Option<i64>), Scope>>,
+    /// Entities found not to be in an archived project, for a run that writes.
+    writable: RefCell<HashSet<(&'static str, i64)>>,
+}
+
+impl<'c> Resolver<'c> {
+    pub fn new(conn: &'c Connection) -> Resolver<'c> {
+        Resolver {
+            conn,
+            scopes: RefCell::new(HashMap::new()),
+            writable: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// The connection it reads from.
+    pub fn conn(&self) -> &'c Connection {
+        self.conn
+    }
+
+    /// [`resolve_project`], cached.
+    pub fn project(&self, name_or_id: &str) -> Result<i64, Error> {
+        self.resolve("project", None, name_or_id, || {
+            resolve_project(self.conn, name_or_id)
+        })
+    }
+
+    /// [`resolve_module`], cached.
+    pub fn module(&self, name_or_id: &str, project_id: Option<i64>) -> Result<i64, Error> {
+        self.resolve("module", project_id, name_or_id, || {
+            resolve_module(self.conn, name_or_id, project_id)
+        })
+    }
+
+    /// [`resolve_feature`], cached.
+    pub fn feature(&self, name_or_id: &str, module_id: Option<i64>) -> Result<i64, Error> {
+        self.resolve("feature", module_id, name_or_id, || {
+            resolve_feature(self.conn, name_or_id, module_id)
+        })
+    }
+
+    /// [`resolve_task`], cached.
+    pub fn task(&self, name_or_id: &str, feature_id: Option<i64>) -> Result<i64, Error> {
+        self.resolve("task", feature_id, name_or_id, || {
+            resolve_task(self.conn, name_or_id, feature_id)
+        })
+    }
+
+    /// [`resolve_research`], cached.
+    pub fn research(&self, name_or_id: &str) -> Result<i64, Error> {
+        self.resolve("research", None, name_or_id, || {
+            resolve_research(self.conn, name_or_id)
+        })
     }
+
+    /// The `kind` entity under `parent` named `name`, matched as a name
+    /// only, never as an ID: exactly, or failing that ignoring case. None
+    /// when there is none; an error when several differ only in case.
+    pub fn named(&self, kind: &str, parent: Option<i64>, name: &str) -> Result<Option<i64>, Error> {
+        let (kind, _, _) = scope(kind)?;
+        match self.find(kind, parent, name)? {
+            Match::One(id) => Ok(Some(id)),
+            Match::None => Ok(None),
+            Match::Several => Err(Error::ambiguous(
+                kind,
+                format!("{} name '{}' is ambiguous", kind, name),
+            )),
+        }
+    }
+
+    /// The existing name under `parent` that `name` would duplicate, ignoring
+    /// case and normal form, as [`names::clash`] finds it.
+    pub fn clash(
+        &self,
+        kind: &str,
+        parent: Option<i64>,
+        name: &str,
+    ) -> Result<Option<String>, Error> {
+        let (kind, _, _) = scope(kind)?;
+        let key = self.key(kind, parent);
+        self.load_once(key)?;
+        let scopes = self.scopes.borrow();
+        Ok(scopes[&key]
+            .get(&names::fold(&names::normalize(name)))
+            .and_then(|found| found.first())
+            .map(|(name, _)| name.clone()))
+    }
+
+    /// Note an entity just created under `parent`, so finding it needs no read.
+    pub fn remember(&self, kind: &str, parent: Option<i64>, name: &str, id: i64) {
+        let Ok((kind, _, column)) = scope(kind) else {
+            return;
+        };
+        let name = names::normalize(name);
+        // Under its parent, and among all of its type when that was read.
+        let mut keys = vec![(kind, None)];
+        if let (Some(_), Some(parent)) = (column, parent) {
+            keys.push((kind, Some(parent)));
+        }
+        let mut scopes = self.scopes.borrow_mut();
+        for key in keys {
+            if let Some(found) = scopes.get_mut(&key) {
+                found
+                    .entry(names::fold(&name))
+                    .or_default()
+                    .push((name.clone(), id));
+            }
+        }
+    }
+
+    /// Rename `kind` `id` with `op`, e.g. a model's `rename_to`, and forget
+    /// the names it was known by.
+    pub fn rename<T>(
+        &self,
+        kind: &str,
+        id: i64,
+        op: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let result = op();
+        self.forget(kind, id);
+        result
+    }
+
+    /// Remove `kind` `id` with `op`, e.g. a model's `delete`, and forget it
+    /// and everything under it.
+    pub fn remove<T>(
+        &self,
+        kind: &str,
+        id: i64,
+        op: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let result = op();
+        self.forget(kind, id);
+        let under = below(kind);
+        self.scopes
+            .borrow_mut()
+            .retain(|(k, _), _| !under.contains(k));
+        self.writable
+            .borrow_mut()
+            .retain(|(k, i)| !(under.contains(k) || (*k == kind && *i == id)));
+        result
+    }
+
+    /// Forget everything, after changes made around the resolver.
+    pub fn clear(&self) {
+        self.scopes.borrow_mut().clear();
+        self.writable.borrow_mut().clear();
+    }
+
+    /// Drop every scope of `kind` that holds `id`.
+    fn forget(&self, kind: &str, id: i64) {
+        self.scopes
+            .borrow_mut()
+            .retain(|(k, _), found| *k != kind || !found.values().flatten().any(|(_, i)| *i == id));
+    }
+
+    fn resolve(
+        &self,
+        kind: &'static str,
+        parent: Option<i64>,
+        name_or_id: &str,
+        plain: impl FnOnce() -> Result<i64, Error>,
+    ) -> Result<i64, Error> {
+        // A name without the parent it is unique under is matched across the
+        // whole table, where the plain resolvers weigh archived projects.
+        let (_, _, column) = scope(kind)?;
+        if (column.is_some() && parent.is_none()) || id_of(self.conn, name_or_id, kind)?.is_some() {
+            return plain();
+        }
+        let Match::One(id) = self.find(kind, parent, name_or_id)? else {
+            return plain();
+        };
+        if REFUSE_ARCHIVED.load(Ordering::Relaxed) && !self.writable.borrow().contains(&(kind, id))
+        {
+            writable(self.conn, kind, id)?;
+            self.writable.borrow_mut().insert((kind, id));
+        }
+        Ok(id)
+    }
+
+    /// The scope `kind` is looked up in: its parent's, or all of its type.
+    fn key(&self, kind: &'static str, parent: Option<i64>) -> (&'static str, Option<i64>) {
+        let column = SCOPES.iter().find(|(k, _, _)| *k == kind).and_then(|s| s.2);
+        (kind, column.and(parent))
+    }
+
+    /// `name` in its scope, read once; a name not there reads it again.
+    fn find(&self, kind: &'static str, parent: Option<i64>, name: &str) -> Result<Match, Error> {
+        let key = self.key(kind, parent);
+        let mut fresh = self.load_once(key)?;
+        let name = names::normalize(name);
+        let folded = names::fold(&name);
+        loop {
+            let found = {
+                let scopes = self.scopes.borrow();
+                let candidates = scopes[&key].get(&folded).map(Vec::as_slice).unwrap_or(&[]);
+                let exact: Vec<i64> = candidates
+                    .iter()
+                    .filter(|(n, _)| *n == name)
+                    .map(|(_, id)| *id)
+                    .collect();
+                match (&exact[..], candidates) {
+                    ([id], _) => Match::One(*id),
+                    ([], [(_, id)]) => Match::One(*id),
+                    ([], []) => Match::None,
+                    _ => Match::Several,
+                }
+            };
+            match found {
+                Match::None if !fresh => {
+                    self.load(key)?;
+                    fresh = true;
+                }
+                found => return Ok(found),
+            }
+        }
+    }
+
+    /// Read the scope unless it already was; true when it was read now.
+    fn load_once(&self, key: (&'static str, Option<i64>)) -> Result<bool, Error> {
+        if self.scopes.borrow().contains_key(&key) {
+            return Ok(false);
+        }
+        self.load(key)?;
+        Ok(true)
+    }
+
+    fn load(&self, key: (&'static str, Option<i64>)) -> Result<(), Error> {
+        let (_, table, column) = scope(key.0)?;
+        let within = match (column, key.1) {
+            (Some(column), Some(_)) => format!(" WHERE {}=?1", column),
+            _ => String::new(),
+        };
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT name, id FROM {}{}", table, within))?;
+        let args: Vec<Value> = key.1.map(Value::Integer).into_iter().collect();
+        let mut found = Scope::new();
+        let rows = stmt.query_map(params_from_iter(args), |r| {
+            Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?))
+        })?;
+        for row in rows {
+            let (name, id) = row?;
+            found
+                .entry(names::fold(&name))
+                .or_default()
+                .push((name, id));
+        }
+        self.scopes.borrow_mut().insert(key, found);
+        Ok(())
+    }
+}
+
+/// The static type name, table, and parent column for `kind`.
+fn scope(kind: &str) -> Result<(&'static str, &'static str, Option<&'static str>), Error> {
+    SCOPES
+        .iter()
+        .find(|(k, _, _)| *k == kind)
+        .copied()
+        .ok_or_else(|| {
+            Error::Invalid(format!(
+                "unknown type: {} (expected one of: project, module, feature, task, research)",
+                kind
+            ))
+        })
 }
@@ -1,3 +1,5 @@
+use crate::config;
+use crate::error::Error;
 use crate::output;
 use std::fs;
 use std::path::PathBuf;
@@ -20,24 +22,38 @@ fn skills_dir(override_path: Option<&String>) -> PathBuf {
     PathBuf::from(format!("{}{}", home, DEFAULT_SKILLS_DIR))
 }
 
+/// The SKILL.md this binary ships with.
+pub fn embedded() -> &'static str {
+    SKILL_CONTENT
+}
+
+/// Where the skill file is installed: `<skills dir>/lopen-memory/SKILL.md`.
+pub fn installed_path(override_path: Option<&String>) -> PathBuf {
+    skills_dir(override_path)
+        .join("lopen-memory")
+        .join("SKILL.md")
+}
+
 pub fn install(override_path: Option<&String>, json: bool) -> i32 {
     let base = skills_dir(override_path);
     let skill_dir = base.join("lopen-memory");
 
     if let Err(e) = fs::create_dir_all(&skill_dir) {
-        output::err(&format!(
+        return output::fail(&Error::Io(format!(
             "failed to create directory {}: {}",
             skill_dir.display(),
             e
-        ));
-        return 2;
+        )));
     }
 
     let dest = skill_dir.join("SKILL.md");
 
     if let Err(e) = fs::write(&dest, SKILL_CONTENT) {
-        output::err(&format!("failed to write {}: {}", dest.display(), e));
-        return 2;
+        return output::fail(&Error::Io(format!(
+            "failed to write {}: {}",
+            dest.display(),
+            e
+        )));
     }
 
     if json {
@@ -51,3 +67,59 @@ pub fn install(override_path: Option<&String>, json: bool) -> i32 {
 
     0
 }
+
+/// Rewrite the installed SKILL.md with the content embedded in this binary.
+pub fn update(override_path: Option<&String>, json: bool) -> i32 {
+    let dest = skills_dir(override_path)
+        .join("lopen-memory")
+        .join("SKILL.md");
+    let current = fs::read_to_string(&dest).ok();
+    if current.as_deref() == Some(SKILL_CONTENT) {
+        if json {
+            output::print_json(&serde_json::json!({
+                "updated": false,
+                "skill_path": dest.display().to_string()
+            }));
+        } else {
+            output::print_plain(&format!("skill already up to date: {}", dest.display()));
+        }
+        return 0;
+    }
+    install(override_path, json)
+}
+
+/// Config key that, set to false, turns the startup skill check off for good.
+pub const CHECK_CONFIG_KEY: &str = "skill_check";
+
+/// Marker recording the day of the last startup check, in the config directory.
+const CHECK_MARKER: &str = "skill-check";
+
+/// Warn on stderr when the installed SKILL.md is missing or differs from the
+/// embedded copy. Runs at most once per day: the marker holds the date of the
+/// last check, so every other command pays only for reading one small file.
+pub fn startup_check() {
+    if !config::get_bool(CHECK_CONFIG_KEY, true) {
+        return;
+    }
+    let marker = config::dir().join(CHECK_MARKER);
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    if fs::read_to_string(&marker).is_ok_and(|d| d.trim() == today) {
+        return;
+    }
+    if fs::create_dir_all(config::dir()).is_ok() {
+        fs::write(&marker, &today).ok();
+    }
+
+    let dest = skills_dir(None).join("lopen-memory").join("SKILL.md");
+    let problem = match fs::read_to_string(&dest) {
+        Err(_) => "is missing",
+        Ok(c) if c != SKILL_CONTENT => "is out of date",
+        Ok(_) => return,
+    };
+    eprintln!(
+        "notice: skill file {} {}; run `lopen-memory skill update` (silence with --no-skill-check or `lopen-memory config set {} false`)",
+        dest.display(),
+        problem,
+        CHECK_CONFIG_KEY
+    );
+}
@@ -1,3 +1,4 @@
+use crate::error::Error;
 use std::fmt;
 use std::str::FromStr;
 
@@ -8,6 +9,9 @@ pub enum State {
     Building,
     Complete,
     Amending,
+    /// Stopped by something outside the work itself, e.g. a missing API key or
+    /// an upstream bug; entered from and left back to Building.
+    Blocked,
 }
 
 impl fmt::Display for State {
@@ -18,6 +22,7 @@ impl fmt::Display for State {
             State::Building => write!(f, "Building"),
             State::Complete => write!(f, "Complete"),
             State::Amending => write!(f, "Amending"),
+            State::Blocked => write!(f, "Blocked"),
         }
     }
 }
@@ -32,31 +37,174 @@ impl FromStr for State {
             "Building" => Ok(State::Building),
             "Complete" => Ok(State::Complete),
             "Amending" => Ok(State::Amending),
+            "Blocked" => Ok(State::Blocked),
             other => Err(format!(
-                "invalid state '{}'; must be one of: Draft, Planning, Building, Complete, Amending",
+                "invalid state '{}'; must be one of: Draft, Planning, Building, Complete, Amending, Blocked",
                 other
             )),
         }
     }
 }
 
+/// Every state, in lifecycle order.
+pub const STATES: [State; 6] = [
+    State::Draft,
+    State::Planning,
+    State::Building,
+    State::Complete,
+    State::Amending,
+    State::Blocked,
+];
+
+/// SQL ranking the state in `column` for ORDER BY, in lifecycle order.
+pub fn rank_sql(column: &str) -> String {
+    let arms: String = STATES
+        .iter()
+        .enumerate()
+        .map(|(i, s)| format!(" WHEN '{}' THEN {}", s, i))
+        .collect();
+    format!("CASE {}{} END", column, arms)
+}
+
+/// The transition graph: each allowed move, forward moves first. Every state
+/// can go back to Draft. Staying put is a no-op, not an edge.
+const TRANSITIONS: [(State, State); 11] = [
+    (State::Draft, State::Planning),
+    (State::Planning, State::Building),
+    (State::Planning, State::Draft),
+    (State::Building, State::Complete),
+    (State::Building, State::Blocked),
+    (State::Building, State::Draft),
+    (State::Complete, State::Amending),
+    (State::Complete, State::Draft),
+    (State::Amending, State::Draft),
+    (State::Blocked, State::Building),
+    (State::Blocked, State::Draft),
+];
+
+/// The states `from` can move to in one transition, in graph order.
+pub fn allowed_from(from: &State) -> Vec<State> {
+    TRANSITIONS
+        .iter()
+        .filter(|(f, _)| f == from)
+        .map(|(_, t)| t.clone())
+        .collect()
+}
+
+/// The shortest walk from `from` to `to`: the states passed through, `to`
+/// included and `from` not. Empty when they are the same; None when `to`
+/// cannot be reached.
+pub fn path_between(from: &State, to: &State) -> Option<Vec<State>> {
+    let index = |s: &State| STATES.iter().position(|x| x == s).unwrap_or_default();
+    let mut came_from: [Option<usize>; STATES.len()] = [None; STATES.len()];
+    let mut queue = std::collections::VecDeque::from([index(from)]);
+    came_from[index(from)] = Some(index(from));
+    while let Some(at) = queue.pop_front() {
+        if at == index(to) {
+            let mut path = Vec::new();
+            let mut step = at;
+            while step != index(from) {
+                path.push(STATES[step].clone());
+                step = came_from[step].unwrap_or(index(from));
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for next in allowed_from(&STATES[at]) {
+            let n = index(&next);
+            if came_from[n].is_none() {
+                came_from[n] = Some(at);
+                queue.push_back(n);
+            }
+        }
+    }
+    None
+}
+
+/// The names of the states an entity in `state` can move to next, for `show`.
+/// Empty for a state name that does not parse.
+pub fn allowed_names(state: &str) -> Vec<String> {
+    state
+        .parse::<State>()
+        .map(|s| allowed_from(&s).iter().map(State::to_string).collect())
+        .unwrap_or_default()
+}
+
 /// Returns Ok(true) if transition is allowed, Ok(false) if it's a no-op (same state),
-/// Err if the transition is invalid.
-pub fn validate_transition(from: &str, to: &State) -> Result<bool, String> {
-    let from_state = from.parse::<State>()?;
+/// Err if the transition is invalid. The error names the moves allowed from
+/// here and, when `to` is further away, the states it is reached through.
+pub fn validate_transition(from: &str, to: &State) -> Result<bool, Error> {
+    let from_state = from.parse::<State>().map_err(Error::InvalidTransition)?;
     if &from_state == to {
         return Ok(false); // no-op
     }
-    let allowed = match from_state {
-        State::Draft => vec![State::Planning, State::Draft],
-        State::Planning => vec![State::Building, State::Draft],
-        State::Building => vec![State::Complete, State::Draft],
-        State::Complete => vec![State::Amending, State::Draft],
-        State::Amending => vec![State::Draft],
-    };
+    let allowed = allowed_from(&from_state);
     if allowed.contains(to) {
-        Ok(true)
+        return Ok(true);
+    }
+    let names: Vec<String> = allowed.iter().map(State::to_string).collect();
+    let mut msg = format!(
+        "invalid transition: {} → {} (allowed from {}: {}",
+        from_state,
+        to,
+        from_state,
+        names.join(" | ")
+    );
+    if let Some(path) = path_between(&from_state, to) {
+        let via: Vec<String> = path[..path.len() - 1]
+            .iter()
+            .map(State::to_string)
+            .collect();
+        msg.push_str(&format!("; {} is reached through {}", to, via.join(" → ")));
+    }
+    msg.push(')');
+    Err(Error::InvalidTransition(msg))
+}
+
+/// The states a new `kind` entity passes through from Draft to `start`,
+/// Draft first. Walking every intermediate state means --plan/--start/--state
+/// can never skip a step the lifecycle would otherwise require.
+pub fn start_walk(start: Option<&State>, kind: &str, name: &str) -> Result<Vec<State>, Error> {
+    let mut walked = vec![State::Draft];
+    if let Some(target) = start {
+        for step in forward_path(&State::Draft, target).unwrap_or_default() {
+            let from = walked.last().unwrap().to_string();
+            if let Err(e) = validate_transition(&from, &step) {
+                return Err(Error::InvalidTransition(format!(
+                    "{} for {} {}",
+                    e, kind, name
+                )));
+            }
+            walked.push(step);
+        }
+    }
+    Ok(walked)
+}
+
+/// ` [Draft → Planning]` for an add message when the walk went past Draft.
+pub fn walk_note(walked: &[State]) -> String {
+    if walked.len() > 1 {
+        let steps: Vec<String> = walked.iter().map(|s| s.to_string()).collect();
+        format!(" [{}]", steps.join(" → "))
     } else {
-        Err(format!("invalid transition: {} → {}", from_state, to))
+        String::new()
+    }
+}
+
+/// The forward lifecycle chain, used to walk an entity through the intermediate
+/// states between `from` and `to`. Returns the states to pass through (excluding
+/// `from`), or None if `to` is not ahead of `from` on the chain.
+pub fn forward_path(from: &State, to: &State) -> Option<Vec<State>> {
+    const CHAIN: [State; 4] = [
+        State::Draft,
+        State::Planning,
+        State::Building,
+        State::Complete,
+    ];
+    let start = CHAIN.iter().position(|s| s == from)?;
+    let end = CHAIN.iter().position(|s| s == to)?;
+    if end <= start {
+        return None;
     }
+    Some(CHAIN[start + 1..=end].to_vec())
 }
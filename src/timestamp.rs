@@ -0,0 +1,58 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+/// The one stored timestamp form: RFC 3339 UTC at second precision. Every
+/// timestamp column holds this so string comparison matches time order.
+pub const CANONICAL: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+pub fn canonical(dt: &DateTime<Utc>) -> String {
+    dt.format(CANONICAL).to_string()
+}
+
+/// The current time, as it is stored.
+pub fn now() -> String {
+    canonical(&Utc::now())
+}
+
+/// The date part of a stored timestamp, or the whole value when it is too
+/// short to have one.
+pub fn date(stored: &str) -> &str {
+    stored.get(..10).unwrap_or(stored)
+}
+
+/// Parse the timestamp shapes found in older databases: RFC 3339 with any
+/// offset or fraction, SQLite's `YYYY-MM-DD HH:MM:SS`, minute precision, bare
+/// dates, slash-separated dates, and Unix seconds. Values without an offset
+/// are taken as UTC.
+pub fn parse_tolerant(s: &str) -> Option<DateTime<Utc>> {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    let naive = s.trim_end_matches(['Z', 'z']);
+    for fmt in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+        "%Y/%m/%d %H:%M:%S",
+    ] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(naive, fmt) {
+            return Some(dt.and_utc());
+        }
+    }
+    for fmt in ["%Y-%m-%d", "%Y/%m/%d"] {
+        if let Ok(d) = NaiveDate::parse_from_str(naive, fmt) {
+            return d.and_hms_opt(0, 0, 0).map(|dt| dt.and_utc());
+        }
+    }
+    if s.len() >= 9 && s.chars().all(|c| c.is_ascii_digit()) {
+        return s
+            .parse::<i64>()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0));
+    }
+    None
+}
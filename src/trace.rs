@@ -0,0 +1,202 @@
+use crate::resolve;
+use crate::timestamp;
+use rusqlite::hooks::Action;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// File descriptor to append trace lines to when --trace-file is not given.
+pub const TRACE_FD_ENV: &str = "LOPEN_MEMORY_TRACE_FD";
+
+/// Who the trace says made a change; falls back to the login name.
+pub const ACTOR_ENV: &str = "LOPEN_MEMORY_ACTOR";
+
+/// Row changes seen since the last commit, and those committed since the
+/// command started, as (table, rowid, action) in the order they happened.
+static PENDING: Mutex<Vec<(String, i64, Action)>> = Mutex::new(Vec::new());
+static COMMITTED: Mutex<Vec<(String, i64, Action)>> = Mutex::new(Vec::new());
+
+/// One mutation as the trace reports it. Built from committed row changes,
+/// one per entity (or per other table touched), so a future audit log can
+/// store the same records.
+pub struct Event {
+    pub ts: String,
+    pub actor: String,
+    pub command: String,
+    pub entity_type: String,
+    /// None for tables that are not entities (links, contexts, locks).
+    pub entity_id: Option<i64>,
+    pub summary: String,
+}
+
+impl Event {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "ts": self.ts,
+            "actor": self.actor,
+            "command": self.command,
+            "type": self.entity_type,
+            "id": self.entity_id,
+            "summary": self.summary,
+        })
+    }
+}
+
+fn entity_type(table: &str) -> Option<&'static str> {
+    match table {
+        "projects" => Some("project"),
+        "modules" => Some("module"),
+        "features" => Some("feature"),
+        "tasks" => Some("task"),
+        "research" => Some("research"),
+        _ => None,
+    }
+}
+
+fn verb(action: Action) -> &'static str {
+    match action {
+        Action::SQLITE_INSERT => "added",
+        Action::SQLITE_DELETE => "deleted",
+        _ => "updated",
+    }
+}
+
+/// Where trace lines go: --trace-file, else the LOPEN_MEMORY_TRACE_FD
+/// descriptor, else nowhere.
+pub fn target(trace_file: Option<&str>) -> Option<String> {
+    if let Some(p) = trace_file {
+        return Some(p.to_string());
+    }
+    let fd = std::env::var(TRACE_FD_ENV).ok()?;
+    let fd = fd.trim();
+    if cfg!(unix) && fd.parse::<u32>().is_ok() {
+        Some(format!("/dev/fd/{}", fd))
+    } else {
+        eprintln!(
+            "warning: {}={} ignored: expected a file descriptor number on a Unix system",
+            TRACE_FD_ENV, fd
+        );
+        None
+    }
+}
+
+/// Watch the connection's row changes. Only changes in transactions that
+/// commit are kept, so a command that rolls back traces nothing.
+pub fn install(conn: &Connection) {
+    conn.update_hook(Some(|action, _db: &str, table: &str, rowid| {
+        if let Ok(mut p) = PENDING.lock() {
+            p.push((table.to_string(), rowid, action));
+        }
+    }));
+    conn.commit_hook(Some(|| {
+        if let (Ok(mut p), Ok(mut c)) = (PENDING.lock(), COMMITTED.lock()) {
+            c.append(&mut p);
+        }
+        false
+    }));
+    conn.rollback_hook(Some(|| {
+        if let Ok(mut p) = PENDING.lock() {
+            p.clear();
+        }
+    }));
+}
+
+/// What the command changed, one event per entity in the order first
+/// touched; an entity both added and changed counts as added, and one
+/// deleted as deleted. Changes to other tables make one event per table.
+pub fn events(conn: &Connection, command: &str) -> Vec<Event> {
+    let changes = match COMMITTED.lock() {
+        Ok(mut c) => std::mem::take(&mut *c),
+        Err(_) => return Vec::new(),
+    };
+    let mut order: Vec<(String, Option<i64>)> = Vec::new();
+    let mut seen: BTreeMap<(String, Option<i64>), (Action, usize)> = BTreeMap::new();
+    for (table, rowid, action) in changes {
+        let key = (table.clone(), entity_type(&table).map(|_| rowid));
+        match seen.get_mut(&key) {
+            Some((first, n)) => {
+                *n += 1;
+                if *first != Action::SQLITE_INSERT && action == Action::SQLITE_DELETE {
+                    *first = action;
+                }
+            }
+            None => {
+                seen.insert(key.clone(), (action, 1));
+                order.push(key);
+            }
+        }
+    }
+    let ts = timestamp::now();
+    let actor = std::env::var(ACTOR_ENV)
+        .or_else(|_| std::env::var("USER"))
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    order
+        .into_iter()
+        .map(|key| {
+            let (action, n) = seen[&key];
+            let (table, id) = key;
+            let (entity_type, summary) = match (entity_type(&table), id) {
+                (Some(kind), Some(id)) => {
+                    let name: Option<String> = conn
+                        .query_row(
+                            &format!("SELECT name FROM {} WHERE id=?1", table),
+                            params![id],
+                            |r| r.get(0),
+                        )
+                        .ok();
+                    let mut summary =
+                        format!("{} {} {}", verb(action), kind, resolve::short_ref(kind, id));
+                    if let Some(name) = name {
+                        summary.push_str(&format!(" ({})", name));
+                    }
+                    (kind.to_string(), summary)
+                }
+                _ => (
+                    table.clone(),
+                    format!(
+                        "{} {} row{} in {}",
+                        verb(action),
+                        n,
+                        if n == 1 { "" } else { "s" },
+                        table
+                    ),
+                ),
+            };
+            Event {
+                ts: ts.clone(),
+                actor: actor.clone(),
+                command: command.to_string(),
+                entity_type,
+                entity_id: id,
+                summary,
+            }
+        })
+        .collect()
+}
+
+/// Append one JSON line per event to `path` and flush. A failure is a
+/// warning, never the command's failure.
+pub fn write(path: &str, events: &[Event]) {
+    if events.is_empty() {
+        return;
+    }
+    let mut lines = String::new();
+    for e in events {
+        lines.push_str(&e.to_json().to_string());
+        lines.push('\n');
+    }
+    let written = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut f| {
+            f.write_all(lines.as_bytes())?;
+            f.flush()
+        });
+    if let Err(e) = written {
+        eprintln!("warning: could not write trace to {}: {}", path, e);
+    }
+}
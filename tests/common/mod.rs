@@ -0,0 +1,13 @@
+//! Helpers shared by the integration tests.
+
+use lopen_memory::{config, db};
+use rusqlite::Connection;
+
+/// A fresh in-memory store. The config, and the attachment copies kept next
+/// to it, live in a directory of this test process's own; it is handed to the
+/// library directly, so tests running side by side never touch the environment.
+pub fn open() -> Connection {
+    let dir = std::env::temp_dir().join(format!("lopen-memory-lib-{}", std::process::id()));
+    config::set_path(dir.join("config.json"));
+    db::open(":memory:", false).expect("open an in-memory store")
+}
@@ -0,0 +1,194 @@
+//! The library API: typed functions return their result or an `Error`, and
+//! each error carries the exit code the CLI would end with.
+
+use lopen_memory::fields::Page;
+use lopen_memory::models::lift::ParentPolicy;
+use lopen_memory::models::{feature, module, project, research};
+use lopen_memory::priority::Priority;
+use lopen_memory::state::State;
+use lopen_memory::Error;
+use rusqlite::Connection;
+
+mod common;
+use common::open;
+
+/// The error `r` failed with; a test failure if it succeeded.
+fn error<T>(r: Result<T, Error>) -> Error {
+    match r {
+        Ok(_) => panic!("expected an error"),
+        Err(e) => e,
+    }
+}
+
+fn module_in(conn: &Connection) -> module::Module {
+    let p = project::create(conn, "myapp", "", "").unwrap();
+    module::create(conn, p.id, "auth", "", "", "", None).unwrap()
+}
+
+#[test]
+fn transition_moves_and_refuses_skipping_states() {
+    let conn = open();
+    let m = module_in(&conn);
+    let moved =
+        module::transition_to(&conn, m.id, &State::Planning, Some("scoped"), false).unwrap();
+    assert!(moved.moved);
+    assert_eq!(moved.from, "Draft");
+    assert_eq!(moved.entity.state, "Planning");
+    assert_eq!(moved.reason.as_deref(), Some("scoped"));
+
+    let again = module::transition_to(&conn, m.id, &State::Planning, None, false).unwrap();
+    assert!(!again.moved);
+
+    let err = error(module::transition_to(
+        &conn,
+        m.id,
+        &State::Amending,
+        None,
+        false,
+    ));
+    assert!(matches!(err, Error::InvalidTransition(_)), "{:?}", err);
+    assert_eq!(err.exit_code(), 1);
+    assert_eq!(module::get(&conn, m.id).unwrap().state, "Planning");
+}
+
+#[test]
+fn locked_entities_refuse_updates() {
+    let conn = open();
+    let m = module_in(&conn);
+    module::update_locked(&conn, m.id, true).unwrap();
+    let err = error(module::update_description(&conn, m.id, "changed"));
+    assert!(matches!(err, Error::Locked(_)), "{:?}", err);
+    assert_eq!(err.exit_code(), 4);
+    assert_eq!(err.code(), "invalid_state");
+
+    module::update_locked(&conn, m.id, false).unwrap();
+    let m = module::update_description(&conn, m.id, "changed").unwrap();
+    assert_eq!(m.description, "changed");
+}
+
+#[test]
+fn feature_priority_returns_the_old_value() {
+    let conn = open();
+    let m = module_in(&conn);
+    let f = feature::create(&conn, m.id, "login", "", "", None, Priority::Medium).unwrap();
+    let (f, old) = feature::update_priority(&conn, f.id, Priority::High).unwrap();
+    assert_eq!(old, Priority::Medium.to_string());
+    assert_eq!(f.priority, Priority::High.to_string());
+
+    let t = feature::transition_to(
+        &conn,
+        f.id,
+        &State::Planning,
+        ParentPolicy::Lift,
+        None,
+        false,
+    )
+    .unwrap();
+    assert_eq!(t.lifted.len(), 1);
+    assert_eq!(module::get(&conn, m.id).unwrap().state, "Planning");
+}
+
+#[test]
+fn rename_to_a_taken_name_conflicts() {
+    let conn = open();
+    project::create(&conn, "first", "", "").unwrap();
+    let second = project::create(&conn, "second", "", "").unwrap();
+    let err = error(project::rename_to(&conn, second.id, "first", false, false));
+    assert!(matches!(err, Error::Conflict(_)), "{:?}", err);
+    assert_eq!(err.code(), "conflict");
+
+    let renamed = project::rename_to(&conn, second.id, "third", false, false).unwrap();
+    assert_eq!(renamed.old_name, "second");
+    assert_eq!(renamed.entity.name, "third");
+}
+
+#[test]
+fn link_and_unlink_report_whether_anything_changed() {
+    let conn = open();
+    let m = module_in(&conn);
+    let r = research::create(&conn, "oauth-notes", "").unwrap();
+
+    let linked = research::link_to(&conn, "module", r.id, m.id, false).unwrap();
+    assert!(linked.changed);
+    assert_eq!(linked.entity_name, "auth");
+    assert_eq!(linked.target_state, "Draft");
+    assert!(linked.warning.is_none());
+    assert!(
+        !research::link_to(&conn, "module", r.id, m.id, false)
+            .unwrap()
+            .changed
+    );
+
+    assert!(
+        research::unlink_from(&conn, "module", r.id, m.id)
+            .unwrap()
+            .changed
+    );
+    assert!(
+        !research::unlink_from(&conn, "module", r.id, m.id)
+            .unwrap()
+            .changed
+    );
+
+    let err = error(research::link_to(&conn, "module", r.id, m.id + 100, false));
+    assert!(matches!(err, Error::NotFound { .. }), "{:?}", err);
+    assert_eq!(err.entity(), Some("module"));
+    let err = error(research::link_to(&conn, "widget", r.id, m.id, false));
+    assert!(matches!(err, Error::Invalid(_)), "{:?}", err);
+}
+
+#[test]
+fn matching_pages_and_counts() {
+    let conn = open();
+    for name in ["cache-a", "cache-b", "cache-c", "other"] {
+        research::create(&conn, name, "").unwrap();
+    }
+    let (all, total) = research::matching(
+        &conn,
+        Some("cache"),
+        None,
+        None,
+        false,
+        false,
+        false,
+        Page::default(),
+    )
+    .unwrap();
+    assert_eq!(all.len(), 3);
+    assert_eq!(total, None);
+
+    let page = Page {
+        limit: Some(2),
+        offset: 2,
+    };
+    let (rest, total) =
+        research::matching(&conn, Some("cache"), None, None, false, false, false, page).unwrap();
+    assert_eq!(rest.len(), 1);
+    assert_eq!(total, Some(3));
+}
+
+#[test]
+fn delete_refuses_children_without_cascade() {
+    let conn = open();
+    let m = module_in(&conn);
+    let err = error(project::delete(&conn, m.project_id, false, false));
+    assert!(matches!(err, Error::InvalidState(_)), "{:?}", err);
+    assert_eq!(err.exit_code(), 1);
+
+    let removed = project::delete(&conn, m.project_id, true, true).unwrap();
+    assert!(removed.trashed);
+    assert_eq!(removed.entity.name, "myapp");
+    let err = error(module::get(&conn, m.id));
+    assert_eq!(err.code(), "not_found");
+}
+
+#[test]
+fn research_delete_leaves_nothing_behind() {
+    let conn = open();
+    let r = research::create(&conn, "scratch", "").unwrap();
+    research::update_source(&conn, r.id, "https://example.com").unwrap();
+    let removed = research::delete(&conn, r.id, false).unwrap();
+    assert!(!removed.trashed);
+    assert!(removed.warnings.is_empty(), "{:?}", removed.warnings);
+    assert_eq!(error(research::get(&conn, r.id)).exit_code(), 1);
+}
@@ -0,0 +1,170 @@
+//! `Resolver` under interleaved creates, renames, and removes: a stale entry
+//! would hand back the id of something renamed or removed, and link research
+//! to the wrong task.
+
+use lopen_memory::models::{feature, module, project, research, task};
+use lopen_memory::priority::Priority;
+use lopen_memory::resolve::Resolver;
+use lopen_memory::Error;
+use rusqlite::Connection;
+
+mod common;
+use common::open;
+
+/// A project, module, and feature; the feature's id.
+fn feature_in(conn: &Connection) -> i64 {
+    let p = project::create(conn, "myapp", "", "").unwrap();
+    let m = module::create(conn, p.id, "auth", "", "", "", None).unwrap();
+    feature::create(conn, m.id, "login", "", "", None, Priority::Medium)
+        .unwrap()
+        .id
+}
+
+fn add_task(conn: &Connection, feature_id: i64, name: &str) -> i64 {
+    task::create(conn, feature_id, name, "", "", None, None, Priority::Medium)
+        .unwrap()
+        .id
+}
+
+fn not_found(r: Result<i64, Error>) -> bool {
+    matches!(r, Err(Error::NotFound { .. }))
+}
+
+#[test]
+fn finds_entities_created_after_the_scope_was_read() {
+    let conn = open();
+    let fid = feature_in(&conn);
+    let resolver = Resolver::new(&conn);
+    let first = add_task(&conn, fid, "form");
+    assert_eq!(resolver.task("form", Some(fid)).unwrap(), first);
+
+    // Made around the resolver: the miss reads the scope again.
+    let second = add_task(&conn, fid, "submit");
+    assert_eq!(resolver.task("submit", Some(fid)).unwrap(), second);
+    assert_eq!(resolver.task("SUBMIT", Some(fid)).unwrap(), second);
+
+    let third = add_task(&conn, fid, "errors");
+    resolver.remember("task", Some(fid), "errors", third);
+    assert_eq!(resolver.task("errors", Some(fid)).unwrap(), third);
+    assert_eq!(
+        resolver
+            .clash("task", Some(fid), "Errors")
+            .unwrap()
+            .as_deref(),
+        Some("errors")
+    );
+}
+
+#[test]
+fn rename_through_it_forgets_the_old_name() {
+    let conn = open();
+    let fid = feature_in(&conn);
+    let a = add_task(&conn, fid, "a");
+    let b = add_task(&conn, fid, "b");
+    let resolver = Resolver::new(&conn);
+    assert_eq!(resolver.task("a", Some(fid)).unwrap(), a);
+    assert_eq!(resolver.task("b", Some(fid)).unwrap(), b);
+
+    // Swap the names: every lookup after each rename sees it.
+    resolver
+        .rename("task", a, || task::rename_to(&conn, a, "tmp", false, false))
+        .unwrap();
+    assert!(not_found(resolver.task("a", Some(fid))));
+    resolver
+        .rename("task", b, || task::rename_to(&conn, b, "a", false, false))
+        .unwrap();
+    resolver
+        .rename("task", a, || task::rename_to(&conn, a, "b", false, false))
+        .unwrap();
+    assert_eq!(resolver.task("a", Some(fid)).unwrap(), b);
+    assert_eq!(resolver.task("b", Some(fid)).unwrap(), a);
+    assert!(not_found(resolver.task("tmp", Some(fid))));
+}
+
+#[test]
+fn remove_through_it_forgets_the_entity_and_everything_under_it() {
+    let conn = open();
+    let fid = feature_in(&conn);
+    let t = add_task(&conn, fid, "form");
+    let resolver = Resolver::new(&conn);
+    assert_eq!(resolver.task("form", Some(fid)).unwrap(), t);
+
+    resolver
+        .remove("task", t, || task::delete(&conn, t, false))
+        .unwrap();
+    assert!(not_found(resolver.task("form", Some(fid))));
+
+    // A new task of the same name, which may even reuse the id, is the one found.
+    let again = add_task(&conn, fid, "form");
+    assert_eq!(resolver.task("form", Some(fid)).unwrap(), again);
+
+    let mid = feature::get(&conn, fid).unwrap().module_id;
+    assert_eq!(resolver.feature("login", Some(mid)).unwrap(), fid);
+    resolver
+        .remove("feature", fid, || {
+            feature::delete(&conn, fid, true, false, false)
+        })
+        .unwrap();
+    assert!(not_found(resolver.feature("login", Some(mid))));
+    assert!(not_found(resolver.task("form", Some(fid))));
+}
+
+#[test]
+fn research_is_linked_to_the_task_the_name_means_now() {
+    let conn = open();
+    let fid = feature_in(&conn);
+    let old = add_task(&conn, fid, "cache");
+    let r = research::create(&conn, "redis-notes", "").unwrap();
+    let resolver = Resolver::new(&conn);
+    let target = resolver.task("cache", Some(fid)).unwrap();
+    research::link_to(&conn, "task", r.id, target, false).unwrap();
+
+    resolver
+        .rename("task", old, || {
+            task::rename_to(&conn, old, "cache-v1", false, false)
+        })
+        .unwrap();
+    let new = add_task(&conn, fid, "cache");
+    let target = resolver.task("cache", Some(fid)).unwrap();
+    assert_eq!(target, new);
+    assert!(
+        research::link_to(&conn, "task", r.id, target, false)
+            .unwrap()
+            .changed
+    );
+    assert_eq!(resolver.research("redis-notes").unwrap(), r.id);
+}
+
+#[test]
+fn changes_around_it_need_a_clear() {
+    let conn = open();
+    let fid = feature_in(&conn);
+    let t = add_task(&conn, fid, "form");
+    let resolver = Resolver::new(&conn);
+    assert_eq!(resolver.task("form", Some(fid)).unwrap(), t);
+
+    task::rename_to(&conn, t, "form-v2", false, false).unwrap();
+    assert_eq!(resolver.task("form", Some(fid)).unwrap(), t);
+    resolver.clear();
+    assert!(not_found(resolver.task("form", Some(fid))));
+    assert_eq!(resolver.task("form-v2", Some(fid)).unwrap(), t);
+}
+
+#[test]
+fn ids_and_errors_read_as_the_plain_resolvers_do() {
+    let conn = open();
+    let fid = feature_in(&conn);
+    let t = add_task(&conn, fid, "form");
+    let resolver = Resolver::new(&conn);
+    assert_eq!(resolver.task(&format!("T{}", t), None).unwrap(), t);
+    assert_eq!(resolver.task(&t.to_string(), Some(fid)).unwrap(), t);
+
+    let Err(e) = resolver.task("from", Some(fid)) else {
+        panic!("a misspelt task resolved");
+    };
+    assert_eq!(e.to_string(), "task not found: from; did you mean: form?");
+    assert!(matches!(
+        resolver.named("widget", None, "x"),
+        Err(Error::Invalid(_))
+    ));
+}